@@ -0,0 +1,150 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `swanky-bench`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Compare two files of benchmark reports and flag regressions.
+//!
+//! ```text
+//! bench-compare [--time 0.10] [--bytes 0.01] [--memory 0.10] old.json new.json
+//! bench-compare criterion target/criterion [baseline] >> criterion.json
+//! ```
+//!
+//! Each file holds reports written by `swanky_bench::Report::emit`,
+//! one per line. Every report of the new file is compared with the last
+//! report of the old one from the same benchmark, printing the change of
+//! each metric of each phase. The flags set how much a metric may grow, as a
+//! fraction of its old value, before it counts as a regression. Exits with 1
+//! if any metric regressed, and with 2 if the files cannot be read.
+//!
+//! The `criterion` form prints the reports of the estimates `criterion` saved
+//! under a directory for the baseline `baseline` (by default `new`, the last
+//! run), so that `cargo bench` runs can be compared the same way.
+
+use std::{convert::TryFrom, path::Path};
+use swanky_bench::{Metric, Report, Thresholds};
+
+const USAGE: &str = "\
+usage: bench-compare [options] <old.json> <new.json>
+       bench-compare criterion <dir> [baseline]
+
+options:
+    --time <fraction>      the allowed growth of the time of a phase [0.10]
+    --bytes <fraction>     the allowed growth of its communication [0.01]
+    --memory <fraction>    the allowed growth of its peak memory [0.10]";
+
+fn read_reports(path: &str) -> Result<Vec<Report>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| Report::from_json(line).map_err(|e| format!("{}:{}: {}", path, i + 1, e)))
+        .collect()
+}
+
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<(Thresholds, String, String), String> {
+    let mut thresholds = Thresholds::default();
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        let threshold = match arg.as_str() {
+            "--time" => &mut thresholds.time,
+            "--bytes" => &mut thresholds.bytes,
+            "--memory" => &mut thresholds.memory,
+            _ if arg.starts_with("--") => return Err(format!("unknown option `{}`", arg)),
+            _ => {
+                paths.push(arg);
+                continue;
+            }
+        };
+        *threshold = args
+            .next()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format!("`{}` takes a fraction", arg))?;
+    }
+    match <[String; 2]>::try_from(paths) {
+        Ok([old, new]) => Ok((thresholds, old, new)),
+        Err(_) => Err("expected two files".to_string()),
+    }
+}
+
+fn describe(report: &Report) -> String {
+    let mut name = report.protocol.clone();
+    for (param, value) in report.params.iter() {
+        name.push_str(&format!(" {}={}", param, value));
+    }
+    name
+}
+
+fn format_value(metric: Metric, value: f64) -> String {
+    match metric {
+        Metric::Time => format!("{:.2} ms", value),
+        _ => format!("{}", value as u64),
+    }
+}
+
+fn print_criterion(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let dir = args.next().ok_or("`criterion` takes a directory")?;
+    let baseline = args.next().unwrap_or_else(|| "new".to_string());
+    if args.next().is_some() {
+        return Err("too many arguments".to_string());
+    }
+    let reports = swanky_bench::criterion::read(Path::new(&dir), &baseline)
+        .map_err(|e| format!("{}: {}", dir, e))?;
+    for report in reports.iter() {
+        println!("{}", report.to_json());
+    }
+    Ok(())
+}
+
+fn run() -> Result<bool, String> {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("criterion") {
+        args.next();
+        return print_criterion(args).map(|()| false);
+    }
+    let (thresholds, old, new) = parse_args(args)?;
+    let (old, new) = (read_reports(&old)?, read_reports(&new)?);
+    let mut regressed = false;
+    for report in new.iter() {
+        let before = match old.iter().rev().find(|old| old.same_benchmark(report)) {
+            Some(before) => before,
+            None => {
+                println!("{}: no earlier run to compare with", describe(report));
+                continue;
+            }
+        };
+        println!("{} ({} -> {})", describe(report), before.git, report.git);
+        for change in swanky_bench::compare(before, report, &thresholds) {
+            println!(
+                "  {} {} {}: {} -> {} ({:+.1}%){}",
+                change.party,
+                change.phase,
+                change.metric,
+                format_value(change.metric, change.old),
+                format_value(change.metric, change.new),
+                change.relative() * 100.0,
+                if change.regression {
+                    "  REGRESSION"
+                } else {
+                    ""
+                }
+            );
+            regressed |= change.regression;
+        }
+    }
+    Ok(regressed)
+}
+
+fn main() {
+    match run() {
+        Ok(regressed) => std::process::exit(regressed as i32),
+        Err(e) => {
+            eprintln!("bench-compare: {}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `swanky-bench`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Reports from the estimates `criterion` saves.
+//!
+//! `cargo bench` leaves, for every benchmark, a directory under
+//! `target/criterion` holding one subdirectory per baseline: `new` for the
+//! last run, `base` for the one before it, and one per `--save-baseline`.
+//! `read` turns the `estimates.json` of one baseline of every benchmark into
+//! a `Report` whose protocol is the path of the benchmark, e.g.
+//! `"psz/receive/1024"`, with a single phase `"iteration"` of party
+//! `"bench"` holding the mean time of an iteration. Criterion does not see
+//! the channel or the heap, so the other metrics are zero.
+
+use crate::report::{PhaseRecord, Report};
+use serde::Deserialize;
+use std::{
+    fs,
+    io,
+    path::Path,
+};
+
+// The parts of an `estimates.json` we use. Criterion 0.2 capitalizes the
+// names of the statistics, and 0.3 does not.
+#[derive(Deserialize)]
+struct Estimates {
+    #[serde(alias = "Mean")]
+    mean: Estimate,
+}
+
+#[derive(Deserialize)]
+struct Estimate {
+    // In nanoseconds.
+    point_estimate: f64,
+}
+
+/// Read the reports of the baseline `baseline` of every benchmark under
+/// `dir`, sorted by protocol.
+pub fn read(dir: &Path, baseline: &str) -> io::Result<Vec<Report>> {
+    let mut reports = Vec::new();
+    visit(dir, dir, baseline, &mut reports)?;
+    reports.sort_by(|a, b| a.protocol.cmp(&b.protocol));
+    Ok(reports)
+}
+
+fn visit(root: &Path, dir: &Path, baseline: &str, reports: &mut Vec<Report>) -> io::Result<()> {
+    let estimates = dir.join(baseline).join("estimates.json");
+    if estimates.is_file() {
+        reports.push(report(root, dir, &estimates)?);
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        // Criterion writes its HTML summaries to `report` directories.
+        if path.is_dir() && path.file_name() != Some("report".as_ref()) {
+            visit(root, &path, baseline, reports)?;
+        }
+    }
+    Ok(())
+}
+
+fn report(root: &Path, dir: &Path, estimates: &Path) -> io::Result<Report> {
+    let invalid = |e: serde_json::Error| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", estimates.display(), e),
+        )
+    };
+    let Estimates { mean } = serde_json::from_slice(&fs::read(estimates)?).map_err(invalid)?;
+    let protocol = dir
+        .strip_prefix(root)
+        .unwrap_or(dir)
+        .iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let mut report = Report::new(&protocol);
+    report.push(PhaseRecord {
+        party: "bench".to_string(),
+        phase: "iteration".to_string(),
+        millis: mean.point_estimate / 1_000_000.0,
+        bytes_written: 0,
+        bytes_read: 0,
+        peak_memory: 0,
+    });
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let root = std::env::temp_dir().join(format!("swanky-bench-{}", std::process::id()));
+        let write = |path: &[&str], contents: &str| {
+            let path = path.iter().fold(root.clone(), |dir, part| dir.join(part));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        // Criterion 0.3, with a parameter, and 0.2.
+        write(
+            &["psz", "receive", "1024", "new", "estimates.json"],
+            r#"{"mean":{"confidence_interval":{},"point_estimate":2500000.0,"standard_error":1.0}}"#,
+        );
+        write(
+            &["aes128", "new", "estimates.json"],
+            r#"{"Mean":{"point_estimate":12.5},"Median":{"point_estimate":12.0}}"#,
+        );
+        write(
+            &["aes128", "base", "estimates.json"],
+            r#"{"Mean":{"point_estimate":10.0}}"#,
+        );
+        write(&["psz", "report", "index.html"], "");
+
+        let reports = read(&root, "new").unwrap();
+        let protocols = reports
+            .iter()
+            .map(|r| r.protocol.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(protocols, vec!["aes128", "psz/receive/1024"]);
+        assert_eq!(reports[0].phases[0].millis, 12.5e-6);
+        assert_eq!(reports[1].phases[0].millis, 2.5);
+        let base = read(&root, "base").unwrap();
+        assert_eq!(base.len(), 1);
+        assert_eq!(base[0].phases[0].millis, 1e-5);
+
+        write(&["bad", "new", "estimates.json"], "{}");
+        assert!(read(&root, "new").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,36 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `swanky-bench`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Machine-readable benchmark reports for the `swanky` crates, and their
+//! comparison.
+//!
+//! The benchmark examples measure each phase of each party with `measure`,
+//! collect the measurements into a `Report`, and `Report::emit` it. The
+//! `criterion` benchmarks are left as they are; `criterion::read` turns the
+//! estimates they save into reports. `compare` flags the metrics that grew
+//! between two runs, and the `bench-compare` binary does so for two files of
+//! reports.
+//!
+//! This crate is tooling only: the protocol crates depend on it for their
+//! examples, never for their libraries.
+
+#![deny(missing_docs)]
+
+pub mod criterion;
+mod report;
+
+pub use crate::report::{
+    compare,
+    measure,
+    Change,
+    Measurement,
+    Metric,
+    ParseError,
+    PhaseRecord,
+    Report,
+    Thresholds,
+    SCHEMA,
+};
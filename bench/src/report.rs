@@ -0,0 +1,491 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `swanky-bench`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Benchmark reports, and their comparison.
+//!
+//! Setting `SWANKY_BENCH_JSON` to a path makes `Report::emit` append the
+//! report to that file as one line of JSON, or print it if the path is `-`;
+//! otherwise nothing is written. A report looks like
+//!
+//! ```text
+//! {"schema":1,"protocol":"psz","params":{"ninputs":1048576,"nbytes":16},
+//!  "git":"<commit>","phases":[{"party":"sender","phase":"init",
+//!  "millis":10.5,"bytes_written":8209,"bytes_read":24617,"peak_memory":96}]}
+//! ```
+//!
+//! (on a single line). Parameters are unsigned integers; `millis` is the
+//! wall-clock time of the phase; the byte counts are those of the party's
+//! `TrackChannel` during the phase; `peak_memory` is the `memtrack` peak of
+//! the phase, zero unless `memtrack::CountingAlloc` is installed. `git` is
+//! the value of `SWANKY_GIT_HASH` when this crate was built, or `unknown`.
+//! Readers ignore fields they do not know, so only removing or changing a
+//! field bumps `SCHEMA`.
+
+use scuttlebutt::{memtrack, TrackChannel};
+use serde::{
+    de::{MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize,
+    Deserializer,
+    Serialize,
+    Serializer,
+};
+use std::{
+    io::{Read, Write},
+    time::Instant,
+};
+
+/// The version of the report format.
+pub const SCHEMA: u64 = 1;
+
+/// The measurements of one phase of one party.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PhaseRecord {
+    /// The party, e.g. `"sender"`.
+    pub party: String,
+    /// The phase, e.g. `"init"`.
+    pub phase: String,
+    /// The wall-clock time of the phase, in milliseconds.
+    pub millis: f64,
+    /// The bytes the party wrote during the phase.
+    pub bytes_written: u64,
+    /// The bytes the party read during the phase.
+    pub bytes_read: u64,
+    /// The most heap bytes live during the phase, beyond those live when it
+    /// started.
+    pub peak_memory: u64,
+}
+
+/// A phase being measured, started by `Measurement::start`.
+#[derive(Debug)]
+pub struct Measurement {
+    party: String,
+    phase: String,
+    start: Instant,
+    memory: memtrack::Phase,
+    bytes_written: u64,
+    bytes_read: u64,
+}
+
+impl Measurement {
+    /// Start measuring the phase `phase` of `party`, which communicates over
+    /// `channel`. Must be finished on the same thread.
+    pub fn start<R: Read, W: Write>(
+        party: &str,
+        phase: &str,
+        channel: &TrackChannel<R, W>,
+    ) -> Self {
+        Self {
+            party: party.to_string(),
+            phase: phase.to_string(),
+            bytes_written: channel.bytes_written(),
+            bytes_read: channel.bytes_read(),
+            memory: memtrack::Phase::start(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Stop measuring.
+    pub fn finish<R: Read, W: Write>(self, channel: &TrackChannel<R, W>) -> PhaseRecord {
+        let millis = self.start.elapsed().as_secs_f64() * 1000.0;
+        let memory = self.memory.finish();
+        PhaseRecord {
+            party: self.party,
+            phase: self.phase,
+            millis,
+            bytes_written: channel.bytes_written() - self.bytes_written,
+            bytes_read: channel.bytes_read() - self.bytes_read,
+            peak_memory: memory.peak as u64,
+        }
+    }
+}
+
+/// Run `f` on `channel` as the phase `phase` of `party`, returning its output
+/// and the measurements of the phase.
+pub fn measure<R: Read, W: Write, T>(
+    party: &str,
+    phase: &str,
+    channel: &mut TrackChannel<R, W>,
+    f: impl FnOnce(&mut TrackChannel<R, W>) -> T,
+) -> (T, PhaseRecord) {
+    let measurement = Measurement::start(party, phase, channel);
+    let out = f(channel);
+    (out, measurement.finish(channel))
+}
+
+/// The results of one run of a benchmark.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// The protocol benchmarked, e.g. `"psz"`.
+    pub protocol: String,
+    /// The parameters of the run, in the order they were given.
+    #[serde(with = "params")]
+    pub params: Vec<(String, u64)>,
+    /// The commit the benchmark ran from.
+    pub git: String,
+    /// The phases measured, in the order they were added.
+    pub phases: Vec<PhaseRecord>,
+}
+
+// The commit the benchmarks were built from, which the build passes in
+// through `SWANKY_GIT_HASH`, e.g. `SWANKY_GIT_HASH=$(git describe --always
+// --dirty) cargo run --example psz`.
+const GIT_HASH: Option<&str> = option_env!("SWANKY_GIT_HASH");
+
+// The report as written, with its format version first.
+#[derive(Serialize)]
+struct Versioned<'a> {
+    schema: u64,
+    #[serde(flatten)]
+    report: &'a Report,
+}
+
+// Only the format version, read before the rest of a report so that a
+// report of another version fails with a clear error.
+#[derive(Deserialize)]
+struct Schema {
+    schema: u64,
+}
+
+// Parameters are written as a JSON object, keeping their order.
+mod params {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        params: &[(String, u64)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(params.len()))?;
+        for (name, value) in params.iter() {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(String, u64)>, D::Error> {
+        struct Params;
+
+        impl<'de> Visitor<'de> for Params {
+            type Value = Vec<(String, u64)>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an object of unsigned integers")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut params = Vec::new();
+                while let Some(param) = map.next_entry()? {
+                    params.push(param);
+                }
+                Ok(params)
+            }
+        }
+
+        deserializer.deserialize_map(Params)
+    }
+}
+
+impl Report {
+    /// Start a report of `protocol` on the current commit.
+    pub fn new(protocol: &str) -> Self {
+        Self {
+            protocol: protocol.to_string(),
+            params: Vec::new(),
+            git: GIT_HASH.unwrap_or("unknown").to_string(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// Add the parameter `name`.
+    pub fn param(mut self, name: &str, value: u64) -> Self {
+        self.params.push((name.to_string(), value));
+        self
+    }
+
+    /// Add the measurements of a phase.
+    pub fn push(&mut self, phase: PhaseRecord) {
+        self.phases.push(phase);
+    }
+
+    /// Return the report as one line of JSON.
+    pub fn to_json(&self) -> String {
+        let versioned = Versioned {
+            schema: SCHEMA,
+            report: self,
+        };
+        serde_json::to_string(&versioned).expect("a report is always valid JSON")
+    }
+
+    /// Parse a report written by `to_json`. Fields unknown to this version
+    /// are ignored.
+    pub fn from_json(json: &str) -> Result<Self, ParseError> {
+        let Schema { schema } = serde_json::from_str(json)?;
+        if schema != SCHEMA {
+            return Err(ParseError(format!("unsupported schema version {}", schema)));
+        }
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Return whether `other` is a run of the same benchmark, that is, of
+    /// the same protocol with the same parameters.
+    pub fn same_benchmark(&self, other: &Report) -> bool {
+        self.protocol == other.protocol && self.params == other.params
+    }
+
+    /// Write the report where `SWANKY_BENCH_JSON` says, if it is set.
+    pub fn emit(&self) -> std::io::Result<()> {
+        match std::env::var_os("SWANKY_BENCH_JSON") {
+            None => Ok(()),
+            Some(path) if path == "-" => writeln!(std::io::stdout(), "{}", self.to_json()),
+            Some(path) => {
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                writeln!(file, "{}", self.to_json())
+            }
+        }
+    }
+}
+
+/// An error parsing a report.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError(pub String);
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid benchmark report: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(e: serde_json::Error) -> Self {
+        ParseError(e.to_string())
+    }
+}
+
+/// A metric of a phase, as compared by `compare`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// `PhaseRecord::millis`.
+    Time,
+    /// `PhaseRecord::bytes_written`.
+    BytesWritten,
+    /// `PhaseRecord::bytes_read`.
+    BytesRead,
+    /// `PhaseRecord::peak_memory`.
+    PeakMemory,
+}
+
+impl std::fmt::Display for Metric {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Metric::Time => "time".fmt(f),
+            Metric::BytesWritten => "bytes written".fmt(f),
+            Metric::BytesRead => "bytes read".fmt(f),
+            Metric::PeakMemory => "peak memory".fmt(f),
+        }
+    }
+}
+
+/// How much each metric may grow, relative to its old value, before
+/// `compare` flags it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    /// The allowed growth of the time of a phase.
+    pub time: f64,
+    /// The allowed growth of the bytes written or read in a phase.
+    pub bytes: f64,
+    /// The allowed growth of the peak memory of a phase.
+    pub memory: f64,
+}
+
+impl Default for Thresholds {
+    /// Allow 10% more time and memory, whose measurements are noisy, but
+    /// only 1% more communication, which is mostly deterministic.
+    fn default() -> Self {
+        Self {
+            time: 0.10,
+            bytes: 0.01,
+            memory: 0.10,
+        }
+    }
+}
+
+/// The change of one metric of one phase between two reports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    /// The party of the phase.
+    pub party: String,
+    /// The phase.
+    pub phase: String,
+    /// The metric compared.
+    pub metric: Metric,
+    /// Its value in the old report.
+    pub old: f64,
+    /// Its value in the new report.
+    pub new: f64,
+    /// Whether it grew by more than its threshold.
+    pub regression: bool,
+}
+
+impl Change {
+    /// Return the change relative to the old value, e.g. `0.25` for 25%
+    /// more. Infinite if the old value was zero and the new one is not.
+    pub fn relative(&self) -> f64 {
+        if self.old == self.new {
+            0.0
+        } else {
+            (self.new - self.old) / self.old
+        }
+    }
+}
+
+/// Compare the phases of `old` and `new`, two runs of the same benchmark,
+/// returning the change of every metric of every phase found in both, in the
+/// order of `new`.
+pub fn compare(old: &Report, new: &Report, thresholds: &Thresholds) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for phase in new.phases.iter() {
+        let before = match old
+            .phases
+            .iter()
+            .find(|p| p.party == phase.party && p.phase == phase.phase)
+        {
+            Some(before) => before,
+            None => continue,
+        };
+        let metrics = [
+            (Metric::Time, before.millis, phase.millis, thresholds.time),
+            (
+                Metric::BytesWritten,
+                before.bytes_written as f64,
+                phase.bytes_written as f64,
+                thresholds.bytes,
+            ),
+            (
+                Metric::BytesRead,
+                before.bytes_read as f64,
+                phase.bytes_read as f64,
+                thresholds.bytes,
+            ),
+            (
+                Metric::PeakMemory,
+                before.peak_memory as f64,
+                phase.peak_memory as f64,
+                thresholds.memory,
+            ),
+        ];
+        for &(metric, old, new, threshold) in metrics.iter() {
+            changes.push(Change {
+                party: phase.party.clone(),
+                phase: phase.phase.clone(),
+                metric,
+                old,
+                new,
+                regression: new > old * (1.0 + threshold),
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        let mut report = Report {
+            protocol: "psz".to_string(),
+            params: vec![("ninputs".to_string(), 1 << 20), ("nbytes".to_string(), 16)],
+            git: "0123abcd-dirty".to_string(),
+            phases: Vec::new(),
+        };
+        report.push(PhaseRecord {
+            party: "sender".to_string(),
+            phase: "init".to_string(),
+            millis: 10.5,
+            bytes_written: 8209,
+            bytes_read: 24617,
+            peak_memory: 96,
+        });
+        report.push(PhaseRecord {
+            party: "receiver \"1\"".to_string(),
+            phase: "receive".to_string(),
+            millis: 1234.25,
+            bytes_written: u64::MAX,
+            bytes_read: 0,
+            peak_memory: 0,
+        });
+        report
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let report = report();
+        let json = report.to_json();
+        assert!(!json.contains('\n'));
+        assert_eq!(Report::from_json(&json).unwrap(), report);
+        // Whitespace and unknown fields are fine.
+        let json = json.replacen(
+            "{\"schema\":1,",
+            "{ \"schema\" : 1 , \"host\": [null, true],",
+            1,
+        );
+        assert_eq!(Report::from_json(&json).unwrap(), report);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let json = report().to_json();
+        for bad in &[
+            json.replace("\"schema\":1", "\"schema\":2"),
+            json.replace("\"git\"", "\"commit\""),
+            json.replace("\"millis\":10.5", "\"millis\":\"10\""),
+            json[..json.len() - 1].to_string(),
+            format!("{} {{}}", json),
+        ] {
+            assert!(Report::from_json(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = report();
+        let mut new = report();
+        new.phases[0].millis = 11.0;
+        new.phases[0].bytes_written += 1000;
+        new.phases[1].peak_memory = 1;
+        new.phases[1].millis = 100.0;
+        new.push(PhaseRecord {
+            phase: "extra".to_string(),
+            ..new.phases[0].clone()
+        });
+        let changes = compare(&old, &new, &Thresholds::default());
+        assert_eq!(changes.len(), 8);
+        let flagged = changes
+            .iter()
+            .filter(|c| c.regression)
+            .map(|c| (c.phase.as_str(), c.metric))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            flagged,
+            vec![
+                ("init", Metric::BytesWritten),
+                ("receive", Metric::PeakMemory)
+            ]
+        );
+        assert!(changes[4].relative() < -0.9);
+        assert!(changes[7].relative().is_infinite());
+        assert!(compare(&old, &old, &Thresholds::default())
+            .iter()
+            .all(|c| !c.regression && c.relative() == 0.0));
+    }
+}
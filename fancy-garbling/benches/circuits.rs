@@ -7,7 +7,10 @@
 //! Benchmark code of garbling / evaluating using Nigel's circuits.
 
 use criterion::{criterion_group, criterion_main, Criterion};
-use fancy_garbling::{circuit::Circuit, classic::garble};
+use fancy_garbling::{
+    circuit::{Circuit, WireArena},
+    classic::garble,
+};
 use std::time::Duration;
 
 fn circuit(fname: &str) -> Circuit {
@@ -34,6 +37,17 @@ fn bench_eval_aes(c: &mut Criterion) {
     });
 }
 
+fn bench_eval_aes_arena(c: &mut Criterion) {
+    let circ = circuit("circuits/AES-non-expanded.txt");
+    let (en, gc) = garble(&circ).unwrap();
+    let gb = en.encode_garbler_inputs(&vec![0u16; 128]);
+    let ev = en.encode_evaluator_inputs(&vec![0u16; 128]);
+    let mut arena = WireArena::new();
+    c.bench_function("eval::aes::arena", move |bench| {
+        bench.iter(|| gc.eval_with(&circ, &gb, &ev, &mut arena));
+    });
+}
+
 fn bench_garble_sha_1(c: &mut Criterion) {
     let circ = circuit("circuits/sha-1.txt");
     c.bench_function("garble::sha-1", move |bench| {
@@ -71,7 +85,7 @@ fn bench_eval_sha_256(c: &mut Criterion) {
 criterion_group! {
     name = parsing;
     config = Criterion::default().warm_up_time(Duration::from_millis(100));
-    targets = bench_garble_aes, bench_eval_aes, bench_garble_sha_1, bench_eval_sha_1, bench_garble_sha_256, bench_eval_sha_256
+    targets = bench_garble_aes, bench_eval_aes, bench_eval_aes_arena, bench_garble_sha_1, bench_eval_sha_1, bench_garble_sha_256, bench_eval_sha_256
 }
 
 criterion_main!(parsing);
@@ -10,71 +10,82 @@ use fancy_garbling::{
     FancyInput,
 };
 use ocelot::ot::{AlszReceiver as OtReceiver, AlszSender as OtSender};
-use scuttlebutt::{unix_channel_pair, AesRng, UnixChannel};
-use std::time::SystemTime;
+use scuttlebutt::{memtrack, AbstractChannel, AesRng, TrackChannel};
+use std::{
+    io::{BufReader, BufWriter},
+    os::unix::net::UnixStream,
+    time::SystemTime,
+};
+use swanky_bench::{measure, Report};
+
+type Channel = TrackChannel<BufReader<UnixStream>, BufWriter<UnixStream>>;
+
+#[global_allocator]
+static ALLOC: memtrack::CountingAlloc = memtrack::CountingAlloc::system();
 
 fn circuit(fname: &str) -> Circuit {
     println!("* Circuit: {}", fname);
     Circuit::parse(fname).unwrap()
 }
 
-fn run_circuit(circ: &mut Circuit, gb_inputs: Vec<u16>, ev_inputs: Vec<u16>) {
+fn track_channel(stream: UnixStream) -> Channel {
+    let reader = BufReader::new(stream.try_clone().unwrap());
+    TrackChannel::new(reader, BufWriter::new(stream))
+}
+
+fn run_circuit(name: &str, circ: &mut Circuit, gb_inputs: Vec<u16>, ev_inputs: Vec<u16>) {
     let circ_ = circ.clone();
-    let (sender, receiver) = unix_channel_pair();
+    let (sender, receiver) = UnixStream::pair().unwrap();
     let n_gb_inputs = gb_inputs.len();
     let n_ev_inputs = ev_inputs.len();
     let total = SystemTime::now();
     let handle = std::thread::spawn(move || {
-        let rng = AesRng::new();
-        let start = SystemTime::now();
-        let mut gb = Garbler::<UnixChannel, AesRng, OtSender>::new(sender, rng).unwrap();
-        println!(
-            "Garbler :: Initialization: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
-        let start = SystemTime::now();
-        let xs = gb.encode_many(&gb_inputs, &vec![2; n_gb_inputs]).unwrap();
-        let ys = gb.receive_many(&vec![2; n_ev_inputs]).unwrap();
-        println!(
-            "Garbler :: Encoding inputs: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
-        let start = SystemTime::now();
-        circ_.eval(&mut gb, &xs, &ys).unwrap();
-        println!(
-            "Garbler :: Circuit garbling: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
+        let mut channel = track_channel(sender);
+        let (mut gb, init) = measure("garbler", "init", &mut channel, |channel| {
+            Garbler::<Channel, AesRng, OtSender>::new(channel.clone(), AesRng::new()).unwrap()
+        });
+        println!("Garbler :: Initialization: {:.0} ms", init.millis);
+        let ((xs, ys), encode) = measure("garbler", "encode", &mut channel, |_| {
+            let xs = gb.encode_many(&gb_inputs, &vec![2; n_gb_inputs]).unwrap();
+            let ys = gb.receive_many(&vec![2; n_ev_inputs]).unwrap();
+            (xs, ys)
+        });
+        println!("Garbler :: Encoding inputs: {:.0} ms", encode.millis);
+        let (_, garble) = measure("garbler", "garble", &mut channel, |_| {
+            circ_.eval(&mut gb, &xs, &ys).unwrap()
+        });
+        println!("Garbler :: Circuit garbling: {:.0} ms", garble.millis);
+        vec![init, encode, garble]
+    });
+    let mut channel = track_channel(receiver);
+    let (mut ev, init) = measure("evaluator", "init", &mut channel, |channel| {
+        Evaluator::<Channel, AesRng, OtReceiver>::new(channel.clone(), AesRng::new()).unwrap()
+    });
+    println!("Evaluator :: Initialization: {:.0} ms", init.millis);
+    let ((xs, ys), encode) = measure("evaluator", "encode", &mut channel, |_| {
+        let xs = ev.receive_many(&vec![2; n_gb_inputs]).unwrap();
+        let ys = ev.encode_many(&ev_inputs, &vec![2; n_ev_inputs]).unwrap();
+        (xs, ys)
+    });
+    println!("Evaluator :: Encoding inputs: {:.0} ms", encode.millis);
+    let (_, evaluate) = measure("evaluator", "evaluate", &mut channel, |_| {
+        circ.eval(&mut ev, &xs, &ys).unwrap()
     });
-    let rng = AesRng::new();
-    let start = SystemTime::now();
-    let mut ev = Evaluator::<UnixChannel, AesRng, OtReceiver>::new(receiver, rng).unwrap();
-    println!(
-        "Evaluator :: Initialization: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    let start = SystemTime::now();
-    let xs = ev.receive_many(&vec![2; n_gb_inputs]).unwrap();
-    let ys = ev.encode_many(&ev_inputs, &vec![2; n_ev_inputs]).unwrap();
-    println!(
-        "Evaluator :: Encoding inputs: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    let start = SystemTime::now();
-    circ.eval(&mut ev, &xs, &ys).unwrap();
-    println!(
-        "Evaluator :: Circuit evaluation: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    handle.join().unwrap();
+    println!("Evaluator :: Circuit evaluation: {:.0} ms", evaluate.millis);
+    let mut report = Report::new(&format!("semihonest_2pc/{}", name))
+        .param("ngarbler_inputs", n_gb_inputs as u64)
+        .param("nevaluator_inputs", n_ev_inputs as u64);
+    report.phases.extend(handle.join().unwrap());
+    report.phases.extend(vec![init, encode, evaluate]);
     println!("Total: {} ms", total.elapsed().unwrap().as_millis());
+    report.emit().unwrap();
 }
 
 fn main() {
     let mut circ = circuit("circuits/AES-non-expanded.txt");
-    run_circuit(&mut circ, vec![0; 128], vec![0; 128]);
+    run_circuit("aes", &mut circ, vec![0; 128], vec![0; 128]);
     let mut circ = circuit("circuits/sha-1.txt");
-    run_circuit(&mut circ, vec![0; 512], vec![]);
+    run_circuit("sha-1", &mut circ, vec![0; 512], vec![]);
     let mut circ = circuit("circuits/sha-256.txt");
-    run_circuit(&mut circ, vec![0; 512], vec![]);
+    run_circuit("sha-256", &mut circ, vec![0; 512], vec![]);
 }
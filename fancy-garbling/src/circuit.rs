@@ -7,6 +7,8 @@
 //! DSL for creating circuits compatible with fancy-garbling in the old-fashioned way,
 //! where you create a circuit for a computation then garble it.
 
+pub mod bristol;
+
 use crate::{
     dummy::{Dummy, DummyVal},
     errors::{CircuitBuilderError, DummyError, FancyError},
@@ -35,6 +37,48 @@ impl HasModulus for CircuitRef {
     }
 }
 
+/// Storage for the value on every wire of a circuit, indexed by wire id, for
+/// use with `Circuit::eval_with`.
+#[derive(Debug)]
+pub struct WireArena<T> {
+    wires: Vec<Option<T>>,
+}
+
+impl<T> WireArena<T> {
+    /// Make an empty arena.
+    pub fn new() -> Self {
+        Self { wires: Vec::new() }
+    }
+
+    /// Make an arena with room for a circuit of `nwires` wires.
+    pub fn with_capacity(nwires: usize) -> Self {
+        Self {
+            wires: Vec::with_capacity(nwires),
+        }
+    }
+
+    // Drop any values from a previous evaluation, and make room for `nwires`
+    // wires.
+    fn reset(&mut self, nwires: usize) {
+        self.wires.clear();
+        self.wires.resize_with(nwires, || None);
+    }
+}
+
+impl<T> Default for WireArena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Look up the value on wire `r`, which must already have been computed.
+#[inline]
+fn lookup<F: Fancy>(cache: &[Option<F::Item>], r: CircuitRef) -> Result<&F::Item, F::Error> {
+    cache[r.ix]
+        .as_ref()
+        .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))
+}
+
 /// Static representation of the type of computation supported by fancy garbling.
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
@@ -137,7 +181,25 @@ impl Circuit {
         garbler_inputs: &[F::Item],
         evaluator_inputs: &[F::Item],
     ) -> Result<Option<Vec<u16>>, F::Error> {
-        let mut cache: Vec<Option<F::Item>> = vec![None; self.gates.len()];
+        let mut arena = WireArena::with_capacity(self.gates.len());
+        self.eval_with(f, garbler_inputs, evaluator_inputs, &mut arena)
+    }
+
+    /// Evaluate the circuit using fancy object `f`, storing the value of
+    /// every wire in `arena`.
+    ///
+    /// The arena is cleared first, and keeps its storage afterwards, so
+    /// evaluating many times with the same arena only allocates on the first
+    /// run.
+    pub fn eval_with<F: Fancy>(
+        &self,
+        f: &mut F,
+        garbler_inputs: &[F::Item],
+        evaluator_inputs: &[F::Item],
+        arena: &mut WireArena<F::Item>,
+    ) -> Result<Option<Vec<u16>>, F::Error> {
+        arena.reset(self.gates.len());
+        let cache = &mut arena.wires;
         for (i, gate) in self.gates.iter().enumerate() {
             let q = self.modulus(i);
             let (zref_, val) = match *gate {
@@ -154,69 +216,31 @@ impl Circuit {
                 Gate::Constant { val } => (None, f.constant(val, q)?),
                 Gate::Add { xref, yref, out } => (
                     out,
-                    f.add(
-                        cache[xref.ix]
-                            .as_ref()
-                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?,
-                        cache[yref.ix]
-                            .as_ref()
-                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?,
-                    )?,
+                    f.add(lookup::<F>(cache, xref)?, lookup::<F>(cache, yref)?)?,
                 ),
                 Gate::Sub { xref, yref, out } => (
                     out,
-                    f.sub(
-                        cache[xref.ix]
-                            .as_ref()
-                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?,
-                        cache[yref.ix]
-                            .as_ref()
-                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?,
-                    )?,
-                ),
-                Gate::Cmul { xref, c, out } => (
-                    out,
-                    f.cmul(
-                        cache[xref.ix]
-                            .as_ref()
-                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?,
-                        c,
-                    )?,
+                    f.sub(lookup::<F>(cache, xref)?, lookup::<F>(cache, yref)?)?,
                 ),
+                Gate::Cmul { xref, c, out } => (out, f.cmul(lookup::<F>(cache, xref)?, c)?),
                 Gate::Proj {
                     xref, ref tt, out, ..
                 } => (
                     out,
-                    f.proj(
-                        cache[xref.ix]
-                            .as_ref()
-                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?,
-                        q,
-                        Some(tt.to_vec()),
-                    )?,
+                    f.proj(lookup::<F>(cache, xref)?, q, Some(tt.to_vec()))?,
                 ),
                 Gate::Mul {
                     xref, yref, out, ..
                 } => (
                     out,
-                    f.mul(
-                        cache[xref.ix]
-                            .as_ref()
-                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?,
-                        cache[yref.ix]
-                            .as_ref()
-                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?,
-                    )?,
+                    f.mul(lookup::<F>(cache, xref)?, lookup::<F>(cache, yref)?)?,
                 ),
             };
             cache[zref_.unwrap_or(i)] = Some(val);
         }
         let mut outputs = Vec::with_capacity(self.output_refs.len());
         for r in self.output_refs.iter() {
-            let r = cache[r.ix]
-                .as_ref()
-                .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?;
-            let out = f.output(r)?;
+            let out = f.output(lookup::<F>(cache, *r)?)?;
             outputs.push(out);
         }
         Ok(outputs.into_iter().collect())
@@ -656,7 +680,7 @@ mod plaintext {
 mod bundle {
     use super::*;
     use crate::{
-        fancy::{BinaryGadgets, BundleGadgets, CrtGadgets},
+        fancy::{BinaryBundle, BinaryGadgets, BundleGadgets, CrtGadgets},
         util::{self, crt_factor, crt_inv_factor, RngExt},
     };
     use itertools::Itertools;
@@ -973,6 +997,102 @@ mod bundle {
         }
     }
     //}}}
+    #[test] // bundle min {{{
+    fn test_min() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_width(10);
+        let n = 10;
+
+        let mut b = CircuitBuilder::new();
+        let xs = (0..n).map(|_| b.crt_garbler_input(q)).collect_vec();
+        let z = b.crt_min(&xs, "100%").unwrap();
+        b.output_bundle(&z).unwrap();
+        let c = b.finish();
+
+        for _ in 0..16 {
+            let inps = (0..n).map(|_| rng.gen_u128() % (q / 2)).collect_vec();
+            let should_be = *inps.iter().min().unwrap();
+
+            let enc_inps = inps
+                .into_iter()
+                .flat_map(|x| crt_factor(x, q))
+                .collect_vec();
+            let res = c.eval_plain(&enc_inps, &[]).unwrap();
+            let z = crt_inv_factor(&res, q);
+            assert_eq!(z, should_be);
+        }
+    }
+    //}}}
+    #[test] // bundle division {{{
+    fn test_div_rem() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(8);
+        let nbits = 10;
+
+        let mut b = CircuitBuilder::new();
+        let x = b.crt_garbler_input(q);
+        let y = b.crt_evaluator_input(q);
+        let (z, r) = b.crt_div_rem(&x, &y, nbits, "100%").unwrap();
+        b.output_bundle(&z).unwrap();
+        b.output_bundle(&r).unwrap();
+        let c = b.finish();
+        let (en, gc) = crate::classic::garble(&c).unwrap();
+
+        for _ in 0..16 {
+            let x = rng.gen_u128() % (1 << nbits);
+            let y = 1 + rng.gen_u128() % ((1 << nbits) - 1);
+            let res = c.eval_plain(&crt_factor(x, q), &crt_factor(y, q)).unwrap();
+            let (z, r) = res.split_at(res.len() / 2);
+            assert_eq!(crt_inv_factor(z, q), x / y, "x={} y={}", x, y);
+            assert_eq!(crt_inv_factor(r, q), x % y, "x={} y={}", x, y);
+            let xs = en.encode_garbler_inputs(&crt_factor(x, q));
+            let ys = en.encode_evaluator_inputs(&crt_factor(y, q));
+            assert_eq!(gc.eval(&c, &xs, &ys).unwrap(), res);
+        }
+    }
+    //}}}
+    #[test] // CRT and binary conversions {{{
+    fn test_crt_bin_conversions() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(6);
+
+        // Compare the garbler's value with the low 8 bits of the evaluator's,
+        // mixing CRT arithmetic with bit operations.
+        let mut b = CircuitBuilder::new();
+        let x = b.crt_garbler_input(q);
+        let y = b.crt_evaluator_input(q);
+        let z = b.crt_add(&x, &y).unwrap();
+        let ys = b.crt_to_bin(&y).unwrap();
+        let low = BinaryBundle::new(ys.wires()[..8].to_vec());
+        let xs = b.crt_to_bin(&x).unwrap();
+        let eq = b
+            .bin_eq(&BinaryBundle::new(xs.wires()[..8].to_vec()), &low)
+            .unwrap();
+        let low = b.bin_to_crt(&low, q).unwrap();
+        b.output_bundle(&z).unwrap();
+        b.output_bundle(&low).unwrap();
+        b.output(&eq).unwrap();
+        let c = b.finish();
+        let (en, gc) = crate::classic::garble(&c).unwrap();
+
+        for _ in 0..16 {
+            let x = rng.gen_u128() % 256;
+            let y = if rng.gen_bool() {
+                x + 256 * (rng.gen_u128() % 100)
+            } else {
+                rng.gen_u128() % (q / 2)
+            };
+            let res = c.eval_plain(&crt_factor(x, q), &crt_factor(y, q)).unwrap();
+            let n = util::factor(q).len();
+            assert_eq!(crt_inv_factor(&res[..n], q), x + y);
+            assert_eq!(crt_inv_factor(&res[n..2 * n], q), y % 256);
+            assert_eq!(res[2 * n], (x == y % 256) as u16);
+            let xs = en.encode_garbler_inputs(&crt_factor(x, q));
+            let ys = en.encode_evaluator_inputs(&crt_factor(y, q));
+            assert_eq!(gc.eval(&c, &xs, &ys).unwrap(), res);
+        }
+    }
+    //}}}
     #[test] // binary addition {{{
     fn test_binary_addition() {
         let mut rng = thread_rng();
@@ -0,0 +1,589 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Reading and writing circuits in the Bristol Fashion format given here:
+//! <https://homes.esat.kuleuven.be/~nsmart/MPC/>.
+//!
+//! A Bristol Fashion file lists its input and output values, each a number of
+//! bits, followed by its gates in topological order. The input wires are the
+//! first wires of the circuit and the output wires its last. When reading a
+//! file, the first `ngarbler_values` input values are the garbler's inputs and
+//! the rest the evaluator's. When writing a circuit, its garbler inputs form
+//! the first input value and its evaluator inputs the second, and its outputs
+//! a single output value.
+//!
+//! The format does not mark the last use of a wire, so reading or evaluating
+//! a file keeps the value of every wire set so far until the end of the file:
+//! its memory grows with the number of wires the file uses, which is the
+//! limit on the circuits it handles, though not with the number it declares.
+
+use crate::{
+    circuit::{Circuit, CircuitBuilder, CircuitRef, Gate},
+    errors::{CircuitParserError as Error, FancyError},
+    fancy::{Fancy, HasModulus},
+};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+};
+
+fn invalid(msg: String) -> Error {
+    Error::InvalidCircuit(msg)
+}
+
+fn parse_usize(s: &str) -> Result<usize, Error> {
+    s.parse().map_err(Error::from)
+}
+
+// Read the integers `n v_1 ... v_n` of a header line.
+fn parse_values(line: &str) -> Result<Vec<usize>, Error> {
+    let mut tokens = line.split_whitespace();
+    let n = parse_usize(tokens.next().ok_or(Error::ParseIntError)?)?;
+    let values = tokens.map(parse_usize).collect::<Result<Vec<_>, _>>()?;
+    if values.len() != n {
+        return Err(Error::ParseLineError(line.to_string()));
+    }
+    Ok(values)
+}
+
+// The sum of `values`, failing if it overflows.
+fn checked_sum(values: &[usize]) -> Result<usize, Error> {
+    values
+        .iter()
+        .try_fold(0usize, |sum, &v| sum.checked_add(v))
+        .ok_or_else(|| invalid("the values have too many bits".to_string()))
+}
+
+/// Read a Bristol Fashion circuit from `filename`, whose first
+/// `ngarbler_values` input values are the garbler's.
+pub fn parse(filename: &str, ngarbler_values: usize) -> Result<Circuit, Error> {
+    read(BufReader::new(File::open(filename)?), ngarbler_values)
+}
+
+/// Read a Bristol Fashion circuit from `reader`, whose first
+/// `ngarbler_values` input values are the garbler's.
+pub fn read<R: BufRead>(reader: R, ngarbler_values: usize) -> Result<Circuit, Error> {
+    let mut b = CircuitBuilder::new();
+    run(&mut b, reader, ngarbler_values, |b, garbler, _| {
+        Ok(if garbler {
+            b.garbler_input(2)
+        } else {
+            b.evaluator_input(2)
+        })
+    })
+    .map_err(|e| match e {
+        Failure::Parse(e) => e,
+        Failure::Fancy(e) => invalid(e.to_string()),
+    })?;
+    Ok(b.finish())
+}
+
+/// Evaluate the Bristol Fashion circuit read from `reader`, whose first
+/// `ngarbler_values` input values are the garbler's, using fancy object `f`,
+/// gate by gate as it is read.
+///
+/// With a streaming `Garbler` or `Evaluator`, or those of `twopac`, the
+/// circuit is garbled and evaluated between two parties without either
+/// holding its gates or garbled tables, only the label of each wire it has
+/// set. Both parties must read the same circuit. Malformed files fail with
+/// `FancyError::InvalidArg`.
+pub fn eval<F: Fancy, R: BufRead>(
+    f: &mut F,
+    reader: R,
+    ngarbler_values: usize,
+    garbler_inputs: &[F::Item],
+    evaluator_inputs: &[F::Item],
+) -> Result<Option<Vec<u16>>, F::Error> {
+    let mut ninputs = (0, 0);
+    let outputs = run(f, reader, ngarbler_values, |_, garbler, k| {
+        let (inputs, n) = if garbler {
+            (garbler_inputs, &mut ninputs.0)
+        } else {
+            (evaluator_inputs, &mut ninputs.1)
+        };
+        *n += 1;
+        inputs.get(k).cloned().ok_or_else(|| {
+            F::Error::from(FancyError::InvalidArgNum {
+                got: inputs.len(),
+                needed: k + 1,
+            })
+        })
+    })
+    .map_err(|e| match e {
+        Failure::Parse(e) => F::Error::from(FancyError::InvalidArg(e.to_string())),
+        Failure::Fancy(e) => e,
+    })?;
+    for &(inputs, n) in [(garbler_inputs, ninputs.0), (evaluator_inputs, ninputs.1)].iter() {
+        if inputs.len() != n {
+            return Err(F::Error::from(FancyError::InvalidArgNum {
+                got: inputs.len(),
+                needed: n,
+            }));
+        }
+    }
+    Ok(outputs)
+}
+
+// The error of `run`, from the file or from the fancy object.
+enum Failure<E> {
+    Parse(Error),
+    Fancy(E),
+}
+
+impl<E> From<Error> for Failure<E> {
+    fn from(e: Error) -> Self {
+        Failure::Parse(e)
+    }
+}
+
+// Evaluate the circuit read from `reader` with `f`, taking the `k`th input
+// bit of the garbler, or of the evaluator, from `input(f, true, k)`, or
+// `input(f, false, k)`.
+fn run<F, R, I>(
+    f: &mut F,
+    reader: R,
+    ngarbler_values: usize,
+    mut input: I,
+) -> Result<Option<Vec<u16>>, Failure<F::Error>>
+where
+    F: Fancy,
+    R: BufRead,
+    I: FnMut(&mut F, bool, usize) -> Result<F::Item, F::Error>,
+{
+    let mut lines = reader.lines().filter(|line| match line {
+        Ok(line) => !line.trim().is_empty(),
+        Err(_) => true,
+    });
+    let mut next_line = || {
+        lines
+            .next()
+            .ok_or_else(|| invalid("the file ends early".to_string()))?
+            .map_err(Error::from)
+    };
+
+    // Parse the header: `ngates nwires`, then the input and output values.
+    let line = next_line()?;
+    let header = line
+        .split_whitespace()
+        .map(parse_usize)
+        .collect::<Result<Vec<_>, _>>()?;
+    let (ngates, nwires) = match header[..] {
+        [ngates, nwires] => (ngates, nwires),
+        _ => return Err(Error::ParseLineError(line).into()),
+    };
+    let inputs = parse_values(&next_line()?)?;
+    let outputs = parse_values(&next_line()?)?;
+    if ngarbler_values > inputs.len() {
+        return Err(invalid(format!(
+            "{} garbler values, but the circuit has {} input values",
+            ngarbler_values,
+            inputs.len()
+        ))
+        .into());
+    }
+    let ninputs = checked_sum(&inputs)?;
+    let noutputs = checked_sum(&outputs)?;
+    if ninputs.checked_add(noutputs).is_none_or(|n| n > nwires) {
+        return Err(invalid(format!(
+            "{} input and {} output wires, but only {} wires",
+            ninputs, noutputs, nwires
+        ))
+        .into());
+    }
+
+    // Only the wires set so far, rather than all `nwires` the header declares.
+    let mut wires: HashMap<usize, F::Item> = HashMap::new();
+    let mut w = 0;
+    let mut counts = (0, 0);
+    for (i, n) in inputs.iter().enumerate() {
+        let garbler = i < ngarbler_values;
+        for _ in 0..*n {
+            let k = if garbler {
+                &mut counts.0
+            } else {
+                &mut counts.1
+            };
+            wires.insert(w, input(f, garbler, *k).map_err(Failure::Fancy)?);
+            *k += 1;
+            w += 1;
+        }
+    }
+
+    for _ in 0..ngates {
+        let line = next_line()?;
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        if tokens.len() < 3 {
+            return Err(Error::ParseLineError(line).into());
+        }
+        let nin = parse_usize(tokens[0])?;
+        let nout = parse_usize(tokens[1])?;
+        let ntokens = nin.checked_add(nout).and_then(|n| n.checked_add(3));
+        if ntokens != Some(tokens.len()) {
+            return Err(Error::ParseLineError(line).into());
+        }
+        let ins = &tokens[2..2 + nin];
+        let outs = tokens[2 + nin..2 + nin + nout]
+            .iter()
+            .map(|t| {
+                let w = parse_usize(t)?;
+                if w < nwires {
+                    Ok(w)
+                } else {
+                    Err(invalid(format!("wire {} of `{}` is out of range", w, line)))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let wire = |t: &str| -> Result<F::Item, Error> {
+            let w = parse_usize(t)?;
+            wires
+                .get(&w)
+                .cloned()
+                .ok_or_else(|| invalid(format!("wire {} of `{}` is undefined", w, line)))
+        };
+        let op = tokens[2 + nin + nout];
+        let zs = match (op, nin, nout) {
+            ("XOR", 2, 1) => vec![f
+                .xor(&wire(ins[0])?, &wire(ins[1])?)
+                .map_err(Failure::Fancy)?],
+            ("AND", 2, 1) => vec![f
+                .and(&wire(ins[0])?, &wire(ins[1])?)
+                .map_err(Failure::Fancy)?],
+            ("INV", 1, 1) => vec![f.negate(&wire(ins[0])?).map_err(Failure::Fancy)?],
+            ("EQW", 1, 1) => vec![wire(ins[0])?],
+            ("EQ", 1, 1) => {
+                let val = match ins[0] {
+                    "0" => 0,
+                    "1" => 1,
+                    _ => return Err(Error::ParseLineError(line).into()),
+                };
+                vec![f.constant(val, 2).map_err(Failure::Fancy)?]
+            }
+            ("MAND", _, _) if nout.checked_mul(2) == Some(nin) => {
+                let mut zs = Vec::with_capacity(nout);
+                for i in 0..nout {
+                    let x = wire(ins[i])?;
+                    let y = wire(ins[nout + i])?;
+                    zs.push(f.and(&x, &y).map_err(Failure::Fancy)?);
+                }
+                zs
+            }
+            ("XOR", ..) | ("AND", ..) | ("INV", ..) | ("EQW", ..) | ("EQ", ..) | ("MAND", ..) => {
+                return Err(Error::ParseLineError(line).into());
+            }
+            _ => return Err(Error::ParseGateError(op.to_string()).into()),
+        };
+        for (w, z) in outs.into_iter().zip(zs) {
+            wires.insert(w, z);
+        }
+    }
+    if next_line().is_ok() {
+        return Err(invalid(format!("more than {} gates", ngates)).into());
+    }
+
+    let outputs = (0..noutputs)
+        .map(|i| {
+            wires
+                .remove(&(nwires - noutputs + i))
+                .ok_or_else(|| invalid(format!("output wire {} is undefined", i)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    f.outputs(&outputs).map_err(Failure::Fancy)
+}
+
+// The gates of a circuit being written, and the Bristol Fashion wire holding
+// the value of each wire of the circuit.
+struct Writer {
+    wires: Vec<Option<usize>>,
+    nwires: usize,
+    gates: Vec<String>,
+}
+
+impl Writer {
+    fn wire(&self, r: CircuitRef) -> Result<usize, Error> {
+        if r.modulus() != 2 {
+            return Err(Error::UnsupportedGate(format!("wire {} is not boolean", r)));
+        }
+        self.wires
+            .get(r.ix)
+            .copied()
+            .flatten()
+            .ok_or_else(|| invalid(format!("wire {} is read before it is set", r)))
+    }
+
+    // Add a gate over the wires or constant `ins`, returning its output wire.
+    fn gate(&mut self, ins: &[usize], op: &str) -> usize {
+        let z = self.nwires;
+        self.nwires += 1;
+        let ins = ins.iter().map(usize::to_string).collect::<Vec<_>>();
+        self.gates
+            .push(format!("{} 1 {} {} {}", ins.len(), ins.join(" "), z, op));
+        z
+    }
+}
+
+/// Write `circ` to `writer` in the Bristol Fashion format. Fails with
+/// `CircuitParserError::UnsupportedGate` unless every wire of `circ` is
+/// boolean.
+pub fn write<W: Write>(circ: &Circuit, mut writer: W) -> Result<(), Error> {
+    let n1 = circ.num_garbler_inputs();
+    let n2 = circ.num_evaluator_inputs();
+    // As large as `circ` itself, so that this keeps every wire too.
+    let mut w = Writer {
+        wires: vec![None; circ.gates.len()],
+        nwires: n1 + n2,
+        gates: Vec::with_capacity(circ.gates.len() + circ.noutputs()),
+    };
+    for (i, gate) in circ.gates.iter().enumerate() {
+        if circ.modulus(i) != 2 {
+            return Err(Error::UnsupportedGate(format!(
+                "gate {} is not boolean",
+                gate
+            )));
+        }
+        let (out, z) = match *gate {
+            Gate::GarblerInput { id } => (None, id),
+            Gate::EvaluatorInput { id } => (None, n1 + id),
+            Gate::Constant { val } => (None, w.gate(&[usize::from(val % 2)], "EQ")),
+            Gate::Add { xref, yref, out } | Gate::Sub { xref, yref, out } => {
+                let (x, y) = (w.wire(xref)?, w.wire(yref)?);
+                (out, w.gate(&[x, y], "XOR"))
+            }
+            Gate::Mul {
+                xref, yref, out, ..
+            } => {
+                let (x, y) = (w.wire(xref)?, w.wire(yref)?);
+                (out, w.gate(&[x, y], "AND"))
+            }
+            Gate::Cmul { xref, c, out } => {
+                let x = w.wire(xref)?;
+                if c % 2 == 1 {
+                    (out, w.gate(&[x], "EQW"))
+                } else {
+                    (out, w.gate(&[0], "EQ"))
+                }
+            }
+            Gate::Proj {
+                xref, ref tt, out, ..
+            } => {
+                let x = w.wire(xref)?;
+                match tt[..] {
+                    [0, 1] => (out, w.gate(&[x], "EQW")),
+                    [1, 0] => (out, w.gate(&[x], "INV")),
+                    [c, d] if c == d => (out, w.gate(&[usize::from(c % 2)], "EQ")),
+                    _ => return Err(Error::UnsupportedGate(gate.to_string())),
+                }
+            }
+        };
+        let ix = out.unwrap_or(i);
+        *w.wires
+            .get_mut(ix)
+            .ok_or_else(|| invalid(format!("gate {} writes an unknown wire", gate)))? = Some(z);
+    }
+    // The outputs must be the last wires.
+    for r in circ.output_refs.iter() {
+        let x = w.wire(*r)?;
+        w.gate(&[x], "EQW");
+    }
+
+    writeln!(writer, "{} {}", w.gates.len(), w.nwires)?;
+    writeln!(writer, "2 {} {}", n1, n2)?;
+    writeln!(writer, "1 {}", circ.noutputs())?;
+    writeln!(writer)?;
+    for gate in w.gates.iter() {
+        writeln!(writer, "{}", gate)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        classic::garble,
+        dummy::{Dummy, DummyVal},
+        twopac::semihonest::{Evaluator, Garbler},
+        util::RngExt,
+        FancyInput,
+    };
+    use itertools::Itertools;
+    use ocelot::ot::{ChouOrlandiReceiver, ChouOrlandiSender};
+    use rand::thread_rng;
+    use scuttlebutt::{unix_channel_pair, AesRng, UnixChannel};
+
+    // The bits of `x & y`, of `!x0`, of `x == y`, and a constant one, for
+    // 2-bit values `x` and `y`, exercising every kind of gate.
+    const EXAMPLE: &str = "11 16
+2 2 2
+3 2 1 2
+
+2 1 0 2 4 XOR
+2 1 1 3 5 XOR
+4 2 0 1 2 3 6 7 MAND
+1 1 4 8 INV
+1 1 5 9 INV
+2 1 8 9 10 AND
+1 1 6 11 EQW
+1 1 7 12 EQW
+1 1 0 13 INV
+1 1 10 14 EQW
+1 1 1 15 EQ
+";
+
+    #[test]
+    fn test_read() {
+        let circ = read(EXAMPLE.as_bytes(), 1).unwrap();
+        assert_eq!(circ.num_garbler_inputs(), 2);
+        assert_eq!(circ.num_evaluator_inputs(), 2);
+        assert_eq!(circ.noutputs(), 5);
+        for v in 0..16 {
+            let (x0, x1, y0, y1) = (v & 1, (v >> 1) & 1, (v >> 2) & 1, (v >> 3) & 1);
+            let eq = (x0 == y0 && x1 == y1) as u16;
+            let outputs = circ.eval_plain(&[x0, x1], &[y0, y1]).unwrap();
+            assert_eq!(outputs, vec![x0 & y0, x1 & y1, 1 - x0, eq, 1]);
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        let valid = "1 3\n1 2\n1 1\n\n2 1 0 1 2 XOR\n";
+        assert!(read(valid.as_bytes(), 1).is_ok());
+        assert!(read(valid.as_bytes(), 2).is_err());
+        let cases = [
+            "1 3\n1 2\n1 1\n\n2 1 0 1 2 NAND\n",
+            "1 3\n1 2\n1 1\n\n2 1 0 5 2 XOR\n",
+            "1 3\n1 2\n1 1\n\n2 1 0 1 2 INV\n",
+            "1 3\n1 2\n1 1\n\n1 1 2 2 EQW\n",
+            "2 3\n1 2\n1 1\n\n2 1 0 1 2 XOR\n",
+            "1 3\n1 2\n1 1\n\n2 1 0 1 2 XOR\n2 1 0 1 2 XOR\n",
+            "1 3\n2 2\n1 1\n\n2 1 0 1 2 XOR\n",
+            "1 3\n2 1 18446744073709551615\n1 1\n\n2 1 0 1 2 XOR\n",
+            "1 3\n1 2\n1 18446744073709551615\n\n2 1 0 1 2 XOR\n",
+            "1 3\n1 2\n1 1\n\n18446744073709551615 18446744073709551615 0 1 2 XOR\n",
+            "1 3\n1 2\n1 1\n\n2 9223372036854775809 0 1 2 MAND\n",
+        ];
+        for case in cases.iter() {
+            assert!(read(case.as_bytes(), 1).is_err(), "read `{}`", case);
+        }
+    }
+
+    #[test] // a file in the format, rather than one written by `write`
+    fn test_read_file() {
+        // The adder of `adder_32bit.txt`, whose header is rewritten in the
+        // Bristol Fashion format, adding 32-bit values least significant bit
+        // first.
+        let circ = read(
+            BufReader::new(File::open("circuits/adder_32bit_fashion.txt").unwrap()),
+            1,
+        )
+        .unwrap();
+        assert_eq!(circ.num_garbler_inputs(), 32);
+        assert_eq!(circ.num_evaluator_inputs(), 32);
+        assert_eq!(circ.noutputs(), 33);
+        let bits = |x: u64, n: usize| (0..n).map(|i| ((x >> i) & 1) as u16).collect_vec();
+        let mut rng = thread_rng();
+        for _ in 0..16 {
+            let (x, y) = (rng.gen_u128() as u32 as u64, rng.gen_u128() as u32 as u64);
+            let outputs = circ.eval_plain(&bits(x, 32), &bits(y, 32)).unwrap();
+            assert_eq!(outputs, bits(x + y, 33));
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut rng = thread_rng();
+        for &(filename, n1, n2) in [
+            ("circuits/adder_32bit.txt", 32, 32),
+            ("circuits/AES-non-expanded.txt", 128, 128),
+        ]
+        .iter()
+        {
+            let circ = Circuit::parse(filename).unwrap();
+            let mut bytes = Vec::new();
+            write(&circ, &mut bytes).unwrap();
+            let circ_ = read(bytes.as_slice(), 1).unwrap();
+            let mut bytes_ = Vec::new();
+            write(&circ_, &mut bytes_).unwrap();
+            let circ__ = read(bytes_.as_slice(), 1).unwrap();
+            let (en, gc) = garble(&circ__).unwrap();
+            for _ in 0..4 {
+                let gb = (0..n1).map(|_| rng.gen_u16() % 2).collect_vec();
+                let ev = (0..n2).map(|_| rng.gen_u16() % 2).collect_vec();
+                let should_be = circ.eval_plain(&gb, &ev).unwrap();
+                assert_eq!(circ_.eval_plain(&gb, &ev).unwrap(), should_be);
+                let outputs = gc
+                    .eval(
+                        &circ__,
+                        &en.encode_garbler_inputs(&gb),
+                        &en.encode_evaluator_inputs(&ev),
+                    )
+                    .unwrap();
+                assert_eq!(outputs, should_be);
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        let bits = |x: u16| [DummyVal::new(x & 1, 2), DummyVal::new(x >> 1, 2)];
+        let outputs = eval(&mut Dummy::new(), EXAMPLE.as_bytes(), 1, &bits(2), &bits(3));
+        assert_eq!(outputs.unwrap(), Some(vec![0, 1, 1, 0, 1]));
+        let too_few = eval(
+            &mut Dummy::new(),
+            EXAMPLE.as_bytes(),
+            1,
+            &bits(2),
+            &bits(3)[..1],
+        );
+        assert!(too_few.is_err());
+        let too_many = eval(&mut Dummy::new(), EXAMPLE.as_bytes(), 2, &bits(2), &bits(3));
+        assert!(too_many.is_err());
+        assert!(eval(&mut Dummy::new(), "1 3\n".as_bytes(), 1, &[], &[]).is_err());
+    }
+
+    #[test] // AES between two parties, streaming both the circuit and its tables
+    fn test_eval_twopac() {
+        let circ = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        let mut bytes = Vec::new();
+        write(&circ, &mut bytes).unwrap();
+        let mut rng = thread_rng();
+        let gb_inputs = (0..128).map(|_| rng.gen_u16() % 2).collect_vec();
+        let ev_inputs = (0..128).map(|_| rng.gen_u16() % 2).collect_vec();
+        let should_be = circ.eval_plain(&gb_inputs, &ev_inputs).unwrap();
+
+        let bytes_ = bytes.clone();
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let rng = AesRng::new();
+            let mut gb =
+                Garbler::<UnixChannel, AesRng, ChouOrlandiSender>::new(sender, rng).unwrap();
+            let xs = gb.encode_many(&gb_inputs, &[2; 128]).unwrap();
+            let ys = gb.receive_many(&[2; 128]).unwrap();
+            eval(&mut gb, bytes_.as_slice(), 1, &xs, &ys).unwrap()
+        });
+        let rng = AesRng::new();
+        let mut ev =
+            Evaluator::<UnixChannel, AesRng, ChouOrlandiReceiver>::new(receiver, rng).unwrap();
+        let xs = ev.receive_many(&[2; 128]).unwrap();
+        let ys = ev.encode_many(&ev_inputs, &[2; 128]).unwrap();
+        let outputs = eval(&mut ev, bytes.as_slice(), 1, &xs, &ys).unwrap();
+        assert_eq!(handle.join().unwrap(), None);
+        assert_eq!(outputs, Some(should_be));
+    }
+
+    #[test]
+    fn test_write_unsupported() {
+        let mut b = CircuitBuilder::new();
+        let x = b.evaluator_input(3);
+        let y = b.evaluator_input(3);
+        let z = b.add(&x, &y).unwrap();
+        b.output(&z).unwrap();
+        match write(&b.finish(), Vec::new()) {
+            Err(Error::UnsupportedGate(_)) => (),
+            r => panic!("wrote a mod-3 circuit: {:?}", r),
+        }
+    }
+}
@@ -8,15 +8,33 @@
 //! circuit without streaming.
 
 use crate::{
-    circuit::Circuit,
+    circuit::{Circuit, WireArena},
     errors::{EvaluatorError, GarblerError},
     fancy::HasModulus,
-    garble::{Evaluator, Garbler},
+    garble::{Evaluator, Garbler, Scheme},
     wire::Wire,
 };
 use itertools::Itertools;
-use scuttlebutt::{AbstractChannel, AesRng, Block, Channel};
-use std::{collections::HashMap, convert::TryInto, rc::Rc};
+use rand::Rng;
+use scuttlebutt::{
+    commitment::{Commitment, ShaCommitment},
+    AbstractChannel,
+    AesRng,
+    Block,
+    Channel,
+};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    io::{Read, Write},
+    rc::Rc,
+};
+
+// Leads the serialized form of a garbled circuit and of an encoder, followed
+// by `FORMAT_VERSION`.
+const CIRCUIT_MAGIC: [u8; 4] = *b"FGGC";
+const ENCODER_MAGIC: [u8; 4] = *b"FGEN";
+const FORMAT_VERSION: u8 = 1;
 
 /// Static evaluator for a circuit, created by the `garble` function.
 ///
@@ -25,12 +43,25 @@ use std::{collections::HashMap, convert::TryInto, rc::Rc};
 #[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub struct GarbledCircuit {
     blocks: Vec<Block>,
+    #[cfg_attr(feature = "serde1", serde(default))]
+    scheme: Scheme,
 }
 
 impl GarbledCircuit {
     /// Create a new object from a vector of garbled gates and constant wires.
     pub fn new(blocks: Vec<Block>) -> Self {
-        GarbledCircuit { blocks }
+        Self::with_scheme(blocks, Scheme::default())
+    }
+
+    /// Create a new object from a vector of garbled gates and constant wires,
+    /// whose AND gates are garbled with `scheme`.
+    pub fn with_scheme(blocks: Vec<Block>, scheme: Scheme) -> Self {
+        GarbledCircuit { blocks, scheme }
+    }
+
+    /// The scheme the AND gates are garbled with.
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
     }
 
     /// The number of garbled rows and constant wires in the garbled circuit.
@@ -38,22 +69,90 @@ impl GarbledCircuit {
         self.blocks.len()
     }
 
+    /// Commit to the garbled circuit with `seed`.
+    pub fn commit(&self, seed: [u8; 32]) -> [u8; 32] {
+        let mut commitment = ShaCommitment::new(seed);
+        for block in self.blocks.iter() {
+            commitment.input(block.as_ref());
+        }
+        commitment.finish()
+    }
+
+    /// Check that `commitment`, opened with `seed`, commits to this garbled
+    /// circuit.
+    pub fn verify(&self, commitment: &[u8; 32], seed: [u8; 32]) -> bool {
+        ShaCommitment::check(&self.commit(seed), commitment)
+    }
+
     /// Evaluate the garbled circuit.
     pub fn eval(
         &self,
         c: &Circuit,
         garbler_inputs: &[Wire],
         evaluator_inputs: &[Wire],
+    ) -> Result<Vec<u16>, EvaluatorError> {
+        let mut arena = WireArena::with_capacity(c.gates.len());
+        self.eval_with(c, garbler_inputs, evaluator_inputs, &mut arena)
+    }
+
+    /// Evaluate the garbled circuit, keeping wire-labels in `arena`, which
+    /// may be reused across evaluations to avoid reallocating it.
+    pub fn eval_with(
+        &self,
+        c: &Circuit,
+        garbler_inputs: &[Wire],
+        evaluator_inputs: &[Wire],
+        arena: &mut WireArena<Wire>,
     ) -> Result<Vec<u16>, EvaluatorError> {
         let channel = Channel::new(GarbledReader::new(&self.blocks), GarbledWriter::new(None));
         let mut evaluator = Evaluator::new(channel);
-        let outputs = c.eval(&mut evaluator, garbler_inputs, evaluator_inputs)?;
+        evaluator.set_scheme(self.scheme);
+        let outputs = c.eval_with(&mut evaluator, garbler_inputs, evaluator_inputs, arena)?;
         Ok(outputs.expect("evaluator outputs always are Some(u16)"))
     }
+
+    /// Serialize the garbled circuit to `writer`, so that it may be evaluated
+    /// later or elsewhere: a header and the scheme, then the number of blocks
+    /// and the blocks, which include the ciphertexts decoding the outputs.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write_header(writer, CIRCUIT_MAGIC)?;
+        let scheme = match self.scheme {
+            Scheme::Bmr16 => 0,
+            Scheme::HalfGates => 1,
+            Scheme::PrivacyFree => 2,
+        };
+        writer.write_all(&[scheme])?;
+        writer.write_all(&(self.blocks.len() as u64).to_le_bytes())?;
+        for block in self.blocks.iter() {
+            writer.write_all(block.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Deserialize a garbled circuit written by `write_to` from `reader`.
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        read_header(reader, CIRCUIT_MAGIC)?;
+        let scheme = match read_array::<_, 1>(reader)?[0] {
+            0 => Scheme::Bmr16,
+            1 => Scheme::HalfGates,
+            2 => Scheme::PrivacyFree,
+            s => return Err(invalid_data(format!("unknown garbling scheme {}", s))),
+        };
+        let blocks = read_vec(reader, read_block)?;
+        Ok(Self::with_scheme(blocks, scheme))
+    }
 }
 
 /// Garble a circuit without streaming.
 pub fn garble(c: &Circuit) -> Result<(Encoder, GarbledCircuit), GarblerError> {
+    garble_with_scheme(c, Scheme::default())
+}
+
+/// Garble a circuit without streaming, garbling its AND gates with `scheme`.
+pub fn garble_with_scheme(
+    c: &Circuit,
+    scheme: Scheme,
+) -> Result<(Encoder, GarbledCircuit), GarblerError> {
     let channel = Channel::new(
         GarbledReader::new(&[]),
         GarbledWriter::new(Some(c.num_nonfree_gates)),
@@ -62,6 +161,7 @@ pub fn garble(c: &Circuit) -> Result<(Encoder, GarbledCircuit), GarblerError> {
 
     let rng = AesRng::new();
     let mut garbler = Garbler::new(channel_, rng);
+    garbler.set_scheme(scheme);
 
     // get input wires, ignoring encoded values
     let gb_inps = (0..c.num_garbler_inputs())
@@ -84,21 +184,125 @@ pub fn garble(c: &Circuit) -> Result<(Encoder, GarbledCircuit), GarblerError> {
 
     let en = Encoder::new(gb_inps, ev_inps, garbler.get_deltas());
 
-    let gc = GarbledCircuit::new(
+    let gc = GarbledCircuit::with_scheme(
         Rc::try_unwrap(channel.writer())
             .unwrap()
             .into_inner()
             .blocks,
+        scheme,
     );
 
     Ok((en, gc))
 }
 
+/// Garble a circuit before the garbler has chosen its inputs.
+///
+/// Garbling only needs the zero wire-label of each input, so the whole
+/// circuit can be prepared offline. The returned `GarbledStructure` commits to
+/// the garbled circuit, so the garbler can bind itself to it, by sending
+/// `GarbledStructure::commitment`, before choosing its inputs, and supply
+/// their wire-labels in a later message with `GarbledStructure::encode_inputs`
+/// or `GarbledStructure::send_inputs`.
+pub fn garble_structure(c: &Circuit) -> Result<GarbledStructure, GarblerError> {
+    let (encoder, gc) = garble(c)?;
+    let seed = AesRng::new().gen::<[u8; 32]>();
+    let commitment = gc.commit(seed);
+    Ok(GarbledStructure {
+        encoder,
+        gc,
+        seed,
+        commitment,
+    })
+}
+
+/// A garbled circuit whose garbler inputs are yet to be supplied, created by
+/// the `garble_structure` function.
+pub struct GarbledStructure {
+    encoder: Encoder,
+    gc: GarbledCircuit,
+    seed: [u8; 32],
+    commitment: [u8; 32],
+}
+
+// The seed stays secret until the garbler opens the commitment.
+impl std::fmt::Debug for GarbledStructure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("GarbledStructure")
+            .field("encoder", &self.encoder)
+            .field("commitment", &self.commitment)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GarbledStructure {
+    /// The commitment to the garbled circuit.
+    pub fn commitment(&self) -> [u8; 32] {
+        self.commitment
+    }
+
+    /// The seed which opens the commitment, to send along with the garbled
+    /// circuit.
+    pub fn opening(&self) -> [u8; 32] {
+        self.seed
+    }
+
+    /// The garbled circuit.
+    pub fn circuit(&self) -> &GarbledCircuit {
+        &self.gc
+    }
+
+    /// The encoder, for the evaluator's inputs.
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    /// Encode the garbler's inputs into their wire-labels, checking that
+    /// there is one input of the right modulus for each garbler input wire.
+    pub fn encode_inputs(&self, inputs: &[u16]) -> Result<Vec<Wire>, GarblerError> {
+        if inputs.len() != self.encoder.num_garbler_inputs()
+            || inputs
+                .iter()
+                .zip(self.encoder.garbler_inputs.iter())
+                .any(|(&x, w)| x >= w.modulus())
+        {
+            return Err(GarblerError::EncodingError);
+        }
+        Ok(self.encoder.encode_garbler_inputs(inputs))
+    }
+
+    /// Encode the garbler's inputs and send their wire-labels over `channel`,
+    /// to be read with `receive_garbler_inputs`.
+    pub fn send_inputs<C: AbstractChannel>(
+        &self,
+        inputs: &[u16],
+        channel: &mut C,
+    ) -> Result<(), GarblerError> {
+        for w in self.encode_inputs(inputs)? {
+            channel.write_block(&w.as_block())?;
+        }
+        channel.flush()?;
+        Ok(())
+    }
+}
+
+/// Read the garbler-input wire-labels of `c` sent with
+/// `GarbledStructure::send_inputs`.
+pub fn receive_garbler_inputs<C: AbstractChannel>(
+    c: &Circuit,
+    channel: &mut C,
+) -> Result<Vec<Wire>, EvaluatorError> {
+    (0..c.num_garbler_inputs())
+        .map(|i| {
+            let block = channel.read_block()?;
+            Ok(Wire::from_block(block, c.garbler_input_mod(i)))
+        })
+        .collect()
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Encoder
 
 /// Encode inputs statically.
-#[derive(Debug)]
 #[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub struct Encoder {
     garbler_inputs: Vec<Wire>,
@@ -106,6 +310,28 @@ pub struct Encoder {
     deltas: HashMap<u16, Wire>,
 }
 
+#[cfg(feature = "zeroize")]
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        self.garbler_inputs.iter_mut().for_each(Wire::erase);
+        self.evaluator_inputs.iter_mut().for_each(Wire::erase);
+        self.deltas.values_mut().for_each(Wire::erase);
+    }
+}
+
+// Shows the shape of the inputs, but none of their labels or the deltas.
+impl std::fmt::Debug for Encoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut moduli = self.deltas.keys().collect::<Vec<_>>();
+        moduli.sort();
+        f.debug_struct("Encoder")
+            .field("ngarbler_inputs", &self.garbler_inputs.len())
+            .field("nevaluator_inputs", &self.evaluator_inputs.len())
+            .field("moduli", &moduli)
+            .finish()
+    }
+}
+
 impl Encoder {
     /// Make a new `Encoder` from lists of garbler and evaluator inputs,
     /// alongside a map of moduli-to-wire-offsets.
@@ -162,6 +388,116 @@ impl Encoder {
             .map(|(id, &x)| self.encode_evaluator_input(x, id))
             .collect()
     }
+
+    /// Serialize the encoder to `writer`: a header, then the zero
+    /// wire-labels of the garbler inputs and of the evaluator inputs, and the
+    /// deltas, each as a count followed by modulus and label pairs.
+    ///
+    /// The output holds the deltas, from which every wire-label of the
+    /// circuit can be computed, so it must never reach the evaluator.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write_header(writer, ENCODER_MAGIC)?;
+        let mut deltas = self.deltas.values().collect_vec();
+        deltas.sort_by_key(|w| w.modulus());
+        for wires in &[
+            self.garbler_inputs.iter().collect_vec(),
+            self.evaluator_inputs.iter().collect_vec(),
+            deltas,
+        ] {
+            writer.write_all(&(wires.len() as u64).to_le_bytes())?;
+            for w in wires {
+                writer.write_all(&w.modulus().to_le_bytes())?;
+                writer.write_all(w.as_block().as_ref())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserialize an encoder written by `write_to` from `reader`, checking
+    /// that it holds a delta for the modulus of each input.
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        read_header(reader, ENCODER_MAGIC)?;
+        let garbler_inputs = read_vec(reader, read_wire)?;
+        let evaluator_inputs = read_vec(reader, read_wire)?;
+        let deltas = read_vec(reader, read_wire)?
+            .into_iter()
+            .map(|w| (w.modulus(), w))
+            .collect::<HashMap<_, _>>();
+        if let Some(w) = garbler_inputs
+            .iter()
+            .chain(evaluator_inputs.iter())
+            .find(|w| !deltas.contains_key(&w.modulus()))
+        {
+            return Err(invalid_data(format!(
+                "no delta for modulus {}",
+                w.modulus()
+            )));
+        }
+        Ok(Self::new(garbler_inputs, evaluator_inputs, deltas))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Helpers for the serialized forms
+
+fn invalid_data(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+fn write_header<W: Write>(writer: &mut W, magic: [u8; 4]) -> std::io::Result<()> {
+    writer.write_all(&magic)?;
+    writer.write_all(&[FORMAT_VERSION])
+}
+
+fn read_header<R: Read>(reader: &mut R, magic: [u8; 4]) -> std::io::Result<()> {
+    if read_array::<_, 4>(reader)? != magic {
+        return Err(invalid_data("unexpected header".to_string()));
+    }
+    match read_array::<_, 1>(reader)?[0] {
+        FORMAT_VERSION => Ok(()),
+        v => Err(invalid_data(format!(
+            "unsupported format version {} (expected {})",
+            v, FORMAT_VERSION
+        ))),
+    }
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_block<R: Read>(reader: &mut R) -> std::io::Result<Block> {
+    Ok(Block::from(read_array::<_, 16>(reader)?))
+}
+
+fn read_wire<R: Read>(reader: &mut R) -> std::io::Result<Wire> {
+    let q = u16::from_le_bytes(read_array(reader)?);
+    let block = read_block(reader)?;
+    if q < 2 {
+        return Err(invalid_data(format!("invalid modulus {}", q)));
+    }
+    // A label mod 3 keeps the two bits of each digit in distinct halves.
+    let x = u128::from(block);
+    if q == 3 && (x as u64) & ((x >> 64) as u64) != 0 {
+        return Err(invalid_data("invalid wire-label mod 3".to_string()));
+    }
+    Ok(Wire::from_block(block, q))
+}
+
+// Reads a count, then that many items, without trusting the count to size
+// the allocation.
+fn read_vec<R: Read, T>(
+    reader: &mut R,
+    read: impl Fn(&mut R) -> std::io::Result<T>,
+) -> std::io::Result<Vec<T>> {
+    let n = u64::from_le_bytes(read_array(reader)?);
+    let mut items = Vec::with_capacity(n.min(1 << 16) as usize);
+    for _ in 0..n {
+        items.push(read(reader)?);
+    }
+    Ok(items)
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -169,21 +505,18 @@ impl Encoder {
 
 /// Implementation of the `Read` trait for use by the `Evaluator`.
 #[derive(Debug)]
-struct GarbledReader {
-    blocks: Vec<Block>,
+struct GarbledReader<'a> {
+    blocks: &'a [Block],
     index: usize,
 }
 
-impl GarbledReader {
-    fn new(blocks: &[Block]) -> Self {
-        Self {
-            blocks: blocks.to_vec(),
-            index: 0,
-        }
+impl<'a> GarbledReader<'a> {
+    fn new(blocks: &'a [Block]) -> Self {
+        Self { blocks, index: 0 }
     }
 }
 
-impl std::io::Read for GarbledReader {
+impl<'a> std::io::Read for GarbledReader<'a> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         assert_eq!(buf.len() % 16, 0);
         for data in buf.chunks_mut(16) {
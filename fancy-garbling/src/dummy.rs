@@ -147,7 +147,7 @@ impl FancyReveal for Dummy {
 mod bundle {
     use super::*;
     use crate::{
-        fancy::{BinaryGadgets, Bundle, BundleGadgets, CrtGadgets},
+        fancy::{BinaryBundle, BinaryGadgets, Bundle, BundleGadgets, CrtGadgets, FancyOutput},
         util::{self, RngExt},
     };
     use itertools::Itertools;
@@ -341,6 +341,14 @@ mod bundle {
             }
             assert_eq!(out > 0, should_be, "x={} y={}", x, y);
         }
+        // Random inputs rarely hit zero or equal values.
+        for &(x, y) in &[(0, 0), (0, 1), (1, 0), (5, 5), (q - 1, q - 1), (q - 1, 0)] {
+            let mut d = Dummy::new();
+            let x_ = d.bin_encode(x, nbits).unwrap();
+            let y_ = d.bin_encode(y, nbits).unwrap();
+            let z = d.bin_lt(&x_, &y_).unwrap();
+            assert_eq!(d.output(&z).unwrap().unwrap() > 0, x < y, "x={} y={}", x, y);
+        }
     }
 
     #[test]
@@ -366,6 +374,180 @@ mod bundle {
         }
     }
 
+    // Encode the signed integer `x` mod `q`.
+    fn signed(x: i128, q: u128) -> u128 {
+        if x < 0 {
+            q - x.unsigned_abs()
+        } else {
+            x as u128
+        }
+    }
+
+    #[test]
+    fn test_pow() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(8);
+        for _ in 0..NITERS {
+            let e = 1 + rng.gen_u16() % 4;
+            let x = (rng.gen_u128() % 61) as i128 - 30;
+            let mut d = Dummy::new();
+            let xs = d.crt_encode(signed(x, q), q).unwrap();
+            let z = d.crt_pow(&xs, e, 30).unwrap();
+            let out = d.crt_output(&z).unwrap().unwrap();
+            assert_eq!(out, signed(x.pow(e as u32), q));
+        }
+        let mut d = Dummy::new();
+        let xs = d.crt_encode(0, q).unwrap();
+        assert!(d.crt_pow(&xs, 8, 1000).is_err());
+        // Large exponents do not overflow the truth tables.
+        assert!(d.crt_cexp(&xs, u16::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_poly() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(8);
+        let coeffs = [7, -3, 0, 2];
+        for _ in 0..NITERS {
+            let x = (rng.gen_u128() % 201) as i128 - 100;
+            let mut d = Dummy::new();
+            let xs = d.crt_encode(signed(x, q), q).unwrap();
+            let z = d.crt_poly(&xs, &coeffs, 100).unwrap();
+            let out = d.crt_output(&z).unwrap().unwrap();
+            assert_eq!(out, signed(7 - 3 * x + 2 * x.pow(3), q));
+        }
+        let mut d = Dummy::new();
+        let xs = d.crt_encode(5, q).unwrap();
+        let z = d.crt_poly(&xs, &[-4], 0).unwrap();
+        assert_eq!(d.crt_output(&z).unwrap().unwrap(), q - 4);
+        match d.crt_poly(&xs, &[0, 0, 0, 0, 1], 1 << 20) {
+            Err(DummyError::FancyError(FancyError::Overflow { capacity })) => {
+                assert_eq!(capacity, (q - 1) / 2)
+            }
+            _ => panic!("expected an overflow"),
+        }
+    }
+
+    #[test]
+    fn test_div_rem() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(8);
+        let nbits = 10;
+        for _ in 0..64 {
+            let x = rng.gen_u128() % (1 << nbits);
+            let y = rng.gen_u128() % (1 << nbits);
+            let c = 1 + rng.gen_u128() % 300;
+            let mut d = Dummy::new();
+            let xs = d.crt_encode(x, q).unwrap();
+            let ys = d.crt_encode(y, q).unwrap();
+            let (z, r) = d.crt_cdiv_rem(&xs, c, nbits, "100%").unwrap();
+            let outs = d.crt_outputs(&[z, r]).unwrap().unwrap();
+            assert_eq!(outs, vec![x / c, x % c], "{} / {}", x, c);
+            let (z, r) = d.crt_div_rem(&xs, &ys, nbits, "100%").unwrap();
+            let outs = d.crt_outputs(&[z, r]).unwrap().unwrap();
+            match (x.checked_div(y), x.checked_rem(y)) {
+                (Some(z), Some(r)) => assert_eq!(outs, vec![z, r], "{} / {}", x, y),
+                _ => assert_eq!(outs, vec![(1 << nbits) - 1, x]),
+            }
+        }
+        let mut d = Dummy::new();
+        let xs = d.crt_encode(100, q).unwrap();
+        let (z, r) = d.crt_cdiv_rem(&xs, 1 << 20, nbits, "100%").unwrap();
+        assert_eq!(d.crt_outputs(&[z, r]).unwrap().unwrap(), vec![0, 100]);
+        assert!(d.crt_cdiv_rem(&xs, 0, nbits, "100%").is_err());
+        // The modulus holds signed integers of 22 bits, enough to compare the
+        // differences of 11 bit divisions but not 12 bit ones.
+        assert!(d.crt_div_rem(&xs, &xs, 11, "100%").is_ok());
+        match d.crt_div_rem(&xs, &xs, 12, "100%") {
+            Err(DummyError::FancyError(FancyError::Overflow { capacity })) => {
+                assert_eq!(capacity, (q - 1) / 2)
+            }
+            _ => panic!("expected an overflow"),
+        }
+        assert!(d.crt_cdiv_rem(&xs, 3, 23, "100%").is_err());
+    }
+
+    #[test]
+    fn test_crt_bin_conversions() {
+        let mut rng = thread_rng();
+        for &nprimes in &[1, 3, 8, 20] {
+            let q = util::modulus_with_nprimes(nprimes);
+            let nbits = (128 - (q - 1).leading_zeros()) as usize;
+            for _ in 0..16 {
+                let x = rng.gen_u128() % q;
+                let mut d = Dummy::new();
+                let xs = d.crt_encode(x, q).unwrap();
+                let bs = d.crt_to_bin(&xs).unwrap();
+                assert_eq!(bs.size(), nbits);
+                assert_eq!(d.bin_output(&bs).unwrap(), Some(x), "{} mod {}", x, q);
+                // Keeping the low bits truncates mod a power of two.
+                let low = BinaryBundle::new(bs.wires()[..nbits / 2].to_vec());
+                let zs = d.bin_to_crt(&low, q).unwrap();
+                assert_eq!(d.crt_output(&zs).unwrap(), Some(x % (1 << (nbits / 2))));
+                let zs = d.bin_to_crt(&bs, q).unwrap();
+                assert_eq!(d.crt_output(&zs).unwrap(), Some(x));
+            }
+        }
+        let mut d = Dummy::new();
+        let q = util::modulus_with_nprimes(4);
+        let bs = d.bin_encode(1000, 12).unwrap();
+        let zs = d.bin_to_crt(&bs, q).unwrap();
+        assert_eq!(d.crt_output(&zs).unwrap(), Some(1000 % q));
+        let xs = d.crt_encode(5, q).unwrap();
+        assert!(d
+            .bin_to_crt(&BinaryBundle::new(xs.wires().to_vec()), q)
+            .is_err());
+    }
+
+    #[test]
+    fn test_bin_eq() {
+        let mut rng = thread_rng();
+        for nbits in 1..10 {
+            let x = rng.gen_u128() % (1 << nbits);
+            let y = if rng.gen_bool() {
+                x
+            } else {
+                rng.gen_u128() % (1 << nbits)
+            };
+            let mut d = Dummy::new();
+            let xs = d.bin_encode(x, nbits).unwrap();
+            let ys = d.bin_encode(y, nbits).unwrap();
+            let z = d.bin_eq(&xs, &ys).unwrap();
+            assert_eq!(z.val(), (x == y) as u16);
+        }
+        let mut d = Dummy::new();
+        let empty = BinaryBundle::new(Vec::new());
+        assert_eq!(d.bin_eq(&empty, &empty).unwrap().val(), 1);
+    }
+
+    #[test]
+    fn test_typed_outputs() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(15);
+        for _ in 0..NITERS {
+            let x = (rng.gen_u64() % 2_000_001) as i64 - 1_000_000;
+            let y = (rng.gen_u64() % 2_000_001) as i64 - 1_000_000;
+            let mut d = Dummy::new();
+            let xs = d.crt_encode(signed(x as i128, q), q).unwrap();
+            let ys = d.crt_encode(signed(y as i128, q), q).unwrap();
+            let z = d.crt_mul(&xs, &ys).unwrap();
+            assert_eq!(d.output_i64(&z).unwrap(), Some(x * y));
+            let xs = d.bin_encode(signed(x as i128, 1 << 32), 32).unwrap();
+            let ys = d.bin_encode(signed(y as i128, 1 << 32), 32).unwrap();
+            let z = d.bin_subtraction(&xs, &ys).unwrap().0;
+            assert_eq!(d.output_i64(&z).unwrap(), Some(x - y));
+            assert_eq!(d.output_fixed(&z, 4).unwrap(), Some((x - y) as f64 / 16.0));
+        }
+        let mut d = Dummy::new();
+        let q = util::modulus_with_nprimes(20);
+        let xs = [
+            d.crt_encode(q - 1, q).unwrap(),
+            d.crt_encode(1 << 70, q).unwrap(),
+        ];
+        assert_eq!(d.output_i64(&xs[0]).unwrap(), Some(-1));
+        assert!(d.output_i64s(&xs).is_err());
+    }
+
     #[test] // bundle relu
     fn test_relu() {
         let mut rng = thread_rng();
@@ -39,6 +39,22 @@ pub enum FancyError {
     InvalidTruthTable,
     /// Uninitialized value encountered.
     UninitializedValue,
+    /// The result of a CRT computation may not fit in its modulus.
+    Overflow {
+        /// The largest magnitude the modulus can represent.
+        capacity: u128,
+    },
+    /// A gate built by a `CrtBundler` may overflow its modulus.
+    BundlerOverflow {
+        /// The number the gate would have had.
+        gate: usize,
+        /// The operation the gate performs.
+        op: &'static str,
+        /// The gates it takes as arguments.
+        args: Vec<usize>,
+        /// The largest magnitude the modulus can represent.
+        capacity: u128,
+    },
 }
 
 /// Errors from the dummy fancy object.
@@ -119,6 +135,21 @@ impl Display for FancyError {
             FancyError::UninitializedValue => {
                 "uninitialized value in circuit. is the circuit topologically sorted?".fmt(f)
             }
+            FancyError::Overflow { capacity } => write!(
+                f,
+                "result may exceed {}, the largest magnitude the modulus can hold",
+                capacity
+            ),
+            FancyError::BundlerOverflow {
+                gate,
+                op,
+                args,
+                capacity,
+            } => write!(
+                f,
+                "gate {} ({} of gates {:?}) may exceed {}, the largest magnitude the modulus can hold",
+                gate, op, args, capacity
+            ),
         }
     }
 }
@@ -256,6 +287,11 @@ pub enum CircuitParserError {
     ParseLineError(String),
     /// An error occurred parsing a gate type.
     ParseGateError(String),
+    /// The circuit parsed is not well formed, e.g., a gate reads an undefined
+    /// wire.
+    InvalidCircuit(String),
+    /// The circuit contains a gate the file format cannot express.
+    UnsupportedGate(String),
 }
 
 impl Display for CircuitParserError {
@@ -266,6 +302,8 @@ impl Display for CircuitParserError {
             CircuitParserError::ParseIntError => write!(f, "unable to parse integer"),
             CircuitParserError::ParseLineError(s) => write!(f, "unable to parse line '{}'", s),
             CircuitParserError::ParseGateError(s) => write!(f, "unable to parse gate '{}'", s),
+            CircuitParserError::InvalidCircuit(s) => write!(f, "invalid circuit: {}", s),
+            CircuitParserError::UnsupportedGate(s) => write!(f, "unsupported gate: {}", s),
         }
     }
 }
@@ -304,6 +342,15 @@ pub enum TwopacError {
     EvaluatorError(EvaluatorError),
     /// Processing the garbled circuit produced an error.
     FancyError(FancyError),
+    /// A run failed to complete by its deadline.
+    DeadlineExceeded {
+        /// The time since the deadline was made.
+        elapsed: std::time::Duration,
+        /// The number of bytes read from the peer.
+        bytes_read: u64,
+        /// The number of bytes written to the peer.
+        bytes_written: u64,
+    },
 }
 
 impl std::error::Error for TwopacError {}
@@ -346,6 +393,15 @@ impl std::fmt::Display for TwopacError {
             TwopacError::EvaluatorError(e) => write!(f, "evaluator error: {}", e),
             TwopacError::GarblerError(e) => write!(f, "garbler error: {}", e),
             TwopacError::FancyError(e) => write!(f, "fancy error: {}", e),
+            TwopacError::DeadlineExceeded {
+                elapsed,
+                bytes_read,
+                bytes_written,
+            } => write!(
+                f,
+                "deadline exceeded after {:?}, having read {} bytes and written {}",
+                elapsed, bytes_read, bytes_written
+            ),
         }
     }
 }
@@ -8,20 +8,50 @@
 //!
 //! An implementer must be able to create inputs, constants, do modular arithmetic, and
 //! create projections.
+//!
+//! Gadgets written against `Fancy` (together with the blanket `BundleGadgets`,
+//! `CrtGadgets` and `BinaryGadgets` extensions) run unchanged on every backend:
+//! `CircuitBuilder` records them as a `Circuit`, `Dummy` computes on plaintext,
+//! `Garbler` and `Evaluator` garble and evaluate them as they stream, and
+//! `Informer` counts their gates. Only the way inputs are made differs between
+//! backends, so a gadget should take its inputs as arguments:
+//!
+//! ```
+//! use fancy_garbling::{dummy::Dummy, Fancy, FancyInput, HasModulus};
+//!
+//! fn square_plus_one<F: Fancy>(f: &mut F, x: &F::Item) -> Result<Option<u16>, F::Error> {
+//!     let y = f.mul(x, x)?;
+//!     let one = f.constant(1, x.modulus())?;
+//!     let z = f.add(&y, &one)?;
+//!     f.output(&z)
+//! }
+//!
+//! let mut dummy = Dummy::new();
+//! let x = dummy.encode(3, 7).unwrap();
+//! assert_eq!(square_plus_one(&mut dummy, &x).unwrap(), Some(3));
+//! ```
 
 use crate::errors::FancyError;
 use itertools::Itertools;
 
 mod binary;
 mod bundle;
+mod bundler;
 mod crt;
+mod fixed;
 mod input;
+mod output;
 mod reveal;
+mod table;
 pub use binary::{BinaryBundle, BinaryGadgets};
 pub use bundle::{Bundle, BundleGadgets};
+pub use bundler::{CrtBundler, Provenance, Tracked};
 pub use crt::{CrtBundle, CrtGadgets};
+pub use fixed::{Fixed, FixedBundler};
 pub use input::FancyInput;
+pub use output::{FancyOutput, SignedBundle};
 pub use reveal::FancyReveal;
+pub use table::{LookupTable, MAX_PACKED_MODULUS};
 
 /// An object that has some modulus. Basic object of `Fancy` computations.
 pub trait HasModulus {
@@ -10,6 +10,7 @@ use crate::{
         bundle::{Bundle, BundleGadgets},
         Fancy,
         HasModulus,
+        LookupTable,
     },
     util,
 };
@@ -285,23 +286,11 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         // requiring special care to remove the y != 0, which is what follows.
         let (_, lhs) = self.bin_subtraction(x, y)?;
 
-        // Now we build a clause equal to (y == 0 || x >= y), which we can OR with
-        // lhs to remove the y==0 aspect.
-        // check if y==0
+        // Every x is at least y == 0, including x == 0, so OR-ing (y == 0)
+        // with lhs gives x >= y.
         let y_contains_1 = self.or_many(y.wires())?;
         let y_eq_0 = self.negate(&y_contains_1)?;
-
-        // if x != 0, then x >= y, ... assuming x is not negative
-        let x_contains_1 = self.or_many(x.wires())?;
-
-        // y == 0 && x >= y
-        let rhs = self.and(&y_eq_0, &x_contains_1)?;
-
-        // (y != 0 && x >= y) || (y == 0 && x >= y)
-        // => x >= y && (y != 0 || y == 0)\
-        // => x >= y && 1
-        // => x >= y
-        let geq = self.or(&lhs, &rhs)?;
+        let geq = self.or(&lhs, &y_eq_0)?;
         self.negate(&geq)
     }
 
@@ -315,6 +304,22 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         self.negate(&z)
     }
 
+    /// Returns 1 if `x == y`, and so for empty bundles. Takes an AND per
+    /// bit, where `eq_bundles` takes a projection into a larger modulus.
+    fn bin_eq(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<Self::Item, Self::Error> {
+        let diff = self.bin_xor(x, y)?;
+        let any = match diff.wires() {
+            [] => return self.constant(1, 2),
+            [d] => d.clone(),
+            ds => self.or_many(ds)?,
+        };
+        self.negate(&any)
+    }
+
     /// Compute the maximum bundle in `xs`.
     fn bin_max(
         &mut self,
@@ -344,6 +349,18 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         })
     }
 
+    /// Look up `x` in the public binary `table`, such as an S-box.
+    fn bin_lookup(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        table: &LookupTable,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        if table.output_moduli().iter().any(|&q| q != 2) {
+            return Err(Self::Error::from(FancyError::ArgNotBinary));
+        }
+        self.lookup(x, table).map(BinaryBundle)
+    }
+
     /// Demux a binary bundle into a unary vector.
     fn bin_demux(&mut self, x: &BinaryBundle<Self::Item>) -> Result<Vec<Self::Item>, Self::Error> {
         let wires = x.wires();
@@ -6,7 +6,7 @@
 
 use crate::{
     errors::FancyError,
-    fancy::{Fancy, HasModulus},
+    fancy::{Fancy, HasModulus, LookupTable},
 };
 use itertools::Itertools;
 use std::ops::Index;
@@ -402,4 +402,14 @@ pub trait BundleGadgets: Fancy {
         tab[b] = 1;
         self.proj(&z, 2, Some(tab))
     }
+
+    /// Look up `x` in the public `table`, returning a bundle with the table's
+    /// output moduli. See `LookupTable` for how the table is compiled.
+    fn lookup(
+        &mut self,
+        x: &Bundle<Self::Item>,
+        table: &LookupTable,
+    ) -> Result<Bundle<Self::Item>, Self::Error> {
+        table.eval(self, x.wires()).map(Bundle)
+    }
 }
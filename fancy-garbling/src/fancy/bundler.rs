@@ -0,0 +1,473 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `CrtBundler`, which tracks how large the values held in
+//! CRT bundles can get, so that overflows are caught while a circuit is built.
+//!
+//! A CRT bundle with composite modulus `Q` holds a signed integer of magnitude
+//! at most `(Q-1)/2`. The plain `CrtGadgets` wrap around silently beyond that;
+//! the bundler instead refuses to emit an operation whose result might not
+//! fit, returning `FancyError::BundlerOverflow` naming the offending gate.
+//! Every tracked bundle carries the number of the gate which made it, and
+//! `CrtBundler::provenance` says how that gate was built, so the chain of
+//! operations leading to an overflow can be walked back. Comparisons, and the
+//! `min` and `max` built on them, read the sign of the difference of their
+//! arguments, so the bundler also checks that the difference fits.
+
+use super::{crt::signed_capacity, CrtBundle, CrtGadgets};
+use crate::errors::FancyError;
+
+/// A CRT bundle made by a `CrtBundler`.
+#[derive(Clone)]
+pub struct Tracked<W> {
+    bundle: CrtBundle<W>,
+    gate: usize,
+}
+
+impl<W> Tracked<W> {
+    /// The underlying bundle.
+    #[inline]
+    pub fn bundle(&self) -> &CrtBundle<W> {
+        &self.bundle
+    }
+
+    /// Extract the underlying bundle.
+    #[inline]
+    pub fn into_bundle(self) -> CrtBundle<W> {
+        self.bundle
+    }
+
+    /// The number of the gate which made this bundle.
+    #[inline]
+    pub fn gate(&self) -> usize {
+        self.gate
+    }
+}
+
+/// How a gate of a `CrtBundler` was built.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Provenance {
+    /// The operation, such as `"mul"`.
+    pub op: &'static str,
+    /// The gates the operation takes as arguments.
+    pub args: Vec<usize>,
+    /// The largest magnitude the gate's output may have.
+    pub bound: u128,
+}
+
+/// A front end to the CRT gadgets of a `Fancy` object which tracks a bound on
+/// the magnitude of every bundle it makes.
+pub struct CrtBundler<'a, F> {
+    f: &'a mut F,
+    gates: Vec<Provenance>,
+}
+
+impl<'a, F: CrtGadgets> CrtBundler<'a, F> {
+    /// Make a bundler building gates with `f`.
+    pub fn new(f: &'a mut F) -> Self {
+        Self {
+            f,
+            gates: Vec::new(),
+        }
+    }
+
+    /// The underlying `Fancy` object, for operations which are not tracked.
+    #[inline]
+    pub fn fancy(&mut self) -> &mut F {
+        self.f
+    }
+
+    /// How `gate` was built, if it exists.
+    #[inline]
+    pub fn provenance(&self, gate: usize) -> Option<&Provenance> {
+        self.gates.get(gate)
+    }
+
+    /// The largest magnitude `x` may have.
+    #[inline]
+    pub fn bound(&self, x: &Tracked<F::Item>) -> u128 {
+        self.gates[x.gate].bound
+    }
+
+    /// Start tracking `x`, such as an input, which the caller promises has
+    /// magnitude at most `bound`.
+    pub fn track(
+        &mut self,
+        x: CrtBundle<F::Item>,
+        bound: u128,
+    ) -> Result<Tracked<F::Item>, F::Error> {
+        let gate = self.gate("input", &[], Some(bound), x.composite_modulus())?;
+        Ok(Tracked { bundle: x, gate })
+    }
+
+    /// Make a constant bundle holding `x` under composite modulus `q`.
+    pub fn constant(&mut self, x: i128, q: u128) -> Result<Tracked<F::Item>, F::Error> {
+        let gate = self.gate("constant", &[], Some(x.unsigned_abs()), q)?;
+        let residue = x.rem_euclid(q as i128) as u128;
+        let bundle = self.f.crt_constant_bundle(residue, q)?;
+        Ok(Tracked { bundle, gate })
+    }
+
+    /// Add `x` and `y`.
+    pub fn add(
+        &mut self,
+        x: &Tracked<F::Item>,
+        y: &Tracked<F::Item>,
+    ) -> Result<Tracked<F::Item>, F::Error> {
+        let bound = self.bound(x).checked_add(self.bound(y));
+        self.build("add", &[x, y], bound, |f| f.crt_add(&x.bundle, &y.bundle))
+    }
+
+    /// Subtract `y` from `x`.
+    pub fn sub(
+        &mut self,
+        x: &Tracked<F::Item>,
+        y: &Tracked<F::Item>,
+    ) -> Result<Tracked<F::Item>, F::Error> {
+        let bound = self.bound(x).checked_add(self.bound(y));
+        self.build("sub", &[x, y], bound, |f| f.crt_sub(&x.bundle, &y.bundle))
+    }
+
+    /// Multiply `x` by the constant `c`.
+    pub fn cmul(&mut self, x: &Tracked<F::Item>, c: u128) -> Result<Tracked<F::Item>, F::Error> {
+        let bound = self.bound(x).checked_mul(c);
+        self.build("cmul", &[x], bound, |f| f.crt_cmul(&x.bundle, c))
+    }
+
+    /// Multiply `x` and `y`.
+    pub fn mul(
+        &mut self,
+        x: &Tracked<F::Item>,
+        y: &Tracked<F::Item>,
+    ) -> Result<Tracked<F::Item>, F::Error> {
+        let bound = self.bound(x).checked_mul(self.bound(y));
+        self.build("mul", &[x, y], bound, |f| f.crt_mul(&x.bundle, &y.bundle))
+    }
+
+    /// Raise `x` to the power `e`.
+    pub fn pow(&mut self, x: &Tracked<F::Item>, e: u16) -> Result<Tracked<F::Item>, F::Error> {
+        let bound = self.bound(x).checked_pow(u32::from(e));
+        self.build("pow", &[x], bound, |f| f.crt_cexp(&x.bundle, e))
+    }
+
+    /// Divide `x` by the constant `c` with `crt_cdiv_rem`, returning the
+    /// quotient and the remainder. The caller promises that `x` is not
+    /// negative, as the bundler only tracks magnitudes.
+    pub fn cdiv_rem(
+        &mut self,
+        x: &Tracked<F::Item>,
+        c: u128,
+        accuracy: &str,
+    ) -> Result<(Tracked<F::Item>, Tracked<F::Item>), F::Error> {
+        let bx = self.bound(x);
+        let nbits = nbits(bx);
+        let (quotient, rem) = self.f.crt_cdiv_rem(&x.bundle, c, nbits, accuracy)?;
+        let q = x.bundle.composite_modulus();
+        let quotient = Tracked {
+            gate: self.gate("cdiv", &[x.gate], Some(bx / c), q)?,
+            bundle: quotient,
+        };
+        let rem = Tracked {
+            gate: self.gate("crem", &[x.gate], Some(bx.min(c - 1)), q)?,
+            bundle: rem,
+        };
+        Ok((quotient, rem))
+    }
+
+    /// Divide `x`, of either sign, by the constant `c` with `crt_sdiv`,
+    /// rounding toward zero.
+    pub fn sdiv(
+        &mut self,
+        x: &Tracked<F::Item>,
+        c: u128,
+        accuracy: &str,
+    ) -> Result<Tracked<F::Item>, F::Error> {
+        let bx = self.bound(x);
+        let bundle = self.f.crt_sdiv(&x.bundle, c, nbits(bx), accuracy)?;
+        let q = x.bundle.composite_modulus();
+        let gate = self.gate("sdiv", &[x.gate], Some(bx / c), q)?;
+        Ok(Tracked { bundle, gate })
+    }
+
+    /// Divide `x` by `y` with `crt_div_rem`, returning the quotient and the
+    /// remainder. The caller promises that neither is negative, as the bundler
+    /// only tracks magnitudes.
+    pub fn div_rem(
+        &mut self,
+        x: &Tracked<F::Item>,
+        y: &Tracked<F::Item>,
+        accuracy: &str,
+    ) -> Result<(Tracked<F::Item>, Tracked<F::Item>), F::Error> {
+        let (bx, by) = (self.bound(x), self.bound(y));
+        let nbits = nbits(bx.max(by));
+        let (quotient, rem) = self.f.crt_div_rem(&x.bundle, &y.bundle, nbits, accuracy)?;
+        let q = x.bundle.composite_modulus();
+        let args = [x.gate, y.gate];
+        // Dividing by zero gives the largest quotient `2^nbits - 1`.
+        let bound = (1 << nbits) - 1;
+        let quotient = Tracked {
+            gate: self.gate("div", &args, Some(bound), q)?,
+            bundle: quotient,
+        };
+        let rem = Tracked {
+            gate: self.gate("rem", &args, Some(bx), q)?,
+            bundle: rem,
+        };
+        Ok((quotient, rem))
+    }
+
+    /// Return 1 if `x < y`, comparing with `crt_lt`.
+    pub fn lt(
+        &mut self,
+        x: &Tracked<F::Item>,
+        y: &Tracked<F::Item>,
+        accuracy: &str,
+    ) -> Result<F::Item, F::Error> {
+        self.check_difference("lt", x, y)?;
+        self.f.crt_lt(&x.bundle, &y.bundle, accuracy)
+    }
+
+    /// Return 1 if `x >= y`, comparing with `crt_geq`.
+    pub fn geq(
+        &mut self,
+        x: &Tracked<F::Item>,
+        y: &Tracked<F::Item>,
+        accuracy: &str,
+    ) -> Result<F::Item, F::Error> {
+        self.check_difference("geq", x, y)?;
+        self.f.crt_geq(&x.bundle, &y.bundle, accuracy)
+    }
+
+    /// The smaller of `x` and `y`, with `crt_min`.
+    pub fn min(
+        &mut self,
+        x: &Tracked<F::Item>,
+        y: &Tracked<F::Item>,
+        accuracy: &str,
+    ) -> Result<Tracked<F::Item>, F::Error> {
+        self.check_difference("min", x, y)?;
+        let bound = self.bound(x).max(self.bound(y));
+        self.build("min", &[x, y], Some(bound), |f| {
+            f.crt_min(&[x.bundle.clone(), y.bundle.clone()], accuracy)
+        })
+    }
+
+    /// The larger of `x` and `y`, with `crt_max`.
+    pub fn max(
+        &mut self,
+        x: &Tracked<F::Item>,
+        y: &Tracked<F::Item>,
+        accuracy: &str,
+    ) -> Result<Tracked<F::Item>, F::Error> {
+        self.check_difference("max", x, y)?;
+        let bound = self.bound(x).max(self.bound(y));
+        self.build("max", &[x, y], Some(bound), |f| {
+            f.crt_max(&[x.bundle.clone(), y.bundle.clone()], accuracy)
+        })
+    }
+
+    /// Output `x` and interpret it mod Q.
+    pub fn output(&mut self, x: &Tracked<F::Item>) -> Result<Option<u128>, F::Error> {
+        self.f.crt_output(&x.bundle)
+    }
+
+    // Check the bound of a new gate over `args` and, if it fits, run `op` to
+    // make it.
+    fn build(
+        &mut self,
+        name: &'static str,
+        args: &[&Tracked<F::Item>],
+        bound: Option<u128>,
+        op: impl FnOnce(&mut F) -> Result<CrtBundle<F::Item>, F::Error>,
+    ) -> Result<Tracked<F::Item>, F::Error> {
+        let q = args[0].bundle.composite_modulus();
+        let args = args.iter().map(|x| x.gate).collect::<Vec<_>>();
+        let gate = self.gate(name, &args, bound, q)?;
+        let bundle = op(self.f)?;
+        Ok(Tracked { bundle, gate })
+    }
+
+    // A comparison of `x` and `y` takes the sign of their difference, which
+    // must fit in the modulus for the result to be right.
+    fn check_difference(
+        &self,
+        op: &'static str,
+        x: &Tracked<F::Item>,
+        y: &Tracked<F::Item>,
+    ) -> Result<(), F::Error> {
+        let bound = self.bound(x).checked_add(self.bound(y));
+        let q = x.bundle.composite_modulus();
+        self.check(op, &[x.gate, y.gate], bound, q).map(|_| ())
+    }
+
+    // Check that `bound`, where `None` means it does not fit in a `u128`, is
+    // small enough for modulus `q`, returning it if so.
+    fn check(
+        &self,
+        op: &'static str,
+        args: &[usize],
+        bound: Option<u128>,
+        q: u128,
+    ) -> Result<u128, F::Error> {
+        let capacity = signed_capacity(q);
+        match bound {
+            Some(bound) if bound <= capacity => Ok(bound),
+            _ => Err(FancyError::BundlerOverflow {
+                gate: self.gates.len(),
+                op,
+                args: args.to_vec(),
+                capacity,
+            }
+            .into()),
+        }
+    }
+
+    // Record a new gate, failing as `check` if its bound is too large.
+    fn gate(
+        &mut self,
+        op: &'static str,
+        args: &[usize],
+        bound: Option<u128>,
+        q: u128,
+    ) -> Result<usize, F::Error> {
+        let bound = self.check(op, args, bound, q)?;
+        self.gates.push(Provenance {
+            op,
+            args: args.to_vec(),
+            bound,
+        });
+        Ok(self.gates.len() - 1)
+    }
+}
+
+// The number of bits of a value of magnitude at most `bound`, and at least one.
+fn nbits(bound: u128) -> usize {
+    (128 - bound.leading_zeros() as usize).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        circuit::CircuitBuilder,
+        dummy::Dummy,
+        errors::DummyError,
+        fancy::FancyInput,
+        util::{self, RngExt},
+    };
+    use rand::thread_rng;
+
+    #[test]
+    fn test_bundler() {
+        let q = util::modulus_with_nprimes(6); // 30030
+        let mut d = Dummy::new();
+        let x = d.crt_encode(q - 7, q).unwrap();
+        let y = d.crt_encode(12, q).unwrap();
+        let mut b = CrtBundler::new(&mut d);
+        let x = b.track(x, 10).unwrap();
+        let y = b.track(y, 20).unwrap();
+        let three = b.constant(-3, q).unwrap();
+        let z = b.add(&x, &y).unwrap();
+        let z = b.mul(&z, &three).unwrap();
+        let z = b.sub(&z, &y).unwrap();
+        assert_eq!(b.bound(&z), 110);
+        assert_eq!(b.output(&z).unwrap(), Some(q - 27));
+        assert_eq!(
+            b.provenance(z.gate()),
+            Some(&Provenance {
+                op: "sub",
+                args: vec![4, 1],
+                bound: 110,
+            })
+        );
+        let w = b.pow(&z, 2).unwrap();
+        assert_eq!(b.output(&w).unwrap(), Some(729));
+        match b.mul(&w, &z) {
+            Err(DummyError::FancyError(FancyError::BundlerOverflow {
+                gate,
+                op,
+                args,
+                capacity,
+            })) => {
+                assert_eq!((gate, op, args, capacity), (7, "mul", vec![6, 5], 15014));
+            }
+            _ => panic!("expected an overflow"),
+        }
+        assert!(b.cmul(&x, u128::MAX).is_err());
+        assert!(b.constant(-20000, q).is_err());
+    }
+
+    #[test]
+    fn test_div_rem() {
+        let q = util::modulus_with_nprimes(8);
+        let mut d = Dummy::new();
+        let x = d.crt_encode(1000, q).unwrap();
+        let y = d.crt_encode(37, q).unwrap();
+        let mut b = CrtBundler::new(&mut d);
+        let x = b.track(x, 1023).unwrap();
+        let y = b.track(y, 100).unwrap();
+        let (z, r) = b.div_rem(&x, &y, "100%").unwrap();
+        assert_eq!(b.output(&z).unwrap(), Some(27));
+        assert_eq!(b.output(&r).unwrap(), Some(1));
+        assert_eq!(b.provenance(r.gate()).unwrap().args, vec![0, 1]);
+        let (z, r) = b.cdiv_rem(&x, 7, "100%").unwrap();
+        assert_eq!(b.output(&z).unwrap(), Some(142));
+        assert_eq!(b.output(&r).unwrap(), Some(6));
+        assert_eq!((b.bound(&z), b.bound(&r)), (146, 6));
+        // The differences compared no longer fit.
+        let w = b.cmul(&x, 4).unwrap();
+        assert!(b.div_rem(&w, &y, "100%").is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        let q = util::modulus_with_nprimes(6); // 30030
+        let mut rng = thread_rng();
+        for _ in 0..64 {
+            let (x, y) = (rng.gen_u128() % 2001, rng.gen_u128() % 2001);
+            let (x, y) = (x as i128 - 1000, y as i128 - 1000);
+            let mut d = Dummy::new();
+            let mut b = CrtBundler::new(&mut d);
+            let xs = b.constant(x, q).unwrap();
+            let ys = b.constant(y, q).unwrap();
+            let lt = b.lt(&xs, &ys, "100%").unwrap();
+            let geq = b.geq(&xs, &ys, "100%").unwrap();
+            assert_eq!((lt.val(), geq.val()), ((x < y) as u16, (x >= y) as u16));
+            let min = b.min(&xs, &ys, "100%").unwrap();
+            let max = b.max(&xs, &ys, "100%").unwrap();
+            let signed = |z: i128| z.rem_euclid(q as i128) as u128;
+            assert_eq!(b.output(&min).unwrap(), Some(signed(x.min(y))));
+            assert_eq!(b.output(&max).unwrap(), Some(signed(x.max(y))));
+            assert_eq!(b.bound(&max), x.unsigned_abs().max(y.unsigned_abs()));
+        }
+        // A difference which may not fit is refused rather than misread.
+        let mut d = Dummy::new();
+        let mut b = CrtBundler::new(&mut d);
+        let x = b.constant(10000, q).unwrap();
+        let y = b.constant(-10000, q).unwrap();
+        match b.lt(&x, &y, "100%") {
+            Err(DummyError::FancyError(FancyError::BundlerOverflow { gate, op, .. })) => {
+                assert_eq!((gate, op), (2, "lt"))
+            }
+            _ => panic!("expected an overflow"),
+        }
+        assert!(b.max(&x, &y, "100%").is_err());
+        assert!(b.provenance(2).is_none());
+    }
+
+    #[test]
+    fn test_build_time() {
+        // Overflows are caught before any gate is added to the circuit.
+        let q = util::modulus_with_nprimes(4); // 210
+        let mut c = CircuitBuilder::new();
+        let x = c.crt_evaluator_input(q);
+        let mut b = CrtBundler::new(&mut c);
+        let x = b.track(x, 5).unwrap();
+        let y = b.mul(&x, &x).unwrap();
+        assert!(b.mul(&y, &x).is_err());
+        assert!(b.pow(&x, 3).is_err());
+    }
+}
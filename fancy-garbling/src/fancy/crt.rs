@@ -9,7 +9,10 @@
 use super::{Fancy, HasModulus};
 use crate::{
     errors::FancyError,
-    fancy::bundle::{Bundle, BundleGadgets},
+    fancy::{
+        binary::{BinaryBundle, BinaryGadgets},
+        bundle::{Bundle, BundleGadgets},
+    },
     util,
 };
 use itertools::Itertools;
@@ -142,8 +145,9 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
             .iter()
             .map(|x| {
                 let p = x.modulus();
+                let b = util::Barrett::new(u64::from(p));
                 let tab = (0..p)
-                    .map(|x| ((x as u64).pow(c as u32) % p as u64) as u16)
+                    .map(|x| b.pow(u64::from(x), u64::from(c)) as u16)
                     .collect_vec();
                 self.proj(x, p, Some(tab))
             })
@@ -151,6 +155,84 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
             .map(CrtBundle::new)
     }
 
+    /// Compute `x^e` for `x` a signed integer (see `crt_sign`) with `|x| <=
+    /// bound`, with one projection per wire.
+    ///
+    /// Fails with `FancyError::Overflow` if `bound^e` does not fit in the
+    /// signed range of the modulus, so that the result is never silently
+    /// reduced.
+    fn crt_pow(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        e: u16,
+        bound: u128,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let capacity = signed_capacity(x.composite_modulus());
+        match bound.checked_pow(u32::from(e)) {
+            Some(b) if b <= capacity => self.crt_cexp(x, e),
+            _ => Err(Self::Error::from(FancyError::Overflow { capacity })),
+        }
+    }
+
+    /// Evaluate the polynomial with coefficients `coeffs`, constant term
+    /// first, on `x` a signed integer (see `crt_sign`) with `|x| <= bound`,
+    /// using Horner's scheme: one multiplication per degree above one.
+    ///
+    /// Negative coefficients are reduced mod the composite modulus. Fails with
+    /// `FancyError::Overflow` if the result may not fit in the signed range of
+    /// the modulus; the intermediate values may wrap, as arithmetic mod the
+    /// modulus is exact.
+    fn crt_poly(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        coeffs: &[i128],
+        bound: u128,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let q = x.composite_modulus();
+        let capacity = signed_capacity(q);
+        // Bound `sum |a_i| bound^i`.
+        let mut power = Some(1u128);
+        let mut total = Some(0u128);
+        for &a in coeffs {
+            total = power
+                .and_then(|p| p.checked_mul(a.unsigned_abs()))
+                .and_then(|t| total?.checked_add(t));
+            power = power.and_then(|p| p.checked_mul(bound));
+        }
+        match total {
+            Some(t) if t <= capacity => (),
+            _ => return Err(Self::Error::from(FancyError::Overflow { capacity })),
+        }
+        let residue = |a: i128| {
+            let r = a.unsigned_abs() % q;
+            if a < 0 && r != 0 {
+                q - r
+            } else {
+                r
+            }
+        };
+        let moduli = x.moduli();
+        let constant = |f: &mut Self, a: i128| {
+            f.constant_bundle(&util::crt(residue(a), &moduli), &moduli)
+                .map(CrtBundle)
+        };
+        let n = coeffs.len();
+        if n < 2 {
+            return constant(self, coeffs.first().cloned().unwrap_or(0));
+        }
+        // The leading coefficient is public, so the first step is a free
+        // constant multiplication.
+        let mut acc = self.crt_cmul(x, residue(coeffs[n - 1]))?;
+        for (i, &a) in coeffs[..n - 1].iter().enumerate().rev() {
+            let c = constant(self, a)?;
+            acc = self.crt_add(&acc, &c)?;
+            if i > 0 {
+                acc = self.crt_mul(&acc, x)?;
+            }
+        }
+        Ok(acc)
+    }
+
     /// Compute the remainder with respect to modulus `p`.
     fn crt_rem(
         &mut self,
@@ -327,6 +409,251 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
             })?
         })
     }
+
+    /// Compute the minimum bundle in `xs`.
+    fn crt_min(
+        &mut self,
+        xs: &[CrtBundle<Self::Item>],
+        accuracy: &str,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        if xs.len() < 2 {
+            return Err(Self::Error::from(FancyError::InvalidArgNum {
+                got: xs.len(),
+                needed: 2,
+            }));
+        }
+        let mut min = xs[0].clone();
+        for y in xs.iter().skip(1) {
+            let pos = self.crt_lt(&min, y, accuracy)?;
+            let neg = self.negate(&pos)?;
+            min = min
+                .wires()
+                .iter()
+                .zip(y.wires().iter())
+                .map(|(x, y)| {
+                    let xp = self.mul(x, &pos)?;
+                    let yp = self.mul(y, &neg)?;
+                    self.add(&xp, &yp)
+                })
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()
+                .map(CrtBundle::new)?;
+        }
+        Ok(min)
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Division, by long division over the bits of the quotient.
+
+    /// Divide `x` by the constant `c`, returning the quotient and the
+    /// remainder, for `x` an integer in `[0, 2^nbits)`.
+    ///
+    /// Each bit of the quotient costs a comparison, as in `crt_geq`, so the
+    /// result is exact only with `accuracy = "100%"`. Fails with
+    /// `FancyError::Overflow` unless `2^nbits - 1` fits in the signed range of
+    /// the modulus, and with `FancyError::InvalidArg` if `c` is zero.
+    fn crt_cdiv_rem(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        c: u128,
+        nbits: usize,
+        accuracy: &str,
+    ) -> Result<(CrtBundle<Self::Item>, CrtBundle<Self::Item>), Self::Error> {
+        let q = x.composite_modulus();
+        let capacity = signed_capacity(q);
+        if c == 0 {
+            return Err(Self::Error::from(FancyError::InvalidArg(
+                "division by zero".to_string(),
+            )));
+        }
+        if nbits >= 128 || 1 << nbits > capacity + 1 {
+            return Err(Self::Error::from(FancyError::Overflow { capacity }));
+        }
+        let ps = x.moduli();
+        let mut quotient = self.crt_constant_bundle(0, q)?;
+        let mut rem = x.clone();
+        // The quotient has a bit `i` only if `c * 2^i < 2^nbits`.
+        let nsteps = (nbits + 1).saturating_sub(bitlen(c));
+        for i in (0..nsteps).rev() {
+            let t = c << i;
+            let ts = self.crt_constant_bundle(t, q)?;
+            let bit = self.crt_geq(&rem, &ts, accuracy)?;
+            let mut rs = Vec::with_capacity(ps.len());
+            let mut qs = Vec::with_capacity(ps.len());
+            for ((r, z), &p) in rem.wires().iter().zip(quotient.wires()).zip(ps.iter()) {
+                let dr = self.proj(&bit, p, Some(vec![0, (t % p as u128) as u16]))?;
+                let dq = self.proj(&bit, p, Some(vec![0, ((1u128 << i) % p as u128) as u16]))?;
+                rs.push(self.sub(r, &dr)?);
+                qs.push(self.add(z, &dq)?);
+            }
+            rem = CrtBundle::new(rs);
+            quotient = CrtBundle::new(qs);
+        }
+        Ok((quotient, rem))
+    }
+
+    /// Divide `x`, a signed integer (see `crt_sign`) with `|x| < 2^nbits`, by
+    /// the constant `c`, rounding toward zero.
+    ///
+    /// Divides the magnitude of `x` with `crt_cdiv_rem`, under the same
+    /// constraints, at the cost of a sign and two multiplications more.
+    fn crt_sdiv(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        c: u128,
+        nbits: usize,
+        accuracy: &str,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let sgn = self.crt_sgn(x, accuracy, None)?;
+        let abs = self.crt_mul(x, &sgn)?;
+        let (quotient, _) = self.crt_cdiv_rem(&abs, c, nbits, accuracy)?;
+        self.crt_mul(&quotient, &sgn)
+    }
+
+    /// Divide `x` by `y`, returning the quotient and the remainder, for `x`
+    /// and `y` integers in `[0, 2^nbits)`. Dividing by zero gives the quotient
+    /// `2^nbits - 1` and the remainder `x`.
+    ///
+    /// Each bit of the quotient costs a multiplication of `y` and a
+    /// comparison, as in `crt_geq`, so the result is exact only with
+    /// `accuracy = "100%"`. Fails with `FancyError::Overflow` unless
+    /// `2^(2*nbits - 1)` fits in the signed range of the modulus, which bounds
+    /// the differences compared.
+    fn crt_div_rem(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        y: &CrtBundle<Self::Item>,
+        nbits: usize,
+        accuracy: &str,
+    ) -> Result<(CrtBundle<Self::Item>, CrtBundle<Self::Item>), Self::Error> {
+        let q = x.composite_modulus();
+        let capacity = signed_capacity(q);
+        if nbits == 0 || 2 * nbits > 128 || 1 << (2 * nbits - 1) > capacity {
+            return Err(Self::Error::from(FancyError::Overflow { capacity }));
+        }
+        let ps = x.moduli();
+        let mut quotient = self.crt_constant_bundle(0, q)?;
+        let mut rem = x.clone();
+        for i in (0..nbits).rev() {
+            let ts = self.crt_cmul(y, 1 << i)?;
+            let bit = self.crt_geq(&rem, &ts, accuracy)?;
+            let mut rs = Vec::with_capacity(ps.len());
+            let mut qs = Vec::with_capacity(ps.len());
+            for (((r, z), t), &p) in rem
+                .wires()
+                .iter()
+                .zip(quotient.wires())
+                .zip(ts.wires())
+                .zip(ps.iter())
+            {
+                let dr = self.mul(t, &bit)?;
+                let dq = self.proj(&bit, p, Some(vec![0, ((1u128 << i) % p as u128) as u16]))?;
+                rs.push(self.sub(r, &dr)?);
+                qs.push(self.add(z, &dq)?);
+            }
+            rem = CrtBundle::new(rs);
+            quotient = CrtBundle::new(qs);
+        }
+        Ok((quotient, rem))
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // Conversions between CRT and binary representations.
+
+    /// Convert `x` to its binary representation, with as many bits as the
+    /// largest value mod Q.
+    ///
+    /// By the CRT, `x` is the sum mod Q of `r_i * c_i` over its residues `r_i`,
+    /// for constants `c_i`. Each term is projected into bits from its
+    /// residue, the terms are summed with binary adders, and the sum is
+    /// reduced mod Q with a conditional subtraction per bit of the number of
+    /// primes.
+    fn crt_to_bin(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error>
+    where
+        Self: Sized,
+    {
+        let q = x.composite_modulus();
+        let ps = x.moduli();
+        let k = ps.len() as u128;
+        let nbits = bitlen(q - 1);
+        // The sum of the terms is below `k * Q`.
+        let width = q
+            .checked_mul(k)
+            .map(|kq| bitlen(kq - 1))
+            .ok_or_else(|| Self::Error::from(FancyError::Overflow { capacity: q }))?;
+        let mut sum = self.bin_constant_bundle(0, width)?;
+        for (r, &p) in x.wires().iter().zip(ps.iter()) {
+            let qp = q / p as u128;
+            let c = qp * util::inv((qp % p as u128) as i128, p as i128) as u128;
+            // The term `r * c mod Q` for each value of `r`.
+            let mut terms = Vec::with_capacity(p as usize);
+            let mut t = 0;
+            for _ in 0..p {
+                terms.push(t);
+                t = if t >= q - c { t - (q - c) } else { t + c };
+            }
+            let bits = (0..width)
+                .map(|j| {
+                    let tt = terms.iter().map(|t| ((t >> j) & 1) as u16).collect();
+                    self.proj(r, 2, Some(tt))
+                })
+                .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+            sum = self.bin_addition_no_carry(&sum, &BinaryBundle::new(bits))?;
+        }
+        // Before step `i`, the sum is below `2^(i+1) * Q`.
+        for i in (0..bitlen(k - 1)).rev() {
+            let m = self.bin_constant_bundle(q << i, width)?;
+            let geq = self.bin_geq(&sum, &m)?;
+            let (diff, _) = self.bin_subtraction(&sum, &m)?;
+            sum = self.multiplex(&geq, &sum, &diff).map(BinaryBundle::from)?;
+        }
+        Ok(BinaryBundle::new(sum.wires()[..nbits].to_vec()))
+    }
+
+    /// Convert the binary `x` to a CRT bundle under composite modulus `q`,
+    /// reducing it mod `q`. Costs a projection per bit and prime.
+    fn bin_to_crt(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        q: u128,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        if !x.is_binary() {
+            return Err(Self::Error::from(FancyError::ArgNotBinary));
+        }
+        util::factor(q)
+            .into_iter()
+            .map(|p| {
+                let mut pow = 1 % p as u128;
+                let terms = x
+                    .wires()
+                    .iter()
+                    .map(|b| {
+                        let term = self.proj(b, p, Some(vec![0, pow as u16]))?;
+                        pow = 2 * pow % p as u128;
+                        Ok(term)
+                    })
+                    .collect::<Result<Vec<Self::Item>, Self::Error>>()?;
+                match terms.as_slice() {
+                    [] => self.constant(0, p),
+                    [t] => Ok(t.clone()),
+                    ts => self.add_many(ts),
+                }
+            })
+            .collect::<Result<Vec<Self::Item>, Self::Error>>()
+            .map(CrtBundle::new)
+    }
+}
+
+// The number of bits of `x`.
+fn bitlen(x: u128) -> usize {
+    (128 - x.leading_zeros()) as usize
+}
+
+// The largest magnitude a signed integer mod `q` may have.
+pub(super) fn signed_capacity(q: u128) -> u128 {
+    (q - 1) / 2
 }
 
 /// Compute the `ms` needed for the number of CRT primes in `x`, with accuracy
@@ -0,0 +1,341 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `FixedBundler`, which computes on fixed-point numbers
+//! held in CRT bundles.
+//!
+//! A number `x` with `k` fractional bits is held as the signed integer
+//! nearest to `x * 2^k`. Sums, differences and comparisons of these integers
+//! are those of the numbers, but the product of two has `2k` fractional bits,
+//! so `FixedBundler::mul` divides it by `2^k` with `crt_sdiv`, rounding toward
+//! zero, at the cost of a comparison per bit of the result. The bundle must
+//! hold the product before it is rescaled; the underlying `CrtBundler` tracks
+//! the magnitudes and fails with `FancyError::BundlerOverflow` if it may not.
+
+use super::{CrtBundle, CrtBundler, CrtGadgets, FancyOutput, Tracked};
+use crate::errors::FancyError;
+
+/// A fixed-point number made by a `FixedBundler`.
+#[derive(Clone)]
+pub struct Fixed<W> {
+    value: Tracked<W>,
+}
+
+impl<W> Fixed<W> {
+    /// The integer `x * 2^k` holding the number.
+    #[inline]
+    pub fn tracked(&self) -> &Tracked<W> {
+        &self.value
+    }
+
+    /// Extract the integer `x * 2^k` holding the number.
+    #[inline]
+    pub fn into_tracked(self) -> Tracked<W> {
+        self.value
+    }
+}
+
+/// A front end to a `CrtBundler` computing on fixed-point numbers with a
+/// given number of fractional bits.
+pub struct FixedBundler<'a, F> {
+    bundler: CrtBundler<'a, F>,
+    frac_bits: u32,
+}
+
+impl<'a, F: CrtGadgets> FixedBundler<'a, F> {
+    /// Make a bundler building gates with `f`, on numbers with `frac_bits`
+    /// fractional bits, failing unless `2^frac_bits` fits in a `u128`.
+    pub fn new(f: &'a mut F, frac_bits: u32) -> Result<Self, F::Error> {
+        if frac_bits >= 128 {
+            return Err(F::Error::from(FancyError::InvalidArg(format!(
+                "{} fractional bits do not fit in a u128",
+                frac_bits
+            ))));
+        }
+        Ok(Self {
+            bundler: CrtBundler::new(f),
+            frac_bits,
+        })
+    }
+
+    /// The number of fractional bits.
+    #[inline]
+    pub fn frac_bits(&self) -> u32 {
+        self.frac_bits
+    }
+
+    /// The underlying `CrtBundler`, for operations on integers.
+    #[inline]
+    pub fn bundler(&mut self) -> &mut CrtBundler<'a, F> {
+        &mut self.bundler
+    }
+
+    /// The integer holding `x`, to encode as an input.
+    pub fn encode(&self, x: f64) -> i128 {
+        (x * self.scale()).round() as i128
+    }
+
+    /// The number held by the integer `z`.
+    pub fn decode(&self, z: i128) -> f64 {
+        z as f64 / self.scale()
+    }
+
+    /// The largest magnitude `x` may have.
+    pub fn bound(&self, x: &Fixed<F::Item>) -> f64 {
+        self.bundler.bound(&x.value) as f64 / self.scale()
+    }
+
+    /// Start tracking `x`, such as an input encoded with `encode`, which the
+    /// caller promises holds a number of magnitude at most `bound`.
+    pub fn track(&mut self, x: CrtBundle<F::Item>, bound: f64) -> Result<Fixed<F::Item>, F::Error> {
+        if bound.is_nan() || bound < 0.0 {
+            return Err(F::Error::from(FancyError::InvalidArg(format!(
+                "bound {} is not a magnitude",
+                bound
+            ))));
+        }
+        // Out of range bounds saturate, and are then caught as overflows.
+        let bound = (bound * self.scale()).ceil() as u128;
+        self.bundler.track(x, bound).map(wrap)
+    }
+
+    /// Make a constant bundle holding `x` under composite modulus `q`.
+    pub fn constant(&mut self, x: f64, q: u128) -> Result<Fixed<F::Item>, F::Error> {
+        let z = self.encode(x);
+        self.bundler.constant(z, q).map(wrap)
+    }
+
+    /// The number equal to the integer `x`.
+    pub fn from_int(&mut self, x: &Tracked<F::Item>) -> Result<Fixed<F::Item>, F::Error> {
+        self.bundler.cmul(x, 1 << self.frac_bits).map(wrap)
+    }
+
+    /// The integer part of `x`, rounding toward zero.
+    pub fn to_int(
+        &mut self,
+        x: &Fixed<F::Item>,
+        accuracy: &str,
+    ) -> Result<Tracked<F::Item>, F::Error> {
+        self.bundler.sdiv(&x.value, 1 << self.frac_bits, accuracy)
+    }
+
+    /// Add `x` and `y`.
+    pub fn add(
+        &mut self,
+        x: &Fixed<F::Item>,
+        y: &Fixed<F::Item>,
+    ) -> Result<Fixed<F::Item>, F::Error> {
+        self.bundler.add(&x.value, &y.value).map(wrap)
+    }
+
+    /// Subtract `y` from `x`.
+    pub fn sub(
+        &mut self,
+        x: &Fixed<F::Item>,
+        y: &Fixed<F::Item>,
+    ) -> Result<Fixed<F::Item>, F::Error> {
+        self.bundler.sub(&x.value, &y.value).map(wrap)
+    }
+
+    /// Multiply `x` and `y`, rounding the product toward zero.
+    pub fn mul(
+        &mut self,
+        x: &Fixed<F::Item>,
+        y: &Fixed<F::Item>,
+        accuracy: &str,
+    ) -> Result<Fixed<F::Item>, F::Error> {
+        let z = self.bundler.mul(&x.value, &y.value)?;
+        self.bundler
+            .sdiv(&z, 1 << self.frac_bits, accuracy)
+            .map(wrap)
+    }
+
+    /// Return 1 if `x < y`.
+    pub fn lt(
+        &mut self,
+        x: &Fixed<F::Item>,
+        y: &Fixed<F::Item>,
+        accuracy: &str,
+    ) -> Result<F::Item, F::Error> {
+        self.bundler.lt(&x.value, &y.value, accuracy)
+    }
+
+    /// Return 1 if `x >= y`.
+    pub fn geq(
+        &mut self,
+        x: &Fixed<F::Item>,
+        y: &Fixed<F::Item>,
+        accuracy: &str,
+    ) -> Result<F::Item, F::Error> {
+        self.bundler.geq(&x.value, &y.value, accuracy)
+    }
+
+    /// The smaller of `x` and `y`.
+    pub fn min(
+        &mut self,
+        x: &Fixed<F::Item>,
+        y: &Fixed<F::Item>,
+        accuracy: &str,
+    ) -> Result<Fixed<F::Item>, F::Error> {
+        self.bundler.min(&x.value, &y.value, accuracy).map(wrap)
+    }
+
+    /// The larger of `x` and `y`.
+    pub fn max(
+        &mut self,
+        x: &Fixed<F::Item>,
+        y: &Fixed<F::Item>,
+        accuracy: &str,
+    ) -> Result<Fixed<F::Item>, F::Error> {
+        self.bundler.max(&x.value, &y.value, accuracy).map(wrap)
+    }
+
+    /// Output `x` as a number.
+    pub fn output(&mut self, x: &Fixed<F::Item>) -> Result<Option<f64>, F::Error> {
+        let frac_bits = self.frac_bits;
+        self.bundler
+            .fancy()
+            .output_fixed(x.value.bundle(), frac_bits)
+    }
+
+    #[inline]
+    fn scale(&self) -> f64 {
+        2f64.powi(self.frac_bits as i32)
+    }
+}
+
+fn wrap<W>(value: Tracked<W>) -> Fixed<W> {
+    Fixed { value }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        circuit::CircuitBuilder,
+        dummy::Dummy,
+        errors::DummyError,
+        fancy::{BundleGadgets, FancyInput},
+        util::{self, RngExt},
+    };
+    use rand::thread_rng;
+
+    const FRAC_BITS: u32 = 8;
+
+    // A number in `(-bound, bound)` with `FRAC_BITS` fractional bits.
+    fn rand_fixed(bound: u128) -> f64 {
+        let mut rng = thread_rng();
+        let range = 2 * (bound << FRAC_BITS) - 1;
+        let z = (rng.gen_u128() % range) as i128 - (range / 2) as i128;
+        z as f64 / 2f64.powi(FRAC_BITS as i32)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let q = util::modulus_with_nprimes(10);
+        let ulp = 2f64.powi(-(FRAC_BITS as i32));
+        for _ in 0..64 {
+            let (x, y) = (rand_fixed(100), rand_fixed(100));
+            let mut d = Dummy::new();
+            let mut b = FixedBundler::new(&mut d, FRAC_BITS).unwrap();
+            let xs = b.constant(x, q).unwrap();
+            let ys = b.constant(y, q).unwrap();
+            let z = b.add(&xs, &ys).unwrap();
+            assert_eq!(b.output(&z).unwrap(), Some(x + y));
+            let z = b.sub(&xs, &ys).unwrap();
+            assert_eq!(b.output(&z).unwrap(), Some(x - y));
+            let z = b.mul(&xs, &ys, "100%").unwrap();
+            let out = b.output(&z).unwrap().unwrap();
+            // Rounded toward zero, to a multiple of the last fractional bit.
+            assert!((x * y - out).abs() < ulp && out.abs() <= (x * y).abs());
+            assert_eq!(out, (x * y / ulp).trunc() * ulp, "{} * {}", x, y);
+            assert!(b.bound(&z) >= out.abs());
+            let lt = b.lt(&xs, &ys, "100%").unwrap();
+            let geq = b.geq(&xs, &ys, "100%").unwrap();
+            assert_eq!((lt.val(), geq.val()), ((x < y) as u16, (x >= y) as u16));
+            let z = b.min(&xs, &ys, "100%").unwrap();
+            assert_eq!(b.output(&z).unwrap(), Some(x.min(y)));
+            let z = b.max(&xs, &ys, "100%").unwrap();
+            assert_eq!(b.output(&z).unwrap(), Some(x.max(y)));
+        }
+    }
+
+    #[test]
+    fn test_integers() {
+        let q = util::modulus_with_nprimes(8);
+        let mut d = Dummy::new();
+        let mut b = FixedBundler::new(&mut d, FRAC_BITS).unwrap();
+        let n = b.bundler().constant(-42, q).unwrap();
+        let x = b.from_int(&n).unwrap();
+        assert_eq!(b.output(&x).unwrap(), Some(-42.0));
+        for &(x, n) in &[(3.75, 3), (-3.75, -3), (0.5, 0), (-0.5, 0), (-7.0, -7)] {
+            let xs = b.constant(x, q).unwrap();
+            let z = b.to_int(&xs, "100%").unwrap();
+            let out = b.bundler().fancy().output_i64(z.bundle()).unwrap();
+            assert_eq!(out, Some(n), "{}", x);
+        }
+        assert_eq!(b.encode(-1.5), -384);
+        assert_eq!(b.decode(-384), -1.5);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let q = util::modulus_with_nprimes(8); // holds signed integers of 22 bits
+        let mut d = Dummy::new();
+        let x = d.crt_encode(0, q).unwrap();
+        let mut b = FixedBundler::new(&mut d, FRAC_BITS).unwrap();
+        let x = b.track(x, 30.0).unwrap();
+        // 30^2 * 2^16 does not fit.
+        match b.mul(&x, &x, "100%") {
+            Err(DummyError::FancyError(FancyError::BundlerOverflow { op, .. })) => {
+                assert_eq!(op, "mul")
+            }
+            _ => panic!("expected an overflow"),
+        }
+        let y = b.constant(2.0, q).unwrap();
+        assert!(b.mul(&x, &y, "100%").is_ok());
+        assert!(b.track(y.into_tracked().into_bundle(), -1.0).is_err());
+        assert!(FixedBundler::new(&mut d, 128).is_err());
+    }
+
+    #[test]
+    fn test_circuit() {
+        let q = util::modulus_with_nprimes(10);
+        let mut c = CircuitBuilder::new();
+        let xs = c.crt_garbler_input(q);
+        let ys = c.crt_evaluator_input(q);
+        let mut b = FixedBundler::new(&mut c, FRAC_BITS).unwrap();
+        let x = b.track(xs, 100.0).unwrap();
+        let y = b.track(ys, 100.0).unwrap();
+        let z = b.mul(&x, &y, "100%").unwrap();
+        let z = b.max(&z, &x, "100%").unwrap();
+        b.bundler()
+            .fancy()
+            .output_bundle(z.tracked().bundle())
+            .unwrap();
+        let circ = c.finish();
+
+        let d = 2f64.powi(FRAC_BITS as i32);
+        for _ in 0..16 {
+            let (x, y) = (rand_fixed(100), rand_fixed(100));
+            let encode = |x: f64| ((x * d) as i128).rem_euclid(q as i128) as u128;
+            let res = circ
+                .eval_plain(
+                    &util::crt_factor(encode(x), q),
+                    &util::crt_factor(encode(y), q),
+                )
+                .unwrap();
+            let z = util::crt_inv_factor(&res, q);
+            let z = if z > q / 2 {
+                z as i128 - q as i128
+            } else {
+                z as i128
+            };
+            let should_be = ((x * y * d).trunc() / d).max(x);
+            assert_eq!(z as f64 / d, should_be, "max({} * {}, {})", x, y, x);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `FancyOutput`, which outputs bundles as typed values.
+//!
+//! `crt_output` and `bin_output` give back the raw encoding of a bundle: a
+//! residue mod Q, or the bits of a two's complement number read as unsigned.
+//! A `SignedBundle` knows how to decode its outputs into the signed integer it
+//! holds, which `FancyOutput` uses to output signed integers and fixed-point
+//! numbers directly.
+
+use super::*;
+use crate::util;
+
+/// A bundle whose wires hold a signed integer.
+pub trait SignedBundle<W: Clone + HasModulus> {
+    /// The underlying bundle.
+    fn bundle(&self) -> &Bundle<W>;
+
+    /// Decode the outputs `xs` of the wires of the bundle into the integer
+    /// they hold.
+    fn decode(&self, xs: &[u16]) -> i128;
+}
+
+impl<W: Clone + HasModulus> SignedBundle<W> for CrtBundle<W> {
+    fn bundle(&self) -> &Bundle<W> {
+        self
+    }
+
+    /// Residues above `(Q-1)/2` hold negative numbers.
+    fn decode(&self, xs: &[u16]) -> i128 {
+        let q = self.composite_modulus();
+        let x = util::crt_inv_factor(xs, q);
+        if x > (q - 1) / 2 {
+            -((q - x) as i128)
+        } else {
+            x as i128
+        }
+    }
+}
+
+impl<W: Clone + HasModulus> SignedBundle<W> for BinaryBundle<W> {
+    fn bundle(&self) -> &Bundle<W> {
+        self
+    }
+
+    /// The bits hold a two's complement number, least significant bit first.
+    fn decode(&self, xs: &[u16]) -> i128 {
+        let x = util::u128_from_bits(xs);
+        match xs.len() {
+            n if n > 0 && n < 128 && xs[n - 1] == 1 => x as i128 - (1 << n),
+            _ => x as i128,
+        }
+    }
+}
+
+impl<F: Fancy> FancyOutput for F {}
+
+/// Extension trait for `Fancy` providing outputs decoded into typed values.
+pub trait FancyOutput: Fancy + BundleGadgets {
+    /// Output a bundle holding a signed integer, failing if it does not fit in
+    /// an `i64`.
+    fn output_i64<B: SignedBundle<Self::Item>>(
+        &mut self,
+        x: &B,
+    ) -> Result<Option<i64>, Self::Error> {
+        match self.output_bundle(x.bundle())? {
+            Some(xs) => {
+                let z = x.decode(&xs);
+                if z < i64::MIN as i128 || z > i64::MAX as i128 {
+                    return Err(Self::Error::from(FancyError::InvalidArg(format!(
+                        "output {} does not fit in an i64",
+                        z
+                    ))));
+                }
+                Ok(Some(z as i64))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Output a slice of bundles holding signed integers.
+    fn output_i64s<B: SignedBundle<Self::Item>>(
+        &mut self,
+        xs: &[B],
+    ) -> Result<Option<Vec<i64>>, Self::Error> {
+        let mut zs = Vec::with_capacity(xs.len());
+        for x in xs.iter() {
+            let z = self.output_i64(x)?;
+            zs.push(z);
+        }
+        Ok(zs.into_iter().collect())
+    }
+
+    /// Output a bundle holding a fixed-point number with `scale` fractional
+    /// bits, that is, the integer `x * 2^scale`.
+    fn output_fixed<B: SignedBundle<Self::Item>>(
+        &mut self,
+        x: &B,
+        scale: u32,
+    ) -> Result<Option<f64>, Self::Error> {
+        Ok(self
+            .output_bundle(x.bundle())?
+            .map(|xs| x.decode(&xs) as f64 / 2f64.powi(scale as i32)))
+    }
+}
@@ -0,0 +1,362 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Public lookup tables, such as S-boxes or quantized activation functions,
+//! compiled into projection gates.
+//!
+//! Each output wire of a table is compiled separately. The inputs it does not
+//! depend on are dropped, and the remaining ones are packed, with a projection
+//! each, into a single wire whose modulus is the product of theirs, on which a
+//! single projection computes the output. When that product exceeds the
+//! largest modulus allowed, the widest input is split off: the output is
+//! computed for each of its values, and the results combined with a
+//! multiplication per value. Packed wires are shared between outputs that
+//! depend on the same inputs.
+
+use super::*;
+use crate::util;
+use std::collections::HashMap;
+
+/// The largest modulus wires are packed into by default, which is the largest
+/// modulus with fast wire-label conversion.
+pub const MAX_PACKED_MODULUS: u16 = 113;
+
+/// A public lookup table from a bundle of inputs to a bundle of outputs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LookupTable {
+    input_moduli: Vec<u16>,
+    output_moduli: Vec<u16>,
+    // `rows[x]` holds the outputs on the input whose mixed radix value,
+    // least significant digit first, is `x`.
+    rows: Vec<Vec<u16>>,
+    max_modulus: u16,
+}
+
+impl LookupTable {
+    /// Make a table from inputs with moduli `input_moduli` to outputs with
+    /// moduli `output_moduli`, by evaluating `f` on every input.
+    ///
+    /// # Panics
+    /// Panics if `f` returns the wrong number of outputs, or an output which
+    /// is not less than its modulus.
+    pub fn new<F>(input_moduli: &[u16], output_moduli: &[u16], f: F) -> Self
+    where
+        F: Fn(&[u16]) -> Vec<u16>,
+    {
+        let size = input_moduli.iter().map(|&q| q as usize).product::<usize>();
+        let rows = (0..size)
+            .map(|x| {
+                let ys = f(&util::as_mixed_radix(x as u128, input_moduli));
+                assert_eq!(ys.len(), output_moduli.len(), "wrong number of outputs");
+                assert!(
+                    ys.iter().zip(output_moduli).all(|(&y, &q)| y < q),
+                    "output exceeds its modulus"
+                );
+                ys
+            })
+            .collect();
+        Self {
+            input_moduli: input_moduli.to_vec(),
+            output_moduli: output_moduli.to_vec(),
+            rows,
+            max_modulus: MAX_PACKED_MODULUS,
+        }
+    }
+
+    /// Make a table from `nbits_in` bits to `nbits_out` bits, where
+    /// `values[x]` is the output on `x`, as with an S-box.
+    ///
+    /// # Panics
+    /// Panics unless there are exactly `2^nbits_in` values, each of which
+    /// fits in `nbits_out` bits.
+    pub fn binary(nbits_in: usize, nbits_out: usize, values: &[u128]) -> Self {
+        assert_eq!(values.len(), 1 << nbits_in, "wrong number of values");
+        assert!(nbits_out == 128 || values.iter().all(|&v| v >> nbits_out == 0));
+        Self::new(&vec![2; nbits_in], &vec![2; nbits_out], |bits| {
+            util::u128_to_bits(values[util::u128_from_bits(bits) as usize], nbits_out)
+        })
+    }
+
+    /// Set the largest modulus inputs may be packed into, which should be one
+    /// that every backend the table is used with supports. Larger moduli need
+    /// fewer gates but bigger ones.
+    pub fn with_max_modulus(mut self, q: u16) -> Self {
+        self.max_modulus = q.max(2);
+        self
+    }
+
+    /// Return the moduli of the inputs.
+    #[inline]
+    pub fn input_moduli(&self) -> &[u16] {
+        &self.input_moduli
+    }
+
+    /// Return the moduli of the outputs.
+    #[inline]
+    pub fn output_moduli(&self) -> &[u16] {
+        &self.output_moduli
+    }
+
+    /// Return the outputs on the input given by `digits`.
+    pub fn get(&self, digits: &[u16]) -> &[u16] {
+        &self.rows[util::from_mixed_radix(digits, &self.input_moduli) as usize]
+    }
+
+    #[inline]
+    fn entry(&self, digits: &[u16], output: usize) -> u16 {
+        self.get(digits)[output]
+    }
+
+    /// Evaluate the table on the wires `xs` using `f`.
+    pub(crate) fn eval<F: Fancy + ?Sized>(
+        &self,
+        f: &mut F,
+        xs: &[F::Item],
+    ) -> Result<Vec<F::Item>, F::Error> {
+        if xs.iter().map(|x| x.modulus()).collect::<Vec<_>>() != self.input_moduli {
+            return Err(F::Error::from(FancyError::InvalidArg(
+                "bundle moduli do not match the lookup table".to_string(),
+            )));
+        }
+        let mut compiler = Compiler {
+            table: self,
+            xs,
+            packed: HashMap::new(),
+        };
+        let mut digits = vec![0; xs.len()];
+        let support = (0..xs.len()).collect::<Vec<_>>();
+        (0..self.output_moduli.len())
+            .map(|output| compiler.compile(f, output, &support, &mut digits))
+            .collect()
+    }
+}
+
+struct Compiler<'a, W> {
+    table: &'a LookupTable,
+    xs: &'a [W],
+    // Packed wires, keyed by the indices of the inputs packed into them.
+    packed: HashMap<Vec<usize>, W>,
+}
+
+impl<'a, W: Clone + HasModulus> Compiler<'a, W> {
+    // Return the subset of `support` on which `output` depends, given the
+    // digits of every input outside `support`.
+    fn restrict(&self, output: usize, support: &[usize], digits: &mut [u16]) -> Vec<usize> {
+        let moduli = support
+            .iter()
+            .map(|&i| self.table.input_moduli[i])
+            .collect::<Vec<_>>();
+        let size = moduli.iter().map(|&q| q as usize).product::<usize>();
+        let mut needed = vec![false; support.len()];
+        for x in 0..size {
+            for (&i, d) in support.iter().zip(util::as_mixed_radix(x as u128, &moduli)) {
+                digits[i] = d;
+            }
+            let y = self.table.entry(digits, output);
+            for (k, &i) in support.iter().enumerate() {
+                if needed[k] || digits[i] != 0 {
+                    continue;
+                }
+                let d = digits[i];
+                needed[k] = (1..moduli[k]).any(|v| {
+                    digits[i] = v;
+                    self.table.entry(digits, output) != y
+                });
+                digits[i] = d;
+            }
+        }
+        support
+            .iter()
+            .zip(needed)
+            .filter(|(_, needed)| *needed)
+            .map(|(&i, _)| i)
+            .collect()
+    }
+
+    // Pack the inputs in `support` into a single wire holding their mixed
+    // radix value.
+    fn pack<F: Fancy<Item = W> + ?Sized>(
+        &mut self,
+        f: &mut F,
+        support: &[usize],
+    ) -> Result<W, F::Error> {
+        if let Some(w) = self.packed.get(support) {
+            return Ok(w.clone());
+        }
+        let w = if support.len() == 1 {
+            self.xs[support[0]].clone()
+        } else {
+            let q = support
+                .iter()
+                .map(|&i| self.table.input_moduli[i])
+                .product::<u16>();
+            let mut weight = 1;
+            let mut terms = Vec::with_capacity(support.len());
+            for &i in support {
+                let x = f.mod_change(&self.xs[i], q)?;
+                terms.push(f.cmul(&x, weight)?);
+                weight *= self.table.input_moduli[i];
+            }
+            f.add_many(&terms)?
+        };
+        self.packed.insert(support.to_vec(), w.clone());
+        Ok(w)
+    }
+
+    // Compute `output` as a function of the inputs in `support`, given the
+    // digits of every input outside `support`.
+    fn compile<F: Fancy<Item = W> + ?Sized>(
+        &mut self,
+        f: &mut F,
+        output: usize,
+        support: &[usize],
+        digits: &mut [u16],
+    ) -> Result<W, F::Error> {
+        let q = self.table.output_moduli[output];
+        let support = self.restrict(output, support, digits);
+        let moduli = support
+            .iter()
+            .map(|&i| self.table.input_moduli[i])
+            .collect::<Vec<_>>();
+        for &i in &support {
+            digits[i] = 0;
+        }
+        if support.is_empty() {
+            return f.constant(self.table.entry(digits, output), q);
+        }
+        let size = moduli.iter().map(|&q| q as u32).product::<u32>();
+        if size <= u32::from(self.table.max_modulus) {
+            let tt = (0..size)
+                .map(|x| {
+                    for (&i, d) in support.iter().zip(util::as_mixed_radix(x as u128, &moduli)) {
+                        digits[i] = d;
+                    }
+                    self.table.entry(digits, output)
+                })
+                .collect::<Vec<_>>();
+            let x = self.pack(f, &support)?;
+            if x.modulus() == q && tt.iter().enumerate().all(|(i, &y)| i == y as usize) {
+                return Ok(x);
+            }
+            return f.proj(&x, q, Some(tt));
+        }
+        // Split off the input with the largest modulus, and select among the
+        // outputs on each of its values.
+        let k = (0..support.len()).max_by_key(|&k| moduli[k]).unwrap();
+        let (i, p) = (support[k], moduli[k]);
+        let rest = support
+            .iter()
+            .cloned()
+            .filter(|&j| j != i)
+            .collect::<Vec<_>>();
+        digits[i] = 0;
+        let z0 = self.compile(f, output, &rest, digits)?;
+        let mut z = z0.clone();
+        for v in 1..p {
+            digits[i] = v;
+            let zv = self.compile(f, output, &rest, digits)?;
+            let diff = f.sub(&zv, &z0)?;
+            let x = &self.xs[i];
+            let select = if p == 2 && q == 2 {
+                x.clone()
+            } else {
+                let tt = (0..p).map(|u| (u == v) as u16).collect();
+                f.proj(x, q, Some(tt))?
+            };
+            let term = f.mul(&select, &diff)?;
+            z = f.add(&z, &term)?;
+        }
+        digits[i] = 0;
+        Ok(z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        circuit::CircuitBuilder,
+        classic::garble,
+        dummy::Dummy,
+        informer::Informer,
+        util::RngExt,
+        BinaryGadgets,
+        FancyInput,
+    };
+    use rand::thread_rng;
+
+    // The PRESENT S-box.
+    const SBOX: [u128; 16] = [
+        0xc, 0x5, 0x6, 0xb, 0x9, 0x0, 0xa, 0xd, 0x3, 0xe, 0xf, 0x8, 0x4, 0x7, 0x1, 0x2,
+    ];
+
+    fn check(table: &LookupTable) {
+        let mut rng = thread_rng();
+        let moduli = table.input_moduli().to_vec();
+        let mut b = CircuitBuilder::new();
+        let xs = Bundle::new(b.evaluator_inputs(&moduli));
+        let zs = b.lookup(&xs, table).unwrap();
+        b.output_bundle(&zs).unwrap();
+        let circ = b.finish();
+        let (en, gc) = garble(&circ).unwrap();
+        for _ in 0..16 {
+            let digits = moduli.iter().map(|&q| rng.gen_u16() % q).collect_vec();
+            let mut dummy = Dummy::new();
+            let xs = Bundle::new(dummy.encode_many(&digits, &moduli).unwrap());
+            let zs = dummy.lookup(&xs, table).unwrap();
+            assert_eq!(
+                dummy.output_bundle(&zs).unwrap().unwrap(),
+                table.get(&digits)
+            );
+            let ws = en.encode_evaluator_inputs(&digits);
+            assert_eq!(gc.eval(&circ, &[], &ws).unwrap(), table.get(&digits));
+        }
+    }
+
+    #[test]
+    fn test_sbox() {
+        check(&LookupTable::binary(4, 4, &SBOX));
+        // Force the inputs to be split.
+        check(&LookupTable::binary(4, 4, &SBOX).with_max_modulus(4));
+    }
+
+    #[test]
+    fn test_wide() {
+        let mut rng = thread_rng();
+        let values = (0..256).map(|_| rng.gen_u128() % 256).collect_vec();
+        check(&LookupTable::binary(8, 8, &values));
+    }
+
+    #[test]
+    fn test_mixed_radix() {
+        let table = LookupTable::new(&[3, 5, 2], &[7, 2], |xs| {
+            vec![(xs[0] * xs[1] + xs[2]) % 7, (xs[0] == xs[1]) as u16]
+        });
+        check(&table);
+        check(&table.with_max_modulus(5));
+    }
+
+    #[test]
+    fn test_unused_inputs() {
+        // The first output ignores the last input, and the second is
+        // constant, so only the first two inputs are packed.
+        let table = LookupTable::new(&[2, 2, 3], &[5, 3], |xs| vec![xs[0] + 2 * xs[1], 1]);
+        let mut informer = Informer::new(Dummy::new());
+        let xs = Bundle::new(informer.encode_many(&[1, 1, 2], &[2, 2, 3]).unwrap());
+        let zs = informer.lookup(&xs, &table).unwrap();
+        assert_eq!(informer.output_bundle(&zs).unwrap(), Some(vec![3, 1]));
+        // Two projections pack the inputs into a wire mod 4, and one more
+        // computes the output.
+        assert_eq!(informer.stats().num_projs(), 3);
+
+        let mut dummy = Dummy::new();
+        let xs = BinaryBundle::new(dummy.encode_many(&[1, 0, 1, 1], &[2; 4]).unwrap());
+        let zs = dummy
+            .bin_lookup(&xs, &LookupTable::binary(4, 4, &SBOX))
+            .unwrap();
+        assert_eq!(dummy.bin_output(&zs).unwrap(), Some(SBOX[0b1101]));
+    }
+}
@@ -11,20 +11,104 @@ mod garbler;
 
 pub use crate::garble::{evaluator::Evaluator, garbler::Garbler};
 
+/// The scheme used to garble multiplication gates between two mod-2 wires,
+/// i.e., AND gates. The garbler and the evaluator must use the same scheme.
+///
+/// The first two schemes send two ciphertexts per AND gate and keep XOR
+/// free; they differ in the work to garble and evaluate each gate. Gates over
+/// any other moduli always use the BMR16 scheme. There is no separate garbled
+/// row reduction: both half-gates schemes already omit the ciphertext of the
+/// first row of each half gate, as every BMR16 projection does, and so send
+/// fewer ciphertexts than the three of a row-reduced garbled table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub enum Scheme {
+    /// The generalized half-gates of BMR16, shared with every other modulus.
+    #[default]
+    Bmr16,
+    /// The half-gates of Zahur, Rosulek, and Evans (cf.
+    /// <https://eprint.iacr.org/2014/756>), which computes each gate with
+    /// four hashes and a handful of XORs.
+    HalfGates,
+    /// The privacy-free half-gates of Zahur, Rosulek, and Evans, for when
+    /// the evaluator may learn every value of the computation, as the prover
+    /// of a zero-knowledge proof of knowledge does: each AND gate sends one
+    /// ciphertext and takes two hashes.
+    ///
+    /// The color of each mod-2 wire-label is its value, which the garbler
+    /// ensures by choosing zero-labels of color 0, so the scheme must be set
+    /// before the first mod-2 wire is encoded, and projections onto mod-2
+    /// wires are not supported.
+    PrivacyFree,
+}
+
+/// A bound on the ciphertexts a streaming `Garbler` sends ahead of its
+/// `Evaluator`. The garbler and the evaluator must use the same window.
+///
+/// With a window, the garbler flushes the channel every half window, and the
+/// evaluator acknowledges each half it reads, so that the garbler blocks,
+/// rather than buffering, while a full window is unacknowledged. The window
+/// should cover the bandwidth-delay product of the link: the garbler then
+/// keeps the link busy, and a slower link slows the garbler instead of
+/// growing its buffers. Without one, when to send is left to the channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub enum Window {
+    /// No bound, and no acknowledgments.
+    #[default]
+    Unbounded,
+    /// At most this many gates and outputs in flight.
+    Gates(usize),
+    /// At most about this many bytes of ciphertexts in flight, rounded up to
+    /// a whole gate.
+    Bytes(usize),
+}
+
+impl Window {
+    // The units of the window taken by a gate of `nblocks` ciphertexts.
+    fn units(self, nblocks: usize) -> usize {
+        match self {
+            Window::Unbounded => 0,
+            Window::Gates(_) => 1,
+            Window::Bytes(_) => 16 * nblocks,
+        }
+    }
+
+    // The units sent between acknowledgments, or `None` without a bound.
+    fn chunk(self) -> Option<usize> {
+        match self {
+            Window::Unbounded => None,
+            Window::Gates(n) | Window::Bytes(n) => Some((n / 2).max(1)),
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // tests
 
 #[cfg(test)]
 mod nonstreaming {
     use crate::{
-        circuit::{Circuit, CircuitBuilder},
-        classic::garble,
+        circuit::{Circuit, CircuitBuilder, WireArena},
+        classic::{
+            garble,
+            garble_structure,
+            garble_with_scheme,
+            receive_garbler_inputs,
+            Encoder,
+            GarbledCircuit,
+        },
         fancy::{Bundle, BundleGadgets, Fancy},
         util::{self, RngExt},
+        Scheme,
     };
     use itertools::Itertools;
     use rand::{thread_rng, SeedableRng};
-    use scuttlebutt::{AesRng, Block};
+    use scuttlebutt::{AesRng, Block, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
 
     // helper
     fn garble_test_helper<F>(f: F)
@@ -281,6 +365,210 @@ mod nonstreaming {
             assert_eq!(Y[0], (x + c) % q, "garbled");
         }
     }
+
+    #[test] // reusing one arena across evaluations
+    fn eval_with_arena() {
+        let mut rng = thread_rng();
+        let q = rng.gen_prime();
+        let mut b = CircuitBuilder::new();
+        let x = b.evaluator_input(q);
+        let y = b.evaluator_input(q);
+        let z = b.mul(&x, &y).unwrap();
+        let tt = (0..q).map(|x| (x * x) % q).collect();
+        let z = b.proj(&z, q, Some(tt)).unwrap();
+        b.output(&z).unwrap();
+        let circ = b.finish();
+        let (en, ev) = garble(&circ).unwrap();
+
+        let mut arena = WireArena::new();
+        for _ in 0..16 {
+            let inps = [rng.gen_u16() % q, rng.gen_u16() % q];
+            let X = en.encode_evaluator_inputs(&inps);
+            let outputs = ev.eval_with(&circ, &[], &X, &mut arena).unwrap();
+            assert_eq!(outputs, circ.eval_plain(&[], &inps).unwrap());
+        }
+    }
+
+    #[test]
+    fn delayed_garbler_inputs() {
+        let mut rng = thread_rng();
+        let q = rng.gen_prime();
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(q);
+        let y = b.evaluator_input(q);
+        let z = b.mul(&x, &y).unwrap();
+        let z = b.add(&z, &x).unwrap();
+        b.output(&z).unwrap();
+        let circ = b.finish();
+
+        // The garbler commits to the garbled circuit before choosing its input.
+        let gs = garble_structure(&circ).unwrap();
+        let commitment = gs.commitment();
+        assert!(gs.circuit().verify(&commitment, gs.opening()));
+        assert!(!gs.circuit().verify(&commitment, [0; 32]));
+        assert!(gs.encode_inputs(&[]).is_err());
+        assert!(gs.encode_inputs(&[q]).is_err());
+
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let mut channel = Channel::new(
+            BufReader::new(sender.try_clone().unwrap()),
+            BufWriter::new(sender),
+        );
+        let mut peer = Channel::new(
+            BufReader::new(receiver.try_clone().unwrap()),
+            BufWriter::new(receiver),
+        );
+        let inps = [rng.gen_u16() % q, rng.gen_u16() % q];
+        gs.send_inputs(&inps[..1], &mut channel).unwrap();
+        let G = receive_garbler_inputs(&circ, &mut peer).unwrap();
+        let E = gs.encoder().encode_evaluator_inputs(&inps[1..]);
+        let outputs = gs.circuit().eval(&circ, &G, &E).unwrap();
+        assert_eq!(outputs, circ.eval_plain(&inps[..1], &inps[1..]).unwrap());
+    }
+
+    #[test] // garble offline, then evaluate what was read back
+    fn serialization() {
+        let mut rng = thread_rng();
+        let mut b = CircuitBuilder::new();
+        let xs = b.garbler_inputs(&[2, 3, 17]);
+        let ys = b.evaluator_inputs(&[2, 3, 17]);
+        let z = b.and(&xs[0], &ys[0]).unwrap();
+        b.output(&z).unwrap();
+        for (x, y) in xs.iter().zip(ys.iter()).skip(1) {
+            let z = b.mul(x, y).unwrap();
+            b.output(&z).unwrap();
+        }
+        let circ = b.finish();
+
+        for &scheme in &[Scheme::Bmr16, Scheme::HalfGates, Scheme::PrivacyFree] {
+            let (en, gc) = garble_with_scheme(&circ, scheme).unwrap();
+            let mut gc_bytes = Vec::new();
+            gc.write_to(&mut gc_bytes).unwrap();
+            let mut en_bytes = Vec::new();
+            en.write_to(&mut en_bytes).unwrap();
+            let gc = GarbledCircuit::read_from(&mut gc_bytes.as_slice()).unwrap();
+            let en = Encoder::read_from(&mut en_bytes.as_slice()).unwrap();
+            assert_eq!(gc.scheme(), scheme);
+            for _ in 0..16 {
+                let inps = [2, 3, 17].iter().map(|q| rng.gen_u16() % q).collect_vec();
+                let outputs = gc
+                    .eval(
+                        &circ,
+                        &en.encode_garbler_inputs(&inps),
+                        &en.encode_evaluator_inputs(&inps),
+                    )
+                    .unwrap();
+                assert_eq!(outputs, circ.eval_plain(&inps, &inps).unwrap());
+            }
+
+            // Truncated input, and that of another version, are rejected.
+            let n = gc_bytes.len();
+            assert!(GarbledCircuit::read_from(&mut &gc_bytes[..n - 1]).is_err());
+            assert!(Encoder::read_from(&mut gc_bytes.as_slice()).is_err());
+            gc_bytes[4] += 1;
+            assert!(GarbledCircuit::read_from(&mut gc_bytes.as_slice()).is_err());
+        }
+    }
+
+    #[test] // privacy-free AND gates send one ciphertext
+    fn privacy_free() {
+        let mut rng = thread_rng();
+        let n = 8;
+        let mut b = CircuitBuilder::new();
+        let xs = b.garbler_inputs(&vec![2; n]);
+        let ys = b.evaluator_inputs(&vec![2; n]);
+        let mut zs = Vec::with_capacity(n + 1);
+        for (i, (x, y)) in xs.iter().zip(ys.iter()).enumerate() {
+            let z = match i % 4 {
+                0 => b.and(x, y).unwrap(),
+                1 => b.xor(x, y).unwrap(),
+                2 => b.or(x, y).unwrap(),
+                _ => b.negate(x).unwrap(),
+            };
+            zs.push(z);
+        }
+        zs.push(b.and_many(&zs).unwrap());
+        b.outputs(&zs).unwrap();
+        let circ = b.finish();
+
+        let (en, ev) = garble_with_scheme(&circ, Scheme::PrivacyFree).unwrap();
+        let (_, ev_) = garble_with_scheme(&circ, Scheme::HalfGates).unwrap();
+        assert_eq!(ev_.size() - ev.size(), circ.num_nonfree_gates);
+        for _ in 0..16 {
+            let gb_inps = (0..n).map(|_| rng.gen_u16() % 2).collect_vec();
+            let ev_inps = (0..n).map(|_| rng.gen_u16() % 2).collect_vec();
+            let outputs = ev
+                .eval(
+                    &circ,
+                    &en.encode_garbler_inputs(&gb_inps),
+                    &en.encode_evaluator_inputs(&ev_inps),
+                )
+                .unwrap();
+            assert_eq!(outputs, circ.eval_plain(&gb_inps, &ev_inps).unwrap());
+        }
+
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(3);
+        let z = b.proj(&x, 2, Some(vec![0, 1, 1])).unwrap();
+        b.output(&z).unwrap();
+        assert!(garble_with_scheme(&b.finish(), Scheme::PrivacyFree).is_err());
+
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(3);
+        let y = b.evaluator_input(2);
+        let z = b.mul(&x, &y).unwrap();
+        b.output(&z).unwrap();
+        assert!(garble_with_scheme(&b.finish(), Scheme::PrivacyFree).is_err());
+    }
+
+    #[test] // both schemes compute the same boolean circuits
+    fn half_gates() {
+        let mut rng = thread_rng();
+        for _ in 0..16 {
+            let n = 8;
+            let mut b = CircuitBuilder::new();
+            let xs = b.garbler_inputs(&vec![2; n]);
+            let ys = b.evaluator_inputs(&vec![2; n]);
+            let mut zs = Vec::with_capacity(n + 1);
+            for (x, y) in xs.iter().zip(ys.iter()) {
+                let z = match rng.gen_u16() % 3 {
+                    0 => b.and(x, y).unwrap(),
+                    1 => b.xor(x, y).unwrap(),
+                    _ => b.or(x, y).unwrap(),
+                };
+                zs.push(z);
+            }
+            zs.push(b.and_many(&zs).unwrap());
+            b.outputs(&zs).unwrap();
+            let circ = b.finish();
+
+            let (en, ev) = garble_with_scheme(&circ, Scheme::Bmr16).unwrap();
+            let (en_, ev_) = garble_with_scheme(&circ, Scheme::HalfGates).unwrap();
+            assert_eq!(ev_.scheme(), Scheme::HalfGates);
+            assert_eq!(ev.size(), ev_.size());
+            for _ in 0..16 {
+                let gb_inps = (0..n).map(|_| rng.gen_u16() % 2).collect_vec();
+                let ev_inps = (0..n).map(|_| rng.gen_u16() % 2).collect_vec();
+                let should_be = circ.eval_plain(&gb_inps, &ev_inps).unwrap();
+                let outputs = ev
+                    .eval(
+                        &circ,
+                        &en.encode_garbler_inputs(&gb_inps),
+                        &en.encode_evaluator_inputs(&ev_inps),
+                    )
+                    .unwrap();
+                let outputs_ = ev_
+                    .eval(
+                        &circ,
+                        &en_.encode_garbler_inputs(&gb_inps),
+                        &en_.encode_evaluator_inputs(&ev_inps),
+                    )
+                    .unwrap();
+                assert_eq!(outputs, should_be);
+                assert_eq!(outputs_, should_be);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -291,7 +579,10 @@ mod streaming {
         Evaluator,
         Fancy,
         FancyInput,
+        FancyReveal,
         Garbler,
+        Scheme,
+        Window,
         Wire,
     };
     use itertools::Itertools;
@@ -437,11 +728,75 @@ mod streaming {
             );
         }
     }
+
+    #[test]
+    fn half_gates() {
+        fn fancy_and<F: Fancy>(b: &mut F, xs: &[F::Item]) -> Option<u16> {
+            let z = b.and(&xs[0], &xs[1]).unwrap();
+            let z = b.and(&z, &xs[2]).unwrap();
+            b.output(&z).unwrap()
+        }
+
+        for _ in 0..16 {
+            streaming_test(
+                move |b, xs| {
+                    b.set_scheme(Scheme::HalfGates);
+                    fancy_and(b, xs)
+                },
+                move |b, xs| {
+                    b.set_scheme(Scheme::HalfGates);
+                    fancy_and(b, xs)
+                },
+                fancy_and,
+                &[2, 2, 2],
+            );
+        }
+    }
+
+    #[test]
+    fn window() {
+        // Enough gates for several windows, with a reveal in the middle, which
+        // reads from the evaluator.
+        fn fancy_chain<F: Fancy + FancyReveal>(b: &mut F, xs: &[F::Item]) -> Option<u16> {
+            let mut z = xs[0].clone();
+            for (i, x) in xs.iter().cycle().take(200).enumerate() {
+                let y = b.mul(&z, x).unwrap();
+                z = b.xor(&y, &xs[i % 3]).unwrap();
+                if i == 100 {
+                    b.reveal(&z).unwrap();
+                }
+            }
+            let y = b.proj(&z, 3, Some(vec![1, 2])).unwrap();
+            b.output(&y).unwrap()
+        }
+
+        for &window in &[Window::Gates(1), Window::Gates(16), Window::Bytes(100)] {
+            for &scheme in &[Scheme::Bmr16, Scheme::HalfGates] {
+                streaming_test(
+                    move |b, xs| {
+                        b.set_scheme(scheme);
+                        b.set_window(window).unwrap();
+                        let z = fancy_chain(b, xs);
+                        b.sync_window().unwrap();
+                        z
+                    },
+                    move |b, xs| {
+                        b.set_scheme(scheme);
+                        b.set_window(window);
+                        fancy_chain(b, xs)
+                    },
+                    fancy_chain,
+                    &[2, 2, 2],
+                );
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod complex {
     use crate::{
+        circuit::CircuitBuilder,
         dummy::Dummy,
         util::RngExt,
         CrtBundle,
@@ -489,6 +844,24 @@ mod complex {
                 .collect_vec();
             let should_be = complex_gadget(&mut dummy, &dinps).unwrap();
 
+            // The same gadget, recorded as a circuit, agrees.
+            let mut builder = CircuitBuilder::new();
+            let binps = (0..N)
+                .map(|_| CrtBundle::new(builder.evaluator_inputs(&qs)))
+                .collect_vec();
+            assert_eq!(complex_gadget(&mut builder, &binps).unwrap(), None);
+            let circ = builder.finish();
+            let digits = input
+                .iter()
+                .flat_map(|x| crate::util::crt(*x, &qs))
+                .collect_vec();
+            let outputs = circ.eval_plain(&[], &digits).unwrap();
+            let outputs = outputs
+                .chunks(qs.len())
+                .map(|zs| crate::util::crt_inv(zs, &qs))
+                .collect_vec();
+            assert_eq!(Some(outputs), should_be);
+
             // test streaming garbler and evaluator
             let (sender, receiver) = unix_channel_pair();
 
@@ -7,10 +7,11 @@
 use crate::{
     errors::{EvaluatorError, FancyError},
     fancy::{Fancy, FancyReveal, HasModulus},
+    garble::{garbler::colorless, Scheme, Window},
     util::{output_tweak, tweak, tweak2},
     wire::Wire,
 };
-use scuttlebutt::AbstractChannel;
+use scuttlebutt::{AbstractChannel, Block};
 
 /// Streaming evaluator using a callback to receive ciphertexts as needed.
 ///
@@ -20,6 +21,13 @@ pub struct Evaluator<C> {
     channel: C,
     current_gate: usize,
     current_output: usize,
+    // Scratch space for the ciphertexts of the current gate, reused across
+    // gates so that evaluating one allocates nothing.
+    gate: Vec<Block>,
+    scheme: Scheme,
+    window: Window,
+    // The units of the window read since the last acknowledgment.
+    inflight: usize,
 }
 
 impl<C: AbstractChannel> Evaluator<C> {
@@ -29,9 +37,62 @@ impl<C: AbstractChannel> Evaluator<C> {
             channel,
             current_gate: 0,
             current_output: 0,
+            gate: Vec::new(),
+            scheme: Scheme::default(),
+            window: Window::default(),
+            inflight: 0,
         }
     }
 
+    /// Evaluate AND gates garbled with `scheme` from now on.
+    pub fn set_scheme(&mut self, scheme: Scheme) {
+        self.scheme = scheme;
+    }
+
+    /// The scheme AND gates are evaluated with.
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    /// Acknowledge the ciphertexts read as the garbler's `window` requires
+    /// from now on, which must be set between the same gates on both sides.
+    pub fn set_window(&mut self, window: Window) {
+        self.window = window;
+        self.inflight = 0;
+    }
+
+    /// The bound on the ciphertexts in flight.
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Evaluate the AND of the mod-2 wires `A` and `B` garbled with
+    /// half-gates.
+    fn and_half_gates(&mut self, A: &Wire, B: &Wire) -> Result<Wire, EvaluatorError> {
+        self.read_gate(2)?;
+        let gate_num = self.current_gate();
+        let (g, e) = (tweak2(gate_num as u64, 0), tweak2(gate_num as u64, 1));
+        let (tg, te) = (self.gate[0], self.gate[1]);
+        let zero = Block::default();
+        let (sa, sb) = (A.color() == 1, B.color() == 1);
+        let wg = A.hash(g) ^ if sa { tg } else { zero };
+        let we = B.hash(e) ^ if sb { te ^ A.as_block() } else { zero };
+        Ok(Wire::from_block(wg ^ we, 2))
+    }
+
+    /// Evaluate the AND of the mod-2 wires `A` and `B` garbled with a
+    /// privacy-free half-gate, where the color of `A` is its value.
+    fn and_privacy_free(&mut self, A: &Wire, B: &Wire) -> Result<Wire, EvaluatorError> {
+        self.read_gate(1)?;
+        let g = tweak(self.current_gate());
+        let block = if A.color() == 0 {
+            colorless(A.hash(g))
+        } else {
+            A.hash(g) ^ self.gate[0] ^ B.as_block()
+        };
+        Ok(Wire::from_block(block, 2))
+    }
+
     /// The current non-free gate index of the garbling computation.
     fn current_gate(&mut self) -> usize {
         let current = self.current_gate;
@@ -46,6 +107,24 @@ impl<C: AbstractChannel> Evaluator<C> {
         current
     }
 
+    /// Read the `n` ciphertexts of the next gate into `self.gate`.
+    fn read_gate(&mut self, n: usize) -> Result<(), EvaluatorError> {
+        self.gate.clear();
+        for _ in 0..n {
+            let block = self.channel.read_block()?;
+            self.gate.push(block);
+        }
+        if let Some(chunk) = self.window.chunk() {
+            self.inflight += self.window.units(n);
+            if self.inflight >= chunk {
+                self.inflight = 0;
+                self.channel.write_u8(0)?;
+                self.channel.flush()?;
+            }
+        }
+        Ok(())
+    }
+
     /// Read a Wire from the reader.
     pub fn read_wire(&mut self, modulus: u16) -> Result<Wire, EvaluatorError> {
         let block = self.channel.read_block()?;
@@ -94,16 +173,18 @@ impl<C: AbstractChannel> Fancy for Evaluator<C> {
         }
         let q = A.modulus();
         let qb = B.modulus();
-        let unequal = q != qb;
-        let ngates = q as usize + qb as usize - 2 + unequal as usize;
-        let mut gate = Vec::with_capacity(ngates);
-        {
-            for _ in 0..ngates {
-                let block = self.channel.read_block()?;
-                gate.push(block);
+        if q == 2 && qb == 2 {
+            match self.scheme {
+                Scheme::Bmr16 => (),
+                Scheme::HalfGates => return self.and_half_gates(A, B),
+                Scheme::PrivacyFree => return self.and_privacy_free(A, B),
             }
         }
+        let unequal = q != qb;
+        let ngates = q as usize + qb as usize - 2 + unequal as usize;
+        self.read_gate(ngates)?;
         let gate_num = self.current_gate();
+        let gate = &self.gate;
         let g = tweak2(gate_num as u64, 0);
 
         // garbler's half gate
@@ -138,16 +219,12 @@ impl<C: AbstractChannel> Fancy for Evaluator<C> {
 
     fn proj(&mut self, x: &Wire, q: u16, _: Option<Vec<u16>>) -> Result<Wire, EvaluatorError> {
         let ngates = (x.modulus() - 1) as usize;
-        let mut gate = Vec::with_capacity(ngates);
-        for _ in 0..ngates {
-            let block = self.channel.read_block()?;
-            gate.push(block);
-        }
+        self.read_gate(ngates)?;
         let t = tweak(self.current_gate());
         if x.color() == 0 {
             Ok(x.hashback(t, q))
         } else {
-            let ct = gate[x.color() as usize - 1];
+            let ct = self.gate[x.color() as usize - 1];
             Ok(Wire::from_block(ct ^ x.hash(t), q))
         }
     }
@@ -157,13 +234,13 @@ impl<C: AbstractChannel> Fancy for Evaluator<C> {
         let i = self.current_output();
 
         // Receive the output ciphertext from the garbler
-        let ct = self.channel.read_blocks(q as usize)?;
+        self.read_gate(q as usize)?;
 
         // Attempt to brute force x using the output ciphertext
         let mut decoded = None;
         for k in 0..q {
             let hashed_wire = x.hash(output_tweak(i, k));
-            if hashed_wire == ct[k as usize] {
+            if hashed_wire == self.gate[k as usize] {
                 decoded = Some(k);
                 break;
             }
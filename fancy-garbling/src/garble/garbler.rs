@@ -7,6 +7,7 @@
 use crate::{
     errors::{FancyError, GarblerError},
     fancy::{BinaryBundle, CrtBundle, Fancy, FancyReveal, HasModulus},
+    garble::{Scheme, Window},
     util::{output_tweak, tweak, tweak2, RngExt},
     wire::Wire,
 };
@@ -14,6 +15,11 @@ use rand::{CryptoRng, RngCore};
 use scuttlebutt::{AbstractChannel, Block};
 use std::collections::HashMap;
 
+// Clears the color bit of a mod-2 wire-label.
+pub(super) fn colorless(block: Block) -> Block {
+    Block::from(u128::from(block) & !1)
+}
+
 /// Streams garbled circuit ciphertexts through a callback.
 pub struct Garbler<C, RNG> {
     channel: C,
@@ -21,6 +27,19 @@ pub struct Garbler<C, RNG> {
     current_output: usize,
     current_gate: usize,
     rng: RNG,
+    scheme: Scheme,
+    window: Window,
+    // The units of the window sent since the last flush.
+    inflight: usize,
+    // The flushed chunks not yet acknowledged by the evaluator.
+    unacked: usize,
+}
+
+#[cfg(feature = "zeroize")]
+impl<C, RNG> Drop for Garbler<C, RNG> {
+    fn drop(&mut self) {
+        self.deltas.values_mut().for_each(Wire::erase);
+    }
 }
 
 impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
@@ -32,6 +51,115 @@ impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
             current_gate: 0,
             current_output: 0,
             rng,
+            scheme: Scheme::default(),
+            window: Window::default(),
+            inflight: 0,
+            unacked: 0,
+        }
+    }
+
+    /// Garble AND gates with `scheme` from now on.
+    pub fn set_scheme(&mut self, scheme: Scheme) {
+        self.scheme = scheme;
+    }
+
+    /// The scheme AND gates are garbled with.
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    /// Bound the ciphertexts in flight to the evaluator by `window` from now
+    /// on, which must be set between the same gates on both sides.
+    pub fn set_window(&mut self, window: Window) -> Result<(), GarblerError> {
+        self.sync_window()?;
+        self.window = window;
+        self.inflight = 0;
+        Ok(())
+    }
+
+    /// The bound on the ciphertexts in flight.
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Flush the channel and read the outstanding acknowledgments of the
+    /// evaluator. With a window, this must be done before reading anything
+    /// else from the evaluator, such as before an oblivious transfer, and
+    /// after the last gate, before the channel is closed under the evaluator
+    /// acknowledging it. `reveal` does so itself.
+    pub fn sync_window(&mut self) -> Result<(), GarblerError> {
+        self.channel.flush()?;
+        while self.unacked > 0 {
+            self.channel.read_u8()?;
+            self.unacked -= 1;
+        }
+        Ok(())
+    }
+
+    // Account for a gate of `nblocks` ciphertexts just written.
+    fn sent(&mut self, nblocks: usize) -> Result<(), GarblerError> {
+        let chunk = match self.window.chunk() {
+            Some(chunk) => chunk,
+            None => return Ok(()),
+        };
+        self.inflight += self.window.units(nblocks);
+        if self.inflight >= chunk {
+            self.inflight = 0;
+            self.channel.flush()?;
+            self.unacked += 1;
+            // Wait until the evaluator has read the chunk before this one, so
+            // that at most two chunks are in flight.
+            if self.unacked > 1 {
+                self.channel.read_u8()?;
+                self.unacked -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Garble the AND of the mod-2 wires `A` and `B` with half-gates.
+    fn and_half_gates(&mut self, A: &Wire, B: &Wire) -> Result<Wire, GarblerError> {
+        let gate_num = self.current_gate();
+        let (g, e) = (tweak2(gate_num as u64, 0), tweak2(gate_num as u64, 1));
+        let R = self.delta(2).as_block();
+        let (a0, b0) = (A.as_block(), B.as_block());
+        let (pa, pb) = (A.color() == 1, B.color() == 1);
+        let (ha0, ha1) = (A.hash(g), Wire::from_block(a0 ^ R, 2).hash(g));
+        let (hb0, hb1) = (B.hash(e), Wire::from_block(b0 ^ R, 2).hash(e));
+        let zero = Block::default();
+        // garbler's half-gate, where the garbler knows the permute bit `pb`
+        let tg = ha0 ^ ha1 ^ if pb { R } else { zero };
+        let wg = ha0 ^ if pa { tg } else { zero };
+        // evaluator's half-gate, where the evaluator knows `b ^ pb`
+        let te = hb0 ^ hb1 ^ a0;
+        let we = hb0 ^ if pb { te ^ a0 } else { zero };
+        self.channel.write_block(&tg)?;
+        self.channel.write_block(&te)?;
+        self.sent(2)?;
+        Ok(Wire::from_block(wg ^ we, 2))
+    }
+
+    /// Garble the AND of the mod-2 wires `A` and `B` with a privacy-free
+    /// half-gate, where the evaluator knows the value of `A`.
+    fn and_privacy_free(&mut self, A: &Wire, B: &Wire) -> Result<Wire, GarblerError> {
+        let g = tweak(self.current_gate());
+        let R = self.delta(2).as_block();
+        let (a0, b0) = (A.as_block(), B.as_block());
+        let c0 = colorless(A.hash(g));
+        let t = Wire::from_block(a0 ^ R, 2).hash(g) ^ c0 ^ b0;
+        self.channel.write_block(&t)?;
+        self.sent(1)?;
+        Ok(Wire::from_block(c0, 2))
+    }
+
+    /// A random zero wire-label of modulus `q`, of color 0 if `q` is 2 and
+    /// the scheme is privacy-free.
+    pub(crate) fn zero_wire(&mut self, q: u16) -> Wire {
+        let zero = Wire::rand(&mut self.rng, q);
+        if q == 2 && self.scheme == Scheme::PrivacyFree {
+            Wire::from_block(colorless(zero.as_block()), 2)
+        } else {
+            zero
         }
     }
 
@@ -63,8 +191,8 @@ impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
     /// Get the deltas, consuming the Garbler.
     ///
     /// This is useful for reusing wires in multiple garbled circuit instances.
-    pub fn get_deltas(self) -> HashMap<u16, Wire> {
-        self.deltas
+    pub fn get_deltas(mut self) -> HashMap<u16, Wire> {
+        std::mem::take(&mut self.deltas)
     }
 
     /// Send a wire over the established channel.
@@ -75,7 +203,7 @@ impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
 
     /// Encode a wire, producing the zero wire as well as the encoded value.
     pub fn encode_wire(&mut self, val: u16, modulus: u16) -> (Wire, Wire) {
-        let zero = Wire::rand(&mut self.rng, modulus);
+        let zero = self.zero_wire(modulus);
         let delta = self.delta(modulus);
         let enc = zero.plus(&delta.cmul(val));
         (zero, enc)
@@ -131,7 +259,7 @@ impl<C: AbstractChannel, RNG: RngCore + CryptoRng> FancyReveal for Garbler<C, RN
         // The evaluator needs our cooperation in order to see the output.
         // Hence, we call output() ourselves.
         self.output(x)?;
-        self.channel.flush()?;
+        self.sync_window()?;
         let val = self.channel.read_u16()?;
         Ok(val)
     }
@@ -142,7 +270,7 @@ impl<C: AbstractChannel, RNG: RngCore + CryptoRng> Fancy for Garbler<C, RNG> {
     type Error = GarblerError;
 
     fn constant(&mut self, x: u16, q: u16) -> Result<Wire, GarblerError> {
-        let zero = Wire::rand(&mut self.rng, q);
+        let zero = self.zero_wire(q);
         let wire = zero.plus(&self.delta(q).cmul_eq(x));
         self.send_wire(&wire)?;
         Ok(zero)
@@ -173,6 +301,20 @@ impl<C: AbstractChannel, RNG: RngCore + CryptoRng> Fancy for Garbler<C, RNG> {
 
         let q = A.modulus();
         let qb = B.modulus();
+        // As in `proj`, the generic gadget below cannot make or consume the
+        // colorless mod-2 labels of privacy-free garbling.
+        if (q == 2) != (qb == 2) && self.scheme == Scheme::PrivacyFree {
+            return Err(GarblerError::FancyError(FancyError::InvalidArg(
+                "privacy-free garbling cannot multiply mod-2 wires by others".to_string(),
+            )));
+        }
+        if q == 2 && qb == 2 {
+            match self.scheme {
+                Scheme::Bmr16 => (),
+                Scheme::HalfGates => return self.and_half_gates(A, B),
+                Scheme::PrivacyFree => return self.and_privacy_free(A, B),
+            }
+        }
         let gate_num = self.current_gate();
 
         let D = self.delta(q);
@@ -278,11 +420,17 @@ impl<C: AbstractChannel, RNG: RngCore + CryptoRng> Fancy for Garbler<C, RNG> {
         for block in gate.iter() {
             self.channel.write_block(block)?;
         }
+        self.sent(gate.len())?;
         Ok(X.plus_mov(&Y))
     }
 
     fn proj(&mut self, A: &Wire, q_out: u16, tt: Option<Vec<u16>>) -> Result<Wire, GarblerError> {
         let tt = tt.ok_or(GarblerError::TruthTableRequired)?;
+        if q_out == 2 && self.scheme == Scheme::PrivacyFree {
+            return Err(GarblerError::FancyError(FancyError::InvalidArg(
+                "privacy-free garbling cannot project onto mod-2 wires".to_string(),
+            )));
+        }
 
         let q_in = A.modulus();
         let mut gate = vec![Block::default(); q_in as usize - 1];
@@ -331,6 +479,7 @@ impl<C: AbstractChannel, RNG: RngCore + CryptoRng> Fancy for Garbler<C, RNG> {
         for block in gate.iter() {
             self.channel.write_block(block)?;
         }
+        self.sent(gate.len())?;
         Ok(C)
     }
 
@@ -342,6 +491,7 @@ impl<C: AbstractChannel, RNG: RngCore + CryptoRng> Fancy for Garbler<C, RNG> {
             let block = X.plus(&D.cmul(k)).hash(output_tweak(i, k));
             self.channel.write_block(&block)?;
         }
+        self.sent(q as usize)?;
         Ok(None)
     }
 }
@@ -6,6 +6,9 @@
 
 //! Functions for parsing and running a circuit file based on the format given
 //! here: <https://homes.esat.kuleuven.be/~nsmart/MPC/>.
+//!
+//! This is the older of the two Bristol formats; files in the newer Bristol
+//! Fashion format are read and written by `circuit::bristol`.
 
 use crate::{
     circuit::{Circuit, CircuitRef, Gate},
@@ -0,0 +1,221 @@
+// -*- mode: rust; -*-
+//
+// This file is part of twopac.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Secure comparison of two integers (Yao's millionaires' problem), for parties
+//! who need only this and not the circuit API.
+//!
+//! The garbler holds `x` and the evaluator `y`, both unsigned and at most
+//! `nbits` bits wide, with `nbits` agreed on in advance. `garbler_compare` and
+//! `evaluator_compare` reveal to both parties how their inputs compare and
+//! nothing else. `garbler_greater_shared` and `evaluator_greater_shared`
+//! instead leave each party with a random-looking bit, which XOR to whether
+//! `x > y`, for use as an input to a later protocol.
+//!
+//! Each call runs a fresh semi-honest garbled circuit, including the base OTs
+//! of `OT`.
+//!
+//! ```no_run
+//! use fancy_garbling::twopac::comparison;
+//! use ocelot::ot::{ChouOrlandiReceiver, ChouOrlandiSender};
+//! use scuttlebutt::{unix_channel_pair, AesRng};
+//!
+//! let (a, b) = unix_channel_pair();
+//! let handle = std::thread::spawn(move || {
+//!     comparison::garbler_compare::<_, _, ChouOrlandiSender>(a, AesRng::new(), 5_000_000, 32)
+//! });
+//! let ordering =
+//!     comparison::evaluator_compare::<_, _, ChouOrlandiReceiver>(b, AesRng::new(), 7_000_000, 32)
+//!         .unwrap();
+//! assert_eq!(ordering, std::cmp::Ordering::Greater);
+//! assert_eq!(handle.join().unwrap().unwrap(), std::cmp::Ordering::Less);
+//! ```
+
+use crate::{
+    errors::{FancyError, TwopacError},
+    twopac::semihonest::{Evaluator, Garbler},
+    BinaryBundle,
+    BinaryGadgets,
+    Fancy,
+    FancyInput,
+    FancyReveal,
+};
+use ocelot::ot::{Receiver as OtReceiver, Sender as OtSender};
+use rand::{CryptoRng, Rng, SeedableRng};
+use scuttlebutt::{AbstractChannel, Block, SemiHonest};
+use std::cmp::Ordering;
+
+// Fail unless `value` fits in `nbits` bits, of which there are at most 128.
+fn check_input(value: u128, nbits: usize) -> Result<(), TwopacError> {
+    if nbits == 0 || nbits > 128 {
+        return Err(
+            FancyError::InvalidArg(format!("cannot compare {}-bit integers", nbits)).into(),
+        );
+    }
+    if nbits < 128 && value >> nbits != 0 {
+        return Err(FancyError::InvalidArg(format!("{} exceeds {} bits", value, nbits)).into());
+    }
+    Ok(())
+}
+
+// Reveal whether `x < y` and whether `y < x`.
+fn reveal_order<F: FancyReveal + BinaryGadgets>(
+    f: &mut F,
+    x: &BinaryBundle<F::Item>,
+    y: &BinaryBundle<F::Item>,
+) -> Result<Ordering, F::Error> {
+    let lt = f.bin_lt(x, y)?;
+    let gt = f.bin_lt(y, x)?;
+    let order = match (f.reveal(&lt)?, f.reveal(&gt)?) {
+        (1, _) => Ordering::Less,
+        (_, 1) => Ordering::Greater,
+        _ => Ordering::Equal,
+    };
+    Ok(order)
+}
+
+/// Compare the garbler's input `x` with the evaluator's, returning how `x`
+/// compares to it. Must be paired with `evaluator_compare`.
+pub fn garbler_compare<C, RNG, OT>(
+    channel: C,
+    rng: RNG,
+    x: u128,
+    nbits: usize,
+) -> Result<Ordering, TwopacError>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + Rng + SeedableRng<Seed = Block>,
+    OT: OtSender<Msg = Block> + SemiHonest,
+{
+    check_input(x, nbits)?;
+    let mut gb = Garbler::<C, RNG, OT>::new(channel, rng)?;
+    let x = gb.bin_encode(x, nbits)?;
+    let y = gb.bin_receive(nbits)?;
+    reveal_order(&mut gb, &x, &y)
+}
+
+/// Compare the evaluator's input `y` with the garbler's, returning how `y`
+/// compares to it. Must be paired with `garbler_compare`.
+pub fn evaluator_compare<C, RNG, OT>(
+    channel: C,
+    rng: RNG,
+    y: u128,
+    nbits: usize,
+) -> Result<Ordering, TwopacError>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + Rng,
+    OT: OtReceiver<Msg = Block> + SemiHonest,
+{
+    check_input(y, nbits)?;
+    let mut ev = Evaluator::<C, RNG, OT>::new(channel, rng)?;
+    let x = ev.bin_receive(nbits)?;
+    let y = ev.bin_encode(y, nbits)?;
+    // Both parties must build the same circuit, so the evaluator compares the
+    // garbler's input to its own and flips the result.
+    reveal_order(&mut ev, &x, &y).map(Ordering::reverse)
+}
+
+/// Compare the garbler's input `x` with the evaluator's, returning the
+/// garbler's share of whether `x` is the greater. Must be paired with
+/// `evaluator_greater_shared`.
+pub fn garbler_greater_shared<C, RNG, OT>(
+    channel: C,
+    mut rng: RNG,
+    x: u128,
+    nbits: usize,
+) -> Result<bool, TwopacError>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + Rng + SeedableRng<Seed = Block>,
+    OT: OtSender<Msg = Block> + SemiHonest,
+{
+    check_input(x, nbits)?;
+    let share = rng.gen::<bool>();
+    let mut gb = Garbler::<C, RNG, OT>::new(channel, rng)?;
+    let x = gb.bin_encode(x, nbits)?;
+    let mask = gb.encode(share as u16, 2)?;
+    let y = gb.bin_receive(nbits)?;
+    let gt = gb.bin_lt(&y, &x)?;
+    let masked = gb.xor(&gt, &mask)?;
+    // Only the evaluator learns the masked bit.
+    gb.output(&masked)?;
+    gb.get_channel().flush()?;
+    Ok(share)
+}
+
+/// Compare the evaluator's input `y` with the garbler's, returning the
+/// evaluator's share of whether the garbler's input is the greater. Must be
+/// paired with `garbler_greater_shared`.
+pub fn evaluator_greater_shared<C, RNG, OT>(
+    channel: C,
+    rng: RNG,
+    y: u128,
+    nbits: usize,
+) -> Result<bool, TwopacError>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + Rng,
+    OT: OtReceiver<Msg = Block> + SemiHonest,
+{
+    check_input(y, nbits)?;
+    let mut ev = Evaluator::<C, RNG, OT>::new(channel, rng)?;
+    let x = ev.bin_receive(nbits)?;
+    let mask = ev.receive(2)?;
+    let y = ev.bin_encode(y, nbits)?;
+    let gt = ev.bin_lt(&y, &x)?;
+    let masked = ev.xor(&gt, &mask)?;
+    let share = ev
+        .output(&masked)?
+        .ok_or_else(|| FancyError::InvalidArg("the evaluator has no output".to_string()))?;
+    Ok(share == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ocelot::ot::{ChouOrlandiReceiver, ChouOrlandiSender};
+    use scuttlebutt::{unix_channel_pair, AesRng};
+
+    const NBITS: usize = 8;
+    const PAIRS: [(u128, u128); 6] = [(0, 0), (3, 200), (200, 3), (255, 255), (128, 127), (0, 1)];
+
+    #[test]
+    fn test_compare() {
+        for &(x, y) in PAIRS.iter() {
+            let (a, b) = unix_channel_pair();
+            let handle = std::thread::spawn(move || {
+                garbler_compare::<_, _, ChouOrlandiSender>(a, AesRng::new(), x, NBITS).unwrap()
+            });
+            let ours =
+                evaluator_compare::<_, _, ChouOrlandiReceiver>(b, AesRng::new(), y, NBITS).unwrap();
+            assert_eq!(handle.join().unwrap(), x.cmp(&y));
+            assert_eq!(ours, y.cmp(&x));
+        }
+    }
+
+    #[test]
+    fn test_greater_shared() {
+        for &(x, y) in PAIRS.iter() {
+            let (a, b) = unix_channel_pair();
+            let handle = std::thread::spawn(move || {
+                garbler_greater_shared::<_, _, ChouOrlandiSender>(a, AesRng::new(), x, NBITS)
+                    .unwrap()
+            });
+            let theirs =
+                evaluator_greater_shared::<_, _, ChouOrlandiReceiver>(b, AesRng::new(), y, NBITS)
+                    .unwrap();
+            assert_eq!(handle.join().unwrap() ^ theirs, x > y);
+        }
+    }
+
+    #[test]
+    fn test_check_input() {
+        assert!(check_input(255, 8).is_ok());
+        assert!(check_input(256, 8).is_err());
+        assert!(check_input(u128::MAX, 128).is_ok());
+        assert!(check_input(0, 0).is_err());
+    }
+}
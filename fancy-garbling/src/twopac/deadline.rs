@@ -0,0 +1,114 @@
+// -*- mode: rust; -*-
+//
+// This file is part of twopac.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Running a two-party computation within a time budget.
+//!
+//! `run` hands a closure a channel which fails once a `Deadline` has passed,
+//! over which it builds a `Garbler` or an `Evaluator` and computes. A run
+//! which fails because its deadline has passed fails with
+//! `TwopacError::DeadlineExceeded`, reporting how far it got; any other error
+//! is returned as is. A read blocked on a peer which sends nothing is only
+//! interrupted by a timeout on the transport, e.g. a read timeout of
+//! `Deadline::remaining` on a `TcpStream`.
+
+use crate::errors::TwopacError;
+use scuttlebutt::{AbstractChannel, DeadlineChannel};
+
+pub use scuttlebutt::Deadline;
+
+/// Run `f` over `channel` until `deadline`, failing with
+/// `TwopacError::DeadlineExceeded` if it fails because the deadline has
+/// passed.
+pub fn run<C, T>(
+    deadline: Deadline,
+    channel: &C,
+    f: impl FnOnce(DeadlineChannel<C>) -> Result<T, TwopacError>,
+) -> Result<T, TwopacError>
+where
+    C: AbstractChannel,
+{
+    let channel = DeadlineChannel::new(channel.clone(), deadline);
+    match f(channel.clone()) {
+        Err(_) if channel.exceeded() => Err(TwopacError::DeadlineExceeded {
+            elapsed: deadline.elapsed(),
+            bytes_read: channel.bytes_read(),
+            bytes_written: channel.bytes_written(),
+        }),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        twopac::semihonest::{Evaluator, Garbler},
+        Fancy,
+        FancyInput,
+    };
+    use ocelot::ot::{ChouOrlandiReceiver, ChouOrlandiSender};
+    use scuttlebutt::{unix_channel_pair, AesRng, UnixChannel};
+    use std::time::{Duration, Instant};
+
+    type Channel = DeadlineChannel<UnixChannel>;
+
+    #[test]
+    fn test_deadline() {
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let deadline = Deadline::after(Duration::from_secs(60));
+            run(deadline, &sender, |channel| {
+                let mut gb =
+                    Garbler::<Channel, AesRng, ChouOrlandiSender>::new(channel, AesRng::new())?;
+                let x = gb.encode(1, 3)?;
+                let y = gb.receive(3)?;
+                let z = gb.add(&x, &y)?;
+                gb.output(&z)?;
+                Ok(())
+            })
+        });
+        let deadline = Deadline::after(Duration::from_secs(60));
+        let output = run(deadline, &receiver, |channel| {
+            let mut ev =
+                Evaluator::<Channel, AesRng, ChouOrlandiReceiver>::new(channel, AesRng::new())?;
+            let x = ev.receive(3)?;
+            let y = ev.encode(1, 3)?;
+            let z = ev.add(&x, &y)?;
+            ev.output(&z)
+        })
+        .unwrap();
+        assert_eq!(output, Some(2));
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_deadline_exceeded() {
+        // The garbler sets up its OTs, then stalls past the deadline of the
+        // evaluator.
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let gb = Garbler::<UnixChannel, AesRng, ChouOrlandiSender>::new(sender, AesRng::new());
+            std::thread::sleep(Duration::from_millis(200));
+            drop(gb);
+        });
+        let deadline = Deadline::at(Instant::now() + Duration::from_millis(50));
+        let e = run(deadline, &receiver, |channel| {
+            let mut ev =
+                Evaluator::<Channel, AesRng, ChouOrlandiReceiver>::new(channel, AesRng::new())?;
+            std::thread::sleep(Duration::from_millis(100));
+            ev.receive(3)?;
+            Ok(())
+        })
+        .unwrap_err();
+        handle.join().unwrap();
+        match e {
+            TwopacError::DeadlineExceeded { elapsed, .. } => {
+                assert!(elapsed >= Duration::from_millis(50))
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
@@ -6,4 +6,6 @@
 
 //! Implementations of two-party secure computation.
 
+pub mod comparison;
+pub mod deadline;
 pub mod semihonest;
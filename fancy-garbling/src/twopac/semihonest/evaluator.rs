@@ -4,7 +4,16 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
-use crate::{errors::TwopacError, Evaluator as Ev, Fancy, FancyInput, FancyReveal, Wire};
+use crate::{
+    errors::TwopacError,
+    Evaluator as Ev,
+    Fancy,
+    FancyInput,
+    FancyReveal,
+    Scheme,
+    Window,
+    Wire,
+};
 use ocelot::ot::Receiver as OtReceiver;
 use rand::{CryptoRng, Rng};
 use scuttlebutt::{AbstractChannel, Block, SemiHonest};
@@ -39,6 +48,18 @@ impl<C: AbstractChannel, RNG: CryptoRng + Rng, OT: OtReceiver<Msg = Block> + Sem
         &mut self.channel
     }
 
+    /// Evaluate AND gates garbled with `scheme`, which must match the
+    /// garbler's `set_scheme`, from now on.
+    pub fn set_scheme(&mut self, scheme: Scheme) {
+        self.evaluator.set_scheme(scheme);
+    }
+
+    /// Acknowledge garbled gates as the garbler's `window`, which must match
+    /// its `set_window`, requires from now on.
+    pub fn set_window(&mut self, window: Window) {
+        self.evaluator.set_window(window);
+    }
+
     fn run_ot(&mut self, inputs: &[bool]) -> Result<Vec<Block>, TwopacError> {
         self.ot
             .receive(&mut self.channel, &inputs, &mut self.rng)
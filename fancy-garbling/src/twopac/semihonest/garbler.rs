@@ -58,7 +58,7 @@ impl<
         let mut wire = Wire::zero(q);
         let inputs = (0..len)
             .map(|i| {
-                let zero = Wire::rand(&mut self.rng, q);
+                let zero = self.garbler.zero_wire(q);
                 let one = zero.plus(&delta);
                 wire = wire.plus(&zero.cmul(1 << i));
                 (zero.as_block(), one.as_block())
@@ -99,6 +99,8 @@ impl<
     }
 
     fn receive_many(&mut self, qs: &[u16]) -> Result<Vec<Wire>, TwopacError> {
+        // Acknowledgments of the garbled gates come before the OT messages.
+        self.garbler.sync_window()?;
         let n = qs.len();
         let lens = qs.iter().map(|q| f32::from(*q).log(2.0).ceil() as usize);
         let mut wires = Vec::with_capacity(n);
@@ -23,6 +23,7 @@ mod tests {
         CrtGadgets,
         Fancy,
         FancyInput,
+        Window,
     };
     use itertools::Itertools;
     use ocelot::ot::{ChouOrlandiReceiver, ChouOrlandiSender};
@@ -105,6 +106,47 @@ mod tests {
         assert_eq!(target, result);
     }
 
+    #[test]
+    fn test_window() {
+        let mut rng = rand::thread_rng();
+        let n = 4;
+        let q = crate::util::modulus_with_width(10);
+        let xs = (0..n).map(|_| rng.gen_u128() % q).collect::<Vec<u128>>();
+        let ys = (0..n).map(|_| rng.gen_u128() % q).collect::<Vec<u128>>();
+        let mut dummy = Dummy::new();
+        let dummy_input = xs
+            .iter()
+            .chain(ys.iter())
+            .map(|x| dummy.crt_encode(*x, q).unwrap())
+            .collect_vec();
+        let target = relu(&mut dummy, &dummy_input).unwrap();
+
+        // The evaluator's inputs are transferred after some gates are
+        // garbled, and so after some acknowledgments are sent.
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let rng = AesRng::new();
+            let mut gb =
+                Garbler::<UnixChannel, AesRng, ChouOrlandiSender>::new(sender, rng).unwrap();
+            gb.set_window(Window::Bytes(1 << 12)).unwrap();
+            let xs = gb.crt_encode_many(&xs, q).unwrap();
+            relu(&mut gb, &xs);
+            let ys = gb.crt_receive_many(n, q).unwrap();
+            relu(&mut gb, &ys);
+            gb.sync_window().unwrap();
+        });
+        let rng = AesRng::new();
+        let mut ev =
+            Evaluator::<UnixChannel, AesRng, ChouOrlandiReceiver>::new(receiver, rng).unwrap();
+        ev.set_window(Window::Bytes(1 << 12));
+        let xs = ev.crt_receive_many(n, q).unwrap();
+        let mut result = relu(&mut ev, &xs).unwrap();
+        let ys = ev.crt_encode_many(&ys, q).unwrap();
+        result.extend(relu(&mut ev, &ys).unwrap());
+        handle.join().unwrap();
+        assert_eq!(target, result);
+    }
+
     #[test]
     fn test_aes() {
         let circ = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
@@ -8,6 +8,7 @@
 //!
 //! Note: all number representations in this library are little-endian.
 
+use crate::errors::FancyError;
 #[cfg(feature = "nightly")]
 use core::arch::x86_64::*;
 use itertools::Itertools;
@@ -368,6 +369,225 @@ pub fn is_power_of_2(x: u16) -> bool {
     (x & (x - 1)) == 0
 }
 
+/// Convert the CRT representation `xs` with respect to the primes `from` into
+/// the CRT representation with respect to the primes `to`.
+pub fn crt_convert(xs: &[u16], from: &[u16], to: &[u16]) -> Vec<u16> {
+    crt(crt_inv(xs, from), to)
+}
+
+/// Check that `ps` are distinct primes whose product fits in a `u128`, and
+/// return the product, so that `ps` may be used as a CRT basis.
+pub fn checked_crt_modulus(ps: &[u16]) -> Result<u128, FancyError> {
+    if ps.is_empty() {
+        return Err(FancyError::InvalidArg("empty CRT basis".to_string()));
+    }
+    let mut q: u128 = 1;
+    for (i, &p) in ps.iter().enumerate() {
+        if !is_prime(u64::from(p)) {
+            return Err(FancyError::InvalidArg(format!("{} is not prime", p)));
+        }
+        if ps[..i].contains(&p) {
+            return Err(FancyError::InvalidArg(format!("{} is repeated", p)));
+        }
+        q = q
+            .checked_mul(u128::from(p))
+            .ok_or_else(|| FancyError::InvalidArg("CRT modulus overflows".to_string()))?;
+    }
+    Ok(q)
+}
+
+/// Like `base_primes_with_width`, but checks that `primes` form a valid CRT
+/// basis, and returns an error rather than panicking if they are too few.
+pub fn checked_primes_with_width(nbits: u32, primes: &[u16]) -> Result<Vec<u16>, FancyError> {
+    let mut ps = Vec::new();
+    for &p in primes.iter() {
+        ps.push(p);
+        let q = checked_crt_modulus(&ps)?;
+        if nbits < 128 && (q >> nbits) > 0 {
+            return Ok(ps);
+        }
+    }
+    Err(FancyError::InvalidArg(format!(
+        "not enough primes for {}-bit integers",
+        nbits
+    )))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// modular reduction
+
+// Return the high 128 bits of the 256-bit product `a * b`.
+#[inline]
+fn mul_hi(a: u128, b: u128) -> u128 {
+    const LO: u128 = (1 << 64) - 1;
+    let (a1, a0) = (a >> 64, a & LO);
+    let (b1, b0) = (b >> 64, b & LO);
+    let (p01, p10) = (a0 * b1, a1 * b0);
+    let mid = ((a0 * b0) >> 64) + (p01 & LO) + (p10 & LO);
+    a1 * b1 + (p01 >> 64) + (p10 >> 64) + (mid >> 64)
+}
+
+/// Barrett reduction modulo a fixed `q`, which computes `x % q` with
+/// multiplications in place of a division.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Barrett {
+    q: u64,
+    // `floor((2^128 - 1) / q)`
+    m: u128,
+}
+
+impl Barrett {
+    /// Prepare to reduce modulo `q`, which must be at least 2.
+    pub fn new(q: u64) -> Self {
+        assert!(q > 1, "modulus must be at least 2");
+        Self {
+            q,
+            m: u128::MAX / u128::from(q),
+        }
+    }
+
+    /// Return the modulus.
+    #[inline]
+    pub fn modulus(&self) -> u64 {
+        self.q
+    }
+
+    /// Compute `x mod q`.
+    #[inline]
+    pub fn reduce(&self, x: u128) -> u64 {
+        let q = u128::from(self.q);
+        // The estimated quotient is at most two less than the real one.
+        let mut r = x - mul_hi(x, self.m) * q;
+        while r >= q {
+            r -= q;
+        }
+        r as u64
+    }
+
+    /// Compute `a * b mod q`.
+    #[inline]
+    pub fn mul(&self, a: u64, b: u64) -> u64 {
+        self.reduce(u128::from(a) * u128::from(b))
+    }
+
+    /// Compute `a^e mod q`.
+    pub fn pow(&self, a: u64, mut e: u64) -> u64 {
+        let mut a = self.reduce(u128::from(a));
+        let mut z = self.reduce(1);
+        while e > 0 {
+            if e & 1 == 1 {
+                z = self.mul(z, a);
+            }
+            a = self.mul(a, a);
+            e >>= 1;
+        }
+        z
+    }
+}
+
+/// Montgomery multiplication modulo a fixed odd `q < 2^63`.
+///
+/// Values are kept in Montgomery form, `x * 2^64 mod q`, in which
+/// multiplication needs no division. Convert with `to_montgomery` and
+/// `from_montgomery` at the ends of a computation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Montgomery {
+    q: u64,
+    // `-q^-1 mod 2^64`
+    qinv: u64,
+    // `2^128 mod q`
+    r2: u64,
+}
+
+impl Montgomery {
+    /// Prepare to multiply modulo `q`, which must be odd, greater than one,
+    /// and less than `2^63`.
+    pub fn new(q: u64) -> Self {
+        assert!(
+            q > 1 && q & 1 == 1 && q >> 63 == 0,
+            "invalid Montgomery modulus"
+        );
+        // Newton's iteration doubles the number of correct low bits of the
+        // inverse each time, starting from three.
+        let mut inv = q;
+        for _ in 0..5 {
+            inv = inv.wrapping_mul(2u64.wrapping_sub(q.wrapping_mul(inv)));
+        }
+        let r = (1u128 << 64) % u128::from(q);
+        Self {
+            q,
+            qinv: inv.wrapping_neg(),
+            r2: (r * r % u128::from(q)) as u64,
+        }
+    }
+
+    /// Return the modulus.
+    #[inline]
+    pub fn modulus(&self) -> u64 {
+        self.q
+    }
+
+    // Compute `t * 2^-64 mod q`, for `t < q * 2^64`.
+    #[inline]
+    fn redc(&self, t: u128) -> u64 {
+        let m = (t as u64).wrapping_mul(self.qinv);
+        let t = ((t + u128::from(m) * u128::from(self.q)) >> 64) as u64;
+        if t >= self.q {
+            t - self.q
+        } else {
+            t
+        }
+    }
+
+    /// Convert `x` into Montgomery form.
+    #[inline]
+    pub fn to_montgomery(&self, x: u64) -> u64 {
+        self.redc(u128::from(x % self.q) * u128::from(self.r2))
+    }
+
+    /// Convert `x` out of Montgomery form.
+    #[inline]
+    pub fn from_montgomery(&self, x: u64) -> u64 {
+        self.redc(u128::from(x))
+    }
+
+    /// Multiply `a` and `b`, both in Montgomery form.
+    #[inline]
+    pub fn mul(&self, a: u64, b: u64) -> u64 {
+        self.redc(u128::from(a) * u128::from(b))
+    }
+}
+
+/// Deterministically check whether `n` is prime, using Miller-Rabin with a
+/// set of bases known to suffice below `2^64`.
+pub fn is_prime(n: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    for &p in BASES.iter() {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+    let d = (n - 1) >> (n - 1).trailing_zeros();
+    let s = (n - 1).trailing_zeros();
+    let b = Barrett::new(n);
+    BASES.iter().all(|&a| {
+        let mut x = b.pow(a, d);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = b.mul(x, x);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
 /// Extra Rng functionality, useful for `fancy-garbling`.
 pub trait RngExt: rand::Rng + Sized {
     /// Randomly generate a `bool`.
@@ -494,6 +714,61 @@ mod tests {
         }
     }
 
+    #[test]
+    fn reduction() {
+        let mut rng = thread_rng();
+        for _ in 0..128 {
+            let q = rng.gen_u64() >> (rng.gen_u16() % 63);
+            let b = Barrett::new(q.max(2));
+            let x = rng.gen_u128();
+            assert_eq!(u128::from(b.reduce(x)), x % u128::from(b.modulus()));
+
+            let q = (q >> 1) | 1;
+            let m = Montgomery::new(q.max(3));
+            let q = m.modulus();
+            let (x, y) = (rng.gen_u64() % q, rng.gen_u64() % q);
+            let z = m.mul(m.to_montgomery(x), m.to_montgomery(y));
+            assert_eq!(m.from_montgomery(z), b_mul(x, y, q));
+            assert_eq!(Barrett::new(q).mul(x, y), b_mul(x, y, q));
+        }
+    }
+
+    fn b_mul(x: u64, y: u64, q: u64) -> u64 {
+        (u128::from(x) * u128::from(y) % u128::from(q)) as u64
+    }
+
+    #[test]
+    fn primality() {
+        for n in 0..1000u64 {
+            assert_eq!(is_prime(n), n > 1 && (2..n).all(|d| n % d != 0), "{}", n);
+        }
+        assert!(PRIMES.iter().all(|&p| is_prime(p.into())));
+        // Carmichael numbers, and a strong pseudoprime to bases 2, 3, 5 and 7.
+        assert!(!is_prime(561) && !is_prime(41041));
+        assert!(!is_prime(3_215_031_751));
+        assert!(is_prime((1 << 61) - 1));
+        assert!(is_prime(18_446_744_073_709_551_557));
+        assert!(!is_prime(((1u64 << 32) - 5) * ((1 << 32) - 17)));
+    }
+
+    #[test]
+    fn checked_moduli() {
+        let mut rng = thread_rng();
+        let ps = checked_primes_with_width(64, &PRIMES).unwrap();
+        assert_eq!(ps, primes_with_width(64));
+        assert_eq!(checked_crt_modulus(&ps).unwrap(), product(&ps));
+        assert!(checked_crt_modulus(&[]).is_err());
+        assert!(checked_crt_modulus(&[3, 5, 9]).is_err());
+        assert!(checked_crt_modulus(&[3, 5, 3]).is_err());
+        assert!(checked_primes_with_width(64, &PRIMES[..5]).is_err());
+
+        let qs = &PRIMES[3..12];
+        for _ in 0..16 {
+            let x = rng.gen_u128() % product(&ps).min(product(qs));
+            assert_eq!(crt_convert(&crt(x, &ps), &ps, qs), crt(x, qs));
+        }
+    }
+
     #[test]
     fn base_q_conversion() {
         let mut rng = thread_rng();
@@ -13,7 +13,9 @@ use scuttlebutt::{Block, AES_HASH};
 mod npaths_tab;
 
 /// The core wire-label type.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// `Debug` shows the modulus of a wire but never its label.
+#[derive(Clone, PartialEq)]
 #[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub enum Wire {
     /// Representation of a `mod-2` wire.
@@ -49,6 +51,20 @@ pub enum Wire {
     },
 }
 
+impl std::fmt::Debug for Wire {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Wire::Mod2 { .. } => write!(f, "Wire::Mod2(..)"),
+            Wire::Mod3 { .. } => write!(f, "Wire::Mod3(..)"),
+            Wire::ModN { q, ds } => f
+                .debug_struct("Wire::ModN")
+                .field("q", q)
+                .field("ndigits", &ds.len())
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
 impl std::default::Default for Wire {
     fn default() -> Self {
         Wire::Mod2 {
@@ -68,6 +84,21 @@ impl HasModulus for Wire {
 }
 
 impl Wire {
+    // Overwrite the label with zeros, for the owners of secret labels to call
+    // when they are dropped.
+    #[cfg(feature = "zeroize")]
+    pub(crate) fn erase(&mut self) {
+        use scuttlebutt::utils::erase;
+        match self {
+            Wire::Mod2 { val } => erase(val),
+            Wire::Mod3 { lsb, msb } => {
+                erase(lsb);
+                erase(msb);
+            }
+            Wire::ModN { ds, .. } => erase(ds),
+        }
+    }
+
     /// Get the digits of the wire.
     pub fn digits(&self) -> Vec<u16> {
         match self {
@@ -610,4 +641,15 @@ mod tests {
 
         assert_eq!(hashes, should_be);
     }
+
+    #[test]
+    fn debug_redacts_labels() {
+        let mut rng = thread_rng();
+        let w = Wire::rand(&mut rng, 2);
+        assert_eq!(format!("{:?}", w), "Wire::Mod2(..)");
+        let w = Wire::rand(&mut rng, 17);
+        let shown = format!("{:?}", w);
+        assert!(shown.contains("q: 17"));
+        assert!(!shown.contains(&format!("{:?}", w.digits())));
+    }
 }
@@ -0,0 +1,47 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ocelot::ggm::GgmKey;
+use scuttlebutt::AesRng;
+use std::time::Duration;
+
+fn bench_expand(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "ggm::expand",
+        |bench, &depth| {
+            let key = GgmKey::gen(&mut AesRng::new(), depth);
+            bench.iter(|| {
+                let leaves = key.expand();
+                criterion::black_box(leaves)
+            })
+        },
+        vec![10, 16, 20],
+    );
+}
+
+fn bench_punctured_expand(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "ggm::punctured_expand",
+        |bench, &depth| {
+            let key = GgmKey::gen(&mut AesRng::new(), depth);
+            let punctured = key.puncture(rand::random::<usize>() % (1 << depth));
+            bench.iter(|| {
+                let leaves = punctured.expand();
+                criterion::black_box(leaves)
+            })
+        },
+        vec![10, 16, 20],
+    );
+}
+
+criterion_group! {
+    name = ggm;
+    config = Criterion::default().warm_up_time(Duration::from_millis(100)).sample_size(10);
+    targets = bench_expand, bench_punctured_expand
+}
+
+criterion_main!(ggm);
@@ -0,0 +1,37 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ocelot::lpn::{LinearCode, LpnParams};
+use scuttlebutt::Block;
+use std::time::Duration;
+
+fn bench_local_linear_code(c: &mut Criterion) {
+    let params = LpnParams::FERRET_SETUP;
+    let code = params.local_linear_code(rand::random::<Block>());
+    let input = (0..params.k)
+        .map(|_| rand::random::<Block>())
+        .collect::<Vec<_>>();
+    c.bench_function_over_inputs(
+        "lpn::local_linear_code (ferret setup)",
+        move |bench, &nthreads| {
+            let mut output = vec![Block::default(); params.n];
+            bench.iter(|| {
+                code.encode_into_parallel(&input, &mut output, nthreads);
+                criterion::black_box(&output);
+            })
+        },
+        vec![1, 4],
+    );
+}
+
+criterion_group! {
+    name = lpn;
+    config = Criterion::default().warm_up_time(Duration::from_millis(100)).sample_size(10);
+    targets = bench_local_linear_code
+}
+
+criterion_main!(lpn);
@@ -163,6 +163,16 @@ fn bench_otext(c: &mut Criterion) {
         let bs = rand_bool_vec(T);
         bench.iter(|| _bench_block_ot::<ot::KosSender, ot::KosReceiver>(&bs, ms.clone()))
     });
+    c.bench_function("ot::FerretOT", move |bench| {
+        let m0s = rand_block_vec(T);
+        let m1s = rand_block_vec(T);
+        let ms = m0s
+            .into_iter()
+            .zip(m1s)
+            .collect::<Vec<(Block, Block)>>();
+        let bs = rand_bool_vec(T);
+        bench.iter(|| _bench_block_ot::<ot::FerretSender, ot::FerretReceiver>(&bs, ms.clone()))
+    });
 }
 
 fn bench_correlated_otext(c: &mut Criterion) {
@@ -187,6 +197,10 @@ fn bench_random_otext(c: &mut Criterion) {
         let bs = rand_bool_vec(T);
         bench.iter(|| _bench_block_rot::<ot::KosSender, ot::KosReceiver>(&bs))
     });
+    c.bench_function("rot::FerretOT", move |bench| {
+        let bs = rand_bool_vec(T);
+        bench.iter(|| _bench_block_rot::<ot::FerretSender, ot::FerretReceiver>(&bs))
+    });
 }
 
 criterion_group! {
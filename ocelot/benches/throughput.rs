@@ -0,0 +1,180 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Throughput benchmarks for the OT and OPRF primitives using `criterion`.
+//!
+//! These cover base OT latency, OT extension throughput across batch sizes,
+//! and the KKRT encoding rate. Building with `--features flamegraph` also
+//! profiles each group with `pprof`, writing a flamegraph per group to
+//! `target/flamegraphs/` in the crate directory.
+
+use criterion::{criterion_group, criterion_main, Criterion, ParameterizedBenchmark, Throughput};
+use ocelot::{
+    oprf::{self, Receiver as OprfReceiver, Sender as OprfSender},
+    ot::{self, Receiver, Sender},
+};
+use scuttlebutt::{AesRng, Block, Block512, Channel};
+use std::{
+    io::{BufReader, BufWriter},
+    os::unix::net::UnixStream,
+    time::Duration,
+};
+
+/// Batch sizes to run OT extension on.
+const OTEXT_SIZES: [usize; 4] = [1 << 10, 1 << 14, 1 << 18, 1 << 20];
+
+/// Number of KKRT encodings per iteration.
+const NENCODES: usize = 1 << 12;
+
+#[cfg(feature = "flamegraph")]
+struct Profile(&'static str, pprof::ProfilerGuard<'static>);
+
+#[cfg(feature = "flamegraph")]
+impl Drop for Profile {
+    fn drop(&mut self) {
+        let dir = std::path::Path::new("target/flamegraphs");
+        std::fs::create_dir_all(dir).unwrap();
+        let file = std::fs::File::create(dir.join(format!("{}.svg", self.0))).unwrap();
+        self.1.report().build().unwrap().flamegraph(file).unwrap();
+    }
+}
+
+/// Profile until the returned value is dropped.
+#[cfg(feature = "flamegraph")]
+fn profile(name: &'static str) -> Profile {
+    Profile(name, pprof::ProfilerGuard::new(997).unwrap())
+}
+
+#[cfg(not(feature = "flamegraph"))]
+struct Profile;
+
+#[cfg(not(feature = "flamegraph"))]
+fn profile(_name: &'static str) -> Profile {
+    Profile
+}
+
+fn rand_ms(size: usize) -> Vec<(Block, Block)> {
+    (0..size)
+        .map(|_| (rand::random::<Block>(), rand::random::<Block>()))
+        .collect()
+}
+fn rand_bool_vec(size: usize) -> Vec<bool> {
+    (0..size).map(|_| rand::random::<bool>()).collect()
+}
+
+fn _bench_block_ot<OTSender: Sender<Msg = Block>, OTReceiver: Receiver<Msg = Block>>(
+    bs: &[bool],
+    ms: Vec<(Block, Block)>,
+) {
+    let (sender, receiver) = UnixStream::pair().unwrap();
+    let handle = std::thread::spawn(move || {
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(sender.try_clone().unwrap());
+        let writer = BufWriter::new(sender);
+        let mut channel = Channel::new(reader, writer);
+        let mut ot = OTSender::init(&mut channel, &mut rng).unwrap();
+        ot.send(&mut channel, &ms, &mut rng).unwrap();
+    });
+    let mut rng = AesRng::new();
+    let reader = BufReader::new(receiver.try_clone().unwrap());
+    let writer = BufWriter::new(receiver);
+    let mut channel = Channel::new(reader, writer);
+    let mut ot = OTReceiver::init(&mut channel, &mut rng).unwrap();
+    ot.receive(&mut channel, bs, &mut rng).unwrap();
+    handle.join().unwrap();
+}
+
+fn bench_base_ot_latency(c: &mut Criterion) {
+    let _profile = profile("base_ot");
+    c.bench(
+        "base_ot",
+        ParameterizedBenchmark::new(
+            "ChouOrlandi",
+            |bench, &n| {
+                let ms = rand_ms(n);
+                let bs = rand_bool_vec(n);
+                bench.iter(|| {
+                    _bench_block_ot::<ot::ChouOrlandiSender, ot::ChouOrlandiReceiver>(
+                        &bs,
+                        ms.clone(),
+                    )
+                })
+            },
+            vec![1, 128],
+        )
+        .with_function("NaorPinkas", |bench, &n| {
+            let ms = rand_ms(n);
+            let bs = rand_bool_vec(n);
+            bench.iter(|| {
+                _bench_block_ot::<ot::NaorPinkasSender, ot::NaorPinkasReceiver>(&bs, ms.clone())
+            })
+        }),
+    );
+}
+
+fn bench_otext_throughput(c: &mut Criterion) {
+    let _profile = profile("ot_extension");
+    c.bench(
+        "ot_extension",
+        ParameterizedBenchmark::new(
+            "Alsz",
+            |bench, &n| {
+                let ms = rand_ms(n);
+                let bs = rand_bool_vec(n);
+                bench.iter(|| _bench_block_ot::<ot::AlszSender, ot::AlszReceiver>(&bs, ms.clone()))
+            },
+            OTEXT_SIZES.to_vec(),
+        )
+        .with_function("Kos", |bench, &n| {
+            let ms = rand_ms(n);
+            let bs = rand_bool_vec(n);
+            bench.iter(|| _bench_block_ot::<ot::KosSender, ot::KosReceiver>(&bs, ms.clone()))
+        })
+        .throughput(|&n| Throughput::Elements(n as u32)),
+    );
+}
+
+fn bench_kkrt_encode(c: &mut Criterion) {
+    let _profile = profile("kkrt_encode");
+    let (sender, receiver) = UnixStream::pair().unwrap();
+    let handle = std::thread::spawn(move || {
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let _ = oprf::KkrtReceiver::init(&mut channel, &mut rng).unwrap();
+    });
+    let mut rng = AesRng::new();
+    let reader = BufReader::new(sender.try_clone().unwrap());
+    let writer = BufWriter::new(sender);
+    let mut channel = Channel::new(reader, writer);
+    let oprf = oprf::KkrtSender::init(&mut channel, &mut rng).unwrap();
+    handle.join().unwrap();
+    let inputs = (0..NENCODES)
+        .map(|_| rand::random::<Block>())
+        .collect::<Vec<Block>>();
+    c.bench(
+        "kkrt",
+        criterion::Benchmark::new("encode", move |bench| {
+            let mut output = Block512::default();
+            bench.iter(|| {
+                for input in inputs.iter() {
+                    oprf.encode(*input, &mut output);
+                    criterion::black_box(&output);
+                }
+            })
+        })
+        .throughput(Throughput::Elements(NENCODES as u32)),
+    );
+}
+
+criterion_group! {
+    name = throughput;
+    config = Criterion::default().warm_up_time(Duration::from_millis(100)).sample_size(10);
+    targets = bench_base_ot_latency, bench_otext_throughput, bench_kkrt_encode
+}
+
+criterion_main!(throughput);
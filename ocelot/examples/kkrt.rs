@@ -5,12 +5,16 @@
 // See LICENSE for licensing information.
 
 use ocelot::oprf::{KkrtReceiver, KkrtSender, Receiver, Sender};
-use scuttlebutt::{AesRng, Block, TrackChannel};
+use scuttlebutt::{memtrack, AesRng, Block, TrackChannel};
 use std::{
     io::{BufReader, BufWriter},
     os::unix::net::UnixStream,
     time::SystemTime,
 };
+use swanky_bench::{measure, Report};
+
+#[global_allocator]
+static ALLOC: memtrack::CountingAlloc = memtrack::CountingAlloc::system();
 
 fn rand_block_vec(size: usize) -> Vec<Block> {
     (0..size).map(|_| rand::random::<Block>()).collect()
@@ -25,19 +29,14 @@ fn _test_oprf(n: usize) {
         let reader = BufReader::new(sender.try_clone().unwrap());
         let writer = BufWriter::new(sender);
         let mut channel = TrackChannel::new(reader, writer);
-        let start = SystemTime::now();
-        let mut oprf = KkrtSender::init(&mut channel, &mut rng).unwrap();
-        println!(
-            "Sender init time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
-        let start = SystemTime::now();
-        let _ = oprf.send(&mut channel, n, &mut rng).unwrap();
-        println!(
-            "[{}] Send time: {} ms",
-            n,
-            start.elapsed().unwrap().as_millis()
-        );
+        let (mut oprf, init) = measure("sender", "init", &mut channel, |channel| {
+            KkrtSender::init(channel, &mut rng).unwrap()
+        });
+        println!("Sender init time: {:.0} ms", init.millis);
+        let (_, send) = measure("sender", "send", &mut channel, |channel| {
+            oprf.send(channel, n, &mut rng).unwrap()
+        });
+        println!("[{}] Send time: {:.0} ms", n, send.millis);
         println!(
             "Sender communication (read): {:.2} Mb",
             channel.kilobits_read() / 1000.0
@@ -46,25 +45,23 @@ fn _test_oprf(n: usize) {
             "Sender communication (write): {:.2} Mb",
             channel.kilobits_written() / 1000.0
         );
+        vec![init, send]
     });
     let mut rng = AesRng::new();
     let reader = BufReader::new(receiver.try_clone().unwrap());
     let writer = BufWriter::new(receiver);
     let mut channel = TrackChannel::new(reader, writer);
-    let start = SystemTime::now();
-    let mut oprf = KkrtReceiver::init(&mut channel, &mut rng).unwrap();
-    println!(
-        "Receiver init time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    let start = SystemTime::now();
-    let _ = oprf.receive(&mut channel, &selections, &mut rng).unwrap();
-    println!(
-        "[{}] Receiver time: {} ms",
-        n,
-        start.elapsed().unwrap().as_millis()
-    );
-    handle.join().unwrap();
+    let (mut oprf, init) = measure("receiver", "init", &mut channel, |channel| {
+        KkrtReceiver::init(channel, &mut rng).unwrap()
+    });
+    println!("Receiver init time: {:.0} ms", init.millis);
+    let (_, receive) = measure("receiver", "receive", &mut channel, |channel| {
+        oprf.receive(channel, &selections, &mut rng).unwrap()
+    });
+    println!("[{}] Receiver time: {:.0} ms", n, receive.millis);
+    let mut report = Report::new("kkrt").param("n", n as u64);
+    report.phases.extend(handle.join().unwrap());
+    report.phases.extend(vec![init, receive]);
     println!(
         "Receiver communication (read): {:.2} Mb",
         channel.kilobits_read() / 1000.0
@@ -74,6 +71,7 @@ fn _test_oprf(n: usize) {
         channel.kilobits_written() / 1000.0
     );
     println!("Total time: {} ms", total.elapsed().unwrap().as_millis());
+    report.emit().unwrap();
 }
 
 fn main() {
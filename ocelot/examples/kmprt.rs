@@ -6,12 +6,16 @@
 
 use ocelot::oprf::{KmprtReceiver, KmprtSender};
 use rand::Rng;
-use scuttlebutt::{AesRng, Block, Block512, TrackChannel};
+use scuttlebutt::{memtrack, AesRng, Block, Block512, TrackChannel};
 use std::{
     io::{BufReader, BufWriter},
     os::unix::net::UnixStream,
     time::SystemTime,
 };
+use swanky_bench::{measure, Report};
+
+#[global_allocator]
+static ALLOC: memtrack::CountingAlloc = memtrack::CountingAlloc::system();
 
 fn rand_block_vec(size: usize) -> Vec<Block> {
     (0..size).map(|_| rand::random::<Block>()).collect()
@@ -31,18 +35,14 @@ fn run(ninputs: usize, npoints: usize) {
         let reader = BufReader::new(sender.try_clone().unwrap());
         let writer = BufWriter::new(sender);
         let mut channel = TrackChannel::new(reader, writer);
-        let start = SystemTime::now();
-        let mut oprf = KmprtSender::init(&mut channel, &mut rng).unwrap();
-        println!(
-            "Sender init time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
-        let start = SystemTime::now();
-        let _ = oprf.send(&mut channel, &points, ninputs, &mut rng).unwrap();
-        println!(
-            "Sender send time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
+        let (mut oprf, init) = measure("sender", "init", &mut channel, |channel| {
+            KmprtSender::init(channel, &mut rng).unwrap()
+        });
+        println!("Sender init time: {:.0} ms", init.millis);
+        let (_, send) = measure("sender", "send", &mut channel, |channel| {
+            oprf.send(channel, &points, ninputs, &mut rng).unwrap()
+        });
+        println!("Sender send time: {:.0} ms", send.millis);
         println!(
             "Sender communication (read): {:.2} Mb",
             channel.kilobits_read() / 1000.0
@@ -51,24 +51,25 @@ fn run(ninputs: usize, npoints: usize) {
             "Sender communication (write): {:.2} Mb",
             channel.kilobits_written() / 1000.0
         );
+        vec![init, send]
     });
     let mut rng = AesRng::new();
     let reader = BufReader::new(receiver.try_clone().unwrap());
     let writer = BufWriter::new(receiver);
     let mut channel = TrackChannel::new(reader, writer);
-    let start = SystemTime::now();
-    let mut oprf = KmprtReceiver::init(&mut channel, &mut rng).unwrap();
-    println!(
-        "Receiver init time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    let start = SystemTime::now();
-    let _ = oprf.receive(&mut channel, &inputs, &mut rng).unwrap();
-    println!(
-        "Receiver send time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    handle.join().unwrap();
+    let (mut oprf, init) = measure("receiver", "init", &mut channel, |channel| {
+        KmprtReceiver::init(channel, &mut rng).unwrap()
+    });
+    println!("Receiver init time: {:.0} ms", init.millis);
+    let (_, receive) = measure("receiver", "receive", &mut channel, |channel| {
+        oprf.receive(channel, &inputs, &mut rng).unwrap()
+    });
+    println!("Receiver send time: {:.0} ms", receive.millis);
+    let mut report = Report::new("kmprt")
+        .param("ninputs", ninputs as u64)
+        .param("npoints", npoints as u64);
+    report.phases.extend(handle.join().unwrap());
+    report.phases.extend(vec![init, receive]);
     println!(
         "Receiver communication (read): {:.2} Mb",
         channel.kilobits_read() / 1000.0
@@ -78,6 +79,7 @@ fn run(ninputs: usize, npoints: usize) {
         channel.kilobits_written() / 1000.0
     );
     println!("Total time: {} ms", total.elapsed().unwrap().as_millis());
+    report.emit().unwrap();
 }
 
 fn main() {
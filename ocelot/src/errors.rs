@@ -15,6 +15,19 @@ pub enum Error {
     Other(String),
     /// Coin tossing failed.
     CoinTossError(scuttlebutt::cointoss::Error),
+    /// A session resumption ticket was rejected, for the reason given by
+    /// `String`.
+    InvalidTicket(String),
+    /// A saved protocol state could not be restored, for the reason given by
+    /// `String`.
+    InvalidCheckpoint(String),
+    /// An internal consistency check failed, typically because of a
+    /// malformed message from the other party. Only returned with the
+    /// `hardened` feature; otherwise such checks panic.
+    InternalInvariant(String),
+    /// The sender's opening of the committed OT at index `usize` does not
+    /// match its commitments or the message received.
+    InvalidOpening(usize),
 }
 
 impl From<std::io::Error> for Error {
@@ -36,6 +49,10 @@ impl std::fmt::Display for Error {
             Error::IoError(e) => write!(f, "IO error: {}", e),
             Error::Other(s) => write!(f, "other error: {}", s),
             Error::CoinTossError(e) => write!(f, "coin toss error: {}", e),
+            Error::InvalidTicket(s) => write!(f, "invalid resumption ticket: {}", s),
+            Error::InvalidCheckpoint(s) => write!(f, "invalid checkpoint: {}", s),
+            Error::InternalInvariant(s) => write!(f, "internal invariant violated: {}", s),
+            Error::InvalidOpening(i) => write!(f, "invalid opening of committed OT {}", i),
         }
     }
 }
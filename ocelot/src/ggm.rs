@@ -0,0 +1,352 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Implementation of the Goldreich-Goldwasser-Micali PRF, with puncturing (cf.
+//! <https://dl.acm.org/doi/10.1145/6490.6503>).
+//!
+//! A key is the root seed of a binary tree of depth `d`, in which each node
+//! is expanded into its two children by `Block::prg_expand`. The PRF on input
+//! `x ∈ [0, 2^d)` is the leaf reached by following the bits of `x`, most
+//! significant first. Puncturing a key at `x` gives the seeds of the `d`
+//! siblings along the path to `x`, which determine every leaf except `x`
+//! itself while revealing nothing about it.
+
+use rand::{CryptoRng, Rng, RngCore};
+use scuttlebutt::Block;
+
+/// Expand `seed` into its left and right children.
+#[inline]
+fn expand_node(seed: Block) -> (Block, Block) {
+    let children = seed.prg_expand(2);
+    (children[0], children[1])
+}
+
+/// Expand every node of one level of the tree into the next level, in place.
+fn expand_level(nodes: &mut Vec<Block>) {
+    let n = nodes.len();
+    nodes.resize(2 * n, Block::default());
+    // Go backwards so that we never overwrite a node before expanding it.
+    for i in (0..n).rev() {
+        let (left, right) = expand_node(nodes[i]);
+        nodes[2 * i] = left;
+        nodes[2 * i + 1] = right;
+    }
+}
+
+#[inline]
+fn bit(x: usize, depth: usize, level: usize) -> usize {
+    (x >> (depth - 1 - level)) & 1
+}
+
+/// A GGM PRF key.
+#[derive(Clone)]
+pub struct GgmKey {
+    seed: Block,
+    depth: usize,
+}
+
+impl GgmKey {
+    /// Make a key for the domain `[0, 2^depth)` from `seed`.
+    pub fn new(seed: Block, depth: usize) -> Self {
+        assert!(depth < 64, "GGM tree depth must be less than 64");
+        Self { seed, depth }
+    }
+
+    /// Generate a random key for the domain `[0, 2^depth)`.
+    pub fn gen<RNG: CryptoRng + RngCore>(rng: &mut RNG, depth: usize) -> Self {
+        Self::new(rng.gen(), depth)
+    }
+
+    /// Return the depth of the tree.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Return the size of the domain.
+    #[inline]
+    pub fn domain_size(&self) -> usize {
+        1 << self.depth
+    }
+
+    /// Evaluate the PRF on `x`.
+    pub fn eval(&self, x: usize) -> Block {
+        assert!(x < self.domain_size());
+        (0..self.depth).fold(self.seed, |node, level| {
+            let (left, right) = expand_node(node);
+            if bit(x, self.depth, level) == 0 {
+                left
+            } else {
+                right
+            }
+        })
+    }
+
+    /// Evaluate the PRF on the whole domain, level by level.
+    pub fn expand(&self) -> Vec<Block> {
+        let mut nodes = Vec::with_capacity(self.domain_size());
+        nodes.push(self.seed);
+        for _ in 0..self.depth {
+            expand_level(&mut nodes);
+        }
+        nodes
+    }
+
+    /// Evaluate the PRF on the whole domain, additionally returning, for
+    /// each level of the tree below the root, the XOR of all left children
+    /// and the XOR of all right children on that level.
+    ///
+    /// Given these sums, the holder of a point `x` can recover the sibling
+    /// seeds of a key punctured at `x` from a single chosen value per level,
+    /// which is how puncturable PRFs are distributed with OT.
+    pub fn expand_with_level_sums(&self) -> (Vec<Block>, Vec<(Block, Block)>) {
+        let mut nodes = Vec::with_capacity(self.domain_size());
+        let mut sums = Vec::with_capacity(self.depth);
+        nodes.push(self.seed);
+        for _ in 0..self.depth {
+            expand_level(&mut nodes);
+            let sum = nodes.chunks(2).fold(
+                (Block::default(), Block::default()),
+                |(left, right), pair| (left ^ pair[0], right ^ pair[1]),
+            );
+            sums.push(sum);
+        }
+        (nodes, sums)
+    }
+
+    /// Puncture the key at `x`.
+    pub fn puncture(&self, x: usize) -> PuncturedKey {
+        assert!(x < self.domain_size());
+        let mut siblings = Vec::with_capacity(self.depth);
+        let mut node = self.seed;
+        for level in 0..self.depth {
+            let (left, right) = expand_node(node);
+            if bit(x, self.depth, level) == 0 {
+                siblings.push(right);
+                node = left;
+            } else {
+                siblings.push(left);
+                node = right;
+            }
+        }
+        PuncturedKey {
+            point: x,
+            depth: self.depth,
+            siblings,
+        }
+    }
+}
+
+// The seed is left out.
+impl std::fmt::Debug for GgmKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("GgmKey")
+            .field("depth", &self.depth)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for GgmKey {
+    fn drop(&mut self) {
+        use scuttlebutt::utils::erase;
+        erase(&mut self.seed);
+    }
+}
+
+/// A GGM PRF key punctured at a single point.
+#[derive(Clone)]
+pub struct PuncturedKey {
+    point: usize,
+    depth: usize,
+    // `siblings[i]` is the sibling, on level `i + 1`, of the path to `point`.
+    siblings: Vec<Block>,
+}
+
+impl PuncturedKey {
+    /// Reconstruct a punctured key from the sibling seeds along the path to
+    /// `point`, ordered from the top of the tree down.
+    pub fn from_siblings(point: usize, siblings: Vec<Block>) -> Self {
+        let depth = siblings.len();
+        assert!(depth < 64 && point < 1 << depth);
+        Self {
+            point,
+            depth,
+            siblings,
+        }
+    }
+
+    /// Reconstruct a punctured key at `point` from the level sums output by
+    /// `GgmKey::expand_with_level_sums`. Only the sum on the side opposite
+    /// the path is used on each level: `sums[i]` may hold anything on the
+    /// side given by bit `i` of `point`, counting from the top.
+    pub fn from_level_sums(point: usize, sums: &[(Block, Block)]) -> Self {
+        let depth = sums.len();
+        assert!(depth < 64 && point < 1 << depth);
+        let mut siblings = Vec::with_capacity(depth);
+        // The nodes on the current level, with the one on the path unknown.
+        let mut nodes = vec![Block::default()];
+        for (level, (left, right)) in sums.iter().enumerate() {
+            expand_level(&mut nodes);
+            let prefix = point >> (depth - 1 - level);
+            // The sibling of the path node is the given sum XORed with every
+            // other known node on the same side.
+            let sibling = prefix ^ 1;
+            let mut seed = if sibling & 1 == 0 { *left } else { *right };
+            for i in ((sibling & 1)..nodes.len()).step_by(2) {
+                if i != sibling {
+                    seed ^= nodes[i];
+                }
+            }
+            nodes[sibling] = seed;
+            nodes[prefix] = Block::default();
+            siblings.push(seed);
+        }
+        Self {
+            point,
+            depth,
+            siblings,
+        }
+    }
+
+    /// Return the point the key is punctured at.
+    #[inline]
+    pub fn point(&self) -> usize {
+        self.point
+    }
+
+    /// Return the depth of the tree.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Return the sibling seeds along the path to the punctured point.
+    #[inline]
+    pub fn siblings(&self) -> &[Block] {
+        &self.siblings
+    }
+
+    /// Evaluate the PRF on `x`, or return `None` if `x` is the punctured
+    /// point.
+    pub fn eval(&self, x: usize) -> Option<Block> {
+        assert!(x < 1 << self.depth);
+        if x == self.point {
+            return None;
+        }
+        // Find the first level at which the paths to `x` and `point` diverge,
+        // and expand from the sibling seed there.
+        let level = (0..self.depth)
+            .find(|&level| bit(x, self.depth, level) != bit(self.point, self.depth, level))
+            .unwrap();
+        let leaf = (level + 1..self.depth).fold(self.siblings[level], |node, level| {
+            let (left, right) = expand_node(node);
+            if bit(x, self.depth, level) == 0 {
+                left
+            } else {
+                right
+            }
+        });
+        Some(leaf)
+    }
+
+    /// Evaluate the PRF on the whole domain, level by level, with the zero
+    /// block in place of the punctured point.
+    pub fn expand(&self) -> Vec<Block> {
+        let mut nodes = Vec::with_capacity(1 << self.depth);
+        nodes.push(Block::default());
+        for level in 0..self.depth {
+            expand_level(&mut nodes);
+            let prefix = self.point >> (self.depth - 1 - level);
+            nodes[prefix] = Block::default();
+            nodes[prefix ^ 1] = self.siblings[level];
+        }
+        nodes
+    }
+}
+
+// The sibling seeds are left out.
+impl std::fmt::Debug for PuncturedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PuncturedKey")
+            .field("point", &self.point)
+            .field("depth", &self.depth)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for PuncturedKey {
+    fn drop(&mut self) {
+        use scuttlebutt::utils::erase;
+        erase(&mut self.siblings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scuttlebutt::AesRng;
+
+    const DEPTH: usize = 8;
+
+    #[test]
+    fn test_eval_matches_expand() {
+        let mut rng = AesRng::new();
+        let key = GgmKey::gen(&mut rng, DEPTH);
+        let leaves = key.expand();
+        assert_eq!(leaves.len(), 1 << DEPTH);
+        for (x, leaf) in leaves.iter().enumerate() {
+            assert_eq!(key.eval(x), *leaf);
+        }
+        assert_eq!(
+            GgmKey::new(Block::default(), 0).expand(),
+            vec![Block::default()]
+        );
+    }
+
+    #[test]
+    fn test_puncture() {
+        let mut rng = AesRng::new();
+        let key = GgmKey::gen(&mut rng, DEPTH);
+        let leaves = key.expand();
+        let point = rng.gen::<usize>() % (1 << DEPTH);
+        let punctured = key.puncture(point);
+        let expanded = punctured.expand();
+        for x in 0..1 << DEPTH {
+            if x == point {
+                assert_eq!(punctured.eval(x), None);
+                assert_eq!(expanded[x], Block::default());
+            } else {
+                assert_eq!(punctured.eval(x), Some(leaves[x]));
+                assert_eq!(expanded[x], leaves[x]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_level_sums() {
+        let mut rng = AesRng::new();
+        let key = GgmKey::gen(&mut rng, DEPTH);
+        let (leaves, sums) = key.expand_with_level_sums();
+        assert_eq!(leaves, key.expand());
+        let point = rng.gen::<usize>() % (1 << DEPTH);
+        // Hide the sum on the path side of each level.
+        let sums = sums
+            .into_iter()
+            .enumerate()
+            .map(|(level, (left, right))| {
+                if bit(point, DEPTH, level) == 0 {
+                    (rng.gen(), right)
+                } else {
+                    (left, rng.gen())
+                }
+            })
+            .collect::<Vec<_>>();
+        let punctured = PuncturedKey::from_level_sums(point, &sums);
+        assert_eq!(punctured.siblings(), key.puncture(point).siblings());
+    }
+}
@@ -0,0 +1,276 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Secure inner products, built on Gilboa's multiplication protocol (cf.
+//! <https://doi.org/10.1007/3-540-48405-1_8>) over random OT.
+//!
+//! The sender holds a vector of weights and the receiver a vector of features
+//! of the same length, both over `Z_{2^64}`, i.e., `u64`s under wrapping
+//! arithmetic, so that signed values in two's complement work as well. For
+//! each bit of each feature the parties run one random OT, after which the
+//! sender sends an 8-byte correction; no circuit is garbled. `send_shared` and
+//! `receive_shared` leave the parties with additive shares of `Σ wᵢ·xᵢ`, while
+//! `send` and `receive` reveal it to the receiver.
+//!
+//! Only the low `nbits` bits of the features are multiplied, so that small
+//! features cost fewer OTs. Both parties pass the same `nbits`, and the
+//! receiver fails unless its features fit.
+//!
+//! The protocol is semi-honest: a malicious sender can shift the result by any
+//! amount of its choosing, even over a maliciously secure OT.
+
+use crate::{
+    errors::Error,
+    ot::{RandomReceiver, RandomSender},
+};
+use itertools::Itertools;
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{AbstractChannel, Block, SemiHonest};
+
+#[inline]
+fn low_u64(block: Block) -> u64 {
+    u128::from(block) as u64
+}
+
+fn check_nbits(nbits: usize) -> Result<(), Error> {
+    if nbits == 0 || nbits > 64 {
+        return Err(Error::Other(format!(
+            "cannot multiply by {}-bit features",
+            nbits
+        )));
+    }
+    Ok(())
+}
+
+/// Compute the inner product of `weights` with the receiver's features,
+/// returning the sender's additive share. Must be paired with
+/// `receive_shared`.
+pub fn send_shared<OT, C, RNG>(
+    ot: &mut OT,
+    channel: &mut C,
+    weights: &[u64],
+    nbits: usize,
+    rng: &mut RNG,
+) -> Result<u64, Error>
+where
+    OT: RandomSender<Msg = Block> + SemiHonest,
+    C: AbstractChannel,
+    RNG: CryptoRng + Rng,
+{
+    check_nbits(nbits)?;
+    channel.write_usize(weights.len())?;
+    channel.write_usize(nbits)?;
+    channel.flush()?;
+    if weights.is_empty() {
+        return Ok(0);
+    }
+    let pairs = ot.send_random(channel, weights.len() * nbits, rng)?;
+    let mut share = 0u64;
+    for (w, pairs) in weights.iter().zip(pairs.chunks(nbits)) {
+        for (j, (m0, m1)) in pairs.iter().enumerate() {
+            // The receiver ends up with `a₀ + xᵢⱼ·wᵢ·2ʲ`, whichever message
+            // it chose.
+            let (a0, a1) = (low_u64(*m0), low_u64(*m1));
+            channel.write_u64(a0.wrapping_add(w << j).wrapping_sub(a1))?;
+            share = share.wrapping_sub(a0);
+        }
+    }
+    channel.flush()?;
+    Ok(share)
+}
+
+/// Compute the inner product of `features` with the sender's weights,
+/// returning the receiver's additive share. Must be paired with
+/// `send_shared`.
+pub fn receive_shared<OT, C, RNG>(
+    ot: &mut OT,
+    channel: &mut C,
+    features: &[u64],
+    nbits: usize,
+    rng: &mut RNG,
+) -> Result<u64, Error>
+where
+    OT: RandomReceiver<Msg = Block> + SemiHonest,
+    C: AbstractChannel,
+    RNG: CryptoRng + Rng,
+{
+    check_nbits(nbits)?;
+    if nbits < 64 && features.iter().any(|x| x >> nbits != 0) {
+        return Err(Error::Other(format!("a feature exceeds {} bits", nbits)));
+    }
+    let n = channel.read_usize()?;
+    let theirs = channel.read_usize()?;
+    if n != features.len() || theirs != nbits {
+        return Err(Error::InvalidInputLength);
+    }
+    if features.is_empty() {
+        return Ok(0);
+    }
+    let choices = features
+        .iter()
+        .flat_map(|&x| (0..nbits).map(move |j| (x >> j) & 1 == 1))
+        .collect_vec();
+    let ms = ot.receive_random(channel, &choices, rng)?;
+    let mut share = 0u64;
+    for (&b, m) in choices.iter().zip(ms) {
+        let correction = channel.read_u64()?;
+        let t = low_u64(m);
+        share = share.wrapping_add(if b { t.wrapping_add(correction) } else { t });
+    }
+    Ok(share)
+}
+
+/// Compute the inner product of `weights` with the receiver's features,
+/// revealing it to the receiver only. Must be paired with `receive`.
+pub fn send<OT, C, RNG>(
+    ot: &mut OT,
+    channel: &mut C,
+    weights: &[u64],
+    nbits: usize,
+    rng: &mut RNG,
+) -> Result<(), Error>
+where
+    OT: RandomSender<Msg = Block> + SemiHonest,
+    C: AbstractChannel,
+    RNG: CryptoRng + Rng,
+{
+    let share = send_shared(ot, channel, weights, nbits, rng)?;
+    channel.write_u64(share)?;
+    channel.flush()?;
+    Ok(())
+}
+
+/// Compute the inner product of `features` with the sender's weights. Must be
+/// paired with `send`.
+pub fn receive<OT, C, RNG>(
+    ot: &mut OT,
+    channel: &mut C,
+    features: &[u64],
+    nbits: usize,
+    rng: &mut RNG,
+) -> Result<u64, Error>
+where
+    OT: RandomReceiver<Msg = Block> + SemiHonest,
+    C: AbstractChannel,
+    RNG: CryptoRng + Rng,
+{
+    let share = receive_shared(ot, channel, features, nbits, rng)?;
+    Ok(share.wrapping_add(channel.read_u64()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ot::{
+        AlszReceiver,
+        AlszSender,
+        KosReceiver,
+        KosSender,
+        Receiver as OtReceiver,
+        Sender as OtSender,
+    };
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    fn dot(weights: &[u64], features: &[u64]) -> u64 {
+        weights
+            .iter()
+            .zip(features)
+            .fold(0u64, |acc, (w, x)| acc.wrapping_add(w.wrapping_mul(*x)))
+    }
+
+    fn test_inner_product<S, R>()
+    where
+        S: RandomSender<Msg = Block> + SemiHonest,
+        R: RandomReceiver<Msg = Block> + SemiHonest,
+    {
+        let mut rng = AesRng::new();
+        let weights = (0..100).map(|_| rng.gen::<u64>()).collect_vec();
+        let features = (0..100).map(|_| rng.gen::<u16>() as u64).collect_vec();
+        let expected = dot(&weights, &features);
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut ot = S::init(&mut channel, &mut rng).unwrap();
+            let share = send_shared(&mut ot, &mut channel, &weights, 16, &mut rng).unwrap();
+            send(&mut ot, &mut channel, &weights, 16, &mut rng).unwrap();
+            share
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut ot = R::init(&mut channel, &mut rng).unwrap();
+        let share = receive_shared(&mut ot, &mut channel, &features, 16, &mut rng).unwrap();
+        let result = receive(&mut ot, &mut channel, &features, 16, &mut rng).unwrap();
+        assert_eq!(handle.join().unwrap().wrapping_add(share), expected);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_alsz() {
+        test_inner_product::<AlszSender, AlszReceiver>();
+    }
+
+    #[test]
+    fn test_kos() {
+        test_inner_product::<KosSender, KosReceiver>();
+    }
+
+    #[test]
+    fn test_signed() {
+        // Two's complement weights and full-width features.
+        let weights = [-3i64 as u64, 7, -1i64 as u64];
+        let features = [5, -2i64 as u64, u64::MAX];
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut ot = AlszSender::init(&mut channel, &mut rng).unwrap();
+            send(&mut ot, &mut channel, &weights, 64, &mut rng).unwrap();
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut ot = AlszReceiver::init(&mut channel, &mut rng).unwrap();
+        let result = receive(&mut ot, &mut channel, &features, 64, &mut rng).unwrap();
+        handle.join().unwrap();
+        assert_eq!(result as i64, -15 - 14 + 1);
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut ot = AlszSender::init(&mut channel, &mut rng).unwrap();
+            send(&mut ot, &mut channel, &[1, 2, 3], 8, &mut rng)
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut ot = AlszReceiver::init(&mut channel, &mut rng).unwrap();
+        assert!(receive(&mut ot, &mut channel, &[256, 0, 0], 8, &mut rng).is_err());
+        match receive(&mut ot, &mut channel, &[1, 2], 8, &mut rng) {
+            Err(Error::InvalidInputLength) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        drop(channel);
+        assert!(handle.join().unwrap().is_err());
+    }
+}
@@ -18,5 +18,9 @@ mod errors;
 mod utils;
 
 pub use crate::errors::Error;
+pub mod ggm;
+pub mod inner_product;
+pub mod lpn;
 pub mod oprf;
 pub mod ot;
+pub mod vole;
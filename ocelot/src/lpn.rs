@@ -0,0 +1,448 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Linear codes for LPN-based correlation expansion, as used by silent OT
+//! and VOLE extension.
+//!
+//! These protocols compute `x·A + e` for a public `k × n` binary matrix `A`,
+//! a short secret `x` and a sparse noise vector `e`, over GF(2) (the
+//! receiver's choice bits), GF(2^128) (the correlated `Block`s) or the fields
+//! of `scuttlebutt::field`. As `A` is binary, encoding only adds up field
+//! elements, so one code serves every case through `LinearCode::encode_into`,
+//! and `Block`s are combined with SSE2 XORs.
+//!
+//! Two families of codes are provided: local linear codes, in which each
+//! output is the sum of `d` random inputs (cf. Ferret,
+//! <https://eprint.iacr.org/2020/924>), and quasi-cyclic codes, made of
+//! sparse circulant blocks (cf. <https://eprint.iacr.org/2019/1159>). Both
+//! are stored in space independent of `n`: the rows of a local linear code
+//! are regenerated from its seed as they are encoded, a batch at a time with
+//! pipelined AES-NI, so that the 432 MB of indices of
+//! `LpnParams::FERRET_MAIN` are never materialized.
+
+use rand::SeedableRng;
+use scuttlebutt::{
+    executor::{Executor, Job, ScopedThreads},
+    field::{F61p, Gf128},
+    Aes128, AesRng, Block,
+};
+
+/// An element of the vectors being encoded.
+pub trait Element: Copy + Send + Sync {
+    /// Add `other` to the element.
+    fn accumulate(&mut self, other: Self);
+}
+
+impl Element for bool {
+    #[inline]
+    fn accumulate(&mut self, other: Self) {
+        *self ^= other;
+    }
+}
+
+impl Element for Block {
+    #[inline]
+    fn accumulate(&mut self, other: Self) {
+        *self ^= other;
+    }
+}
+
+impl Element for Gf128 {
+    #[inline]
+    fn accumulate(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Element for F61p {
+    #[inline]
+    fn accumulate(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+/// A binary linear code mapping `k` inputs to `n` outputs.
+pub trait LinearCode: Sync {
+    /// The number of inputs, `k`.
+    fn input_len(&self) -> usize;
+    /// The number of outputs, `n`.
+    fn output_len(&self) -> usize;
+    /// Add the encoding of `input` to the outputs `start..start +
+    /// output.len()`.
+    fn encode_range<T: Element>(&self, input: &[T], start: usize, output: &mut [T]);
+
+    /// Add the encoding of `input` to `output`.
+    fn encode_into<T: Element>(&self, input: &[T], output: &mut [T]) {
+        assert_eq!(input.len(), self.input_len());
+        assert_eq!(output.len(), self.output_len());
+        self.encode_range(input, 0, output);
+    }
+
+    /// Add the encoding of `input` to `output`, splitting the outputs across
+    /// `nthreads` threads.
+    fn encode_into_parallel<T: Element>(&self, input: &[T], output: &mut [T], nthreads: usize) {
+        self.encode_into_with(input, output, &ScopedThreads::new(nthreads))
+    }
+
+    /// Add the encoding of `input` to `output`, splitting the outputs into
+    /// one job per unit of parallelism of `executor`.
+    fn encode_into_with<T: Element, E: Executor>(
+        &self,
+        input: &[T],
+        output: &mut [T],
+        executor: &E,
+    ) {
+        assert_eq!(input.len(), self.input_len());
+        assert_eq!(output.len(), self.output_len());
+        let chunk = output.len().div_ceil(executor.parallelism().max(1));
+        if chunk == 0 {
+            return;
+        }
+        let jobs = output
+            .chunks_mut(chunk)
+            .enumerate()
+            .map(|(i, out)| Box::new(move || self.encode_range(input, i * chunk, out)) as Job)
+            .collect();
+        executor.run(jobs);
+    }
+}
+
+/// Parameters for the LPN problem underlying a correlation expansion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LpnParams {
+    /// The number of outputs.
+    pub n: usize,
+    /// The number of inputs, i.e. the LPN secret length.
+    pub k: usize,
+    /// The Hamming weight of the noise vector.
+    pub t: usize,
+    /// The number of inputs combined into each output of a local linear code.
+    pub d: usize,
+}
+
+impl LpnParams {
+    /// Ferret's parameters for its main iteration, for 128-bit security with
+    /// regular noise.
+    pub const FERRET_MAIN: LpnParams = LpnParams {
+        n: 10_805_248,
+        k: 589_760,
+        t: 1_319,
+        d: 10,
+    };
+
+    /// Ferret's parameters for bootstrapping from base OTs, for 128-bit
+    /// security with regular noise.
+    pub const FERRET_SETUP: LpnParams = LpnParams {
+        n: 649_728,
+        k: 36_288,
+        t: 1_269,
+        d: 10,
+    };
+
+    /// Return the depth `h` of the GGM trees generating regular noise for
+    /// these parameters, each tree covering `2^h` consecutive outputs of
+    /// which exactly one is noisy. Panics unless `n` is `t` times a power of
+    /// two.
+    pub fn tree_depth(&self) -> usize {
+        assert!(
+            self.t > 0 && self.n.is_multiple_of(self.t) && (self.n / self.t).is_power_of_two(),
+            "the number of outputs must be `t` times a power of two"
+        );
+        (self.n / self.t).trailing_zeros() as usize
+    }
+
+    /// Sample the local linear code for these parameters from `seed`.
+    pub fn local_linear_code(&self, seed: Block) -> LocalLinearCode {
+        LocalLinearCode::new(seed, self.k, self.n, self.d)
+    }
+}
+
+// The number of rows of a local linear code generated at once.
+const ROW_BATCH: usize = 64;
+
+/// A local linear code: output `i` is the sum of `d` pseudorandomly chosen
+/// inputs.
+#[derive(Clone)]
+pub struct LocalLinearCode {
+    k: usize,
+    n: usize,
+    d: usize,
+    // Row `i` is read from the encryptions of the counters `i·w..(i + 1)·w`,
+    // for `w` the number of blocks holding `d` 32-bit words.
+    aes: Aes128,
+}
+
+impl LocalLinearCode {
+    /// Sample a code with `k` inputs, `n` outputs and locality `d` from
+    /// `seed`.
+    pub fn new(seed: Block, k: usize, n: usize, d: usize) -> Self {
+        assert!(k > 0 && k <= u32::MAX as usize && d > 0);
+        let aes = Aes128::new(seed);
+        Self { k, n, d, aes }
+    }
+
+    /// Return the input indices combined into output `i`.
+    pub fn row(&self, i: usize) -> Vec<u32> {
+        let mut indices = Vec::with_capacity(self.d);
+        self.rows_into(i, 1, &mut Vec::new(), &mut indices);
+        indices
+    }
+
+    // Replace `indices` with the `d` input indices of each of the `count`
+    // rows from `start`, using `blocks` as scratch space.
+    fn rows_into(
+        &self,
+        start: usize,
+        count: usize,
+        blocks: &mut Vec<Block>,
+        indices: &mut Vec<u32>,
+    ) {
+        let w = self.d.div_ceil(4);
+        blocks.clear();
+        blocks.extend((start * w..(start + count) * w).map(|c| Block::from(c as u128)));
+        self.aes.encrypt_blocks(blocks);
+        indices.clear();
+        for row in blocks.chunks(w) {
+            let words = row.iter().flat_map(|&b| {
+                let b = u128::from(b);
+                (0..4).map(move |j| (b >> (32 * j)) as u32)
+            });
+            // Scale each word into `0..k`, with a bias of at most `k / 2^32`.
+            indices.extend(
+                words
+                    .take(self.d)
+                    .map(|x| ((u64::from(x) * self.k as u64) >> 32) as u32),
+            );
+        }
+    }
+}
+
+// The AES key is the seed, which never shows up in debug output.
+impl std::fmt::Debug for LocalLinearCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("LocalLinearCode")
+            .field("k", &self.k)
+            .field("n", &self.n)
+            .field("d", &self.d)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LinearCode for LocalLinearCode {
+    #[inline]
+    fn input_len(&self) -> usize {
+        self.k
+    }
+
+    #[inline]
+    fn output_len(&self) -> usize {
+        self.n
+    }
+
+    fn encode_range<T: Element>(&self, input: &[T], start: usize, output: &mut [T]) {
+        let mut blocks = Vec::with_capacity(ROW_BATCH * self.d.div_ceil(4));
+        let mut indices = Vec::with_capacity(ROW_BATCH * self.d);
+        for (i, output) in output.chunks_mut(ROW_BATCH).enumerate() {
+            let first = start + i * ROW_BATCH;
+            self.rows_into(first, output.len(), &mut blocks, &mut indices);
+            for (out, row) in output.iter_mut().zip(indices.chunks(self.d)) {
+                for &j in row {
+                    out.accumulate(input[j as usize]);
+                }
+            }
+        }
+    }
+}
+
+/// A quasi-cyclic code, whose generator matrix is a row of `n / k` sparse
+/// `k × k` circulant blocks.
+#[derive(Clone, Debug)]
+pub struct QuasiCyclicCode {
+    k: usize,
+    nblocks: usize,
+    // The nonzero positions of the first row of each circulant block.
+    offsets: Vec<Vec<usize>>,
+}
+
+impl QuasiCyclicCode {
+    /// Sample a code with `k` inputs and `nblocks * k` outputs from `seed`,
+    /// in which each circulant block has `weight` nonzero entries per row.
+    pub fn new(seed: Block, k: usize, nblocks: usize, weight: usize) -> Self {
+        assert!(k > 0 && weight <= k);
+        let mut rng = AesRng::from_seed(seed);
+        let offsets = (0..nblocks)
+            .map(|_| {
+                let mut offsets = rand::seq::index::sample(&mut rng, k, weight).into_vec();
+                offsets.sort();
+                offsets
+            })
+            .collect();
+        Self {
+            k,
+            nblocks,
+            offsets,
+        }
+    }
+}
+
+impl LinearCode for QuasiCyclicCode {
+    #[inline]
+    fn input_len(&self) -> usize {
+        self.k
+    }
+
+    #[inline]
+    fn output_len(&self) -> usize {
+        self.nblocks * self.k
+    }
+
+    fn encode_range<T: Element>(&self, input: &[T], start: usize, output: &mut [T]) {
+        // Output `b·k + i` of block `b` is `Σ_o input[(i - o) mod k]`.
+        for (pos, out) in (start..).zip(output.iter_mut()) {
+            let (b, i) = (pos / self.k, pos % self.k);
+            for &o in self.offsets[b].iter() {
+                out.accumulate(input[(i + self.k - o) % self.k]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use scuttlebutt::field::FiniteField;
+
+    const K: usize = 257;
+    const N: usize = 4 * K;
+
+    fn check<C: LinearCode>(code: &C) {
+        let mut rng = AesRng::new();
+        let delta = rng.gen::<Block>();
+        let bits = (0..K).map(|_| rng.gen::<bool>()).collect::<Vec<_>>();
+        let x = (0..K).map(|_| rng.gen::<Block>()).collect::<Vec<_>>();
+        let y = (0..K).map(|_| rng.gen::<Block>()).collect::<Vec<_>>();
+        // Encoding is linear.
+        let mut ex = vec![Block::default(); N];
+        let mut ey = vec![Block::default(); N];
+        let mut exy = vec![Block::default(); N];
+        code.encode_into(&x, &mut ex);
+        code.encode_into(&y, &mut ey);
+        let xy = x
+            .iter()
+            .zip(y.iter())
+            .map(|(a, b)| *a ^ *b)
+            .collect::<Vec<_>>();
+        code.encode_into(&xy, &mut exy);
+        for i in 0..N {
+            assert_eq!(exy[i], ex[i] ^ ey[i]);
+        }
+        // Encoding preserves the correlation `z = y ⊕ b·Δ`.
+        let z = x
+            .iter()
+            .zip(bits.iter())
+            .map(|(a, &b)| if b { *a ^ delta } else { *a })
+            .collect::<Vec<_>>();
+        let mut ebits = vec![false; N];
+        let mut ez = vec![Block::default(); N];
+        code.encode_into(&bits, &mut ebits);
+        code.encode_into(&z, &mut ez);
+        for i in 0..N {
+            let expected = if ebits[i] { ex[i] ^ delta } else { ex[i] };
+            assert_eq!(ez[i], expected);
+        }
+        // Over a prime field, encoding adds up the same inputs.
+        let fs = (0..K).map(|_| F61p::random(&mut rng)).collect::<Vec<_>>();
+        let gs = (0..K).map(|_| F61p::random(&mut rng)).collect::<Vec<_>>();
+        let fgs = fs
+            .iter()
+            .zip(gs.iter())
+            .map(|(a, b)| *a + *b)
+            .collect::<Vec<_>>();
+        let mut efs = vec![F61p::ZERO; N];
+        let mut egs = vec![F61p::ZERO; N];
+        let mut efgs = vec![F61p::ZERO; N];
+        code.encode_into(&fs, &mut efs);
+        code.encode_into(&gs, &mut egs);
+        code.encode_into(&fgs, &mut efgs);
+        for i in 0..N {
+            assert_eq!(efgs[i], efs[i] + egs[i]);
+        }
+        // Threads don't change the result.
+        for &nthreads in &[1, 3, 8] {
+            let mut out = vec![Block::default(); N];
+            code.encode_into_parallel(&x, &mut out, nthreads);
+            assert_eq!(out, ex);
+        }
+        let mut out = vec![Block::default(); N];
+        code.encode_into_with(&x, &mut out, &scuttlebutt::executor::Sequential);
+        assert_eq!(out, ex);
+    }
+
+    #[test]
+    fn test_local_linear_code() {
+        let seed = rand::random::<Block>();
+        let code = LocalLinearCode::new(seed, K, N, 10);
+        check(&code);
+        // Unit vectors pick out the rows referencing them.
+        let mut input = vec![false; K];
+        input[3] = true;
+        let mut output = vec![false; N];
+        code.encode_into(&input, &mut output);
+        for (i, out) in output.iter().enumerate() {
+            let count = code.row(i).iter().filter(|&&j| j == 3).count();
+            assert_eq!(*out, count % 2 == 1);
+        }
+        // The code is determined by its seed.
+        let again = LocalLinearCode::new(seed, K, N, 10);
+        assert!((0..N).all(|i| again.row(i) == code.row(i)));
+        assert!((0..N).all(|i| code.row(i).iter().all(|&j| (j as usize) < K)));
+    }
+
+    #[test]
+    fn test_quasi_cyclic_code() {
+        let code = QuasiCyclicCode::new(rand::random::<Block>(), K, N / K, 5);
+        check(&code);
+        // Shifting the input cyclically shifts each output block.
+        let mut rng = AesRng::new();
+        let x = (0..K).map(|_| rng.gen::<bool>()).collect::<Vec<_>>();
+        let mut shifted = x.clone();
+        shifted.rotate_right(1);
+        let mut ex = vec![false; N];
+        let mut es = vec![false; N];
+        code.encode_into(&x, &mut ex);
+        code.encode_into(&shifted, &mut es);
+        for (bx, bs) in ex.chunks(K).zip(es.chunks(K)) {
+            let mut rotated = bx.to_vec();
+            rotated.rotate_right(1);
+            assert_eq!(rotated, bs.to_vec());
+        }
+    }
+
+    #[test]
+    fn test_presets() {
+        for params in &[LpnParams::FERRET_MAIN, LpnParams::FERRET_SETUP] {
+            assert!(params.k < params.n);
+            assert!(params.t < params.k);
+            // The code is cheap to make however long, as its rows are not
+            // stored.
+            let code = params.local_linear_code(Block::default());
+            let row = code.row(params.n - 1);
+            assert_eq!(row.len(), params.d);
+            assert!(row.iter().all(|&j| (j as usize) < params.k));
+        }
+        let params = LpnParams {
+            n: 64,
+            k: 16,
+            t: 4,
+            d: 10,
+        };
+        let code = params.local_linear_code(Block::default());
+        assert_eq!(code.input_len(), 16);
+        assert_eq!(code.output_len(), 64);
+        assert_eq!(params.tree_depth(), 4);
+    }
+}
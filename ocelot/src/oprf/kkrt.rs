@@ -7,6 +7,20 @@
 //! Implementation of the batched, related-key oblivious pseudorandom function
 //! (BaRK-OPRF) protocol of Kolesnikov, Kumaresan, Rosulek, and Trieu (cf.
 //! <https://eprint.iacr.org/2016/799>, Figure 2).
+//!
+//! Either party can save its state with `checkpoint` once `init` is done, and
+//! later continue the session from a new connection with `resume`, skipping
+//! the base OTs. Both parties must resume with the same nonce, which re-keys
+//! every seed so that the PRG outputs of the resumed session are independent
+//! of those of the original one. A checkpoint holds the party's secrets in the
+//! clear, so it must be stored securely.
+//!
+//! With the `serde1` feature, both parties also implement `Serialize` and
+//! `Deserialize`, which capture the whole state, including how far each PRG
+//! has advanced, so a deserialized party continues exactly where the
+//! serialized one left off. Deserializing the same state twice and running
+//! both copies reuses PRG outputs and breaks security: ship each state to a
+//! single worker, or give every worker its own session with `resume`.
 
 #![allow(non_upper_case_globals)]
 
@@ -22,6 +36,7 @@ use scuttlebutt::{
     cointoss,
     utils as scutils,
     AbstractChannel,
+    Aes128,
     AesRng,
     Block,
     Block512,
@@ -29,12 +44,57 @@ use scuttlebutt::{
 };
 use std::{convert::TryInto, marker::PhantomData};
 
+// The number of base OTs, which is also the width of the pseudorandom code.
+const NBASE: usize = 512;
+
+// Re-key the seed of a base OT with the nonce of a resumed session.
+fn rekey(seed: Block, nonce: Block) -> Block {
+    Aes128::new(seed).encrypt(nonce)
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::InvalidCheckpoint(msg.to_string())
+}
+
+// Split `n` bytes off the front of `bytes`.
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+    if bytes.len() < n {
+        return Err(invalid("truncated"));
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn take_block(bytes: &mut &[u8]) -> Result<Block, Error> {
+    let mut block = Block::default();
+    block.as_mut().copy_from_slice(take(bytes, 16)?);
+    Ok(block)
+}
+
+fn write_keys(bytes: &mut Vec<u8>, keys: &[Block; 4]) {
+    for key in keys.iter() {
+        bytes.extend_from_slice(key.as_ref());
+    }
+}
+
+fn read_keys(bytes: &mut &[u8]) -> Result<[Block; 4], Error> {
+    Ok([
+        take_block(bytes)?,
+        take_block(bytes)?,
+        take_block(bytes)?,
+        take_block(bytes)?,
+    ])
+}
+
 /// KKRT oblivious PRF sender.
 pub struct Sender<OT: OtReceiver + SemiHonest> {
     _ot: PhantomData<OT>,
     s: Vec<bool>,
     s_: [u8; 64],
+    keys: [Block; 4],
     code: PseudorandomCode,
+    ks: Vec<Block>,
     rngs: Vec<AesRng>,
 }
 
@@ -56,19 +116,9 @@ impl<OT: OtReceiver<Msg = Block> + SemiHonest> OprfSender for Sender<OT> {
         let s = utils::u8vec_to_boolvec(&s_);
         let seeds = (0..4).map(|_| rng.gen()).collect::<Vec<Block>>();
         let keys = cointoss::send(channel, &seeds)?;
-        let code = PseudorandomCode::new(keys[0], keys[1], keys[2], keys[3]);
+        let keys = [keys[0], keys[1], keys[2], keys[3]];
         let ks = ot.receive(channel, &s, rng)?;
-        let rngs = ks
-            .into_iter()
-            .map(AesRng::from_seed)
-            .collect::<Vec<AesRng>>();
-        Ok(Self {
-            _ot: PhantomData::<OT>,
-            s,
-            s_,
-            code,
-            rngs,
-        })
+        Ok(Self::from_parts(s_, keys, ks))
     }
 
     fn send<C, RNG>(
@@ -111,6 +161,52 @@ impl<OT: OtReceiver<Msg = Block> + SemiHonest> OprfSender for Sender<OT> {
     }
 }
 
+impl<OT: OtReceiver<Msg = Block> + SemiHonest> Sender<OT> {
+    fn from_parts(s_: [u8; 64], keys: [Block; 4], ks: Vec<Block>) -> Self {
+        let code = PseudorandomCode::new(keys[0], keys[1], keys[2], keys[3]);
+        let rngs = ks
+            .iter()
+            .map(|k| AesRng::from_seed(*k))
+            .collect::<Vec<AesRng>>();
+        Self {
+            _ot: PhantomData::<OT>,
+            s: utils::u8vec_to_boolvec(&s_),
+            s_,
+            keys,
+            code,
+            ks,
+            rngs,
+        }
+    }
+
+    /// Serialize the state set up by `init`.
+    pub fn checkpoint(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64 + 4 * 16 + NBASE * 16);
+        bytes.extend_from_slice(&self.s_);
+        write_keys(&mut bytes, &self.keys);
+        for k in self.ks.iter() {
+            bytes.extend_from_slice(k.as_ref());
+        }
+        bytes
+    }
+
+    /// Continue a session from a state written by `checkpoint`, re-keyed with
+    /// `nonce`.
+    pub fn resume(checkpoint: &[u8], nonce: Block) -> Result<Self, Error> {
+        let mut bytes = checkpoint;
+        let mut s_ = [0u8; 64];
+        s_.copy_from_slice(take(&mut bytes, 64)?);
+        let keys = read_keys(&mut bytes)?;
+        let ks = (0..NBASE)
+            .map(|_| take_block(&mut bytes).map(|k| rekey(k, nonce)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !bytes.is_empty() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(Self::from_parts(s_, keys, ks))
+    }
+}
+
 // Separate out `encode` function for optimization purposes.
 impl<OT: OtReceiver<Msg = Block> + SemiHonest> Sender<OT> {
     /// Encode `input` into `output`. This is *not* the same as the `compute`
@@ -125,12 +221,49 @@ impl<OT: OtReceiver<Msg = Block> + SemiHonest> Sender<OT> {
         self.code.encode(input, output.into());
         scutils::and_inplace(&mut output.as_mut(), &self.s_);
     }
+
+    /// Encode each of `inputs` into the output at the same index, as
+    /// `encode` does, batching the AES calls.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `inputs` and `outputs` differ in length.
+    pub fn encode_many(
+        &self,
+        inputs: &[<Sender<OT> as ObliviousPrf>::Input],
+        outputs: &mut [<Sender<OT> as ObliviousPrf>::Output],
+    ) {
+        self.code.encode_many(inputs, outputs);
+        for output in outputs.iter_mut() {
+            scutils::and_inplace(output.as_mut(), &self.s_);
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<OT: OtReceiver + SemiHonest> Drop for Sender<OT> {
+    fn drop(&mut self) {
+        scutils::erase(&mut self.s);
+        scutils::erase(&mut self.s_);
+        scutils::erase(&mut self.ks);
+    }
+}
+
+// The keys and seeds are left out, leaving only the number of base OTs.
+impl<OT: OtReceiver + SemiHonest> std::fmt::Debug for Sender<OT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("kkrt::Sender")
+            .field("nbase", &self.ks.len())
+            .finish_non_exhaustive()
+    }
 }
 
 /// KKRT oblivious PRF receiver.
 pub struct Receiver<OT: OtSender + SemiHonest> {
     _ot: PhantomData<OT>,
+    keys: [Block; 4],
     code: PseudorandomCode,
+    ks: Vec<(Block, Block)>,
     rngs: Vec<(AesRng, AesRng)>,
 }
 
@@ -148,25 +281,17 @@ impl<OT: OtSender<Msg = Block> + SemiHonest> OprfReceiver for Receiver<OT> {
         let mut ot = OT::init(channel, rng)?;
         let seeds = (0..4).map(|_| rng.gen()).collect::<Vec<Block>>();
         let keys = cointoss::receive(channel, &seeds)?;
-        let code = PseudorandomCode::new(keys[0], keys[1], keys[2], keys[3]);
-        let mut ks = Vec::with_capacity(512);
+        let keys = [keys[0], keys[1], keys[2], keys[3]];
+        let mut ks = Vec::with_capacity(NBASE);
         let mut k0 = Block::default();
         let mut k1 = Block::default();
-        for _ in 0..512 {
+        for _ in 0..NBASE {
             rng.fill_bytes(&mut k0.as_mut());
             rng.fill_bytes(&mut k1.as_mut());
             ks.push((k0, k1));
         }
         ot.send(channel, &ks, rng)?;
-        let rngs = ks
-            .into_iter()
-            .map(|(k0, k1)| (AesRng::from_seed(k0), AesRng::from_seed(k1)))
-            .collect::<Vec<(AesRng, AesRng)>>();
-        Ok(Self {
-            _ot: PhantomData::<OT>,
-            code,
-            rngs,
-        })
+        Ok(Self::from_parts(keys, ks))
     }
 
     fn receive<C: AbstractChannel, RNG: CryptoRng + Rng>(
@@ -214,9 +339,177 @@ impl<OT: OtSender<Msg = Block> + SemiHonest> OprfReceiver for Receiver<OT> {
     }
 }
 
+impl<OT: OtSender<Msg = Block> + SemiHonest> Receiver<OT> {
+    fn from_parts(keys: [Block; 4], ks: Vec<(Block, Block)>) -> Self {
+        let code = PseudorandomCode::new(keys[0], keys[1], keys[2], keys[3]);
+        let rngs = ks
+            .iter()
+            .map(|(k0, k1)| (AesRng::from_seed(*k0), AesRng::from_seed(*k1)))
+            .collect::<Vec<(AesRng, AesRng)>>();
+        Self {
+            _ot: PhantomData::<OT>,
+            keys,
+            code,
+            ks,
+            rngs,
+        }
+    }
+
+    /// Serialize the state set up by `init`.
+    pub fn checkpoint(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 * 16 + NBASE * 32);
+        write_keys(&mut bytes, &self.keys);
+        for (k0, k1) in self.ks.iter() {
+            bytes.extend_from_slice(k0.as_ref());
+            bytes.extend_from_slice(k1.as_ref());
+        }
+        bytes
+    }
+
+    /// Continue a session from a state written by `checkpoint`, re-keyed with
+    /// `nonce`.
+    pub fn resume(checkpoint: &[u8], nonce: Block) -> Result<Self, Error> {
+        let mut bytes = checkpoint;
+        let keys = read_keys(&mut bytes)?;
+        let ks = (0..NBASE)
+            .map(|_| {
+                let k0 = take_block(&mut bytes)?;
+                let k1 = take_block(&mut bytes)?;
+                Ok((rekey(k0, nonce), rekey(k1, nonce)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        if !bytes.is_empty() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(Self::from_parts(keys, ks))
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<OT: OtSender + SemiHonest> Drop for Receiver<OT> {
+    fn drop(&mut self) {
+        scutils::erase(&mut self.ks);
+    }
+}
+
+impl<OT: OtSender + SemiHonest> std::fmt::Debug for Receiver<OT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("kkrt::Receiver")
+            .field("nbase", &self.ks.len())
+            .finish_non_exhaustive()
+    }
+}
+
 impl<OT: OtReceiver<Msg = Block> + SemiHonest> SemiHonest for Sender<OT> {}
 impl<OT: OtSender<Msg = Block> + SemiHonest> SemiHonest for Receiver<OT> {}
 
+// The serialized state of a `Sender`, erased once used.
+#[cfg(feature = "serde1")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SenderState {
+    s_: Vec<u8>,
+    keys: [Block; 4],
+    ks: Vec<Block>,
+    positions: Vec<u64>,
+}
+
+#[cfg(all(feature = "serde1", feature = "zeroize"))]
+impl Drop for SenderState {
+    fn drop(&mut self) {
+        scutils::erase(&mut self.s_);
+        scutils::erase(&mut self.ks);
+    }
+}
+
+#[cfg(feature = "serde1")]
+impl<OT: OtReceiver<Msg = Block> + SemiHonest> serde::Serialize for Sender<OT> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SenderState {
+            s_: self.s_.to_vec(),
+            keys: self.keys,
+            ks: self.ks.clone(),
+            positions: self.rngs.iter().map(AesRng::position).collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde1")]
+impl<'de, OT: OtReceiver<Msg = Block> + SemiHonest> serde::Deserialize<'de> for Sender<OT> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut state = SenderState::deserialize(deserializer)?;
+        if state.s_.len() != 64 || state.ks.len() != NBASE || state.positions.len() != NBASE {
+            return Err(serde::de::Error::custom("malformed KKRT sender"));
+        }
+        let mut s_ = [0u8; 64];
+        s_.copy_from_slice(&state.s_);
+        let mut sender = Self::from_parts(s_, state.keys, std::mem::take(&mut state.ks));
+        scutils::erase(&mut s_);
+        sender.rngs = sender
+            .ks
+            .iter()
+            .zip(state.positions.iter())
+            .map(|(k, position)| AesRng::from_seed_at(*k, *position))
+            .collect();
+        Ok(sender)
+    }
+}
+
+// The serialized state of a `Receiver`, erased once used.
+#[cfg(feature = "serde1")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ReceiverState {
+    keys: [Block; 4],
+    ks: Vec<(Block, Block)>,
+    positions: Vec<(u64, u64)>,
+}
+
+#[cfg(all(feature = "serde1", feature = "zeroize"))]
+impl Drop for ReceiverState {
+    fn drop(&mut self) {
+        scutils::erase(&mut self.ks);
+    }
+}
+
+#[cfg(feature = "serde1")]
+impl<OT: OtSender<Msg = Block> + SemiHonest> serde::Serialize for Receiver<OT> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ReceiverState {
+            keys: self.keys,
+            ks: self.ks.clone(),
+            positions: self
+                .rngs
+                .iter()
+                .map(|(rng0, rng1)| (rng0.position(), rng1.position()))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde1")]
+impl<'de, OT: OtSender<Msg = Block> + SemiHonest> serde::Deserialize<'de> for Receiver<OT> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut state = ReceiverState::deserialize(deserializer)?;
+        if state.ks.len() != NBASE || state.positions.len() != NBASE {
+            return Err(serde::de::Error::custom("malformed KKRT receiver"));
+        }
+        let mut receiver = Self::from_parts(state.keys, std::mem::take(&mut state.ks));
+        receiver.rngs = receiver
+            .ks
+            .iter()
+            .zip(state.positions.iter())
+            .map(|((k0, k1), (p0, p1))| {
+                (
+                    AesRng::from_seed_at(*k0, *p0),
+                    AesRng::from_seed_at(*k1, *p1),
+                )
+            })
+            .collect();
+        Ok(receiver)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -241,7 +534,11 @@ mod tests {
         (0..size).map(|_| rand::random::<Block>()).collect()
     }
 
-    fn _test_oprf(n: usize) {
+    fn _test_oprf<S, R>(n: usize)
+    where
+        S: OprfSender + ObliviousPrf<Input = Block, Output = Block512>,
+        R: OprfReceiver + ObliviousPrf<Input = Block, Output = Block512>,
+    {
         let selections = rand_block_vec(n);
         let selections_ = selections.clone();
         let results = Arc::new(Mutex::new(vec![]));
@@ -252,7 +549,7 @@ mod tests {
             let reader = BufReader::new(sender.try_clone().unwrap());
             let writer = BufWriter::new(sender);
             let mut channel = Channel::new(reader, writer);
-            let mut oprf = oprf::KkrtSender::init(&mut channel, &mut rng).unwrap();
+            let mut oprf = S::init(&mut channel, &mut rng).unwrap();
             let seeds = oprf.send(&mut channel, n, &mut rng).unwrap();
             let mut results = results.lock().unwrap();
             *results = selections_
@@ -265,7 +562,7 @@ mod tests {
         let reader = BufReader::new(receiver.try_clone().unwrap());
         let writer = BufWriter::new(receiver);
         let mut channel = Channel::new(reader, writer);
-        let mut oprf = oprf::KkrtReceiver::init(&mut channel, &mut rng).unwrap();
+        let mut oprf = R::init(&mut channel, &mut rng).unwrap();
         let outputs = oprf.receive(&mut channel, &selections, &mut rng).unwrap();
         handle.join().unwrap();
         let results_ = results_.lock().unwrap();
@@ -276,9 +573,64 @@ mod tests {
 
     #[test]
     fn test_oprf() {
-        _test_oprf(1);
-        _test_oprf(8);
-        _test_oprf(11);
-        _test_oprf(64);
+        _test_oprf::<oprf::KkrtSender, oprf::KkrtReceiver>(1);
+        _test_oprf::<oprf::KkrtSender, oprf::KkrtReceiver>(8);
+        _test_oprf::<oprf::KkrtSender, oprf::KkrtReceiver>(11);
+        _test_oprf::<oprf::KkrtSender, oprf::KkrtReceiver>(64);
+    }
+
+    #[test]
+    fn test_encode_many() {
+        let mut rng = AesRng::new();
+        let mut s_ = [0u8; 64];
+        rng.fill_bytes(&mut s_);
+        let keys = [rng.gen(), rng.gen(), rng.gen(), rng.gen()];
+        let oprf = oprf::KkrtSender::from_parts(s_, keys, rand_block_vec(NBASE));
+        let inputs = rand_block_vec(21);
+        let mut outputs = vec![Block512::default(); inputs.len()];
+        oprf.encode_many(&inputs, &mut outputs);
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            let mut encoded = Block512::default();
+            oprf.encode(*input, &mut encoded);
+            assert_eq!(encoded, *output);
+        }
+    }
+
+    #[test]
+    fn test_resume() {
+        let selections = rand_block_vec(16);
+        let selections_ = selections.clone();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let oprf = oprf::KkrtSender::init(&mut channel, &mut rng).unwrap();
+            let checkpoint = oprf.checkpoint();
+            drop(oprf);
+            let nonce = channel.read_block().unwrap();
+            let mut oprf = oprf::KkrtSender::resume(&checkpoint, nonce).unwrap();
+            let seeds = oprf.send(&mut channel, 16, &mut rng).unwrap();
+            selections_
+                .iter()
+                .zip(seeds)
+                .map(|(inp, seed)| oprf.compute(seed, *inp))
+                .collect::<Vec<Block512>>()
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let oprf = oprf::KkrtReceiver::init(&mut channel, &mut rng).unwrap();
+        let checkpoint = oprf.checkpoint();
+        assert!(oprf::KkrtReceiver::resume(&checkpoint[1..], Block::default()).is_err());
+        assert!(oprf::KkrtSender::resume(&checkpoint, Block::default()).is_err());
+        let nonce = rng.gen::<Block>();
+        channel.write_block(&nonce).unwrap();
+        channel.flush().unwrap();
+        let mut oprf = oprf::KkrtReceiver::resume(&checkpoint, nonce).unwrap();
+        let outputs = oprf.receive(&mut channel, &selections, &mut rng).unwrap();
+        assert_eq!(handle.join().unwrap(), outputs);
     }
 }
@@ -6,14 +6,22 @@
 
 //! Implementation of the hash-based multi-use OPPRF of Kolesnikov, Matania,
 //! Pinkas, Rosulek, and Trieu (cf. <https://eprint.iacr.org/2017/799>).
+//!
+//! Each bin of the hash table runs a one-time OPPRF whose hint is encoded by
+//! an `opprf::Hint`, the table-based `opprf::TableHint` unless chosen
+//! otherwise.
 
 use crate::{
     errors::Error,
-    oprf::{Receiver as OprfReceiver, Sender as OprfSender},
+    oprf::{
+        opprf::{Hint, TableHint},
+        Receiver as OprfReceiver,
+        Sender as OprfSender,
+    },
 };
 use rand::{CryptoRng, Rng};
 use scuttlebutt::{AbstractChannel, Aes128, Block, Block512, SemiHonest};
-use std::collections::HashSet;
+use std::marker::PhantomData;
 
 mod cuckoo;
 
@@ -23,9 +31,6 @@ impl From<cuckoo::Error> for Error {
     }
 }
 
-// Number of times to iterate when creating the sender's hash table.
-const N_TABLE_LOOPS: usize = 128;
-
 // Hash `x` with key `k`, producing a result in the range `[0..range-1]`. We use
 // the Davies-Meyer-esque single-block-length compression function
 // under-the-hood, and we pre-key `k`.
@@ -35,27 +40,6 @@ fn hash_input_keyed(k: &Aes128, x: Block, range: usize) -> usize {
     (u128::from(h) % (range as u128)) as usize
 }
 
-// Hash `y` with key `k`, producing a result in the range `[0..range-1]`.
-fn hash_output(k: Block, y: Block512, range: usize) -> usize {
-    let aes = Aes128::new(k);
-    hash_output_keyed(&aes, y, range)
-}
-
-// Hash `y` with pre-keyed `k`. Uses a Davies-Meyer-esque hash function.
-//
-// XXX: can we remove this re-keying? It'll speed things up a bunch.
-fn hash_output_keyed(k: &Aes128, y: Block512, range: usize) -> usize {
-    let ys: [Block; 4] = y.into();
-    let h = k.encrypt(ys[0]) ^ ys[0];
-    let k = Aes128::new(h);
-    let h = k.encrypt(ys[1]) ^ ys[1];
-    let k = Aes128::new(h);
-    let h = k.encrypt(ys[2]) ^ ys[2];
-    let k = Aes128::new(h);
-    let h = k.encrypt(ys[3]) ^ ys[3];
-    (u128::from(h) % (range as u128)) as usize
-}
-
 // OPPRF parameters.
 #[derive(Debug)]
 struct Parameters {
@@ -104,14 +88,15 @@ impl Parameters {
 /// KMPRT hashing-based OPPRF sender.
 ///
 /// This implements the hashing-based OPPRF sender in Figure 7 of the paper. It
-/// uses the table-based one-time OPPRF under-the-hood (Figure 6 of the paper),
-/// which itself uses an OPRF.
-pub struct Sender<OPRF> {
+/// uses a one-time OPPRF with hints `H` under-the-hood (the table-based one of
+/// Figure 6 of the paper by default), which itself uses an OPRF.
+pub struct Sender<OPRF, H = TableHint> {
     oprf: OPRF,
+    _hint: PhantomData<H>,
 }
 
-impl<OPRF: OprfSender<Seed = Block512, Input = Block, Output = Block512> + SemiHonest>
-    Sender<OPRF>
+impl<OPRF: OprfSender<Seed = Block512, Input = Block, Output = Block512> + SemiHonest, H: Hint>
+    Sender<OPRF, H>
 {
     /// Initialize the OPPRF sender.
     pub fn init<C, RNG>(channel: &mut C, rng: &mut RNG) -> Result<Self, Error>
@@ -120,7 +105,10 @@ impl<OPRF: OprfSender<Seed = Block512, Input = Block, Output = Block512> + SemiH
         RNG: CryptoRng + Rng,
     {
         let oprf = OPRF::init(channel, rng)?;
-        Ok(Self { oprf })
+        Ok(Self {
+            oprf,
+            _hint: PhantomData,
+        })
     }
 
     /// Run the OPPRF for `ninputs` inputs with the pairs given in
@@ -213,95 +201,31 @@ impl<OPRF: OprfSender<Seed = Block512, Input = Block, Output = Block512> + SemiH
             },
             points.len()
         );
-        assert!(points.len() <= npoints);
-        let mut v = rng.gen::<Block>();
-        let mut aes = Aes128::new(v);
-        let mut map = HashSet::with_capacity(points.len());
-        // Store compute `y`s and `h`s for later use.
-        let mut ys = vec![Block512::default(); points.len()];
-        let mut hs = vec![usize::default(); points.len()];
-        // Guess a size for `table` using `offset`, and then try to fill
-        // `map` with points hashed into the space `[0..m-1]`. If this fails
-        // (because `m` is too small), we change `offset` and try again,
-        // looping until we choose an appropriate `m` such that we can find
-        // a `v` such that every entry in `map` is distinct.
-        //
-        // Note that choosing `m` correctly quickly matters **a lot** to the
-        // overall running time.
-        let mut m = Self::table_size(npoints);
-        let increment = m;
-        loop {
-            // Sample `v` until all values in `map` are distinct.
-            for _ in 0..N_TABLE_LOOPS {
-                for (i, (x, _)) in points.iter().enumerate() {
-                    ys[i] = self.oprf.compute(seed, *x);
-                    hs[i] = hash_output_keyed(&aes, ys[i], m);
-                    if !map.insert(hs[i]) {
-                        break;
-                    }
-                }
-                if map.len() == points.len() {
-                    break;
-                }
-                // Try again.
-                v = rng.gen::<Block>();
-                aes = Aes128::new(v);
-                map.clear();
-            }
-            if map.len() == points.len() {
-                // Success! Send `m` to the receiver and exit the loop.
-                channel.write_usize(m)?;
-                break;
-            }
-            // Failure :-(. Increment `offset` and try again.
-            m += increment;
-        }
-        let mut table = vec![Block512::default(); m];
-        // Place points in table based on the hash of their OPRF output.
-        for (h, (y_, (_, y))) in hs.into_iter().zip(ys.into_iter().zip(points.into_iter())) {
-            table[h] = y ^ y_;
-        }
-        // Fill rest of table with random elements.
-        for entry in table.iter_mut() {
-            if *entry == Block512::default() {
-                *entry = rng.gen::<Block512>();
-            }
-        }
-        // Send `v` and `table` to the receiver.
-        channel.write_block(&v)?;
-        for entry in table.iter() {
-            channel.write_block512(entry)?;
-        }
+        let points = points
+            .into_iter()
+            .map(|(x, y)| (self.oprf.compute(seed, x), y))
+            .collect::<Vec<_>>();
+        let hint = H::encode(&points, npoints, rng)?;
+        hint.write(channel)?;
         channel.flush()?;
         Ok(())
     }
-
-    // Compute the table size for the OPPRF.
-    #[inline(always)]
-    fn table_size(npoints: usize) -> usize {
-        // These are over-approximations, but appear to lead to better running
-        // times (at the expense of more communication).
-        if npoints <= 32 {
-            32
-        } else if npoints <= 64 {
-            256
-        } else {
-            (((npoints + 2) as f32).log2().ceil()).exp2() as usize
-        }
-    }
 }
 
 /// KMPRT oblivious programmable PRF receiver.
 ///
 /// This implements the hashing-based OPPRF receiver in Figure 7 of the paper. It
-/// uses the table-based one-time OPPRF under-the-hood (Figure 6 of the paper),
-/// which itself uses an OPRF.
-pub struct Receiver<OPRF: OprfReceiver + SemiHonest> {
+/// uses a one-time OPPRF with hints `H` under-the-hood (the table-based one of
+/// Figure 6 of the paper by default), which itself uses an OPRF.
+pub struct Receiver<OPRF: OprfReceiver + SemiHonest, H = TableHint> {
     oprf: OPRF,
+    _hint: PhantomData<H>,
 }
 
-impl<OPRF: OprfReceiver<Seed = Block512, Input = Block, Output = Block512> + SemiHonest>
-    Receiver<OPRF>
+impl<
+        OPRF: OprfReceiver<Seed = Block512, Input = Block, Output = Block512> + SemiHonest,
+        H: Hint,
+    > Receiver<OPRF, H>
 {
     /// Initialize the OPPRF receiver.
     pub fn init<C, RNG>(channel: &mut C, rng: &mut RNG) -> Result<Self, Error>
@@ -310,7 +234,10 @@ impl<OPRF: OprfReceiver<Seed = Block512, Input = Block, Output = Block512> + Sem
         RNG: CryptoRng + Rng,
     {
         let oprf = OPRF::init(channel, rng)?;
-        Ok(Self { oprf })
+        Ok(Self {
+            oprf,
+            _hint: PhantomData,
+        })
     }
 
     /// Run the OPPRF on inputs provided by the `inputs` slice.
@@ -367,18 +294,15 @@ impl<OPRF: OprfReceiver<Seed = Block512, Input = Block, Output = Block512> + Sem
             .collect::<Vec<Block>>();
         let oprf_outputs = self.oprf.receive(channel, &items, rng)?;
 
-        let zero = Block512::default();
-        for (item, output) in table.items.into_iter().zip(oprf_outputs.into_iter()) {
-            let m = channel.read_usize()?;
-            let v = channel.read_block()?;
-            let h = hash_output(v, output, m);
-            let mut output = output;
-            for i in 0..m {
-                let entry = channel.read_block512()?;
-                output ^= if i == h { entry } else { zero };
-            }
+        for (j, (item, output)) in table.items.into_iter().zip(oprf_outputs).enumerate() {
+            let beta = if j < params.m1 {
+                params.beta1
+            } else {
+                params.beta2
+            };
+            let hint = H::read(channel, beta)?;
             if let Some(item) = item {
-                outputs[item.index] = output;
+                outputs[item.index] = hint.decode(output);
             }
         }
         Ok(outputs)
@@ -392,14 +316,18 @@ impl<OPRF: OprfReceiver<Seed = Block512, Input = Block, Output = Block512> + Sem
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::oprf::{KmprtReceiver, KmprtSender};
+    use crate::oprf::{opprf::PolynomialHint, KkrtReceiver, KkrtSender};
     use scuttlebutt::{AesRng, Channel};
     use std::{
         io::{BufReader, BufWriter},
         os::unix::net::UnixStream,
     };
 
-    fn _test_opprf_points(ninputs: usize, npoints: usize, npoints_bound: usize) {
+    fn _test_opprf_points_with<H: Hint + 'static>(
+        ninputs: usize,
+        npoints: usize,
+        npoints_bound: usize,
+    ) {
         assert!(ninputs <= npoints);
         assert!(npoints <= npoints_bound);
         let mut rng = AesRng::new();
@@ -421,7 +349,7 @@ mod tests {
             let reader = BufReader::new(sender.try_clone().unwrap());
             let writer = BufWriter::new(sender);
             let mut channel = Channel::new(reader, writer);
-            let mut oprf = KmprtSender::init(&mut channel, &mut rng).unwrap();
+            let mut oprf = Sender::<KkrtSender, H>::init(&mut channel, &mut rng).unwrap();
             let _ = oprf
                 .send(&mut channel, &points_, ninputs, &mut rng)
                 .unwrap();
@@ -430,7 +358,7 @@ mod tests {
         let reader = BufReader::new(receiver.try_clone().unwrap());
         let writer = BufWriter::new(receiver);
         let mut channel = Channel::new(reader, writer);
-        let mut oprf = KmprtReceiver::init(&mut channel, &mut rng).unwrap();
+        let mut oprf = Receiver::<KkrtReceiver, H>::init(&mut channel, &mut rng).unwrap();
         let outputs = oprf.receive(&mut channel, &xs, &mut rng).unwrap();
         handle.join().unwrap();
         let mut okay = true;
@@ -442,6 +370,10 @@ mod tests {
         assert_eq!(okay, true);
     }
 
+    fn _test_opprf_points(ninputs: usize, npoints: usize, npoints_bound: usize) {
+        _test_opprf_points_with::<TableHint>(ninputs, npoints, npoints_bound)
+    }
+
     #[test]
     fn test_opprf() {
         _test_opprf_points(1, 8, 8);
@@ -454,6 +386,13 @@ mod tests {
         // Settings for PSTY with `n = 2^16`.
         _test_opprf_points(83231, 196608, 196608);
     }
+
+    #[test]
+    fn test_opprf_polynomial() {
+        _test_opprf_points_with::<PolynomialHint>(1, 8, 8);
+        _test_opprf_points_with::<PolynomialHint>(21, 48, 48);
+        _test_opprf_points_with::<PolynomialHint>(163, 384, 384);
+    }
 }
 
 //
@@ -466,23 +405,6 @@ mod benchmarks {
     use super::*;
     use test::{black_box, Bencher};
 
-    #[bench]
-    fn bench_hash_output(b: &mut Bencher) {
-        let k = black_box(rand::random::<Block>());
-        let x = black_box(rand::random::<Block512>());
-        let range = 15;
-        b.iter(|| super::hash_output(k, x, range));
-    }
-
-    #[bench]
-    fn bench_hash_output_keyed(b: &mut Bencher) {
-        let k = black_box(rand::random::<Block>());
-        let x = black_box(rand::random::<Block512>());
-        let aes = Aes128::new(k);
-        let range = 15;
-        b.iter(|| super::hash_output_keyed(&aes, x, range));
-    }
-
     #[bench]
     fn bench_hash_input_keyed(b: &mut Bencher) {
         let k = black_box(rand::random::<Block>());
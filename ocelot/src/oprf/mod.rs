@@ -8,6 +8,7 @@
 
 pub mod kkrt;
 pub mod kmprt;
+pub mod opprf;
 mod prc;
 
 use crate::{errors::Error, ot};
@@ -22,6 +23,12 @@ pub type KkrtReceiver = kkrt::Receiver<ot::AlszSender>;
 pub type KmprtSender = kmprt::Sender<KkrtSender>;
 /// KMPRT hash-based OPPRF receiver, using KKRT as the underlying OPRF.
 pub type KmprtReceiver = kmprt::Receiver<KkrtReceiver>;
+/// KMPRT hash-based OPPRF sender with polynomial-based hints, using KKRT as
+/// the underlying OPRF.
+pub type KmprtPolynomialSender = kmprt::Sender<KkrtSender, opprf::PolynomialHint>;
+/// KMPRT hash-based OPPRF receiver with polynomial-based hints, using KKRT as
+/// the underlying OPRF.
+pub type KmprtPolynomialReceiver = kmprt::Receiver<KkrtReceiver, opprf::PolynomialHint>;
 
 /// Trait containing the associated types used by an oblivious PRF.
 pub trait ObliviousPrf
@@ -0,0 +1,96 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Hint encodings for oblivious programmable PRFs (OPPRFs).
+//!
+//! An OPPRF lets the sender program the output of the PRF at points of its
+//! choosing. In the one-time OPPRFs of Kolesnikov, Matania, Pinkas, Rosulek,
+//! and Trieu (cf. <https://eprint.iacr.org/2017/799>, Section 4), both
+//! parties run an OPRF, and the sender then sends a *hint*, from which the
+//! receiver corrects the OPRF output of each programmed point to the value it
+//! is programmed to. The outputs at other points stay pseudorandom, and a hint
+//! for up to `n` points reveals nothing about the points beyond `n`.
+//!
+//! The `Hint` trait abstracts over the encoding of the hint, so that the
+//! hashing-based multi-use OPPRF of `kmprt`, and the protocols above it, can
+//! switch encodings by changing a type:
+//!
+//! * `table::TableHint`: the table-based encoding of Figure 6 of the paper,
+//!   cheap to compute, which the sender may have to grow until the points
+//!   land in distinct entries.
+//! * `polynomial::PolynomialHint`: the polynomial-based encoding, which
+//!   interpolates a polynomial over GF(2^128) through the corrections. Its
+//!   size is exactly `n` outputs, at a cost quadratic in `n` for the sender.
+
+pub mod polynomial;
+pub mod table;
+
+pub use polynomial::PolynomialHint;
+pub use table::TableHint;
+
+use crate::errors::Error;
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{AbstractChannel, Block512};
+
+/// Trait for the hint a one-time OPPRF sender sends the receiver.
+pub trait Hint
+where
+    Self: Sized,
+{
+    /// Encodes the hint of `points`, pairs of the OPRF output at a programmed
+    /// point and the value it is programmed to, for a bound of `npoints`
+    /// points.
+    fn encode<RNG: CryptoRng + Rng>(
+        points: &[(Block512, Block512)],
+        npoints: usize,
+        rng: &mut RNG,
+    ) -> Result<Self, Error>;
+    /// Returns the OPPRF output at the point whose OPRF output is `output`.
+    fn decode(&self, output: Block512) -> Block512;
+    /// Writes the hint to `channel`.
+    fn write<C: AbstractChannel>(&self, channel: &mut C) -> Result<(), Error>;
+    /// Reads the hint of `write` from `channel`, for a bound of `npoints`
+    /// points.
+    fn read<C: AbstractChannel>(channel: &mut C, npoints: usize) -> Result<Self, Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scuttlebutt::{AesRng, Channel};
+    use std::io::Cursor;
+
+    fn test_hint<H: Hint>(npoints: usize, bound: usize) {
+        let mut rng = AesRng::new();
+        let points = (0..npoints)
+            .map(|_| (rng.gen::<Block512>(), rng.gen::<Block512>()))
+            .collect::<Vec<_>>();
+        let hint = H::encode(&points, bound, &mut rng).unwrap();
+        let mut bytes = Vec::new();
+        hint.write(&mut Channel::new(Cursor::new(vec![]), &mut bytes))
+            .unwrap();
+        let hint = H::read(&mut Channel::new(Cursor::new(bytes), vec![]), bound).unwrap();
+        for (output, value) in points.iter() {
+            assert_eq!(hint.decode(*output), *value);
+        }
+        let other = rng.gen::<Block512>();
+        assert!(points.iter().all(|(_, value)| hint.decode(other) != *value));
+    }
+
+    #[test]
+    fn test_table() {
+        test_hint::<TableHint>(0, 8);
+        test_hint::<TableHint>(5, 8);
+        test_hint::<TableHint>(63, 63);
+    }
+
+    #[test]
+    fn test_polynomial() {
+        test_hint::<PolynomialHint>(0, 8);
+        test_hint::<PolynomialHint>(5, 8);
+        test_hint::<PolynomialHint>(63, 63);
+    }
+}
@@ -0,0 +1,164 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! The polynomial-based hint of the one-time OPPRF of Kolesnikov, Matania,
+//! Pinkas, Rosulek, and Trieu (cf. <https://eprint.iacr.org/2017/799>,
+//! Section 4).
+//!
+//! Each OPRF output is read as an element `x` of GF(2^128), its first 128
+//! bits, and four corrections, one per 128-bit lane. For a bound of `n`
+//! points, the sender pads its points with random ones to `n`, and
+//! interpolates four polynomials of degree `n - 1` through the corrections at
+//! each `x`, which the receiver evaluates at its own `x`. The hint always has
+//! `n` coefficients per lane, whatever the number of points.
+
+use crate::{errors::Error, oprf::opprf::Hint, utils};
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{
+    field::{FiniteField, Gf128},
+    AbstractChannel,
+    Block,
+    Block512,
+};
+use std::collections::HashSet;
+
+// The four lanes of `y`, as field elements.
+#[inline]
+fn lanes(y: Block512) -> [Gf128; 4] {
+    let ys: [Block; 4] = y.into();
+    [
+        Gf128::from_block(ys[0]),
+        Gf128::from_block(ys[1]),
+        Gf128::from_block(ys[2]),
+        Gf128::from_block(ys[3]),
+    ]
+}
+
+#[inline]
+fn from_lanes(ys: [Gf128; 4]) -> Block512 {
+    Block512::from([
+        ys[0].to_block(),
+        ys[1].to_block(),
+        ys[2].to_block(),
+        ys[3].to_block(),
+    ])
+}
+
+// Return the coefficients of the polynomials through `(xs[i], ys[i])` in each
+// lane, lowest degree first, by Lagrange interpolation. The `xs` must be
+// distinct.
+fn interpolate(xs: &[Gf128], ys: &[[Gf128; 4]]) -> Vec<[Gf128; 4]> {
+    let n = xs.len();
+    // `∏ (X - x_j)`. Subtraction is addition in GF(2^128).
+    let mut master = vec![Gf128::ZERO; n + 1];
+    master[0] = Gf128::ONE;
+    for (j, x) in xs.iter().enumerate() {
+        for k in (0..=j).rev() {
+            let c = master[k];
+            master[k + 1] = master[k + 1] + c;
+            master[k] = c * *x;
+        }
+    }
+    let mut coefficients = vec![[Gf128::ZERO; 4]; n];
+    let mut quotient = vec![Gf128::ZERO; n];
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        // `∏_{j ≠ i} (X - x_j)`, by synthetic division of the master
+        // polynomial by `X - x_i`.
+        quotient[n - 1] = master[n];
+        for k in (1..n).rev() {
+            quotient[k - 1] = master[k] + *x * quotient[k];
+        }
+        let denominator = quotient
+            .iter()
+            .rev()
+            .fold(Gf128::ZERO, |acc, q| acc * *x + *q);
+        let w = denominator.inverse();
+        let scales = [y[0] * w, y[1] * w, y[2] * w, y[3] * w];
+        for (c, q) in coefficients.iter_mut().zip(quotient.iter()) {
+            for l in 0..4 {
+                c[l] = c[l] + scales[l] * *q;
+            }
+        }
+    }
+    coefficients
+}
+
+/// Polynomial-based OPPRF hint.
+#[derive(Debug)]
+pub struct PolynomialHint {
+    // The coefficient of `X^i` of the polynomial of each lane, lowest degree
+    // first.
+    coefficients: Vec<Block512>,
+}
+
+impl Hint for PolynomialHint {
+    fn encode<RNG: CryptoRng + Rng>(
+        points: &[(Block512, Block512)],
+        npoints: usize,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        utils::invariant(points.len() <= npoints, "OPPRF bin overflows its bound")?;
+        let mut xs = Vec::with_capacity(npoints);
+        let mut ys = Vec::with_capacity(npoints);
+        let mut seen = HashSet::with_capacity(npoints);
+        for (y_, y) in points.iter() {
+            let x = lanes(*y_)[0];
+            if !seen.insert(x) {
+                return Err(Error::Other(
+                    "two OPPRF points have colliding OPRF outputs".to_string(),
+                ));
+            }
+            xs.push(x);
+            ys.push(lanes(*y ^ *y_));
+        }
+        // Pad with random points, so that the hint says nothing about the
+        // number of points.
+        while xs.len() < npoints {
+            let x = Gf128::random(rng);
+            if seen.insert(x) {
+                xs.push(x);
+                ys.push(lanes(rng.gen()));
+            }
+        }
+        let coefficients = interpolate(&xs, &ys)
+            .into_iter()
+            .map(from_lanes)
+            .collect();
+        Ok(Self { coefficients })
+    }
+
+    fn decode(&self, output: Block512) -> Block512 {
+        let x = lanes(output)[0];
+        let correction = self
+            .coefficients
+            .iter()
+            .rev()
+            .fold([Gf128::ZERO; 4], |acc, c| {
+                let c = lanes(*c);
+                [
+                    acc[0] * x + c[0],
+                    acc[1] * x + c[1],
+                    acc[2] * x + c[2],
+                    acc[3] * x + c[3],
+                ]
+            });
+        output ^ from_lanes(correction)
+    }
+
+    fn write<C: AbstractChannel>(&self, channel: &mut C) -> Result<(), Error> {
+        for c in self.coefficients.iter() {
+            channel.write_block512(c)?;
+        }
+        Ok(())
+    }
+
+    fn read<C: AbstractChannel>(channel: &mut C, npoints: usize) -> Result<Self, Error> {
+        let coefficients = (0..npoints)
+            .map(|_| channel.read_block512())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { coefficients })
+    }
+}
@@ -0,0 +1,173 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! The table-based hint of the one-time OPPRF of Kolesnikov, Matania, Pinkas,
+//! Rosulek, and Trieu (cf. <https://eprint.iacr.org/2017/799>, Figure 6).
+//!
+//! The sender picks a key `v` under which the OPRF outputs of its points hash
+//! to distinct entries of a table of size `m`, and stores in each such entry
+//! the correction between the OPRF output and the programmed value.
+
+use crate::{errors::Error, oprf::opprf::Hint, utils};
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{AbstractChannel, Aes128, Block, Block512};
+use std::collections::HashSet;
+
+// Number of times to iterate when creating the sender's hash table.
+const N_TABLE_LOOPS: usize = 128;
+
+// Hash `y` with key `k`, producing a result in the range `[0..range-1]`.
+fn hash_output(k: Block, y: Block512, range: usize) -> usize {
+    let aes = Aes128::new(k);
+    hash_output_keyed(&aes, y, range)
+}
+
+// Hash `y` with pre-keyed `k`. Uses a Davies-Meyer-esque hash function.
+//
+// XXX: can we remove this re-keying? It'll speed things up a bunch.
+fn hash_output_keyed(k: &Aes128, y: Block512, range: usize) -> usize {
+    let ys: [Block; 4] = y.into();
+    let h = k.encrypt(ys[0]) ^ ys[0];
+    let k = Aes128::new(h);
+    let h = k.encrypt(ys[1]) ^ ys[1];
+    let k = Aes128::new(h);
+    let h = k.encrypt(ys[2]) ^ ys[2];
+    let k = Aes128::new(h);
+    let h = k.encrypt(ys[3]) ^ ys[3];
+    (u128::from(h) % (range as u128)) as usize
+}
+
+// Compute the table size for the OPPRF.
+#[inline(always)]
+fn table_size(npoints: usize) -> usize {
+    // These are over-approximations, but appear to lead to better running
+    // times (at the expense of more communication).
+    if npoints <= 32 {
+        32
+    } else if npoints <= 64 {
+        256
+    } else {
+        (((npoints + 2) as f32).log2().ceil()).exp2() as usize
+    }
+}
+
+/// Table-based OPPRF hint.
+#[derive(Debug)]
+pub struct TableHint {
+    v: Block,
+    table: Vec<Block512>,
+}
+
+impl Hint for TableHint {
+    fn encode<RNG: CryptoRng + Rng>(
+        points: &[(Block512, Block512)],
+        npoints: usize,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        utils::invariant(points.len() <= npoints, "OPPRF bin overflows its bound")?;
+        let mut v = rng.gen::<Block>();
+        let mut aes = Aes128::new(v);
+        let mut map = HashSet::with_capacity(points.len());
+        let mut hs = vec![usize::default(); points.len()];
+        // Guess a size for `table` using `offset`, and then try to fill
+        // `map` with points hashed into the space `[0..m-1]`. If this fails
+        // (because `m` is too small), we change `offset` and try again,
+        // looping until we choose an appropriate `m` such that we can find
+        // a `v` such that every entry in `map` is distinct.
+        //
+        // Note that choosing `m` correctly quickly matters **a lot** to the
+        // overall running time.
+        let mut m = table_size(npoints);
+        let increment = m;
+        loop {
+            // Sample `v` until all values in `map` are distinct.
+            for _ in 0..N_TABLE_LOOPS {
+                for (i, (y_, _)) in points.iter().enumerate() {
+                    hs[i] = hash_output_keyed(&aes, *y_, m);
+                    if !map.insert(hs[i]) {
+                        break;
+                    }
+                }
+                if map.len() == points.len() {
+                    break;
+                }
+                // Try again.
+                v = rng.gen::<Block>();
+                aes = Aes128::new(v);
+                map.clear();
+            }
+            if map.len() == points.len() {
+                break;
+            }
+            // Failure :-(. Increment `offset` and try again.
+            m += increment;
+        }
+        let mut table = vec![Block512::default(); m];
+        // Place points in table based on the hash of their OPRF output.
+        for (h, (y_, y)) in hs.into_iter().zip(points.iter()) {
+            table[h] = *y ^ *y_;
+        }
+        // Fill rest of table with random elements.
+        for entry in table.iter_mut() {
+            if *entry == Block512::default() {
+                *entry = rng.gen::<Block512>();
+            }
+        }
+        Ok(Self { v, table })
+    }
+
+    #[inline]
+    fn decode(&self, output: Block512) -> Block512 {
+        output ^ self.table[hash_output(self.v, output, self.table.len())]
+    }
+
+    fn write<C: AbstractChannel>(&self, channel: &mut C) -> Result<(), Error> {
+        channel.write_usize(self.table.len())?;
+        channel.write_block(&self.v)?;
+        for entry in self.table.iter() {
+            channel.write_block512(entry)?;
+        }
+        Ok(())
+    }
+
+    fn read<C: AbstractChannel>(channel: &mut C, _: usize) -> Result<Self, Error> {
+        let m = channel.read_usize()?;
+        utils::invariant(m > 0, "OPPRF table must be nonempty")?;
+        let v = channel.read_block()?;
+        let table = (0..m)
+            .map(|_| channel.read_block512())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { v, table })
+    }
+}
+
+//
+// Benchmarks.
+//
+
+#[cfg(all(feature = "nightly", test))]
+mod benchmarks {
+    extern crate test;
+    use super::*;
+    use test::{black_box, Bencher};
+
+    #[bench]
+    fn bench_hash_output(b: &mut Bencher) {
+        let k = black_box(rand::random::<Block>());
+        let x = black_box(rand::random::<Block512>());
+        let range = 15;
+        b.iter(|| super::hash_output(k, x, range));
+    }
+
+    #[bench]
+    fn bench_hash_output_keyed(b: &mut Bencher) {
+        let k = black_box(rand::random::<Block>());
+        let x = black_box(rand::random::<Block512>());
+        let aes = Aes128::new(k);
+        let range = 15;
+        b.iter(|| super::hash_output_keyed(&aes, x, range));
+    }
+}
@@ -4,7 +4,7 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
-use scuttlebutt::{Aes128, Block};
+use scuttlebutt::{Aes128, Block, Block512};
 
 pub struct PseudorandomCode {
     cipher1: Aes128,
@@ -33,6 +33,22 @@ impl PseudorandomCode {
         out[2] = self.cipher3.encrypt(m);
         out[3] = self.cipher4.encrypt(m);
     }
+
+    pub fn encode_many(&self, ms: &[Block], out: &mut [Block512]) {
+        assert_eq!(ms.len(), out.len());
+        let ciphers = [&self.cipher1, &self.cipher2, &self.cipher3, &self.cipher4];
+        let mut cts = [[Block::default(); 8]; 4];
+        for (ms, out) in ms.chunks(8).zip(out.chunks_mut(8)) {
+            for (cipher, cts) in ciphers.iter().zip(cts.iter_mut()) {
+                let cts = &mut cts[..ms.len()];
+                cts.copy_from_slice(ms);
+                cipher.encrypt_blocks(cts);
+            }
+            for (k, out) in out.iter_mut().enumerate() {
+                *out = Block512::from([cts[0][k], cts[1][k], cts[2][k], cts[3][k]]);
+            }
+        }
+    }
 }
 
 #[cfg(all(feature = "nightly", test))]
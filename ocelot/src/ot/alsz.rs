@@ -12,6 +12,7 @@
 use crate::{
     errors::Error,
     ot::{
+        resumption::{BaseOtState, Resumable},
         CorrelatedReceiver,
         CorrelatedSender,
         RandomReceiver,
@@ -48,7 +49,36 @@ pub struct Receiver<OT: OtSender<Msg = Block> + SemiHonest> {
     rngs: Vec<(AesRng, AesRng)>,
 }
 
+// Only the number of base OTs is shown, never their outputs.
+impl<OT: OtReceiver<Msg = Block> + SemiHonest> std::fmt::Debug for Sender<OT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("alsz::Sender")
+            .field("nbase", &self.rngs.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + SemiHonest> std::fmt::Debug for Receiver<OT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("alsz::Receiver")
+            .field("nbase", &self.rngs.len())
+            .finish_non_exhaustive()
+    }
+}
+
 impl<OT: OtReceiver<Msg = Block> + SemiHonest> Sender<OT> {
+    fn from_base_ots(s_: Block, ks: &[Block]) -> Self {
+        let s = utils::u8vec_to_boolvec(s_.as_ref());
+        let rngs = ks.iter().map(|k| AesRng::from_seed(*k)).collect();
+        Self {
+            _ot: PhantomData::<OT>,
+            hash: AES_HASH,
+            s,
+            s_,
+            rngs,
+        }
+    }
+
     pub(super) fn send_setup<C: AbstractChannel>(
         &mut self,
         channel: &mut C,
@@ -77,22 +107,7 @@ impl<OT: OtReceiver<Msg = Block> + SemiHonest> OtSender for Sender<OT> {
         channel: &mut C,
         rng: &mut RNG,
     ) -> Result<Self, Error> {
-        let mut ot = OT::init(channel, rng)?;
-        let mut s_ = [0u8; 16];
-        rng.fill_bytes(&mut s_);
-        let s = utils::u8vec_to_boolvec(&s_);
-        let ks = ot.receive(channel, &s, rng)?;
-        let rngs = ks
-            .into_iter()
-            .map(AesRng::from_seed)
-            .collect::<Vec<AesRng>>();
-        Ok(Self {
-            _ot: PhantomData::<OT>,
-            hash: AES_HASH,
-            s,
-            s_: Block::from(s_),
-            rngs,
-        })
+        Self::init_resumable(channel, rng).map(|(ot, _)| ot)
     }
 
     fn send<C: AbstractChannel, RNG: CryptoRng + Rng>(
@@ -118,6 +133,14 @@ impl<OT: OtReceiver<Msg = Block> + SemiHonest> OtSender for Sender<OT> {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl<OT: OtReceiver<Msg = Block> + SemiHonest> Drop for Sender<OT> {
+    fn drop(&mut self) {
+        scutils::erase(&mut self.s);
+        scutils::erase(&mut self.s_);
+    }
+}
+
 impl<OT: OtReceiver<Msg = Block> + SemiHonest> std::fmt::Display for Sender<OT> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "ALSZ Sender")
@@ -173,6 +196,18 @@ impl<OT: OtReceiver<Msg = Block> + SemiHonest> RandomSender for Sender<OT> {
 }
 
 impl<OT: OtSender<Msg = Block> + SemiHonest> Receiver<OT> {
+    fn from_base_ots(ks: &[(Block, Block)]) -> Self {
+        let rngs = ks
+            .iter()
+            .map(|(k0, k1)| (AesRng::from_seed(*k0), AesRng::from_seed(*k1)))
+            .collect();
+        Self {
+            _ot: PhantomData::<OT>,
+            hash: AES_HASH,
+            rngs,
+        }
+    }
+
     pub(super) fn receive_setup<C: AbstractChannel>(
         &mut self,
         channel: &mut C,
@@ -204,25 +239,7 @@ impl<OT: OtSender<Msg = Block> + SemiHonest> OtReceiver for Receiver<OT> {
         channel: &mut C,
         rng: &mut RNG,
     ) -> Result<Self, Error> {
-        let mut ot = OT::init(channel, rng)?;
-        let mut ks = Vec::with_capacity(128);
-        let mut k0 = Block::default();
-        let mut k1 = Block::default();
-        for _ in 0..128 {
-            rng.fill_bytes(&mut k0.as_mut());
-            rng.fill_bytes(&mut k1.as_mut());
-            ks.push((k0, k1));
-        }
-        ot.send(channel, &ks, rng)?;
-        let rngs = ks
-            .into_iter()
-            .map(|(k0, k1)| (AesRng::from_seed(k0), AesRng::from_seed(k1)))
-            .collect::<Vec<(AesRng, AesRng)>>();
-        Ok(Self {
-            _ot: PhantomData::<OT>,
-            hash: AES_HASH,
-            rngs,
-        })
+        Self::init_resumable(channel, rng).map(|(ot, _)| ot)
     }
 
     fn receive<C: AbstractChannel, RNG: CryptoRng + Rng>(
@@ -295,5 +312,65 @@ impl<OT: OtSender<Msg = Block> + SemiHonest> std::fmt::Display for Receiver<OT>
     }
 }
 
+impl<OT: OtReceiver<Msg = Block> + SemiHonest> Resumable for Sender<OT> {
+    fn init_resumable<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(Self, BaseOtState), Error> {
+        let mut ot = OT::init(channel, rng)?;
+        let mut s_ = [0u8; 16];
+        rng.fill_bytes(&mut s_);
+        let s = utils::u8vec_to_boolvec(&s_);
+        let ks = ot.receive(channel, &s, rng)?;
+        let s_ = Block::from(s_);
+        let ot = Self::from_base_ots(s_, &ks);
+        Ok((
+            ot,
+            BaseOtState::Sender {
+                delta: s_,
+                seeds: ks,
+            },
+        ))
+    }
+
+    fn resume(state: &BaseOtState, nonce: Block) -> Result<Self, Error> {
+        match &state.rekey(nonce) {
+            BaseOtState::Sender { delta, seeds } => Ok(Self::from_base_ots(*delta, seeds)),
+            BaseOtState::Receiver { .. } => Err(Error::InvalidTicket(
+                "expected an OT extension sender's state".to_string(),
+            )),
+        }
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + SemiHonest> Resumable for Receiver<OT> {
+    fn init_resumable<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(Self, BaseOtState), Error> {
+        let mut ot = OT::init(channel, rng)?;
+        let mut ks = Vec::with_capacity(128);
+        let mut k0 = Block::default();
+        let mut k1 = Block::default();
+        for _ in 0..128 {
+            rng.fill_bytes(k0.as_mut());
+            rng.fill_bytes(k1.as_mut());
+            ks.push((k0, k1));
+        }
+        ot.send(channel, &ks, rng)?;
+        let ot = Self::from_base_ots(&ks);
+        Ok((ot, BaseOtState::Receiver { seeds: ks }))
+    }
+
+    fn resume(state: &BaseOtState, nonce: Block) -> Result<Self, Error> {
+        match &state.rekey(nonce) {
+            BaseOtState::Receiver { seeds } => Ok(Self::from_base_ots(seeds)),
+            BaseOtState::Sender { .. } => Err(Error::InvalidTicket(
+                "expected an OT extension receiver's state".to_string(),
+            )),
+        }
+    }
+}
+
 impl<OT: OtReceiver<Msg = Block> + SemiHonest> SemiHonest for Sender<OT> {}
 impl<OT: OtSender<Msg = Block> + SemiHonest> SemiHonest for Receiver<OT> {}
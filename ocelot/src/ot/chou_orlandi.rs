@@ -16,6 +16,12 @@
 //! `x^i` produced by the receiver is not randomized, all the random-OTs
 //! produced by the protocol will be the same. We fix this by hashing in `i`
 //! during the key derivation phase.
+//!
+//! Point compression needs a field inversion, which dominates the cost of
+//! each OT once the scalar multiplications are done. Both parties therefore
+//! compute half of every point they need and compress all of them at once
+//! with `RistrettoPoint::double_and_compress_batch`, which shares a single
+//! inversion among the whole batch. The messages sent are unchanged.
 
 use crate::{
     errors::Error,
@@ -25,6 +31,7 @@ use curve25519_dalek::{
     constants::RISTRETTO_BASEPOINT_TABLE,
     ristretto::{RistrettoBasepointTable, RistrettoPoint},
     scalar::Scalar,
+    traits::Identity,
 };
 use rand::{CryptoRng, Rng};
 use scuttlebutt::{AbstractChannel, Block, Malicious, SemiHonest};
@@ -55,16 +62,24 @@ impl OtSender for Sender {
         inputs: &[(Block, Block)],
         _: &mut RNG,
     ) -> Result<(), Error> {
-        let ys = self.y * self.s;
-        let ks = (0..inputs.len())
-            .map(|i| {
-                let r = channel.read_pt()?;
-                let yr = self.y * r;
-                let k0 = Block::hash_pt(i, &yr);
-                let k1 = Block::hash_pt(i, &(yr - ys));
-                Ok((k0, k1))
+        let half_y = self.y * Scalar::from(2u8).invert();
+        let half_ys = half_y * self.s;
+        let mut halves = Vec::with_capacity(2 * inputs.len());
+        for _ in 0..inputs.len() {
+            let half_yr = half_y * channel.read_pt()?;
+            halves.push(half_yr);
+            halves.push(half_yr - half_ys);
+        }
+        let ks = RistrettoPoint::double_and_compress_batch(&halves)
+            .chunks(2)
+            .enumerate()
+            .map(|(i, k)| {
+                (
+                    Block::hash_compressed_pt(i, &k[0]),
+                    Block::hash_compressed_pt(i, &k[1]),
+                )
             })
-            .collect::<Result<Vec<(Block, Block)>, Error>>()?;
+            .collect::<Vec<(Block, Block)>>();
         for (input, k) in inputs.iter().zip(ks.into_iter()) {
             let c0 = k.0 ^ input.0;
             let c1 = k.1 ^ input.1;
@@ -76,6 +91,14 @@ impl OtSender for Sender {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl Drop for Sender {
+    fn drop(&mut self) {
+        use scuttlebutt::utils::erase;
+        erase(&mut self.y);
+    }
+}
+
 impl std::fmt::Display for Sender {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "Chou-Orlandi Sender")
@@ -105,19 +128,22 @@ impl OtReceiver for Receiver {
         inputs: &[bool],
         mut rng: &mut RNG,
     ) -> Result<Vec<Block>, Error> {
-        let zero = &Scalar::zero() * &self.s;
-        let one = &Scalar::one() * &self.s;
-        let ks = inputs
-            .iter()
-            .enumerate()
-            .map(|(i, b)| {
-                let x = Scalar::random(&mut rng);
-                let c = if *b { one } else { zero };
-                let r = c + &x * &RISTRETTO_BASEPOINT_TABLE;
-                channel.write_pt(&r)?;
-                Ok(Block::hash_pt(i, &(&x * &self.s)))
-            })
-            .collect::<Result<Vec<Block>, Error>>()?;
+        let half = Scalar::from(2u8).invert();
+        let half_s = &half * &self.s;
+        let zero = RistrettoPoint::identity();
+        let mut halves = Vec::with_capacity(2 * inputs.len());
+        for b in inputs.iter() {
+            let half_x = Scalar::random(&mut rng) * half;
+            let c = if *b { half_s } else { zero };
+            halves.push(c + &half_x * &RISTRETTO_BASEPOINT_TABLE);
+            halves.push(&half_x * &self.s);
+        }
+        let compressed = RistrettoPoint::double_and_compress_batch(&halves);
+        let mut ks = Vec::with_capacity(inputs.len());
+        for (i, pts) in compressed.chunks(2).enumerate() {
+            channel.write_bytes(pts[0].as_bytes())?;
+            ks.push(Block::hash_compressed_pt(i, &pts[1]));
+        }
         channel.flush()?;
         inputs
             .iter()
@@ -0,0 +1,410 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Committed oblivious transfer, on top of any OT on `Block`s.
+//!
+//! Before each batch of transfers, the sender commits to both messages of
+//! every pair with `ShaCommitment`, hashing in the index of the transfer and
+//! which of the two messages it is. The transfers then run over the
+//! underlying OT as usual. Later, the receiver may ask the sender to open
+//! some of the transfers made so far: the parties toss coins to pick which,
+//! the sender reveals both messages and the seeds of their commitments, and
+//! the receiver checks them against the commitments and against the message
+//! it received.
+//!
+//! This is the building block of cut-and-choose: the receiver opens a random
+//! subset of the transfers and uses the rest, catching a sender who cheats on
+//! any of them with the probability that the cheating transfer is opened. The
+//! opened messages must never be used afterwards, since the receiver learns
+//! both of them. Since the coin toss, and not the receiver, picks the subset,
+//! and the sender refuses to open more than half of its transfers, the
+//! receiver cannot learn both messages of the transfers it keeps. Still, the
+//! sender is not a maliciously secure OT for the opened transfers, so it is
+//! only marked `SemiHonest`.
+//!
+//! Transfers are indexed from zero, in the order they are made, across all
+//! calls to `send` and `receive`. Both parties keep their side of every
+//! transfer until dropped, so the memory used grows with the number of
+//! transfers.
+
+use crate::{
+    errors::Error,
+    ot::{Receiver as OtReceiver, Sender as OtSender},
+};
+use rand::{CryptoRng, Rng, SeedableRng};
+use scuttlebutt::{
+    cointoss,
+    commitment::{Commitment, ShaCommitment},
+    AbstractChannel,
+    AesRng,
+    Block,
+    Malicious,
+    SemiHonest,
+};
+
+// Commit to message `b` of the transfer at `index`.
+fn commit(seed: [u8; 32], index: usize, b: bool, m: Block) -> [u8; 32] {
+    let mut commitment = ShaCommitment::new(seed);
+    commitment.input(&(index as u64).to_le_bytes());
+    commitment.input(&[b as u8]);
+    commitment.input(m.as_ref());
+    commitment.finish()
+}
+
+// Pick `n` of the transfers not yet `opened` at random from the coin toss
+// `seed`, in increasing order.
+fn choose(opened: impl Iterator<Item = bool>, n: usize, seed: Block) -> Vec<usize> {
+    let mut unopened = opened
+        .enumerate()
+        .filter(|(_, opened)| !opened)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut rng = AesRng::from_seed(seed);
+    for i in 0..n {
+        let j = rng.gen_range(i, unopened.len());
+        unopened.swap(i, j);
+    }
+    unopened.truncate(n);
+    unopened.sort_unstable();
+    unopened
+}
+
+// The sender's side of a transfer: both messages, the seeds of their
+// commitments, and whether it was opened.
+struct Opening {
+    m0: Block,
+    m1: Block,
+    r0: [u8; 32],
+    r1: [u8; 32],
+    opened: bool,
+}
+
+// The receiver's side of a transfer.
+struct Transfer {
+    b: bool,
+    m: Block,
+    c0: [u8; 32],
+    c1: [u8; 32],
+    opened: bool,
+}
+
+/// Committed oblivious transfer sender.
+pub struct Sender<OT: OtSender<Msg = Block>> {
+    ot: OT,
+    openings: Vec<Opening>,
+}
+
+/// Committed oblivious transfer receiver.
+pub struct Receiver<OT: OtReceiver<Msg = Block>> {
+    ot: OT,
+    transfers: Vec<Transfer>,
+}
+
+impl<OT: OtSender<Msg = Block>> Sender<OT> {
+    /// The number of transfers made so far.
+    pub fn len(&self) -> usize {
+        self.openings.len()
+    }
+
+    /// Whether no transfers have been made yet.
+    pub fn is_empty(&self) -> bool {
+        self.openings.is_empty()
+    }
+
+    /// Answer `Receiver::open`, opening as many transfers as the receiver
+    /// asks for, picked at random from those not yet opened. Returns their
+    /// indices, whose messages must not be used afterwards. Fails if the
+    /// receiver asks for more than half of the transfers made to be opened in
+    /// all.
+    pub fn open<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<usize>, Error> {
+        let n = channel.read_usize()?;
+        let nopened = self.openings.iter().filter(|o| o.opened).count();
+        if n > self.openings.len() / 2 - nopened {
+            return Err(Error::Other(format!(
+                "the receiver asks to open {} more of {} transfers, {} of which are open",
+                n,
+                self.openings.len(),
+                nopened
+            )));
+        }
+        let seed = cointoss::send(channel, &[rng.gen()])?[0];
+        let indices = choose(self.openings.iter().map(|o| o.opened), n, seed);
+        for &i in indices.iter() {
+            let opening = &mut self.openings[i];
+            opening.opened = true;
+            channel.write_block(&opening.m0)?;
+            channel.write_block(&opening.m1)?;
+            channel.write_bytes(&opening.r0)?;
+            channel.write_bytes(&opening.r1)?;
+        }
+        channel.flush()?;
+        Ok(indices)
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block>> Receiver<OT> {
+    /// The number of transfers made so far.
+    pub fn len(&self) -> usize {
+        self.transfers.len()
+    }
+
+    /// Whether no transfers have been made yet.
+    pub fn is_empty(&self) -> bool {
+        self.transfers.is_empty()
+    }
+
+    /// Ask the sender to open `n` transfers, picked at random from those not
+    /// yet opened, and check the openings. Returns the index and both
+    /// messages of each opened transfer, or `Error::InvalidOpening` if the
+    /// sender cheated on any of them. At most half of the transfers made may
+    /// be opened in all. Must be paired with `Sender::open`.
+    pub fn open<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        n: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<(usize, (Block, Block))>, Error> {
+        let nopened = self.transfers.iter().filter(|t| t.opened).count();
+        if nopened + n > self.transfers.len() / 2 {
+            return Err(Error::InvalidInputLength);
+        }
+        channel.write_usize(n)?;
+        channel.flush()?;
+        let seed = cointoss::receive(channel, &[rng.gen()])?[0];
+        let indices = choose(self.transfers.iter().map(|t| t.opened), n, seed);
+        let mut out = Vec::with_capacity(n);
+        for i in indices {
+            let m0 = channel.read_block()?;
+            let m1 = channel.read_block()?;
+            let mut r0 = [0u8; 32];
+            let mut r1 = [0u8; 32];
+            channel.read_bytes(&mut r0)?;
+            channel.read_bytes(&mut r1)?;
+            let transfer = &self.transfers[i];
+            let received = if transfer.b { m1 } else { m0 };
+            if !ShaCommitment::check(&commit(r0, i, false, m0), &transfer.c0)
+                || !ShaCommitment::check(&commit(r1, i, true, m1), &transfer.c1)
+                || received != transfer.m
+            {
+                return Err(Error::InvalidOpening(i));
+            }
+            self.transfers[i].opened = true;
+            out.push((i, (m0, m1)));
+        }
+        Ok(out)
+    }
+}
+
+impl<OT: OtSender<Msg = Block>> OtSender for Sender<OT> {
+    type Msg = Block;
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        let ot = OT::init(channel, rng)?;
+        Ok(Self {
+            ot,
+            openings: Vec::new(),
+        })
+    }
+
+    fn send<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        inputs: &[(Block, Block)],
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        let start = self.openings.len();
+        self.openings.reserve(inputs.len());
+        for (j, &(m0, m1)) in inputs.iter().enumerate() {
+            let (r0, r1) = (rng.gen::<[u8; 32]>(), rng.gen::<[u8; 32]>());
+            channel.write_bytes(&commit(r0, start + j, false, m0))?;
+            channel.write_bytes(&commit(r1, start + j, true, m1))?;
+            self.openings.push(Opening {
+                m0,
+                m1,
+                r0,
+                r1,
+                opened: false,
+            });
+        }
+        channel.flush()?;
+        self.ot.send(channel, inputs, rng)
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block>> OtReceiver for Receiver<OT> {
+    type Msg = Block;
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        let ot = OT::init(channel, rng)?;
+        Ok(Self {
+            ot,
+            transfers: Vec::new(),
+        })
+    }
+
+    fn receive<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        inputs: &[bool],
+        rng: &mut RNG,
+    ) -> Result<Vec<Block>, Error> {
+        let mut commitments = Vec::with_capacity(inputs.len());
+        for _ in 0..inputs.len() {
+            let (mut c0, mut c1) = ([0u8; 32], [0u8; 32]);
+            channel.read_bytes(&mut c0)?;
+            channel.read_bytes(&mut c1)?;
+            commitments.push((c0, c1));
+        }
+        let out = self.ot.receive(channel, inputs, rng)?;
+        self.transfers.extend(
+            inputs
+                .iter()
+                .zip(out.iter())
+                .zip(commitments)
+                .map(|((&b, &m), (c0, c1))| Transfer {
+                    b,
+                    m,
+                    c0,
+                    c1,
+                    opened: false,
+                }),
+        );
+        Ok(out)
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + std::fmt::Display> std::fmt::Display for Sender<OT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Committed {}", self.ot)
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block> + std::fmt::Display> std::fmt::Display for Receiver<OT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Committed {}", self.ot)
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + SemiHonest> SemiHonest for Sender<OT> {}
+impl<OT: OtReceiver<Msg = Block> + SemiHonest> SemiHonest for Receiver<OT> {}
+impl<OT: OtReceiver<Msg = Block> + Malicious> Malicious for Receiver<OT> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ot::{ChouOrlandiReceiver, ChouOrlandiSender};
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const N: usize = 64;
+
+    #[test]
+    fn test_open() {
+        let mut rng = AesRng::new();
+        let ms = (0..2 * N)
+            .map(|_| (rng.gen::<Block>(), rng.gen::<Block>()))
+            .collect::<Vec<_>>();
+        let bs = (0..2 * N).map(|_| rng.gen::<bool>()).collect::<Vec<_>>();
+        let ms_ = ms.clone();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut ot = Sender::<ChouOrlandiSender>::init(&mut channel, &mut rng).unwrap();
+            ot.send(&mut channel, &ms_[..N], &mut rng).unwrap();
+            ot.send(&mut channel, &ms_[N..], &mut rng).unwrap();
+            let mut indices = ot.open(&mut channel, &mut rng).unwrap();
+            indices.extend(ot.open(&mut channel, &mut rng).unwrap());
+            assert_eq!(ot.len(), 2 * N);
+            // No more than half of the transfers are opened.
+            assert!(ot.open(&mut channel, &mut rng).is_err());
+            indices
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut ot = Receiver::<ChouOrlandiReceiver>::init(&mut channel, &mut rng).unwrap();
+        let mut out = ot.receive(&mut channel, &bs[..N], &mut rng).unwrap();
+        out.extend(ot.receive(&mut channel, &bs[N..], &mut rng).unwrap());
+        for (j, m) in out.iter().enumerate() {
+            assert_eq!(*m, if bs[j] { ms[j].1 } else { ms[j].0 });
+        }
+        let mut opened = ot.open(&mut channel, N / 2, &mut rng).unwrap();
+        opened.extend(ot.open(&mut channel, N / 2, &mut rng).unwrap());
+        let indices = opened.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        // Transfers are opened once.
+        let mut distinct = indices.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct.len(), N);
+        for (i, pair) in opened.iter() {
+            assert_eq!(*pair, ms[*i]);
+        }
+        assert!(ot.open(&mut channel, 1, &mut rng).is_err());
+        // A receiver asking for more anyway is refused.
+        channel.write_usize(1).unwrap();
+        channel.flush().unwrap();
+        assert_eq!(handle.join().unwrap(), indices);
+    }
+
+    #[test]
+    fn test_cheating_sender() {
+        // The sender commits to one message, then transfers another, in each
+        // of two transfers.
+        let mut rng = AesRng::new();
+        let m0 = rng.gen::<Block>();
+        let m1 = rng.gen::<Block>();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut ot = Sender::<ChouOrlandiSender>::init(&mut channel, &mut rng).unwrap();
+            for j in 0..2 {
+                let (r0, r1) = (rng.gen::<[u8; 32]>(), rng.gen::<[u8; 32]>());
+                channel.write_bytes(&commit(r0, j, false, m0)).unwrap();
+                channel.write_bytes(&commit(r1, j, true, m1)).unwrap();
+                channel.flush().unwrap();
+                ot.ot.send(&mut channel, &[(m1, m0)], &mut rng).unwrap();
+                ot.openings.push(Opening {
+                    m0,
+                    m1,
+                    r0,
+                    r1,
+                    opened: false,
+                });
+            }
+            ot.open(&mut channel, &mut rng).unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut ot = Receiver::<ChouOrlandiReceiver>::init(&mut channel, &mut rng).unwrap();
+        ot.receive(&mut channel, &[true], &mut rng).unwrap();
+        ot.receive(&mut channel, &[true], &mut rng).unwrap();
+        match ot.open(&mut channel, 1, &mut rng) {
+            Err(Error::InvalidOpening(_)) => (),
+            r => panic!("expected an invalid opening, got {:?}", r),
+        }
+        handle.join().unwrap();
+    }
+}
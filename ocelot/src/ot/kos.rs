@@ -6,6 +6,19 @@
 
 //! Implementation of the Keller-Orsini-Scholl oblivious transfer extension
 //! protocol (cf. <https://eprint.iacr.org/2015/546>).
+//!
+//! The extension runs that of ALSZ on `128 + 40` extra columns, then checks a
+//! random linear combination of the extended matrix, with coefficients from a
+//! coin toss, against the sender's base OT choices. This catches a receiver
+//! using inconsistent choice bits, so the standard, correlated and random OTs
+//! of `Sender` and `Receiver` are all secure against malicious adversaries
+//! and marked `Malicious`, given maliciously secure base OTs (as with
+//! `KosSender` and `KosReceiver`, over Chou-Orlandi). A failed check is an
+//! `InvalidData` I/O error.
+//!
+//! Unlike ALSZ, KOS does not implement `Resumable`. Resuming would keep the
+//! sender's base OT choices across sessions, so a malicious receiver could
+//! learn them a bit at a time from whether each session's check fails.
 
 use crate::{
     errors::Error,
@@ -36,6 +49,18 @@ pub struct Receiver<OT: OtSender<Msg = Block> + Malicious> {
     ot: AlszReceiver<OT>,
 }
 
+impl<OT: OtReceiver<Msg = Block> + Malicious> std::fmt::Debug for Sender<OT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("kos::Sender").field(&self.ot).finish()
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + Malicious> std::fmt::Debug for Receiver<OT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("kos::Receiver").field(&self.ot).finish()
+    }
+}
+
 impl<OT: OtReceiver<Msg = Block> + Malicious> Sender<OT> {
     fn send_setup<C: AbstractChannel, RNG: CryptoRng + Rng>(
         &mut self,
@@ -0,0 +1,317 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Oblivious transfer of long messages, such as files, on top of any OT on
+//! `Block`s.
+//!
+//! Rather than move the messages themselves through the OT, the sender picks a
+//! fresh random key for each of them and transfers the keys. The messages are
+//! then sent encrypted with the AES keystream seeded by their key, so that the
+//! receiver can only decrypt those whose key it chose. `LongSender::send_long`
+//! transfers one of each pair of messages; `LongSender::send_one_of_n`
+//! transfers one of `n` messages with `⌈log₂ n⌉` OTs, following Naor-Pinkas.
+//!
+//! The receiver learns the length of every message, so messages must be
+//! padded to a common length if their lengths are sensitive.
+
+use crate::{
+    errors::Error,
+    ot::{Receiver, Sender},
+};
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use scuttlebutt::{utils as scutils, AbstractChannel, Aes128, AesRng, Block};
+
+// The messages are encrypted and sent in chunks of this many bytes.
+const CHUNK_SIZE: usize = 1 << 16;
+
+// Write `msg` preceded by its length, encrypted under the keystream seeded by
+// `key`.
+fn write_encrypted<C: AbstractChannel>(
+    channel: &mut C,
+    key: Block,
+    msg: &[u8],
+) -> Result<(), Error> {
+    channel.write_usize(msg.len())?;
+    let mut keystream = AesRng::from_seed(key);
+    let mut buf = vec![0u8; CHUNK_SIZE.min(msg.len())];
+    for chunk in msg.chunks(CHUNK_SIZE) {
+        let ct = &mut buf[..chunk.len()];
+        keystream.fill_bytes(ct);
+        scutils::xor_inplace(ct, chunk);
+        channel.write_bytes(ct)?;
+    }
+    Ok(())
+}
+
+// Read a message written by `write_encrypted`, decrypting it under `key`, or
+// skipping it if `key` is `None`.
+fn read_encrypted<C: AbstractChannel>(
+    channel: &mut C,
+    key: Option<Block>,
+) -> Result<Option<Vec<u8>>, Error> {
+    let len = channel.read_usize()?;
+    let mut keystream = key.map(AesRng::from_seed);
+    // The message grows with the data received, not with the claimed length.
+    let mut msg = Vec::new();
+    let mut buf = vec![0u8; CHUNK_SIZE.min(len)];
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(CHUNK_SIZE);
+        let ct = &mut buf[..n];
+        channel.read_bytes(ct)?;
+        if let Some(keystream) = keystream.as_mut() {
+            let start = msg.len();
+            msg.resize(start + n, 0);
+            keystream.fill_bytes(&mut msg[start..]);
+            scutils::xor_inplace(&mut msg[start..], ct);
+        }
+        remaining -= n;
+    }
+    Ok(keystream.map(|_| msg))
+}
+
+// The number of OTs and of key pairs needed to choose one of `n` messages.
+fn index_bits(n: usize) -> usize {
+    (usize::BITS - (n.max(2) - 1).leading_zeros()) as usize
+}
+
+// The key of message `i` of `send_one_of_n`, from the keys of the bits of `i`.
+fn index_key(keys: &[Block], i: usize) -> Block {
+    keys.iter().fold(Block::default(), |acc, &k| {
+        acc ^ Aes128::new(k).encrypt(Block::from(i as u128))
+    })
+}
+
+/// Oblivious transfer of long messages from the sender's point-of-view.
+/// Implemented for every OT sender on `Block`s.
+pub trait LongSender: Sender<Msg = Block> {
+    /// Send one message of each pair of `inputs`, as `Sender::send` does, but
+    /// with messages of any length. Must be paired with
+    /// `LongReceiver::receive_long`.
+    fn send_long<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        inputs: &[(&[u8], &[u8])],
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        let mut keys = (0..inputs.len())
+            .map(|_| (rng.gen::<Block>(), rng.gen::<Block>()))
+            .collect::<Vec<_>>();
+        self.send(channel, &keys, rng)?;
+        for (&(k0, k1), &(m0, m1)) in keys.iter().zip(inputs) {
+            write_encrypted(channel, k0, m0)?;
+            write_encrypted(channel, k1, m1)?;
+        }
+        scutils::erase(&mut keys);
+        channel.flush()?;
+        Ok(())
+    }
+
+    /// Send one of `messages`, of any length. The receiver learns
+    /// `messages.len()`. Must be paired with `LongReceiver::receive_one_of_n`.
+    fn send_one_of_n<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        messages: &[&[u8]],
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        if messages.is_empty() {
+            return Err(Error::InvalidInputLength);
+        }
+        channel.write_usize(messages.len())?;
+        channel.flush()?;
+        // Message `i` is keyed by one key of each pair, picked by the bits of
+        // `i`, so the receiver holds all the keys of its choice only.
+        let mut keys = (0..index_bits(messages.len()))
+            .map(|_| (rng.gen::<Block>(), rng.gen::<Block>()))
+            .collect::<Vec<_>>();
+        self.send(channel, &keys, rng)?;
+        let mut chosen = vec![Block::default(); keys.len()];
+        for (i, msg) in messages.iter().enumerate() {
+            for (j, (k, &(k0, k1))) in chosen.iter_mut().zip(&keys).enumerate() {
+                *k = if (i >> j) & 1 == 1 { k1 } else { k0 };
+            }
+            write_encrypted(channel, index_key(&chosen, i), msg)?;
+        }
+        scutils::erase(&mut keys);
+        scutils::erase(&mut chosen);
+        channel.flush()?;
+        Ok(())
+    }
+}
+
+impl<OT: Sender<Msg = Block>> LongSender for OT {}
+
+/// Oblivious transfer of long messages from the receiver's point-of-view.
+/// Implemented for every OT receiver on `Block`s.
+pub trait LongReceiver: Receiver<Msg = Block> {
+    /// Receive the message of each pair picked by `inputs`, as
+    /// `Receiver::receive` does, from a sender running
+    /// `LongSender::send_long`.
+    fn receive_long<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        inputs: &[bool],
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let mut keys = self.receive(channel, inputs, rng)?;
+        let result = keys
+            .iter()
+            .zip(inputs)
+            .map(|(&k, &b)| {
+                let m0 = read_encrypted(channel, if b { None } else { Some(k) })?;
+                let m1 = read_encrypted(channel, if b { Some(k) } else { None })?;
+                Ok(m0.or(m1).unwrap_or_default())
+            })
+            .collect();
+        scutils::erase(&mut keys);
+        result
+    }
+
+    /// Receive message `choice` of the `n` messages of a sender running
+    /// `LongSender::send_one_of_n`.
+    fn receive_one_of_n<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        n: usize,
+        choice: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<u8>, Error> {
+        if choice >= n {
+            return Err(Error::InvalidInputLength);
+        }
+        let nsent = channel.read_usize()?;
+        if nsent != n {
+            return Err(Error::Other(format!(
+                "the sender offers {} messages rather than {}",
+                nsent, n
+            )));
+        }
+        let bits = (0..index_bits(n))
+            .map(|j| (choice >> j) & 1 == 1)
+            .collect::<Vec<_>>();
+        let mut keys = self.receive(channel, &bits, rng)?;
+        let mut msg = None;
+        for i in 0..n {
+            let key = if i == choice {
+                Some(index_key(&keys, i))
+            } else {
+                None
+            };
+            if let Some(m) = read_encrypted(channel, key)? {
+                msg = Some(m);
+            }
+        }
+        scutils::erase(&mut keys);
+        msg.ok_or_else(|| Error::Other("the chosen message is missing".to_string()))
+    }
+}
+
+impl<OT: Receiver<Msg = Block>> LongReceiver for OT {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ot::{AlszReceiver, AlszSender, ChouOrlandiReceiver, ChouOrlandiSender};
+    use scuttlebutt::Channel;
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    fn rand_bytes(n: usize, rng: &mut AesRng) -> Vec<u8> {
+        let mut bytes = vec![0u8; n];
+        rng.fill_bytes(&mut bytes);
+        bytes
+    }
+
+    fn test_long<OTSender: Sender<Msg = Block>, OTReceiver: Receiver<Msg = Block>>() {
+        let mut rng = AesRng::new();
+        // Lengths straddling the chunk size, including an empty message.
+        let lens = [0, 1, CHUNK_SIZE, 3 * CHUNK_SIZE + 17, 1 << 20];
+        let m0s = lens
+            .iter()
+            .map(|&n| rand_bytes(n, &mut rng))
+            .collect::<Vec<_>>();
+        let m1s = lens
+            .iter()
+            .rev()
+            .map(|&n| rand_bytes(n, &mut rng))
+            .collect::<Vec<_>>();
+        let bs = [true, false, true, false, true];
+        let (m0s_, m1s_) = (m0s.clone(), m1s.clone());
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut ot = OTSender::init(&mut channel, &mut rng).unwrap();
+            let inputs = m0s_
+                .iter()
+                .zip(&m1s_)
+                .map(|(m0, m1)| (m0.as_slice(), m1.as_slice()))
+                .collect::<Vec<_>>();
+            ot.send_long(&mut channel, &inputs, &mut rng).unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut ot = OTReceiver::init(&mut channel, &mut rng).unwrap();
+        let results = ot.receive_long(&mut channel, &bs, &mut rng).unwrap();
+        handle.join().unwrap();
+        for (j, &b) in bs.iter().enumerate() {
+            assert_eq!(&results[j], if b { &m1s[j] } else { &m0s[j] });
+        }
+    }
+
+    #[test]
+    fn test_send_long() {
+        test_long::<ChouOrlandiSender, ChouOrlandiReceiver>();
+        test_long::<AlszSender, AlszReceiver>();
+    }
+
+    #[test]
+    fn test_one_of_n() {
+        let mut rng = AesRng::new();
+        for &(n, choice) in &[(1, 0), (2, 1), (5, 3), (8, 7)] {
+            let messages = (0..n)
+                .map(|i| rand_bytes(1000 + i, &mut rng))
+                .collect::<Vec<_>>();
+            let messages_ = messages.clone();
+            let (sender, receiver) = UnixStream::pair().unwrap();
+            let handle = std::thread::spawn(move || {
+                let mut rng = AesRng::new();
+                let reader = BufReader::new(sender.try_clone().unwrap());
+                let writer = BufWriter::new(sender);
+                let mut channel = Channel::new(reader, writer);
+                let mut ot = ChouOrlandiSender::init(&mut channel, &mut rng).unwrap();
+                let messages = messages_.iter().map(Vec::as_slice).collect::<Vec<_>>();
+                ot.send_one_of_n(&mut channel, &messages, &mut rng).unwrap();
+            });
+            let reader = BufReader::new(receiver.try_clone().unwrap());
+            let writer = BufWriter::new(receiver);
+            let mut channel = Channel::new(reader, writer);
+            let mut ot = ChouOrlandiReceiver::init(&mut channel, &mut rng).unwrap();
+            let msg = ot
+                .receive_one_of_n(&mut channel, n, choice, &mut rng)
+                .unwrap();
+            handle.join().unwrap();
+            assert_eq!(msg, messages[choice]);
+        }
+    }
+
+    #[test]
+    fn test_index_key() {
+        // The key of a message needs every key picked by the bits of its index.
+        let keys = [Block::from(1u128), Block::from(2u128)];
+        assert_eq!(index_bits(1), 1);
+        assert_eq!(index_bits(4), 2);
+        assert_eq!(index_bits(5), 3);
+        assert_ne!(index_key(&keys, 2), index_key(&keys[..1], 2));
+        assert_ne!(index_key(&keys, 2), index_key(&keys, 3));
+    }
+}
@@ -14,18 +14,34 @@
 //! * `chou_orlandi`: Chou-Orlandi malicious OT.
 //! * `alsz`: Asharov-Lindell-Schneider-Zohner semi-honest OT extension (+ correlated and random OT).
 //! * `kos`: Keller-Orsini-Scholl malicious OT extension (+ correlated and random OT).
+//! * `ferret`: Ferret semi-honest silent OT extension, with communication
+//!   sublinear in the number of OTs (+ random OT).
 //!
+//! The `committed` module wraps any of these OTs so that the sender commits
+//! to its messages and can later be asked to open a random subset of them.
+//!
+//! The `long` module transfers messages of any length, such as files, over
+//! any of these OTs.
+//!
+//! The `resumption` module lets the semi-honest ALSZ extension skip its base
+//! OTs when reconnecting to a peer it has run them with before.
 
 pub mod alsz;
 pub mod chou_orlandi;
+pub mod committed;
 pub mod dummy;
+pub mod ferret;
 pub mod kos;
+pub mod long;
 pub mod naor_pinkas;
+pub mod resumption;
 
 use crate::errors::Error;
 use rand::{CryptoRng, Rng};
 use scuttlebutt::AbstractChannel;
 
+pub use long::{LongReceiver, LongSender};
+
 /// Instantiation of the Chou-Orlandi OT sender.
 pub type ChouOrlandiSender = chou_orlandi::Sender;
 /// Instantiation of the Chou-Orlandi OT receiver.
@@ -46,6 +62,14 @@ pub type AlszReceiver = alsz::Receiver<ChouOrlandiSender>;
 pub type KosSender = kos::Sender<ChouOrlandiReceiver>;
 /// Instantiation of the KOS OT extension receiver, using Chou-Orlandi as the base OT.
 pub type KosReceiver = kos::Receiver<ChouOrlandiSender>;
+/// Instantiation of the Ferret silent OT extension sender, using ALSZ for the initial reserve.
+pub type FerretSender = ferret::Sender<AlszSender>;
+/// Instantiation of the Ferret silent OT extension receiver, using ALSZ for the initial reserve.
+pub type FerretReceiver = ferret::Receiver<AlszReceiver>;
+/// Instantiation of the committed OT sender, using KOS as the underlying OT.
+pub type CommittedSender = committed::Sender<KosSender>;
+/// Instantiation of the committed OT receiver, using KOS as the underlying OT.
+pub type CommittedReceiver = committed::Receiver<KosReceiver>;
 
 /// Trait for one-out-of-two oblivious transfer from the sender's point-of-view.
 pub trait Sender
@@ -325,6 +349,24 @@ mod tests {
         test_ot::<ChouOrlandiSender, ChouOrlandiReceiver>();
     }
 
+    #[test]
+    fn test_batch_compression() {
+        // The base OTs compress `2 · (P / 2)` in batches in place of `P`,
+        // which must give the same bytes on the wire.
+        use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
+        let mut rng = AesRng::new();
+        let half = Scalar::from(2u8).invert();
+        let pts = (0..17)
+            .map(|_| RistrettoPoint::random(&mut rng))
+            .collect::<Vec<_>>();
+        let halves = pts.iter().map(|pt| pt * half).collect::<Vec<_>>();
+        let compressed = RistrettoPoint::double_and_compress_batch(&halves);
+        for (i, (pt, c)) in pts.iter().zip(compressed.iter()).enumerate() {
+            assert_eq!(pt.compress(), *c);
+            assert_eq!(Block::hash_pt(i, pt), Block::hash_compressed_pt(i, c));
+        }
+    }
+
     #[test]
     fn test_alsz() {
         let ninputs = 1 << 10;
@@ -348,4 +390,144 @@ mod tests {
         test_cotext::<KosSender, KosReceiver>(ninputs);
         test_rotext::<KosSender, KosReceiver>(ninputs);
     }
+
+    #[test]
+    fn test_committed() {
+        test_otext::<CommittedSender, CommittedReceiver>(1 << 10);
+    }
+
+    #[test]
+    fn test_ferret() {
+        let ninputs = 1 << 10;
+        test_otext::<FerretSender, FerretReceiver>(ninputs);
+        test_rotext::<FerretSender, FerretReceiver>(ninputs);
+        let ninputs = (1 << 10) + 1;
+        test_otext::<FerretSender, FerretReceiver>(ninputs);
+        test_rotext::<FerretSender, FerretReceiver>(ninputs);
+    }
+
+    #[test]
+    fn test_ferret_iterations() {
+        // Small parameters, so that the OTs span several iterations, each
+        // running on the reserve left by the previous one.
+        let params = crate::lpn::LpnParams {
+            n: 4096,
+            k: 512,
+            t: 32,
+            d: 10,
+        };
+        let m = 3 * (4096 - 512 - 32 * 7) + 100;
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut ot = FerretSender::init_with_params(&mut channel, &mut rng, params).unwrap();
+            let vs = ot
+                .send_correlated_silent(&mut channel, m, &mut rng)
+                .unwrap();
+            let ms = ot.send_silent(&mut channel, 100, &mut rng).unwrap();
+            (ot.delta(), vs, ms)
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut ot = FerretReceiver::init_with_params(&mut channel, &mut rng, params).unwrap();
+        let ws = ot
+            .receive_correlated_silent(&mut channel, m, &mut rng)
+            .unwrap();
+        let ms = ot.receive_silent(&mut channel, 100, &mut rng).unwrap();
+        let (delta, vs, ms_) = handle.join().unwrap();
+        for ((x, w), v) in ws.iter().zip(vs.iter()) {
+            assert_eq!(*w, if *x { *v ^ delta } else { *v });
+        }
+        // The choice bits are LPN samples, so about half of them are set.
+        let nset = ws.iter().filter(|(x, _)| *x).count();
+        assert!(nset > m / 3 && nset < 2 * m / 3);
+        for ((x, m), (m0, m1)) in ms.iter().zip(ms_.iter()) {
+            assert_eq!(m, if *x { m1 } else { m0 });
+        }
+    }
+
+    #[test]
+    fn test_ferret_invalid_params() {
+        let mut rng = AesRng::new();
+        let mut channel = Channel::new(std::io::empty(), std::io::sink());
+        let invalid = [
+            // Not `t` times a power of two.
+            (4000, 512, 32),
+            // An iteration uses more OTs than it gives.
+            (4096, 4000, 32),
+            (4096, 512, 0),
+            (4096, 0, 32),
+        ];
+        for &(n, k, t) in &invalid {
+            let params = crate::lpn::LpnParams { n, k, t, d: 10 };
+            assert!(FerretSender::init_with_params(&mut channel, &mut rng, params).is_err());
+            assert!(FerretReceiver::init_with_params(&mut channel, &mut rng, params).is_err());
+        }
+    }
+
+    // A channel flipping the first bit of the first `ncorrupt` messages
+    // written once armed.
+    struct FlipChannel<C> {
+        channel: C,
+        armed: bool,
+        ncorrupt: usize,
+    }
+
+    impl<C: AbstractChannel> AbstractChannel for FlipChannel<C> {
+        fn read_bytes(&mut self, bytes: &mut [u8]) -> std::io::Result<()> {
+            self.channel.read_bytes(bytes)
+        }
+
+        fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            if self.armed && self.ncorrupt > 0 {
+                self.ncorrupt -= 1;
+                let mut bytes = bytes.to_vec();
+                bytes[0] ^= 1;
+                return self.channel.write_bytes(&bytes);
+            }
+            self.channel.write_bytes(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.channel.flush()
+        }
+
+        fn clone(&self) -> Self {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn test_kos_rejects_inconsistent_receiver() {
+        // The receiver uses a different choice bit for the first OT in half
+        // of the columns of the extension matrix, which the correlation check
+        // catches unless all or none of the sender's matching base OT choices
+        // are set.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut ot = KosSender::init(&mut channel, &mut rng).unwrap();
+            ot.send_random(&mut channel, 1 << 10, &mut rng)
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = FlipChannel {
+            channel: Channel::new(reader, writer),
+            armed: false,
+            ncorrupt: 64,
+        };
+        let mut ot = KosReceiver::init(&mut channel, &mut rng).unwrap();
+        channel.armed = true;
+        let _ = ot.receive_random(&mut channel, &rand_bool_vec(1 << 10), &mut rng);
+        assert!(handle.join().unwrap().is_err());
+    }
 }
@@ -56,18 +56,25 @@ impl OtSender for Sender {
             let pk0 = channel.read_pt()?;
             pks.push((pk0, c - pk0));
         }
-        for (i, (input, pk)) in inputs.iter().zip(pks.into_iter()).enumerate() {
-            let r0 = Scalar::random(&mut rng);
-            let r1 = Scalar::random(&mut rng);
-            let e00 = &r0 * &RISTRETTO_BASEPOINT_TABLE;
-            let e10 = &r1 * &RISTRETTO_BASEPOINT_TABLE;
-            let h = Block::hash_pt(i, &(pk.0 * r0));
-            let e01 = h ^ input.0;
-            let h = Block::hash_pt(i, &(pk.1 * r1));
-            let e11 = h ^ input.1;
-            channel.write_pt(&e00)?;
+        // Compute half of every point we need and compress them in one batch,
+        // sharing a single field inversion.
+        let half = Scalar::from(2u8).invert();
+        let mut halves = Vec::with_capacity(4 * m);
+        for pk in pks.into_iter() {
+            let r0 = Scalar::random(&mut rng) * half;
+            let r1 = Scalar::random(&mut rng) * half;
+            halves.push(&r0 * &RISTRETTO_BASEPOINT_TABLE);
+            halves.push(pk.0 * r0);
+            halves.push(&r1 * &RISTRETTO_BASEPOINT_TABLE);
+            halves.push(pk.1 * r1);
+        }
+        let compressed = RistrettoPoint::double_and_compress_batch(&halves);
+        for (i, (input, pts)) in inputs.iter().zip(compressed.chunks(4)).enumerate() {
+            let e01 = Block::hash_compressed_pt(i, &pts[1]) ^ input.0;
+            let e11 = Block::hash_compressed_pt(i, &pts[3]) ^ input.1;
+            channel.write_bytes(pts[0].as_bytes())?;
             channel.write_block(&e01)?;
-            channel.write_pt(&e10)?;
+            channel.write_bytes(pts[2].as_bytes())?;
             channel.write_block(&e11)?;
         }
         channel.flush()?;
@@ -115,23 +122,27 @@ impl OtReceiver for Receiver {
             ks.push(k);
         }
         channel.flush()?;
-        inputs
+        let half = Scalar::from(2u8).invert();
+        let mut halves = Vec::with_capacity(m);
+        let mut es = Vec::with_capacity(m);
+        for (b, k) in inputs.iter().zip(ks) {
+            let e00 = channel.read_pt()?;
+            let e01 = channel.read_block()?;
+            let e10 = channel.read_pt()?;
+            let e11 = channel.read_block()?;
+            let (e0, e1) = match b {
+                false => (e00, e01),
+                true => (e10, e11),
+            };
+            halves.push(e0 * (k * half));
+            es.push(e1);
+        }
+        Ok(RistrettoPoint::double_and_compress_batch(&halves)
             .iter()
-            .zip(ks.into_iter())
+            .zip(es)
             .enumerate()
-            .map(|(i, (b, k))| {
-                let e00 = channel.read_pt()?;
-                let e01 = channel.read_block()?;
-                let e10 = channel.read_pt()?;
-                let e11 = channel.read_block()?;
-                let (e0, e1) = match b {
-                    false => (e00, e01),
-                    true => (e10, e11),
-                };
-                let h = Block::hash_pt(i, &(e0 * k));
-                Ok(h ^ e1)
-            })
-            .collect()
+            .map(|(i, (pt, e1))| Block::hash_compressed_pt(i, pt) ^ e1)
+            .collect())
     }
 }
 
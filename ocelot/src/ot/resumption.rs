@@ -0,0 +1,735 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Session resumption for OT extension, so that a client which has already
+//! run the base OTs with a server can skip them when it reconnects.
+//!
+//! Once the base OTs are done, the server seals its outputs into a `Ticket`
+//! using a `TicketIssuer`. The outputs are encrypted with AES-128 in counter
+//! mode and authenticated with HMAC-SHA256, using keys that only the server
+//! holds, so the server keeps no per-client state. The client stores the
+//! ticket next to its own outputs in a `ClientSession`, and presents the
+//! ticket when it reconnects.
+//!
+//! A ticket is valid for the lifetime given to its issuer and can be redeemed
+//! only once, since the issuer remembers the ids of redeemed tickets until
+//! they expire. Each resumed session hands the client a new ticket for the
+//! next reconnect, so a client that reconnects often can keep resuming the
+//! same base OTs. To bound how long a leaked ticket and client session stay
+//! useful, tickets also record when their base OTs were run, and a renewed
+//! ticket expires no later than the issuer's maximum age after that. Once the
+//! base OTs are too old, the client runs them afresh and starts a new chain of
+//! tickets. A resumed session never uses the saved seeds directly.
+//! Both parties pick a fresh nonce, and every seed is re-keyed with a hash of
+//! the two nonces. This keeps the PRG outputs of different sessions
+//! independent, even if an issuer that has restarted and forgotten its
+//! redeemed tickets accepts one a second time.
+//!
+//! `connect` and `accept` run the client and server sides of a connection.
+//! They resume when they can and fall back to fresh base OTs otherwise. Note
+//! that a `ClientSession` holds the client's base OT outputs in the clear,
+//! so the client must store it securely.
+
+use crate::errors::Error;
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{
+    keyschedule::hmac,
+    utils::{self as scutils, constant_time_eq},
+    AbstractChannel,
+    Aes128,
+    Block,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const VERSION: u8 = 1;
+// The header holds the version, ticket id, the time the base OTs were run,
+// and the expiry time.
+const HEADER_SIZE: usize = 1 + 16 + 8 + 8;
+const TAG_SIZE: usize = 32;
+// Large enough for the state of an OT extension with 128 base OTs.
+const MAX_TICKET_SIZE: usize = 1 << 16;
+
+/// How long a `TicketIssuer` keeps renewing tickets for the same base OTs,
+/// unless configured otherwise.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// OT extensions whose base OTs can be saved and resumed.
+///
+/// Only semi-honest extensions implement this: a resumed session reuses the
+/// sender's base OT choices, which a malicious receiver could learn across
+/// sessions.
+pub trait Resumable: Sized {
+    /// Run the base OTs, as `init` does, also returning their outputs so
+    /// that a later session can skip them.
+    fn init_resumable<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(Self, BaseOtState), Error>;
+    /// Start a new session from saved base OT outputs, re-keyed with `nonce`.
+    /// Both parties must use the same nonce.
+    fn resume(state: &BaseOtState, nonce: Block) -> Result<Self, Error>;
+}
+
+/// The outputs of the base OTs run by one party of an OT extension.
+#[derive(Clone, PartialEq, Eq)]
+pub enum BaseOtState {
+    /// The state of an OT extension sender, which acts as the base OT
+    /// receiver.
+    Sender {
+        /// The choice bits of the base OTs.
+        delta: Block,
+        /// The seed received in each base OT.
+        seeds: Vec<Block>,
+    },
+    /// The state of an OT extension receiver, which acts as the base OT
+    /// sender.
+    Receiver {
+        /// The pair of seeds sent in each base OT.
+        seeds: Vec<(Block, Block)>,
+    },
+}
+
+impl std::fmt::Debug for BaseOtState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BaseOtState::Sender { seeds, .. } => write!(f, "Sender({} base OTs)", seeds.len()),
+            BaseOtState::Receiver { seeds } => write!(f, "Receiver({} base OTs)", seeds.len()),
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for BaseOtState {
+    fn drop(&mut self) {
+        match self {
+            BaseOtState::Sender { delta, seeds } => {
+                scutils::erase(delta);
+                scutils::erase(seeds);
+            }
+            BaseOtState::Receiver { seeds } => scutils::erase(seeds),
+        }
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::InvalidTicket(msg.to_string())
+}
+
+// Reads fields off the front of a byte string.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < n {
+            return Err(invalid("truncated"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn block(&mut self) -> Result<Block, Error> {
+        let bytes: [u8; 16] = self.take(16)?.try_into().unwrap();
+        Ok(Block::from(bytes))
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("trailing bytes"))
+        }
+    }
+}
+
+impl BaseOtState {
+    /// Re-key every seed with `nonce`.
+    pub fn rekey(&self, nonce: Block) -> Self {
+        let rekey = |seed: &Block| Aes128::new(*seed).encrypt(nonce);
+        match self {
+            BaseOtState::Sender { delta, seeds } => BaseOtState::Sender {
+                delta: *delta,
+                seeds: seeds.iter().map(rekey).collect(),
+            },
+            BaseOtState::Receiver { seeds } => BaseOtState::Receiver {
+                seeds: seeds
+                    .iter()
+                    .map(|(k0, k1)| (rekey(k0), rekey(k1)))
+                    .collect(),
+            },
+        }
+    }
+
+    /// Serialize the state.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            BaseOtState::Sender { delta, seeds } => {
+                bytes.push(0);
+                bytes.extend_from_slice(delta.as_ref());
+                bytes.extend_from_slice(&(seeds.len() as u64).to_le_bytes());
+                for seed in seeds.iter() {
+                    bytes.extend_from_slice(seed.as_ref());
+                }
+            }
+            BaseOtState::Receiver { seeds } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(seeds.len() as u64).to_le_bytes());
+                for (k0, k1) in seeds.iter() {
+                    bytes.extend_from_slice(k0.as_ref());
+                    bytes.extend_from_slice(k1.as_ref());
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Deserialize a state written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader(bytes);
+        let state = match reader.u8()? {
+            0 => {
+                let delta = reader.block()?;
+                let n = reader.u64()? as usize;
+                if n > bytes.len() / 16 {
+                    return Err(invalid("truncated"));
+                }
+                let seeds = (0..n)
+                    .map(|_| reader.block())
+                    .collect::<Result<Vec<_>, _>>()?;
+                BaseOtState::Sender { delta, seeds }
+            }
+            1 => {
+                let n = reader.u64()? as usize;
+                if n > bytes.len() / 32 {
+                    return Err(invalid("truncated"));
+                }
+                let seeds = (0..n)
+                    .map(|_| Ok((reader.block()?, reader.block()?)))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                BaseOtState::Receiver { seeds }
+            }
+            _ => return Err(invalid("unknown state type")),
+        };
+        reader.finish()?;
+        Ok(state)
+    }
+}
+
+/// An encrypted and authenticated copy of a server's base OT state, which is
+/// opaque to the client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ticket(Vec<u8>);
+
+impl Ticket {
+    /// Wrap a ticket previously returned by `as_bytes`.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Ticket(bytes)
+    }
+
+    /// Return the ticket's encoding.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Return the time at which the ticket expires. This is read from the
+    /// ticket without checking its authenticity, and is meant for clients
+    /// deciding whether a ticket is still worth presenting.
+    pub fn expires(&self) -> Option<SystemTime> {
+        let mut reader = Reader(&self.0);
+        reader.take(1 + 16 + 8).ok()?;
+        let expires = reader.u64().ok()?;
+        Some(UNIX_EPOCH + Duration::from_millis(expires))
+    }
+
+    fn write<C: AbstractChannel>(&self, channel: &mut C) -> Result<(), Error> {
+        channel.write_usize(self.0.len())?;
+        channel.write_bytes(&self.0)?;
+        Ok(())
+    }
+
+    fn read<C: AbstractChannel>(channel: &mut C) -> Result<Self, Error> {
+        let len = channel.read_usize()?;
+        if len > MAX_TICKET_SIZE {
+            return Err(invalid("too large"));
+        }
+        Ok(Ticket(channel.read_vec(len)?))
+    }
+}
+
+// Milliseconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Issues and redeems tickets on behalf of a server.
+pub struct TicketIssuer {
+    enc: Aes128,
+    mac_key: [u8; 16],
+    lifetime: Duration,
+    max_age: Duration,
+    // The ids of redeemed tickets, with their expiry times.
+    redeemed: HashMap<Block, u64>,
+}
+
+impl TicketIssuer {
+    /// Make an issuer from a 256-bit secret `key`, whose tickets are valid for
+    /// `lifetime`, and which renews tickets for the same base OTs for up to
+    /// `DEFAULT_MAX_AGE`. Servers which restart, or share tickets among
+    /// several machines, must use the same key throughout.
+    pub fn new(key: [u8; 32], lifetime: Duration) -> Self {
+        let enc_key: [u8; 16] = key[..16].try_into().unwrap();
+        let mac_key: [u8; 16] = key[16..].try_into().unwrap();
+        Self {
+            enc: Aes128::new(Block::from(enc_key)),
+            mac_key,
+            lifetime,
+            max_age: DEFAULT_MAX_AGE,
+            redeemed: HashMap::new(),
+        }
+    }
+
+    /// Stop renewing tickets for base OTs run more than `max_age` ago, so
+    /// that clients must run them afresh.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Make an issuer with a random key.
+    pub fn random<RNG: CryptoRng + Rng>(rng: &mut RNG, lifetime: Duration) -> Self {
+        Self::new(rng.gen(), lifetime)
+    }
+
+    fn apply_keystream(&self, id: Block, data: &mut [u8]) {
+        let id = u128::from(id);
+        for (i, chunk) in data.chunks_mut(16).enumerate() {
+            let pad = self.enc.encrypt(Block::from(id.wrapping_add(i as u128)));
+            for (b, p) in chunk.iter_mut().zip(pad.as_ref()) {
+                *b ^= p;
+            }
+        }
+    }
+
+    /// Seal `state`, the outputs of base OTs which have just been run, into a
+    /// new ticket.
+    pub fn issue<RNG: CryptoRng + Rng>(&self, state: &BaseOtState, rng: &mut RNG) -> Ticket {
+        self.issue_at(state, now(), rng)
+    }
+
+    // Seal `state`, the outputs of base OTs run at time `born`, into a new
+    // ticket.
+    fn issue_at<RNG: CryptoRng + Rng>(
+        &self,
+        state: &BaseOtState,
+        born: u64,
+        rng: &mut RNG,
+    ) -> Ticket {
+        let id = rng.gen::<Block>();
+        let expires = now()
+            .saturating_add(self.lifetime.as_millis() as u64)
+            .min(born.saturating_add(self.max_age.as_millis() as u64));
+        let mut bytes = Vec::new();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(id.as_ref());
+        bytes.extend_from_slice(&born.to_le_bytes());
+        bytes.extend_from_slice(&expires.to_le_bytes());
+        let mut body = state.to_bytes();
+        self.apply_keystream(id, &mut body);
+        bytes.extend_from_slice(&body);
+        let tag = hmac(&self.mac_key, &[&bytes]);
+        bytes.extend_from_slice(&tag);
+        Ticket(bytes)
+    }
+
+    /// Check and decrypt `ticket`, returning the state sealed in it. Fails if
+    /// the ticket was not issued with our key, has expired, or was already
+    /// redeemed.
+    pub fn redeem(&mut self, ticket: &Ticket) -> Result<BaseOtState, Error> {
+        self.open(ticket).map(|(state, _)| state)
+    }
+
+    // As `redeem`, also returning when the base OTs were run.
+    fn open(&mut self, ticket: &Ticket) -> Result<(BaseOtState, u64), Error> {
+        let bytes = ticket.as_bytes();
+        if bytes.len() < HEADER_SIZE + TAG_SIZE {
+            return Err(invalid("truncated"));
+        }
+        let (data, tag) = bytes.split_at(bytes.len() - TAG_SIZE);
+        if !constant_time_eq(&hmac(&self.mac_key, &[data]), tag) {
+            return Err(invalid("authentication failed"));
+        }
+        let mut reader = Reader(data);
+        if reader.u8()? != VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let id = reader.block()?;
+        let born = reader.u64()?;
+        let expires = reader.u64()?;
+        let now = now();
+        if now >= expires {
+            return Err(invalid("expired"));
+        }
+        // Forget tickets which would now be rejected as expired anyway.
+        self.redeemed.retain(|_, expires| now < *expires);
+        if self.redeemed.contains_key(&id) {
+            return Err(invalid("already redeemed"));
+        }
+        let mut body = reader.0.to_vec();
+        self.apply_keystream(id, &mut body);
+        let state = BaseOtState::from_bytes(&body);
+        scutils::erase(&mut body);
+        let state = state?;
+        self.redeemed.insert(id, expires);
+        Ok((state, born))
+    }
+}
+
+// The keys are left out.
+impl std::fmt::Debug for TicketIssuer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TicketIssuer")
+            .field("lifetime", &self.lifetime)
+            .field("max_age", &self.max_age)
+            .field("nredeemed", &self.redeemed.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for TicketIssuer {
+    fn drop(&mut self) {
+        scutils::erase(&mut self.enc);
+        scutils::erase(&mut self.mac_key);
+    }
+}
+
+/// What a client keeps between connections to resume its session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientSession {
+    state: BaseOtState,
+    ticket: Ticket,
+}
+
+impl ClientSession {
+    /// Return the ticket to present on the next reconnect.
+    #[inline]
+    pub fn ticket(&self) -> &Ticket {
+        &self.ticket
+    }
+
+    /// Return whether the session's ticket has expired, in which case
+    /// `connect` runs the base OTs afresh rather than present it.
+    pub fn is_expired(&self) -> bool {
+        self.ticket
+            .expires()
+            .is_none_or(|expires| SystemTime::now() >= expires)
+    }
+
+    /// Serialize the session, including the client's base OT outputs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut state = self.state.to_bytes();
+        let mut bytes = Vec::with_capacity(8 + state.len() + self.ticket.0.len());
+        bytes.extend_from_slice(&(state.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&state);
+        scutils::erase(&mut state);
+        bytes.extend_from_slice(&self.ticket.0);
+        bytes
+    }
+
+    /// Deserialize a session written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader(bytes);
+        let len = reader.u64()? as usize;
+        let state = BaseOtState::from_bytes(reader.take(len)?)?;
+        let ticket = Ticket(reader.0.to_vec());
+        Ok(Self { state, ticket })
+    }
+}
+
+// The nonce a resumed session re-keys its seeds with. Hashing in the client's
+// fresh nonce stops the server from forcing a nonce used before.
+fn session_nonce(client: Block, server: Block) -> Block {
+    let digest = Sha256::new()
+        .chain(b"ocelot session resumption")
+        .chain(client)
+        .chain(server)
+        .result();
+    Block::try_from_slice(&digest[..16]).unwrap()
+}
+
+/// Connect to a server as the client of an OT extension.
+///
+/// If `session` is given and has not expired, presents its ticket to resume
+/// the session without running base OTs; otherwise, or if the server rejects
+/// the ticket, runs the base OTs afresh. Returns the OT extension object along
+/// with the session to resume from on the next connection.
+pub fn connect<OT: Resumable, C: AbstractChannel, RNG: CryptoRng + Rng>(
+    channel: &mut C,
+    session: Option<ClientSession>,
+    rng: &mut RNG,
+) -> Result<(OT, ClientSession), Error> {
+    let resumed = match session.filter(|session| !session.is_expired()) {
+        Some(session) => {
+            let ours = rng.gen::<Block>();
+            channel.write_bool(true)?;
+            channel.write_block(&ours)?;
+            session.ticket.write(channel)?;
+            channel.flush()?;
+            if channel.read_bool()? {
+                let theirs = channel.read_block()?;
+                let ot = OT::resume(&session.state, session_nonce(ours, theirs))?;
+                Some((ot, session.state))
+            } else {
+                None
+            }
+        }
+        None => {
+            channel.write_bool(false)?;
+            channel.flush()?;
+            None
+        }
+    };
+    let (ot, state) = match resumed {
+        Some(resumed) => resumed,
+        None => OT::init_resumable(channel, rng)?,
+    };
+    let ticket = Ticket::read(channel)?;
+    Ok((ot, ClientSession { state, ticket }))
+}
+
+/// Accept a connection from a client of an OT extension, resuming its
+/// session if it presents a valid ticket for the same kind of OT extension
+/// object, and running the base OTs afresh otherwise. Either way, sends the
+/// client a new ticket, which expires no later than the issuer's maximum age
+/// after the base OTs were run.
+pub fn accept<OT: Resumable, C: AbstractChannel, RNG: CryptoRng + Rng>(
+    channel: &mut C,
+    issuer: &mut TicketIssuer,
+    rng: &mut RNG,
+) -> Result<OT, Error> {
+    let resumed = if channel.read_bool()? {
+        let theirs = channel.read_block()?;
+        let ticket = Ticket::read(channel)?;
+        let ours = rng.gen::<Block>();
+        let nonce = session_nonce(theirs, ours);
+        let resumed = issuer
+            .open(&ticket)
+            .and_then(|(state, born)| Ok((OT::resume(&state, nonce)?, state, born)));
+        channel.write_bool(resumed.is_ok())?;
+        if resumed.is_ok() {
+            channel.write_block(&ours)?;
+        }
+        channel.flush()?;
+        resumed.ok()
+    } else {
+        None
+    };
+    let (ot, state, born) = match resumed {
+        Some(resumed) => resumed,
+        None => {
+            let (ot, state) = OT::init_resumable(channel, rng)?;
+            (ot, state, now())
+        }
+    };
+    issuer.issue_at(&state, born, rng).write(channel)?;
+    channel.flush()?;
+    Ok(ot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ot::{
+        AlszReceiver,
+        AlszSender,
+        Receiver as OtReceiver,
+        Sender as OtSender,
+    };
+    use scuttlebutt::{AesRng, Channel, TrackChannel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+        sync::{Arc, Mutex},
+    };
+
+    const NINPUTS: usize = 1 << 8;
+
+    // Connect a client OT receiver to a server OT sender, and check that an OT
+    // goes through. Returns the client's new session and the number of
+    // kilobits it exchanged while connecting.
+    fn session<S, R>(
+        issuer: &Arc<Mutex<TicketIssuer>>,
+        session: Option<ClientSession>,
+    ) -> (ClientSession, f64)
+    where
+        S: OtSender<Msg = Block> + Resumable,
+        R: OtReceiver<Msg = Block> + Resumable,
+    {
+        let m0s = (0..NINPUTS).map(|_| rand::random()).collect::<Vec<Block>>();
+        let m1s = (0..NINPUTS).map(|_| rand::random()).collect::<Vec<Block>>();
+        let bs = (0..NINPUTS).map(|_| rand::random()).collect::<Vec<bool>>();
+        let inputs = m0s
+            .iter()
+            .cloned()
+            .zip(m1s.iter().cloned())
+            .collect::<Vec<_>>();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let issuer = issuer.clone();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut ot =
+                accept::<S, _, _>(&mut channel, &mut issuer.lock().unwrap(), &mut rng).unwrap();
+            ot.send(&mut channel, &inputs, &mut rng).unwrap();
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = TrackChannel::new(reader, writer);
+        let (mut ot, session) = connect::<R, _, _>(&mut channel, session, &mut rng).unwrap();
+        let kilobits = channel.total_kilobits();
+        let results = ot.receive(&mut channel, &bs, &mut rng).unwrap();
+        handle.join().unwrap();
+        for j in 0..NINPUTS {
+            assert_eq!(results[j], if bs[j] { m1s[j] } else { m0s[j] });
+        }
+        (session, kilobits)
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut rng = AesRng::new();
+        let issuer = Arc::new(Mutex::new(TicketIssuer::random(
+            &mut rng,
+            Duration::from_secs(60),
+        )));
+        let (first, fresh) = session::<AlszSender, AlszReceiver>(&issuer, None);
+        let (second, resumed) = session::<AlszSender, AlszReceiver>(&issuer, Some(first.clone()));
+        assert!(resumed < fresh / 2.0);
+        assert_ne!(first.ticket(), second.ticket());
+        // The client's state survives serialization.
+        let second = ClientSession::from_bytes(&second.to_bytes()).unwrap();
+        let (_, resumed) = session::<AlszSender, AlszReceiver>(&issuer, Some(second));
+        assert!(resumed < fresh / 2.0);
+        // A replayed ticket is rejected, and we fall back to fresh base OTs.
+        let (_, replayed) = session::<AlszSender, AlszReceiver>(&issuer, Some(first));
+        assert!(replayed > fresh);
+    }
+
+    #[test]
+    fn test_max_age() {
+        let mut rng = AesRng::new();
+        let issuer = Arc::new(Mutex::new(
+            TicketIssuer::random(&mut rng, Duration::from_secs(60))
+                .with_max_age(Duration::from_secs(30)),
+        ));
+        let (first, fresh) = session::<AlszSender, AlszReceiver>(&issuer, None);
+        let (second, resumed) = session::<AlszSender, AlszReceiver>(&issuer, Some(first.clone()));
+        assert!(resumed < fresh / 2.0);
+        // Renewing a ticket does not extend the life of its base OTs.
+        assert_eq!(second.ticket().expires(), first.ticket().expires());
+        assert!(!second.is_expired());
+        // Once the base OTs are too old, the client runs them afresh without
+        // presenting its ticket.
+        let issuer = Arc::new(Mutex::new(
+            TicketIssuer::random(&mut rng, Duration::from_secs(60))
+                .with_max_age(Duration::from_millis(0)),
+        ));
+        let (stale, _) = session::<AlszSender, AlszReceiver>(&issuer, None);
+        assert!(stale.is_expired());
+        let (_, skipped) = session::<AlszSender, AlszReceiver>(&issuer, Some(stale));
+        assert_eq!(skipped, fresh);
+    }
+
+    #[test]
+    fn test_redeem() {
+        let mut rng = AesRng::new();
+        let state = BaseOtState::Receiver {
+            seeds: (0..128).map(|_| (rng.gen(), rng.gen())).collect(),
+        };
+        let mut issuer = TicketIssuer::random(&mut rng, Duration::from_secs(60));
+        let ticket = issuer.issue(&state, &mut rng);
+        // Tickets are encrypted.
+        let plaintext = state.to_bytes();
+        assert!(!ticket
+            .as_bytes()
+            .windows(plaintext.len())
+            .any(|w| w == &plaintext[..]));
+        // Tickets are authenticated.
+        let mut forged = ticket.as_bytes().to_vec();
+        forged[HEADER_SIZE] ^= 1;
+        assert!(issuer.redeem(&Ticket::from_bytes(forged)).is_err());
+        let mut other = TicketIssuer::random(&mut rng, Duration::from_secs(60));
+        assert!(other.redeem(&ticket).is_err());
+        // Tickets can be redeemed once.
+        assert_eq!(issuer.redeem(&ticket).unwrap(), state);
+        assert!(issuer.redeem(&ticket).is_err());
+        // Tickets expire.
+        let mut expired = TicketIssuer::random(&mut rng, Duration::from_millis(0));
+        let ticket = expired.issue(&state, &mut rng);
+        assert!(expired.redeem(&ticket).is_err());
+    }
+
+    #[test]
+    fn test_rekey() {
+        let mut rng = AesRng::new();
+        let delta = rng.gen::<Block>();
+        let pairs = (0..128)
+            .map(|_| (rng.gen::<Block>(), rng.gen::<Block>()))
+            .collect::<Vec<_>>();
+        let bits = crate::utils::u8vec_to_boolvec(delta.as_ref());
+        let chosen = pairs
+            .iter()
+            .zip(bits.iter())
+            .map(|((k0, k1), b)| if *b { *k1 } else { *k0 })
+            .collect();
+        let sender = BaseOtState::Sender {
+            delta,
+            seeds: chosen,
+        };
+        let receiver = BaseOtState::Receiver { seeds: pairs };
+        let nonce = rng.gen::<Block>();
+        // Re-keying preserves the base OT correlation, and changes every seed.
+        match (&sender.rekey(nonce), &receiver.rekey(nonce)) {
+            (BaseOtState::Sender { seeds: s, .. }, BaseOtState::Receiver { seeds: r }) => {
+                for ((k, (k0, k1)), b) in s.iter().zip(r.iter()).zip(bits.iter()) {
+                    assert_eq!(*k, if *b { *k1 } else { *k0 });
+                }
+            }
+            _ => unreachable!(),
+        }
+        assert_ne!(sender.rekey(nonce), sender);
+        // States only resume the kind of OT extension object they came from.
+        assert!(AlszSender::resume(&sender, nonce).is_ok());
+        assert!(AlszReceiver::resume(&sender, nonce).is_err());
+        assert!(AlszSender::resume(&receiver, nonce).is_err());
+        assert_eq!(
+            BaseOtState::from_bytes(&receiver.to_bytes()).unwrap(),
+            receiver
+        );
+        assert!(BaseOtState::from_bytes(&receiver.to_bytes()[1..]).is_err());
+    }
+}
@@ -4,8 +4,24 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
+use crate::errors::Error;
 use scuttlebutt::Block;
 
+// Check an internal invariant, with `msg` describing the condition. Without
+// the `hardened` feature a violation panics, as an `assert!` would; with it,
+// the violation is returned as `Error::InternalInvariant` so that a
+// misbehaving peer cannot abort the process.
+#[inline]
+pub fn invariant(cond: bool, msg: &str) -> Result<(), Error> {
+    if cond {
+        Ok(())
+    } else if cfg!(feature = "hardened") {
+        Err(Error::InternalInvariant(msg.to_string()))
+    } else {
+        panic!("internal invariant violated: {}", msg)
+    }
+}
+
 #[inline]
 pub fn transpose(m: &[u8], nrows: usize, ncols: usize) -> Vec<u8> {
     let mut m_ = vec![0u8; nrows * ncols / 8];
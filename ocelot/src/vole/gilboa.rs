@@ -0,0 +1,207 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Implementation of Gilboa's semi-honest VOLE from oblivious transfer (cf.
+//! <https://link.springer.com/chapter/10.1007/3-540-48405-1_8>).
+//!
+//! The product `u·Δ` is split over the bits `Δ_j` of the sender's key, for
+//! each of which the receiver offers `(r_j, r_j + u·e_j)` by OT, with `e_j`
+//! the `j`th basis element of the field and `r_j` random. The sender picks
+//! with `Δ_j`, so that the messages it gets add up to `Σ r_j + u·Δ`. As `Δ`
+//! never changes, the sender's choice bits are the same for every
+//! correlation, which suits OT extension.
+//!
+//! Each correlation takes one OT per bit of the field, 128 for `Gf128`, so
+//! the protocol only serves to draw the initial reserve of an extension such
+//! as `wykw`.
+
+use crate::{
+    errors::Error,
+    lpn::Element,
+    ot::{Receiver as OtReceiver, Sender as OtSender},
+    vole::{Receiver as VoleReceiver, Sender as VoleSender},
+};
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{
+    field::FiniteField,
+    utils as scutils,
+    AbstractChannel,
+    Block,
+    SemiHonest,
+};
+use std::marker::PhantomData;
+
+/// Gilboa VOLE sender.
+pub struct Sender<OT: OtReceiver<Msg = Block> + SemiHonest, F: FiniteField + Element> {
+    ot: OT,
+    delta: F,
+    // The bits of `delta`, which are the OT choice bits.
+    bits: Vec<bool>,
+}
+
+/// Gilboa VOLE receiver.
+pub struct Receiver<OT: OtSender<Msg = Block> + SemiHonest, F: FiniteField + Element> {
+    _field: PhantomData<F>,
+    ot: OT,
+}
+
+// The key never shows up in debug output.
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, F: FiniteField + Element> std::fmt::Debug
+    for Sender<OT, F>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("gilboa::Sender").finish_non_exhaustive()
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + SemiHonest, F: FiniteField + Element> std::fmt::Debug
+    for Receiver<OT, F>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("gilboa::Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, F: FiniteField + Element> Sender<OT, F> {
+    // Run the protocol for `m` correlations, whatever the receiver's inputs.
+    fn run<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        m: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<F>, Error> {
+        let choices = (0..m)
+            .flat_map(|_| self.bits.iter().cloned())
+            .collect::<Vec<bool>>();
+        let ms = self.ot.receive(channel, &choices, rng)?;
+        Ok(ms
+            .chunks(F::NBITS)
+            .map(|ms| -ms.iter().fold(F::ZERO, |sum, m| sum + F::from_block(*m)))
+            .collect())
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, F: FiniteField + Element> VoleSender
+    for Sender<OT, F>
+{
+    type Field = F;
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        let ot = OT::init(channel, rng)?;
+        let delta = F::random(rng);
+        let bits = (0..F::NBITS).map(|j| delta.bit(j)).collect();
+        Ok(Self { ot, delta, bits })
+    }
+
+    #[inline]
+    fn delta(&self) -> F {
+        self.delta
+    }
+
+    fn send_random<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        m: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<F>, Error> {
+        self.run(channel, m, rng)
+    }
+
+    fn send<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        m: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<F>, Error> {
+        self.run(channel, m, rng)
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + SemiHonest, F: FiniteField + Element> VoleReceiver
+    for Receiver<OT, F>
+{
+    type Field = F;
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        let ot = OT::init(channel, rng)?;
+        Ok(Self {
+            _field: PhantomData::<F>,
+            ot,
+        })
+    }
+
+    fn receive_random<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        m: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<(F, F)>, Error> {
+        let us = (0..m).map(|_| F::random(rng)).collect::<Vec<F>>();
+        let ws = self.receive(channel, &us, rng)?;
+        Ok(us.into_iter().zip(ws).collect())
+    }
+
+    fn receive<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        inputs: &[F],
+        rng: &mut RNG,
+    ) -> Result<Vec<F>, Error> {
+        let mut pairs = Vec::with_capacity(inputs.len() * F::NBITS);
+        let mut ws = Vec::with_capacity(inputs.len());
+        for u in inputs.iter() {
+            let mut sum = F::ZERO;
+            for j in 0..F::NBITS {
+                let r = F::random(rng);
+                sum = sum + r;
+                pairs.push((r.to_block(), (r + *u * F::basis(j)).to_block()));
+            }
+            ws.push(-sum);
+        }
+        self.ot.send(channel, &pairs, rng)?;
+        scutils::erase(&mut pairs);
+        Ok(ws)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, F: FiniteField + Element> Drop for Sender<OT, F> {
+    fn drop(&mut self) {
+        scutils::erase(&mut self.delta);
+        scutils::erase(&mut self.bits);
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, F: FiniteField + Element> std::fmt::Display
+    for Sender<OT, F>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Gilboa Sender")
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + SemiHonest, F: FiniteField + Element> std::fmt::Display
+    for Receiver<OT, F>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Gilboa Receiver")
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, F: FiniteField + Element> SemiHonest
+    for Sender<OT, F>
+{
+}
+impl<OT: OtSender<Msg = Block> + SemiHonest, F: FiniteField + Element> SemiHonest
+    for Receiver<OT, F>
+{
+}
@@ -0,0 +1,284 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Vector oblivious linear evaluation (VOLE) traits + instantiations.
+//!
+//! VOLE is the arithmetic analogue of correlated OT. Over a finite field `F`,
+//! the sender holds a global key `Δ`, and each VOLE correlation gives the
+//! sender a value `v` and the receiver a pair `(u, w)` with `w = v + u·Δ`.
+//! Random VOLE draws `u` at random, while chosen-input VOLE lets the receiver
+//! pick it, at the cost of sending one field element per correlation.
+//!
+//! This module provides traits for the sender and the receiver, generic over
+//! the fields of `scuttlebutt::field`, alongside two protocols:
+//!
+//! * `gilboa`: Gilboa's semi-honest VOLE from oblivious transfer, with one OT
+//!   per bit of `Δ` and correlation; the base protocol.
+//! * `wykw`: Weng-Yang-Katz-Wang semi-honest VOLE extension, which expands a
+//!   reserve of correlations with an LPN code and communication sublinear in
+//!   the number of correlations.
+
+pub mod gilboa;
+pub mod wykw;
+
+use crate::{
+    errors::Error,
+    lpn::Element,
+    ot::{AlszReceiver, AlszSender},
+};
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{field::FiniteField, AbstractChannel};
+
+/// Instantiation of the Gilboa VOLE sender, using ALSZ for the OTs.
+pub type GilboaSender<F> = gilboa::Sender<AlszReceiver, F>;
+/// Instantiation of the Gilboa VOLE receiver, using ALSZ for the OTs.
+pub type GilboaReceiver<F> = gilboa::Receiver<AlszSender, F>;
+/// Instantiation of the WYKW VOLE extension sender, using ALSZ for the OTs and
+/// Gilboa for the initial reserve.
+pub type WykwSender<F> = wykw::Sender<AlszSender, GilboaSender<F>>;
+/// Instantiation of the WYKW VOLE extension receiver, using ALSZ for the OTs
+/// and Gilboa for the initial reserve.
+pub type WykwReceiver<F> = wykw::Receiver<AlszReceiver, GilboaReceiver<F>>;
+
+/// Trait for VOLE from the sender's point-of-view.
+pub trait Sender
+where
+    Self: Sized,
+{
+    /// The field of the correlations.
+    type Field: FiniteField + Element;
+    /// Runs any one-time initialization, drawing the global key `Δ`.
+    fn init<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error>;
+    /// Returns the global key `Δ`.
+    fn delta(&self) -> Self::Field;
+    /// Returns the values `v` of `m` correlations, in each of which the
+    /// receiver gets a random `u` and `w = v + u·Δ`.
+    fn send_random<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        m: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<Self::Field>, Error>;
+    /// Returns the values `v` of `m` correlations with the `u`s of the
+    /// receiver's choosing. By default, derandomizes random correlations by
+    /// the difference between each `u` and the random one.
+    fn send<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        m: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<Self::Field>, Error> {
+        let vs = self.send_random(channel, m, rng)?;
+        let delta = self.delta();
+        vs.into_iter()
+            .map(|v| {
+                let d = Self::Field::from_block(channel.read_block()?);
+                Ok(v - d * delta)
+            })
+            .collect()
+    }
+}
+
+/// Trait for VOLE from the receiver's point-of-view.
+pub trait Receiver
+where
+    Self: Sized,
+{
+    /// The field of the correlations.
+    type Field: FiniteField + Element;
+    /// Runs any one-time initialization.
+    fn init<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error>;
+    /// Returns `m` correlations `(u, w)`, each with a random `u` and `w = v +
+    /// u·Δ` for the sender's `v`.
+    fn receive_random<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        m: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<(Self::Field, Self::Field)>, Error>;
+    /// Returns `w = v + u·Δ` for each `u` of `inputs`. By default,
+    /// derandomizes random correlations, as expected by `Sender::send`.
+    fn receive<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        inputs: &[Self::Field],
+        rng: &mut RNG,
+    ) -> Result<Vec<Self::Field>, Error> {
+        let correlations = self.receive_random(channel, inputs.len(), rng)?;
+        for (u, (r, _)) in inputs.iter().zip(correlations.iter()) {
+            channel.write_block(&(*u - *r).to_block())?;
+        }
+        channel.flush()?;
+        Ok(correlations.into_iter().map(|(_, w)| w).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lpn::LpnParams;
+    use scuttlebutt::{
+        field::{F61p, Gf128},
+        AesRng,
+        Channel,
+    };
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const T: usize = 1 << 10;
+
+    // Parameters small enough for tests, giving 3,552 correlations per
+    // iteration.
+    const PARAMS: LpnParams = LpnParams {
+        n: 4096,
+        k: 512,
+        t: 32,
+        d: 10,
+    };
+
+    fn check<F: FiniteField>(delta: F, vs: &[F], uws: &[(F, F)]) {
+        assert_eq!(vs.len(), uws.len());
+        for (v, (u, w)) in vs.iter().zip(uws.iter()) {
+            assert_eq!(*w, *v + *u * delta);
+        }
+    }
+
+    // Run `S` and `R` with the given initializations, checking both random
+    // and chosen-input correlations.
+    fn test_vole<S, R>(
+        init_sender: fn(&mut Channel<BufReader<UnixStream>, BufWriter<UnixStream>>) -> S,
+        init_receiver: fn(&mut Channel<BufReader<UnixStream>, BufWriter<UnixStream>>) -> R,
+    ) where
+        S: Sender + Send + 'static,
+        R: Receiver<Field = S::Field>,
+    {
+        let mut rng = AesRng::new();
+        let inputs = (0..T)
+            .map(|_| S::Field::random(&mut rng))
+            .collect::<Vec<_>>();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut vole = init_sender(&mut channel);
+            let random = vole.send_random(&mut channel, T, &mut rng).unwrap();
+            let chosen = vole.send(&mut channel, T, &mut rng).unwrap();
+            (vole.delta(), random, chosen)
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut vole = init_receiver(&mut channel);
+        let random = vole.receive_random(&mut channel, T, &mut rng).unwrap();
+        let chosen = vole.receive(&mut channel, &inputs, &mut rng).unwrap();
+        let (delta, vs, vs_chosen) = handle.join().unwrap();
+        check(delta, &vs, &random);
+        let chosen = inputs.into_iter().zip(chosen).collect::<Vec<_>>();
+        check(delta, &vs_chosen, &chosen);
+    }
+
+    fn test_gilboa<F: FiniteField + Element + 'static>() {
+        test_vole::<GilboaSender<F>, GilboaReceiver<F>>(
+            |channel| GilboaSender::init(channel, &mut AesRng::new()).unwrap(),
+            |channel| GilboaReceiver::init(channel, &mut AesRng::new()).unwrap(),
+        );
+    }
+
+    fn test_wykw<F: FiniteField + Element + 'static>() {
+        test_vole::<WykwSender<F>, WykwReceiver<F>>(
+            |channel| WykwSender::init_with_params(channel, &mut AesRng::new(), PARAMS).unwrap(),
+            |channel| WykwReceiver::init_with_params(channel, &mut AesRng::new(), PARAMS).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_gilboa_gf128() {
+        test_gilboa::<Gf128>();
+    }
+
+    #[test]
+    fn test_gilboa_f61p() {
+        test_gilboa::<F61p>();
+    }
+
+    #[test]
+    fn test_wykw_gf128() {
+        test_wykw::<Gf128>();
+    }
+
+    #[test]
+    fn test_wykw_f61p() {
+        test_wykw::<F61p>();
+    }
+
+    #[test]
+    fn test_wykw_params() {
+        let (sender, _receiver) = UnixStream::pair().unwrap();
+        let reader = BufReader::new(sender.try_clone().unwrap());
+        let writer = BufWriter::new(sender);
+        let mut channel = Channel::new(reader, writer);
+        let mut rng = AesRng::new();
+        // Neither a number of outputs which is not `t` times a power of two,
+        // nor an iteration using all it gives, is accepted.
+        for params in [
+            LpnParams {
+                n: PARAMS.n + 1,
+                ..PARAMS
+            },
+            LpnParams {
+                k: PARAMS.n - PARAMS.t,
+                ..PARAMS
+            },
+        ] {
+            assert!(WykwSender::<F61p>::init_with_params(&mut channel, &mut rng, params).is_err());
+            assert!(
+                WykwReceiver::<F61p>::init_with_params(&mut channel, &mut rng, params).is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_wykw_iterations() {
+        // Ask for more correlations than an iteration gives, so that the
+        // extension runs again from its own outputs.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut vole =
+                WykwSender::<F61p>::init_with_params(&mut channel, &mut rng, PARAMS).unwrap();
+            let vs = vole.send_random(&mut channel, 3 * T, &mut rng).unwrap();
+            let more = vole.send_random(&mut channel, 4 * T, &mut rng).unwrap();
+            (vole.delta(), vs, more)
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut vole =
+            WykwReceiver::<F61p>::init_with_params(&mut channel, &mut rng, PARAMS).unwrap();
+        let uws = vole.receive_random(&mut channel, 3 * T, &mut rng).unwrap();
+        let more = vole.receive_random(&mut channel, 4 * T, &mut rng).unwrap();
+        let (delta, vs, vs_more) = handle.join().unwrap();
+        check(delta, &vs, &uws);
+        check(delta, &vs_more, &more);
+        // The receiver's inputs look random, not sparse.
+        let zeros = uws.iter().filter(|(u, _)| *u == F61p::ZERO).count();
+        assert!(zeros < T / 100);
+    }
+}
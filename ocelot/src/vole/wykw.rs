@@ -0,0 +1,372 @@
+// -*- mode: rust; -*-
+//
+// This file is part of ocelot.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Implementation of the Weng-Yang-Katz-Wang VOLE extension protocol,
+//! semi-honest variant (cf. <https://eprint.iacr.org/2020/925>, Figures 3 and
+//! 4).
+//!
+//! The extension follows Ferret (see `ot::ferret`) over an arbitrary field.
+//! Each iteration turns `k + t` reserved correlations, for LPN parameters `(n,
+//! k, t)`, into `n` correlations: `t` single-point VOLEs, each a GGM tree of
+//! depth `h = log2(n / t)` punctured with `h` OTs, give a noise vector of
+//! regular weight `t` whose nonzero entries come from `t` reserved
+//! correlations, to which the LPN code adds the encoding of the other `k`.
+//! The first `k + t` outputs become the reserve of the next iteration, the
+//! rest are handed out. With `LpnParams::FERRET_SETUP` an iteration sends
+//! about half a megabyte for 612,000 correlations, where Gilboa's protocol
+//! sends several kilobytes per correlation over `Gf128`.
+//!
+//! The initial reserve comes from the base VOLE `V`, and the OTs puncturing
+//! the trees from `OT`. The LPN code is drawn by coin tossing. The protocol
+//! does not check that the receiver's punctured points are well formed, and
+//! so is only secure against semi-honest adversaries.
+
+use crate::{
+    errors::Error,
+    ggm::{GgmKey, PuncturedKey},
+    lpn::{LinearCode, LocalLinearCode, LpnParams},
+    ot::{Receiver as OtReceiver, Sender as OtSender},
+    vole::{Receiver as VoleReceiver, Sender as VoleSender},
+};
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{
+    cointoss,
+    field::FiniteField,
+    utils as scutils,
+    AbstractChannel,
+    Block,
+    SemiHonest,
+};
+use std::marker::PhantomData;
+
+// Check that `n` is `t` times a power of two, that the code can be sampled,
+// and that an iteration gives more correlations than it uses.
+fn check_params(params: &LpnParams) -> Result<(), Error> {
+    let LpnParams { n, k, t, d } = *params;
+    if t == 0 || n % t != 0 || !(n / t).is_power_of_two() {
+        return Err(Error::Other(
+            "the number of outputs must be `t` times a power of two".to_string(),
+        ));
+    }
+    if k == 0 || k > u32::MAX as usize || d == 0 || n.checked_mul(d).is_none() {
+        return Err(Error::Other("invalid local linear code".to_string()));
+    }
+    match k.checked_add(t) {
+        Some(used) if used < n => Ok(()),
+        _ => Err(Error::Other(
+            "an iteration must give more correlations than it uses".to_string(),
+        )),
+    }
+}
+
+// The number of correlations an iteration for `params` uses.
+fn reserve_len(params: &LpnParams) -> usize {
+    params.k + params.t
+}
+
+// Bit `level` of `point` in a tree of depth `depth`, counting from the top.
+fn path_bit(point: usize, depth: usize, level: usize) -> bool {
+    (point >> (depth - 1 - level)) & 1 == 1
+}
+
+/// VOLE extension sender.
+pub struct Sender<OT: OtSender<Msg = Block> + SemiHonest, V: VoleSender + SemiHonest> {
+    _vole: PhantomData<V>,
+    ot: OT,
+    params: LpnParams,
+    code: LocalLinearCode,
+    delta: V::Field,
+    reserve: Vec<V::Field>,
+    // The correlations extended but not handed out yet.
+    buffer: Vec<V::Field>,
+}
+
+/// VOLE extension receiver.
+pub struct Receiver<OT: OtReceiver<Msg = Block> + SemiHonest, V: VoleReceiver + SemiHonest> {
+    _vole: PhantomData<V>,
+    ot: OT,
+    params: LpnParams,
+    code: LocalLinearCode,
+    reserve: Vec<(V::Field, V::Field)>,
+    buffer: Vec<(V::Field, V::Field)>,
+}
+
+// As for Ferret, only the parameters and the number of correlations at hand
+// are shown.
+impl<OT: OtSender<Msg = Block> + SemiHonest, V: VoleSender + SemiHonest> std::fmt::Debug
+    for Sender<OT, V>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("wykw::Sender")
+            .field("params", &self.params)
+            .field("buffered", &self.buffer.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, V: VoleReceiver + SemiHonest> std::fmt::Debug
+    for Receiver<OT, V>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("wykw::Receiver")
+            .field("params", &self.params)
+            .field("buffered", &self.buffer.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + SemiHonest, V: VoleSender + SemiHonest> Sender<OT, V> {
+    /// Run the initialization with LPN parameters `params`, drawing the
+    /// first reserve from `V`. Fails unless `params.n` is `params.t` times a
+    /// power of two and `params.k + params.t < params.n`.
+    pub fn init_with_params<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+        params: LpnParams,
+    ) -> Result<Self, Error> {
+        check_params(&params)?;
+        let nreserve = reserve_len(&params);
+        let seed = cointoss::send(channel, &[rng.gen()])?[0];
+        let ot = OT::init(channel, rng)?;
+        let mut vole = V::init(channel, rng)?;
+        let reserve = vole.send_random(channel, nreserve, rng)?;
+        Ok(Self {
+            _vole: PhantomData::<V>,
+            ot,
+            params,
+            code: params.local_linear_code(seed),
+            delta: vole.delta(),
+            reserve,
+            buffer: Vec::new(),
+        })
+    }
+
+    // Run one iteration, replacing the reserve and buffering the remaining
+    // outputs.
+    fn extend<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        let LpnParams { n, k, t, .. } = self.params;
+        let depth = self.params.tree_depth();
+        let reserve = std::mem::take(&mut self.reserve);
+        let (inputs, noise) = reserve.split_at(k);
+        let mut outputs = Vec::with_capacity(n);
+        let mut sums = Vec::with_capacity(t * depth);
+        let mut corrections = Vec::with_capacity(t);
+        for v in noise.iter() {
+            let key = GgmKey::gen(rng, depth);
+            let (leaves, level_sums) = key.expand_with_level_sums();
+            sums.extend(level_sums);
+            let start = outputs.len();
+            outputs.extend(leaves.into_iter().map(V::Field::from_block));
+            let sum = outputs[start..]
+                .iter()
+                .fold(V::Field::ZERO, |sum, leaf| sum + *leaf);
+            corrections.push(*v - sum);
+        }
+        self.ot.send(channel, &sums, rng)?;
+        scutils::erase(&mut sums);
+        for correction in corrections.iter() {
+            channel.write_block(&correction.to_block())?;
+        }
+        channel.flush()?;
+        self.code.encode_into(inputs, &mut outputs);
+        self.buffer = outputs.split_off(reserve_len(&self.params));
+        self.reserve = outputs;
+        Ok(())
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + SemiHonest, V: VoleSender + SemiHonest> VoleSender
+    for Sender<OT, V>
+{
+    type Field = V::Field;
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        Self::init_with_params(channel, rng, LpnParams::FERRET_SETUP)
+    }
+
+    #[inline]
+    fn delta(&self) -> V::Field {
+        self.delta
+    }
+
+    fn send_random<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        m: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<V::Field>, Error> {
+        let mut out = Vec::with_capacity(m);
+        while out.len() < m {
+            if self.buffer.is_empty() {
+                self.extend(channel, rng)?;
+            }
+            let take = self.buffer.len().min(m - out.len());
+            let at = self.buffer.len() - take;
+            out.extend(self.buffer.drain(at..));
+        }
+        Ok(out)
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, V: VoleReceiver + SemiHonest> Receiver<OT, V> {
+    /// Run the initialization with LPN parameters `params`, drawing the
+    /// first reserve from `V`. The parameters must be those of the sender.
+    pub fn init_with_params<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+        params: LpnParams,
+    ) -> Result<Self, Error> {
+        check_params(&params)?;
+        let nreserve = reserve_len(&params);
+        let seed = cointoss::receive(channel, &[rng.gen()])?[0];
+        let ot = OT::init(channel, rng)?;
+        let mut vole = V::init(channel, rng)?;
+        let reserve = vole.receive_random(channel, nreserve, rng)?;
+        Ok(Self {
+            _vole: PhantomData::<V>,
+            ot,
+            params,
+            code: params.local_linear_code(seed),
+            reserve,
+            buffer: Vec::new(),
+        })
+    }
+
+    fn extend<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        let LpnParams { n, k, t, .. } = self.params;
+        let depth = self.params.tree_depth();
+        let reserve = std::mem::take(&mut self.reserve);
+        let (inputs, noise) = reserve.split_at(k);
+        let points = (0..t)
+            .map(|_| rng.gen_range(0, 1 << depth))
+            .collect::<Vec<usize>>();
+        // Learn the level sums off the path to each point.
+        let mut choices = Vec::with_capacity(t * depth);
+        for &point in points.iter() {
+            choices.extend((0..depth).map(|level| !path_bit(point, depth, level)));
+        }
+        let mut sums = self.ot.receive(channel, &choices, rng)?;
+        let mut us = vec![V::Field::ZERO; n];
+        let mut ws = Vec::with_capacity(n);
+        for (tree, (&point, (u, w))) in points.iter().zip(noise.iter()).enumerate() {
+            let level_sums = (0..depth)
+                .map(|level| {
+                    let i = tree * depth + level;
+                    if choices[i] {
+                        (Block::default(), sums[i])
+                    } else {
+                        (sums[i], Block::default())
+                    }
+                })
+                .collect::<Vec<_>>();
+            let leaves = PuncturedKey::from_level_sums(point, &level_sums).expand();
+            let start = ws.len();
+            ws.extend(leaves.into_iter().map(V::Field::from_block));
+            // The punctured leaf is zero, so the sum of the leaves is the sum
+            // of the others.
+            let sum = ws[start..]
+                .iter()
+                .fold(V::Field::ZERO, |sum, leaf| sum + *leaf);
+            let correction = V::Field::from_block(channel.read_block()?);
+            ws[start + point] = *w - correction - sum;
+            us[start + point] = *u;
+        }
+        scutils::erase(&mut sums);
+        let (xs, wxs): (Vec<V::Field>, Vec<V::Field>) = inputs.iter().cloned().unzip();
+        self.code.encode_into(&xs, &mut us);
+        self.code.encode_into(&wxs, &mut ws);
+        let mut outputs = us.into_iter().zip(ws).collect::<Vec<_>>();
+        self.buffer = outputs.split_off(reserve_len(&self.params));
+        self.reserve = outputs;
+        Ok(())
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, V: VoleReceiver + SemiHonest> VoleReceiver
+    for Receiver<OT, V>
+{
+    type Field = V::Field;
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        Self::init_with_params(channel, rng, LpnParams::FERRET_SETUP)
+    }
+
+    fn receive_random<C: AbstractChannel, RNG: CryptoRng + Rng>(
+        &mut self,
+        channel: &mut C,
+        m: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<(V::Field, V::Field)>, Error> {
+        let mut out = Vec::with_capacity(m);
+        while out.len() < m {
+            if self.buffer.is_empty() {
+                self.extend(channel, rng)?;
+            }
+            let take = self.buffer.len().min(m - out.len());
+            let at = self.buffer.len() - take;
+            out.extend(self.buffer.drain(at..));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<OT: OtSender<Msg = Block> + SemiHonest, V: VoleSender + SemiHonest> Drop for Sender<OT, V> {
+    fn drop(&mut self) {
+        scutils::erase(&mut self.delta);
+        scutils::erase(&mut self.reserve);
+        scutils::erase(&mut self.buffer);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, V: VoleReceiver + SemiHonest> Drop
+    for Receiver<OT, V>
+{
+    fn drop(&mut self) {
+        scutils::erase(&mut self.reserve);
+        scutils::erase(&mut self.buffer);
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + SemiHonest, V: VoleSender + SemiHonest> std::fmt::Display
+    for Sender<OT, V>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "WYKW Sender")
+    }
+}
+
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, V: VoleReceiver + SemiHonest> std::fmt::Display
+    for Receiver<OT, V>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "WYKW Receiver")
+    }
+}
+
+impl<OT: OtSender<Msg = Block> + SemiHonest, V: VoleSender + SemiHonest> SemiHonest
+    for Sender<OT, V>
+{
+}
+impl<OT: OtReceiver<Msg = Block> + SemiHonest, V: VoleReceiver + SemiHonest> SemiHonest
+    for Receiver<OT, V>
+{
+}
@@ -0,0 +1,251 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! The versioned message frames with which `psi` opens and closes sessions.
+//!
+//! Every frame is laid out on the wire as
+//!
+//! ```text
+//! +---------+------+----------------+-------------------+
+//! | version | kind | length (u32le) | payload (length)  |
+//! |  1 byte |1 byte|    4 bytes     |                   |
+//! +---------+------+----------------+-------------------+
+//! ```
+//!
+//! This layout, the values of `FRAME_VERSION`, and the discriminants of
+//! `FrameKind` are part of the stable wire protocol: changing any of them is a
+//! breaking change, and is pinned by the tests at the bottom of this file.
+//! New frame kinds may be added without bumping `FRAME_VERSION`, as peers
+//! reject kinds they do not know with an error rather than misparsing them.
+
+use scuttlebutt::AbstractChannel;
+use std::io::{Error, ErrorKind, Result};
+
+/// The current version of the frame layout.
+pub const FRAME_VERSION: u8 = 1;
+
+/// The size of a frame header in bytes.
+pub const FRAME_HEADER_SIZE: usize = 6;
+
+/// The maximum payload length accepted when reading a frame.
+pub const MAX_FRAME_PAYLOAD: usize = 1 << 30;
+
+/// The type of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameKind {
+    /// Opening frame of a session, carrying the sender's frame version.
+    Hello = 0x01,
+    /// Protocol data.
+    Data = 0x02,
+    /// Signals that the sender is done with the session.
+    Close = 0x03,
+    /// Signals an error on the sender's side; the payload is a UTF-8 message.
+    Error = 0x04,
+}
+
+impl FrameKind {
+    /// Convert a byte into a `FrameKind`, if it is a known kind.
+    pub fn from_u8(b: u8) -> Option<Self> {
+        match b {
+            0x01 => Some(FrameKind::Hello),
+            0x02 => Some(FrameKind::Data),
+            0x03 => Some(FrameKind::Close),
+            0x04 => Some(FrameKind::Error),
+            _ => None,
+        }
+    }
+}
+
+/// A single message frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The type of this frame.
+    pub kind: FrameKind,
+    /// The frame payload.
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Make a new frame of type `kind` carrying `payload`.
+    pub fn new(kind: FrameKind, payload: Vec<u8>) -> Self {
+        Self { kind, payload }
+    }
+
+    /// Serialize the frame into its wire representation.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FRAME_HEADER_SIZE + self.payload.len());
+        bytes.push(FRAME_VERSION);
+        bytes.push(self.kind as u8);
+        bytes.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Write the frame to `channel`.
+    pub fn write<C: AbstractChannel>(&self, channel: &mut C) -> Result<()> {
+        if self.payload.len() > MAX_FRAME_PAYLOAD {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "frame payload too large",
+            ));
+        }
+        channel.write_bytes(&self.to_bytes())
+    }
+
+    /// Read a frame from `channel`.
+    pub fn read<C: AbstractChannel>(channel: &mut C) -> Result<Self> {
+        let mut header = [0u8; FRAME_HEADER_SIZE];
+        channel.read_bytes(&mut header)?;
+        if header[0] != FRAME_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported frame version {}", header[0]),
+            ));
+        }
+        let kind = FrameKind::from_u8(header[1]).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown frame kind {:#04x}", header[1]),
+            )
+        })?;
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&header[2..6]);
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_FRAME_PAYLOAD {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "frame payload too large",
+            ));
+        }
+        let payload = channel.read_vec(len)?;
+        Ok(Self { kind, payload })
+    }
+
+    /// Read a frame from `channel`, failing if it is not of type `kind`.
+    ///
+    /// An `Error` frame from the peer is turned into an `io::Error` carrying
+    /// the peer's message.
+    pub fn read_expecting<C: AbstractChannel>(channel: &mut C, kind: FrameKind) -> Result<Self> {
+        let frame = Self::read(channel)?;
+        if frame.kind == kind {
+            Ok(frame)
+        } else if frame.kind == FrameKind::Error {
+            Err(Error::new(
+                ErrorKind::ConnectionAborted,
+                String::from_utf8_lossy(&frame.payload).into_owned(),
+            ))
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("expected {:?} frame, got {:?}", kind, frame.kind),
+            ))
+        }
+    }
+}
+
+/// Exchange `Hello` frames with the other party, checking that both sides
+/// speak the same frame version. Returns the peer's version.
+pub fn hello<C: AbstractChannel>(channel: &mut C) -> Result<u8> {
+    Frame::new(FrameKind::Hello, vec![FRAME_VERSION]).write(channel)?;
+    channel.flush()?;
+    let frame = Frame::read_expecting(channel, FrameKind::Hello)?;
+    match frame.payload.as_slice() {
+        [v] if *v == FRAME_VERSION => Ok(*v),
+        [v] => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("peer speaks frame version {}", v),
+        )),
+        _ => Err(Error::new(ErrorKind::InvalidData, "malformed hello frame")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scuttlebutt::Channel;
+    use std::{
+        io::{BufReader, BufWriter, Cursor},
+        os::unix::net::UnixStream,
+    };
+
+    fn roundtrip(bytes: Vec<u8>) -> Result<Frame> {
+        let mut channel = Channel::new(Cursor::new(bytes), Vec::new());
+        Frame::read(&mut channel)
+    }
+
+    #[test]
+    fn test_pinned_kinds() {
+        assert_eq!(FRAME_VERSION, 1);
+        assert_eq!(FrameKind::Hello as u8, 0x01);
+        assert_eq!(FrameKind::Data as u8, 0x02);
+        assert_eq!(FrameKind::Close as u8, 0x03);
+        assert_eq!(FrameKind::Error as u8, 0x04);
+        for b in 0..=255u8 {
+            if let Some(kind) = FrameKind::from_u8(b) {
+                assert_eq!(kind as u8, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pinned_layout() {
+        let frame = Frame::new(FrameKind::Data, vec![0xaa, 0xbb, 0xcc]);
+        assert_eq!(
+            frame.to_bytes(),
+            vec![0x01, 0x02, 0x03, 0x00, 0x00, 0x00, 0xaa, 0xbb, 0xcc]
+        );
+        let frame = Frame::new(FrameKind::Close, vec![]);
+        assert_eq!(frame.to_bytes(), vec![0x01, 0x03, 0x00, 0x00, 0x00, 0x00]);
+        let frame = Frame::new(FrameKind::Hello, vec![FRAME_VERSION]);
+        assert_eq!(
+            frame.to_bytes(),
+            vec![0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x01]
+        );
+        let frame = Frame::new(FrameKind::Data, vec![0; 0x0102]);
+        assert_eq!(
+            &frame.to_bytes()[..6],
+            &[0x01, 0x02, 0x02, 0x01, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_parse_pinned_bytes() {
+        let bytes = vec![0x01, 0x04, 0x02, 0x00, 0x00, 0x00, b'n', b'o'];
+        let frame = roundtrip(bytes).unwrap();
+        assert_eq!(frame, Frame::new(FrameKind::Error, b"no".to_vec()));
+    }
+
+    #[test]
+    fn test_reject_bad_header() {
+        assert!(roundtrip(vec![0x02, 0x02, 0x00, 0x00, 0x00, 0x00]).is_err());
+        assert!(roundtrip(vec![0x01, 0x7f, 0x00, 0x00, 0x00, 0x00]).is_err());
+        assert!(roundtrip(vec![0x01, 0x02, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(roundtrip(vec![0x01, 0x02, 0x04, 0x00, 0x00, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_hello() {
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            assert_eq!(hello(&mut channel).unwrap(), FRAME_VERSION);
+            Frame::new(FrameKind::Data, vec![1, 2, 3])
+                .write(&mut channel)
+                .unwrap();
+            channel.flush().unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        assert_eq!(hello(&mut channel).unwrap(), FRAME_VERSION);
+        let frame = Frame::read_expecting(&mut channel, FrameKind::Data).unwrap();
+        assert_eq!(frame.payload, vec![1, 2, 3]);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Run either endpoint of PSZ private set intersection over TCP.
+//!
+//! ```text
+//! psi send --listen 0.0.0.0:4000 --input set.txt
+//! psi receive --connect host:4000 --input set.txt --output intersection.txt
+//! ```
+//!
+//! Each line of an input file is one item; line endings are stripped and
+//! blank lines skipped. The sender streams its file in bounded chunks, so it
+//! may be much larger than memory, while the receiver holds its own set in
+//! memory. The receiver writes the items of its set in the intersection, in
+//! the order of its file, to `--output` or to standard output.
+//!
+//! `--timeout-ms` bounds how long either side waits for the other to connect,
+//! and `--idle-timeout-ms` how long a read or write may block once they are
+//! connected. With the `config` feature, `--config` reads the security
+//! parameters, session lifetime, limits and threads of the run from a
+//! `popsicle::config` file, whose `[network]` section, which must be of the
+//! role of the command, stands in for `--listen` or `--connect` and
+//! `--timeout-ms` where they are not given. Failures are reported on standard error as one
+//! line of JSON, `popsicle::Error::to_json`, and exit with the code of their
+//! `popsicle::ErrorClass`.
+
+mod frame;
+
+use crate::frame::{Frame, FrameKind};
+#[cfg(feature = "config")]
+use popsicle::{config::Config, topology::Role as PartyRole};
+use popsicle::{
+    psz::{Receiver, Sender},
+    Error,
+};
+use scuttlebutt::{AbstractChannel, AesRng, Channel};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+const USAGE: &str = "\
+usage: psi send --listen <addr> --input <file> [options]
+       psi receive --connect <addr> --input <file> [--output <file>] [options]
+
+options:
+    --timeout-ms <ms>         how long to wait for the peer to connect [10000]
+    --idle-timeout-ms <ms>    how long a read or write may block [600000]
+    --config <file>           the settings of the run, with the `config` feature";
+
+// How often to retry while waiting for the peer.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+type TcpChannel = Channel<BufReader<TcpStream>, BufWriter<TcpStream>>;
+
+enum Role {
+    Send {
+        listen: String,
+    },
+    Receive {
+        connect: String,
+        output: Option<PathBuf>,
+    },
+}
+
+struct Args {
+    role: Role,
+    input: PathBuf,
+    timeout: Duration,
+    idle_timeout: Duration,
+    #[cfg(feature = "config")]
+    config: Option<Config>,
+}
+
+fn usage_error(msg: impl std::fmt::Display) -> Error {
+    Error::IoError(io::Error::new(
+        ErrorKind::InvalidInput,
+        format!("{}\n\n{}", msg, USAGE),
+    ))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, Error> {
+    let command = args.next().ok_or_else(|| usage_error("no command given"))?;
+    let (mut listen, mut connect, mut input, mut output) = (None, None, None, None);
+    let mut timeout = None;
+    let mut idle_timeout = Duration::from_millis(600_000);
+    #[cfg(feature = "config")]
+    let mut config = None;
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| usage_error(format!("`{}` needs a value", flag)))?;
+        let millis = || {
+            value
+                .parse()
+                .map(Duration::from_millis)
+                .map_err(|_| usage_error(format!("`{}` takes milliseconds", flag)))
+        };
+        match flag.as_str() {
+            "--listen" => listen = Some(value.clone()),
+            "--connect" => connect = Some(value.clone()),
+            "--input" => input = Some(PathBuf::from(&value)),
+            "--output" => output = Some(PathBuf::from(&value)),
+            "--timeout-ms" => timeout = Some(millis()?),
+            "--idle-timeout-ms" => idle_timeout = millis()?,
+            #[cfg(feature = "config")]
+            "--config" => config = Some(Config::from_file(&value)?),
+            _ => return Err(usage_error(format!("unknown option `{}`", flag))),
+        }
+    }
+    // The `[network]` section of a config stands in for missing flags.
+    #[cfg(feature = "config")]
+    if let Some(config) = &config {
+        let network = &config.network;
+        let endpoint = match command.as_str() {
+            "send" => Some((PartyRole::Sender, &mut listen, Some(&network.address))),
+            "receive" => Some((PartyRole::Receiver, &mut connect, network.peer.as_ref())),
+            _ => None,
+        };
+        if let Some((role, addr, default)) = endpoint {
+            if network.role != role {
+                return Err(usage_error(format!(
+                    "the `[network]` role of the config is not that of `{}`",
+                    command
+                )));
+            }
+            if addr.is_none() {
+                *addr = default.cloned();
+            }
+        }
+        timeout = timeout.or(Some(network.timeout()));
+    }
+    let timeout = timeout.unwrap_or(Duration::from_millis(10_000));
+    let role = match (command.as_str(), listen, connect, output) {
+        ("send", Some(listen), None, None) => Role::Send { listen },
+        ("receive", None, Some(connect), output) => Role::Receive { connect, output },
+        ("send", ..) => return Err(usage_error("`send` takes `--listen` only")),
+        ("receive", ..) => return Err(usage_error("`receive` takes `--connect`")),
+        _ => return Err(usage_error(format!("unknown command `{}`", command))),
+    };
+    let input = input.ok_or_else(|| usage_error("no `--input` given"))?;
+    Ok(Args {
+        role,
+        input,
+        timeout,
+        idle_timeout,
+        #[cfg(feature = "config")]
+        config,
+    })
+}
+
+fn timed_out(what: &str) -> Error {
+    Error::IoError(io::Error::new(ErrorKind::TimedOut, what.to_string()))
+}
+
+// Wait up to `timeout` for a peer to connect to `addr`.
+fn accept(addr: &str, timeout: Duration) -> Result<TcpStream, Error> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                return Ok(stream);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Err(timed_out("no peer connected in time"));
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// Connect to `addr`, retrying for up to `timeout` in case the peer is not
+// listening yet.
+fn connect(addr: &str, timeout: Duration) -> Result<TcpStream, Error> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut last = None;
+        for addr in addr.to_socket_addrs()? {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match TcpStream::connect_timeout(&addr, left.max(POLL_INTERVAL)) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last = Some(e),
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(last.map_or_else(|| timed_out("no address to connect to"), Error::from));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn channel(stream: TcpStream, idle_timeout: Duration) -> Result<TcpChannel, Error> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(idle_timeout))?;
+    stream.set_write_timeout(Some(idle_timeout))?;
+    let reader = BufReader::new(stream.try_clone()?);
+    let writer = BufWriter::new(stream);
+    Ok(Channel::new(reader, writer))
+}
+
+// Open the items of the file at `path`, one per line, skipping blank lines.
+fn items(path: &Path) -> Result<impl Iterator<Item = io::Result<Vec<u8>>>, Error> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(file
+        .split(b'\n')
+        .map(|line| {
+            line.map(|mut line| {
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                line
+            })
+        })
+        .filter(|line| line.as_ref().map_or(true, |line| !line.is_empty())))
+}
+
+fn send(listen: &str, args: &Args) -> Result<(), Error> {
+    // Count the items first, so that the file can then be streamed.
+    let mut ninputs = 0;
+    for item in items(&args.input)? {
+        item?;
+        ninputs += 1;
+    }
+    let mut channel = channel(accept(listen, args.timeout)?, args.idle_timeout)?;
+    let mut rng = AesRng::new();
+    frame::hello(&mut channel)?;
+    #[cfg(feature = "config")]
+    let mut psi = match &args.config {
+        Some(config) => config.psz_sender(&mut channel, &mut rng)?,
+        None => Sender::init(&mut channel, &mut rng)?,
+    };
+    #[cfg(not(feature = "config"))]
+    let mut psi = Sender::init(&mut channel, &mut rng)?;
+    // A read error ends the stream early, failing the run, and is reported
+    // in its place.
+    let failure = RefCell::new(None);
+    let inputs = items(&args.input)?
+        .map_while(|item| item.map_err(|e| *failure.borrow_mut() = Some(e)).ok());
+    let result = psi.send_stream(inputs, ninputs, &mut channel, &mut rng);
+    if let Some(e) = failure.into_inner() {
+        return Err(e.into());
+    }
+    result?;
+    Frame::read_expecting(&mut channel, FrameKind::Close)?;
+    eprintln!("sent {} items", ninputs);
+    Ok(())
+}
+
+fn receive(connect_to: &str, output: Option<&Path>, args: &Args) -> Result<(), Error> {
+    let inputs = items(&args.input)?.collect::<io::Result<Vec<_>>>()?;
+    #[cfg(feature = "config")]
+    if let Some(config) = &args.config {
+        config.limits.check(&inputs)?;
+    }
+    let mut channel = channel(connect(connect_to, args.timeout)?, args.idle_timeout)?;
+    let mut rng = AesRng::new();
+    frame::hello(&mut channel)?;
+    #[cfg(feature = "config")]
+    let mut psi = match &args.config {
+        Some(config) => config.psz_receiver(&mut channel, &mut rng)?,
+        None => Receiver::init(&mut channel, &mut rng)?,
+    };
+    #[cfg(not(feature = "config"))]
+    let mut psi = Receiver::init(&mut channel, &mut rng)?;
+    let intersection = psi
+        .receive_stream(&inputs, &mut channel, &mut rng)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    Frame::new(FrameKind::Close, Vec::new()).write(&mut channel)?;
+    channel.flush()?;
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    for item in inputs.iter().filter(|item| intersection.contains(*item)) {
+        writer.write_all(item)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    eprintln!(
+        "found {} of {} items in the intersection",
+        intersection.len(),
+        inputs.len()
+    );
+    Ok(())
+}
+
+fn run() -> Result<(), Error> {
+    let args = parse_args(std::env::args().skip(1))?;
+    match &args.role {
+        Role::Send { listen } => send(listen, &args),
+        Role::Receive { connect, output } => receive(connect, output.as_deref(), &args),
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e.to_json());
+        std::process::exit(e.class().exit_code());
+    }
+}
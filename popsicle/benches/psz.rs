@@ -5,10 +5,27 @@
 // See LICENSE for licensing information.
 
 //! Private set intersection (PSZ) benchmarks using `criterion`.
+//!
+//! The benchmarks are split by phase, so that a regression shows up in the
+//! layer that caused it:
+//!
+//! * `psz/init` runs the base OTs and sets up the OPRF, once per session.
+//! * `psz/offline` evaluates the KKRT OPRF alone on `n` points, on parties
+//!   that are already initialized.
+//! * `psz/online` runs the full intersection of two sets of `n` items, again
+//!   on initialized parties.
+//!
+//! Each phase is timed from the side of the sender and of the receiver, with
+//! the other party on its own thread, for sets of `2^10` to `2^24` items.
+//! Before timing a phase, the benchmark prints how much the timed party wrote
+//! and read in one run. The largest sizes take a long time and a lot of
+//! memory: pass a filter, e.g. `cargo bench --bench psz -- 2^16`, to run only
+//! some.
 
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use ocelot::oprf::{self, Receiver as OprfReceiver, Sender as OprfSender};
 use popsicle::psz;
-use scuttlebutt::{AesRng, Channel};
+use scuttlebutt::{AbstractChannel, AesRng, Block, Channel, TrackChannel};
 use std::{
     io::{BufReader, BufWriter},
     os::unix::net::UnixStream,
@@ -17,6 +34,69 @@ use std::{
 
 const SIZE: usize = 15;
 
+/// The logarithms of the set sizes to benchmark.
+const LOG_SIZES: [usize; 5] = [10, 12, 16, 20, 24];
+
+type UnixChannel = Channel<BufReader<UnixStream>, BufWriter<UnixStream>>;
+
+/// One side of a phase: a party that, once initialized, runs on its inputs.
+trait Party: Sized + Send + 'static {
+    type Input: Send + 'static;
+
+    fn init<C: AbstractChannel>(channel: &mut C, rng: &mut AesRng) -> Self;
+
+    fn run<C: AbstractChannel>(&mut self, inputs: &Self::Input, channel: &mut C, rng: &mut AesRng);
+}
+
+impl Party for psz::Sender {
+    type Input = Vec<Vec<u8>>;
+
+    fn init<C: AbstractChannel>(channel: &mut C, rng: &mut AesRng) -> Self {
+        psz::Sender::init(channel, rng).unwrap()
+    }
+
+    fn run<C: AbstractChannel>(&mut self, inputs: &Self::Input, channel: &mut C, rng: &mut AesRng) {
+        self.send(inputs, channel, rng).unwrap();
+    }
+}
+
+impl Party for psz::Receiver {
+    type Input = Vec<Vec<u8>>;
+
+    fn init<C: AbstractChannel>(channel: &mut C, rng: &mut AesRng) -> Self {
+        psz::Receiver::init(channel, rng).unwrap()
+    }
+
+    fn run<C: AbstractChannel>(&mut self, inputs: &Self::Input, channel: &mut C, rng: &mut AesRng) {
+        criterion::black_box(self.receive(inputs, channel, rng).unwrap());
+    }
+}
+
+impl Party for oprf::KkrtSender {
+    // The number of points the receiver evaluates the OPRF on.
+    type Input = usize;
+
+    fn init<C: AbstractChannel>(channel: &mut C, rng: &mut AesRng) -> Self {
+        OprfSender::init(channel, rng).unwrap()
+    }
+
+    fn run<C: AbstractChannel>(&mut self, n: &usize, channel: &mut C, rng: &mut AesRng) {
+        criterion::black_box(OprfSender::send(self, channel, *n, rng).unwrap());
+    }
+}
+
+impl Party for oprf::KkrtReceiver {
+    type Input = Vec<Block>;
+
+    fn init<C: AbstractChannel>(channel: &mut C, rng: &mut AesRng) -> Self {
+        OprfReceiver::init(channel, rng).unwrap()
+    }
+
+    fn run<C: AbstractChannel>(&mut self, inputs: &Vec<Block>, channel: &mut C, rng: &mut AesRng) {
+        criterion::black_box(OprfReceiver::receive(self, channel, inputs, rng).unwrap());
+    }
+}
+
 fn rand_vec(n: usize) -> Vec<u8> {
     (0..n).map(|_| rand::random::<u8>()).collect()
 }
@@ -25,84 +105,171 @@ fn rand_vec_vec(size: usize) -> Vec<Vec<u8>> {
     (0..size).map(|_| rand_vec(SIZE)).collect()
 }
 
-fn _bench_psz_init() {
-    let (sender, receiver) = UnixStream::pair().unwrap();
-    let handle = std::thread::spawn(move || {
-        let mut rng = AesRng::new();
-        let reader = BufReader::new(sender.try_clone().unwrap());
-        let writer = BufWriter::new(sender);
-        let mut channel = Channel::new(reader, writer);
-        let _ = psz::Sender::init(&mut channel, &mut rng).unwrap();
-    });
-    let mut rng = AesRng::new();
-    let reader = BufReader::new(receiver.try_clone().unwrap());
-    let writer = BufWriter::new(receiver);
-    let mut channel = Channel::new(reader, writer);
-    let _ = psz::Receiver::init(&mut channel, &mut rng).unwrap();
-    handle.join().unwrap();
+fn rand_block_vec(size: usize) -> Vec<Block> {
+    (0..size).map(|_| rand::random::<Block>()).collect()
+}
+
+fn streams() -> (UnixStream, UnixStream) {
+    UnixStream::pair().unwrap()
 }
 
-fn _bench_psz(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
-    let (sender, receiver) = UnixStream::pair().unwrap();
-    let handle = std::thread::spawn(move || {
+fn channel(stream: UnixStream) -> UnixChannel {
+    let reader = BufReader::new(stream.try_clone().unwrap());
+    let writer = BufWriter::new(stream);
+    Channel::new(reader, writer)
+}
+
+/// Initialize `P` on its own thread and, unless `inputs` is `None`, run it.
+fn spawn_peer<P: Party>(
+    stream: UnixStream,
+    inputs: Option<P::Input>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
         let mut rng = AesRng::new();
-        let reader = BufReader::new(sender.try_clone().unwrap());
-        let writer = BufWriter::new(sender);
-        let mut channel = Channel::new(reader, writer);
-        let mut psi = psz::Sender::init(&mut channel, &mut rng).unwrap();
-        psi.send(&inputs1, &mut channel, &mut rng).unwrap();
-    });
+        let mut channel = channel(stream);
+        let mut peer = P::init(&mut channel, &mut rng);
+        if let Some(inputs) = inputs {
+            peer.run(&inputs, &mut channel, &mut rng);
+        }
+    })
+}
+
+/// Print how many bytes `M` writes and reads when run on `inputs` against `P`
+/// on `theirs`, leaving out initialization, or when just initialized against
+/// `P` if there are no inputs.
+fn report_traffic<M: Party, P: Party>(name: &str, inputs: Option<(&M::Input, P::Input)>) {
+    let (mine, peer) = streams();
+    let (inputs, theirs) = match inputs {
+        Some((inputs, theirs)) => (Some(inputs), Some(theirs)),
+        None => (None, None),
+    };
+    let handle = spawn_peer::<P>(peer, theirs);
     let mut rng = AesRng::new();
-    let reader = BufReader::new(receiver.try_clone().unwrap());
-    let writer = BufWriter::new(receiver);
-    let mut channel = Channel::new(reader, writer);
-    let mut psi = psz::Receiver::init(&mut channel, &mut rng).unwrap();
-    let intersection = psi.receive(&inputs2, &mut channel, &mut rng).unwrap();
+    let reader = BufReader::new(mine.try_clone().unwrap());
+    let writer = BufWriter::new(mine);
+    let mut channel = TrackChannel::new(reader, writer);
+    let mut party = M::init(&mut channel, &mut rng);
+    if let Some(inputs) = inputs {
+        channel.clear();
+        party.run(inputs, &mut channel, &mut rng);
+    }
     handle.join().unwrap();
-    intersection
+    println!(
+        "{}: wrote {:.1} KiB, read {:.1} KiB",
+        name,
+        channel.kilobytes_written(),
+        channel.kilobytes_read()
+    );
 }
 
-fn bench_psi(c: &mut Criterion) {
-    c.bench_function("psi::PSZ (initialization)", move |bench| {
-        bench.iter(|| {
-            let result = _bench_psz_init();
-            criterion::black_box(result)
-        })
+/// Benchmark the initialization of `M` against `P`.
+fn bench_init<M: Party, P: Party>(c: &mut Criterion, name: &str) {
+    let mut reported = false;
+    let id = name.to_string();
+    c.bench_function(name, move |bench| {
+        if !reported {
+            report_traffic::<M, P>(&id, None);
+            reported = true;
+        }
+        bench.iter_batched(
+            || {
+                let (mine, peer) = streams();
+                (channel(mine), spawn_peer::<P>(peer, None))
+            },
+            |(mut channel, handle)| {
+                let mut rng = AesRng::new();
+                let party = M::init(&mut channel, &mut rng);
+                handle.join().unwrap();
+                party
+            },
+            BatchSize::PerIteration,
+        )
     });
-    c.bench_function("psi::PSZ (n = 2^8)", move |bench| {
-        let rs = rand_vec_vec(1 << 8);
-        bench.iter(|| {
-            let v = _bench_psz(rs.clone(), rs.clone());
-            criterion::black_box(v)
-        })
-    });
-    c.bench_function("psi::PSZ (n = 2^12)", move |bench| {
-        let rs = rand_vec_vec(1 << 12);
-        bench.iter(|| {
-            let v = _bench_psz(rs.clone(), rs.clone());
-            criterion::black_box(v)
-        })
-    });
-    c.bench_function("psi::PSZ (n = 2^16)", move |bench| {
-        let rs = rand_vec_vec(1 << 16);
-        bench.iter(|| {
-            let v = _bench_psz(rs.clone(), rs.clone());
-            criterion::black_box(v)
-        })
-    });
-    c.bench_function("psi::PSZ (n = 2^20)", move |bench| {
-        let rs = rand_vec_vec(1 << 20);
-        bench.iter(|| {
-            let v = _bench_psz(rs.clone(), rs.clone());
-            criterion::black_box(v)
-        })
+}
+
+/// Benchmark a run of `M` against `P`, both initialized beforehand, on the
+/// inputs made by `inputs`. These are only made, once, if the benchmark is
+/// not filtered out.
+fn bench_run<M, P, F>(c: &mut Criterion, name: &str, inputs: F)
+where
+    M: Party,
+    P: Party,
+    P::Input: Clone,
+    F: Fn() -> (M::Input, P::Input) + 'static,
+{
+    let mut made = None;
+    let id = name.to_string();
+    c.bench_function(name, move |bench| {
+        let (inputs, theirs) = made.get_or_insert_with(|| {
+            let (inputs, theirs) = inputs();
+            report_traffic::<M, P>(&id, Some((&inputs, theirs.clone())));
+            (inputs, theirs)
+        });
+        bench.iter_batched(
+            || {
+                let (mine, peer) = streams();
+                let handle = spawn_peer::<P>(peer, Some(theirs.clone()));
+                let mut rng = AesRng::new();
+                let mut channel = channel(mine);
+                let party = M::init(&mut channel, &mut rng);
+                (party, channel, rng, handle)
+            },
+            |(mut party, mut channel, mut rng, handle)| {
+                party.run(inputs, &mut channel, &mut rng);
+                handle.join().unwrap();
+            },
+            BatchSize::PerIteration,
+        )
     });
 }
 
+fn bench_psz_init(c: &mut Criterion) {
+    bench_init::<psz::Sender, psz::Receiver>(c, "psz/init/sender");
+    bench_init::<psz::Receiver, psz::Sender>(c, "psz/init/receiver");
+}
+
+fn bench_psz_offline(c: &mut Criterion) {
+    for &log in LOG_SIZES.iter() {
+        let n = 1 << log;
+        bench_run::<oprf::KkrtSender, oprf::KkrtReceiver, _>(
+            c,
+            &format!("psz/offline/sender (n = 2^{})", log),
+            move || (n, rand_block_vec(n)),
+        );
+        bench_run::<oprf::KkrtReceiver, oprf::KkrtSender, _>(
+            c,
+            &format!("psz/offline/receiver (n = 2^{})", log),
+            move || (rand_block_vec(n), n),
+        );
+    }
+}
+
+fn bench_psz_online(c: &mut Criterion) {
+    for &log in LOG_SIZES.iter() {
+        let n = 1 << log;
+        bench_run::<psz::Sender, psz::Receiver, _>(
+            c,
+            &format!("psz/online/sender (n = 2^{})", log),
+            move || {
+                let inputs = rand_vec_vec(n);
+                (inputs.clone(), inputs)
+            },
+        );
+        bench_run::<psz::Receiver, psz::Sender, _>(
+            c,
+            &format!("psz/online/receiver (n = 2^{})", log),
+            move || {
+                let inputs = rand_vec_vec(n);
+                (inputs.clone(), inputs)
+            },
+        );
+    }
+}
+
 criterion_group! {
     name = psi;
     config = Criterion::default().warm_up_time(Duration::from_millis(100)).sample_size(10);
-    targets = bench_psi
+    targets = bench_psz_init, bench_psz_offline, bench_psz_online
 }
 
 criterion_main!(psi);
@@ -5,17 +5,21 @@
 // See LICENSE for licensing information.
 
 use popsicle::psty::{Receiver, Sender};
-use scuttlebutt::{AesRng, TrackChannel};
+use scuttlebutt::{memtrack, AesRng, TrackChannel};
 use std::{
     io::{BufReader, BufWriter},
     os::unix::net::UnixStream,
     time::SystemTime,
 };
+use swanky_bench::{measure, Report};
 
 const NBYTES: usize = 16;
 const NINPUTS: usize = 1 << 20;
 const PAYLOAD_SIZE: usize = 64;
 
+#[global_allocator]
+static ALLOC: memtrack::CountingAlloc = memtrack::CountingAlloc::system();
+
 fn rand_vec(nbytes: usize) -> Vec<u8> {
     (0..nbytes).map(|_| rand::random::<u8>()).collect()
 }
@@ -34,18 +38,14 @@ fn psty_payload(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>, payloads: Vec<Vec<
         let writer = BufWriter::new(sender);
         let mut channel = TrackChannel::new(reader, writer);
 
-        let start = SystemTime::now();
-        let mut sender = Sender::init(&mut channel, &mut rng).unwrap();
-        println!(
-            "Sender :: init time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
-        let start = SystemTime::now();
-        let state = sender.send(&inputs1, &mut channel, &mut rng).unwrap();
-        println!(
-            "Sender :: send time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
+        let (mut sender, init) = measure("sender", "init", &mut channel, |channel| {
+            Sender::init(channel, &mut rng).unwrap()
+        });
+        println!("Sender :: init time: {:.0} ms", init.millis);
+        let (state, send) = measure("sender", "send", &mut channel, |channel| {
+            sender.send(&inputs1, channel, &mut rng).unwrap()
+        });
+        println!("Sender :: send time: {:.0} ms", send.millis);
         println!(
             "Sender :: pre-payload communication (read): {:.2} Mb",
             channel.kilobits_read() / 1000.0
@@ -54,11 +54,12 @@ fn psty_payload(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>, payloads: Vec<Vec<
             "Sender :: pre-payloads communication (write): {:.2} Mb",
             channel.kilobits_written() / 1000.0
         );
-        let start = SystemTime::now();
-        let _ = state.receive_payloads(payload_size, &mut channel).unwrap();
+        let (_, payloads) = measure("sender", "payloads", &mut channel, |channel| {
+            state.receive_payloads(payload_size, channel).unwrap()
+        });
         println!(
-            "Sender :: payload intersection time: {} ms",
-            start.elapsed().unwrap().as_millis()
+            "Sender :: payload intersection time: {:.0} ms",
+            payloads.millis
         );
         println!(
             "Sender :: total communication (read): {:.2} Mb",
@@ -68,6 +69,7 @@ fn psty_payload(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>, payloads: Vec<Vec<
             "Sender :: total communication (write): {:.2} Mb",
             channel.kilobits_written() / 1000.0
         );
+        vec![init, send, payloads]
     });
 
     let mut rng = AesRng::new();
@@ -75,27 +77,27 @@ fn psty_payload(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>, payloads: Vec<Vec<
     let writer = BufWriter::new(receiver);
     let mut channel = TrackChannel::new(reader, writer);
 
-    let start = SystemTime::now();
-    let mut receiver = Receiver::init(&mut channel, &mut rng).unwrap();
-    println!(
-        "Receiver :: init time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    let start = SystemTime::now();
-    let state = receiver.receive(&inputs2, &mut channel, &mut rng).unwrap();
-    println!(
-        "Receiver :: receive time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    let start = SystemTime::now();
-    state
-        .send_payloads(&payloads, &mut channel, &mut rng)
-        .unwrap();
+    let (mut receiver, init) = measure("receiver", "init", &mut channel, |channel| {
+        Receiver::init(channel, &mut rng).unwrap()
+    });
+    println!("Receiver :: init time: {:.0} ms", init.millis);
+    let (state, receive) = measure("receiver", "receive", &mut channel, |channel| {
+        receiver.receive(&inputs2, channel, &mut rng).unwrap()
+    });
+    println!("Receiver :: receive time: {:.0} ms", receive.millis);
+    let ((), payloads) = measure("receiver", "payloads", &mut channel, |channel| {
+        state.send_payloads(&payloads, channel, &mut rng).unwrap()
+    });
     println!(
-        "Receiver :: payload intersection time: {} ms",
-        start.elapsed().unwrap().as_millis()
+        "Receiver :: payload intersection time: {:.0} ms",
+        payloads.millis
     );
-    let _ = handle.join().unwrap();
+    let mut report = Report::new("psty-payload")
+        .param("ninputs", inputs2.len() as u64)
+        .param("nbytes", NBYTES as u64)
+        .param("payload_size", payload_size as u64);
+    report.phases.extend(handle.join().unwrap());
+    report.phases.extend(vec![init, receive, payloads]);
     println!(
         "Receiver :: communication (read): {:.2} Mb",
         channel.kilobits_read() / 1000.0
@@ -109,6 +111,7 @@ fn psty_payload(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>, payloads: Vec<Vec<
         (channel.kilobits_written() + channel.kilobits_read()) / 1000.0
     );
     println!("Total time: {} ms", total.elapsed().unwrap().as_millis());
+    report.emit().unwrap();
 }
 
 fn main() {
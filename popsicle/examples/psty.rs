@@ -7,16 +7,20 @@
 //! Private set intersection (PSTY) benchmarks using `criterion`.
 
 use popsicle::psty::{Receiver, Sender};
-use scuttlebutt::{AesRng, TrackChannel};
+use scuttlebutt::{memtrack, AesRng, TrackChannel};
 use std::{
     io::{BufReader, BufWriter},
     os::unix::net::UnixStream,
     time::SystemTime,
 };
+use swanky_bench::{measure, Report};
 
 const NBYTES: usize = 16;
 const NINPUTS: usize = 1 << 16;
 
+#[global_allocator]
+static ALLOC: memtrack::CountingAlloc = memtrack::CountingAlloc::system();
+
 fn rand_vec(nbytes: usize) -> Vec<u8> {
     (0..nbytes).map(|_| rand::random::<u8>()).collect()
 }
@@ -34,18 +38,14 @@ fn psty(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>) {
         let writer = BufWriter::new(sender);
         let mut channel = TrackChannel::new(reader, writer);
 
-        let start = SystemTime::now();
-        let mut sender = Sender::init(&mut channel, &mut rng).unwrap();
-        println!(
-            "Sender :: init time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
-        let start = SystemTime::now();
-        let state = sender.send(&inputs1, &mut channel, &mut rng).unwrap();
-        println!(
-            "Sender :: send time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
+        let (mut sender, init) = measure("sender", "init", &mut channel, |channel| {
+            Sender::init(channel, &mut rng).unwrap()
+        });
+        println!("Sender :: init time: {:.0} ms", init.millis);
+        let (state, send) = measure("sender", "send", &mut channel, |channel| {
+            sender.send(&inputs1, channel, &mut rng).unwrap()
+        });
+        println!("Sender :: send time: {:.0} ms", send.millis);
         println!(
             "Sender :: pre-circuit communication (read): {:.2} Mb",
             channel.kilobits_read() / 1000.0
@@ -54,12 +54,10 @@ fn psty(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>) {
             "Sender :: pre-circuit communication (write): {:.2} Mb",
             channel.kilobits_written() / 1000.0
         );
-        let start = SystemTime::now();
-        let _ = state.compute_intersection(&mut channel, &mut rng).unwrap();
-        println!(
-            "Sender :: intersection time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
+        let (_, intersection) = measure("sender", "intersection", &mut channel, |channel| {
+            state.compute_intersection(channel, &mut rng).unwrap()
+        });
+        println!("Sender :: intersection time: {:.0} ms", intersection.millis);
         println!(
             "Sender :: total communication (read): {:.2} Mb",
             channel.kilobits_read() / 1000.0
@@ -68,6 +66,7 @@ fn psty(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>) {
             "Sender :: total communication (write): {:.2} Mb",
             channel.kilobits_written() / 1000.0
         );
+        vec![init, send, intersection]
     });
 
     let mut rng = AesRng::new();
@@ -75,25 +74,26 @@ fn psty(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>) {
     let writer = BufWriter::new(receiver);
     let mut channel = TrackChannel::new(reader, writer);
 
-    let start = SystemTime::now();
-    let mut receiver = Receiver::init(&mut channel, &mut rng).unwrap();
-    println!(
-        "Receiver :: init time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    let start = SystemTime::now();
-    let state = receiver.receive(&inputs2, &mut channel, &mut rng).unwrap();
-    println!(
-        "Receiver :: receive time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    let start = SystemTime::now();
-    let _ = state.compute_intersection(&mut channel, &mut rng).unwrap();
+    let (mut receiver, init) = measure("receiver", "init", &mut channel, |channel| {
+        Receiver::init(channel, &mut rng).unwrap()
+    });
+    println!("Receiver :: init time: {:.0} ms", init.millis);
+    let (state, receive) = measure("receiver", "receive", &mut channel, |channel| {
+        receiver.receive(&inputs2, channel, &mut rng).unwrap()
+    });
+    println!("Receiver :: receive time: {:.0} ms", receive.millis);
+    let (_, intersection) = measure("receiver", "intersection", &mut channel, |channel| {
+        state.compute_intersection(channel, &mut rng).unwrap()
+    });
     println!(
-        "Receiver :: intersection time: {} ms",
-        start.elapsed().unwrap().as_millis()
+        "Receiver :: intersection time: {:.0} ms",
+        intersection.millis
     );
-    let _ = handle.join().unwrap();
+    let mut report = Report::new("psty")
+        .param("ninputs", inputs2.len() as u64)
+        .param("nbytes", NBYTES as u64);
+    report.phases.extend(handle.join().unwrap());
+    report.phases.extend(vec![init, receive, intersection]);
     println!(
         "Receiver :: communication (read): {:.2} Mb",
         channel.kilobits_read() / 1000.0
@@ -103,6 +103,7 @@ fn psty(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>) {
         channel.kilobits_written() / 1000.0
     );
     println!("Total time: {} ms", total.elapsed().unwrap().as_millis());
+    report.emit().unwrap();
 }
 
 fn main() {
@@ -5,17 +5,21 @@
 // See LICENSE for licensing information.
 
 use popsicle::psz::{Receiver, Sender};
-use scuttlebutt::{AesRng, TrackChannel};
+use scuttlebutt::{memtrack, AesRng, TrackChannel};
 use std::{
     io::{BufReader, BufWriter},
     os::unix::net::UnixStream,
     time::SystemTime,
 };
+use swanky_bench::{measure, Report};
 
 const NBYTES: usize = 16;
 const NINPUTS: usize = 1 << 20;
 const PAYLOAD_SIZE: usize = 64;
 
+#[global_allocator]
+static ALLOC: memtrack::CountingAlloc = memtrack::CountingAlloc::system();
+
 fn rand_vec(nbytes: usize) -> Vec<u8> {
     (0..nbytes).map(|_| rand::random::<u8>()).collect()
 }
@@ -24,29 +28,25 @@ fn rand_vec_vec(ninputs: usize, nbytes: usize) -> Vec<Vec<u8>> {
     (0..ninputs).map(|_| rand_vec(nbytes)).collect()
 }
 
-fn psz_payload(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>) {
+fn psz_payload(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>, payloads: Vec<Vec<u8>>) {
     let (sender, receiver) = UnixStream::pair().unwrap();
     let total = SystemTime::now();
-    std::thread::spawn(move || {
+    let handle = std::thread::spawn(move || {
         let mut rng = AesRng::new();
         let reader = BufReader::new(sender.try_clone().unwrap());
         let writer = BufWriter::new(sender);
         let mut channel = TrackChannel::new(reader, writer);
 
-        let start = SystemTime::now();
-        let mut sender = Sender::init(&mut channel, &mut rng).unwrap();
-        println!(
-            "Sender :: init time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
-        let start = SystemTime::now();
-        sender
-            .send_payloads(&inputs1, &mut channel, &mut rng)
-            .unwrap();
-        println!(
-            "Sender :: send time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
+        let (mut sender, init) = measure("sender", "init", &mut channel, |channel| {
+            Sender::init(channel, &mut rng).unwrap()
+        });
+        println!("Sender :: init time: {:.0} ms", init.millis);
+        let ((), send) = measure("sender", "send", &mut channel, |channel| {
+            sender
+                .send_payloads(&inputs1, &payloads, channel, &mut rng)
+                .unwrap()
+        });
+        println!("Sender :: send time: {:.0} ms", send.millis);
         println!(
             "Sender :: communication (read): {:.2} Mb",
             channel.kilobits_read() / 1000.0
@@ -55,6 +55,7 @@ fn psz_payload(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>) {
             "Sender :: communication (write): {:.2} Mb",
             channel.kilobits_written() / 1000.0
         );
+        vec![init, send]
     });
 
     let mut rng = AesRng::new();
@@ -62,20 +63,23 @@ fn psz_payload(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>) {
     let writer = BufWriter::new(receiver);
     let mut channel = TrackChannel::new(reader, writer);
 
-    let start = SystemTime::now();
-    let mut receiver = Receiver::init(&mut channel, &mut rng).unwrap();
-    println!(
-        "Receiver :: init time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    let start = SystemTime::now();
-    let _intersection = receiver
-        .receive_payloads(&inputs2, &mut channel, &mut rng)
-        .unwrap();
-    println!(
-        "Receiver :: receive time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
+    let (mut receiver, init) = measure("receiver", "init", &mut channel, |channel| {
+        Receiver::init(channel, &mut rng).unwrap()
+    });
+    println!("Receiver :: init time: {:.0} ms", init.millis);
+    let (_intersection, receive) = measure("receiver", "receive", &mut channel, |channel| {
+        receiver
+            .receive_payloads(&inputs2, channel, &mut rng)
+            .unwrap()
+    });
+    println!("Receiver :: receive time: {:.0} ms", receive.millis);
+    let mut report = Report::new("psz-payload")
+        .param("ninputs", inputs2.len() as u64)
+        .param("nbytes", NBYTES as u64)
+        .param("payload_size", PAYLOAD_SIZE as u64);
+    report.phases.extend(handle.join().unwrap());
+    report.push(init);
+    report.push(receive);
     println!(
         "Receiver :: communication (read): {:.2} Mb",
         channel.kilobits_read() / 1000.0
@@ -89,13 +93,15 @@ fn psz_payload(inputs1: Vec<Vec<u8>>, inputs2: Vec<Vec<u8>>) {
         (channel.kilobits_written() + channel.kilobits_read()) / 1000.0
     );
     println!("Total time: {} ms", total.elapsed().unwrap().as_millis());
+    report.emit().unwrap();
 }
 
 fn main() {
     println!(
-        "* Running PSTY on {} inputs each of length {} bytes with {} byte payloads",
+        "* Running PSZ on {} inputs each of length {} bytes with {} byte payloads",
         NINPUTS, NBYTES, PAYLOAD_SIZE
     );
     let rs = rand_vec_vec(NINPUTS, NBYTES);
-    psz_payload(rs.clone(), rs.clone());
+    let payloads = rand_vec_vec(NINPUTS, PAYLOAD_SIZE);
+    psz_payload(rs.clone(), rs.clone(), payloads);
 }
@@ -5,16 +5,20 @@
 // See LICENSE for licensing information.
 
 use popsicle::psz::{Receiver, Sender};
-use scuttlebutt::{AesRng, TrackChannel};
+use scuttlebutt::{memtrack, AesRng, TrackChannel};
 use std::{
     io::{BufReader, BufWriter},
     os::unix::net::UnixStream,
     time::SystemTime,
 };
+use swanky_bench::{measure, Report};
 
 const NBYTES: usize = 16;
 const NINPUTS: usize = 1 << 20;
 
+#[global_allocator]
+static ALLOC: memtrack::CountingAlloc = memtrack::CountingAlloc::system();
+
 fn rand_vec(nbytes: usize) -> Vec<u8> {
     (0..nbytes).map(|_| rand::random::<u8>()).collect()
 }
@@ -23,6 +27,18 @@ fn rand_vec_vec(ninputs: usize, nbytes: usize) -> Vec<Vec<u8>> {
     (0..ninputs).map(|_| rand_vec(nbytes)).collect()
 }
 
+fn print_memory(party: &str) {
+    for (phase, stats) in memtrack::take_report() {
+        println!(
+            "{} :: {} memory: {:.2} Mb peak, {:.2} Mb allocated",
+            party,
+            phase,
+            stats.peak as f64 / 1_000_000.0,
+            stats.allocated as f64 / 1_000_000.0
+        );
+    }
+}
+
 fn psi(ninputs: usize, nbytes: usize) {
     let (sender, receiver) = UnixStream::pair().unwrap();
     let sender_inputs = rand_vec_vec(ninputs, nbytes);
@@ -33,18 +49,16 @@ fn psi(ninputs: usize, nbytes: usize) {
         let reader = BufReader::new(sender.try_clone().unwrap());
         let writer = BufWriter::new(sender);
         let mut channel = TrackChannel::new(reader, writer);
-        let start = SystemTime::now();
-        let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
-        println!(
-            "Sender :: init time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
-        let start = SystemTime::now();
-        psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
-        println!(
-            "Sender :: send time: {} ms",
-            start.elapsed().unwrap().as_millis()
-        );
+        let (mut psi, init) = measure("sender", "init", &mut channel, |channel| {
+            Sender::init(channel, &mut rng).unwrap()
+        });
+        println!("Sender :: init time: {:.0} ms", init.millis);
+        memtrack::start_recording();
+        let ((), send) = measure("sender", "send", &mut channel, |channel| {
+            psi.send(&sender_inputs, channel, &mut rng).unwrap()
+        });
+        println!("Sender :: send time: {:.0} ms", send.millis);
+        print_memory("Sender");
         println!(
             "Sender :: communication (read): {:.2} Mb",
             channel.kilobits_read() / 1000.0
@@ -53,26 +67,27 @@ fn psi(ninputs: usize, nbytes: usize) {
             "Sender :: communication (write): {:.2} Mb",
             channel.kilobits_written() / 1000.0
         );
+        vec![init, send]
     });
     let mut rng = AesRng::new();
     let reader = BufReader::new(receiver.try_clone().unwrap());
     let writer = BufWriter::new(receiver);
     let mut channel = TrackChannel::new(reader, writer);
-    let start = SystemTime::now();
-    let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
-    println!(
-        "Receiver :: init time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    let start = SystemTime::now();
-    let _ = psi
-        .receive(&receiver_inputs, &mut channel, &mut rng)
-        .unwrap();
-    println!(
-        "Receiver :: receive time: {} ms",
-        start.elapsed().unwrap().as_millis()
-    );
-    handle.join().unwrap();
+    let (mut psi, init) = measure("receiver", "init", &mut channel, |channel| {
+        Receiver::init(channel, &mut rng).unwrap()
+    });
+    println!("Receiver :: init time: {:.0} ms", init.millis);
+    memtrack::start_recording();
+    let (_, receive) = measure("receiver", "receive", &mut channel, |channel| {
+        psi.receive(&receiver_inputs, channel, &mut rng).unwrap()
+    });
+    println!("Receiver :: receive time: {:.0} ms", receive.millis);
+    print_memory("Receiver");
+    let mut report = Report::new("psz")
+        .param("ninputs", ninputs as u64)
+        .param("nbytes", nbytes as u64);
+    report.phases.extend(handle.join().unwrap());
+    report.phases.extend(vec![init, receive]);
     println!(
         "Receiver :: communication (read): {:.2} Mb",
         channel.kilobits_read() / 1000.0
@@ -82,6 +97,7 @@ fn psi(ninputs: usize, nbytes: usize) {
         channel.kilobits_written() / 1000.0
     );
     println!("Total time: {} ms", total.elapsed().unwrap().as_millis());
+    report.emit().unwrap();
 }
 
 fn main() {
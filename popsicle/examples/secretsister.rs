@@ -5,14 +5,17 @@
 // See LICENSE for licensing information.
 
 // Frontend for KMPRT multi-party PSI over sets of IPV6 addresses
+//
+// On failure, the error is printed to stderr as a JSON object, and the process
+// exits with the code of its `ErrorClass`.
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
 use itertools::Itertools;
-use popsicle::{MultiPartyReceiver, MultiPartySender};
+use popsicle::{Error, MultiPartyReceiver, MultiPartySender};
 use scuttlebutt::{AesRng, Block, TrackChannel};
 use serde::Deserialize;
 use std::{
-    io::{BufRead, BufReader, BufWriter, Read, Write},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
     net::{TcpListener, TcpStream, ToSocketAddrs},
     time::SystemTime,
 };
@@ -66,18 +69,33 @@ fn main() {
         .setting(clap::AppSettings::ColorAlways)
         .get_matches();
 
-    let config: Vec<PartyConfig> = serde_yaml::from_reader(
-        &mut std::fs::File::open(matches.value_of("CONFIG_FILE").unwrap()).unwrap(),
-    )
-    .unwrap();
+    if let Err(e) = run(&matches) {
+        eprintln!("{}", e.to_json());
+        std::process::exit(e.class().exit_code());
+    }
+}
+
+fn invalid_input(msg: String) -> Error {
+    Error::IoError(std::io::Error::new(ErrorKind::InvalidInput, msg))
+}
+
+fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let config: Vec<PartyConfig> = serde_yaml::from_reader(&mut std::fs::File::open(
+        matches.value_of("CONFIG_FILE").unwrap(),
+    )?)
+    .map_err(|e| invalid_input(format!("invalid config file: {}", e)))?;
 
-    let my_id = usize::from_str_radix(&matches.value_of("PARTY_ID").unwrap(), 10).unwrap();
+    let my_id = matches
+        .value_of("PARTY_ID")
+        .unwrap()
+        .parse::<usize>()
+        .map_err(|e| invalid_input(format!("invalid party id: {}", e)))?;
 
-    let input_file = std::fs::File::open(matches.value_of("INPUT_FILE").unwrap()).unwrap();
+    let input_file = std::fs::File::open(matches.value_of("INPUT_FILE").unwrap())?;
     let inputs = BufReader::new(input_file)
         .lines()
-        .map(|s| ipv6_to_block(&s.unwrap()))
-        .collect_vec();
+        .map(|s| ipv6_to_block(&s?))
+        .collect::<Result<Vec<_>, Error>>()?;
 
     let mut cons = connect_to_parties(my_id, &config);
     let mut rng = AesRng::new();
@@ -87,7 +105,7 @@ fn main() {
 
         println!("[receiver] init");
         let init_time = SystemTime::now();
-        let mut receiver = MultiPartyReceiver::init(&mut cons, &mut rng).unwrap();
+        let mut receiver = MultiPartyReceiver::init(&mut cons, &mut rng)?;
         println!(
             "- init time: {} ms",
             init_time.elapsed().unwrap().as_millis()
@@ -95,7 +113,7 @@ fn main() {
 
         println!("[receiver] receive");
         let receive_time = SystemTime::now();
-        let intersection = receiver.receive(&inputs, &mut cons, &mut rng).unwrap();
+        let intersection = receiver.receive(&inputs, &mut cons, &mut rng)?;
         println!(
             "- receive time: {} ms",
             receive_time.elapsed().unwrap().as_millis()
@@ -122,17 +140,18 @@ fn main() {
         );
 
         if let Some(filename) = matches.value_of("OUTPUT_FILE") {
-            let mut f = std::fs::File::open(filename).unwrap();
+            let mut f = std::fs::File::create(filename)?;
             for blk in intersection {
-                writeln!(f, "{}", block_to_ipv6(blk)).unwrap();
+                writeln!(f, "{}", block_to_ipv6(blk))?;
             }
         }
     } else {
         println!("[sender] init");
-        let mut sender = MultiPartySender::init(my_id, &mut cons, &mut rng).unwrap();
+        let mut sender = MultiPartySender::init(my_id, &mut cons, &mut rng)?;
         println!("[sender] send");
-        sender.send(&inputs, &mut cons, &mut rng).unwrap();
+        sender.send(&inputs, &mut cons, &mut rng)?;
     }
+    Ok(())
 }
 
 fn connect_to_parties(
@@ -202,14 +221,18 @@ fn connect_to_parties(
         .collect()
 }
 
-fn ipv6_to_block(addr: &str) -> Block {
+fn ipv6_to_block(addr: &str) -> Result<Block, Error> {
+    let invalid = || invalid_input(format!("invalid IPv6 address: {}", addr));
     let mut nums = [0_u8; 16];
     for (i, hex) in addr.split(":").enumerate() {
-        let x = u16::from_str_radix(hex, 16).unwrap();
+        if i >= 8 {
+            return Err(invalid());
+        }
+        let x = u16::from_str_radix(hex, 16).map_err(|_| invalid())?;
         nums[2 * i + 1] = (x & 0xFF) as u8;
         nums[2 * i] = ((x >> 8) & 0xFF) as u8;
     }
-    Block::from(nums)
+    Ok(Block::from(nums))
 }
 
 fn block_to_ipv6(b: Block) -> String {
@@ -223,10 +246,10 @@ fn block_to_ipv6(b: Block) -> String {
 fn read_usize<R: Read>(r: &mut R) -> usize {
     let mut buf = [0; 8];
     r.read(&mut buf).unwrap();
-    unsafe { std::mem::transmute(buf) }
+    usize::from_ne_bytes(buf)
 }
 
 fn write_usize<W: Write>(x: usize, w: &mut W) {
-    let buf: [u8; 8] = unsafe { std::mem::transmute(x) };
+    let buf = x.to_ne_bytes();
     w.write(&buf).unwrap();
 }
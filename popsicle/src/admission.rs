@@ -0,0 +1,289 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! An optional admission check a server runs before any PSI protocol.
+//!
+//! Setting up a PSI session costs the server base OTs and OPRF work, which an
+//! anonymous client gets for free. A server exposed to such clients can call
+//! `admit` on each new connection, before initializing its `Sender` or
+//! `Receiver`, and the client answers with `request_admission`. Depending on
+//! the `Requirement`, the client either solves a proof of work on a fresh
+//! challenge, making each session cost the client more than it costs the
+//! server to check, or presents a token the server issued with a
+//! `TokenKey`. Connections which fail the check are closed before any
+//! expensive work.
+//!
+//! Tokens are bearer credentials: anyone who observes one can replay it, so
+//! they should only travel over an encrypted transport.
+
+use crate::Error;
+use rand::{CryptoRng, Rng, RngCore};
+use scuttlebutt::{keyschedule::hmac, utils::constant_time_eq, AbstractChannel};
+use sha2::{Digest, Sha256};
+
+// Separates the proofs of work of this check from other uses of SHA-256.
+const POW_DOMAIN: &[u8] = b"popsicle admission";
+const CHALLENGE_SIZE: usize = 16;
+const TAG_SIZE: usize = 32;
+/// The most bytes a server reads as a token.
+pub const MAX_TOKEN_SIZE: usize = 1024;
+/// The most leading zero bits a proof of work may require.
+pub const MAX_DIFFICULTY: u32 = 64;
+
+// Tags of the requirement on the wire.
+const NOTHING: u8 = 0;
+const PROOF_OF_WORK: u8 = 1;
+const TOKEN: u8 = 2;
+
+fn denied(msg: &str) -> Error {
+    Error::AdmissionDenied(msg.to_string())
+}
+
+/// A key issuing and verifying admission tokens.
+#[derive(Clone)]
+pub struct TokenKey([u8; 32]);
+
+impl TokenKey {
+    /// Use `bytes`, which must be uniformly random and kept secret, as the key.
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Make a fresh key.
+    pub fn random<RNG: CryptoRng + RngCore>(rng: &mut RNG) -> Self {
+        Self(rng.gen())
+    }
+
+    /// Issue the token of the client `id`.
+    pub fn issue(&self, id: &[u8]) -> Vec<u8> {
+        let mut token = id.to_vec();
+        token.extend_from_slice(&hmac(&self.0, &[id]));
+        token
+    }
+
+    /// The id of the client `token` was issued to, if `token` was issued under
+    /// this key.
+    pub fn verify<'a>(&self, token: &'a [u8]) -> Option<&'a [u8]> {
+        if token.len() < TAG_SIZE {
+            return None;
+        }
+        let (id, tag) = token.split_at(token.len() - TAG_SIZE);
+        if constant_time_eq(&hmac(&self.0, &[id]), tag) {
+            Some(id)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Debug for TokenKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "TokenKey(..)")
+    }
+}
+
+/// What a server requires of a client before running a protocol.
+#[derive(Clone, Debug)]
+pub enum Requirement {
+    /// Admit every client.
+    Nothing,
+    /// Admit clients solving a proof of work with this many leading zero
+    /// bits, at most `MAX_DIFFICULTY`. Each extra bit doubles the expected
+    /// work of the client.
+    ProofOfWork(u32),
+    /// Admit clients presenting a token issued under this key.
+    Token(TokenKey),
+}
+
+/// The outcome of a successful admission check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Admitted {
+    /// The client was admitted without a token.
+    Anonymous,
+    /// The client presented the token of this id.
+    Client(Vec<u8>),
+}
+
+// The number of leading zero bits of the proof of work `nonce` on
+// `challenge`.
+fn work(challenge: &[u8], nonce: u64) -> u32 {
+    let hash = Sha256::new()
+        .chain(POW_DOMAIN)
+        .chain(challenge)
+        .chain(nonce.to_le_bytes())
+        .result();
+    let mut zeros = 0;
+    for byte in hash.iter() {
+        zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    zeros
+}
+
+// Find a nonce whose proof of work on `challenge` has `difficulty` leading
+// zero bits.
+fn solve(challenge: &[u8], difficulty: u32) -> u64 {
+    (0..)
+        .find(|nonce| work(challenge, *nonce) >= difficulty)
+        .unwrap()
+}
+
+/// Check that the client on `channel`, running `request_admission`, meets
+/// `requirement`, failing with `Error::AdmissionDenied` otherwise. The client
+/// learns the outcome.
+pub fn admit<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+    channel: &mut C,
+    requirement: &Requirement,
+    rng: &mut RNG,
+) -> Result<Admitted, Error> {
+    let admitted = match requirement {
+        Requirement::Nothing => {
+            channel.write_u8(NOTHING)?;
+            channel.flush()?;
+            return Ok(Admitted::Anonymous);
+        }
+        Requirement::ProofOfWork(difficulty) => {
+            if *difficulty > MAX_DIFFICULTY {
+                return Err(denied("the proof of work is too hard"));
+            }
+            let challenge = rng.gen::<[u8; CHALLENGE_SIZE]>();
+            channel.write_u8(PROOF_OF_WORK)?;
+            channel.write_u8(*difficulty as u8)?;
+            channel.write_bytes(&challenge)?;
+            channel.flush()?;
+            let nonce = channel.read_u64()?;
+            if work(&challenge, nonce) >= *difficulty {
+                Some(Admitted::Anonymous)
+            } else {
+                None
+            }
+        }
+        Requirement::Token(key) => {
+            channel.write_u8(TOKEN)?;
+            channel.flush()?;
+            let len = channel.read_usize()?;
+            if len > MAX_TOKEN_SIZE {
+                channel.write_bool(false)?;
+                channel.flush()?;
+                return Err(denied("the token is too long"));
+            }
+            let token = channel.read_vec(len)?;
+            key.verify(&token).map(|id| Admitted::Client(id.to_vec()))
+        }
+    };
+    channel.write_bool(admitted.is_some())?;
+    channel.flush()?;
+    admitted.ok_or_else(|| denied("the client failed the check"))
+}
+
+/// Pass the admission check of the server on `channel`, running `admit`,
+/// presenting `token` if asked for one. Proofs of work requiring more than
+/// `max_difficulty` leading zero bits are refused, so that a server cannot
+/// make its clients spin.
+pub fn request_admission<C: AbstractChannel>(
+    channel: &mut C,
+    token: Option<&[u8]>,
+    max_difficulty: u32,
+) -> Result<(), Error> {
+    match channel.read_u8()? {
+        NOTHING => return Ok(()),
+        PROOF_OF_WORK => {
+            let difficulty = u32::from(channel.read_u8()?);
+            let challenge = channel.read_vec(CHALLENGE_SIZE)?;
+            if difficulty > max_difficulty.min(MAX_DIFFICULTY) {
+                return Err(denied("the proof of work is too hard"));
+            }
+            channel.write_u64(solve(&challenge, difficulty))?;
+        }
+        TOKEN => {
+            let token = token.unwrap_or(&[]);
+            channel.write_usize(token.len())?;
+            channel.write_bytes(token)?;
+        }
+        _ => return Err(denied("unknown requirement")),
+    }
+    channel.flush()?;
+    if !channel.read_bool()? {
+        return Err(denied("the server refused the client"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    // Run `admit` against `request_admission` with `token`, returning the
+    // outcomes of the server and of the client.
+    fn run(
+        requirement: Requirement,
+        token: Option<Vec<u8>>,
+        max_difficulty: u32,
+    ) -> (Result<Admitted, Error>, Result<(), Error>) {
+        let (server, client) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(server.try_clone().unwrap());
+            let writer = BufWriter::new(server);
+            let mut channel = Channel::new(reader, writer);
+            admit(&mut channel, &requirement, &mut rng)
+        });
+        let reader = BufReader::new(client.try_clone().unwrap());
+        let writer = BufWriter::new(client);
+        let mut channel = Channel::new(reader, writer);
+        let client = request_admission(&mut channel, token.as_deref(), max_difficulty);
+        // The client hangs up on refusing the check.
+        drop(channel);
+        (handle.join().unwrap(), client)
+    }
+
+    #[test]
+    fn test_proof_of_work() {
+        let (server, client) = run(Requirement::ProofOfWork(12), None, 16);
+        assert_eq!(server.unwrap(), Admitted::Anonymous);
+        client.unwrap();
+        let (server, client) = run(Requirement::ProofOfWork(12), None, 8);
+        assert!(server.is_err());
+        assert!(client.is_err());
+        let (server, client) = run(Requirement::Nothing, None, 0);
+        assert_eq!(server.unwrap(), Admitted::Anonymous);
+        client.unwrap();
+        let challenge = [0u8; CHALLENGE_SIZE];
+        assert!(work(&challenge, solve(&challenge, 8)) >= 8);
+    }
+
+    #[test]
+    fn test_token() {
+        let mut rng = AesRng::new();
+        let key = TokenKey::random(&mut rng);
+        let token = key.issue(b"client 7");
+        assert_eq!(key.verify(&token), Some(&b"client 7"[..]));
+        assert_eq!(TokenKey::random(&mut rng).verify(&token), None);
+
+        let (server, client) = run(Requirement::Token(key.clone()), Some(token.clone()), 0);
+        assert_eq!(server.unwrap(), Admitted::Client(b"client 7".to_vec()));
+        client.unwrap();
+
+        let mut forged = token;
+        *forged.last_mut().unwrap() ^= 1;
+        let (server, client) = run(Requirement::Token(key.clone()), Some(forged), 0);
+        match server {
+            Err(Error::AdmissionDenied(_)) => (),
+            _ => panic!("a forged token was accepted"),
+        }
+        assert!(client.is_err());
+        let (server, client) = run(Requirement::Token(key), None, 0);
+        assert!(server.is_err());
+        assert!(client.is_err());
+    }
+}
@@ -0,0 +1,324 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Deliberately misbehaving counterparts of the VOPRF-based protocols, `voprf`
+//! and `unbalanced`, for testing that a deployment detects the attacks they
+//! are secure against.
+//!
+//! `Sender` plays the sender of `voprf`, or the server of `unbalanced`, and
+//! `Receiver` the receiver of `voprf`, each mounting one `SenderAttack` or
+//! `ReceiverAttack` and otherwise following the protocol. Run one against the
+//! honest party as deployed, with its channel and configuration, and check
+//! that the honest party fails rather than completing the session: the honest
+//! receivers and clients fail with `Error::PsiProtocolError` or an I/O error,
+//! and the honest sender with an I/O error.
+//!
+//! This module is only built with the `adversary` feature, and is meant for
+//! tests: the counterparts are no use against anyone but a test deployment.
+//! It does not cover PSZ, which is only secure against semi-honest parties:
+//! a PSZ peer sending the wrong number of masks, or using the OPRF
+//! inconsistently, is outside of its threat model.
+
+use crate::{psi::voprf, Error};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_TABLE,
+    ristretto::RistrettoPoint,
+    scalar::Scalar,
+};
+use rand::{seq::SliceRandom, CryptoRng, RngCore};
+use scuttlebutt::AbstractChannel;
+
+/// An attack on the receiver of `voprf`, or on the client of `unbalanced`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SenderAttack {
+    /// Answer every query under a key other than the published one, proving
+    /// consistency with the other key.
+    WrongKey,
+    /// Answer the query at this index under another key, and the others
+    /// honestly, which would tell the sender whether that item is in the
+    /// intersection.
+    SelectiveKey(usize),
+    /// Answer every query but the last.
+    MissingEvaluation,
+    /// Answer the first session honestly, and every later one with the
+    /// answers and proof of the first.
+    ReplayTranscript,
+}
+
+/// An attack on the sender of `voprf`, or on the server of `unbalanced`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReceiverAttack {
+    /// Send a query which is not the encoding of a group element.
+    InvalidPoint,
+    /// Announce one more query than it sends, then hang up.
+    ShortQuery,
+}
+
+/// A `voprf` sender, or `unbalanced` server, mounting a `SenderAttack`.
+pub struct Sender {
+    attack: SenderAttack,
+    key: Scalar,
+    public: RistrettoPoint,
+    transcript: Option<(Vec<RistrettoPoint>, Scalar, Scalar)>,
+}
+
+impl Sender {
+    /// Initialize the sender, publishing its key to the receiver as the
+    /// honest sender does.
+    pub fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        attack: SenderAttack,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        let sender = Self::from_key(attack, Scalar::random(rng));
+        channel.write_pt(&sender.public)?;
+        channel.flush()?;
+        Ok(sender)
+    }
+
+    /// Make a server holding the key returned by
+    /// `unbalanced::Server::key_bytes`, under which the client's digest was
+    /// built.
+    pub fn from_key_bytes(attack: SenderAttack, bytes: [u8; 32]) -> Result<Self, Error> {
+        let key = Scalar::from_canonical_bytes(bytes)
+            .ok_or_else(|| Error::InvalidInput("non-canonical server key".to_string()))?;
+        Ok(Self::from_key(attack, key))
+    }
+
+    fn from_key(attack: SenderAttack, key: Scalar) -> Self {
+        let public = &key * &RISTRETTO_BASEPOINT_TABLE;
+        Self {
+            attack,
+            key,
+            public,
+            transcript: None,
+        }
+    }
+
+    /// Run a session of `voprf` over `inputs`.
+    pub fn send<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        self.serve(channel, rng)?;
+        let mut outputs = inputs
+            .iter()
+            .map(|x| voprf::hash_to_output(x, &(self.key * voprf::hash_to_group(x))))
+            .collect::<Vec<_>>();
+        outputs.shuffle(rng);
+        channel.write_usize(outputs.len())?;
+        for output in &outputs {
+            channel.write_bytes(output)?;
+        }
+        channel.flush()?;
+        Ok(())
+    }
+
+    /// Run the online phase of `unbalanced` with a client.
+    pub fn serve<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        let n = channel.read_usize()?;
+        let blinded = (0..n)
+            .map(|_| channel.read_pt())
+            .collect::<Result<Vec<_>, _>>()?;
+        let (evaluated, e, s) = match (self.attack, &self.transcript) {
+            (SenderAttack::ReplayTranscript, Some(transcript)) => transcript.clone(),
+            (attack, _) => {
+                let mut key = self.key;
+                let mut public = self.public;
+                let mut evaluated = blinded.iter().map(|b| key * b).collect::<Vec<_>>();
+                match attack {
+                    SenderAttack::WrongKey => {
+                        key = Scalar::random(rng);
+                        public = &key * &RISTRETTO_BASEPOINT_TABLE;
+                        evaluated = blinded.iter().map(|b| key * b).collect();
+                    }
+                    SenderAttack::SelectiveKey(i) => {
+                        if let Some(z) = evaluated.get_mut(i) {
+                            *z = Scalar::random(rng) * blinded[i];
+                        }
+                    }
+                    SenderAttack::MissingEvaluation => {
+                        evaluated.pop();
+                    }
+                    SenderAttack::ReplayTranscript => (),
+                }
+                let blinded = &blinded[..evaluated.len()];
+                let (e, s) = voprf::prove(&key, &public, blinded, &evaluated, rng);
+                (evaluated, e, s)
+            }
+        };
+        for z in &evaluated {
+            channel.write_pt(z)?;
+        }
+        channel.write_bytes(e.as_bytes())?;
+        channel.write_bytes(s.as_bytes())?;
+        channel.flush()?;
+        if self.transcript.is_none() {
+            self.transcript = Some((evaluated, e, s));
+        }
+        Ok(())
+    }
+}
+
+/// A `voprf` receiver, or `unbalanced` client, mounting a `ReceiverAttack`.
+pub struct Receiver {
+    attack: ReceiverAttack,
+}
+
+impl Receiver {
+    /// Initialize the receiver, reading the sender's key.
+    pub fn init<C: AbstractChannel>(
+        attack: ReceiverAttack,
+        channel: &mut C,
+    ) -> Result<Self, Error> {
+        channel.read_pt()?;
+        Ok(Self::new(attack))
+    }
+
+    /// Make a client of an `unbalanced` server, which publishes no key.
+    pub fn new(attack: ReceiverAttack) -> Self {
+        Self { attack }
+    }
+
+    /// Send the malformed query of `inputs`, returning once it is sent. The
+    /// channel should then be dropped, so that the sender sees it close.
+    pub fn query<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        let blinded = inputs
+            .iter()
+            .map(|y| Scalar::random(rng) * voprf::hash_to_group(y))
+            .collect::<Vec<_>>();
+        match self.attack {
+            ReceiverAttack::InvalidPoint => {
+                channel.write_usize(blinded.len().max(1))?;
+                // Not the canonical encoding of any element.
+                channel.write_bytes(&[0xff; 32])?;
+                for b in blinded.iter().skip(1) {
+                    channel.write_pt(b)?;
+                }
+            }
+            ReceiverAttack::ShortQuery => {
+                channel.write_usize(blinded.len() + 1)?;
+                for b in &blinded {
+                    channel.write_pt(b)?;
+                }
+            }
+        }
+        channel.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        psi::unbalanced::{Client, Server},
+        utils::rand_vec_vec,
+    };
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const ITEM_SIZE: usize = 8;
+    const SET_SIZE: usize = 1 << 5;
+
+    fn channel(stream: UnixStream) -> Channel<BufReader<UnixStream>, BufWriter<UnixStream>> {
+        Channel::new(
+            BufReader::new(stream.try_clone().unwrap()),
+            BufWriter::new(stream),
+        )
+    }
+
+    #[test]
+    fn test_sender_attacks() {
+        let attacks = [
+            SenderAttack::WrongKey,
+            SenderAttack::SelectiveKey(3),
+            SenderAttack::MissingEvaluation,
+        ];
+        for &attack in &attacks {
+            let mut rng = AesRng::new();
+            let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+            let inputs_ = inputs.clone();
+            let (sender, receiver) = UnixStream::pair().unwrap();
+            let handle = std::thread::spawn(move || {
+                let mut rng = AesRng::new();
+                let mut channel = channel(sender);
+                let mut psi = Sender::init(attack, &mut channel, &mut rng).unwrap();
+                let _ = psi.send(&inputs_, &mut channel, &mut rng);
+            });
+            let mut channel = channel(receiver);
+            let mut psi = voprf::Receiver::init(&mut channel, &mut rng).unwrap();
+            let result = psi.receive(&inputs, &mut channel, &mut rng);
+            drop(channel);
+            handle.join().unwrap();
+            assert!(result.is_err(), "the receiver accepted {:?}", attack);
+        }
+    }
+
+    #[test]
+    fn test_replayed_transcript() {
+        let mut rng = AesRng::new();
+        let server = Server::new(&mut rng);
+        let key = server.key_bytes();
+        let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut client = Client::new(server.digest(&inputs));
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let mut channel = channel(sender);
+            let mut server = Sender::from_key_bytes(SenderAttack::ReplayTranscript, key).unwrap();
+            server.serve(&mut channel, &mut rng).unwrap();
+            server.serve(&mut channel, &mut rng).unwrap();
+        });
+        let mut channel = channel(receiver);
+        // The first session is honest.
+        let intersection = client.receive(&inputs, &mut channel, &mut rng).unwrap();
+        assert_eq!(intersection, inputs);
+        match client.receive(&inputs, &mut channel, &mut rng) {
+            Err(Error::PsiProtocolError(_)) => (),
+            _ => panic!("the client accepted a replayed transcript"),
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_receiver_attacks() {
+        for &attack in &[ReceiverAttack::InvalidPoint, ReceiverAttack::ShortQuery] {
+            let mut rng = AesRng::new();
+            let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+            let inputs_ = inputs.clone();
+            let (sender, receiver) = UnixStream::pair().unwrap();
+            let handle = std::thread::spawn(move || {
+                let mut rng = AesRng::new();
+                let mut channel = channel(sender);
+                let mut psi = voprf::Sender::init(&mut channel, &mut rng).unwrap();
+                psi.send(&inputs_, &mut channel, &mut rng)
+            });
+            let mut channel = channel(receiver);
+            let mut psi = Receiver::init(attack, &mut channel).unwrap();
+            psi.query(&inputs, &mut channel, &mut rng).unwrap();
+            drop(channel);
+            match handle.join().unwrap() {
+                Err(Error::IoError(_)) => (),
+                _ => panic!("the sender accepted {:?}", attack),
+            }
+        }
+    }
+}
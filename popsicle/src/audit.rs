@@ -0,0 +1,448 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Chunked cross-validation of a PSI run.
+//!
+//! Before running PSI, both parties agree on a random key partitioning the
+//! universe of items into `nchunks` chunks, and commit to a digest of the items
+//! they hold in each chunk. After the PSI run, they toss coins to sample
+//! `nsamples` chunks, open their commitments, and reveal their items in the
+//! sampled chunks. Each party then checks that the revealed items match the
+//! committed digests, and that the intersection cardinality reported by the
+//! receiver for each sampled chunk matches the one recomputed from the revealed
+//! items.
+//!
+//! A party that manipulated the run in a fraction `f` of the chunks is caught
+//! with probability roughly `1 - (1 - f)^nsamples`. Note that the items of the
+//! sampled chunks are disclosed to the other party: this mode is meant for
+//! partnerships where such a disclosure is acceptable.
+//!
+//! A party reads at most `MAX_REVEALED_ITEMS` items of at most
+//! `MAX_ITEM_SIZE` bytes from the peer's opening, rejecting larger counts
+//! before allocating.
+
+use crate::Error;
+use rand::{seq::index, CryptoRng, Rng, RngCore, SeedableRng};
+use scuttlebutt::{
+    cointoss,
+    commitment::{Commitment, ShaCommitment},
+    AbstractChannel,
+    AesRng,
+    Block,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+/// The most items a party reads from the peer's opening of the sampled
+/// chunks.
+pub const MAX_REVEALED_ITEMS: usize = 1 << 24;
+/// The largest item, in bytes, a party reads from the peer's opening.
+pub const MAX_ITEM_SIZE: usize = 1 << 20;
+
+/// Parameters of the audit, which both parties must agree on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AuditParams {
+    /// The number of chunks the items are partitioned into.
+    pub nchunks: usize,
+    /// The number of chunks sampled for auditing.
+    pub nsamples: usize,
+}
+
+/// The outcome of a successful audit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditReport {
+    /// The sampled chunk indices.
+    pub chunks: Vec<usize>,
+    /// The intersection cardinality of each sampled chunk.
+    pub cardinalities: Vec<usize>,
+}
+
+// State common to both parties.
+struct Committed {
+    params: AuditParams,
+    key: Block,
+    chunks: Vec<Vec<Vec<u8>>>,
+    digests: Vec<[u8; 32]>,
+    seed: [u8; 32],
+    peer_commitment: [u8; 32],
+}
+
+/// Audit sender, i.e., the party playing the PSI sender.
+pub struct Sender(Committed);
+/// Audit receiver, i.e., the party playing the PSI receiver.
+pub struct Receiver(Committed);
+
+fn chunk_index(key: Block, item: &[u8], nchunks: usize) -> usize {
+    let mut hasher = Sha256::new();
+    hasher.input(key.as_ref());
+    hasher.input(item);
+    let h = hasher.result();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&h[0..8]);
+    (u64::from_le_bytes(bytes) % nchunks as u64) as usize
+}
+
+fn chunk_digest(items: &[Vec<u8>]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input((items.len() as u64).to_le_bytes());
+    for item in items {
+        hasher.input((item.len() as u64).to_le_bytes());
+        hasher.input(item);
+    }
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&hasher.result());
+    digest
+}
+
+fn commit_to(digests: &[[u8; 32]], seed: [u8; 32]) -> [u8; 32] {
+    let mut commit = ShaCommitment::new(seed);
+    for digest in digests {
+        commit.input(digest);
+    }
+    commit.finish()
+}
+
+impl Committed {
+    fn new<RNG: CryptoRng + RngCore>(
+        inputs: &[Vec<u8>],
+        params: AuditParams,
+        key: Block,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        if params.nchunks == 0 || params.nsamples > params.nchunks {
+            return Err(Error::InvalidInput(format!(
+                "invalid audit parameters: {} samples of {} chunks",
+                params.nsamples, params.nchunks
+            )));
+        }
+        let mut chunks = vec![Vec::new(); params.nchunks];
+        for input in inputs {
+            chunks[chunk_index(key, input, params.nchunks)].push(input.clone());
+        }
+        // Sort chunks so the digests do not depend on the input order.
+        for chunk in chunks.iter_mut() {
+            chunk.sort();
+        }
+        let digests = chunks.iter().map(|c| chunk_digest(c)).collect::<Vec<_>>();
+        let seed = rng.gen::<[u8; 32]>();
+        Ok(Committed {
+            params,
+            key,
+            chunks,
+            digests,
+            seed,
+            peer_commitment: [0u8; 32],
+        })
+    }
+
+    // Open our commitment and reveal the items of the sampled chunks.
+    fn write_opening<C: AbstractChannel>(
+        &self,
+        channel: &mut C,
+        sample: &[usize],
+    ) -> Result<(), Error> {
+        channel.write_bytes(&self.seed)?;
+        for digest in self.digests.iter() {
+            channel.write_bytes(digest)?;
+        }
+        for &i in sample {
+            channel.write_usize(self.chunks[i].len())?;
+            for item in self.chunks[i].iter() {
+                channel.write_usize(item.len())?;
+                channel.write_bytes(item)?;
+            }
+        }
+        channel.flush()?;
+        Ok(())
+    }
+
+    // Check the peer's opening and revealed items, returning the items of
+    // each sampled chunk.
+    fn read_opening<C: AbstractChannel>(
+        &self,
+        channel: &mut C,
+        sample: &[usize],
+    ) -> Result<Vec<HashSet<Vec<u8>>>, Error> {
+        let mut seed = [0u8; 32];
+        channel.read_bytes(&mut seed)?;
+        let mut digests = vec![[0u8; 32]; self.params.nchunks];
+        for digest in digests.iter_mut() {
+            channel.read_bytes(digest)?;
+        }
+        if !ShaCommitment::check(&commit_to(&digests, seed), &self.peer_commitment) {
+            return Err(Error::AuditFailed(
+                "commitment to chunk digests does not open".to_string(),
+            ));
+        }
+        let mut revealed = Vec::with_capacity(sample.len());
+        let mut nrevealed = 0usize;
+        for &i in sample {
+            let nitems = channel.read_usize()?;
+            nrevealed = nrevealed.saturating_add(nitems);
+            if nrevealed > MAX_REVEALED_ITEMS {
+                return Err(Error::PsiProtocolError(format!(
+                    "the peer revealed {} items, more than the limit of {}",
+                    nrevealed, MAX_REVEALED_ITEMS
+                )));
+            }
+            let mut items = Vec::with_capacity(nitems);
+            for _ in 0..nitems {
+                let len = channel.read_usize()?;
+                if len > MAX_ITEM_SIZE {
+                    return Err(Error::PsiProtocolError(format!(
+                        "the peer revealed an item of {} bytes, more than the limit of {}",
+                        len, MAX_ITEM_SIZE
+                    )));
+                }
+                items.push(channel.read_vec(len)?);
+            }
+            let sorted = items.windows(2).all(|w| w[0] < w[1]);
+            if !sorted || chunk_digest(&items) != digests[i] {
+                return Err(Error::AuditFailed(format!(
+                    "revealed items do not match the digest of chunk {}",
+                    i
+                )));
+            }
+            if items
+                .iter()
+                .any(|item| chunk_index(self.key, item, self.params.nchunks) != i)
+            {
+                return Err(Error::AuditFailed(format!(
+                    "revealed items do not belong to chunk {}",
+                    i
+                )));
+            }
+            revealed.push(items.into_iter().collect());
+        }
+        Ok(revealed)
+    }
+
+    fn sample(&self, seed: Block) -> Vec<usize> {
+        let mut rng = AesRng::from_seed(seed);
+        let mut sample =
+            index::sample(&mut rng, self.params.nchunks, self.params.nsamples).into_vec();
+        sample.sort();
+        sample
+    }
+
+    fn cardinalities(&self, sample: &[usize], revealed: &[HashSet<Vec<u8>>]) -> Vec<usize> {
+        sample
+            .iter()
+            .zip(revealed.iter())
+            .map(|(&i, theirs)| {
+                self.chunks[i]
+                    .iter()
+                    .filter(|x| theirs.contains(*x))
+                    .count()
+            })
+            .collect()
+    }
+}
+
+impl Sender {
+    /// Commit to `inputs`. Must be run before the PSI protocol.
+    pub fn commit<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        inputs: &[Vec<u8>],
+        params: AuditParams,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        channel.write_usize(params.nchunks)?;
+        channel.write_usize(params.nsamples)?;
+        channel.flush()?;
+        let key = cointoss::send(channel, &[rng.gen()])?[0];
+        let mut state = Committed::new(inputs, params, key, rng)?;
+        channel.write_bytes(&commit_to(&state.digests, state.seed))?;
+        channel.flush()?;
+        channel.read_bytes(&mut state.peer_commitment)?;
+        Ok(Sender(state))
+    }
+
+    /// Audit the PSI run. Must be run after the PSI protocol.
+    pub fn audit<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<AuditReport, Error> {
+        let state = self.0;
+        let seed = cointoss::send(channel, &[rng.gen()])?[0];
+        let sample = state.sample(seed);
+        state.write_opening(channel, &sample)?;
+        let revealed = state.read_opening(channel, &sample)?;
+        let cardinalities = state.cardinalities(&sample, &revealed);
+        for (&i, &expected) in sample.iter().zip(cardinalities.iter()) {
+            let reported = channel.read_usize()?;
+            if reported != expected {
+                return Err(Error::AuditFailed(format!(
+                    "receiver reported {} matches in chunk {}, expected {}",
+                    reported, i, expected
+                )));
+            }
+        }
+        Ok(AuditReport {
+            chunks: sample,
+            cardinalities,
+        })
+    }
+}
+
+impl Receiver {
+    /// Commit to `inputs`. Must be run before the PSI protocol.
+    pub fn commit<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        inputs: &[Vec<u8>],
+        params: AuditParams,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        let nchunks = channel.read_usize()?;
+        let nsamples = channel.read_usize()?;
+        if (nchunks, nsamples) != (params.nchunks, params.nsamples) {
+            return Err(Error::PsiProtocolError(format!(
+                "audit parameter mismatch: sender uses {} samples of {} chunks",
+                nsamples, nchunks
+            )));
+        }
+        let key = cointoss::receive(channel, &[rng.gen()])?[0];
+        let mut state = Committed::new(inputs, params, key, rng)?;
+        channel.read_bytes(&mut state.peer_commitment)?;
+        channel.write_bytes(&commit_to(&state.digests, state.seed))?;
+        channel.flush()?;
+        Ok(Receiver(state))
+    }
+
+    /// Audit the PSI run, given the `intersection` it output. Must be run
+    /// after the PSI protocol.
+    pub fn audit<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        self,
+        intersection: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<AuditReport, Error> {
+        let state = self.0;
+        let seed = cointoss::receive(channel, &[rng.gen()])?[0];
+        let sample = state.sample(seed);
+        let revealed = state.read_opening(channel, &sample)?;
+        state.write_opening(channel, &sample)?;
+        let cardinalities = state.cardinalities(&sample, &revealed);
+
+        let mut reported = vec![0; state.params.nchunks];
+        for item in intersection {
+            reported[chunk_index(state.key, item, state.params.nchunks)] += 1;
+        }
+        for &i in sample.iter() {
+            channel.write_usize(reported[i])?;
+        }
+        channel.flush()?;
+        for (&i, &expected) in sample.iter().zip(cardinalities.iter()) {
+            if reported[i] != expected {
+                return Err(Error::AuditFailed(format!(
+                    "PSI output {} matches in chunk {}, expected {}",
+                    reported[i], i, expected
+                )));
+            }
+        }
+        Ok(AuditReport {
+            chunks: sample,
+            cardinalities,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{psi::psz, utils::rand_vec_vec};
+    use scuttlebutt::Channel;
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const ITEM_SIZE: usize = 8;
+    const SET_SIZE: usize = 1 << 10;
+    const PARAMS: AuditParams = AuditParams {
+        nchunks: 64,
+        nsamples: 8,
+    };
+
+    // Run PSZ with auditing, letting the receiver tamper with its PSI output
+    // via `tamper`.
+    fn run(
+        tamper: fn(&mut Vec<Vec<u8>>),
+    ) -> (Result<AuditReport, Error>, Result<AuditReport, Error>) {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(SET_SIZE / 2, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..SET_SIZE / 2]);
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let audit = Sender::commit(&sender_inputs, PARAMS, &mut channel, &mut rng).unwrap();
+            let mut psi = psz::Sender::init(&mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+            audit.audit(&mut channel, &mut rng)
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let audit = Receiver::commit(&receiver_inputs, PARAMS, &mut channel, &mut rng).unwrap();
+        let mut psi = psz::Receiver::init(&mut channel, &mut rng).unwrap();
+        let mut intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        tamper(&mut intersection);
+        let receiver_report = audit.audit(&intersection, &mut channel, &mut rng);
+        (handle.join().unwrap(), receiver_report)
+    }
+
+    #[test]
+    fn test_audit_honest() {
+        let (sender_report, receiver_report) = run(|_| ());
+        let sender_report = sender_report.unwrap();
+        let receiver_report = receiver_report.unwrap();
+        assert_eq!(sender_report, receiver_report);
+        assert_eq!(sender_report.chunks.len(), PARAMS.nsamples);
+    }
+
+    #[test]
+    fn test_audit_detects_dropped_items() {
+        // Dropping every matched item makes every non-empty sampled chunk
+        // disagree; with 512 matches in 64 chunks some sampled chunk is
+        // non-empty with overwhelming probability.
+        let (sender_report, receiver_report) = run(|xs| xs.clear());
+        assert!(sender_report.is_err());
+        assert!(receiver_report.is_err());
+    }
+
+    #[test]
+    fn test_audit_caps_revealed_items() {
+        let mut rng = AesRng::new();
+        let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let key = rng.gen();
+        let mut state = Committed::new(&inputs, PARAMS, key, &mut rng).unwrap();
+        state.peer_commitment = commit_to(&state.digests, state.seed);
+        let sample = state.sample(rng.gen());
+        let mut opening = Vec::new();
+        {
+            let mut channel = Channel::new(std::io::empty(), &mut opening);
+            channel.write_bytes(&state.seed).unwrap();
+            for digest in state.digests.iter() {
+                channel.write_bytes(digest).unwrap();
+            }
+            channel.write_usize(MAX_REVEALED_ITEMS + 1).unwrap();
+            channel.flush().unwrap();
+        }
+        let mut channel = Channel::new(&opening[..], std::io::sink());
+        match state.read_opening(&mut channel, &sample) {
+            Err(Error::PsiProtocolError(_)) => (),
+            _ => panic!("the party read more items than the limit"),
+        }
+    }
+}
@@ -0,0 +1,523 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Operational configuration for applications built on `popsicle`, read from
+//! TOML.
+//!
+//! A config file declares which protocol to run, the security level it must
+//! meet, how to reach the other parties, resource limits on the inputs, and
+//! how long cached state stays valid. Every section and field may be omitted
+//! in favor of its default:
+//!
+//! ```toml
+//! protocol = "psz"
+//!
+//! [security]
+//! level = "standard"
+//! statistical = 40
+//! computational = 128
+//!
+//! [network]
+//! role = "receiver"
+//! address = "0.0.0.0:7000"
+//! peer = "10.0.0.2:7000"
+//! timeout_ms = 10000
+//!
+//! [limits]
+//! max_set_size = 1048576
+//! max_item_size = 64
+//! threads = 4
+//!
+//! [expiry]
+//! session_ms = 3600000
+//! ticket_ms = 86400000
+//! base_ot_max_age_ms = 604800000
+//! digest_ms = 2592000000
+//! ```
+//!
+//! Multi-party sessions name a `topology` file (see `crate::topology`) under
+//! `[network]` in place of `peer`. `Config::validate`, which runs whenever a
+//! config is parsed, checks the combination of fields. `Config::psz_sender`
+//! and `Config::psz_receiver` start PSZ sessions with the settings of the
+//! config, as the `psi` binary does with `--config`, where the sender also
+//! listens on `address` and the receiver connects to `peer`.
+//! `Config::unbalanced_server` and `Config::ticket_issuer` make the long-lived
+//! state whose lifetimes `[expiry]` sets. `Config::connect_parties` opens the
+//! channels of a multi-party session to the parties of the topology file.
+
+use crate::{
+    psi::{kmprt::PartyId, psz, unbalanced},
+    security::{SecurityLevel, SecurityParams},
+    topology::{Role, TcpChannel, Topology},
+    Error,
+};
+use ocelot::ot::resumption::TicketIssuer;
+use rand::{CryptoRng, Rng, RngCore};
+use scuttlebutt::AbstractChannel;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+pub use crate::any::Protocol;
+
+/// The security level a session must meet: a `SecurityLevel`, whose
+/// `SecurityParams` may be overridden.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Security {
+    /// The preset of the session's settings.
+    pub level: SecurityLevel,
+    /// Statistical security, in bits, if not that of `level`.
+    pub statistical: Option<usize>,
+    /// Computational security, in bits, if not that of `level`.
+    pub computational: Option<usize>,
+}
+
+impl Security {
+    /// Return the security parameters, failing unless they are supported.
+    pub fn params(&self) -> Result<SecurityParams, Error> {
+        let params = self.level.params();
+        SecurityParams::new(
+            self.statistical.unwrap_or(params.lambda_stat),
+            self.computational.unwrap_or(params.lambda_comp),
+        )
+    }
+}
+
+/// How to reach the other parties.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Network {
+    /// Our role in the session.
+    pub role: Role,
+    /// The address, as `host:port`, we listen on.
+    pub address: String,
+    /// The address of the other party, for two-party protocols.
+    pub peer: Option<String>,
+    /// The topology file describing all parties, for multi-party protocols.
+    pub topology: Option<PathBuf>,
+    /// How long to wait for another party to connect, in milliseconds.
+    pub timeout_ms: u64,
+}
+
+impl Network {
+    /// Return the connection timeout.
+    #[inline]
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self {
+            role: Role::Receiver,
+            address: "127.0.0.1:7000".to_string(),
+            peer: None,
+            topology: None,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+/// Limits on the resources a session may use.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// The maximum number of items in our set, if any.
+    pub max_set_size: Option<usize>,
+    /// The maximum length of a single item in bytes, if any.
+    pub max_item_size: Option<usize>,
+    /// The number of worker threads.
+    pub threads: usize,
+}
+
+impl Limits {
+    /// Check that `inputs` respects the limits.
+    pub fn check(&self, inputs: &[Vec<u8>]) -> Result<(), Error> {
+        if let Some(max) = self.max_set_size {
+            if inputs.len() > max {
+                return Err(invalid(format!(
+                    "set of {} items exceeds the limit of {}",
+                    inputs.len(),
+                    max
+                )));
+            }
+        }
+        if let Some(max) = self.max_item_size {
+            if let Some(i) = inputs.iter().position(|x| x.len() > max) {
+                return Err(invalid(format!(
+                    "item {} is longer than the limit of {} bytes",
+                    i, max
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_set_size: None,
+            max_item_size: None,
+            threads: 1,
+        }
+    }
+}
+
+/// How long cached protocol state stays valid before it must be rebuilt.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Expiry {
+    /// The lifetime of PSZ sessions and their checkpoints, in milliseconds,
+    /// if any.
+    pub session_ms: Option<u64>,
+    /// The lifetime of session resumption tickets, in milliseconds.
+    pub ticket_ms: u64,
+    /// How long resumption tickets are renewed for the same base OTs, in
+    /// milliseconds.
+    pub base_ot_max_age_ms: u64,
+    /// The lifetime of the server key and digest of unbalanced PSI, in
+    /// milliseconds, if any.
+    pub digest_ms: Option<u64>,
+}
+
+impl Expiry {
+    /// Return the lifetime of PSZ sessions, for `Sender::set_lifetime` and
+    /// `Receiver::set_lifetime` of `crate::psi::psz`.
+    #[inline]
+    pub fn session(&self) -> Option<Duration> {
+        self.session_ms.map(Duration::from_millis)
+    }
+
+    /// Return the lifetime of resumption tickets, as issued by
+    /// `Config::ticket_issuer`.
+    #[inline]
+    pub fn ticket(&self) -> Duration {
+        Duration::from_millis(self.ticket_ms)
+    }
+
+    /// Return the maximum age of resumed base OTs, as enforced by
+    /// `Config::ticket_issuer`.
+    #[inline]
+    pub fn base_ot_max_age(&self) -> Duration {
+        Duration::from_millis(self.base_ot_max_age_ms)
+    }
+
+    /// Return the lifetime of unbalanced PSI keys and digests, from which
+    /// `Config::unbalanced_server` sets the expiry of the keys it makes.
+    #[inline]
+    pub fn digest(&self) -> Option<Duration> {
+        self.digest_ms.map(Duration::from_millis)
+    }
+}
+
+impl Default for Expiry {
+    fn default() -> Self {
+        Self {
+            session_ms: None,
+            ticket_ms: 24 * 60 * 60 * 1000,
+            base_ot_max_age_ms: ocelot::ot::resumption::DEFAULT_MAX_AGE.as_millis() as u64,
+            digest_ms: None,
+        }
+    }
+}
+
+/// An application configuration.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The protocol to run.
+    pub protocol: Protocol,
+    /// The security level required.
+    pub security: Security,
+    /// How to reach the other parties.
+    pub network: Network,
+    /// Resource limits.
+    pub limits: Limits,
+    /// Lifetimes of cached state.
+    pub expiry: Expiry,
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidConfig(msg.into())
+}
+
+impl Config {
+    /// Read a config from the TOML file at `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Render the config as TOML.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("configs serialize to TOML")
+    }
+
+    /// Check that the fields of the config are consistent with each other and
+    /// with what the protocols provide.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.security.params()?;
+        self.security.level.check_build()?;
+        match (
+            self.protocol.is_multiparty(),
+            &self.network.peer,
+            &self.network.topology,
+        ) {
+            (false, Some(_), None) | (true, None, Some(_)) => (),
+            (false, _, _) => {
+                return Err(invalid(
+                    "two-party protocols need a `peer` and no `topology`",
+                ))
+            }
+            (true, _, _) => {
+                return Err(invalid(
+                    "multi-party protocols need a `topology` and no `peer`",
+                ))
+            }
+        }
+        if self.limits.threads == 0 {
+            return Err(invalid("`threads` must be at least one"));
+        }
+        let expiry = &self.expiry;
+        if expiry.session_ms == Some(0)
+            || expiry.ticket_ms == 0
+            || expiry.base_ot_max_age_ms == 0
+            || expiry.digest_ms == Some(0)
+        {
+            return Err(invalid("lifetimes under `[expiry]` must be positive"));
+        }
+        if expiry.ticket_ms > expiry.base_ot_max_age_ms {
+            return Err(invalid(
+                "`ticket_ms` exceeds `base_ot_max_age_ms`, which caps every ticket",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Initialize a PSZ sender with the security level and parameters,
+    /// session lifetime and threads of the config. The receiver must use the
+    /// same security settings.
+    pub fn psz_sender<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<psz::Sender, Error> {
+        let mut psi = psz::Sender::init_with_level(channel, rng, self.security.level)?;
+        psi.set_security(self.security.params()?);
+        psi.set_lifetime(self.expiry.session());
+        psi.set_threads(self.limits.threads)?;
+        Ok(psi)
+    }
+
+    /// Initialize a PSZ receiver with the security level and parameters and
+    /// session lifetime of the config, as `psz_sender` does the sender.
+    pub fn psz_receiver<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<psz::Receiver, Error> {
+        let mut psi = psz::Receiver::init_with_level(channel, rng, self.security.level)?;
+        psi.set_security(self.security.params()?);
+        psi.set_lifetime(self.expiry.session());
+        Ok(psi)
+    }
+
+    /// Open the channels of a multi-party session to every other party of
+    /// the `topology` file, within the connection `timeout_ms`, returning our
+    /// party id and the channels, as `Topology::connect` does. We are the
+    /// party of the topology at `address`, which must have our `role`.
+    pub fn connect_parties(&self) -> Result<(PartyId, Vec<(PartyId, TcpChannel)>), Error> {
+        let path = self
+            .network
+            .topology
+            .as_ref()
+            .ok_or_else(|| invalid("two-party configs have no `topology`"))?;
+        let topology = Topology::from_file(path)?;
+        let me = topology
+            .parties()
+            .iter()
+            .position(|party| party.address() == self.network.address)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "`address` {} is not a party of the topology",
+                    self.network.address
+                ))
+            })?;
+        if topology.parties()[me].role() != self.network.role {
+            return Err(invalid(format!(
+                "the topology gives party {} another `role`",
+                me
+            )));
+        }
+        let channels = topology.connect(me, self.network.timeout())?;
+        Ok((me, channels))
+    }
+
+    /// Make an unbalanced PSI server with a fresh key, expiring after the
+    /// digest lifetime of the config.
+    pub fn unbalanced_server<RNG: CryptoRng + RngCore>(&self, rng: &mut RNG) -> unbalanced::Server {
+        let mut server = unbalanced::Server::new(rng);
+        server.set_expiry(
+            self.expiry
+                .digest()
+                .map(|lifetime| SystemTime::now() + lifetime),
+        );
+        server
+    }
+
+    /// Make an issuer of session resumption tickets with a random key, and
+    /// the ticket lifetime and maximum base OT age of the config.
+    pub fn ticket_issuer<RNG: CryptoRng + Rng>(&self, rng: &mut RNG) -> TicketIssuer {
+        TicketIssuer::random(rng, self.expiry.ticket()).with_max_age(self.expiry.base_ot_max_age())
+    }
+}
+
+impl FromStr for Config {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let config = toml::from_str::<Config>(s).map_err(|e| invalid(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = "
+            protocol = \"kmprt\"
+
+            [network]
+            role = \"sender\"
+            address = \"10.0.0.2:7000\"
+            topology = \"parties.txt\"
+
+            [limits]
+            max_set_size = 4
+
+            [expiry]
+            session_ms = 60000
+        "
+        .parse::<Config>()
+        .unwrap();
+        assert_eq!(config.protocol, Protocol::Kmprt);
+        assert!(!config.protocol.is_any());
+        assert_eq!(config.security, Security::default());
+        assert_eq!(
+            config.security.params().unwrap(),
+            SecurityLevel::Standard.params()
+        );
+        assert_eq!(config.network.role, Role::Sender);
+        assert_eq!(config.network.timeout(), Duration::from_secs(10));
+        assert_eq!(config.limits.threads, 1);
+        assert_eq!(config.expiry.session(), Some(Duration::from_secs(60)));
+        assert_eq!(config.expiry.ticket(), Duration::from_secs(24 * 60 * 60));
+        assert_eq!(config.expiry.digest(), None);
+        let mut rng = scuttlebutt::AesRng::new();
+        assert_eq!(
+            config.unbalanced_server(&mut rng).digest(&[]).expires(),
+            None
+        );
+        let expiring = "[network]\npeer = \"a:1\"\n[expiry]\ndigest_ms = 60000\n"
+            .parse::<Config>()
+            .unwrap();
+        let digest = expiring.unbalanced_server(&mut rng).digest(&[]);
+        assert!(digest.expires() > Some(SystemTime::now()));
+        assert_eq!(config.to_toml().parse::<Config>().unwrap(), config);
+        assert!(config.limits.check(&vec![vec![0; 100]; 4]).is_ok());
+        assert!(config.limits.check(&vec![vec![0; 1]; 5]).is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let peer = "[network]\npeer = \"a:1\"\n";
+        assert!(peer.parse::<Config>().is_ok());
+        // Two-party protocols need a peer, and multi-party ones a topology.
+        assert!("".parse::<Config>().is_err());
+        assert!(format!("protocol = \"kmprt\"\n{}", peer)
+            .parse::<Config>()
+            .is_err());
+        assert!(format!("protocol = \"oprf\"\n{}", peer)
+            .parse::<Config>()
+            .is_err());
+        assert!(format!("{}threads = 2\n", peer).parse::<Config>().is_err());
+        assert!(format!("{}[security]\nstatistical = 64\n", peer)
+            .parse::<Config>()
+            .is_ok());
+        assert_eq!(
+            format!("{}[security]\nlevel = \"strict\"\n", peer)
+                .parse::<Config>()
+                .is_ok(),
+            cfg!(all(feature = "zeroize", feature = "hardened"))
+        );
+        assert!(format!("{}[security]\nstatistical = 129\n", peer)
+            .parse::<Config>()
+            .is_err());
+        assert!(format!("{}[security]\ncomputational = 256\n", peer)
+            .parse::<Config>()
+            .is_err());
+        assert!(format!("{}[limits]\nthreads = 0\n", peer)
+            .parse::<Config>()
+            .is_err());
+        assert!(format!("{}[expiry]\nsession_ms = 0\n", peer)
+            .parse::<Config>()
+            .is_err());
+        assert!(format!("{}[expiry]\nbase_ot_max_age_ms = 1000\n", peer)
+            .parse::<Config>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_two_party() {
+        let config = "protocol = \"ecdh\"\n[network]\npeer = \"a:1\"\n"
+            .parse::<Config>()
+            .unwrap();
+        assert_eq!(config.protocol, Protocol::Dh);
+        assert!(config.protocol.is_any());
+        assert_eq!(config.to_toml().parse::<Config>().unwrap(), config);
+    }
+
+    #[test]
+    fn test_connect_parties() {
+        let addresses = (0..2)
+            .map(|_| {
+                let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+                listener.local_addr().unwrap().to_string()
+            })
+            .collect::<Vec<_>>();
+        let path = std::env::temp_dir().join(format!("topology-{}.txt", std::process::id()));
+        let topology = format!("receiver {}\nsender {}\n", addresses[0], addresses[1]);
+        std::fs::write(&path, topology).unwrap();
+        let config = |role: &str, address: &str| {
+            format!(
+                "protocol = \"kmprt\"\n[network]\nrole = \"{}\"\naddress = \"{}\"\ntopology = {:?}\n",
+                role, address, path
+            )
+            .parse::<Config>()
+            .unwrap()
+        };
+        let member = config("sender", &addresses[1]);
+        let handle = std::thread::spawn(move || {
+            let (me, channels) = member.connect_parties().unwrap();
+            (me, channels[0].0)
+        });
+        let (me, channels) = config("receiver", &addresses[0]).connect_parties().unwrap();
+        assert_eq!((me, channels[0].0), (0, 1));
+        assert_eq!(handle.join().unwrap(), (1, 0));
+        assert!(config("sender", &addresses[0]).connect_parties().is_err());
+        assert!(config("sender", "127.0.0.1:1").connect_parties().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -4,12 +4,32 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
-use crate::Error;
+//! Cuckoo hashing, as used by the receiver in the PSI protocols.
+//!
+//! The table is exposed read-only, together with statistics gathered while it
+//! was built, so that receiver-side hashing state can be inspected, persisted
+//! (with the `serde1` feature), and used to debug pathological inputs. A
+//! deserialized table is checked to be one that hashing could have built.
+//!
+//! Both parties must map an item to the same bins. The bin hash is therefore
+//! pinned down exactly by `DefaultBinHash`, as a function of the `u128` value
+//! of the hashed item alone, so that it does not depend on the platform. A
+//! peer using its own cuckoo implementation can instead be matched with a
+//! custom `BinHash`.
+
+use crate::{
+    security::{SecurityParams, DEFAULT_LAMBDA_STAT},
+    Error,
+};
 use scuttlebutt::{Aes128, Block};
 use std::fmt::Debug;
+#[cfg(feature = "serde1")]
+use std::{collections::HashSet, convert::TryFrom};
 
+/// An entry in a cuckoo hash table.
 #[derive(Clone, Debug)]
-pub(crate) struct CuckooItem {
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct CuckooItem {
     // The actual value.
     pub(crate) entry: Block,
     // The input index associated with the entry.
@@ -18,51 +38,246 @@ pub(crate) struct CuckooItem {
     pub(crate) hash_index: usize,
 }
 
-pub(crate) struct CuckooHash {
+impl CuckooItem {
+    /// The hashed input stored in the bin, with the hash index XORed into its
+    /// lowest byte.
+    #[inline]
+    pub fn entry(&self) -> Block {
+        self.entry
+    }
+
+    /// The index of the input this entry corresponds to.
+    #[inline]
+    pub fn input_index(&self) -> usize {
+        self.input_index
+    }
+
+    /// The index of the hash function that placed this entry.
+    #[inline]
+    pub fn hash_index(&self) -> usize {
+        self.hash_index
+    }
+}
+
+/// Statistics gathered while building a cuckoo hash table.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct CuckooStats {
+    /// The total number of evictions performed.
+    pub nevictions: usize,
+    /// The longest chain of evictions caused by a single insertion.
+    pub max_eviction_chain: usize,
+    /// For each bin, the number of times an item was placed in it. A bin whose
+    /// depth exceeds one was contested during construction.
+    pub bin_depths: Vec<usize>,
+}
+
+/// A cuckoo hash table.
+#[cfg_attr(
+    feature = "serde1",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "CuckooParts")
+)]
+pub struct CuckooHash {
     pub(crate) items: Vec<Option<CuckooItem>>,
     pub(crate) nbins: usize,
     pub(crate) nhashes: usize,
+    pub(crate) stats: CuckooStats,
+}
+
+// The fields of a deserialized `CuckooHash`, before they are validated.
+#[cfg(feature = "serde1")]
+#[derive(serde::Deserialize)]
+struct CuckooParts {
+    items: Vec<Option<CuckooItem>>,
+    nbins: usize,
+    nhashes: usize,
+    stats: CuckooStats,
+}
+
+// Check that the table has `nbins` bins, each with a depth, holding distinct
+// inputs placed by one of `nhashes` supported hash functions.
+#[cfg(feature = "serde1")]
+impl TryFrom<CuckooParts> for CuckooHash {
+    type Error = Error;
+
+    fn try_from(parts: CuckooParts) -> Result<Self, Error> {
+        let CuckooParts {
+            items,
+            nbins,
+            nhashes,
+            stats,
+        } = parts;
+        let invalid = |msg: String| Error::InvalidCheckpoint(format!("cuckoo table: {}", msg));
+        if !supports_nhashes(nhashes) {
+            return Err(Error::InvalidCuckooParameters {
+                nitems: items.iter().flatten().count(),
+                nhashes,
+            });
+        }
+        if nbins == 0 || items.len() != nbins || stats.bin_depths.len() != nbins {
+            return Err(invalid(format!(
+                "{} bins with {} entries and {} depths",
+                nbins,
+                items.len(),
+                stats.bin_depths.len()
+            )));
+        }
+        let mut inputs = HashSet::new();
+        for (i, item) in items.iter().enumerate() {
+            let item = match item {
+                Some(item) => item,
+                None => continue,
+            };
+            // Placing an item XORs its hash index into the cleared low byte.
+            if item.hash_index >= nhashes
+                || (u128::from(item.entry) & 0xFF) as usize != item.hash_index
+            {
+                return Err(invalid(format!("bin {} has an invalid hash index", i)));
+            }
+            if stats.bin_depths[i] == 0 {
+                return Err(invalid(format!("bin {} is filled at depth zero", i)));
+            }
+            if !inputs.insert(item.input_index) {
+                return Err(invalid(format!(
+                    "input {} is in two bins",
+                    item.input_index
+                )));
+            }
+        }
+        Ok(CuckooHash {
+            items,
+            nbins,
+            nhashes,
+            stats,
+        })
+    }
+}
+
+/// A function assigning hashed items to bins.
+pub trait BinHash: Send + Sync {
+    /// The bin, less than `nbins`, of the hashed item `hash` under hash
+    /// function `hidx`. The lowest-order byte of `hash` is zero.
+    fn bin(&self, hash: Block, hidx: usize, nbins: usize) -> usize;
+}
+
+/// The bin hash used by default.
+///
+/// Write `hash` as the 16 little-endian bytes of its `u128` value. For
+/// `hidx < 3`, the bin is the little-endian `u32` in bytes `1 + 4 * hidx` to
+/// `4 + 4 * hidx`, reduced mod `nbins`; the lowest-order byte is skipped, as
+/// it holds the hash index in the table. Tables with more than `2^32` bins
+/// instead use the 40-bit little-endian value in bytes `1 + 5 * hidx` to
+/// `5 + 5 * hidx`, so that every bin of the at most `2^40` that sets of up to
+/// `MAX_SET_SIZE` items need can be reached. For larger `hidx`, the
+/// bin is the `u128` value of `AES_{hash}(hidx)` reduced mod `nbins`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultBinHash;
+
+impl BinHash for DefaultBinHash {
+    fn bin(&self, hash: Block, hidx: usize, nbins: usize) -> usize {
+        if hidx < 3 {
+            let width = if nbins as u64 > 1 << 32 { 5 } else { 4 };
+            let mut array = [0u8; 8];
+            let bytes = u128::from(hash).to_le_bytes();
+            array[..width].copy_from_slice(&bytes[1 + width * hidx..1 + width * (hidx + 1)]);
+            let value = u64::from_le_bytes(array);
+            (value % nbins as u64) as usize
+        } else {
+            // This is slow, but the bytes used above run out.
+            let aes = Aes128::new(hash);
+            let h = aes.encrypt(Block::from(hidx as u128));
+            (u128::from(h) % (nbins as u128)) as usize
+        }
+    }
 }
 
 /// The number of times to loop when trying to place an entry in a bin.
 const NITERS: usize = 1000;
 
-fn compute_nbins(n: usize, nhashes: usize) -> Result<usize, Error> {
-    // Numbers taken from <https://thomaschneider.de/papers/PSZ18.pdf>, §3.2.2.
-    if nhashes == 3 {
+/// The number of hash functions the PSI protocols use unless configured
+/// otherwise.
+pub const DEFAULT_NHASHES: usize = 3;
+
+/// Whether `CuckooHash` supports `nhashes` hash functions, that is, 3, 4 or 5.
+/// More hash functions fill the table more densely, so it needs fewer bins,
+/// but the PSZ sender sends a tag per item for each of them.
+pub fn supports_nhashes(nhashes: usize) -> bool {
+    (3..=5).contains(&nhashes)
+}
+
+pub(crate) fn compute_nbins(
+    n: usize,
+    nhashes: usize,
+    security: &SecurityParams,
+) -> Result<usize, Error> {
+    security.check()?;
+    if n as u64 > MAX_SET_SIZE {
+        return Err(Error::InvalidCuckooSetSize(n));
+    }
+    // Numbers taken from <https://thomaschneider.de/papers/PSZ18.pdf>, §3.2.2,
+    // for a failure probability of `2^-40`.
+    let factor = if nhashes == 3 {
         if n < 1 << 27 {
-            Ok((1.27 * (n as f64)).ceil() as usize) // good up to set size 2^26
+            1.27 // good up to set size 2^26
         } else {
-            Ok((1.62 * (n as f64)).ceil() as usize) // required for 2^27
+            1.62 // required for 2^27
         }
     } else if nhashes == 4 {
-        Ok((1.09 * (n as f64)).ceil() as usize)
+        1.09
     } else if nhashes == 5 {
-        Ok((1.05 * (n as f64)).ceil() as usize)
+        1.05
     } else {
-        Err(Error::InvalidCuckooParameters { nitems: n, nhashes })
+        return Err(Error::InvalidCuckooParameters { nitems: n, nhashes });
+    };
+    // PSZ18 observe the failure probability to fall exponentially in the
+    // table size past the threshold, so stronger security grows the excess
+    // over `n` in proportion. This extrapolates their measurements rather
+    // than proving a bound. Weaker security keeps the tables for `2^-40`.
+    let lambda = security.lambda_stat.max(DEFAULT_LAMBDA_STAT) as f64;
+    let factor = 1.0 + (factor - 1.0) * lambda / DEFAULT_LAMBDA_STAT as f64;
+    let nbins = (factor * (n as f64)).ceil();
+    // Casting would saturate rather than fail on platforms where the table
+    // cannot be addressed, and `DefaultBinHash` only reaches `2^40` bins.
+    if nbins >= usize::MAX as f64 || nbins > MAX_NBINS as f64 {
+        return Err(Error::InvalidCuckooSetSize(n));
     }
+    Ok(nbins as usize)
 }
 
+/// The largest set size the PSI protocols support. This is far more than fits
+/// in memory, but keeps the number of bins within the `2^40` that
+/// `DefaultBinHash` reaches, at any `SecurityParams`.
+pub const MAX_SET_SIZE: u64 = 1 << 38;
+
+// The number of bins the 40-bit windows of `DefaultBinHash` reach.
+const MAX_NBINS: u64 = 1 << 40;
+
+/// Compute the number of bytes of OPRF output the PSI protocols compare for
+/// sets of size `n`, at the default `SecurityParams`.
 pub fn compute_masksize(n: usize) -> Result<usize, Error> {
+    compute_masksize_with(n, &SecurityParams::default())
+}
+
+/// Compute the number of bytes of OPRF output the PSI protocols compare for
+/// sets of size `n`, so that a false match occurs with probability at most
+/// `2^-security.lambda_stat`.
+pub fn compute_masksize_with(n: usize, security: &SecurityParams) -> Result<usize, Error> {
+    security.check()?;
+    if n as u64 > MAX_SET_SIZE {
+        return Err(Error::InvalidCuckooSetSize(n));
+    }
     // Numbers taken from <https://eprint.iacr.org/2016/799>, Table 2 (the `v`
-    // column).
-    let masksize = if n <= 1 << 8 {
-        7
-    } else if n <= 1 << 12 {
-        8
-    } else if n <= 1 << 16 {
-        9
-    } else if n <= 1 << 20 {
-        10
-    } else if n <= 1 << 24 {
-        11
-    } else if n <= 1 << 28 {
-        12
+    // column), which are `ceil((40 + 2 log n) / 8)` bytes for a statistical
+    // security of 40 bits, with `log n` rounded up to a multiple of 4 from 8
+    // to 28. Larger sets use `log n` itself.
+    let log_n = 64 - (n.max(1) as u64 - 1).leading_zeros() as usize;
+    let log_n = if n <= 1 << 28 {
+        log_n.max(8).div_ceil(4) * 4
     } else {
-        return Err(Error::InvalidCuckooSetSize(n));
+        log_n
     };
-    Ok(masksize)
+    Ok((security.lambda_stat + 2 * log_n).div_ceil(8))
 }
 
 impl CuckooHash {
@@ -70,17 +285,42 @@ impl CuckooHash {
     /// lower-order-bits of the values in `inputs` are zero-ed out, as those
     /// bits will be used to store the hash index.
     pub fn new(inputs: &[Block], nhashes: usize) -> Result<CuckooHash, Error> {
-        let nbins = compute_nbins(inputs.len(), nhashes)?;
+        Self::with_bin_hash(inputs, nhashes, &DefaultBinHash)
+    }
+
+    /// Build a new cuckoo hash table as in `new`, assigning items to bins with
+    /// `bin_hash`.
+    pub fn with_bin_hash(
+        inputs: &[Block],
+        nhashes: usize,
+        bin_hash: &dyn BinHash,
+    ) -> Result<CuckooHash, Error> {
+        Self::with_security(inputs, nhashes, bin_hash, &SecurityParams::default())
+    }
+
+    /// Build a new cuckoo hash table as in `with_bin_hash`, with enough bins
+    /// that it fills up with probability about `2^-security.lambda_stat`.
+    pub fn with_security(
+        inputs: &[Block],
+        nhashes: usize,
+        bin_hash: &dyn BinHash,
+        security: &SecurityParams,
+    ) -> Result<CuckooHash, Error> {
+        let nbins = compute_nbins(inputs.len(), nhashes, security)?;
 
         let mut tbl = CuckooHash {
             items: vec![None; nbins],
             nbins,
             nhashes,
+            stats: CuckooStats {
+                bin_depths: vec![0; nbins],
+                ..CuckooStats::default()
+            },
         };
 
         // Fill table with `inputs`.
         for (j, input) in inputs.iter().enumerate() {
-            tbl.hash(*input, j)?;
+            tbl.hash_with(*input, j, bin_hash)?;
         }
 
         Ok(tbl)
@@ -89,6 +329,17 @@ impl CuckooHash {
     /// Place `input`, alongside the input index `idx` it corresponds to, in the
     /// hash table.
     pub fn hash(&mut self, input: Block, idx: usize) -> Result<(), Error> {
+        self.hash_with(input, idx, &DefaultBinHash)
+    }
+
+    /// Place `input` in the hash table as in `hash`, assigning it to bins
+    /// with `bin_hash`, which must be the one the table was built with.
+    pub fn hash_with(
+        &mut self,
+        input: Block,
+        idx: usize,
+        bin_hash: &dyn BinHash,
+    ) -> Result<(), Error> {
         let mut item = CuckooItem {
             entry: input,
             input_index: idx,
@@ -96,43 +347,62 @@ impl CuckooHash {
         };
         let mask = Block::from(0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FF00);
 
-        for _ in 0..NITERS {
+        for chain in 0..NITERS {
             item.entry &= mask;
-            let i = CuckooHash::bin(item.entry, item.hash_index, self.nbins);
+            let i = bin_hash.bin(item.entry, item.hash_index, self.nbins);
             item.entry ^= Block::from(item.hash_index as u128);
             let opt_item = self.items[i].replace(item);
+            self.stats.bin_depths[i] += 1;
             if let Some(x) = opt_item {
                 // If there is an item already in the bin, keep iterating,
                 // trying to place the new item.
                 item = x;
                 // Bump the hash index.
                 item.hash_index = (item.hash_index + 1) % self.nhashes;
+                self.stats.nevictions += 1;
             } else {
+                self.stats.max_eviction_chain = self.stats.max_eviction_chain.max(chain);
                 return Ok(());
             }
         }
         Err(Error::CuckooHashFull)
     }
 
-    /// Output the bin number for a given hash output `hash` and hash index `hidx`.
+    /// Return the bins of the table.
+    #[inline]
+    pub fn items(&self) -> &[Option<CuckooItem>] {
+        &self.items
+    }
+
+    /// Return the number of bins.
+    #[inline]
+    pub fn nbins(&self) -> usize {
+        self.nbins
+    }
+
+    /// Return the number of hash functions.
+    #[inline]
+    pub fn nhashes(&self) -> usize {
+        self.nhashes
+    }
+
+    /// Return the fraction of bins that are occupied.
+    pub fn fill_factor(&self) -> f64 {
+        let nfilled = self.items.iter().filter(|item| item.is_some()).count();
+        nfilled as f64 / self.nbins as f64
+    }
+
+    /// Return the statistics gathered while building the table.
+    #[inline]
+    pub fn stats(&self) -> &CuckooStats {
+        &self.stats
+    }
+
+    /// Output the bin number for a given hash output `hash` and hash index
+    /// `hidx`, under `DefaultBinHash`.
+    #[inline]
     pub fn bin(hash: Block, hidx: usize, nbins: usize) -> usize {
-        // The first 15 bytes of `hash` are uniformly(-ish) random, so we use it
-        // directly to determine our bin by indexing the `hidx`th 32 bits of
-        // `hash` then mod-ing it by `nbins`. We can't do this for more than
-        // three hash functions though, as the last byte is *not*
-        // uniformly(-ish) random. Instead, we run it through AES (slow!).
-        if hidx < 3 {
-            let mut array = [0u8; 4];
-            let bytes: [u8; 16] = hash.into();
-            array.copy_from_slice(&bytes[4 * hidx..4 * (hidx + 1)]);
-            let value = u32::from_le_bytes(array);
-            (value as usize) % nbins
-        } else {
-            // In this case, compute `AES_{hash}(hidx)`.
-            let aes = Aes128::new(hash);
-            let h = aes.encrypt(Block::from(hidx as u128));
-            (u128::from(h) % (nbins as u128)) as usize
-        }
+        DefaultBinHash.bin(hash, hidx, nbins)
     }
 }
 
@@ -165,6 +435,18 @@ mod tests {
         let hashes = utils::compress_and_hash_inputs(&inputs, key);
         let tbl = CuckooHash::new(&hashes, NHASHES);
         assert!(tbl.err().is_none());
+        // More hash functions pack the same items into fewer bins.
+        let nbins = (4..=5)
+            .map(|nhashes| CuckooHash::new(&hashes, nhashes).unwrap().nbins())
+            .collect_vec();
+        let security = SecurityParams::default();
+        assert!(
+            nbins[1] < nbins[0] && nbins[0] < compute_nbins(SETSIZE, NHASHES, &security).unwrap()
+        );
+        for &nhashes in &[2, 6] {
+            assert!(!supports_nhashes(nhashes));
+            assert!(CuckooHash::new(&hashes, nhashes).is_err());
+        }
     }
 
     #[test]
@@ -200,6 +482,203 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_build_nbins_multiple_of_eight() {
+        // 49152 items yields 62424 bins, exposing bin choices that depend on
+        // the zeroed lowest-order byte.
+        let mut rng = AesRng::new();
+        let inputs = utils::rand_vec_vec(49152, ITEMSIZE, &mut rng);
+        let key = rng.gen();
+        let hashes = utils::compress_and_hash_inputs(&inputs, key);
+        let tbl = CuckooHash::new(&hashes, NHASHES).unwrap();
+        assert_eq!(tbl.nbins() % 8, 0);
+    }
+
+    #[test]
+    fn golden_bins() {
+        let hash = Block::from(0x0123_4567_89ab_cdef_fedc_ba98_7654_3200);
+        // Bins must not change across platforms or releases, or peers running
+        // different builds of this crate will fail to find each other's items.
+        let bins = (0..5)
+            .map(|h| {
+                (
+                    CuckooHash::bin(hash, h, 1000),
+                    CuckooHash::bin(hash, h, 62424),
+                )
+            })
+            .collect_vec();
+        assert_eq!(
+            bins,
+            vec![
+                (634, 5810),
+                (274, 46850),
+                (661, 3013),
+                (33, 61121),
+                (886, 12030)
+            ]
+        );
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn golden_bins_large_tables() {
+        // Tables past `2^32` bins read wider windows, reaching every bin.
+        let hash = Block::from(0x0123_4567_89ab_cdef_fedc_ba98_7654_3200);
+        let nbins = (1 << 40) - 87;
+        let bins = (0..3)
+            .map(|h| CuckooHash::bin(hash, h, nbins))
+            .collect_vec();
+        assert_eq!(bins, vec![0xba_9876_5432, 0xab_cdef_fedc, 0x01_2345_6789]);
+        assert!(bins[1] > u32::MAX as usize);
+        // At exactly `2^32` bins the narrow windows still cover every bin.
+        assert_eq!(CuckooHash::bin(hash, 0, 1 << 32), 0x9876_5432);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn masksize_boundaries() {
+        let masksize = |n: u64| compute_masksize(n as usize).unwrap();
+        assert_eq!(masksize(1 << 8), 7);
+        assert_eq!(masksize((1 << 8) + 1), 8);
+        assert_eq!(masksize(1 << 28), 12);
+        assert_eq!(masksize((1 << 28) + 1), 13);
+        assert_eq!(masksize(1 << 32), 13);
+        assert_eq!(masksize((1 << 32) + 1), 14);
+        assert_eq!(masksize(MAX_SET_SIZE), 15);
+        assert!(compute_masksize(MAX_SET_SIZE as usize + 1).is_err());
+        // The largest tables stay within the reach of the bin hash.
+        let strongest = SecurityParams::new(128, 128).unwrap();
+        let nbins = compute_nbins(MAX_SET_SIZE as usize, 3, &strongest).unwrap();
+        assert!(nbins as u64 <= MAX_NBINS);
+        assert!(compute_nbins(MAX_SET_SIZE as usize + 1, 3, &strongest).is_err());
+        // Bin counts stay exact past `2^32` items.
+        let n = (1 << 32) + 1;
+        let security = SecurityParams::default();
+        assert_eq!(compute_nbins(n, 3, &security).unwrap(), 6_957_847_022);
+        assert_eq!(compute_nbins(n, 5, &security).unwrap(), 4_509_715_662);
+    }
+
+    #[test]
+    fn test_security() {
+        let mut rng = AesRng::new();
+        let inputs = utils::rand_vec_vec(SETSIZE, ITEMSIZE, &mut rng);
+        let key = rng.gen();
+        let hashes = utils::compress_and_hash_inputs(&inputs, key);
+        let weak = SecurityParams::new(20, 128).unwrap();
+        let strong = SecurityParams::new(60, 128).unwrap();
+        // The default reproduces KKRT16, and weaker security saves tag bytes.
+        for &(n, masksize) in &[
+            (0, 7),
+            (1 << 10, 8),
+            (1 << 16, 9),
+            (1 << 20, 10),
+            (1 << 24, 11),
+        ] {
+            assert_eq!(compute_masksize(n).unwrap(), masksize);
+            assert!(compute_masksize_with(n, &weak).unwrap() < masksize);
+            assert!(compute_masksize_with(n, &strong).unwrap() > masksize);
+        }
+        let bins = |security: SecurityParams| {
+            CuckooHash::with_security(&hashes, NHASHES, &DefaultBinHash, &security)
+                .unwrap()
+                .nbins()
+        };
+        assert_eq!(bins(weak), bins(SecurityParams::default()));
+        assert!(bins(strong) > bins(SecurityParams::default()));
+        let invalid = SecurityParams {
+            lambda_stat: 0,
+            lambda_comp: 128,
+        };
+        assert!(compute_masksize_with(1, &invalid).is_err());
+        assert!(CuckooHash::with_security(&hashes, NHASHES, &DefaultBinHash, &invalid).is_err());
+    }
+
+    struct HighBits;
+
+    impl BinHash for HighBits {
+        fn bin(&self, hash: Block, hidx: usize, nbins: usize) -> usize {
+            ((u128::from(hash) >> (32 * hidx + 32)) as u32 as usize) % nbins
+        }
+    }
+
+    #[test]
+    fn custom_bin_hash() {
+        let mut rng = AesRng::new();
+        let inputs = utils::rand_vec_vec(SETSIZE, ITEMSIZE, &mut rng);
+        let hashes = utils::compress_and_hash_inputs(&inputs, rng.gen());
+        let tbl = CuckooHash::with_bin_hash(&hashes, NHASHES, &HighBits).unwrap();
+        for (i, opt_item) in tbl.items().iter().enumerate() {
+            if let Some(item) = opt_item {
+                let hash = hashes[item.input_index()];
+                assert_eq!(HighBits.bin(hash, item.hash_index(), tbl.nbins()), i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut rng = AesRng::new();
+        let inputs = utils::rand_vec_vec(SETSIZE, ITEMSIZE, &mut rng);
+        let key = rng.gen();
+        let hashes = utils::compress_and_hash_inputs(&inputs, key);
+        let tbl = CuckooHash::new(&hashes, NHASHES).unwrap();
+        let stats = tbl.stats();
+
+        assert_eq!(tbl.items().len(), tbl.nbins());
+        assert!((tbl.fill_factor() - SETSIZE as f64 / tbl.nbins() as f64).abs() < 1e-9);
+        // Every insertion and every eviction places exactly one item.
+        let depth: usize = stats.bin_depths.iter().sum();
+        assert_eq!(depth, SETSIZE + stats.nevictions);
+        assert!(stats.max_eviction_chain <= stats.nevictions);
+        for (item, &depth) in tbl.items().iter().zip(stats.bin_depths.iter()) {
+            assert_eq!(item.is_some(), depth > 0);
+        }
+        let mut indices = tbl
+            .items()
+            .iter()
+            .filter_map(|item| item.as_ref().map(CuckooItem::input_index))
+            .collect_vec();
+        indices.sort();
+        assert_eq!(indices, (0..SETSIZE).collect_vec());
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn test_deserialize() {
+        let mut rng = AesRng::new();
+        let inputs = utils::rand_vec_vec(1 << 8, ITEMSIZE, &mut rng);
+        let hashes = utils::compress_and_hash_inputs(&inputs, rng.gen());
+        let tbl = CuckooHash::new(&hashes, NHASHES).unwrap();
+        let check = |tbl: &CuckooHash| {
+            bincode::deserialize::<CuckooHash>(&bincode::serialize(tbl).unwrap())
+        };
+        let copy = check(&tbl).unwrap();
+        assert_eq!(copy.nbins(), tbl.nbins());
+        assert_eq!(copy.stats(), tbl.stats());
+
+        let filled = tbl.items().iter().position(Option::is_some).unwrap();
+        let empty = tbl.items().iter().position(Option::is_none).unwrap();
+        let tampered = |f: &dyn Fn(&mut CuckooHash)| {
+            let mut copy = check(&tbl).unwrap();
+            f(&mut copy);
+            check(&copy).is_err()
+        };
+        assert!(tampered(&|t| t.nhashes = 6));
+        assert!(tampered(&|t| t.nbins += 1));
+        assert!(tampered(&|t| {
+            t.items.pop();
+        }));
+        assert!(tampered(&|t| t.stats.bin_depths[filled] = 0));
+        assert!(tampered(&|t| {
+            t.items[filled].as_mut().unwrap().hash_index = NHASHES;
+        }));
+        assert!(tampered(&|t| {
+            let item = t.items[filled].clone();
+            t.items[empty] = item;
+            t.stats.bin_depths[empty] = 1;
+        }));
+    }
 }
 
 #[cfg(all(feature = "nightly", test))]
@@ -0,0 +1,281 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Running a PSI session within a time budget.
+//!
+//! `send` and `receive` initialize and run a two-party protocol over a
+//! channel which fails once a `Deadline` has passed, and `run` does the same
+//! for any other flow, such as a streaming one, given as a closure. A run
+//! which fails because its deadline has passed fails with
+//! `Error::DeadlineExceeded`, carrying the `Progress` it had made; any other
+//! error is returned as is, even after the deadline.
+//!
+//! The deadline is checked whenever the protocol reads, writes or flushes,
+//! which it does between every two phases, so a run ends soon after its
+//! deadline unless it is blocked reading from a peer which sends nothing.
+//! `send_tcp` and `receive_tcp`, which own their transport, bound that case
+//! too by setting the timeouts of the stream to `Deadline::remaining` before
+//! each read and write; over any other transport, set such a timeout there.
+//! The garbler and evaluator of `fancy_garbling::twopac` are bounded in the
+//! same way by `fancy_garbling::twopac::deadline`.
+
+use crate::{
+    psi::{PsiReceiver, PsiSender},
+    Error,
+};
+use rand::{CryptoRng, RngCore};
+use scuttlebutt::{memtrack, AbstractChannel, Channel, DeadlineChannel};
+use std::{
+    cell::RefCell,
+    io::{BufReader, BufWriter, Read, Write},
+    net::TcpStream,
+    rc::Rc,
+    time::Duration,
+};
+
+pub use scuttlebutt::Deadline;
+
+/// What a run had done by the time it was stopped by its deadline.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// The time since the deadline was made.
+    pub elapsed: Duration,
+    /// The phases completed on the thread of the run, in order.
+    pub phases: Vec<&'static str>,
+    /// The number of bytes read from the peer.
+    pub bytes_read: u64,
+    /// The number of bytes written to the peer.
+    pub bytes_written: u64,
+}
+
+// The shortest timeout set on a stream, as a zero timeout is rejected.
+const MIN_TIMEOUT: Duration = Duration::from_millis(1);
+
+// A stream whose reads and writes time out at a deadline.
+struct TimedStream {
+    stream: TcpStream,
+    deadline: Deadline,
+}
+
+impl Read for TimedStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let timeout = self.deadline.remaining().max(MIN_TIMEOUT);
+        self.stream.set_read_timeout(Some(timeout))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TimedStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let timeout = self.deadline.remaining().max(MIN_TIMEOUT);
+        self.stream.set_write_timeout(Some(timeout))?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+// Make a channel over `stream` whose operations time out at `deadline`.
+fn timed_channel(
+    stream: TcpStream,
+    deadline: Deadline,
+) -> Result<Channel<BufReader<TimedStream>, BufWriter<TimedStream>>, Error> {
+    let reader = TimedStream {
+        stream: stream.try_clone()?,
+        deadline,
+    };
+    let writer = TimedStream { stream, deadline };
+    Ok(Channel::new(BufReader::new(reader), BufWriter::new(writer)))
+}
+
+/// Run `f` over `channel` until `deadline`, failing with
+/// `Error::DeadlineExceeded` if it fails because the deadline has passed.
+pub fn run<C, T>(
+    deadline: Deadline,
+    channel: &mut C,
+    f: impl FnOnce(&mut DeadlineChannel<C>) -> Result<T, Error>,
+) -> Result<T, Error>
+where
+    C: AbstractChannel,
+{
+    let mut channel = DeadlineChannel::new(channel.clone(), deadline);
+    let phases = Rc::new(RefCell::new(Vec::new()));
+    let phases_ = phases.clone();
+    let result = memtrack::observe(
+        move |phase, _| phases_.borrow_mut().push(phase),
+        || f(&mut channel),
+    );
+    match result {
+        Err(_) if channel.exceeded() => Err(Error::DeadlineExceeded(Progress {
+            elapsed: deadline.elapsed(),
+            phases: phases.take(),
+            bytes_read: channel.bytes_read(),
+            bytes_written: channel.bytes_written(),
+        })),
+        result => result,
+    }
+}
+
+/// Initialize the sender of protocol `P` and run it over `inputs`, until
+/// `deadline`.
+pub fn send<P, C, RNG>(
+    deadline: Deadline,
+    inputs: &[Vec<u8>],
+    channel: &mut C,
+    rng: &mut RNG,
+) -> Result<(), Error>
+where
+    P: PsiSender,
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    run(deadline, channel, |channel| {
+        P::init(channel, rng)?.send(inputs, channel, rng)
+    })
+}
+
+/// Initialize the receiver of protocol `P` and run it over `inputs`, until
+/// `deadline`, returning the intersection.
+pub fn receive<P, C, RNG>(
+    deadline: Deadline,
+    inputs: &[Vec<u8>],
+    channel: &mut C,
+    rng: &mut RNG,
+) -> Result<Vec<Vec<u8>>, Error>
+where
+    P: PsiReceiver,
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    run(deadline, channel, |channel| {
+        P::init(channel, rng)?.receive(inputs, channel, rng)
+    })
+}
+
+/// Initialize the sender of protocol `P` and run it over `inputs` and
+/// `stream`, until `deadline`, timing out reads and writes at the deadline.
+pub fn send_tcp<P, RNG>(
+    deadline: Deadline,
+    inputs: &[Vec<u8>],
+    stream: TcpStream,
+    rng: &mut RNG,
+) -> Result<(), Error>
+where
+    P: PsiSender,
+    RNG: CryptoRng + RngCore,
+{
+    send::<P, _, _>(deadline, inputs, &mut timed_channel(stream, deadline)?, rng)
+}
+
+/// Initialize the receiver of protocol `P` and run it over `inputs` and
+/// `stream`, until `deadline`, timing out reads and writes at the deadline,
+/// and return the intersection.
+pub fn receive_tcp<P, RNG>(
+    deadline: Deadline,
+    inputs: &[Vec<u8>],
+    stream: TcpStream,
+    rng: &mut RNG,
+) -> Result<Vec<Vec<u8>>, Error>
+where
+    P: PsiReceiver,
+    RNG: CryptoRng + RngCore,
+{
+    receive::<P, _, _>(deadline, inputs, &mut timed_channel(stream, deadline)?, rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{psz, utils::rand_vec_vec, ErrorClass};
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        net::TcpListener,
+        os::unix::net::UnixStream,
+        time::Instant,
+    };
+
+    const ITEM_SIZE: usize = 8;
+    const SET_SIZE: usize = 1 << 7;
+
+    fn channel(stream: UnixStream) -> Channel<BufReader<UnixStream>, BufWriter<UnixStream>> {
+        Channel::new(
+            BufReader::new(stream.try_clone().unwrap()),
+            BufWriter::new(stream),
+        )
+    }
+
+    #[test]
+    fn test_deadline() {
+        let mut rng = AesRng::new();
+        let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let inputs_ = inputs.clone();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let deadline = Deadline::after(Duration::from_secs(60));
+            send::<psz::Sender, _, _>(deadline, &inputs_, &mut channel(sender), &mut rng)
+        });
+        let deadline = Deadline::after(Duration::from_secs(60));
+        let intersection =
+            receive::<psz::Receiver, _, _>(deadline, &inputs, &mut channel(receiver), &mut rng)
+                .unwrap();
+        assert_eq!(intersection.len(), SET_SIZE);
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_deadline_exceeded() {
+        // The sender initializes, then stalls past the deadline of the
+        // receiver before hanging up.
+        let mut rng = AesRng::new();
+        let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (sender, _) = listener.accept().unwrap();
+            let mut channel = Channel::new(
+                BufReader::new(sender.try_clone().unwrap()),
+                BufWriter::new(sender),
+            );
+            let _ = psz::Sender::init(&mut channel, &mut AesRng::new());
+            std::thread::sleep(Duration::from_millis(200));
+        });
+        let receiver = TcpStream::connect(address).unwrap();
+        let deadline = Deadline::at(Instant::now() + Duration::from_millis(50));
+        let e = receive_tcp::<psz::Receiver, _>(deadline, &inputs, receiver, &mut rng)
+            .unwrap_err();
+        handle.join().unwrap();
+        match &e {
+            Error::DeadlineExceeded(progress) => {
+                assert!(progress.elapsed >= Duration::from_millis(50));
+                assert!(progress.bytes_written > 0);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(e.class(), ErrorClass::ResourceLimit);
+    }
+
+    #[test]
+    fn test_deadline_keeps_other_errors() {
+        // The sender hangs up after the deadline of the receiver, which does
+        // not time out its reads, so the run fails on the hang up instead.
+        let mut rng = AesRng::new();
+        let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut channel = channel(sender);
+            let _ = psz::Sender::init(&mut channel, &mut AesRng::new());
+            std::thread::sleep(Duration::from_millis(200));
+        });
+        let deadline = Deadline::at(Instant::now() + Duration::from_millis(50));
+        let e = receive::<psz::Receiver, _, _>(deadline, &inputs, &mut channel(receiver), &mut rng)
+            .unwrap_err();
+        handle.join().unwrap();
+        assert_eq!(e.class(), ErrorClass::PeerAbort);
+    }
+}
@@ -0,0 +1,155 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! An optional deduplication of a party's inputs, run before any PSI protocol.
+//!
+//! The protocols take their inputs as given. A receiver listing an item many
+//! times crowds the bins of that item in its cuckoo table, so that the table
+//! may fail to build, and a sender listing an item many times sends a tag for
+//! each copy, for no benefit. `dedup` drops the repeated items, keeping the
+//! first copy of each in order, and reports how many it dropped; `send` and
+//! `receive` run a protocol over the deduplicated inputs.
+//!
+//! The peer learns the number of distinct items rather than the number of
+//! inputs, as it would from any run over them.
+
+use crate::{
+    psi::{PsiReceiver, PsiSender},
+    Error,
+};
+use rand::{CryptoRng, RngCore};
+use scuttlebutt::AbstractChannel;
+use std::collections::HashSet;
+
+/// The inputs of a party with the repeated items dropped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deduplicated {
+    items: Vec<Vec<u8>>,
+    nremoved: usize,
+}
+
+impl Deduplicated {
+    /// The distinct items, in the order of their first copy.
+    pub fn items(&self) -> &[Vec<u8>] {
+        &self.items
+    }
+
+    /// The number of inputs dropped as copies of an earlier one.
+    pub fn nremoved(&self) -> usize {
+        self.nremoved
+    }
+
+    /// Take the distinct items.
+    pub fn into_items(self) -> Vec<Vec<u8>> {
+        self.items
+    }
+}
+
+/// Drop the items of `inputs` equal to an earlier one.
+pub fn dedup(inputs: &[Vec<u8>]) -> Deduplicated {
+    let mut seen = HashSet::with_capacity(inputs.len());
+    let items = inputs
+        .iter()
+        .filter(|item| seen.insert(item.as_slice()))
+        .cloned()
+        .collect::<Vec<_>>();
+    Deduplicated {
+        nremoved: inputs.len() - items.len(),
+        items,
+    }
+}
+
+/// Run the PSI protocol `psi` over the distinct items of `inputs`, returning
+/// the number of inputs dropped.
+pub fn send<S, C, RNG>(
+    psi: &mut S,
+    inputs: &[Vec<u8>],
+    channel: &mut C,
+    rng: &mut RNG,
+) -> Result<usize, Error>
+where
+    S: PsiSender,
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    let inputs = dedup(inputs);
+    psi.send(inputs.items(), channel, rng)?;
+    Ok(inputs.nremoved())
+}
+
+/// Run the PSI protocol `psi` over the distinct items of `inputs`, returning
+/// the intersection, in which each item appears once, and the number of
+/// inputs dropped.
+pub fn receive<R, C, RNG>(
+    psi: &mut R,
+    inputs: &[Vec<u8>],
+    channel: &mut C,
+    rng: &mut RNG,
+) -> Result<(Vec<Vec<u8>>, usize), Error>
+where
+    R: PsiReceiver,
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    let inputs = dedup(inputs);
+    let intersection = psi.receive(inputs.items(), channel, rng)?;
+    Ok((intersection, inputs.nremoved()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{psi::psz, utils::rand_vec_vec};
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    #[test]
+    fn test_dedup() {
+        let inputs = vec![vec![1], vec![2], vec![1], vec![3], vec![2], vec![1]];
+        let deduplicated = dedup(&inputs);
+        assert_eq!(deduplicated.items(), &[vec![1], vec![2], vec![3]][..]);
+        assert_eq!(deduplicated.nremoved(), 3);
+        assert_eq!(dedup(&[]).nremoved(), 0);
+    }
+
+    #[test]
+    fn test_psi() {
+        let mut rng = AesRng::new();
+        // The protocol expects sets of the same size, which they are once the
+        // copies are dropped.
+        let distinct = rand_vec_vec(1 << 8, 8, &mut rng);
+        let mut others = rand_vec_vec(1 << 7, 8, &mut rng);
+        others.extend_from_slice(&distinct[..1 << 7]);
+        // Every sender item four times, and every receiver item eight times, so
+        // that the receiver's cuckoo table could not hold its inputs as given.
+        let sender_inputs = vec![distinct.clone(); 4].concat();
+        let receiver_inputs = vec![others; 8].concat();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = psz::Sender::init(&mut channel, &mut rng).unwrap();
+            send(&mut psi, &sender_inputs, &mut channel, &mut rng).unwrap()
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = psz::Receiver::init(&mut channel, &mut rng).unwrap();
+        let (mut intersection, nremoved) =
+            receive(&mut psi, &receiver_inputs, &mut channel, &mut rng).unwrap();
+        assert_eq!(handle.join().unwrap(), 3 << 8);
+        assert_eq!(nremoved, 7 << 8);
+        intersection.sort();
+        let mut expected = distinct[..1 << 7].to_vec();
+        expected.sort();
+        assert_eq!(intersection, expected);
+    }
+}
@@ -0,0 +1,309 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Comparing the intersection of a run against that of a previous run, on the
+//! receiver.
+//!
+//! A receiver running PSI periodically against the same sender often wants
+//! what changed rather than the whole intersection. It keeps a `Snapshot` of
+//! the last intersection, and `Snapshot::diff` reports the `Delta` of the
+//! next one: the items added, and those removed. Nothing is sent to the
+//! sender, which learns nothing more than from the runs themselves.
+//!
+//! A plain snapshot holds the items themselves. A hashed snapshot holds only
+//! their keyed hashes, under a key stored apart from it, so that a leaked
+//! snapshot reveals nothing about past intersections, even of items with
+//! little entropy, without the key too. It still names each added item, and
+//! each removed item which is among the inputs of the run, since the receiver
+//! can hash those, but only counts the removed items it no longer holds.
+
+use crate::Error;
+use scuttlebutt::keyschedule::hmac;
+use std::{
+    collections::{BTreeSet, HashSet},
+    io::{Read, Write},
+};
+
+// Labels of the keyed hashes, and the tags of the kinds of snapshot on the
+// wire.
+const ITEM_LABEL: &[u8] = b"popsicle::diff::item";
+const CHECK_LABEL: &[u8] = b"popsicle::diff::check";
+const PLAIN: u8 = 0;
+const HASHED: u8 = 1;
+
+fn invalid_data(msg: &str) -> Error {
+    Error::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, msg))
+}
+
+fn tag(key: &[u8; 32], item: &[u8]) -> [u8; 32] {
+    hmac(key, &[ITEM_LABEL, &(item.len() as u64).to_le_bytes(), item])
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// What changed between two intersections.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Delta {
+    /// The items of the new intersection which are not in the old one, in
+    /// the order of the new intersection.
+    pub added: Vec<Vec<u8>>,
+    /// The items of the old intersection which are not in the new one, as
+    /// far as the snapshot can name them.
+    pub removed: Vec<Vec<u8>>,
+    /// The number of items of the old intersection which are not in the new
+    /// one, named in `removed` or not.
+    pub nremoved: usize,
+}
+
+impl Delta {
+    /// Whether the intersections are the same.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.nremoved == 0
+    }
+}
+
+/// A previous intersection, to compare later ones against.
+#[derive(Clone)]
+pub struct Snapshot {
+    entries: Entries,
+}
+
+#[derive(Clone)]
+enum Entries {
+    Plain(BTreeSet<Vec<u8>>),
+    Hashed {
+        key: [u8; 32],
+        tags: HashSet<[u8; 32]>,
+    },
+}
+
+impl Snapshot {
+    /// Keep `intersection` as it is.
+    pub fn plain(intersection: &[Vec<u8>]) -> Self {
+        Self {
+            entries: Entries::Plain(intersection.iter().cloned().collect()),
+        }
+    }
+
+    /// Keep only the hashes of `intersection` under `key`, which must be
+    /// uniformly random and kept secret, and is needed to read the snapshot
+    /// back.
+    pub fn hashed(key: [u8; 32], intersection: &[Vec<u8>]) -> Self {
+        let tags = intersection.iter().map(|x| tag(&key, x)).collect();
+        Self {
+            entries: Entries::Hashed { key, tags },
+        }
+    }
+
+    /// Whether the snapshot holds only hashes of the items.
+    pub fn is_hashed(&self) -> bool {
+        matches!(self.entries, Entries::Hashed { .. })
+    }
+
+    /// The number of items in the intersection.
+    pub fn len(&self) -> usize {
+        match &self.entries {
+            Entries::Plain(items) => items.len(),
+            Entries::Hashed { tags, .. } => tags.len(),
+        }
+    }
+
+    /// Whether the intersection is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Compare `intersection`, obtained by running PSI over `inputs`, against
+    /// the snapshot. A hashed snapshot names the removed items among
+    /// `inputs`, in their order there; a plain one names every removed item,
+    /// in sorted order, and ignores `inputs`.
+    pub fn diff(&self, intersection: &[Vec<u8>], inputs: &[Vec<u8>]) -> Delta {
+        match &self.entries {
+            Entries::Plain(items) => {
+                let new = intersection.iter().collect::<HashSet<_>>();
+                let removed = items
+                    .iter()
+                    .filter(|x| !new.contains(x))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                Delta {
+                    added: added(intersection, |x| items.contains(x)),
+                    nremoved: removed.len(),
+                    removed,
+                }
+            }
+            Entries::Hashed { key, tags } => {
+                let new = intersection
+                    .iter()
+                    .map(|x| tag(key, x))
+                    .collect::<HashSet<_>>();
+                let mut named = HashSet::new();
+                let removed = inputs
+                    .iter()
+                    .filter(|x| {
+                        let t = tag(key, x);
+                        tags.contains(&t) && !new.contains(&t) && named.insert(t)
+                    })
+                    .cloned()
+                    .collect();
+                Delta {
+                    added: added(intersection, |x| tags.contains(&tag(key, x))),
+                    removed,
+                    nremoved: tags.difference(&new).count(),
+                }
+            }
+        }
+    }
+
+    /// Replace the snapshot with `intersection`, keeping its kind and key,
+    /// e.g. once a run has been compared against it.
+    pub fn update(&mut self, intersection: &[Vec<u8>]) {
+        *self = match &self.entries {
+            Entries::Plain(_) => Self::plain(intersection),
+            Entries::Hashed { key, .. } => Self::hashed(*key, intersection),
+        };
+    }
+
+    /// Serialize the snapshot to `writer`: its kind, then for a plain
+    /// snapshot the number of items and each item prefixed by its length, and
+    /// for a hashed one a check value of its key, the number of hashes and
+    /// the hashes. The key is never written.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        match &self.entries {
+            Entries::Plain(items) => {
+                writer.write_all(&[PLAIN])?;
+                writer.write_all(&(items.len() as u64).to_le_bytes())?;
+                for item in items {
+                    writer.write_all(&(item.len() as u64).to_le_bytes())?;
+                    writer.write_all(item)?;
+                }
+            }
+            Entries::Hashed { key, tags } => {
+                writer.write_all(&[HASHED])?;
+                writer.write_all(&hmac(key, &[CHECK_LABEL]))?;
+                writer.write_all(&(tags.len() as u64).to_le_bytes())?;
+                for tag in tags {
+                    writer.write_all(tag)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserialize a snapshot written by `write_to` from `reader`, given the
+    /// key of a hashed snapshot, failing if it is not the key the snapshot was
+    /// made under.
+    pub fn read_from<R: Read>(reader: &mut R, key: Option<[u8; 32]>) -> Result<Self, Error> {
+        let mut kind = [0u8; 1];
+        reader.read_exact(&mut kind)?;
+        match kind[0] {
+            PLAIN => {
+                let n = read_u64(reader)?;
+                let mut items = BTreeSet::new();
+                for _ in 0..n {
+                    let len = read_u64(reader)?;
+                    let mut item = Vec::new();
+                    reader.take(len).read_to_end(&mut item)?;
+                    if item.len() as u64 != len {
+                        return Err(invalid_data("truncated snapshot"));
+                    }
+                    items.insert(item);
+                }
+                Ok(Self {
+                    entries: Entries::Plain(items),
+                })
+            }
+            HASHED => {
+                let key = key.ok_or_else(|| invalid_data("a hashed snapshot needs its key"))?;
+                let mut check = [0u8; 32];
+                reader.read_exact(&mut check)?;
+                if check != hmac(&key, &[CHECK_LABEL]) {
+                    return Err(invalid_data("the snapshot was hashed under another key"));
+                }
+                let n = read_u64(reader)?;
+                let mut tags = HashSet::new();
+                for _ in 0..n {
+                    let mut tag = [0u8; 32];
+                    reader.read_exact(&mut tag)?;
+                    tags.insert(tag);
+                }
+                Ok(Self {
+                    entries: Entries::Hashed { key, tags },
+                })
+            }
+            _ => Err(invalid_data("unknown kind of snapshot")),
+        }
+    }
+}
+
+// The items of `intersection` not in the old one, counting each once.
+fn added(intersection: &[Vec<u8>], old: impl Fn(&Vec<u8>) -> bool) -> Vec<Vec<u8>> {
+    let mut seen = HashSet::new();
+    intersection
+        .iter()
+        .filter(|x| !old(x) && seen.insert(*x))
+        .cloned()
+        .collect()
+}
+
+// Neither the items nor the key show up in debug output.
+impl std::fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("hashed", &self.is_hashed())
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(xs: &[&str]) -> Vec<Vec<u8>> {
+        xs.iter().map(|x| x.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let day1 = items(&["alice", "bob", "carol"]);
+        // `carol` is still an input, but no longer matched; `bob` is gone.
+        let inputs = items(&["alice", "carol", "dave"]);
+        let day2 = items(&["alice", "dave"]);
+        let key = [7u8; 32];
+        for snapshot in &[Snapshot::plain(&day1), Snapshot::hashed(key, &day1)] {
+            let mut bytes = Vec::new();
+            snapshot.write_to(&mut bytes).unwrap();
+            let mut snapshot = Snapshot::read_from(&mut bytes.as_slice(), Some(key)).unwrap();
+            let delta = snapshot.diff(&day2, &inputs);
+            assert_eq!(delta.added, items(&["dave"]));
+            assert_eq!(delta.nremoved, 2);
+            if snapshot.is_hashed() {
+                assert_eq!(delta.removed, items(&["carol"]));
+                assert!(!bytes.windows(5).any(|w| w == b"alice"));
+            } else {
+                assert_eq!(delta.removed, items(&["bob", "carol"]));
+            }
+            snapshot.update(&day2);
+            assert!(snapshot.diff(&day2, &inputs).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let mut bytes = Vec::new();
+        Snapshot::hashed([1u8; 32], &items(&["alice"]))
+            .write_to(&mut bytes)
+            .unwrap();
+        assert!(Snapshot::read_from(&mut bytes.as_slice(), Some([2u8; 32])).is_err());
+        assert!(Snapshot::read_from(&mut bytes.as_slice(), None).is_err());
+        assert!(Snapshot::read_from(&mut bytes.as_slice(), Some([1u8; 32])).is_ok());
+    }
+}
@@ -4,6 +4,9 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
+use crate::{deadline::Progress, security::MasksizeBounds};
+use std::io::ErrorKind;
+
 /// Errors produced by the private set intersection protocols.
 #[derive(Debug)]
 pub enum Error {
@@ -24,10 +27,59 @@ pub enum Error {
         /// Number of hashes.
         nhashes: usize,
     },
+    /// A bin of a hash table is full.
+    HashTableFull,
+    /// The provided two-choice hash set size is invalid.
+    InvalidTwoChoiceSetSize(usize),
+    /// The provided two-choice hash parameters are invalid.
+    InvalidTwoChoiceParameters {
+        /// Number of bins.
+        nbins: usize,
+        /// Maximum number of items per bin.
+        binsize: usize,
+    },
     /// An error occurred in the PSI protocol.
     PsiProtocolError(String),
+    /// The local inputs or arguments of a call are invalid.
+    InvalidInput(String),
     /// Not enough payloads.
     InvalidPayloadsLength,
+    /// Auditing a PSI run failed.
+    AuditFailed(String),
+    /// A multi-party session topology is invalid.
+    InvalidTopology(String),
+    /// Sealed intersection results could not be opened.
+    InvalidSealedResults(String),
+    /// A checkpoint could not be resumed.
+    InvalidCheckpoint(String),
+    /// A peer failed, or refused, the admission check of a server.
+    AdmissionDenied(String),
+    /// A declared set size was rejected by a party's size policy.
+    SetSizeRejected(String),
+    /// The requested security parameters are not supported.
+    InvalidSecurityParameters(String),
+    /// The parties accept no common length of tags.
+    IncompatibleMasksize {
+        /// The bounds of this party.
+        ours: MasksizeBounds,
+        /// The bounds of the peer.
+        theirs: MasksizeBounds,
+    },
+    /// The parties could not agree on which of them sends and which receives.
+    RoleConflict(String),
+    /// The inputs or parameters of a fuzzy PSI run are invalid.
+    InvalidFuzzyInput(String),
+    /// A session, checkpoint or digest was used after it expired.
+    Expired(String),
+    /// A run failed to complete by its deadline, after making `Progress`.
+    DeadlineExceeded(Progress),
+    /// An application configuration is invalid.
+    #[cfg(feature = "config")]
+    InvalidConfig(String),
+    /// An internal consistency check failed, typically because of a
+    /// malformed message from the other party. Only returned with the
+    /// `hardened` feature; otherwise such checks panic.
+    InternalInvariant(String),
     /// SSL Error
     #[cfg(feature = "psty")]
     SSLError(openssl::error::ErrorStack),
@@ -73,6 +125,208 @@ impl From<fancy_garbling::errors::TwopacError> for Error {
     }
 }
 
+/// A coarse class of errors, telling an orchestrator whether a failed run is
+/// worth retrying. Each class exits applications with its own stable code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// The local inputs or configuration are invalid.
+    InvalidInput,
+    /// The parties disagree on the parameters of the session, e.g. on their
+    /// set sizes or thresholds.
+    ParameterMismatch,
+    /// The network failed, e.g. a connection was refused or timed out.
+    Network,
+    /// The peer hung up in the middle of the protocol.
+    PeerAbort,
+    /// A hash table or another bounded resource ran out.
+    ResourceLimit,
+    /// The peer sent a malformed message or failed a consistency check.
+    Protocol,
+    /// The peer refused to run the protocol with this party.
+    Denied,
+}
+
+impl ErrorClass {
+    /// The process exit code of the class, from 64 upwards, clear of the
+    /// codes of generic failures and of panics.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorClass::InvalidInput => 64,
+            ErrorClass::ParameterMismatch => 65,
+            ErrorClass::Network => 66,
+            ErrorClass::PeerAbort => 67,
+            ErrorClass::ResourceLimit => 68,
+            ErrorClass::Protocol => 69,
+            ErrorClass::Denied => 70,
+        }
+    }
+
+    /// Whether running again with the same inputs and configuration may
+    /// succeed.
+    pub fn is_transient(self) -> bool {
+        matches!(
+            self,
+            ErrorClass::Network | ErrorClass::PeerAbort | ErrorClass::ResourceLimit
+        )
+    }
+
+    /// The name of the class.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorClass::InvalidInput => "invalid_input",
+            ErrorClass::ParameterMismatch => "parameter_mismatch",
+            ErrorClass::Network => "network",
+            ErrorClass::PeerAbort => "peer_abort",
+            ErrorClass::ResourceLimit => "resource_limit",
+            ErrorClass::Protocol => "protocol",
+            ErrorClass::Denied => "denied",
+        }
+    }
+}
+
+fn io_class(e: &std::io::Error) -> ErrorClass {
+    match e.kind() {
+        ErrorKind::UnexpectedEof
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::BrokenPipe => ErrorClass::PeerAbort,
+        ErrorKind::InvalidData => ErrorClass::Protocol,
+        ErrorKind::NotFound | ErrorKind::PermissionDenied | ErrorKind::InvalidInput => {
+            ErrorClass::InvalidInput
+        }
+        _ => ErrorClass::Network,
+    }
+}
+
+fn cointoss_class(e: &scuttlebutt::cointoss::Error) -> ErrorClass {
+    match e {
+        scuttlebutt::cointoss::Error::IoError(e) => io_class(e),
+        _ => ErrorClass::Protocol,
+    }
+}
+
+// Write `s` as a JSON string.
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl Error {
+    /// Return a short label for the kind of error, suitable for grouping
+    /// failures in metrics (see `scuttlebutt::metrics`).
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Error::CoinTossError(_) => "coin_toss",
+            Error::OprfError(_) => "oprf",
+            Error::IoError(_) => "io",
+            Error::CuckooHashFull
+            | Error::InvalidCuckooSetSize(_)
+            | Error::InvalidCuckooParameters { .. } => "cuckoo_hash",
+            Error::HashTableFull
+            | Error::InvalidTwoChoiceSetSize(_)
+            | Error::InvalidTwoChoiceParameters { .. } => "hash_table",
+            Error::PsiProtocolError(_) => "protocol",
+            Error::InvalidInput(_) => "input",
+            Error::InvalidPayloadsLength => "payloads",
+            Error::AuditFailed(_) => "audit",
+            Error::InvalidTopology(_) => "topology",
+            Error::InvalidSealedResults(_) => "sealed",
+            Error::InvalidCheckpoint(_) => "checkpoint",
+            Error::AdmissionDenied(_) => "admission",
+            Error::SetSizeRejected(_) => "set_size",
+            Error::InvalidSecurityParameters(_) => "security",
+            Error::IncompatibleMasksize { .. } => "masksize",
+            Error::RoleConflict(_) => "role",
+            Error::InvalidFuzzyInput(_) => "fuzzy",
+            Error::Expired(_) => "expired",
+            Error::DeadlineExceeded(_) => "deadline",
+            Error::InternalInvariant(_) => "internal_invariant",
+            #[cfg(feature = "config")]
+            Error::InvalidConfig(_) => "config",
+            #[cfg(feature = "psty")]
+            Error::SSLError(_) => "ssl",
+            #[cfg(feature = "psty")]
+            Error::TwopcError(_) => "twopc",
+        }
+    }
+
+    /// Return the class of the error.
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Error::CoinTossError(e) => cointoss_class(e),
+            Error::OprfError(e) => match e {
+                ocelot::Error::IoError(e) => io_class(e),
+                ocelot::Error::CoinTossError(e) => cointoss_class(e),
+                ocelot::Error::InvalidInputLength | ocelot::Error::InvalidCheckpoint(_) => {
+                    ErrorClass::InvalidInput
+                }
+                ocelot::Error::InvalidTicket(_) => ErrorClass::Denied,
+                ocelot::Error::Other(_)
+                | ocelot::Error::InternalInvariant(_)
+                | ocelot::Error::InvalidOpening(_) => ErrorClass::Protocol,
+            },
+            Error::IoError(e) => io_class(e),
+            Error::CuckooHashFull | Error::HashTableFull | Error::DeadlineExceeded(_) => {
+                ErrorClass::ResourceLimit
+            }
+            Error::InvalidCuckooSetSize(_)
+            | Error::InvalidCuckooParameters { .. }
+            | Error::InvalidTwoChoiceSetSize(_)
+            | Error::InvalidTwoChoiceParameters { .. }
+            | Error::InvalidPayloadsLength
+            | Error::InvalidTopology(_)
+            | Error::InvalidSealedResults(_)
+            | Error::InvalidCheckpoint(_)
+            | Error::InvalidSecurityParameters(_)
+            | Error::InvalidFuzzyInput(_)
+            | Error::InvalidInput(_)
+            | Error::Expired(_) => ErrorClass::InvalidInput,
+            Error::PsiProtocolError(_)
+            | Error::SetSizeRejected(_)
+            | Error::IncompatibleMasksize { .. }
+            | Error::RoleConflict(_) => ErrorClass::ParameterMismatch,
+            Error::AuditFailed(_) | Error::InternalInvariant(_) => ErrorClass::Protocol,
+            Error::AdmissionDenied(_) => ErrorClass::Denied,
+            #[cfg(feature = "config")]
+            Error::InvalidConfig(_) => ErrorClass::InvalidInput,
+            #[cfg(feature = "psty")]
+            Error::SSLError(_) => ErrorClass::Network,
+            #[cfg(feature = "psty")]
+            Error::TwopcError(_) => ErrorClass::Protocol,
+        }
+    }
+
+    /// Return the error as a single-line JSON object, for applications to
+    /// report failures to the tools running them, e.g.
+    /// `{"class":"peer_abort","reason":"io","exit_code":67,"transient":true,"message":"..."}`.
+    pub fn to_json(&self) -> String {
+        let class = self.class();
+        let mut out = String::from("{\"class\":");
+        write_json_string(&mut out, class.name());
+        out.push_str(",\"reason\":");
+        write_json_string(&mut out, self.reason());
+        out.push_str(&format!(
+            ",\"exit_code\":{},\"transient\":{},\"message\":",
+            class.exit_code(),
+            class.is_transient()
+        ));
+        write_json_string(&mut out, &self.to_string());
+        out.push('}');
+        out
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -88,8 +342,46 @@ impl std::fmt::Display for Error {
                 "cuckoo hash error: no parameters set for {} items and {} hashes",
                 nitems, nhashes
             ),
+            Error::HashTableFull => write!(f, "hash table error: bin is full"),
+            Error::InvalidTwoChoiceSetSize(n) => {
+                write!(f, "two-choice hash error: invalid set size {}", n)
+            }
+            Error::InvalidTwoChoiceParameters { nbins, binsize } => write!(
+                f,
+                "two-choice hash error: invalid table of {} bins of {} items",
+                nbins, binsize
+            ),
             Error::PsiProtocolError(s) => write!(f, "PSI protocol error: {}", s),
+            Error::InvalidInput(s) => write!(f, "invalid input: {}", s),
             Error::InvalidPayloadsLength => write!(f, "Invalid length of payloads!"),
+            Error::AuditFailed(s) => write!(f, "audit failed: {}", s),
+            Error::InvalidTopology(s) => write!(f, "invalid topology: {}", s),
+            Error::InvalidSealedResults(s) => write!(f, "invalid sealed results: {}", s),
+            Error::InvalidCheckpoint(s) => write!(f, "invalid checkpoint: {}", s),
+            Error::AdmissionDenied(s) => write!(f, "admission denied: {}", s),
+            Error::SetSizeRejected(s) => write!(f, "set size rejected: {}", s),
+            Error::InvalidSecurityParameters(s) => {
+                write!(f, "invalid security parameters: {}", s)
+            }
+            Error::IncompatibleMasksize { ours, theirs } => write!(
+                f,
+                "incompatible mask sizes: this party accepts tags of {} to {} bytes, the peer {} to {}",
+                ours.min, ours.max, theirs.min, theirs.max
+            ),
+            Error::RoleConflict(s) => write!(f, "role conflict: {}", s),
+            Error::InvalidFuzzyInput(s) => write!(f, "invalid fuzzy PSI input: {}", s),
+            Error::Expired(s) => write!(f, "expired: {}", s),
+            Error::DeadlineExceeded(p) => write!(
+                f,
+                "deadline exceeded after {:?} and {} phases, having read {} bytes and written {}",
+                p.elapsed,
+                p.phases.len(),
+                p.bytes_read,
+                p.bytes_written
+            ),
+            #[cfg(feature = "config")]
+            Error::InvalidConfig(s) => write!(f, "invalid configuration: {}", s),
+            Error::InternalInvariant(s) => write!(f, "internal invariant violated: {}", s),
             #[cfg(feature = "psty")]
             Error::SSLError(e) => write!(f, "SSL Error: {}", e),
             #[cfg(feature = "psty")]
@@ -97,3 +389,34 @@ impl std::fmt::Display for Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_class() {
+        let eof = std::io::Error::new(ErrorKind::UnexpectedEof, "eof");
+        assert_eq!(Error::from(eof).class(), ErrorClass::PeerAbort);
+        let refused = std::io::Error::new(ErrorKind::ConnectionRefused, "refused");
+        let e = Error::from(ocelot::Error::from(refused));
+        assert_eq!(e.class(), ErrorClass::Network);
+        assert!(e.class().is_transient());
+        let e = Error::from(scuttlebutt::cointoss::Error::CommitmentCheckFailed);
+        assert_eq!(e.class(), ErrorClass::Protocol);
+        assert!(!e.class().is_transient());
+        // Errors raised on local inputs are never the peer's.
+        let e = "pzs".parse::<crate::psi::any::Protocol>().unwrap_err();
+        assert_eq!(e.class(), ErrorClass::InvalidInput);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let e = Error::PsiProtocolError("a \"quoted\"\nline".to_string());
+        assert_eq!(
+            e.to_json(),
+            "{\"class\":\"parameter_mismatch\",\"reason\":\"protocol\",\"exit_code\":65,\
+             \"transient\":false,\"message\":\"PSI protocol error: a \\\"quoted\\\"\\nline\"}"
+        );
+    }
+}
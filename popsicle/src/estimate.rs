@@ -0,0 +1,530 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Estimates of what a PSI session costs, computed from the message formats of
+//! the protocols without running them.
+//!
+//! `estimate` returns, for a protocol `Variant` and the two set sizes, the
+//! bytes each party sends, the number of rounds, the peak memory of each party
+//! and its CPU time, separately for the setup of a session and for one run.
+//! Communication and rounds follow the messages exactly, up to the cases
+//! documented on each `Variant`. Memory counts the protocol's own
+//! buffers, not allocator overhead or the caller's inputs, and CPU time is
+//! extrapolated from the per-operation costs of a `CostModel`, so both are
+//! meant for capacity planning rather than as bounds.
+//!
+//! ```rust
+//! use popsicle::estimate::{estimate, Params, Variant};
+//!
+//! let cost = estimate(&Params::new(Variant::Psz), 1 << 20, 1 << 20).unwrap();
+//! assert!(cost.run.bytes() < cost.total().bytes());
+//! println!("{} MB per run", cost.run.bytes() >> 20);
+//! ```
+
+use crate::{
+    cuckoo::{compute_masksize_with, compute_nbins, DEFAULT_NHASHES},
+    psi::{unbalanced::tag_len, voprf::DEFAULT_MAX_QUERIES},
+    security::SecurityParams,
+    Error,
+};
+use std::{ops::Add, time::Duration};
+
+// The number of base OTs of the KKRT OPRF, which is also the width in bits of
+// its pseudorandom code, and the number of public-key OTs these are extended
+// from.
+const NBASE: u64 = 512;
+const NPUBLIC: u64 = 128;
+// The sizes of a block and of a compressed Ristretto point or scalar.
+const BLOCK: u64 = 16;
+const POINT: u64 = 32;
+const USIZE: u64 = 8;
+// The versioned magic PSZ peers exchange.
+const VERSION: u64 = 32;
+// Bytes held per tag in a hash set, beyond its contents: a `Vec` header and
+// the table entry.
+const SET_OVERHEAD: u64 = 32;
+
+/// A protocol variant whose cost can be estimated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// `psz::Sender::send` and `psz::Receiver::receive`.
+    Psz,
+    /// `psz::Sender::send_confirmed` and `psz::Receiver::receive_confirmed`.
+    /// The confirmation round is estimated as if every item of the receiver
+    /// were a candidate, which bounds its cost from above.
+    PszConfirmed,
+    /// `psz::Sender::send_payloads` and `psz::Receiver::receive_payloads`,
+    /// with payloads of this many bytes.
+    PszPayloads(usize),
+    /// The maliciously secure `voprf` protocol.
+    Voprf,
+    /// The `dh` protocol for small sets.
+    Dh,
+    /// The `unbalanced` protocol, whose setup is the server building and
+    /// shipping the digest of its set; the sender is the server.
+    Unbalanced,
+}
+
+/// The time a `CostModel` charges each primitive operation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CostModel {
+    /// An AES block encryption, in nanoseconds.
+    pub aes_ns: f64,
+    /// A SHA-256 hash of a short input.
+    pub sha_ns: f64,
+    /// A hash set insertion or lookup.
+    pub set_ns: f64,
+    /// A Ristretto scalar multiplication.
+    pub scalar_mul_ns: f64,
+    /// A hash to the Ristretto group.
+    pub hash_to_group_ns: f64,
+    /// Each point of a multiscalar multiplication.
+    pub multiscalar_ns: f64,
+}
+
+impl Default for CostModel {
+    /// Rough costs on a recent x86-64 core with AES-NI.
+    fn default() -> Self {
+        Self {
+            aes_ns: 2.0,
+            sha_ns: 250.0,
+            set_ns: 60.0,
+            scalar_mul_ns: 50_000.0,
+            hash_to_group_ns: 10_000.0,
+            multiscalar_ns: 12_000.0,
+        }
+    }
+}
+
+/// What to estimate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Params {
+    /// The protocol variant.
+    pub variant: Variant,
+    /// The costs of primitive operations.
+    pub model: CostModel,
+    /// The number of hash functions of the receiver's cuckoo table, in the
+    /// PSZ variants.
+    pub nhashes: usize,
+    /// The security parameters, which size the cuckoo table and the tags in
+    /// the PSZ variants.
+    pub security: SecurityParams,
+}
+
+impl Params {
+    /// Estimate `variant` under the default `CostModel`.
+    pub fn new(variant: Variant) -> Self {
+        Self {
+            variant,
+            model: CostModel::default(),
+            nhashes: DEFAULT_NHASHES,
+            security: SecurityParams::default(),
+        }
+    }
+}
+
+/// The cost of a phase of a session.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cost {
+    /// The bytes the sender sends.
+    pub sender_bytes: u64,
+    /// The bytes the receiver sends.
+    pub receiver_bytes: u64,
+    /// The number of rounds, that is, of messages in alternating directions.
+    pub rounds: usize,
+    /// The peak memory of the sender, in bytes.
+    pub sender_memory: u64,
+    /// The peak memory of the receiver, in bytes.
+    pub receiver_memory: u64,
+    /// The CPU time of the sender.
+    pub sender_cpu: Duration,
+    /// The CPU time of the receiver.
+    pub receiver_cpu: Duration,
+}
+
+impl Cost {
+    /// The bytes both parties send.
+    pub fn bytes(&self) -> u64 {
+        self.sender_bytes + self.receiver_bytes
+    }
+}
+
+impl Add for Cost {
+    type Output = Cost;
+
+    /// The cost of running two phases one after the other.
+    fn add(self, rhs: Cost) -> Cost {
+        Cost {
+            sender_bytes: self.sender_bytes + rhs.sender_bytes,
+            receiver_bytes: self.receiver_bytes + rhs.receiver_bytes,
+            rounds: self.rounds + rhs.rounds,
+            sender_memory: self.sender_memory.max(rhs.sender_memory),
+            receiver_memory: self.receiver_memory.max(rhs.receiver_memory),
+            sender_cpu: self.sender_cpu + rhs.sender_cpu,
+            receiver_cpu: self.receiver_cpu + rhs.receiver_cpu,
+        }
+    }
+}
+
+/// The estimated cost of a session.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    /// Setting up the session, done once per pair of parties.
+    pub setup: Cost,
+    /// One run over the sets.
+    pub run: Cost,
+}
+
+impl Estimate {
+    /// The cost of setting up a session and doing one run.
+    pub fn total(&self) -> Cost {
+        self.setup + self.run
+    }
+}
+
+fn ns(x: f64) -> Duration {
+    Duration::from_nanos(x.round() as u64)
+}
+
+// Coin tossing `n` blocks, as the initiating party: its commitments and
+// openings, and the other party's seeds.
+fn cointoss(n: u64) -> (u64, u64) {
+    (2 * BLOCK * n, BLOCK * n)
+}
+
+// The KKRT setup: the base OTs, which the OPRF sender receives from an OT
+// extension it starts with public-key OTs in the other direction, and the
+// coin toss of the keys of the pseudorandom code.
+fn kkrt_setup(model: &CostModel) -> Cost {
+    let (toss_sender, toss_receiver) = cointoss(4);
+    let base_ots = NPUBLIC as f64 * 2.0 * model.scalar_mul_ns;
+    Cost {
+        sender_bytes: POINT + NPUBLIC * 2 * BLOCK + toss_sender + NPUBLIC * NBASE / 8,
+        receiver_bytes: NPUBLIC * POINT + toss_receiver + NBASE * 2 * BLOCK,
+        rounds: 6,
+        sender_memory: NBASE * BLOCK + NBASE / 8,
+        receiver_memory: NBASE * 2 * BLOCK,
+        sender_cpu: ns(base_ots),
+        receiver_cpu: ns(base_ots),
+    }
+}
+
+// The PSZ setup: the exchange of protocol versions, then the KKRT setup.
+fn psz_setup(model: &CostModel) -> Cost {
+    let mut setup = kkrt_setup(model);
+    setup.sender_bytes += VERSION;
+    setup.receiver_bytes += VERSION;
+    setup.rounds += 1;
+    setup
+}
+
+// One run of PSZ after the coin toss, with `tag` bytes per element of the
+// sender's `nhashes` sets.
+fn psz_run(
+    model: &CostModel,
+    nhashes: u64,
+    nsender: u64,
+    nreceiver: u64,
+    nbins: u64,
+    masksize: u64,
+    tag: u64,
+) -> Cost {
+    let nrows = nbins.div_ceil(16) * 16;
+    let ntags = nhashes * nsender;
+    // The receiver expands two PRGs per base OT over the rows and encodes its
+    // inputs; the sender expands one and encodes each of its tags. Both hash
+    // each OPRF output down to a tag.
+    let receiver_aes = 2 * NBASE * nrows / 128 + 4 * nbins + 4 * nreceiver;
+    let sender_aes = NBASE * nrows / 128 + 4 * ntags + nsender;
+    Cost {
+        sender_bytes: ntags * tag,
+        receiver_bytes: 2 * USIZE + 2 * nrows * NBASE / 8,
+        rounds: 2,
+        sender_memory: 2 * nrows * NBASE / 8 + nsender * BLOCK,
+        receiver_memory: 4 * nrows * NBASE / 8
+            + nbins * 2 * BLOCK
+            + ntags * (masksize + SET_OVERHEAD),
+        sender_cpu: ns(sender_aes as f64 * model.aes_ns + ntags as f64 * model.sha_ns),
+        receiver_cpu: ns(receiver_aes as f64 * model.aes_ns
+            + ntags as f64 * model.set_ns
+            + nreceiver as f64 * model.sha_ns),
+    }
+}
+
+// The coin toss starting a PSZ run, in three rounds.
+fn psz_cointoss() -> Cost {
+    let (sender_bytes, receiver_bytes) = cointoss(1);
+    Cost {
+        sender_bytes,
+        receiver_bytes,
+        rounds: 3,
+        ..Cost::default()
+    }
+}
+
+// The oblivious PRF of `voprf` on `n` queries, with its proof.
+fn voprf_query(model: &CostModel, n: u64) -> Cost {
+    let n_ = n as f64;
+    Cost {
+        sender_bytes: n * POINT + 2 * POINT,
+        receiver_bytes: USIZE + n * POINT,
+        rounds: 2,
+        sender_memory: 2 * n * POINT,
+        receiver_memory: 3 * n * POINT,
+        sender_cpu: ns(n_ * model.scalar_mul_ns + 2.0 * n_ * model.multiscalar_ns),
+        receiver_cpu: ns(
+            n_ * (model.hash_to_group_ns + 2.0 * model.scalar_mul_ns + model.sha_ns)
+                + 2.0 * n_ * model.multiscalar_ns,
+        ),
+    }
+}
+
+// Evaluating the PRF of `voprf` directly on `n` items under the key.
+fn voprf_evaluate(model: &CostModel, n: u64) -> Duration {
+    ns(n as f64 * (model.hash_to_group_ns + model.scalar_mul_ns + model.sha_ns))
+}
+
+/// Estimate the cost of `params.variant` for a sender holding `nsender`
+/// items and a receiver holding `nreceiver` items, failing if the protocol
+/// does not support sets of these sizes.
+pub fn estimate(params: &Params, nsender: usize, nreceiver: usize) -> Result<Estimate, Error> {
+    let model = &params.model;
+    let (ns_, nr_) = (nsender as u64, nreceiver as u64);
+    match params.variant {
+        Variant::Psz | Variant::PszConfirmed | Variant::PszPayloads(_) => {
+            let nhashes = params.nhashes as u64;
+            let nbins = compute_nbins(nreceiver, params.nhashes, &params.security)? as u64;
+            let masksize = compute_masksize_with(nsender, &params.security)? as u64;
+            let mut run = match params.variant {
+                Variant::PszPayloads(size) => {
+                    let size = size as u64;
+                    let mut run =
+                        psz_run(model, nhashes, ns_, nr_, nbins, masksize, masksize + size);
+                    run.sender_bytes += 2 * USIZE;
+                    run.receiver_memory += nhashes * ns_ * size;
+                    // Each pad is seeded by a hash of the OPRF output.
+                    run.sender_cpu += ns((nhashes * ns_) as f64
+                        * (size.div_ceil(BLOCK) as f64 * model.aes_ns + model.sha_ns));
+                    run
+                }
+                _ => psz_run(model, nhashes, ns_, nr_, nbins, masksize, masksize),
+            };
+            if params.variant == Variant::PszConfirmed {
+                // The receiver sends its number of candidates, the sender its
+                // set size, and they run again over the candidates.
+                let mut confirm = psz_run(model, nhashes, ns_, nr_, nbins, masksize, masksize);
+                confirm.sender_bytes += USIZE;
+                confirm.receiver_bytes += USIZE;
+                confirm.rounds += 2;
+                run = run + confirm;
+            }
+            Ok(Estimate {
+                setup: psz_setup(model),
+                run: psz_cointoss() + run,
+            })
+        }
+        Variant::Voprf => {
+            let mut run = voprf_query(model, nr_);
+            run.sender_bytes += USIZE + ns_ * POINT;
+            run.sender_cpu += voprf_evaluate(model, ns_);
+            run.receiver_memory += ns_ * (POINT + SET_OVERHEAD);
+            run.receiver_cpu += ns(ns_ as f64 * model.set_ns);
+            Ok(Estimate {
+                setup: Cost {
+                    sender_bytes: POINT,
+                    rounds: 1,
+                    sender_cpu: ns(model.scalar_mul_ns),
+                    ..Cost::default()
+                },
+                run,
+            })
+        }
+        Variant::Dh => {
+            let nr_f = nr_ as f64;
+            let run = Cost {
+                sender_bytes: nr_ * POINT + USIZE + ns_ * POINT,
+                receiver_bytes: USIZE + nr_ * POINT,
+                rounds: 2,
+                sender_memory: nr_ * POINT,
+                receiver_memory: 2 * nr_ * POINT + ns_ * (POINT + SET_OVERHEAD),
+                sender_cpu: ns(nr_f * model.scalar_mul_ns) + voprf_evaluate(model, ns_),
+                receiver_cpu: ns(nr_f
+                    * (model.hash_to_group_ns + 2.0 * model.scalar_mul_ns + model.sha_ns)
+                    + ns_ as f64 * model.set_ns),
+            };
+            Ok(Estimate {
+                setup: Cost::default(),
+                run,
+            })
+        }
+        Variant::Unbalanced => {
+            let digest = ns_ * tag_len(nsender, DEFAULT_MAX_QUERIES) as u64;
+            let mut run = voprf_query(model, nr_);
+            run.receiver_memory += digest;
+            run.receiver_cpu += ns(nr_ as f64 * model.set_ns);
+            Ok(Estimate {
+                setup: Cost {
+                    sender_bytes: POINT + 2 * USIZE + digest,
+                    rounds: 1,
+                    sender_memory: digest,
+                    receiver_memory: digest,
+                    sender_cpu: voprf_evaluate(model, ns_),
+                    ..Cost::default()
+                },
+                run,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        psi::{dh, voprf},
+        utils::rand_vec_vec,
+        Receiver,
+        Sender,
+    };
+    use scuttlebutt::{AesRng, TrackChannel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    // The bytes each party sends setting up a session and doing one run of
+    // `variant` over random sets of the given sizes, measured on the wire.
+    fn measure(variant: Variant, nsender: usize, nreceiver: usize) -> [(u64, u64); 2] {
+        let mut rng = AesRng::new();
+        let sender_inputs = rand_vec_vec(nsender, 8, &mut rng);
+        let receiver_inputs = rand_vec_vec(nreceiver, 8, &mut rng);
+        let (a, b) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(a.try_clone().unwrap());
+            let writer = BufWriter::new(a);
+            let mut channel = TrackChannel::new(reader, writer);
+            let bytes = |channel: &TrackChannel<_, _>| (channel.kilobits_written() * 125.0) as u64;
+            match variant {
+                Variant::Psz => {
+                    let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+                    let setup = bytes(&channel);
+                    psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+                    [setup, bytes(&channel) - setup]
+                }
+                Variant::Voprf => {
+                    let mut psi = voprf::Sender::init(&mut channel, &mut rng).unwrap();
+                    let setup = bytes(&channel);
+                    psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+                    [setup, bytes(&channel) - setup]
+                }
+                Variant::Dh => {
+                    let mut psi = dh::Sender::init(&mut channel, &mut rng).unwrap();
+                    let setup = bytes(&channel);
+                    psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+                    [setup, bytes(&channel) - setup]
+                }
+                _ => unimplemented!(),
+            }
+        });
+        let reader = BufReader::new(b.try_clone().unwrap());
+        let writer = BufWriter::new(b);
+        let mut channel = TrackChannel::new(reader, writer);
+        let bytes = |channel: &TrackChannel<_, _>| (channel.kilobits_written() * 125.0) as u64;
+        let receiver = match variant {
+            Variant::Psz => {
+                let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+                let setup = bytes(&channel);
+                psi.receive(&receiver_inputs, &mut channel, &mut rng)
+                    .unwrap();
+                [setup, bytes(&channel) - setup]
+            }
+            Variant::Voprf => {
+                let mut psi = voprf::Receiver::init(&mut channel, &mut rng).unwrap();
+                let setup = bytes(&channel);
+                psi.receive(&receiver_inputs, &mut channel, &mut rng)
+                    .unwrap();
+                [setup, bytes(&channel) - setup]
+            }
+            Variant::Dh => {
+                let mut psi = dh::Receiver::init(&mut channel, &mut rng).unwrap();
+                let setup = bytes(&channel);
+                psi.receive(&receiver_inputs, &mut channel, &mut rng)
+                    .unwrap();
+                [setup, bytes(&channel) - setup]
+            }
+            _ => unimplemented!(),
+        };
+        let sender = handle.join().unwrap();
+        [(sender[0], receiver[0]), (sender[1], receiver[1])]
+    }
+
+    #[test]
+    fn test_communication() {
+        for (variant, nsender, nreceiver) in &[
+            (Variant::Psz, 1000, 1000),
+            (Variant::Voprf, 300, 200),
+            (Variant::Dh, 300, 200),
+        ] {
+            let cost = estimate(&Params::new(*variant), *nsender, *nreceiver).unwrap();
+            let measured = measure(*variant, *nsender, *nreceiver);
+            assert_eq!(
+                measured[0],
+                (cost.setup.sender_bytes, cost.setup.receiver_bytes)
+            );
+            assert_eq!(
+                measured[1],
+                (cost.run.sender_bytes, cost.run.receiver_bytes)
+            );
+        }
+    }
+
+    #[test]
+    fn test_estimate() {
+        let params = Params::new(Variant::Psz);
+        let small = estimate(&params, 1 << 10, 1 << 10).unwrap();
+        let large = estimate(&params, 1 << 20, 1 << 20).unwrap();
+        assert_eq!(small.setup, large.setup);
+        assert!(large.run.bytes() > 500 * small.run.bytes());
+        assert!(large.run.receiver_cpu > small.run.receiver_cpu);
+        let confirmed = estimate(&Params::new(Variant::PszConfirmed), 1 << 10, 1 << 10).unwrap();
+        assert!(confirmed.run.bytes() > small.run.bytes());
+        let payloads = estimate(&Params::new(Variant::PszPayloads(16)), 1 << 10, 1 << 10).unwrap();
+        assert!(payloads.run.sender_bytes > small.run.sender_bytes);
+        // A fourth hash function shrinks the OPRF but grows the sender's tags.
+        let params = Params {
+            nhashes: 4,
+            ..params
+        };
+        let four = estimate(&params, 1 << 10, 1 << 10).unwrap();
+        assert!(four.run.receiver_bytes < small.run.receiver_bytes);
+        assert!(four.run.sender_bytes > small.run.sender_bytes);
+        // Stronger statistical security costs both parties.
+        let params = Params {
+            security: SecurityParams::new(60, 128).unwrap(),
+            ..Params::new(Variant::Psz)
+        };
+        let strong = estimate(&params, 1 << 10, 1 << 10).unwrap();
+        assert!(strong.run.receiver_bytes > small.run.receiver_bytes);
+        assert!(strong.run.sender_bytes > small.run.sender_bytes);
+
+        // Without a proof, `dh` is cheaper than `voprf`, and for small sets
+        // than `psz` with its base OTs.
+        let dh = estimate(&Params::new(Variant::Dh), 200, 200).unwrap();
+        let voprf = estimate(&Params::new(Variant::Voprf), 200, 200).unwrap();
+        let psz = estimate(&Params::new(Variant::Psz), 200, 200).unwrap();
+        assert!(dh.run.sender_cpu < voprf.run.sender_cpu);
+        assert!(dh.setup.bytes() + dh.run.bytes() < psz.setup.bytes() + psz.run.bytes());
+
+        // The online phase of unbalanced PSI does not grow with the server's
+        // set.
+        let params = Params::new(Variant::Unbalanced);
+        let small = estimate(&params, 1 << 10, 100).unwrap();
+        let large = estimate(&params, 1 << 24, 100).unwrap();
+        assert_eq!(small.run.bytes(), large.run.bytes());
+        assert!(large.setup.bytes() > small.setup.bytes());
+    }
+}
@@ -0,0 +1,372 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Lifecycle events of a PSI session, reported to an `AuditSink`.
+//!
+//! An application implements `AuditSink` to forward the events of its
+//! sessions, such as to a SIEM: the session started, the parameters agreed
+//! with the peer, each phase the protocol completed, the size of the result,
+//! and the error which aborted the session, if any. `send` and `receive`
+//! initialize and run a two-party protocol reporting to a sink; an `Auditor`
+//! reports the steps of any other flow, such as one run through
+//! `AnyPsiReceiver`, when given each step as a closure.
+//!
+//! Entry points beyond `PsiSender` and `PsiReceiver` report their own events
+//! to the sink of the `Auditor` running them: the `init_negotiated` and
+//! `init_with_level` of `psz` report the features agreed, and its
+//! `receive_checkpointed` and `receive_stream` the size of the result.
+//!
+//! The phases are those the protocols mark with `scuttlebutt::memtrack::phase`,
+//! and are only seen when completed on the thread running the step. The
+//! events carry no items, but the result size is the cardinality of the
+//! intersection, which the receiver may not be allowed to disclose.
+
+use crate::{
+    features::Features,
+    psi::{PsiReceiver, PsiSender},
+    topology::Role,
+    Error,
+};
+use rand::{CryptoRng, RngCore};
+use scuttlebutt::{memtrack, AbstractChannel};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// An event in the lifecycle of a session.
+#[derive(Clone, Copy, Debug)]
+pub enum AuditEvent<'a> {
+    /// The session started, with this party playing `role` in `protocol`.
+    SessionStarted {
+        /// The role of this party.
+        role: Role,
+        /// The name of the protocol.
+        protocol: &'a str,
+    },
+    /// The parties agreed on the optional features of the session.
+    ParametersNegotiated {
+        /// The features enabled.
+        features: Features,
+    },
+    /// The protocol completed a phase.
+    PhaseCompleted {
+        /// The name of the phase, such as `psz::receiver::oprf`.
+        phase: &'a str,
+        /// The time spent in the phase.
+        elapsed: Duration,
+    },
+    /// The receiver learned an intersection of `nitems` items.
+    ResultSize {
+        /// The number of items in the intersection.
+        nitems: usize,
+    },
+    /// The session failed with `error`.
+    Aborted {
+        /// The error which ended the session.
+        error: &'a Error,
+    },
+}
+
+impl std::fmt::Display for AuditEvent<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AuditEvent::SessionStarted { role, protocol } => {
+                write!(f, "session started: {:?} of {}", role, protocol)
+            }
+            AuditEvent::ParametersNegotiated { features } => {
+                write!(f, "parameters negotiated: features {:#x}", features.bits())
+            }
+            AuditEvent::PhaseCompleted { phase, elapsed } => {
+                write!(f, "phase completed: {} in {:?}", phase, elapsed)
+            }
+            AuditEvent::ResultSize { nitems } => write!(f, "result size: {}", nitems),
+            AuditEvent::Aborted { error } => {
+                write!(f, "aborted: {} ({})", error, error.class().name())
+            }
+        }
+    }
+}
+
+/// A destination for the events of a session. Any `FnMut(&AuditEvent)`
+/// closure is a sink.
+pub trait AuditSink: Send {
+    /// Record `event`. Must not itself run protocol phases.
+    fn record(&mut self, event: &AuditEvent);
+}
+
+impl<F: FnMut(&AuditEvent) + Send> AuditSink for F {
+    fn record(&mut self, event: &AuditEvent) {
+        self(event)
+    }
+}
+
+// Records an event to a sink.
+type Emitter = Rc<dyn Fn(&AuditEvent)>;
+
+thread_local! {
+    // Where the events of the step an `Auditor` runs on this thread go.
+    static EMITTER: RefCell<Option<Emitter>> = const { RefCell::new(None) };
+}
+
+// Report `event` to the sink of the `Auditor` running a step on the current
+// thread, if any.
+pub(crate) fn emit(event: &AuditEvent) {
+    let emitter = EMITTER.with(|e| e.borrow().clone());
+    if let Some(emitter) = emitter {
+        emitter(event)
+    }
+}
+
+// Puts back the emitter replaced while a step runs.
+struct Restore(Option<Emitter>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        EMITTER.with(|e| *e.borrow_mut() = previous);
+    }
+}
+
+/// Reports the steps of a session to a sink.
+pub struct Auditor<S> {
+    // Shared with the phase observer while a step runs.
+    sink: Arc<Mutex<S>>,
+}
+
+impl<S: AuditSink + 'static> Auditor<S> {
+    /// Start a session, in which this party plays `role` in `protocol`.
+    pub fn start(sink: S, role: Role, protocol: &str) -> Self {
+        let mut auditor = Self {
+            sink: Arc::new(Mutex::new(sink)),
+        };
+        auditor.record(&AuditEvent::SessionStarted { role, protocol });
+        auditor
+    }
+
+    /// Record `event`.
+    pub fn record(&mut self, event: &AuditEvent) {
+        record(&self.sink, event)
+    }
+
+    /// Run the step `f`, recording the phases it completes and the events
+    /// the protocol reports on this thread, and the error aborting the
+    /// session if it fails.
+    pub fn run<T>(&mut self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        let sink = self.sink.clone();
+        let emitter: Emitter = Rc::new(move |event| record(&sink, event));
+        let _restore = Restore(EMITTER.with(|e| e.borrow_mut().replace(emitter)));
+        let sink = self.sink.clone();
+        memtrack::observe(
+            move |phase, elapsed| record(&sink, &AuditEvent::PhaseCompleted { phase, elapsed }),
+            f,
+        )
+        .inspect_err(|error| self.record(&AuditEvent::Aborted { error }))
+    }
+
+    /// Release the sink, failing if a step still holds it, as it does when
+    /// the auditor is dropped by that step.
+    pub fn into_sink(self) -> Result<S, Error> {
+        match Arc::try_unwrap(self.sink) {
+            Ok(sink) => Ok(sink.into_inner().unwrap_or_else(|e| e.into_inner())),
+            Err(_) => Err(Error::InternalInvariant(
+                "the audit sink is still held by a running step".to_string(),
+            )),
+        }
+    }
+}
+
+impl<S> std::fmt::Debug for Auditor<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Auditor").finish()
+    }
+}
+
+fn record<S: AuditSink>(sink: &Mutex<S>, event: &AuditEvent) {
+    // A sink which panicked has seen none of the event, so may see the next.
+    let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+    sink.record(event)
+}
+
+/// Initialize the sender of protocol `P` and run it over `inputs`, reporting
+/// to `sink`.
+pub fn send<P, S, C, RNG>(
+    sink: S,
+    inputs: &[Vec<u8>],
+    channel: &mut C,
+    rng: &mut RNG,
+) -> Result<S, Error>
+where
+    P: PsiSender,
+    S: AuditSink + 'static,
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    let mut auditor = Auditor::start(sink, Role::Sender, P::PROTOCOL);
+    let mut psi = auditor.run(|| P::init(channel, rng))?;
+    auditor.record(&AuditEvent::ParametersNegotiated {
+        features: psi.features(),
+    });
+    auditor.run(|| psi.send(inputs, channel, rng))?;
+    auditor.into_sink()
+}
+
+/// Initialize the receiver of protocol `P` and run it over `inputs`,
+/// reporting to `sink`, and returning the intersection.
+pub fn receive<P, S, C, RNG>(
+    sink: S,
+    inputs: &[Vec<u8>],
+    channel: &mut C,
+    rng: &mut RNG,
+) -> Result<(Vec<Vec<u8>>, S), Error>
+where
+    P: PsiReceiver,
+    S: AuditSink + 'static,
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    let mut auditor = Auditor::start(sink, Role::Receiver, P::PROTOCOL);
+    let mut psi = auditor.run(|| P::init(channel, rng))?;
+    auditor.record(&AuditEvent::ParametersNegotiated {
+        features: psi.features(),
+    });
+    let intersection = auditor.run(|| psi.receive(inputs, channel, rng))?;
+    auditor.record(&AuditEvent::ResultSize {
+        nitems: intersection.len(),
+    });
+    Ok((intersection, auditor.into_sink()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dh, psz, security::SecurityLevel, utils::rand_vec_vec};
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const ITEM_SIZE: usize = 8;
+    const SET_SIZE: usize = 1 << 7;
+
+    // A sink keeping the events it records as strings, and those strings.
+    fn collect() -> (impl AuditSink + 'static, Arc<Mutex<Vec<String>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_ = events.clone();
+        let sink = move |event: &AuditEvent| events_.lock().unwrap().push(event.to_string());
+        (sink, events)
+    }
+
+    fn run<S: PsiSender, R: PsiReceiver>() -> (Vec<String>, Vec<String>) {
+        let mut rng = AesRng::new();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(SET_SIZE / 2, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..SET_SIZE / 2]);
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let (sink, events) = collect();
+            send::<S, _, _, _>(sink, &sender_inputs, &mut channel, &mut rng).unwrap();
+            let events = events.lock().unwrap().clone();
+            events
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let (sink, events) = collect();
+        let (intersection, _) =
+            receive::<R, _, _, _>(sink, &receiver_inputs, &mut channel, &mut rng).unwrap();
+        assert_eq!(intersection.len(), SET_SIZE / 2);
+        let events = events.lock().unwrap().clone();
+        (handle.join().unwrap(), events)
+    }
+
+    #[test]
+    fn test_events() {
+        let (sender, receiver) = run::<psz::Sender, psz::Receiver>();
+        assert_eq!(sender[0], "session started: Sender of psz");
+        assert_eq!(sender[1], "parameters negotiated: features 0x0");
+        assert!(sender[2..]
+            .iter()
+            .any(|e| e.starts_with("phase completed: psz::sender::oprf")));
+        assert!(receiver[0].starts_with("session started: Receiver"));
+        assert!(receiver[2..]
+            .iter()
+            .any(|e| e.starts_with("phase completed: psz::receiver::intersect")));
+        assert_eq!(
+            receiver.last().unwrap(),
+            &format!("result size: {}", SET_SIZE / 2)
+        );
+
+        let (sender, receiver) = run::<dh::Sender, dh::Receiver>();
+        assert!(sender.iter().any(|e| e.contains("dh::sender::evaluate")));
+        assert!(receiver.iter().any(|e| e.contains("dh::receiver::unblind")));
+    }
+
+    #[test]
+    fn test_aborted() {
+        // The sender hangs up before the session starts.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        drop(sender);
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let (sink, events) = collect();
+        assert!(receive::<psz::Receiver, _, _, _>(sink, &[], &mut channel, &mut rng).is_err());
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events[1].starts_with("aborted: "), "{}", events[1]);
+    }
+
+    #[test]
+    fn test_entry_points() {
+        // A streaming flow initialized at a security level, run through an
+        // auditor, reports the features agreed and the result size itself.
+        let mut rng = AesRng::new();
+        let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let inputs_ = inputs.clone();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi =
+                psz::Sender::init_with_level(&mut channel, &mut rng, SecurityLevel::Standard)
+                    .unwrap();
+            psi.send_stream(inputs_, SET_SIZE, &mut channel, &mut rng)
+                .unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let (sink, events) = collect();
+        let mut auditor = Auditor::start(sink, Role::Receiver, "psz");
+        let mut psi = auditor
+            .run(|| psz::Receiver::init_with_level(&mut channel, &mut rng, SecurityLevel::Standard))
+            .unwrap();
+        auditor
+            .run(|| psi.receive_stream(&inputs, &mut channel, &mut rng))
+            .unwrap();
+        auditor.into_sink().unwrap();
+        handle.join().unwrap();
+        let events = events.lock().unwrap();
+        assert!(events
+            .iter()
+            .any(|e| e.starts_with("parameters negotiated")));
+        assert_eq!(
+            events.last().unwrap(),
+            &format!("result size: {}", SET_SIZE)
+        );
+    }
+}
@@ -0,0 +1,125 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Optional protocol behaviors, negotiated between peers at initialization.
+//!
+//! Each peer sends the set of `Features` it supports as a 64-bit mask, and
+//! both enable exactly the features in the intersection of the two masks. A
+//! peer ignores bits it does not know, so a new behavior can be shipped behind
+//! a fresh bit and only takes effect once both sides of a session have been
+//! upgraded, without coordinating the upgrade.
+//!
+//! Bits are part of the wire format: once assigned, a bit keeps its meaning.
+
+use crate::Error;
+use scuttlebutt::AbstractChannel;
+use std::ops::{BitAnd, BitOr};
+
+/// A set of optional protocol behaviors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct Features(u64);
+
+impl Features {
+    /// No optional behavior.
+    pub const NONE: Features = Features(0);
+    /// Every item is placed in a cuckoo bin, with no stash. Cuckoo hashing in
+    /// this crate never uses a stash, so the PSI protocols require this
+    /// feature of their peer.
+    pub const STASH_FREE: Features = Features(1);
+    /// OPRF outputs are hashed before being masked and compared. Never
+    /// negotiated: the PSZ protocol always hashes them, and peers check the
+    /// protocol version instead.
+    pub const HASHED_OUTPUTS: Features = Features(1 << 1);
+    /// Every run is followed by a confirmation round under an independent key,
+    /// as in `psz::Sender::send_confirmed`.
+    pub const CONFIRMATION: Features = Features(1 << 2);
+    /// Messages are compressed. Reserved; not yet supported.
+    pub const COMPRESSION: Features = Features(1 << 3);
+    /// The length of the tags of every run is negotiated within the
+    /// `MasksizeBounds` of both parties, rather than computed by each.
+    pub const MASKSIZE_NEGOTIATION: Features = Features(1 << 4);
+    /// The features this version of the crate implements.
+    pub const SUPPORTED: Features =
+        Features(Self::STASH_FREE.0 | Self::CONFIRMATION.0 | Self::MASKSIZE_NEGOTIATION.0);
+
+    /// The features of the mask `bits`, including unknown ones.
+    #[inline]
+    pub fn from_bits(bits: u64) -> Self {
+        Features(bits)
+    }
+
+    /// The mask of the features.
+    #[inline]
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// Whether every feature of `other` is in `self`.
+    #[inline]
+    pub fn contains(self, other: Features) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Features {
+    type Output = Features;
+
+    #[inline]
+    fn bitor(self, rhs: Features) -> Features {
+        Features(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Features {
+    type Output = Features;
+
+    #[inline]
+    fn bitand(self, rhs: Features) -> Features {
+        Features(self.0 & rhs.0)
+    }
+}
+
+/// Exchange masks of supported features with the peer, returning the features
+/// both support.
+pub fn negotiate<C: AbstractChannel>(channel: &mut C, ours: Features) -> Result<Features, Error> {
+    channel.write_u64(ours.bits())?;
+    channel.flush()?;
+    let theirs = Features::from_bits(channel.read_u64()?);
+    Ok(ours & theirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scuttlebutt::Channel;
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    #[test]
+    fn test_negotiate() {
+        let (a, b) = UnixStream::pair().unwrap();
+        // The peer runs a later version, knowing a bit this one does not.
+        let unknown = Features::from_bits(1 << 40);
+        let handle = std::thread::spawn(move || {
+            let reader = BufReader::new(a.try_clone().unwrap());
+            let writer = BufWriter::new(a);
+            let mut channel = Channel::new(reader, writer);
+            negotiate(&mut channel, Features::SUPPORTED | unknown).unwrap()
+        });
+        let reader = BufReader::new(b.try_clone().unwrap());
+        let writer = BufWriter::new(b);
+        let mut channel = Channel::new(reader, writer);
+        let ours = Features::STASH_FREE | Features::COMPRESSION;
+        let agreed = negotiate(&mut channel, ours).unwrap();
+        assert_eq!(handle.join().unwrap(), agreed);
+        assert_eq!(agreed, Features::STASH_FREE);
+        assert!(!agreed.contains(unknown));
+        assert!(Features::SUPPORTED.contains(agreed));
+    }
+}
@@ -0,0 +1,242 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! A cuckoo filter, a probabilistic set membership structure of bounded size
+//! (cf. Fan, Andersen, Kaminsky, and Mitzenmacher, "Cuckoo Filter: Practically
+//! Better Than Bloom").
+//!
+//! The filter stores a short fingerprint of each item in one of two buckets of
+//! four slots, instead of the item itself, so that it takes a few bytes per
+//! item whatever their length. It never misses an inserted item, but reports
+//! an item that was never inserted with probability at most
+//! `CuckooFilter::false_positive_rate`, which is chosen by the caller when
+//! building the filter.
+
+use crate::Error;
+use std::{collections::hash_map::RandomState, hash::BuildHasher};
+
+// The number of slots of a bucket.
+const SLOTS: usize = 4;
+
+// The fraction of the slots filled at capacity.
+const LOAD: f64 = 0.9;
+
+// The number of relocations tried before an insertion gives up and stashes
+// its fingerprint.
+const MAX_KICKS: usize = 500;
+
+// The widest fingerprint, in bits.
+const MAX_FINGERPRINT_BITS: usize = 32;
+
+/// The most items a filter can be built to hold, which takes about a
+/// gigabyte.
+pub const MAX_CAPACITY: usize = 1 << 28;
+
+/// A cuckoo filter over byte strings.
+#[derive(Clone, Debug)]
+pub struct CuckooFilter {
+    // `SLOTS` fingerprints per bucket, where zero marks an empty slot.
+    slots: Vec<u32>,
+    // The fingerprints which found no slot, with the bucket they belong in.
+    stash: Vec<(usize, u32)>,
+    nbuckets: usize,
+    bits: usize,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl CuckooFilter {
+    /// A filter holding up to about `capacity` items, and reporting an item
+    /// which was not inserted with probability at most `fp_rate`. Fails unless
+    /// `capacity` is at most `MAX_CAPACITY` and `fp_rate` is strictly between
+    /// `2^-29` and one.
+    pub fn new(capacity: usize, fp_rate: f64) -> Result<Self, Error> {
+        if capacity > MAX_CAPACITY {
+            return Err(Error::InvalidCuckooSetSize(capacity));
+        }
+        // A lookup compares against the `2 * SLOTS` fingerprints of two
+        // buckets, each matching with probability `1 / (2^bits - 1)`.
+        if !(fp_rate > 0.0 && fp_rate < 1.0) {
+            return Err(Error::InvalidSecurityParameters(format!(
+                "false positive rate {} is not between zero and one",
+                fp_rate
+            )));
+        }
+        let bits = ((2 * SLOTS) as f64 / fp_rate + 1.0).log2().ceil() as usize;
+        if bits > MAX_FINGERPRINT_BITS {
+            return Err(Error::InvalidSecurityParameters(format!(
+                "false positive rate {} needs fingerprints of more than {} bits",
+                fp_rate, MAX_FINGERPRINT_BITS
+            )));
+        }
+        let nslots = ((capacity as f64 / (SLOTS as f64 * LOAD)).ceil() as usize)
+            .max(1)
+            .checked_next_power_of_two()
+            .and_then(|nbuckets| Some((nbuckets, nbuckets.checked_mul(SLOTS)?)));
+        let (nbuckets, nslots) = nslots.ok_or(Error::InvalidCuckooSetSize(capacity))?;
+        Ok(Self {
+            slots: vec![0; nslots],
+            stash: Vec::new(),
+            nbuckets,
+            bits,
+            len: 0,
+            hasher: RandomState::new(),
+        })
+    }
+
+    /// The number of items inserted.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no item was inserted.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of bits of each fingerprint.
+    pub fn fingerprint_bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The size of the filter, in bytes.
+    pub fn size_bytes(&self) -> usize {
+        4 * (self.slots.len() + 2 * self.stash.len())
+    }
+
+    /// The probability that `contains` reports an item which was not
+    /// inserted. It is at most the rate the filter was built with until an
+    /// insertion finds no slot, which is unlikely below its capacity.
+    pub fn false_positive_rate(&self) -> f64 {
+        (2 * SLOTS + self.stash.len()) as f64 / ((1u64 << self.bits) - 1) as f64
+    }
+
+    // The first bucket and the fingerprint of `item`.
+    fn locate(&self, item: &[u8]) -> (usize, u32) {
+        let h = self.hasher.hash_one(item);
+        // Fingerprints are never zero, which marks empty slots, so they are
+        // spread evenly over the `2^bits - 1` others.
+        let fp = ((h >> 32) % ((1u64 << self.bits) - 1) + 1) as u32;
+        (h as usize & (self.nbuckets - 1), fp)
+    }
+
+    // The other bucket of fingerprint `fp`, in bucket `i`.
+    #[inline]
+    fn alternate(&self, i: usize, fp: u32) -> usize {
+        (i ^ (fp.wrapping_mul(0x5bd1_e995) as usize)) & (self.nbuckets - 1)
+    }
+
+    #[inline]
+    fn bucket(&self, i: usize) -> &[u32] {
+        &self.slots[i * SLOTS..(i + 1) * SLOTS]
+    }
+
+    // Put `fp` in an empty slot of bucket `i`, if any.
+    fn put(&mut self, i: usize, fp: u32) -> bool {
+        match self.bucket(i).iter().position(|&s| s == 0) {
+            Some(j) => {
+                self.slots[i * SLOTS + j] = fp;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Insert `item`.
+    pub fn insert(&mut self, item: &[u8]) {
+        self.len += 1;
+        let (i, mut fp) = self.locate(item);
+        let j = self.alternate(i, fp);
+        if self.put(i, fp) || self.put(j, fp) {
+            return;
+        }
+        // Evict a fingerprint from one of the full buckets to its other
+        // bucket, until one has room.
+        let mut i = j;
+        for kick in 0..MAX_KICKS {
+            let slot = i * SLOTS + (fp as usize + kick) % SLOTS;
+            std::mem::swap(&mut fp, &mut self.slots[slot]);
+            i = self.alternate(i, fp);
+            if self.put(i, fp) {
+                return;
+            }
+        }
+        self.stash.push((i, fp));
+    }
+
+    /// Whether `item` may have been inserted.
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let (i, fp) = self.locate(item);
+        let j = self.alternate(i, fp);
+        self.bucket(i).contains(&fp)
+            || self.bucket(j).contains(&fp)
+            || self
+                .stash
+                .iter()
+                .any(|&(k, fp_)| fp_ == fp && (k == i || k == j))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rand_vec_vec;
+    use scuttlebutt::AesRng;
+
+    #[test]
+    fn test_filter() {
+        let mut rng = AesRng::new();
+        let n = 1 << 12;
+        let items = rand_vec_vec(n, 16, &mut rng);
+        let others = rand_vec_vec(1 << 14, 16, &mut rng);
+        for &fp_rate in &[0.1, 0.01, 1e-4] {
+            let mut filter = CuckooFilter::new(n, fp_rate).unwrap();
+            for item in items.iter() {
+                filter.insert(item);
+            }
+            assert_eq!(filter.len(), n);
+            assert!(filter.false_positive_rate() <= fp_rate);
+            assert!(items.iter().all(|item| filter.contains(item)));
+            let nfalse = others.iter().filter(|item| filter.contains(item)).count();
+            // Far above the expected number of false positives.
+            assert!(
+                (nfalse as f64) < 4.0 * fp_rate * others.len() as f64 + 8.0,
+                "{} false positives at rate {}",
+                nfalse,
+                fp_rate
+            );
+        }
+    }
+
+    #[test]
+    fn test_overfull() {
+        let mut rng = AesRng::new();
+        let items = rand_vec_vec(1 << 10, 16, &mut rng);
+        let mut filter = CuckooFilter::new(16, 0.01).unwrap();
+        for item in items.iter() {
+            filter.insert(item);
+        }
+        assert!(items.iter().all(|item| filter.contains(item)));
+        assert!(filter.false_positive_rate() > 0.01);
+    }
+
+    #[test]
+    fn test_params() {
+        for &fp_rate in &[0.0, 1.0, -0.5, f64::NAN, 1e-12] {
+            match CuckooFilter::new(16, fp_rate) {
+                Err(Error::InvalidSecurityParameters(_)) => (),
+                _ => panic!("accepted a false positive rate of {}", fp_rate),
+            }
+        }
+        assert_eq!(CuckooFilter::new(16, 0.5).unwrap().fingerprint_bits(), 5);
+        for &capacity in &[MAX_CAPACITY + 1, usize::MAX] {
+            match CuckooFilter::new(capacity, 0.5) {
+                Err(Error::InvalidCuckooSetSize(n)) => assert_eq!(n, capacity),
+                _ => panic!("accepted a capacity of {}", capacity),
+            }
+        }
+    }
+}
@@ -0,0 +1,402 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Fuzzy PSI over numeric keys, matching items which are close rather than
+//! byte-identical.
+//!
+//! Each item is a point with `i64` coordinates, such as a timestamp, or a
+//! latitude and longitude scaled to integers. Space is cut into cells of width
+//! `granularity` along every coordinate. The sender encodes each of its points
+//! as the cell holding it, and the receiver each of its points as every cell
+//! within `tolerance` of it along every coordinate, and the parties run any
+//! PSI protocol over the encodings. Every receiver point within `tolerance` of
+//! a sender point, coordinate by coordinate, is then found. As cells rather
+//! than points are compared, so may be points up to `tolerance + granularity`
+//! away. A finer granularity tightens the match, but a receiver point takes up
+//! to `(2 * tolerance / granularity + 2)^d` encodings in `d` dimensions.
+//!
+//! The receiver maps the matched cells back to its points locally, so that a
+//! point matching in several cells, or several points sharing a cell, are each
+//! reported once.
+//!
+//! `send` and `receive` first exchange, with `sizes::exchange`, how many
+//! encodings each party has, and pad theirs to the larger count, as some
+//! protocols require sets of the same size. Beyond the set sizes any PSI
+//! reveals, this reveals roughly how many cells the tolerance covers. As a
+//! party pads up to the count the peer declares, the caller's `SizePolicy`
+//! must set a `max_size`, and a larger declaration fails the run.
+
+use crate::{
+    psi::{PsiReceiver, PsiSender},
+    sizes::{self, SizePolicy},
+    Error,
+};
+use itertools::Itertools;
+use rand::{seq::SliceRandom, CryptoRng, Rng, RngCore};
+use scuttlebutt::AbstractChannel;
+use std::collections::HashMap;
+
+/// The most encodings a receiver point may expand to.
+pub const MAX_CELLS_PER_POINT: u64 = 1 << 16;
+
+// Leading bytes of encodings of cells and of padding, which therefore never
+// match each other.
+const CELL: u8 = 0;
+const DUMMY: u8 = 1;
+const DUMMY_SIZE: usize = 16;
+
+fn invalid(msg: String) -> Error {
+    Error::InvalidFuzzyInput(msg)
+}
+
+/// How close two points must be to match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FuzzyParams {
+    granularity: i64,
+    tolerance: i64,
+}
+
+impl FuzzyParams {
+    /// Match points within `tolerance` of each other along every coordinate,
+    /// comparing cells of width `granularity`. Both parties must use the same
+    /// granularity, and the sender's tolerance is unused.
+    pub fn new(granularity: u64, tolerance: u64) -> Result<Self, Error> {
+        if granularity == 0 || granularity > i64::MAX as u64 {
+            return Err(invalid(format!("unsupported granularity {}", granularity)));
+        }
+        if tolerance > i64::MAX as u64 {
+            return Err(invalid(format!("unsupported tolerance {}", tolerance)));
+        }
+        Ok(Self {
+            granularity: granularity as i64,
+            tolerance: tolerance as i64,
+        })
+    }
+
+    /// The width of a cell.
+    pub fn granularity(&self) -> u64 {
+        self.granularity as u64
+    }
+
+    /// The distance along each coordinate within which points match.
+    pub fn tolerance(&self) -> u64 {
+        self.tolerance as u64
+    }
+
+    fn cell(&self, x: i64) -> i64 {
+        x.div_euclid(self.granularity)
+    }
+
+    // Encode the cell with coordinates `cell`.
+    fn encode(&self, cell: &[i64]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(9 + 8 * cell.len());
+        bytes.push(CELL);
+        bytes.extend_from_slice(&self.granularity.to_le_bytes());
+        for c in cell {
+            bytes.extend_from_slice(&c.to_le_bytes());
+        }
+        bytes
+    }
+
+    // The encodings of the cells within `tolerance` of `point`.
+    fn window(&self, point: &[i64]) -> Result<Vec<Vec<u8>>, Error> {
+        let ranges = point
+            .iter()
+            .map(|&x| {
+                let lo = self.cell(x.saturating_sub(self.tolerance));
+                let hi = self.cell(x.saturating_add(self.tolerance));
+                lo..=hi
+            })
+            .collect_vec();
+        let ncells = ranges.iter().try_fold(1u64, |acc, r| {
+            let n = (*r.end() as i128 - *r.start() as i128 + 1) as u64;
+            acc.checked_mul(n).filter(|&n| n <= MAX_CELLS_PER_POINT)
+        });
+        if ncells.is_none() {
+            return Err(invalid(format!(
+                "a point covers more than {} cells",
+                MAX_CELLS_PER_POINT
+            )));
+        }
+        Ok(ranges
+            .into_iter()
+            .multi_cartesian_product()
+            .map(|cell| self.encode(&cell))
+            .collect())
+    }
+}
+
+// Fail unless every point has the same, non-zero, number of coordinates.
+fn check_points(points: &[Vec<i64>]) -> Result<(), Error> {
+    let dim = points.first().map_or(1, Vec::len);
+    if dim == 0 || points.iter().any(|p| p.len() != dim) {
+        return Err(invalid(
+            "points must all have the same, non-zero, dimension".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Encode the sender's `points` as the distinct cells holding them.
+pub fn sender_encodings(params: &FuzzyParams, points: &[Vec<i64>]) -> Result<Vec<Vec<u8>>, Error> {
+    check_points(points)?;
+    let mut items = points
+        .iter()
+        .map(|p| params.encode(&p.iter().map(|&x| params.cell(x)).collect_vec()))
+        .collect_vec();
+    items.sort();
+    items.dedup();
+    Ok(items)
+}
+
+/// The receiver's encodings of its points, remembering which points each came
+/// from.
+#[derive(Clone, Debug)]
+pub struct ReceiverEncodings {
+    items: Vec<Vec<u8>>,
+    owners: HashMap<Vec<u8>, Vec<usize>>,
+}
+
+impl ReceiverEncodings {
+    /// Encode the receiver's `points` as the distinct cells within
+    /// `params.tolerance()` of them.
+    pub fn new(params: &FuzzyParams, points: &[Vec<i64>]) -> Result<Self, Error> {
+        check_points(points)?;
+        let mut owners = HashMap::<_, Vec<usize>>::new();
+        for (i, point) in points.iter().enumerate() {
+            for cell in params.window(point)? {
+                owners.entry(cell).or_default().push(i);
+            }
+        }
+        let items = owners.keys().cloned().sorted().collect();
+        Ok(Self { items, owners })
+    }
+
+    /// The encodings to run the PSI protocol over.
+    pub fn items(&self) -> &[Vec<u8>] {
+        &self.items
+    }
+
+    /// The indices of the points with an encoding in `intersection`, in
+    /// increasing order and each once.
+    pub fn matches(&self, intersection: &[Vec<u8>]) -> Vec<usize> {
+        intersection
+            .iter()
+            .filter_map(|item| self.owners.get(item))
+            .flatten()
+            .copied()
+            .sorted()
+            .dedup()
+            .collect()
+    }
+}
+
+// Exchange the number of encodings with the peer, returning theirs.
+fn exchange<C: AbstractChannel>(
+    channel: &mut C,
+    ours: usize,
+    policy: &SizePolicy,
+) -> Result<usize, Error> {
+    if policy.size_limit().is_none() {
+        return Err(invalid(
+            "the size policy must limit the size the peer declares".to_string(),
+        ));
+    }
+    sizes::exchange(channel, ours, policy)
+}
+
+// Pad `items` with dummies up to `n` items, and shuffle them.
+fn pad<RNG: CryptoRng + RngCore>(items: &mut Vec<Vec<u8>>, n: usize, rng: &mut RNG) {
+    while items.len() < n {
+        let mut dummy = vec![0u8; 1 + DUMMY_SIZE];
+        dummy[0] = DUMMY;
+        rng.fill_bytes(&mut dummy[1..]);
+        items.push(dummy);
+    }
+    items.shuffle(rng);
+}
+
+/// Run the PSI protocol `psi` over the cells holding the sender's `points`,
+/// checking the receiver's number of encodings against `policy`. Must be
+/// paired with `receive`.
+pub fn send<S, C, RNG>(
+    psi: &mut S,
+    params: &FuzzyParams,
+    policy: &SizePolicy,
+    points: &[Vec<i64>],
+    channel: &mut C,
+    rng: &mut RNG,
+) -> Result<(), Error>
+where
+    S: PsiSender,
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    let mut items = sender_encodings(params, points)?;
+    let theirs = exchange(channel, items.len(), policy)?;
+    pad(&mut items, theirs, rng);
+    psi.send(&items, channel, rng)
+}
+
+/// Run the PSI protocol `psi` over the cells near the receiver's `points`,
+/// checking the sender's number of encodings against `policy`, and return
+/// the indices of the points close to one of the sender's, in increasing
+/// order. Must be paired with `send`.
+pub fn receive<R, C, RNG>(
+    psi: &mut R,
+    params: &FuzzyParams,
+    policy: &SizePolicy,
+    points: &[Vec<i64>],
+    channel: &mut C,
+    rng: &mut RNG,
+) -> Result<Vec<usize>, Error>
+where
+    R: PsiReceiver,
+    C: AbstractChannel,
+    RNG: CryptoRng + Rng,
+{
+    let encodings = ReceiverEncodings::new(params, points)?;
+    let mut items = encodings.items().to_vec();
+    let theirs = exchange(channel, items.len(), policy)?;
+    pad(&mut items, theirs, rng);
+    let intersection = psi.receive(&items, channel, rng)?;
+    Ok(encodings.matches(&intersection))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::psi::psz;
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    fn run_with(
+        params: FuzzyParams,
+        policy: SizePolicy,
+        sender_points: Vec<Vec<i64>>,
+        receiver_points: Vec<Vec<i64>>,
+    ) -> (Result<(), Error>, Result<Vec<usize>, Error>) {
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = psz::Sender::init(&mut channel, &mut rng).unwrap();
+            send(
+                &mut psi,
+                &params,
+                &policy,
+                &sender_points,
+                &mut channel,
+                &mut rng,
+            )
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = psz::Receiver::init(&mut channel, &mut rng).unwrap();
+        let matches = receive(
+            &mut psi,
+            &params,
+            &policy,
+            &receiver_points,
+            &mut channel,
+            &mut rng,
+        );
+        (handle.join().unwrap(), matches)
+    }
+
+    fn run(
+        params: FuzzyParams,
+        sender_points: Vec<Vec<i64>>,
+        receiver_points: Vec<Vec<i64>>,
+    ) -> Vec<usize> {
+        let policy = SizePolicy::new().max_size(1 << 16);
+        let (sent, matches) = run_with(params, policy, sender_points, receiver_points);
+        sent.unwrap();
+        matches.unwrap()
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let params = FuzzyParams::new(10, 10).unwrap();
+        let sender = vec![vec![1000], vec![5000], vec![-300]];
+        let receiver = vec![
+            vec![1004],
+            vec![2000],
+            vec![-296],
+            vec![4990],
+            vec![9000],
+            vec![996],
+        ];
+        assert_eq!(run(params, sender, receiver), vec![0, 2, 3, 5]);
+    }
+
+    #[test]
+    fn test_geo() {
+        // Coordinates in millionths of a degree, matched to about 100m.
+        let params = FuzzyParams::new(500, 1000).unwrap();
+        let sender = vec![vec![48_856_600, 2_352_200], vec![-33_868_800, 151_209_300]];
+        let receiver = vec![
+            vec![48_857_100, 2_351_500],
+            vec![48_856_600, 2_372_200],
+            vec![-33_869_400, 151_208_900],
+        ];
+        assert_eq!(run(params, sender, receiver), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_encodings() {
+        let params = FuzzyParams::new(10, 5).unwrap();
+        // Negative coordinates round down.
+        assert_eq!(params.cell(-1), -1);
+        assert_eq!(params.cell(9), 0);
+        let sender = sender_encodings(&params, &[vec![3], vec![7], vec![-3]]).unwrap();
+        assert_eq!(sender.len(), 2);
+        let receiver = ReceiverEncodings::new(&params, &[vec![3], vec![12]]).unwrap();
+        assert_eq!(receiver.items().len(), 3);
+        // Cell 0 is near both points, and reported once for each.
+        assert_eq!(receiver.matches(&sender), vec![0, 1]);
+        let far = sender_encodings(&params, &[vec![17]]).unwrap();
+        assert_eq!(receiver.matches(&far), vec![1]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(FuzzyParams::new(0, 5).is_err());
+        let params = FuzzyParams::new(1, 5).unwrap();
+        assert!(sender_encodings(&params, &[vec![1], vec![1, 2]]).is_err());
+        assert!(sender_encodings(&params, &[vec![]]).is_err());
+        let wide = FuzzyParams::new(1, 1 << 20).unwrap();
+        match ReceiverEncodings::new(&wide, &[vec![0]]) {
+            Err(Error::InvalidFuzzyInput(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // The window saturates at the ends of the range.
+        let encodings = ReceiverEncodings::new(&params, &[vec![i64::MAX]]).unwrap();
+        assert_eq!(encodings.items().len(), 6);
+    }
+
+    #[test]
+    fn test_size_policy() {
+        let params = FuzzyParams::new(1, 5).unwrap();
+        let points = vec![vec![0], vec![100]];
+        // The receiver's points take more than 16 encodings.
+        let policy = SizePolicy::new().max_size(16);
+        let (sent, received) = run_with(params, policy, points.clone(), points);
+        match (sent, received) {
+            (Err(Error::SetSizeRejected(_)), Err(Error::SetSizeRejected(_))) => (),
+            _ => panic!("a declaration over the limit was accepted"),
+        }
+        let mut channel = Channel::new(std::io::empty(), std::io::sink());
+        assert!(exchange(&mut channel, 1, &SizePolicy::new()).is_err());
+    }
+}
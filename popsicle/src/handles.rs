@@ -0,0 +1,169 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Opaque handles for the receiver's records, returned in place of its
+//! matched items.
+//!
+//! A receiver often matches its records on a sensitive key, such as an email
+//! address, when what it processes afterwards is the records themselves.
+//! `Records` pairs each key with a handle of the caller's choosing, such as a
+//! row number, and `receive` runs a protocol over the keys and returns the
+//! handles of the matched records, built from `PsiReceiver::receive_indices`.
+//! `Records` never gives its keys back, so the code downstream of the match
+//! cannot touch them, and erases them when dropped if the `zeroize` feature
+//! is enabled.
+
+use crate::{psi::PsiReceiver, Error};
+use rand::{CryptoRng, RngCore};
+use scuttlebutt::AbstractChannel;
+use std::iter::FromIterator;
+
+/// The records of a receiver, each a key to match on and a handle to return.
+pub struct Records<H> {
+    keys: Vec<Vec<u8>>,
+    handles: Vec<H>,
+}
+
+impl<H> Records<H> {
+    /// No records.
+    pub fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+            handles: Vec::new(),
+        }
+    }
+
+    /// Add the record with `key` and `handle`.
+    pub fn push(&mut self, key: Vec<u8>, handle: H) {
+        self.keys.push(key);
+        self.handles.push(handle);
+    }
+
+    /// The number of records.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether there are no records.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The handles of the records, in the order they were added.
+    pub fn handles(&self) -> &[H] {
+        &self.handles
+    }
+}
+
+impl<H> Default for Records<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H> FromIterator<(Vec<u8>, H)> for Records<H> {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, H)>>(iter: I) -> Self {
+        let (keys, handles) = iter.into_iter().unzip();
+        Self { keys, handles }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<H> Drop for Records<H> {
+    fn drop(&mut self) {
+        use scuttlebutt::utils::erase;
+        erase(&mut self.keys);
+    }
+}
+
+impl<H> std::fmt::Debug for Records<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Records")
+            .field("nrecords", &self.len())
+            .finish()
+    }
+}
+
+/// Run the PSI protocol `psi` over the keys of `records`, returning the
+/// handles of those whose key is in the intersection.
+pub fn receive<R, H, C, RNG>(
+    psi: &mut R,
+    records: &Records<H>,
+    channel: &mut C,
+    rng: &mut RNG,
+) -> Result<Vec<H>, Error>
+where
+    R: PsiReceiver,
+    H: Clone,
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    let indices = psi.receive_indices(&records.keys, channel, rng)?;
+    Ok(indices
+        .into_iter()
+        .map(|i| records.handles[i].clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dh, psi::PsiSender, psz, utils::rand_vec_vec};
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const ITEM_SIZE: usize = 8;
+    const SET_SIZE: usize = 1 << 8;
+
+    // Match the records of the receiver, whose handles are their positions,
+    // against a sender holding the keys at the even positions.
+    fn run<S: PsiSender, R: PsiReceiver>() -> Vec<usize> {
+        let mut rng = AesRng::new();
+        let keys = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut sender_inputs = rand_vec_vec(SET_SIZE / 2, ITEM_SIZE, &mut rng);
+        sender_inputs.extend(keys.iter().step_by(2).cloned());
+        let records = keys.into_iter().zip(0..).collect::<Records<usize>>();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = S::init(&mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = R::init(&mut channel, &mut rng).unwrap();
+        let mut handles = receive(&mut psi, &records, &mut channel, &mut rng).unwrap();
+        handle.join().unwrap();
+        handles.sort();
+        handles
+    }
+
+    #[test]
+    fn test_handles() {
+        let expected = (0..SET_SIZE).step_by(2).collect::<Vec<_>>();
+        assert_eq!(run::<psz::Sender, psz::Receiver>(), expected);
+        assert_eq!(run::<dh::Sender, dh::Receiver>(), expected);
+    }
+
+    #[test]
+    fn test_records() {
+        let mut records = Records::new();
+        assert!(records.is_empty());
+        records.push(b"alice".to_vec(), 'a');
+        records.push(b"bob".to_vec(), 'b');
+        assert_eq!(records.len(), 2);
+        assert_eq!(records.handles(), &['a', 'b']);
+        // The keys are not shown.
+        assert_eq!(format!("{:?}", records), "Records { nrecords: 2 }");
+    }
+}
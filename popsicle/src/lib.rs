@@ -11,9 +11,33 @@
 
 //!
 
-mod cuckoo;
+pub mod admission;
+#[cfg(any(test, feature = "adversary"))]
+pub mod adversary;
+pub mod audit;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod cuckoo;
+pub mod deadline;
+pub mod dedup;
+pub mod diff;
+pub mod estimate;
 mod errors;
+pub mod events;
+pub mod features;
+pub mod filter;
+pub mod fuzzy;
+pub mod handles;
 mod psi;
+pub mod retry;
+pub mod sealed;
+pub mod security;
+pub mod sizes;
+pub mod topology;
+pub mod two_choice;
 mod utils;
 
-pub use crate::{errors::Error, psi::*};
+pub use crate::{
+    errors::{Error, ErrorClass},
+    psi::*,
+};
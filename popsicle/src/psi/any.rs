@@ -0,0 +1,338 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Two-party PSI with the protocol chosen at runtime.
+//!
+//! `PsiSender` and `PsiReceiver` have generic methods, so they cannot be used
+//! as trait objects. `AnyPsiSender` and `AnyPsiReceiver` instead hold any of
+//! the two-party protocols of this crate that implement those traits, chosen
+//! by a `Protocol` value, e.g. the one of a config file, so that applications
+//! only name a single type whichever protocol they run. They implement the
+//! traits themselves, initializing the default protocol, so that they also
+//! fit code generic over the protocol. Both parties must choose the same
+//! protocol.
+
+use crate::{dh, features::Features, psz, voprf, Error, PsiReceiver, PsiSender};
+use rand::{CryptoRng, RngCore};
+use scuttlebutt::AbstractChannel;
+use std::str::FromStr;
+
+/// A PSI protocol, as named in config files (see `crate::config`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// Two-party PSI, see `crate::psz`.
+    #[default]
+    Psz,
+    /// Two-party PSI for small sets, see `crate::dh`.
+    Dh,
+    /// Maliciously secure two-party PSI, see `crate::voprf`.
+    Voprf,
+    /// Two-party PSI with computation on the intersection, see `crate::psty`.
+    Psty,
+    /// Multi-party PSI, see `crate::kmprt`.
+    Kmprt,
+}
+
+impl Protocol {
+    /// All protocols.
+    pub const ALL: [Protocol; 5] = [
+        Protocol::Psz,
+        Protocol::Dh,
+        Protocol::Voprf,
+        Protocol::Psty,
+        Protocol::Kmprt,
+    ];
+
+    /// The name of the protocol, as parsed by `Protocol::from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Psz => "psz",
+            Protocol::Dh => "dh",
+            Protocol::Voprf => "voprf",
+            Protocol::Psty => "psty",
+            Protocol::Kmprt => "kmprt",
+        }
+    }
+
+    /// Whether the protocol is run between more than two parties.
+    #[inline]
+    pub fn is_multiparty(self) -> bool {
+        self == Protocol::Kmprt
+    }
+
+    /// Whether `AnyPsiSender` and `AnyPsiReceiver` can run the protocol.
+    #[inline]
+    pub fn is_any(self) -> bool {
+        match self {
+            Protocol::Psz | Protocol::Dh | Protocol::Voprf => true,
+            Protocol::Psty | Protocol::Kmprt => false,
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "ecdh" => Ok(Protocol::Dh),
+            _ => Protocol::ALL
+                .iter()
+                .copied()
+                .find(|protocol| protocol.name() == s)
+                .ok_or_else(|| Error::InvalidInput(format!("unknown protocol `{}`", s))),
+        }
+    }
+}
+
+impl std::fmt::Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+// Protocols are (de)serialized by name, so that config files accept the same
+// names as `Protocol::from_str`.
+#[cfg(feature = "serde1")]
+impl serde::Serialize for Protocol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde1")]
+impl<'de> serde::Deserialize<'de> for Protocol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// The error of initializing a protocol that `AnyPsiSender` and
+// `AnyPsiReceiver` do not run.
+fn unsupported(protocol: Protocol) -> Error {
+    Error::InvalidInput(format!(
+        "`{}` cannot run through `AnyPsiSender` and `AnyPsiReceiver`",
+        protocol
+    ))
+}
+
+/// The sender of any two-party PSI protocol.
+// An application holds one per session, so the size of the largest variant
+// does not matter.
+#[allow(clippy::large_enum_variant)]
+pub enum AnyPsiSender {
+    /// A `psz` sender.
+    Psz(psz::Sender),
+    /// A `dh` sender.
+    Dh(dh::Sender),
+    /// A `voprf` sender.
+    Voprf(voprf::Sender),
+}
+
+/// The receiver of any two-party PSI protocol.
+#[allow(clippy::large_enum_variant)]
+pub enum AnyPsiReceiver {
+    /// A `psz` receiver.
+    Psz(psz::Receiver),
+    /// A `dh` receiver.
+    Dh(dh::Receiver),
+    /// A `voprf` receiver.
+    Voprf(voprf::Receiver),
+}
+
+impl AnyPsiSender {
+    /// Initialize the sender of `protocol`, failing unless
+    /// `Protocol::is_any`.
+    pub fn init_with_protocol<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        protocol: Protocol,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        match protocol {
+            Protocol::Psz => psz::Sender::init(channel, rng).map(AnyPsiSender::Psz),
+            Protocol::Dh => dh::Sender::init(channel, rng).map(AnyPsiSender::Dh),
+            Protocol::Voprf => voprf::Sender::init(channel, rng).map(AnyPsiSender::Voprf),
+            Protocol::Psty | Protocol::Kmprt => Err(unsupported(protocol)),
+        }
+    }
+
+    /// The protocol the sender runs.
+    pub fn protocol(&self) -> Protocol {
+        match self {
+            AnyPsiSender::Psz(_) => Protocol::Psz,
+            AnyPsiSender::Dh(_) => Protocol::Dh,
+            AnyPsiSender::Voprf(_) => Protocol::Voprf,
+        }
+    }
+}
+
+impl PsiSender for AnyPsiSender {
+    const PROTOCOL: &'static str = "any";
+
+    /// Initialize the sender of the default protocol; see
+    /// `AnyPsiSender::init_with_protocol` to choose another.
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        Self::init_with_protocol(Protocol::default(), channel, rng)
+    }
+
+    fn features(&self) -> Features {
+        match self {
+            AnyPsiSender::Psz(sender) => PsiSender::features(sender),
+            AnyPsiSender::Dh(sender) => PsiSender::features(sender),
+            AnyPsiSender::Voprf(sender) => PsiSender::features(sender),
+        }
+    }
+
+    fn send<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        match self {
+            AnyPsiSender::Psz(sender) => PsiSender::send(sender, inputs, channel, rng),
+            AnyPsiSender::Dh(sender) => PsiSender::send(sender, inputs, channel, rng),
+            AnyPsiSender::Voprf(sender) => PsiSender::send(sender, inputs, channel, rng),
+        }
+    }
+}
+
+impl AnyPsiReceiver {
+    /// Initialize the receiver of `protocol`, failing unless
+    /// `Protocol::is_any`.
+    pub fn init_with_protocol<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        protocol: Protocol,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        match protocol {
+            Protocol::Psz => psz::Receiver::init(channel, rng).map(AnyPsiReceiver::Psz),
+            Protocol::Dh => dh::Receiver::init(channel, rng).map(AnyPsiReceiver::Dh),
+            Protocol::Voprf => voprf::Receiver::init(channel, rng).map(AnyPsiReceiver::Voprf),
+            Protocol::Psty | Protocol::Kmprt => Err(unsupported(protocol)),
+        }
+    }
+
+    /// The protocol the receiver runs.
+    pub fn protocol(&self) -> Protocol {
+        match self {
+            AnyPsiReceiver::Psz(_) => Protocol::Psz,
+            AnyPsiReceiver::Dh(_) => Protocol::Dh,
+            AnyPsiReceiver::Voprf(_) => Protocol::Voprf,
+        }
+    }
+}
+
+impl PsiReceiver for AnyPsiReceiver {
+    const PROTOCOL: &'static str = "any";
+
+    /// Initialize the receiver of the default protocol; see
+    /// `AnyPsiReceiver::init_with_protocol` to choose another.
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        Self::init_with_protocol(Protocol::default(), channel, rng)
+    }
+
+    fn features(&self) -> Features {
+        match self {
+            AnyPsiReceiver::Psz(receiver) => PsiReceiver::features(receiver),
+            AnyPsiReceiver::Dh(receiver) => PsiReceiver::features(receiver),
+            AnyPsiReceiver::Voprf(receiver) => PsiReceiver::features(receiver),
+        }
+    }
+
+    fn receive<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        match self {
+            AnyPsiReceiver::Psz(receiver) => PsiReceiver::receive(receiver, inputs, channel, rng),
+            AnyPsiReceiver::Dh(receiver) => PsiReceiver::receive(receiver, inputs, channel, rng),
+            AnyPsiReceiver::Voprf(receiver) => PsiReceiver::receive(receiver, inputs, channel, rng),
+        }
+    }
+
+    fn receive_indices<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<usize>, Error> {
+        match self {
+            AnyPsiReceiver::Psz(receiver) => receiver.receive_indices(inputs, channel, rng),
+            AnyPsiReceiver::Dh(receiver) => receiver.receive_indices(inputs, channel, rng),
+            AnyPsiReceiver::Voprf(receiver) => receiver.receive_indices(inputs, channel, rng),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rand_vec_vec;
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const ITEM_SIZE: usize = 8;
+    const SET_SIZE: usize = 1 << 7;
+
+    #[test]
+    fn test_protocol() {
+        for protocol in Protocol::ALL.iter() {
+            assert_eq!(protocol.name().parse::<Protocol>().unwrap(), *protocol);
+        }
+        assert_eq!("ecdh".parse::<Protocol>().unwrap(), Protocol::Dh);
+        assert!("kkrt".parse::<Protocol>().is_err());
+        let (mut channel, _) = scuttlebutt::unix_channel_pair();
+        let mut rng = AesRng::new();
+        for &protocol in &[Protocol::Psty, Protocol::Kmprt] {
+            assert!(!protocol.is_any());
+            assert!(AnyPsiSender::init_with_protocol(protocol, &mut channel, &mut rng).is_err());
+        }
+    }
+
+    #[test]
+    fn test_psi() {
+        let mut rng = AesRng::new();
+        for &protocol in Protocol::ALL.iter().filter(|p| p.is_any()) {
+            let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+            let mut receiver_inputs = rand_vec_vec(SET_SIZE / 2, ITEM_SIZE, &mut rng);
+            receiver_inputs.extend_from_slice(&sender_inputs[..SET_SIZE / 2]);
+            let (sender, receiver) = UnixStream::pair().unwrap();
+            let handle = std::thread::spawn(move || {
+                let mut rng = AesRng::new();
+                let reader = BufReader::new(sender.try_clone().unwrap());
+                let writer = BufWriter::new(sender);
+                let mut channel = Channel::new(reader, writer);
+                let mut psi =
+                    AnyPsiSender::init_with_protocol(protocol, &mut channel, &mut rng).unwrap();
+                assert_eq!(psi.protocol(), protocol);
+                psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+            });
+            let reader = BufReader::new(receiver.try_clone().unwrap());
+            let writer = BufWriter::new(receiver);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi =
+                AnyPsiReceiver::init_with_protocol(protocol, &mut channel, &mut rng).unwrap();
+            let intersection = psi
+                .receive(&receiver_inputs, &mut channel, &mut rng)
+                .unwrap();
+            handle.join().unwrap();
+            assert_eq!(intersection.len(), SET_SIZE / 2, "{}", protocol);
+        }
+    }
+}
@@ -0,0 +1,420 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Async counterparts of `PsiSender` and `PsiReceiver`, running over tokio's
+//! `AsyncRead` and `AsyncWrite`.
+//!
+//! `Async<P>` implements the async traits for every protocol `P` implementing
+//! the blocking ones, so a service can accept connections as tokio tasks
+//! rather than spawning a thread for each. It works on any runtime, including
+//! a current-thread one, at a cost: a call runs the blocking protocol on
+//! tokio's blocking thread pool, holding one of its threads for the length of
+//! the call, and copies `inputs` there. A service therefore still uses a
+//! thread per running session, only taken from a pool: once the pool's
+//! `max_blocking_threads` (512 by default) are busy, further calls wait for
+//! one to finish. The protocol's reads and writes are forwarded to the stream
+//! by the calling task, so no runtime worker blocks on the peer; reads are
+//! buffered, and at most `MAX_QUEUED` writes and flushes wait for the task at
+//! any time.
+//!
+//! Each call runs the protocol on an `AesRng` seeded from `rng`. Dropping a
+//! call's future abandons the session: the party is still in the blocking
+//! thread, and every later call fails.
+
+use crate::{
+    psi::{PsiReceiver, PsiSender},
+    Error,
+};
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use scuttlebutt::{AbstractChannel, AesRng, Channel};
+use std::{
+    future::Future,
+    io::{self, BufWriter, Read, Write},
+    sync::mpsc as std_mpsc,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+/// The most requests the blocking protocol queues for the task driving the
+/// stream before waiting for it.
+pub const MAX_QUEUED: usize = 64;
+
+// The most bytes the task reads from the stream for a read of the protocol.
+const READ_BUFFER: usize = 1 << 16;
+
+// A request from the blocking protocol to the task driving the stream.
+enum Request {
+    Read(usize),
+    Write(Vec<u8>),
+    Flush,
+}
+
+fn stopped() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "the task driving the stream stopped",
+    )
+}
+
+// The reading end of the blocking protocol, waiting on the task's replies.
+// It buffers what the task reads, which may include bytes the peer sent for
+// the next call, so the bytes left unread are handed to that call.
+struct BridgeReader {
+    requests: mpsc::Sender<Request>,
+    replies: std_mpsc::Receiver<Vec<u8>>,
+    buffered: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for BridgeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buffered.len() {
+            self.requests
+                .blocking_send(Request::Read(READ_BUFFER))
+                .map_err(|_| stopped())?;
+            self.buffered = self.replies.recv().map_err(|_| stopped())?;
+            self.pos = 0;
+        }
+        let n = buf.len().min(self.buffered.len() - self.pos);
+        buf[..n].copy_from_slice(&self.buffered[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl BridgeReader {
+    // The bytes read from the stream which the protocol has not read.
+    fn unread(&mut self) -> Vec<u8> {
+        self.buffered.split_off(self.pos)
+    }
+}
+
+// The writing end of the blocking protocol. The task reports failed writes by
+// stopping, which fails the next read or write.
+struct BridgeWriter {
+    requests: mpsc::Sender<Request>,
+}
+
+impl Write for BridgeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.requests
+            .blocking_send(Request::Write(buf.to_vec()))
+            .map_err(|_| stopped())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.requests
+            .blocking_send(Request::Flush)
+            .map_err(|_| stopped())
+    }
+}
+
+// A blocking channel whose reads and writes are forwarded to an async stream.
+type BridgeChannel = Channel<BridgeReader, BufWriter<BridgeWriter>>;
+
+// Serve the requests of the blocking protocol until it drops its channel.
+async fn drive<S>(
+    stream: &mut S,
+    mut requests: mpsc::Receiver<Request>,
+    replies: std_mpsc::Sender<Vec<u8>>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    while let Some(request) = requests.recv().await {
+        match request {
+            Request::Read(len) => {
+                let mut bytes = vec![0; len];
+                let n = stream.read(&mut bytes).await?;
+                bytes.truncate(n);
+                if replies.send(bytes).is_err() {
+                    break;
+                }
+            }
+            Request::Write(bytes) => stream.write_all(&bytes).await?,
+            Request::Flush => stream.flush().await?,
+        }
+    }
+    Ok(())
+}
+
+// Run `f` on `party` on the blocking thread pool, over a channel on top of
+// `stream` which first reads the bytes `unread` by the previous call, and hand
+// the party back along with the bytes this call left unread and the result.
+async fn run_blocking<S, P, F, T>(
+    stream: &mut S,
+    party: P,
+    unread: Vec<u8>,
+    f: F,
+) -> (P, Vec<u8>, Result<T, Error>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    P: Send + 'static,
+    F: FnOnce(&mut P, &mut BridgeChannel) -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let (requests, requests_rx) = mpsc::channel(MAX_QUEUED);
+    let (replies_tx, replies) = std_mpsc::channel();
+    let reader = BridgeReader {
+        requests: requests.clone(),
+        replies,
+        buffered: unread,
+        pos: 0,
+    };
+    let writer = BufWriter::new(BridgeWriter { requests });
+    let task = tokio::task::spawn_blocking(move || {
+        let mut party = party;
+        let mut channel = Channel::new(reader, writer);
+        let result = f(&mut party, &mut channel).and_then(|t| {
+            channel.flush()?;
+            Ok(t)
+        });
+        let unread = channel.reader().borrow_mut().unread();
+        (party, unread, result)
+    });
+    let driven = drive(stream, requests_rx, replies_tx).await;
+    let (party, unread, result) = match task.await {
+        Ok(output) => output,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    };
+    (party, unread, driven.map_err(Error::from).and(result))
+}
+
+/// A party of the blocking protocol `P`, run over async streams.
+pub struct Async<P> {
+    party: Option<P>,
+    unread: Vec<u8>,
+}
+
+impl<P> Async<P> {
+    /// The blocking party, unless a call was abandoned while running it.
+    pub fn into_inner(self) -> Option<P> {
+        self.party
+    }
+
+    fn take(&mut self) -> Result<P, Error> {
+        self.party
+            .take()
+            .ok_or_else(|| Error::InvalidInput("an earlier call was abandoned mid-run".to_string()))
+    }
+}
+
+// Initialize a blocking party on the blocking thread pool.
+async fn init<S, RNG, P, F>(stream: &mut S, rng: &mut RNG, init: F) -> Result<Async<P>, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    RNG: CryptoRng + RngCore,
+    P: Send + 'static,
+    F: FnOnce(&mut BridgeChannel, &mut AesRng) -> Result<P, Error> + Send + 'static,
+{
+    let mut rng = AesRng::from_seed(rng.gen());
+    let ((), unread, party) = run_blocking(stream, (), Vec::new(), move |(), channel| {
+        init(channel, &mut rng)
+    })
+    .await;
+    Ok(Async {
+        party: Some(party?),
+        unread,
+    })
+}
+
+/// The sender side of a two-party PSI protocol, over an async stream.
+pub trait AsyncPsiSender: Sized {
+    /// Initialize the sender.
+    fn init<S, RNG>(
+        stream: &mut S,
+        rng: &mut RNG,
+    ) -> impl Future<Output = Result<Self, Error>> + Send
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+        RNG: CryptoRng + RngCore + Send;
+
+    /// Run the protocol over `inputs`.
+    fn send<S, RNG>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        stream: &mut S,
+        rng: &mut RNG,
+    ) -> impl Future<Output = Result<(), Error>> + Send
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+        RNG: CryptoRng + RngCore + Send;
+}
+
+/// The receiver side of a two-party PSI protocol, over an async stream.
+pub trait AsyncPsiReceiver: Sized {
+    /// Initialize the receiver.
+    fn init<S, RNG>(
+        stream: &mut S,
+        rng: &mut RNG,
+    ) -> impl Future<Output = Result<Self, Error>> + Send
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+        RNG: CryptoRng + RngCore + Send;
+
+    /// Run the protocol over `inputs`, returning those in the intersection.
+    fn receive<S, RNG>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        stream: &mut S,
+        rng: &mut RNG,
+    ) -> impl Future<Output = Result<Vec<Vec<u8>>, Error>> + Send
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+        RNG: CryptoRng + RngCore + Send;
+}
+
+impl<P: PsiSender + Send + 'static> AsyncPsiSender for Async<P> {
+    async fn init<S, RNG>(stream: &mut S, rng: &mut RNG) -> Result<Self, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+        RNG: CryptoRng + RngCore + Send,
+    {
+        init(stream, rng, |channel, rng| P::init(channel, rng)).await
+    }
+
+    async fn send<S, RNG>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        stream: &mut S,
+        rng: &mut RNG,
+    ) -> Result<(), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+        RNG: CryptoRng + RngCore + Send,
+    {
+        let party = self.take()?;
+        let inputs = inputs.to_vec();
+        let mut rng = AesRng::from_seed(rng.gen());
+        let unread = std::mem::take(&mut self.unread);
+        let (party, unread, result) = run_blocking(stream, party, unread, move |party, channel| {
+            PsiSender::send(party, &inputs, channel, &mut rng)
+        })
+        .await;
+        self.party = Some(party);
+        self.unread = unread;
+        result
+    }
+}
+
+impl<P: PsiReceiver + Send + 'static> AsyncPsiReceiver for Async<P> {
+    async fn init<S, RNG>(stream: &mut S, rng: &mut RNG) -> Result<Self, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+        RNG: CryptoRng + RngCore + Send,
+    {
+        init(stream, rng, |channel, rng| P::init(channel, rng)).await
+    }
+
+    async fn receive<S, RNG>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        stream: &mut S,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+        RNG: CryptoRng + RngCore + Send,
+    {
+        let party = self.take()?;
+        let inputs = inputs.to_vec();
+        let mut rng = AesRng::from_seed(rng.gen());
+        let unread = std::mem::take(&mut self.unread);
+        let (party, unread, result) = run_blocking(stream, party, unread, move |party, channel| {
+            PsiReceiver::receive(party, &inputs, channel, &mut rng)
+        })
+        .await;
+        self.party = Some(party);
+        self.unread = unread;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        psi::{MaliciousReceiver, MaliciousSender, Receiver, Sender},
+        utils::rand_vec_vec,
+    };
+    use scuttlebutt::AesRng;
+    use std::collections::HashSet;
+
+    async fn run<S: AsyncPsiSender + Send + 'static, R: AsyncPsiReceiver>(
+        sender_inputs: Vec<Vec<u8>>,
+        receiver_inputs: &[Vec<u8>],
+    ) -> Vec<Vec<u8>> {
+        let (mut a, mut b) = tokio::io::duplex(1 << 16);
+        let sender = tokio::spawn(async move {
+            let mut rng = AesRng::new();
+            let mut psi = S::init(&mut a, &mut rng).await.unwrap();
+            // Two runs share the session.
+            for _ in 0..2 {
+                psi.send(&sender_inputs, &mut a, &mut rng).await.unwrap();
+            }
+        });
+        let mut rng = AesRng::new();
+        let mut psi = R::init(&mut b, &mut rng).await.unwrap();
+        let first = psi
+            .receive(receiver_inputs, &mut b, &mut rng)
+            .await
+            .unwrap();
+        let second = psi
+            .receive(receiver_inputs, &mut b, &mut rng)
+            .await
+            .unwrap();
+        sender.await.unwrap();
+        assert_eq!(
+            first.iter().collect::<HashSet<_>>(),
+            second.iter().collect::<HashSet<_>>()
+        );
+        first
+    }
+
+    fn run_all(runtime: tokio::runtime::Runtime) {
+        let mut rng = AesRng::new();
+        let sender_inputs = rand_vec_vec(1 << 8, 8, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(1 << 7, 8, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..1 << 7]);
+        let expected = sender_inputs[..1 << 7].iter().collect::<HashSet<_>>();
+        runtime.block_on(async {
+            let intersection =
+                run::<Async<Sender>, Async<Receiver>>(sender_inputs.clone(), &receiver_inputs)
+                    .await;
+            assert_eq!(intersection.iter().collect::<HashSet<_>>(), expected);
+            let intersection = run::<Async<MaliciousSender>, Async<MaliciousReceiver>>(
+                sender_inputs.clone(),
+                &receiver_inputs,
+            )
+            .await;
+            assert_eq!(intersection.iter().collect::<HashSet<_>>(), expected);
+        });
+    }
+
+    #[test]
+    fn test_async_psi() {
+        run_all(
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .enable_all()
+                .build()
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_async_psi_current_thread() {
+        run_all(
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap(),
+        );
+    }
+}
@@ -7,7 +7,7 @@
 //! Implementation of the "Kolesnikov-Matania-Pinkas-Rosulek-Trieu" multi-party private
 //! set intersection protocol (cf. <https://eprint.iacr.org/2017/799.pdf>).
 
-use crate::Error;
+use crate::{utils, Error};
 use itertools::Itertools;
 use ocelot::oprf::{KmprtReceiver, KmprtSender};
 use rand::{CryptoRng, Rng, RngCore, SeedableRng};
@@ -46,15 +46,17 @@ impl Sender {
         channels: &mut [(PartyId, C)],
         rng: &mut RNG,
     ) -> Result<(), Error> {
-        assert!(self.0.id != 0);
+        utils::session(|| {
+            utils::invariant(self.0.id != 0, "party 0 is the receiver")?;
 
-        let s_hat = self.0.conditional_secret_sharing(inputs, channels, rng)?;
+            let s_hat = self.0.conditional_secret_sharing(inputs, channels, rng)?;
 
-        // conditional reconstruction
-        let points = inputs.iter().cloned().zip(s_hat.into_iter()).collect_vec();
-        self.0.opprf_senders[0].send(&mut channels[0].1, &points, inputs.len(), rng)?;
+            // conditional reconstruction
+            let points = inputs.iter().cloned().zip(s_hat).collect_vec();
+            self.0.opprf_senders[0].send(&mut channels[0].1, &points, inputs.len(), rng)?;
 
-        Ok(())
+            Ok(())
+        })
     }
 }
 
@@ -74,29 +76,31 @@ impl Receiver {
         channels: &mut [(PartyId, C)],
         rng: &mut RNG,
     ) -> Result<Vec<Block>, Error> {
-        let mut s_hat = self.0.conditional_secret_sharing(inputs, channels, rng)?;
-
-        // conditional reconstruction
-        for (channel_num, (_, channel)) in channels.iter_mut().enumerate() {
-            let shares = self.0.opprf_receivers[channel_num].receive(channel, inputs, rng)?;
-            for (i, share) in shares.into_iter().enumerate() {
-                s_hat[i] ^= share;
+        utils::session(|| {
+            let mut s_hat = self.0.conditional_secret_sharing(inputs, channels, rng)?;
+
+            // conditional reconstruction
+            for (channel_num, (_, channel)) in channels.iter_mut().enumerate() {
+                let shares = self.0.opprf_receivers[channel_num].receive(channel, inputs, rng)?;
+                for (i, share) in shares.into_iter().enumerate() {
+                    s_hat[i] ^= share;
+                }
             }
-        }
 
-        let intersection = inputs
-            .iter()
-            .zip(s_hat.into_iter())
-            .filter_map(|(x, s)| {
-                if s == Block512::default() {
-                    Some(*x)
-                } else {
-                    None
-                }
-            })
-            .collect_vec();
+            let intersection = inputs
+                .iter()
+                .zip(s_hat)
+                .filter_map(|(x, s)| {
+                    if s == Block512::default() {
+                        Some(*x)
+                    } else {
+                        None
+                    }
+                })
+                .collect_vec();
 
-        Ok(intersection)
+            Ok(intersection)
+        })
     }
 }
 
@@ -149,6 +153,10 @@ impl Party {
             .collect_vec();
 
         for (channel_num, (other_id, channel)) in channels.iter_mut().enumerate() {
+            utils::invariant(
+                *other_id < nparties,
+                "party ids are below the number of parties",
+            )?;
             let points = inputs
                 .iter()
                 .enumerate()
@@ -229,18 +237,12 @@ mod tests {
         let mut channels = (0..nparties)
             .map(|_| (0..nparties).map(|_| None).collect_vec())
             .collect_vec();
-        for i in 0..nparties {
-            for j in 0..nparties {
-                if i != j {
-                    let (s, r) = UnixStream::pair().unwrap();
-                    let left =
-                        SyncChannel::new(BufReader::new(s.try_clone().unwrap()), BufWriter::new(s));
-                    let right =
-                        SyncChannel::new(BufReader::new(r.try_clone().unwrap()), BufWriter::new(r));
-                    channels[i][j] = Some((j, left));
-                    channels[j][i] = Some((i, right));
-                }
-            }
+        for (i, j) in (0..nparties).tuple_combinations() {
+            let (s, r) = UnixStream::pair().unwrap();
+            let left = SyncChannel::new(BufReader::new(s.try_clone().unwrap()), BufWriter::new(s));
+            let right = SyncChannel::new(BufReader::new(r.try_clone().unwrap()), BufWriter::new(r));
+            channels[i][j] = Some((j, left));
+            channels[j][i] = Some((i, right));
         }
         let mut channels = channels
             .into_iter()
@@ -4,16 +4,176 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
+pub mod any;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod dh;
 pub mod kmprt;
+pub mod multiparty;
+pub mod peer;
 #[cfg(feature = "psty")]
 pub mod psty;
 pub mod psz;
+pub mod unbalanced;
+pub mod voprf;
+
+use crate::{features::Features, Error};
+use rand::{seq::SliceRandom, CryptoRng, RngCore};
+use scuttlebutt::AbstractChannel;
+use std::collections::HashSet;
+
+pub use any::{AnyPsiReceiver, AnyPsiSender};
+#[cfg(feature = "async")]
+pub use asynchronous::{Async, AsyncPsiReceiver, AsyncPsiSender};
+
+/// The sender side of a two-party PSI protocol.
+///
+/// Integration code written against `PsiSender` and `PsiReceiver` can switch
+/// protocols, such as from the semi-honest `psz` to the maliciously secure
+/// `voprf`, or to the `dh` protocol for small sets, by changing a type. The
+/// security a protocol provides is marked by `scuttlebutt::SemiHonest` and
+/// `scuttlebutt::Malicious`. To choose the protocol at runtime instead, see
+/// `any::AnyPsiSender`.
+pub trait PsiSender: Sized {
+    /// The name of the protocol, such as `psz`.
+    const PROTOCOL: &'static str;
+
+    /// Initialize the sender.
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error>;
+
+    /// The features agreed with the receiver at initialization, which are
+    /// none for protocols that negotiate none.
+    fn features(&self) -> Features {
+        Features::NONE
+    }
+
+    /// Run the protocol over `inputs`.
+    fn send<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error>;
+}
+
+/// The receiver side of a two-party PSI protocol, which learns the
+/// intersection.
+pub trait PsiReceiver: Sized {
+    /// The name of the protocol, such as `psz`.
+    const PROTOCOL: &'static str;
+
+    /// Initialize the receiver.
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error>;
+
+    /// The features agreed with the sender at initialization, which are none
+    /// for protocols that negotiate none.
+    fn features(&self) -> Features {
+        Features::NONE
+    }
+
+    /// Run the protocol over `inputs`, returning those in the intersection.
+    fn receive<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error>;
+
+    /// Run the protocol over `inputs`, returning the indices in `inputs` of
+    /// those in the intersection rather than the items.
+    ///
+    /// The default implementation looks the items returned by `receive` up in
+    /// `inputs`, so that every copy of a matched item is returned; the
+    /// protocols of this crate track the indices instead.
+    fn receive_indices<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<usize>, Error> {
+        let intersection = self.receive(inputs, channel, rng)?;
+        let matched = intersection.iter().collect::<HashSet<_>>();
+        Ok((0..inputs.len())
+            .filter(|&i| matched.contains(&inputs[i]))
+            .collect())
+    }
+}
+
+/// A receiver-side policy deciding which matched items are revealed.
+///
+/// The receiver consults the policy on each of its inputs found in the
+/// intersection, before including it in the result, so that governance logic
+/// such as a denylist or sampling for review runs inside the library. Any
+/// `FnMut(&[u8]) -> bool` closure is a policy.
+pub trait MatchPolicy: Send {
+    /// Whether to include `item`, an input of the receiver which matched, in
+    /// the intersection.
+    fn reveal(&mut self, item: &[u8]) -> bool;
+}
+
+impl<F: FnMut(&[u8]) -> bool + Send> MatchPolicy for F {
+    fn reveal(&mut self, item: &[u8]) -> bool {
+        self(item)
+    }
+}
+
+/// The order in which a receiver returns the intersection.
+///
+/// Matches are found bin by bin, so in the order of the receiver's cuckoo
+/// table, which depends on the hashing key agreed with the sender and on the
+/// other inputs. Results passed on in that order tell whoever sees them more
+/// than which items matched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub enum OutputOrder {
+    /// The order of the receiver's inputs, the default.
+    #[default]
+    Input,
+    /// Sorted by item, bytewise.
+    Sorted,
+    /// A uniformly random order, drawn anew each run.
+    Shuffled,
+}
+
+impl OutputOrder {
+    // Put the indices into `inputs` of `intersection` in this order.
+    pub(crate) fn arrange<RNG: CryptoRng + RngCore>(
+        self,
+        inputs: &[Vec<u8>],
+        intersection: &mut [usize],
+        rng: &mut RNG,
+    ) {
+        match self {
+            OutputOrder::Input => intersection.sort_unstable(),
+            OutputOrder::Sorted => {
+                intersection.sort_unstable_by(|&i, &j| inputs[i].cmp(&inputs[j]))
+            }
+            OutputOrder::Shuffled => intersection.shuffle(rng),
+        }
+    }
+}
 
 /// Private set intersection sender.
 pub type Sender = psz::Sender;
 /// Private set intersection receiver.
 pub type Receiver = psz::Receiver;
 
+/// Private set intersection sender for small sets.
+pub type SmallSetSender = dh::Sender;
+/// Private set intersection receiver for small sets.
+pub type SmallSetReceiver = dh::Receiver;
+
+/// Maliciously secure private set intersection sender.
+pub type MaliciousSender = voprf::Sender;
+/// Maliciously secure private set intersection receiver.
+pub type MaliciousReceiver = voprf::Receiver;
+
 #[cfg(feature = "psty")]
 /// Extended private set intersection sender.
 pub type ExtendedSender = psty::Sender;
@@ -0,0 +1,264 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Multi-party private set intersection over byte-string items.
+//!
+//! This wraps the Kolesnikov-Matania-Pinkas-Rosulek-Trieu protocol of `kmprt`,
+//! which works on `Block`s, in an API matching the two-party protocols. Each
+//! party holds a channel to every other party, in the `(PartyId, channel)`
+//! form produced by `crate::topology::Topology::connect`. Party zero is the
+//! `Leader`, which learns the intersection; every other party is a `Member`,
+//! which contributes its set but learns nothing. Code which runs the same
+//! binary for every party can use `Party`, which picks the role from the
+//! party id.
+//!
+//! All parties must hold sets of the same size, which is checked before the
+//! protocol runs.
+
+use crate::{
+    psi::kmprt::{self, PartyId},
+    topology::Role,
+    utils,
+    Error,
+};
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use scuttlebutt::{AbstractChannel, Block};
+
+/// The party which learns the intersection.
+pub struct Leader {
+    key: Block,
+    inner: kmprt::Receiver,
+}
+
+/// A party which contributes its set but learns nothing.
+pub struct Member {
+    key: Block,
+    inner: kmprt::Sender,
+}
+
+/// Either role of a multi-party session.
+pub enum Party {
+    /// Party zero.
+    Leader(Leader),
+    /// Any other party.
+    Member(Member),
+}
+
+fn check_channels<C>(me: PartyId, channels: &[(PartyId, C)]) -> Result<(), Error> {
+    let nparties = channels.len() + 1;
+    let mut expected = (0..nparties).filter(|&id| id != me);
+    if nparties < 2 || !channels.iter().all(|(id, _)| expected.next() == Some(*id)) {
+        return Err(Error::InvalidTopology(format!(
+            "party {} needs one channel to each of the other parties, in id order",
+            me
+        )));
+    }
+    Ok(())
+}
+
+impl Leader {
+    /// Initialize the leader, choosing the key with which every party hashes
+    /// its items.
+    pub fn init<C: AbstractChannel, RNG: RngCore + CryptoRng + SeedableRng>(
+        channels: &mut [(PartyId, C)],
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        check_channels(0, channels)?;
+        let key = rng.gen::<Block>();
+        for (_, channel) in channels.iter_mut() {
+            channel.write_block(&key)?;
+            channel.flush()?;
+        }
+        let inner = kmprt::Receiver::init(channels, rng)?;
+        Ok(Self { key, inner })
+    }
+
+    /// Run the protocol over `inputs`, returning those items held by every
+    /// party, in the order they appear in `inputs`.
+    pub fn receive<C: AbstractChannel, RNG: RngCore + CryptoRng + SeedableRng>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channels: &mut [(PartyId, C)],
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        utils::session(|| {
+            let mut sizes_match = true;
+            for (_, channel) in channels.iter_mut() {
+                sizes_match &= channel.read_usize()? == inputs.len();
+            }
+            for (_, channel) in channels.iter_mut() {
+                channel.write_bool(sizes_match)?;
+                channel.flush()?;
+            }
+            if !sizes_match {
+                return Err(Error::PsiProtocolError(
+                    "parties hold sets of different sizes".to_string(),
+                ));
+            }
+            let hashed = utils::compress_and_hash_inputs(inputs, self.key);
+            let intersection = self.inner.receive(&hashed, channels, rng)?;
+            // `kmprt` returns the intersection in input order, so a single pass
+            // recovers the items.
+            let mut intersection = intersection.into_iter().peekable();
+            let mut items = Vec::new();
+            for (input, h) in inputs.iter().zip(hashed) {
+                if intersection.peek() == Some(&h) {
+                    intersection.next();
+                    items.push(input.clone());
+                }
+            }
+            Ok(items)
+        })
+    }
+}
+
+impl Member {
+    /// Initialize member `me`, which must not be zero.
+    pub fn init<C: AbstractChannel, RNG: RngCore + CryptoRng + SeedableRng>(
+        me: PartyId,
+        channels: &mut [(PartyId, C)],
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        utils::invariant(me != 0, "party 0 is the leader")?;
+        check_channels(me, channels)?;
+        let key = channels[0].1.read_block()?;
+        let inner = kmprt::Sender::init(me, channels, rng)?;
+        Ok(Self { key, inner })
+    }
+
+    /// Run the protocol over `inputs`.
+    pub fn send<C: AbstractChannel, RNG: RngCore + CryptoRng + SeedableRng>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channels: &mut [(PartyId, C)],
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        utils::session(|| {
+            let leader = &mut channels[0].1;
+            leader.write_usize(inputs.len())?;
+            leader.flush()?;
+            if !leader.read_bool()? {
+                return Err(Error::PsiProtocolError(
+                    "parties hold sets of different sizes".to_string(),
+                ));
+            }
+            let hashed = utils::compress_and_hash_inputs(inputs, self.key);
+            self.inner.send(&hashed, channels, rng)
+        })
+    }
+}
+
+impl Party {
+    /// Initialize party `me`: the leader if `me` is zero, and a member
+    /// otherwise.
+    pub fn init<C: AbstractChannel, RNG: RngCore + CryptoRng + SeedableRng>(
+        me: PartyId,
+        channels: &mut [(PartyId, C)],
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        if me == 0 {
+            Leader::init(channels, rng).map(Party::Leader)
+        } else {
+            Member::init(me, channels, rng).map(Party::Member)
+        }
+    }
+
+    /// The role of the party.
+    pub fn role(&self) -> Role {
+        match self {
+            Party::Leader(_) => Role::Receiver,
+            Party::Member(_) => Role::Sender,
+        }
+    }
+
+    /// Run the protocol over `inputs`, returning the intersection for the
+    /// leader and `None` for members.
+    pub fn run<C: AbstractChannel, RNG: RngCore + CryptoRng + SeedableRng>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channels: &mut [(PartyId, C)],
+        rng: &mut RNG,
+    ) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        match self {
+            Party::Leader(leader) => leader.receive(inputs, channels, rng).map(Some),
+            Party::Member(member) => member.send(inputs, channels, rng).map(|_| None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::TopologyBuilder;
+    use scuttlebutt::AesRng;
+
+    const ITEM_SIZE: usize = 8;
+
+    // Run a session between one party per set, returning each party's output.
+    fn run(sets: Vec<Vec<Vec<u8>>>) -> Vec<Result<Option<Vec<Vec<u8>>>, Error>> {
+        let topology = (1..sets.len())
+            .fold(
+                TopologyBuilder::new().party(Role::Receiver, "local"),
+                |builder, _| builder.party(Role::Sender, "local"),
+            )
+            .fully_connected()
+            .build()
+            .unwrap();
+        let handles = topology
+            .local_channels()
+            .into_iter()
+            .zip(sets)
+            .enumerate()
+            .map(|(me, (mut channels, set))| {
+                std::thread::spawn(move || {
+                    let mut rng = AesRng::new();
+                    let mut party = Party::init(me, &mut channels, &mut rng).unwrap();
+                    assert_eq!(party.role() == Role::Receiver, me == 0);
+                    party.run(&set, &mut channels, &mut rng)
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_protocol() {
+        let mut rng = AesRng::new();
+        for &nparties in &[4, 10] {
+            let set_size = 1 << 5;
+            let intersection =
+                utils::rand_vec_vec(rng.gen::<usize>() % set_size, ITEM_SIZE, &mut rng);
+            let sets = (0..nparties)
+                .map(|_| {
+                    let mut set = intersection.clone();
+                    set.extend(utils::rand_vec_vec(
+                        set_size - intersection.len(),
+                        ITEM_SIZE,
+                        &mut rng,
+                    ));
+                    set
+                })
+                .collect();
+            let mut outputs = run(sets).into_iter();
+            assert_eq!(outputs.next().unwrap().unwrap(), Some(intersection));
+            for output in outputs {
+                assert_eq!(output.unwrap(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_different_sizes() {
+        let mut rng = AesRng::new();
+        let sets = vec![
+            utils::rand_vec_vec(8, ITEM_SIZE, &mut rng),
+            utils::rand_vec_vec(8, ITEM_SIZE, &mut rng),
+            utils::rand_vec_vec(9, ITEM_SIZE, &mut rng),
+            utils::rand_vec_vec(8, ITEM_SIZE, &mut rng),
+        ];
+        assert!(run(sets).iter().all(Result::is_err));
+    }
+}
@@ -0,0 +1,312 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Two-party PSI between peers which decide at connection time which of them
+//! sends and which receives.
+//!
+//! Each peer calls `Peer::connect` with its `RolePolicy` and the size of its
+//! set. Both peers exchange their policy, set size and a random nonce, and
+//! resolve their roles the same way:
+//!
+//! 1. A peer with `RolePolicy::Require` gets its role. If both require the
+//!    same role, both fail with `Error::RoleConflict`.
+//! 2. Otherwise, a peer with `RolePolicy::Prefer` gets its role, unless the
+//!    other peer prefers the same one.
+//! 3. Otherwise, the peer with the larger set sends, which is the cheaper
+//!    assignment for the protocols of this crate; ties go to the peer with the
+//!    larger nonce.
+//!
+//! The peer then initializes the `PsiSender` or `PsiReceiver` for its role, so
+//! both peers must be built with the same pair of protocol types.
+//!
+//! The set size is revealed to the other peer, so it may be rounded up with
+//! `crate::sizes::pad` first.
+
+use crate::{
+    psi::{PsiReceiver, PsiSender},
+    topology::Role,
+    Error,
+};
+use rand::{CryptoRng, Rng, RngCore};
+use scuttlebutt::AbstractChannel;
+
+/// How a peer would like its role to be chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RolePolicy {
+    /// Take this role, or fail.
+    Require(Role),
+    /// Take this role, unless the other peer requires or prefers it too.
+    Prefer(Role),
+    /// Let the set sizes decide.
+    Either,
+}
+
+impl RolePolicy {
+    fn to_bytes(self) -> [u8; 2] {
+        let role = |r: Role| match r {
+            Role::Sender => 0,
+            Role::Receiver => 1,
+        };
+        match self {
+            RolePolicy::Require(r) => [0, role(r)],
+            RolePolicy::Prefer(r) => [1, role(r)],
+            RolePolicy::Either => [2, 0],
+        }
+    }
+
+    fn from_bytes(bytes: [u8; 2]) -> Result<Self, Error> {
+        let role = match bytes[1] {
+            0 => Role::Sender,
+            1 => Role::Receiver,
+            b => return Err(conflict(format!("the peer sent an unknown role {}", b))),
+        };
+        match bytes[0] {
+            0 => Ok(RolePolicy::Require(role)),
+            1 => Ok(RolePolicy::Prefer(role)),
+            2 => Ok(RolePolicy::Either),
+            b => Err(conflict(format!(
+                "the peer sent an unknown role policy {}",
+                b
+            ))),
+        }
+    }
+}
+
+fn conflict(msg: String) -> Error {
+    Error::RoleConflict(msg)
+}
+
+fn other(role: Role) -> Role {
+    match role {
+        Role::Sender => Role::Receiver,
+        Role::Receiver => Role::Sender,
+    }
+}
+
+// The role of a peer with policy `ours`, set size `nours` and nonce `ours_`,
+// against a peer with `theirs`, `ntheirs` and `theirs_`.
+fn resolve(
+    (ours, nours, ours_): (RolePolicy, usize, u64),
+    (theirs, ntheirs, theirs_): (RolePolicy, usize, u64),
+) -> Result<Role, Error> {
+    match (ours, theirs) {
+        (RolePolicy::Require(a), RolePolicy::Require(b)) if a == b => {
+            Err(conflict(format!("both peers require to be the {:?}", a)))
+        }
+        (RolePolicy::Require(a), _) => Ok(a),
+        (_, RolePolicy::Require(b)) => Ok(other(b)),
+        (RolePolicy::Prefer(a), RolePolicy::Prefer(b)) if a != b => Ok(a),
+        (RolePolicy::Prefer(a), RolePolicy::Either) => Ok(a),
+        (RolePolicy::Either, RolePolicy::Prefer(b)) => Ok(other(b)),
+        _ => {
+            if (nours, ours_) == (ntheirs, theirs_) {
+                return Err(conflict("both peers drew the same nonce".to_string()));
+            }
+            if (nours, ours_) > (ntheirs, theirs_) {
+                Ok(Role::Sender)
+            } else {
+                Ok(Role::Receiver)
+            }
+        }
+    }
+}
+
+/// Agree with the peer on `channel`, which also runs `negotiate`, on the role
+/// of this party, which has a set of `set_size` items. Returns the role.
+pub fn negotiate<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+    channel: &mut C,
+    policy: RolePolicy,
+    set_size: usize,
+    rng: &mut RNG,
+) -> Result<Role, Error> {
+    let nonce = rng.gen::<u64>();
+    channel.write_bytes(&policy.to_bytes())?;
+    channel.write_usize(set_size)?;
+    channel.write_u64(nonce)?;
+    channel.flush()?;
+    let mut bytes = [0u8; 2];
+    channel.read_bytes(&mut bytes)?;
+    let theirs = RolePolicy::from_bytes(bytes)?;
+    let ntheirs = channel.read_usize()?;
+    let nonce_ = channel.read_u64()?;
+    resolve((policy, set_size, nonce), (theirs, ntheirs, nonce_))
+}
+
+/// A peer of a two-party PSI session, which is either its sender or its
+/// receiver.
+pub enum Peer<S: PsiSender, R: PsiReceiver> {
+    /// The peer sends.
+    Sender(S),
+    /// The peer receives, and learns the intersection.
+    Receiver(R),
+}
+
+impl<S: PsiSender, R: PsiReceiver> Peer<S, R> {
+    /// Initialize the peer in `role`.
+    pub fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        role: Role,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        match role {
+            Role::Sender => S::init(channel, rng).map(Peer::Sender),
+            Role::Receiver => R::init(channel, rng).map(Peer::Receiver),
+        }
+    }
+
+    /// Agree with the other peer on the role of this one, with `negotiate`,
+    /// and initialize it in that role.
+    pub fn connect<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        policy: RolePolicy,
+        set_size: usize,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        let role = negotiate(channel, policy, set_size, rng)?;
+        Self::init(role, channel, rng)
+    }
+
+    /// The role of the peer.
+    pub fn role(&self) -> Role {
+        match self {
+            Peer::Sender(_) => Role::Sender,
+            Peer::Receiver(_) => Role::Receiver,
+        }
+    }
+
+    /// Run the protocol over `inputs`, returning the intersection for the
+    /// receiver and `None` for the sender.
+    pub fn run<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        match self {
+            Peer::Sender(sender) => sender.send(inputs, channel, rng).map(|_| None),
+            Peer::Receiver(receiver) => receiver.receive(inputs, channel, rng).map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dh, utils::rand_vec_vec};
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const ITEM_SIZE: usize = 8;
+
+    #[test]
+    fn test_resolve() {
+        let s = RolePolicy::Require(Role::Sender);
+        let r = RolePolicy::Require(Role::Receiver);
+        let ps = RolePolicy::Prefer(Role::Sender);
+        let pr = RolePolicy::Prefer(Role::Receiver);
+        let e = RolePolicy::Either;
+        let cases = [
+            (s, r, 1, 2, Some(Role::Sender)),
+            (s, s, 1, 2, None),
+            (s, ps, 1, 2, Some(Role::Sender)),
+            (ps, r, 2, 1, Some(Role::Sender)),
+            (pr, s, 2, 1, Some(Role::Receiver)),
+            (ps, pr, 1, 2, Some(Role::Sender)),
+            (ps, ps, 1, 2, Some(Role::Receiver)),
+            (pr, e, 2, 1, Some(Role::Receiver)),
+            (e, ps, 2, 1, Some(Role::Receiver)),
+            (e, e, 2, 1, Some(Role::Sender)),
+            (e, e, 1, 2, Some(Role::Receiver)),
+        ];
+        for &(a, b, na, nb, expected) in cases.iter() {
+            let ours = resolve((a, na, 0), (b, nb, 1)).ok();
+            let theirs = resolve((b, nb, 1), (a, na, 0)).ok();
+            assert_eq!(ours, expected, "{:?} against {:?}", a, b);
+            assert_eq!(theirs, expected.map(other), "{:?} against {:?}", b, a);
+        }
+        // Equal set sizes fall back to the nonces.
+        assert_eq!(resolve((e, 5, 7), (e, 5, 3)).ok(), Some(Role::Sender));
+        assert_eq!(resolve((e, 5, 3), (e, 5, 7)).ok(), Some(Role::Receiver));
+        assert!(resolve((e, 5, 3), (e, 5, 3)).is_err());
+    }
+
+    #[test]
+    fn test_connect() {
+        let mut rng = AesRng::new();
+        // Over two days, the bigger set changes sides.
+        for &(na, nb) in [(256, 64), (64, 256)].iter() {
+            let a = rand_vec_vec(na, ITEM_SIZE, &mut rng);
+            let mut b = rand_vec_vec(nb - 16, ITEM_SIZE, &mut rng);
+            b.extend_from_slice(&a[..16]);
+            let (x, y) = UnixStream::pair().unwrap();
+            let handle = std::thread::spawn(move || {
+                let mut rng = AesRng::new();
+                let reader = BufReader::new(x.try_clone().unwrap());
+                let writer = BufWriter::new(x);
+                let mut channel = Channel::new(reader, writer);
+                let mut peer = Peer::<dh::Sender, dh::Receiver>::connect(
+                    RolePolicy::Either,
+                    a.len(),
+                    &mut channel,
+                    &mut rng,
+                )
+                .unwrap();
+                (peer.role(), peer.run(&a, &mut channel, &mut rng).unwrap())
+            });
+            let reader = BufReader::new(y.try_clone().unwrap());
+            let writer = BufWriter::new(y);
+            let mut channel = Channel::new(reader, writer);
+            let mut peer = Peer::<dh::Sender, dh::Receiver>::connect(
+                RolePolicy::Either,
+                b.len(),
+                &mut channel,
+                &mut rng,
+            )
+            .unwrap();
+            let role = peer.role();
+            let output = peer.run(&b, &mut channel, &mut rng).unwrap();
+            let (role_, output_) = handle.join().unwrap();
+            assert_eq!(role_, other(role));
+            assert_eq!(role == Role::Sender, nb > na);
+            let intersection = output.or(output_).unwrap();
+            assert_eq!(intersection.len(), 16);
+        }
+    }
+
+    #[test]
+    fn test_conflict() {
+        let (x, y) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(x.try_clone().unwrap());
+            let writer = BufWriter::new(x);
+            let mut channel = Channel::new(reader, writer);
+            negotiate(
+                &mut channel,
+                RolePolicy::Require(Role::Receiver),
+                10,
+                &mut rng,
+            )
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(y.try_clone().unwrap());
+        let writer = BufWriter::new(y);
+        let mut channel = Channel::new(reader, writer);
+        let role = negotiate(
+            &mut channel,
+            RolePolicy::Require(Role::Receiver),
+            10,
+            &mut rng,
+        );
+        match (role, handle.join().unwrap()) {
+            (Err(Error::RoleConflict(_)), Err(Error::RoleConflict(_))) => (),
+            r => panic!("expected a conflict on both sides, got {:?}", r),
+        }
+    }
+}
@@ -11,6 +11,7 @@ use crate::{cuckoo::CuckooHash, errors::Error, utils};
 use fancy_garbling::{
     twopac::semihonest::{Evaluator, Garbler},
     BinaryBundle,
+    BinaryGadgets,
     BundleGadgets,
     CrtBundle,
     CrtGadgets,
@@ -40,6 +41,11 @@ const PAD_LEN: usize = 16;
 /// The type of values in the sender and receiver's sets.
 pub type Msg = Vec<u8>;
 
+// A party to the circuit, with one wire per bin holding the membership bit of
+// the receiver's item in that bin.
+type GarblerMembership<C, RNG> = (Garbler<C, RNG, OtSender>, Vec<Wire>);
+type EvaluatorMembership<C, RNG> = (Evaluator<C, RNG, OtReceiver>, Vec<Wire>);
+
 /// Private set intersection sender.
 pub struct Sender {
     opprf: KmprtSender,
@@ -85,6 +91,7 @@ impl Sender {
 
         // map inputs to table using all hash functions
         let nbins = channel.read_usize()?;
+        utils::invariant(nbins > 0, "the cuckoo table must have a bin")?;
         let mut table = vec![Vec::new(); nbins];
 
         for &x in &hashes {
@@ -135,6 +142,10 @@ impl SenderState {
         let mods = vec![2; my_input_bits.len()]; // all binary moduli
         let sender_inputs = gb.encode_many(&my_input_bits, &mods)?;
         let receiver_inputs = gb.receive_many(&mods)?;
+        utils::invariant(
+            sender_inputs.len() == receiver_inputs.len(),
+            "both parties encode the same number of bits",
+        )?;
         Ok((gb, sender_inputs, receiver_inputs))
     }
 
@@ -162,6 +173,104 @@ impl SenderState {
         Ok(())
     }
 
+    /// Run the setup phase and compare the tags of each bin, without
+    /// outputting anything. Returns the garbler along with one wire per bin
+    /// holding a bit which is one if the receiver's item in that bin is in
+    /// the sender's set, for use in further computation.
+    pub fn compute_membership<C, RNG>(
+        &self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<GarblerMembership<C, RNG>, Error>
+    where
+        C: AbstractChannel,
+        RNG: RngCore + CryptoRng + SeedableRng<Seed = Block>,
+    {
+        let (mut gb, x, y) = self.compute_setup(channel, rng)?;
+        let bits = fancy_compute_intersection(&mut gb, &x, &y)?;
+        Ok((gb, bits))
+    }
+
+    /// Compute XOR secret shares of the membership bits of
+    /// `compute_membership`, returning the sender's share of each bin.
+    pub fn compute_membership_shares<C, RNG>(
+        &self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<bool>, Error>
+    where
+        C: AbstractChannel,
+        RNG: RngCore + CryptoRng + SeedableRng<Seed = Block>,
+    {
+        let (mut gb, bits) = self.compute_membership(channel, rng)?;
+        let shares = (0..bits.len())
+            .map(|_| u16::from(rng.gen::<bool>()))
+            .collect_vec();
+        let masks = gb.encode_many(&shares, &vec![2; bits.len()])?;
+        let outs = fancy_mask_bits(&mut gb, &bits, &masks)?;
+        gb.outputs(&outs)?;
+        channel.flush()?;
+        Ok(shares.into_iter().map(|b| b == 1).collect())
+    }
+
+    /// Compute the intersection for the receiver only if it has at least
+    /// `threshold` items, returning whether it did. Must be paired with
+    /// `ReceiverState::compute_threshold_intersection` under the same
+    /// threshold.
+    pub fn compute_threshold_intersection<C, RNG>(
+        &self,
+        threshold: usize,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<bool, Error>
+    where
+        C: AbstractChannel,
+        RNG: RngCore + CryptoRng + SeedableRng<Seed = Block>,
+    {
+        self.compute_threshold(threshold, Reveal::Intersection, channel, rng)
+    }
+
+    /// Compute the cardinality of the intersection for the receiver only if
+    /// it is at least `threshold`, returning whether it is. Must be paired
+    /// with `ReceiverState::compute_threshold_cardinality` under the same
+    /// threshold.
+    pub fn compute_threshold_cardinality<C, RNG>(
+        &self,
+        threshold: usize,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<bool, Error>
+    where
+        C: AbstractChannel,
+        RNG: RngCore + CryptoRng + SeedableRng<Seed = Block>,
+    {
+        self.compute_threshold(threshold, Reveal::Cardinality, channel, rng)
+    }
+
+    fn compute_threshold<C, RNG>(
+        &self,
+        threshold: usize,
+        reveal: Reveal,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<bool, Error>
+    where
+        C: AbstractChannel,
+        RNG: RngCore + CryptoRng + SeedableRng<Seed = Block>,
+    {
+        let thresholds_match = channel.read_usize()? == threshold;
+        channel.write_bool(thresholds_match)?;
+        channel.flush()?;
+        if !thresholds_match {
+            return Err(threshold_mismatch());
+        }
+        let (mut gb, x, y) = self.compute_setup(channel, rng)?;
+        let outs = fancy_compute_threshold(&mut gb, &x, &y, threshold, reveal)?;
+        gb.outputs(&outs)?;
+        channel.flush()?;
+        Ok(channel.read_bool()?)
+    }
+
     /// Receive encrypted payloads from the Sender.
     pub fn receive_payloads<C>(
         &self,
@@ -253,6 +362,10 @@ impl ReceiverState {
         let mods = vec![2; nbins * HASH_SIZE * 8];
         let sender_inputs = ev.receive_many(&mods)?;
         let receiver_inputs = ev.encode_many(&my_input_bits, &mods)?;
+        utils::invariant(
+            sender_inputs.len() == receiver_inputs.len(),
+            "both parties encode the same number of bits",
+        )?;
         Ok((ev, sender_inputs, receiver_inputs))
     }
 
@@ -270,7 +383,7 @@ impl ReceiverState {
         let outs = fancy_compute_intersection(&mut ev, &x, &y)?;
         let mpc_outs = ev
             .outputs(&outs)?
-            .expect("evaluator should produce outputs");
+            .ok_or_else(|| utils::violated("the evaluator produces outputs"))?;
 
         let mut intersection = Vec::new();
         for (opt_item, in_intersection) in self.cuckoo.items.iter().zip_eq(mpc_outs.into_iter()) {
@@ -297,12 +410,139 @@ impl ReceiverState {
         let (outs, mods) = fancy_compute_cardinality(&mut ev, &x, &y)?;
         let mpc_outs = ev
             .outputs(&outs)?
-            .expect("evaluator should produce outputs");
+            .ok_or_else(|| utils::violated("the evaluator produces outputs"))?;
 
         let cardinality = fancy_garbling::util::crt_inv(&mpc_outs, &mods);
         Ok(cardinality as usize)
     }
 
+    /// Run the setup phase and compare the tags of each bin, without
+    /// outputting anything. Returns the evaluator along with one wire per bin
+    /// holding a bit which is one if the receiver's item in that bin, given
+    /// by `bin_item`, is in the sender's set, for use in further computation.
+    pub fn compute_membership<C, RNG>(
+        &self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<EvaluatorMembership<C, RNG>, Error>
+    where
+        C: AbstractChannel,
+        RNG: RngCore + CryptoRng + SeedableRng<Seed = Block>,
+    {
+        let (mut ev, x, y) = self.compute_setup(channel, rng)?;
+        let bits = fancy_compute_intersection(&mut ev, &x, &y)?;
+        Ok((ev, bits))
+    }
+
+    /// Compute XOR secret shares of the membership bits of
+    /// `compute_membership`, returning the receiver's share of each bin.
+    /// Neither party learns the intersection unless the shares are combined.
+    pub fn compute_membership_shares<C, RNG>(
+        &self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<bool>, Error>
+    where
+        C: AbstractChannel,
+        RNG: RngCore + CryptoRng + SeedableRng<Seed = Block>,
+    {
+        let (mut ev, bits) = self.compute_membership(channel, rng)?;
+        let masks = ev.receive_many(&vec![2; bits.len()])?;
+        let outs = fancy_mask_bits(&mut ev, &bits, &masks)?;
+        let shares = ev
+            .outputs(&outs)?
+            .ok_or_else(|| utils::violated("the evaluator produces outputs"))?;
+        Ok(shares.into_iter().map(|b| b == 1).collect())
+    }
+
+    /// Compute the intersection only if it has at least `threshold` items,
+    /// returning `None` otherwise. Below the threshold, neither party learns
+    /// anything but that fact, which is also sent to the sender.
+    pub fn compute_threshold_intersection<C, RNG>(
+        &self,
+        threshold: usize,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Option<Vec<Msg>>, Error>
+    where
+        C: AbstractChannel,
+        RNG: RngCore + CryptoRng + SeedableRng<Seed = Block>,
+    {
+        let bits = match self.compute_threshold(threshold, Reveal::Intersection, channel, rng)? {
+            Some(bits) => bits,
+            None => return Ok(None),
+        };
+        let mut intersection = Vec::new();
+        for (opt_item, in_intersection) in self.cuckoo.items.iter().zip_eq(bits) {
+            if let Some(item) = opt_item {
+                if in_intersection == 1_u16 {
+                    intersection.push(self.inputs[item.input_index].clone());
+                }
+            }
+        }
+        Ok(Some(intersection))
+    }
+
+    /// Compute the cardinality of the intersection only if it is at least
+    /// `threshold`, returning `None` otherwise. Below the threshold, neither
+    /// party learns anything but that fact, which is also sent to the sender.
+    pub fn compute_threshold_cardinality<C, RNG>(
+        &self,
+        threshold: usize,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Option<usize>, Error>
+    where
+        C: AbstractChannel,
+        RNG: RngCore + CryptoRng + SeedableRng<Seed = Block>,
+    {
+        let bits = self.compute_threshold(threshold, Reveal::Cardinality, channel, rng)?;
+        Ok(bits.map(|bits| fancy_garbling::util::u128_from_bits(&bits) as usize))
+    }
+
+    // Run the threshold circuit, returning the revealed bits if the
+    // threshold is met.
+    fn compute_threshold<C, RNG>(
+        &self,
+        threshold: usize,
+        reveal: Reveal,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Option<Vec<u16>>, Error>
+    where
+        C: AbstractChannel,
+        RNG: RngCore + CryptoRng + SeedableRng<Seed = Block>,
+    {
+        channel.write_usize(threshold)?;
+        channel.flush()?;
+        if !channel.read_bool()? {
+            return Err(threshold_mismatch());
+        }
+        let (mut ev, x, y) = self.compute_setup(channel, rng)?;
+        let outs = fancy_compute_threshold(&mut ev, &x, &y, threshold, reveal)?;
+        let mpc_outs = ev
+            .outputs(&outs)?
+            .ok_or_else(|| utils::violated("the evaluator produces outputs"))?;
+        let reached = mpc_outs[0] == 1;
+        channel.write_bool(reached)?;
+        channel.flush()?;
+        Ok(if reached {
+            Some(mpc_outs[1..].to_vec())
+        } else {
+            None
+        })
+    }
+
+    /// The number of bins, each of which holds at most one receiver item.
+    pub fn nbins(&self) -> usize {
+        self.cuckoo.nbins
+    }
+
+    /// The index into the receiver's inputs of the item in bin `bin`, if any.
+    pub fn bin_item(&self, bin: usize) -> Option<usize> {
+        self.cuckoo.items[bin].as_ref().map(|item| item.input_index)
+    }
+
     /// Send encrypted payloads to the Receiver, who can only decrypt a payload if they
     /// share the associated element in the intersection.
     pub fn send_payloads<C, RNG>(
@@ -354,12 +594,13 @@ fn encode_inputs(opprf_outputs: &[Block512]) -> Vec<u16> {
 }
 
 /// Fancy function to compute the intersection and return encoded vector of 0/1 masks.
+///
+/// The callers check that both input vectors have the same length.
 fn fancy_compute_intersection<F: Fancy>(
     f: &mut F,
     sender_inputs: &[F::Item],
     receiver_inputs: &[F::Item],
 ) -> Result<Vec<F::Item>, F::Error> {
-    assert_eq!(sender_inputs.len(), receiver_inputs.len());
     sender_inputs
         .chunks(HASH_SIZE * 8)
         .zip_eq(receiver_inputs.chunks(HASH_SIZE * 8))
@@ -372,15 +613,27 @@ fn fancy_compute_intersection<F: Fancy>(
         .collect()
 }
 
+/// Fancy function to mask each of `bits` with the corresponding bit of `masks`.
+fn fancy_mask_bits<F: Fancy>(
+    f: &mut F,
+    bits: &[F::Item],
+    masks: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    bits.iter()
+        .zip_eq(masks.iter())
+        .map(|(b, m)| f.add(b, m))
+        .collect()
+}
+
 /// Fancy function to compute the cardinaility and return CRT value containing the result
 /// along with the moduli of that value.
+///
+/// The callers check that both input vectors have the same length.
 fn fancy_compute_cardinality<F: Fancy>(
     f: &mut F,
     sender_inputs: &[F::Item],
     receiver_inputs: &[F::Item],
 ) -> Result<(Vec<F::Item>, Vec<u16>), F::Error> {
-    assert_eq!(sender_inputs.len(), receiver_inputs.len());
-
     let eqs = sender_inputs
         .chunks(HASH_SIZE * 8)
         .zip_eq(receiver_inputs.chunks(HASH_SIZE * 8))
@@ -409,6 +662,57 @@ fn fancy_compute_cardinality<F: Fancy>(
     Ok((acc.wires().to_vec(), qs))
 }
 
+// What the threshold circuit reveals once the threshold is met.
+#[derive(Clone, Copy)]
+enum Reveal {
+    Intersection,
+    Cardinality,
+}
+
+fn threshold_mismatch() -> Error {
+    Error::PsiProtocolError("the parties use different thresholds".to_string())
+}
+
+/// Fancy function to compare the cardinality of the intersection against
+/// `threshold`. Returns a bit which is one if the threshold is met, followed
+/// by the membership bits or the binary cardinality, as chosen by `reveal`,
+/// each ANDed with that bit so that they are all zero below the threshold.
+///
+/// The callers check that both input vectors have the same length.
+fn fancy_compute_threshold<F: Fancy>(
+    f: &mut F,
+    sender_inputs: &[F::Item],
+    receiver_inputs: &[F::Item],
+    threshold: usize,
+    reveal: Reveal,
+) -> Result<Vec<F::Item>, F::Error> {
+    let bits = fancy_compute_intersection(f, sender_inputs, receiver_inputs)?;
+
+    // Enough bits to hold both the count and the threshold, plus a zero sign
+    // bit for the comparison.
+    let max = bits.len().max(threshold);
+    let nbits = (usize::BITS - max.leading_zeros()) as usize + 1;
+    let zero = f.constant(0, 2)?;
+    let mut count = BinaryBundle::new(vec![zero.clone(); nbits]);
+    for b in bits.iter() {
+        let mut ws = vec![zero.clone(); nbits];
+        ws[0] = b.clone();
+        count = f.bin_addition_no_carry(&count, &BinaryBundle::new(ws))?;
+    }
+    let threshold = f.bin_constant_bundle(threshold as u128, nbits)?;
+    let reached = f.bin_geq(&count, &threshold)?;
+
+    let revealed = match reveal {
+        Reveal::Intersection => &bits[..],
+        Reveal::Cardinality => count.wires(),
+    };
+    let mut outs = vec![reached.clone()];
+    for w in revealed {
+        outs.push(f.and(w, &reached)?);
+    }
+    Ok(outs)
+}
+
 impl SemiHonest for Sender {}
 impl SemiHonest for Receiver {}
 
@@ -457,6 +761,115 @@ mod tests {
         assert_eq!(cardinality, SET_SIZE);
     }
 
+    #[test]
+    fn membership_shares() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(SET_SIZE / 2, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..SET_SIZE / 2]);
+
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            let state = psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+            let shares = state
+                .compute_membership_shares(&mut channel, &mut rng)
+                .unwrap();
+            // The membership bits can also feed further garbled computation.
+            let (mut gb, bits) = state.compute_membership(&mut channel, &mut rng).unwrap();
+            let zero = gb.constant(0, 2).unwrap();
+            let any = bits.iter().fold(zero, |acc, b| gb.or(&acc, b).unwrap());
+            gb.output(&any).unwrap();
+            shares
+        });
+
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let state = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        let shares = state
+            .compute_membership_shares(&mut channel, &mut rng)
+            .unwrap();
+        let (mut ev, bits) = state.compute_membership(&mut channel, &mut rng).unwrap();
+        let zero = ev.constant(0, 2).unwrap();
+        let any = bits.iter().fold(zero, |acc, b| ev.or(&acc, b).unwrap());
+        assert_eq!(ev.output(&any).unwrap(), Some(1));
+
+        let sender_shares = handle.join().unwrap();
+        assert_eq!(shares.len(), state.nbins());
+        for (bin, (a, b)) in shares.into_iter().zip(sender_shares).enumerate() {
+            let expected = state.bin_item(bin).is_some_and(|i| i >= SET_SIZE / 2);
+            assert_eq!(a ^ b, expected);
+        }
+    }
+
+    // Run the threshold protocol with `shared` items in common, returning the
+    // receiver's intersection, cardinality and whether the sender learned that
+    // the threshold was met for each.
+    fn run_threshold(
+        shared: usize,
+        threshold: usize,
+    ) -> (Option<Vec<Msg>>, Option<usize>, (bool, bool)) {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(SET_SIZE - shared, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..shared]);
+
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            let state = psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+            let a = state
+                .compute_threshold_intersection(threshold, &mut channel, &mut rng)
+                .unwrap();
+            let b = state
+                .compute_threshold_cardinality(threshold, &mut channel, &mut rng)
+                .unwrap();
+            (a, b)
+        });
+
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let state = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        let intersection = state
+            .compute_threshold_intersection(threshold, &mut channel, &mut rng)
+            .unwrap();
+        let cardinality = state
+            .compute_threshold_cardinality(threshold, &mut channel, &mut rng)
+            .unwrap();
+        (intersection, cardinality, handle.join().unwrap())
+    }
+
+    #[test]
+    fn threshold() {
+        let (intersection, cardinality, revealed) = run_threshold(SET_SIZE / 4, SET_SIZE / 4);
+        assert_eq!(intersection.map(|i| i.len()), Some(SET_SIZE / 4));
+        assert_eq!(cardinality, Some(SET_SIZE / 4));
+        assert_eq!(revealed, (true, true));
+
+        let (intersection, cardinality, revealed) = run_threshold(SET_SIZE / 4, SET_SIZE / 4 + 1);
+        assert_eq!(intersection, None);
+        assert_eq!(cardinality, None);
+        assert_eq!(revealed, (false, false));
+    }
+
     #[test]
     fn payloads() {
         let payload_size = 16;
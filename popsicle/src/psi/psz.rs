@@ -8,28 +8,676 @@
 //! protocol (cf. <https://eprint.iacr.org/2014/447>) as specified by
 //! Kolesnikov-Kumaresan-Rosulek-Trieu (cf. <https://eprint.iacr.org/2016/799>).
 //!
-//! The current implementation does not hash the output of the (relaxed) OPRF.
+//! The parties compare tags hashed from the outputs of the (relaxed) OPRF
+//! rather than truncations of the outputs themselves, with the payloads of
+//! `Sender::send_payloads` masked under an independent hash of the same
+//! outputs. Peers exchange a protocol version when a session starts or
+//! resumes, failing at once rather than comparing incompatible tags.
+//!
+//! Both parties may optionally agree on an *epoch* (see `Sender::set_epoch` and
+//! `Receiver::set_epoch`), which is mixed into every OPRF input. Outputs
+//! retained by the receiver under one epoch are unlinkable to those derived
+//! under any other epoch. The epoch is not transmitted: if the parties use
+//! different epochs the intersection is (with overwhelming probability) empty.
+//!
+//! Items are assigned to cuckoo bins with `DefaultBinHash` unless both parties
+//! set another `BinHash`, e.g. to interoperate with a peer running a different
+//! cuckoo implementation (see `Sender::set_bin_hash` and
+//! `Receiver::set_bin_hash`).
+//!
+//! Each run tosses a single master secret, from which a `KeySchedule` derives
+//! the key hashing the inputs and, in `Sender::send_confirmed` and
+//! `Receiver::receive_confirmed`, the independent key of the confirmation
+//! round.
+//!
+//! Parties initialized with `Sender::init_negotiated` and
+//! `Receiver::init_negotiated` first agree on a set of optional `Features`.
+//! When both enable `Features::CONFIRMATION`, `send` and `receive` run with the
+//! confirmation round.
+//!
+//! A session can be saved with `Sender::checkpoint` and `Receiver::checkpoint`
+//! and continued from a new connection with `Sender::resume` and
+//! `Receiver::resume`, without redoing the base OTs. Likewise a run of
+//! `Sender::send_checkpointed` and `Receiver::receive_checkpointed` keeps its
+//! progress in a `SendCheckpoint` and a `ReceiveCheckpoint`, so that a run
+//! interrupted by a dropped connection picks up after the last tag the receiver
+//! got. `crate::retry` reconnects and resumes from these checkpoints as a
+//! `RetryPolicy` decides. Checkpoints hold secrets in the clear and must be
+//! stored securely.
+//! A session given a lifetime with `Sender::set_lifetime` or
+//! `Receiver::set_lifetime` refuses to run once it has expired, and its
+//! session checkpoints record the expiry, so that resuming one neither
+//! extends the session nor succeeds after it.
+//!
+//! The receiver hashes its set into a cuckoo table with `DEFAULT_NHASHES`
+//! hash functions, or as many as set with `Receiver::set_nhashes`, and tells
+//! the sender at the start of each run. More hash functions shrink the table,
+//! and so the OPRF, but the sender sends a tag per item and hash function. A
+//! sender bounds what it accepts with `Sender::set_max_nhashes`.
+//!
+//! Runs fail or report a false match with probability about `2^-40`. Parties
+//! needing a smaller probability set matching `SecurityParams` with
+//! `Sender::set_security` and `Receiver::set_security`, which lengthens the
+//! tags and enlarges the cuckoo table. Parties may also bound the length of
+//! the tags with `Sender::set_masksize_bounds` and
+//! `Receiver::set_masksize_bounds`; when both enable
+//! `Features::MASKSIZE_NEGOTIATION`, they agree on it within both
+//! `MasksizeBounds` at the start of every run. Neither party goes below the
+//! length it computed unless its bounds allow shrinking the tags.
+//!
+//! A receiver short of memory may keep the tags it receives in cuckoo
+//! filters, with `Receiver::set_mask_filter`, at the cost of more false
+//! matches; `Receiver::false_positive_rate` accounts for both sources.
+//!
+//! `Sender::set_executor` spreads the encoding of the sender's tags over any
+//! `scuttlebutt::executor::Executor`, and `Sender::set_threads` over a number
+//! of threads: a rayon thread pool with the `parallel` feature, or scoped
+//! threads otherwise. The tags go out in the same order, so the receiver
+//! cannot tell.
+//!
+//! With the `serde1` feature, `Sender` and `Receiver` implement `Serialize`
+//! and `Deserialize`, so that a party can be initialized on one machine and
+//! shipped to a worker. The bin hash and match policy are not serialized: a
+//! deserialized party uses `DefaultBinHash` and no policy until they are set
+//! again. As with the OPRF state it contains, each serialized party must be
+//! deserialized and run at most once.
 
 use crate::{
-    cuckoo::{compute_masksize, CuckooHash},
+    cuckoo::{self, compute_masksize_with, BinHash, CuckooHash, DefaultBinHash, DEFAULT_NHASHES},
+    events::{self, AuditEvent},
+    features::{self, Features},
+    filter::{self, CuckooFilter},
+    psi::{MatchPolicy, OutputOrder, PsiReceiver, PsiSender},
+    security::{MasksizeBounds, SecurityLevel, SecurityParams},
+    sizes,
     utils,
     Error,
 };
 use itertools::Itertools;
 use ocelot::oprf::{self, Receiver as OprfReceiver, Sender as OprfSender};
-use rand::{seq::SliceRandom, CryptoRng, Rng, RngCore};
-use scuttlebutt::{cointoss, AbstractChannel, Block, Block512, SemiHonest};
-use std::collections::{HashMap, HashSet};
+use rand::{seq::SliceRandom, CryptoRng, Rng, RngCore, SeedableRng};
+use scuttlebutt::{
+    cointoss,
+    executor::{Executor, Job, Sequential},
+    keyschedule::KeySchedule,
+    memtrack,
+    utils as scutils,
+    AbstractChannel,
+    AesRng,
+    Block,
+    Block512,
+    SemiHonest,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// The number of items `Sender::send_stream` holds in memory at once.
+const STREAM_CHUNK_SIZE: usize = 1 << 16;
+// The number of tags the sender encodes before writing them out.
+const ENCODE_BATCH_SIZE: usize = 1 << 14;
+// The number of tags encoded together, matching the eight blocks AES-NI
+// pipelines in `Aes128::encrypt_blocks`.
+const ENCODE_LANES: usize = 8;
+// The most tags per hash function the receiver makes room for before reading
+// them, as the number of items the sender claims to hold is not trusted.
+const MAX_RESERVED_TAGS: usize = 1 << 20;
+
+/// The longest payload, in bytes, that `Sender::send_payloads` can carry for
+/// each item.
+pub const MAX_PAYLOAD_SIZE: usize = 1 << 20;
+
+// Salt of the key schedule, and labels of the keys derived from it.
+const KEY_SCHEDULE_SALT: &[u8] = b"popsicle psz";
+const HASHING_KEY: &[u8] = b"hashing key";
+const CONFIRMATION_KEY: &[u8] = b"confirmation key";
+const RUN_ID: &[u8] = b"run id";
+
+// Identifies the format of session and run checkpoints.
+const CHECKPOINT_VERSION: u8 = 4;
 
-const NHASHES: usize = 3;
+// Identifies the wire protocol. Version 1 hashes the OPRF outputs, and
+// version 2 sends the version in `PROTOCOL_MAGIC` rather than as a lone byte;
+// earlier releases sent no version.
+const PROTOCOL_VERSION: u8 = 2;
+
+// Sent at session start, ending in `PROTOCOL_VERSION`. Releases before version
+// 1 start on the OPRF setup, whose first message either way is a Ristretto
+// point: a receiver from before the version sends one, which never matches
+// this, and a sender from before it reads this as one, which fails as the odd
+// first byte makes it a negative, and so invalid, encoding. A version 1 peer
+// reads the first byte as its version. Either way both sides fail rather than
+// misread each other's messages.
+const PROTOCOL_MAGIC: [u8; 32] = *b"#popsicle psz protocol version \x02";
+
+// Labels of the hashes of an OPRF output giving its tag and its payload pad.
+const TAG_LABEL: &[u8] = b"psz tag";
+const PAD_LABEL: &[u8] = b"psz pad";
 
 /// Private set intersection sender.
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sender {
     oprf: oprf::KkrtSender,
+    epoch: Option<u64>,
+    #[cfg_attr(feature = "serde1", serde(skip, default = "default_bin_hash"))]
+    bin_hash: Box<dyn BinHash>,
+    features: Features,
+    max_nhashes: usize,
+    security: SecurityParams,
+    masksize_bounds: MasksizeBounds,
+    expires: Option<SystemTime>,
+    #[cfg_attr(feature = "serde1", serde(skip, default = "default_executor"))]
+    executor: Box<dyn Executor + Send>,
 }
 /// Private set intersection receiver.
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub struct Receiver {
     oprf: oprf::KkrtReceiver,
+    epoch: Option<u64>,
+    #[cfg_attr(feature = "serde1", serde(skip, default = "default_bin_hash"))]
+    bin_hash: Box<dyn BinHash>,
+    features: Features,
+    nhashes: usize,
+    security: SecurityParams,
+    masksize_bounds: MasksizeBounds,
+    mask_filter: Option<f64>,
+    // The false positive rate of the mask filters of the last run using them.
+    filter_rate: Option<f64>,
+    declared_size: Option<usize>,
+    expires: Option<SystemTime>,
+    #[cfg_attr(feature = "serde1", serde(skip))]
+    policy: Option<Box<dyn MatchPolicy>>,
+    order: OutputOrder,
+}
+
+// The OPRF shows no more than its number of base OTs, so that printing a party
+// never leaks its keys.
+impl std::fmt::Debug for Sender {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("psz::Sender")
+            .field("oprf", &self.oprf)
+            .field("epoch", &self.epoch)
+            .field("features", &self.features)
+            .field("max_nhashes", &self.max_nhashes)
+            .field("security", &self.security)
+            .field("masksize_bounds", &self.masksize_bounds)
+            .field("expires", &self.expires)
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Debug for Receiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("psz::Receiver")
+            .field("oprf", &self.oprf)
+            .field("epoch", &self.epoch)
+            .field("features", &self.features)
+            .field("nhashes", &self.nhashes)
+            .field("security", &self.security)
+            .field("masksize_bounds", &self.masksize_bounds)
+            .field("mask_filter", &self.mask_filter)
+            .field("declared_size", &self.declared_size)
+            .field("expires", &self.expires)
+            .field("policy", &self.policy.is_some())
+            .field("order", &self.order)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "serde1")]
+fn default_bin_hash() -> Box<dyn BinHash> {
+    Box::new(DefaultBinHash)
+}
+
+fn default_executor() -> Box<dyn Executor + Send> {
+    Box::new(Sequential)
+}
+
+fn key_schedule(master: Block) -> KeySchedule {
+    KeySchedule::new(KEY_SCHEDULE_SALT, master.as_ref())
+}
+
+// Map an epoch to the salt XORed into each compressed input. The top bit
+// separates epoch `0` from running without an epoch.
+fn epoch_salt(epoch: Option<u64>) -> Block {
+    match epoch {
+        None => Block::default(),
+        Some(e) => Block::from((1u128 << 127) | u128::from(e)),
+    }
+}
+
+/// The OPRF output the receiver obtained for one of its own inputs, retained
+/// after the protocol completes.
+///
+/// The raw OPRF output is hashed before being handed out, so the value never
+/// matches the masked encodings exchanged on the wire. Only the receiver can
+/// obtain a `ReceiverOutput`; there is deliberately no sender-side analog, and
+/// values cannot be constructed outside this crate.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ReceiverOutput([u8; 32]);
+
+impl ReceiverOutput {
+    fn new(output: &Block512, epoch: Option<u64>) -> Self {
+        let mut hasher = Sha256::new();
+        if let Some(e) = epoch {
+            hasher.input(e.to_le_bytes());
+        }
+        hasher.input(output.as_ref());
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&hasher.result());
+        Self(bytes)
+    }
+
+    /// Return the output as a byte slice, e.g., for use as a join key.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// Exchange `PROTOCOL_MAGIC` with the peer, failing unless it runs the same
+// version.
+fn exchange_version<C: AbstractChannel>(channel: &mut C) -> Result<(), Error> {
+    channel.write_bytes(&PROTOCOL_MAGIC)?;
+    channel.flush()?;
+    let mut theirs = [0u8; 32];
+    channel.read_bytes(&mut theirs)?;
+    if theirs[..31] != PROTOCOL_MAGIC[..31] {
+        return Err(Error::PsiProtocolError(
+            "the peer runs a version of the protocol before 2".to_string(),
+        ));
+    }
+    if theirs[31] != PROTOCOL_VERSION {
+        return Err(Error::PsiProtocolError(format!(
+            "the peer runs version {} of the protocol, not {}",
+            theirs[31], PROTOCOL_VERSION
+        )));
+    }
+    Ok(())
+}
+
+// Hash the OPRF output `encoded` under `label`, so that what is derived from
+// it depends on the whole output.
+fn hash_output(encoded: &Block512, label: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(label);
+    hasher.input(encoded.as_ref());
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&hasher.result());
+    digest
+}
+
+// The `masksize`-byte tag compared for the OPRF output `encoded`.
+fn output_tag(encoded: &Block512, masksize: usize) -> Vec<u8> {
+    hash_output(encoded, TAG_LABEL)[..masksize].to_vec()
+}
+
+// The tags the receiver got from the sender for one hash function.
+trait TagSet {
+    fn insert_tag(&mut self, tag: Vec<u8>);
+    fn contains_tag(&self, tag: &[u8]) -> bool;
+}
+
+impl TagSet for HashSet<Vec<u8>> {
+    fn insert_tag(&mut self, tag: Vec<u8>) {
+        self.insert(tag);
+    }
+
+    fn contains_tag(&self, tag: &[u8]) -> bool {
+        self.contains(tag)
+    }
+}
+
+impl TagSet for CuckooFilter {
+    fn insert_tag(&mut self, tag: Vec<u8>) {
+        self.insert(&tag);
+    }
+
+    fn contains_tag(&self, tag: &[u8]) -> bool {
+        self.contains(tag)
+    }
+}
+
+// Read the `masksize`-byte tags of a sender holding `nsender` items into `hs`,
+// one set per hash function, sent in chunks of `chunk_size`.
+fn collect_tags<T: TagSet, C: AbstractChannel>(
+    mut hs: Vec<T>,
+    nsender: usize,
+    chunk_size: usize,
+    masksize: usize,
+    channel: &mut C,
+) -> Result<Vec<T>, Error> {
+    memtrack::phase("psz::receiver::collect", || {
+        let mut remaining = nsender;
+        while remaining > 0 {
+            let n = remaining.min(chunk_size);
+            for h in hs.iter_mut() {
+                for _ in 0..n {
+                    let buf = channel.read_vec(masksize)?;
+                    h.insert_tag(buf);
+                }
+            }
+            remaining -= n;
+        }
+        Ok(hs)
+    })
+}
+
+// Agree with the peer on the length of the tags of a run against a sender
+// holding `nsender` items, exchanging `bounds` if `features` enables
+// `Features::MASKSIZE_NEGOTIATION`.
+fn agree_masksize<C: AbstractChannel>(
+    channel: &mut C,
+    nsender: usize,
+    security: &SecurityParams,
+    bounds: &MasksizeBounds,
+    features: Features,
+) -> Result<usize, Error> {
+    let masksize = compute_masksize_with(nsender, security)?;
+    if !features.contains(Features::MASKSIZE_NEGOTIATION) {
+        let exactly = MasksizeBounds {
+            min: masksize,
+            max: masksize,
+            shrink: false,
+        };
+        return bounds.agree(masksize, &exactly, masksize);
+    }
+    channel.write_usize(bounds.min)?;
+    channel.write_usize(bounds.max)?;
+    channel.write_usize(masksize)?;
+    channel.flush()?;
+    let theirs = MasksizeBounds {
+        min: channel.read_usize()?,
+        max: channel.read_usize()?,
+        shrink: false,
+    };
+    let their_masksize = channel.read_usize()?;
+    bounds.agree(masksize, &theirs, their_masksize)
+}
+
+fn invalid_checkpoint(msg: &str) -> Error {
+    Error::InvalidCheckpoint(msg.to_string())
+}
+
+// Split `n` bytes off the front of `bytes`.
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+    if bytes.len() < n {
+        return Err(invalid_checkpoint("truncated"));
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn take_u64(bytes: &mut &[u8]) -> Result<u64, Error> {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(take(bytes, 8)?);
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn take_usize(bytes: &mut &[u8]) -> Result<usize, Error> {
+    Ok(take_u64(bytes)? as usize)
+}
+
+fn take_block(bytes: &mut &[u8]) -> Result<Block, Error> {
+    let mut block = Block::default();
+    block.as_mut().copy_from_slice(take(bytes, 16)?);
+    Ok(block)
+}
+
+fn take_block512s(bytes: &mut &[u8]) -> Result<Vec<Block512>, Error> {
+    let n = take_usize(bytes)?;
+    let mut blocks = Vec::with_capacity(n.min(bytes.len() / 64));
+    for _ in 0..n {
+        let mut block = Block512::default();
+        block.as_mut().copy_from_slice(take(bytes, 64)?);
+        blocks.push(block);
+    }
+    Ok(blocks)
+}
+
+fn write_block512s(bytes: &mut Vec<u8>, blocks: &[Block512]) {
+    bytes.extend_from_slice(&(blocks.len() as u64).to_le_bytes());
+    for block in blocks {
+        bytes.extend_from_slice(block.as_ref());
+    }
+}
+
+fn take_version(bytes: &mut &[u8]) -> Result<(), Error> {
+    if take(bytes, 1)?[0] != CHECKPOINT_VERSION {
+        return Err(invalid_checkpoint("unsupported version"));
+    }
+    Ok(())
+}
+
+fn finish(bytes: &[u8]) -> Result<(), Error> {
+    if !bytes.is_empty() {
+        return Err(invalid_checkpoint("trailing bytes"));
+    }
+    Ok(())
+}
+
+// The settings of a session saved in a checkpoint.
+struct Session {
+    epoch: Option<u64>,
+    features: Features,
+    expires: Option<SystemTime>,
+}
+
+// Serialize the settings of a session, followed by the state of its OPRF.
+// Expiry times are saved in milliseconds since the Unix epoch, with zero for
+// sessions that never expire.
+fn write_session(session: Session, oprf: &[u8]) -> Vec<u8> {
+    let expires = session.expires.map_or(0, |expires| {
+        expires
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |d| (d.as_millis() as u64).max(1))
+    });
+    let mut bytes = Vec::with_capacity(26 + oprf.len());
+    bytes.push(CHECKPOINT_VERSION);
+    bytes.push(session.epoch.is_some() as u8);
+    bytes.extend_from_slice(&session.epoch.unwrap_or(0).to_le_bytes());
+    bytes.extend_from_slice(&session.features.bits().to_le_bytes());
+    bytes.extend_from_slice(&expires.to_le_bytes());
+    bytes.extend_from_slice(oprf);
+    bytes
+}
+
+// Parse the output of `write_session`, returning the state of the OPRF last.
+// Fails if the session has expired.
+fn read_session(mut bytes: &[u8]) -> Result<(Session, &[u8]), Error> {
+    take_version(&mut bytes)?;
+    let has_epoch = take(&mut bytes, 1)?[0];
+    let epoch = take_u64(&mut bytes)?;
+    let epoch = match has_epoch {
+        0 => None,
+        1 => Some(epoch),
+        _ => return Err(invalid_checkpoint("invalid epoch")),
+    };
+    let features = Features::from_bits(take_u64(&mut bytes)?);
+    let expires = match take_u64(&mut bytes)? {
+        0 => None,
+        ms => Some(UNIX_EPOCH + Duration::from_millis(ms)),
+    };
+    utils::check_expiry(expires, "session")?;
+    let session = Session {
+        epoch,
+        features,
+        expires,
+    };
+    Ok((session, bytes))
+}
+
+// Agree with the peer on whether a run is resumed.
+fn exchange_resuming<C: AbstractChannel>(channel: &mut C, resuming: bool) -> Result<(), Error> {
+    channel.write_bool(resuming)?;
+    channel.flush()?;
+    if channel.read_bool()? != resuming {
+        return Err(Error::PsiProtocolError(
+            "only one party holds a checkpoint of the run".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The progress of a run of `Sender::send_checkpointed`.
+pub struct SendCheckpoint {
+    run_id: Block,
+    key: Block,
+    nhashes: usize,
+    seeds: Vec<Block512>,
+    // Seeds the shuffle of the tags, so a resumed run sends them in the same
+    // order.
+    shuffle_seed: Block,
+}
+
+impl SendCheckpoint {
+    /// Serialize the checkpoint.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(65 + 64 * self.seeds.len());
+        bytes.push(CHECKPOINT_VERSION);
+        bytes.extend_from_slice(self.run_id.as_ref());
+        bytes.extend_from_slice(self.key.as_ref());
+        bytes.extend_from_slice(&(self.nhashes as u64).to_le_bytes());
+        bytes.extend_from_slice(self.shuffle_seed.as_ref());
+        write_block512s(&mut bytes, &self.seeds);
+        bytes
+    }
+
+    /// Parse a checkpoint written by `to_bytes`.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, Error> {
+        take_version(&mut bytes)?;
+        let run_id = take_block(&mut bytes)?;
+        let key = take_block(&mut bytes)?;
+        let nhashes = take_usize(&mut bytes)?;
+        let shuffle_seed = take_block(&mut bytes)?;
+        let seeds = take_block512s(&mut bytes)?;
+        finish(bytes)?;
+        if !cuckoo::supports_nhashes(nhashes) {
+            return Err(invalid_checkpoint("unsupported number of hash functions"));
+        }
+        Ok(Self {
+            run_id,
+            key,
+            nhashes,
+            seeds,
+            shuffle_seed,
+        })
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for SendCheckpoint {
+    fn drop(&mut self) {
+        scutils::erase(&mut self.seeds);
+    }
+}
+
+impl std::fmt::Debug for SendCheckpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SendCheckpoint")
+            .field("nhashes", &self.nhashes)
+            .field("nbins", &self.seeds.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The progress of a run of `Receiver::receive_checkpointed`.
+///
+/// The cuckoo table is not saved, as it is rebuilt from the receiver's inputs
+/// and the hashing key when the run resumes.
+pub struct ReceiveCheckpoint {
+    run_id: Block,
+    key: Block,
+    nsender: usize,
+    outputs: Vec<Block512>,
+    // The tags received so far, for each hash function.
+    tags: Vec<HashSet<Vec<u8>>>,
+    nreceived: usize,
+}
+
+impl ReceiveCheckpoint {
+    /// The number of tags received so far.
+    pub fn nreceived(&self) -> usize {
+        self.nreceived
+    }
+
+    /// Serialize the checkpoint.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let masksize = self.tags.iter().flatten().next().map_or(0, Vec::len);
+        let mut bytes =
+            Vec::with_capacity(65 + 64 * self.outputs.len() + 8 + masksize * self.nreceived);
+        bytes.push(CHECKPOINT_VERSION);
+        bytes.extend_from_slice(self.run_id.as_ref());
+        bytes.extend_from_slice(self.key.as_ref());
+        bytes.extend_from_slice(&(self.nsender as u64).to_le_bytes());
+        write_block512s(&mut bytes, &self.outputs);
+        bytes.extend_from_slice(&(self.nreceived as u64).to_le_bytes());
+        bytes.extend_from_slice(&(masksize as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.tags.len() as u64).to_le_bytes());
+        for h in self.tags.iter() {
+            bytes.extend_from_slice(&(h.len() as u64).to_le_bytes());
+            for tag in h {
+                bytes.extend_from_slice(tag);
+            }
+        }
+        bytes
+    }
+
+    /// Parse a checkpoint written by `to_bytes`.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, Error> {
+        take_version(&mut bytes)?;
+        let run_id = take_block(&mut bytes)?;
+        let key = take_block(&mut bytes)?;
+        let nsender = take_usize(&mut bytes)?;
+        let outputs = take_block512s(&mut bytes)?;
+        let nreceived = take_usize(&mut bytes)?;
+        let masksize = take_usize(&mut bytes)?;
+        let nhashes = take_usize(&mut bytes)?;
+        if !cuckoo::supports_nhashes(nhashes) {
+            return Err(invalid_checkpoint("unsupported number of hash functions"));
+        }
+        let mut tags = Vec::with_capacity(nhashes);
+        for _ in 0..nhashes {
+            let n = take_usize(&mut bytes)?;
+            let mut h = HashSet::with_capacity(n.min(bytes.len()));
+            for _ in 0..n {
+                h.insert(take(&mut bytes, masksize)?.to_vec());
+            }
+            tags.push(h);
+        }
+        finish(bytes)?;
+        match nhashes.checked_mul(nsender) {
+            Some(total) if nreceived <= total => (),
+            _ => return Err(invalid_checkpoint("more tags than the sender holds")),
+        }
+        Ok(Self {
+            run_id,
+            key,
+            nsender,
+            outputs,
+            tags,
+            nreceived,
+        })
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for ReceiveCheckpoint {
+    fn drop(&mut self) {
+        scutils::erase(&mut self.outputs);
+    }
+}
+
+impl std::fmt::Debug for ReceiveCheckpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ReceiveCheckpoint")
+            .field("nsender", &self.nsender)
+            .field("nbins", &self.outputs.len())
+            .field("nreceived", &self.nreceived)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Sender {
@@ -38,280 +686,2170 @@ impl Sender {
         channel: &mut C,
         rng: &mut RNG,
     ) -> Result<Self, Error> {
+        exchange_version(channel)?;
         let oprf = oprf::KkrtSender::init(channel, rng)?;
-        Ok(Self { oprf })
+        Ok(Self {
+            oprf,
+            epoch: None,
+            bin_hash: Box::new(DefaultBinHash),
+            features: Features::NONE,
+            max_nhashes: DEFAULT_NHASHES,
+            security: SecurityParams::default(),
+            masksize_bounds: MasksizeBounds::default(),
+            expires: None,
+            executor: default_executor(),
+        })
     }
 
-    /// Run the PSI protocol over `inputs`.
-    pub fn send<C: AbstractChannel, RNG: CryptoRng + RngCore>(
-        &mut self,
-        inputs: &[Vec<u8>],
+    /// Initialize the PSI sender, first agreeing with the receiver on which of the
+    /// optional `features` to enable. Both parties must initialize this way.
+    ///
+    /// Features this crate does not implement are never enabled, and
+    /// initialization fails if the receiver does not support
+    /// `Features::STASH_FREE`.
+    pub fn init_negotiated<C: AbstractChannel, RNG: CryptoRng + RngCore>(
         channel: &mut C,
         rng: &mut RNG,
-    ) -> Result<(), Error> {
-        let key = cointoss::send(channel, &[rng.gen()])?[0];
-        let inputs = utils::compress_and_hash_inputs(inputs, key);
-        let masksize = compute_masksize(inputs.len())?;
-        let nbins = channel.read_usize()?;
-        let seeds = self.oprf.send(channel, nbins, rng)?;
+        features: Features,
+    ) -> Result<Self, Error> {
+        let psi = Self::negotiate(channel, rng, features)?;
+        events::emit(&AuditEvent::ParametersNegotiated {
+            features: psi.features,
+        });
+        Ok(psi)
+    }
 
-        // For each hash function `hᵢ`, construct set `Hᵢ = {F(k_{hᵢ(x)}, x ||
-        // i) | x ∈ X)}`, randomly permute it, and send it to the receiver.
-        let mut encoded = Block512::default();
-        let mut indices = (0..inputs.len()).collect_vec();
-        for i in 0..NHASHES {
-            // shuffle the indices in order to send out of order
-            indices.shuffle(rng);
+    // Run `init_negotiated` without reporting the features agreed.
+    fn negotiate<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+        features: Features,
+    ) -> Result<Self, Error> {
+        let ours = (features & Features::SUPPORTED) | Features::STASH_FREE;
+        let features = features::negotiate(channel, ours)?;
+        if !features.contains(Features::STASH_FREE) {
+            return Err(Error::PsiProtocolError(
+                "the receiver's cuckoo table uses a stash".to_string(),
+            ));
+        }
+        let mut psi = Self::init(channel, rng)?;
+        psi.features = features;
+        Ok(psi)
+    }
 
-            let hidx = Block::from(i as u128);
-            for &j in &indices {
-                // Compute `bin := hᵢ(x)`.
-                let bin = CuckooHash::bin(inputs[j], i, nbins);
+    /// Initialize the PSI sender with the settings of `level`, which must be
+    /// that of the receiver. Fails if the crate was not built with the features
+    /// `level` needs, or if the receiver does not support the behaviors it
+    /// requires.
+    pub fn init_with_level<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+        level: SecurityLevel,
+    ) -> Result<Self, Error> {
+        level.check_build()?;
+        // `Legacy` negotiates nothing, as a plain `init`.
+        let mut psi = match level {
+            SecurityLevel::Legacy => Self::init(channel, rng)?,
+            _ => Self::negotiate(channel, rng, level.features())?,
+        };
+        level.check_features(psi.features)?;
+        psi.set_security(level.params());
+        psi.set_masksize_bounds(level.masksize_bounds());
+        events::emit(&AuditEvent::ParametersNegotiated {
+            features: psi.features,
+        });
+        Ok(psi)
+    }
 
-                // Compute `F(k_{hᵢ(x)}, x || i)` and chop off extra bytes.
-                self.oprf.encode(inputs[j] ^ hidx, &mut encoded);
-                encoded ^= seeds[bin];
+    /// The optional features enabled for this session.
+    pub fn features(&self) -> Features {
+        self.features
+    }
 
-                channel.write_bytes(&encoded.prefix(masksize))?;
-            }
+    /// Set the epoch mixed into subsequent runs of the protocol, or `None` to
+    /// run without one. Must match the receiver's epoch.
+    pub fn set_epoch(&mut self, epoch: Option<u64>) {
+        self.epoch = epoch;
+    }
+
+    /// Set the function assigning items to bins in subsequent runs of the
+    /// protocol. Must match the receiver's.
+    pub fn set_bin_hash(&mut self, bin_hash: impl BinHash + 'static) {
+        self.bin_hash = Box::new(bin_hash);
+    }
+
+    /// Accept receivers hashing with up to `max_nhashes` hash functions in
+    /// subsequent runs of the protocol, `DEFAULT_NHASHES` unless set. The
+    /// sender's communication grows linearly with the receiver's choice.
+    pub fn set_max_nhashes(&mut self, max_nhashes: usize) {
+        self.max_nhashes = max_nhashes;
+    }
+
+    /// Set the security parameters of subsequent runs of the protocol, which
+    /// fix the length of the tags sent. Must match the receiver's.
+    pub fn set_security(&mut self, security: SecurityParams) {
+        self.security = security;
+    }
+
+    /// Bound the length of the tags of subsequent runs of the protocol, any
+    /// length up to `security::MAX_MASKSIZE` unless set. Runs fail with
+    /// `Error::IncompatibleMasksize` unless the receiver accepts a length
+    /// within `bounds`.
+    pub fn set_masksize_bounds(&mut self, bounds: MasksizeBounds) {
+        self.masksize_bounds = bounds;
+    }
+
+    /// Expire the session `lifetime` from now, or never if `None`, after
+    /// which runs fail and its checkpoints cannot be resumed.
+    pub fn set_lifetime(&mut self, lifetime: Option<Duration>) {
+        self.expires = lifetime.map(|lifetime| SystemTime::now() + lifetime);
+    }
+
+    /// The time at which the session expires, if any.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    // Agree with the receiver on the length of the tags of a run over
+    // `ninputs` items.
+    fn agree_masksize<C: AbstractChannel>(
+        &self,
+        channel: &mut C,
+        ninputs: usize,
+    ) -> Result<usize, Error> {
+        agree_masksize(
+            channel,
+            ninputs,
+            &self.security,
+            &self.masksize_bounds,
+            self.features,
+        )
+    }
+
+    // Read the number of hash functions and of bins of the receiver's cuckoo
+    // table, sent by `Receiver::perform_oprfs`.
+    fn read_table<C: AbstractChannel>(&self, channel: &mut C) -> Result<(usize, usize), Error> {
+        let nhashes = channel.read_usize()?;
+        if !cuckoo::supports_nhashes(nhashes) || nhashes > self.max_nhashes {
+            return Err(Error::PsiProtocolError(format!(
+                "the receiver's cuckoo table uses {} hash functions",
+                nhashes
+            )));
         }
-        channel.flush()?;
+        let nbins = channel.read_usize()?;
+        utils::invariant(nbins > 0, "the cuckoo table must have a bin")?;
+        Ok((nhashes, nbins))
+    }
+
+    /// Encode the tags of subsequent runs on `executor`, on the calling
+    /// thread unless set.
+    pub fn set_executor(&mut self, executor: impl Executor + Send + 'static) {
+        self.executor = Box::new(executor);
+    }
+
+    /// Encode the tags of subsequent runs on `nthreads` threads, or on the
+    /// calling thread if `nthreads` is at most one. The threads are those of
+    /// a rayon thread pool with the `parallel` feature, and scoped threads
+    /// spawned for each batch of tags otherwise.
+    pub fn set_threads(&mut self, nthreads: usize) -> Result<(), Error> {
+        if nthreads <= 1 {
+            self.set_executor(Sequential);
+            return Ok(());
+        }
+        #[cfg(feature = "parallel")]
+        {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(nthreads)
+                .build()
+                .map_err(std::io::Error::other)?;
+            self.set_executor(pool);
+        }
+        #[cfg(not(feature = "parallel"))]
+        self.set_executor(scuttlebutt::executor::ScopedThreads::new(nthreads));
         Ok(())
     }
 
-    /// Run the PSI protocol over `inputs`. Returns a random key for each input which can
-    /// be used to encrypt payloads.
-    pub fn send_payloads<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+    /// Run the PSI protocol over `inputs`, with the confirmation round of
+    /// `send_confirmed` if `Features::CONFIRMATION` is enabled.
+    pub fn send<C: AbstractChannel, RNG: CryptoRng + RngCore>(
         &mut self,
         inputs: &[Vec<u8>],
         channel: &mut C,
         rng: &mut RNG,
-    ) -> Result<Vec<Block>, Error> {
-        let key = cointoss::send(channel, &[rng.gen()])?[0];
-        let masksize = compute_masksize(inputs.len())?;
-        let inputs = utils::compress_and_hash_inputs(inputs, key);
-        let nbins = channel.read_usize()?;
-        let seeds = self.oprf.send(channel, nbins, rng)?;
-        let payloads = (0..inputs.len()).map(|_| rng.gen::<Block>()).collect_vec();
+    ) -> Result<(), Error> {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "session")?;
+            if self.features.contains(Features::CONFIRMATION) {
+                return self.send_confirmed(inputs, channel, rng);
+            }
+            let keys = key_schedule(cointoss::send(channel, &[rng.gen()])?[0]);
+            self.send_keyed(inputs, keys.block(HASHING_KEY), channel, rng)
+        })
+    }
 
-        // For each hash function `hᵢ`, construct set `Hᵢ = {F(k_{hᵢ(x)}, x ||
-        // i) | x ∈ X)}`, randomly permute it, and send it to the receiver.
-        let mut encoded = Block512::default();
+    // Run the PSI protocol over `inputs`, hashed under `key`.
+    fn send_keyed<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        key: Block,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        let inputs = memtrack::phase("psz::sender::hash", || {
+            utils::compress_and_hash_inputs_salted(inputs, key, epoch_salt(self.epoch))
+        });
+        let masksize = self.agree_masksize(channel, inputs.len())?;
+        let (nhashes, nbins) = self.read_table(channel)?;
+        let mut seeds =
+            memtrack::phase("psz::sender::oprf", || self.oprf.send(channel, nbins, rng))?;
+        let result = memtrack::phase("psz::sender::encode", || {
+            self.encode_chunk(&inputs, &seeds, nhashes, masksize, 0, channel, rng)
+        });
+        scutils::erase(&mut seeds);
+        result?;
+        channel.flush()?;
+        Ok(())
+    }
+
+    // For each of the `nhashes` hash functions `hᵢ`, construct set `Hᵢ =
+    // {F(k_{hᵢ(x)}, x || i) | x ∈ X)}` over the hashed `inputs`, randomly
+    // permute it, and send it to the receiver, skipping the first `skip`
+    // elements of the sets.
+    #[allow(clippy::too_many_arguments)]
+    fn encode_chunk<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &self,
+        inputs: &[Block],
+        seeds: &[Block512],
+        nhashes: usize,
+        masksize: usize,
+        skip: usize,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        let executor = &self.executor;
         let mut indices = (0..inputs.len()).collect_vec();
-        for i in 0..NHASHES {
+        let mut tags = vec![0u8; ENCODE_BATCH_SIZE.min(inputs.len()) * masksize];
+        for i in 0..nhashes {
             // shuffle the indices in order to send out of order
             indices.shuffle(rng);
 
-            let hidx = Block::from(i as u128);
-            for &j in &indices {
-                // Compute `bin := hᵢ(x)`.
-                let bin = CuckooHash::bin(inputs[j], i, nbins);
-
-                // Compute `F(k_{hᵢ(x)}, x || i)` and chop off extra bytes.
-                self.oprf.encode(inputs[j] ^ hidx, &mut encoded);
-                encoded ^= seeds[bin];
-
-                let tag = &encoded.as_ref()[0..masksize];
-                let key = &encoded.as_ref()[masksize..masksize + 16];
+            let start = skip.saturating_sub(i * inputs.len()).min(inputs.len());
+            for batch in indices[start..].chunks(ENCODE_BATCH_SIZE) {
+                let tags = &mut tags[..batch.len() * masksize];
+                let per_job = batch.len().div_ceil(executor.parallelism().max(1));
+                let jobs = batch
+                    .chunks(per_job)
+                    .zip(tags.chunks_mut(per_job * masksize))
+                    .map(|(batch, tags)| {
+                        Box::new(move || self.encode_tags(inputs, seeds, i, batch, tags)) as Job
+                    })
+                    .collect();
+                executor.run(jobs);
+                channel.write_bytes(tags)?;
+            }
+        }
+        Ok(())
+    }
 
-                // encrypt payload
-                let mut ct = payloads[j];
-                scuttlebutt::utils::xor_inplace(ct.as_mut(), key);
+    // Write into `tags` the `tags.len() / indices.len()` byte tags of the
+    // items of `inputs` at `indices` under hash function `i`.
+    fn encode_tags(
+        &self,
+        inputs: &[Block],
+        seeds: &[Block512],
+        i: usize,
+        indices: &[usize],
+        tags: &mut [u8],
+    ) {
+        let masksize = tags.len() / indices.len();
+        let hidx = Block::from(i as u128);
+        let mut xs = [Block::default(); ENCODE_LANES];
+        let mut masks = [Block512::default(); ENCODE_LANES];
+        let mut encoded = [Block512::default(); ENCODE_LANES];
+        for (lanes, tags) in indices
+            .chunks(ENCODE_LANES)
+            .zip(tags.chunks_mut(ENCODE_LANES * masksize))
+        {
+            let n = lanes.len();
+            for (k, &j) in lanes.iter().enumerate() {
+                // Compute `bin := hᵢ(x)`.
+                let bin = self.bin_hash.bin(inputs[j], i, seeds.len());
+                xs[k] = inputs[j] ^ hidx;
+                masks[k] = seeds[bin];
+            }
 
-                channel.write_bytes(&tag[0..masksize])?;
-                channel.write_bytes(ct.as_ref())?;
+            // Compute `F(k_{hᵢ(x)}, x || i)` and hash it down to a tag.
+            self.oprf.encode_many(&xs[..n], &mut encoded[..n]);
+            Block512::xor_many(&mut encoded[..n], &masks[..n]);
+            for (encoded, tag) in encoded[..n].iter().zip(tags.chunks_mut(masksize)) {
+                tag.copy_from_slice(&hash_output(encoded, TAG_LABEL)[..masksize]);
             }
         }
-        channel.flush()?;
-        Ok(payloads)
     }
-}
 
-impl Receiver {
-    /// Initialize the PSI receiver.
-    pub fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+    /// Save the session, to be continued from another connection with
+    /// `Sender::resume`. The `BinHash`, the `SecurityParams` and the
+    /// `MasksizeBounds` are not saved.
+    pub fn checkpoint(&self) -> Vec<u8> {
+        let session = Session {
+            epoch: self.epoch,
+            features: self.features,
+            expires: self.expires,
+        };
+        write_session(session, &self.oprf.checkpoint())
+    }
+
+    /// Continue a session saved by `Sender::checkpoint`, against a receiver
+    /// running `Receiver::resume`. The session keeps its expiry time, and
+    /// fails to resume if it has expired.
+    pub fn resume<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        checkpoint: &[u8],
         channel: &mut C,
         rng: &mut RNG,
     ) -> Result<Self, Error> {
-        let oprf = oprf::KkrtReceiver::init(channel, rng)?;
-        Ok(Self { oprf })
+        let (session, oprf) = read_session(checkpoint)?;
+        exchange_version(channel)?;
+        let nonce = cointoss::send(channel, &[rng.gen()])?[0];
+        let oprf = oprf::KkrtSender::resume(oprf, nonce)?;
+        Ok(Self {
+            oprf,
+            epoch: session.epoch,
+            bin_hash: Box::new(DefaultBinHash),
+            features: session.features,
+            max_nhashes: DEFAULT_NHASHES,
+            security: SecurityParams::default(),
+            masksize_bounds: MasksizeBounds::default(),
+            expires: session.expires,
+            executor: default_executor(),
+        })
     }
 
-    /// Run the PSI protocol over `inputs`.
-    pub fn receive<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+    /// Run the PSI protocol over `inputs`, saving its progress in
+    /// `checkpoint`. Must be paired with `Receiver::receive_checkpointed`,
+    /// which does not require the sets to have the same size.
+    ///
+    /// If `checkpoint` is `None` a new run starts. If the run fails, e.g.
+    /// because the connection dropped, `checkpoint` holds the state of the
+    /// run, and calling this method again with the same `inputs`, from a
+    /// session resumed from the same session checkpoint, sends the rest of the
+    /// tags. `checkpoint` is reset once the run is done. No confirmation round
+    /// is run.
+    pub fn send_checkpointed<C: AbstractChannel, RNG: CryptoRng + RngCore>(
         &mut self,
         inputs: &[Vec<u8>],
+        checkpoint: &mut Option<SendCheckpoint>,
         channel: &mut C,
         rng: &mut RNG,
-    ) -> Result<Vec<Vec<u8>>, Error> {
-        let n = inputs.len();
-        let masksize = compute_masksize(n)?;
+    ) -> Result<(), Error> {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "session")?;
+            exchange_resuming(channel, checkpoint.is_some())?;
+            let skip = match checkpoint {
+                None => {
+                    let keys = key_schedule(cointoss::send(channel, &[rng.gen()])?[0]);
+                    channel.write_usize(inputs.len())?;
+                    channel.flush()?;
+                    let (nhashes, nbins) = self.read_table(channel)?;
+                    let seeds = self.oprf.send(channel, nbins, rng)?;
+                    *checkpoint = Some(SendCheckpoint {
+                        run_id: keys.block(RUN_ID),
+                        key: keys.block(HASHING_KEY),
+                        nhashes,
+                        seeds,
+                        shuffle_seed: rng.gen(),
+                    });
+                    0
+                }
+                Some(checkpoint) => {
+                    let run_id = channel.read_block()?;
+                    let nreceived = channel.read_usize()?;
+                    let ok = run_id == checkpoint.run_id
+                        && checkpoint
+                            .nhashes
+                            .checked_mul(inputs.len())
+                            .is_some_and(|total| nreceived <= total);
+                    channel.write_bool(ok)?;
+                    channel.flush()?;
+                    if !ok {
+                        return Err(Error::PsiProtocolError(
+                            "the receiver's checkpoint is of another run".to_string(),
+                        ));
+                    }
+                    nreceived
+                }
+            };
+            let saved = checkpoint
+                .as_ref()
+                .ok_or_else(|| utils::violated("a run has a checkpoint"))?;
+            let masksize = self.agree_masksize(channel, inputs.len())?;
+            let hashed =
+                utils::compress_and_hash_inputs_salted(inputs, saved.key, epoch_salt(self.epoch));
+            let mut shuffle = AesRng::from_seed(saved.shuffle_seed);
+            self.encode_chunk(
+                &hashed,
+                &saved.seeds,
+                saved.nhashes,
+                masksize,
+                skip,
+                channel,
+                &mut shuffle,
+            )?;
+            channel.flush()?;
+            *checkpoint = None;
+            Ok(())
+        })
+    }
+
+    /// Run the PSI protocol over the `ninputs` items of `inputs`, holding only
+    /// a bounded chunk of them in memory at a time. Must be paired with
+    /// `Receiver::receive_stream`, which does not require the sets to have
+    /// the same size. Fails if `inputs` does not yield exactly `ninputs`
+    /// items, leaving the session unusable.
+    ///
+    /// The tags of each chunk are shuffled within the chunk only, so the
+    /// receiver learns which chunk of the stream each item of the
+    /// intersection came from; shuffle the stream beforehand if its order is
+    /// sensitive. No confirmation round is run.
+    pub fn send_stream<I, C, RNG>(
+        &mut self,
+        inputs: I,
+        ninputs: usize,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        C: AbstractChannel,
+        RNG: CryptoRng + RngCore,
+    {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "session")?;
+            self.send_chunked(inputs, ninputs, STREAM_CHUNK_SIZE, channel, rng)
+        })
+    }
 
-        let (tbl, outputs) = self.perform_oprfs(inputs, channel, rng)?;
+    fn send_chunked<I, C, RNG>(
+        &mut self,
+        inputs: I,
+        ninputs: usize,
+        chunk_size: usize,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        C: AbstractChannel,
+        RNG: CryptoRng + RngCore,
+    {
+        let key = key_schedule(cointoss::send(channel, &[rng.gen()])?[0]).block(HASHING_KEY);
+        channel.write_usize(ninputs)?;
+        channel.write_usize(chunk_size)?;
+        channel.flush()?;
+        let masksize = self.agree_masksize(channel, ninputs)?;
+        let (nhashes, nbins) = self.read_table(channel)?;
+        let mut seeds = self.oprf.send(channel, nbins, rng)?;
 
-        // Receive all the sets from the sender.
-        let mut hs = vec![HashSet::with_capacity(n); NHASHES];
-        for h in hs.iter_mut() {
-            for _ in 0..n {
-                let buf = channel.read_vec(masksize)?;
-                h.insert(buf);
+        let mut inputs = inputs.into_iter();
+        let result = (|| -> Result<(), Error> {
+            let mut remaining = ninputs;
+            while remaining > 0 {
+                let chunk = inputs
+                    .by_ref()
+                    .take(remaining.min(chunk_size))
+                    .collect::<Vec<_>>();
+                if chunk.is_empty() {
+                    break;
+                }
+                remaining -= chunk.len();
+                let chunk =
+                    utils::compress_and_hash_inputs_salted(&chunk, key, epoch_salt(self.epoch));
+                self.encode_chunk(&chunk, &seeds, nhashes, masksize, 0, channel, rng)?;
+            }
+            if remaining > 0 || inputs.next().is_some() {
+                return Err(Error::InvalidInput(format!(
+                    "the input stream does not hold {} items",
+                    ninputs
+                )));
             }
+            Ok(())
+        })();
+        scutils::erase(&mut seeds);
+        result?;
+        channel.flush()?;
+        Ok(())
+    }
+
+    /// Run the PSI protocol over `inputs`, followed by the confirmation round
+    /// requested by `Receiver::receive_confirmed`.
+    pub fn send_confirmed<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "session")?;
+            let keys = key_schedule(cointoss::send(channel, &[rng.gen()])?[0]);
+            self.send_keyed(inputs, keys.block(HASHING_KEY), channel, rng)?;
+            let ncandidates = channel.read_usize()?;
+            if ncandidates == 0 {
+                return Ok(());
+            }
+            channel.write_usize(inputs.len())?;
+            channel.flush()?;
+            // A second run uses an independent hashing key and fresh OPRF seeds.
+            self.send_keyed(inputs, keys.block(CONFIRMATION_KEY), channel, rng)
+        })
+    }
+
+    /// Run the PSI protocol over `inputs`, associating `payloads[i]` with
+    /// `inputs[i]`. The receiver learns the payloads of the inputs in the
+    /// intersection only. All payloads must have the same length, at most
+    /// `MAX_PAYLOAD_SIZE`, which the receiver learns.
+    pub fn send_payloads<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        payloads: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "session")?;
+            let payload_size = payloads.first().map_or(0, Vec::len);
+            if payloads.len() != inputs.len()
+                || payloads.iter().any(|p| p.len() != payload_size)
+                || payload_size > MAX_PAYLOAD_SIZE
+            {
+                return Err(Error::InvalidPayloadsLength);
+            }
+            let key = key_schedule(cointoss::send(channel, &[rng.gen()])?[0]).block(HASHING_KEY);
+            let inputs =
+                utils::compress_and_hash_inputs_salted(inputs, key, epoch_salt(self.epoch));
+            let (nhashes, nbins) = self.read_table(channel)?;
+            let mut seeds = self.oprf.send(channel, nbins, rng)?;
+            channel.write_usize(inputs.len())?;
+            channel.write_usize(payload_size)?;
+            let masksize = self.agree_masksize(channel, inputs.len())?;
+
+            // As in `send`, but each tag `F(k_{hᵢ(x)}, x || i)` comes with the
+            // payload of `x` masked by a pad derived from the OPRF output.
+            let mut encoded = Block512::default();
+            let mut indices = (0..inputs.len()).collect_vec();
+            let mut ct = vec![0u8; payload_size];
+            let result = (|| -> Result<(), Error> {
+                for i in 0..nhashes {
+                    indices.shuffle(rng);
+
+                    let hidx = Block::from(i as u128);
+                    for &j in &indices {
+                        let bin = self.bin_hash.bin(inputs[j], i, nbins);
+                        self.oprf.encode(inputs[j] ^ hidx, &mut encoded);
+                        encoded ^= seeds[bin];
+
+                        payload_pad(&encoded, &mut ct);
+                        scutils::xor_inplace(&mut ct, &payloads[j]);
+
+                        channel.write_bytes(&output_tag(&encoded, masksize))?;
+                        channel.write_bytes(&ct)?;
+                    }
+                }
+                Ok(())
+            })();
+            scutils::erase(&mut seeds);
+            result?;
+            channel.flush()?;
+            Ok(())
+        })
+    }
+}
+
+// Fill `pad` with a mask for a payload, derived from the OPRF output
+// `encoded` independently of its tag.
+fn payload_pad(encoded: &Block512, pad: &mut [u8]) {
+    let seed = Block::try_from_slice(&hash_output(encoded, PAD_LABEL)[..16])
+        .expect("the digest has 16 bytes");
+    AesRng::from_seed(seed).fill_bytes(pad);
+}
+
+impl Receiver {
+    /// Initialize the PSI receiver.
+    pub fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        exchange_version(channel)?;
+        let oprf = oprf::KkrtReceiver::init(channel, rng)?;
+        Ok(Self {
+            oprf,
+            epoch: None,
+            bin_hash: Box::new(DefaultBinHash),
+            features: Features::NONE,
+            nhashes: DEFAULT_NHASHES,
+            security: SecurityParams::default(),
+            masksize_bounds: MasksizeBounds::default(),
+            mask_filter: None,
+            filter_rate: None,
+            declared_size: None,
+            expires: None,
+            policy: None,
+            order: OutputOrder::default(),
+        })
+    }
+
+    /// Initialize the PSI receiver, first agreeing with the sender on which of the
+    /// optional `features` to enable. Both parties must initialize this way.
+    ///
+    /// Features this crate does not implement are never enabled, and
+    /// initialization fails if the sender does not support
+    /// `Features::STASH_FREE`.
+    pub fn init_negotiated<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+        features: Features,
+    ) -> Result<Self, Error> {
+        let psi = Self::negotiate(channel, rng, features)?;
+        events::emit(&AuditEvent::ParametersNegotiated {
+            features: psi.features,
+        });
+        Ok(psi)
+    }
+
+    // Run `init_negotiated` without reporting the features agreed.
+    fn negotiate<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+        features: Features,
+    ) -> Result<Self, Error> {
+        let ours = (features & Features::SUPPORTED) | Features::STASH_FREE;
+        let features = features::negotiate(channel, ours)?;
+        if !features.contains(Features::STASH_FREE) {
+            return Err(Error::PsiProtocolError(
+                "the sender's cuckoo table uses a stash".to_string(),
+            ));
+        }
+        let mut psi = Self::init(channel, rng)?;
+        psi.features = features;
+        Ok(psi)
+    }
+
+    /// Initialize the PSI receiver with the settings of `level`, which must be
+    /// that of the sender. Fails if the crate was not built with the features
+    /// `level` needs, or if the sender does not support the behaviors it
+    /// requires.
+    pub fn init_with_level<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+        level: SecurityLevel,
+    ) -> Result<Self, Error> {
+        level.check_build()?;
+        // `Legacy` negotiates nothing, as a plain `init`.
+        let mut psi = match level {
+            SecurityLevel::Legacy => Self::init(channel, rng)?,
+            _ => Self::negotiate(channel, rng, level.features())?,
+        };
+        level.check_features(psi.features)?;
+        psi.set_security(level.params());
+        psi.set_masksize_bounds(level.masksize_bounds());
+        events::emit(&AuditEvent::ParametersNegotiated {
+            features: psi.features,
+        });
+        Ok(psi)
+    }
+
+    /// The optional features enabled for this session.
+    pub fn features(&self) -> Features {
+        self.features
+    }
+
+    /// Set the epoch mixed into subsequent runs of the protocol, or `None` to
+    /// run without one. Must match the sender's epoch.
+    ///
+    /// Rotating the epoch makes the outputs returned by
+    /// `receive_with_outputs` unlinkable to those of earlier epochs.
+    pub fn set_epoch(&mut self, epoch: Option<u64>) {
+        self.epoch = epoch;
+    }
+
+    /// Set the function assigning items to bins in subsequent runs of the
+    /// protocol. Must match the sender's.
+    pub fn set_bin_hash(&mut self, bin_hash: impl BinHash + 'static) {
+        self.bin_hash = Box::new(bin_hash);
+    }
+
+    /// Hash the inputs of subsequent runs of the protocol with `nhashes` hash
+    /// functions, `DEFAULT_NHASHES` unless set. Runs fail unless
+    /// `cuckoo::supports_nhashes(nhashes)` and the sender accepts the choice.
+    pub fn set_nhashes(&mut self, nhashes: usize) {
+        self.nhashes = nhashes;
+    }
+
+    /// Set the security parameters of subsequent runs of the protocol, which
+    /// fix the size of the cuckoo table and the length of the tags received.
+    /// Must match the sender's.
+    pub fn set_security(&mut self, security: SecurityParams) {
+        self.security = security;
+    }
+
+    /// Bound the length of the tags of subsequent runs of the protocol, any
+    /// length up to `security::MAX_MASKSIZE` unless set. Runs fail with
+    /// `Error::IncompatibleMasksize` unless the sender accepts a length
+    /// within `bounds`.
+    pub fn set_masksize_bounds(&mut self, bounds: MasksizeBounds) {
+        self.masksize_bounds = bounds;
+    }
+
+    /// Keep the tags received in subsequent runs of the protocol in a
+    /// `CuckooFilter` per hash function, which reports a tag the sender did
+    /// not send with probability `fp_rate`, or in exact sets if `None`, the
+    /// default. A filter takes a few bytes per tag whatever the length of the
+    /// tags, but makes false matches more likely, as reported by
+    /// `false_positive_rate`. The sender cannot tell. Fails unless `fp_rate`
+    /// is supported by `CuckooFilter::new`, and runs fail against senders
+    /// holding more than `filter::MAX_CAPACITY` items.
+    ///
+    /// Runs of `receive_checkpointed` always keep exact sets, which are saved
+    /// in their checkpoints.
+    pub fn set_mask_filter(&mut self, fp_rate: Option<f64>) -> Result<(), Error> {
+        if let Some(fp_rate) = fp_rate {
+            CuckooFilter::new(0, fp_rate)?;
+        }
+        self.mask_filter = fp_rate;
+        self.filter_rate = None;
+        Ok(())
+    }
+
+    /// Bind subsequent runs of the protocol to the size the sender declared
+    /// with `sizes::exchange`, failing with `Error::SetSizeRejected` those in
+    /// which it sends tags for more items, or to no size if `None`, the
+    /// default. Runs of `receive`, in which the sender holds as many items as
+    /// this party, fail if this party holds more than declared.
+    pub fn set_declared_size(&mut self, declared: Option<usize>) {
+        self.declared_size = declared;
+    }
+
+    /// A bound on the probability that a run over `ninputs` items reports a
+    /// false match: the `2^-lambda_stat` of the security parameters, plus
+    /// that of a false positive of the mask filter on any of the inputs, if
+    /// set with `set_mask_filter`. Once a run has used the filters, their
+    /// rate is that of the filters of the last such run, which is higher if
+    /// the sender sent more tags than they hold; before, it is that of fresh
+    /// filters.
+    pub fn false_positive_rate(&self, ninputs: usize) -> f64 {
+        let tags = 0.5f64.powi(self.security.lambda_stat as i32);
+        let filter = self.mask_filter.map_or(0.0, |fp_rate| {
+            self.filter_rate.unwrap_or_else(|| {
+                CuckooFilter::new(0, fp_rate).map_or(fp_rate, |f| f.false_positive_rate())
+            })
+        });
+        (tags + ninputs as f64 * filter).min(1.0)
+    }
+
+    // Agree with the sender on the length of the tags of a run against a set
+    // of `nsender` items.
+    fn agree_masksize<C: AbstractChannel>(
+        &self,
+        channel: &mut C,
+        nsender: usize,
+    ) -> Result<usize, Error> {
+        agree_masksize(
+            channel,
+            nsender,
+            &self.security,
+            &self.masksize_bounds,
+            self.features,
+        )
+    }
+
+    /// Expire the session `lifetime` from now, or never if `None`, after
+    /// which runs fail and its checkpoints cannot be resumed.
+    pub fn set_lifetime(&mut self, lifetime: Option<Duration>) {
+        self.expires = lifetime.map(|lifetime| SystemTime::now() + lifetime);
+    }
+
+    /// The time at which the session expires, if any.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// Set the policy consulted on each matched item of subsequent runs of
+    /// the protocol, which drops the items it does not reveal from the
+    /// intersection. The sender is not told which items were dropped.
+    pub fn set_policy(&mut self, policy: impl MatchPolicy + 'static) {
+        self.policy = Some(Box::new(policy));
+    }
+
+    /// Remove the policy, revealing every matched item.
+    pub fn clear_policy(&mut self) {
+        self.policy = None;
+    }
+
+    // Whether the policy reveals the matched `item`.
+    fn reveals(&mut self, item: &[u8]) -> bool {
+        self.policy.as_mut().is_none_or(|p| p.reveal(item))
+    }
+
+    /// Return the intersection of subsequent runs of the protocol in `order`,
+    /// the order of `inputs` unless set, rather than that in which the
+    /// matches were found.
+    pub fn set_output_order(&mut self, order: OutputOrder) {
+        self.order = order;
+    }
+
+    /// The order in which the intersection is returned.
+    pub fn output_order(&self) -> OutputOrder {
+        self.order
+    }
+
+    // Drop the indices into `inputs` of `intersection` whose items the policy
+    // does not reveal, and arrange the rest in the output order.
+    fn apply_policy<RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        mut intersection: Vec<usize>,
+        rng: &mut RNG,
+    ) -> Vec<usize> {
+        intersection.retain(|&i| self.reveals(&inputs[i]));
+        self.order.arrange(inputs, &mut intersection, rng);
+        intersection
+    }
+
+    /// Run the PSI protocol over `inputs`.
+    pub fn receive<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let intersection = self.receive_indices(inputs, channel, rng)?;
+        Ok(utils::select(inputs, &intersection))
+    }
+
+    /// Run the PSI protocol over `inputs`, returning the indices in `inputs`
+    /// of those in the intersection rather than the items.
+    pub fn receive_indices<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<usize>, Error> {
+        let (intersection, _) = self.receive_indices_with_outputs(inputs, channel, rng)?;
+        Ok(intersection)
+    }
+
+    /// Run the PSI protocol over `inputs`, additionally returning the OPRF
+    /// output for each input, indexed as in `inputs`. If
+    /// `Features::CONFIRMATION` is enabled, the intersection is that of
+    /// `receive_confirmed`, and the outputs are those of the first round.
+    ///
+    /// The outputs are pseudorandom and distinct per input, so they can serve
+    /// as pseudonymous join keys when later processing the receiver's own
+    /// records locally.
+    pub fn receive_with_outputs<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(Vec<Vec<u8>>, Vec<ReceiverOutput>), Error> {
+        let (intersection, outputs) = self.receive_indices_with_outputs(inputs, channel, rng)?;
+        Ok((utils::select(inputs, &intersection), outputs))
+    }
+
+    // Run `receive_with_outputs`, returning indices into `inputs` rather than
+    // items.
+    fn receive_indices_with_outputs<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(Vec<usize>, Vec<ReceiverOutput>), Error> {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "session")?;
+            if self.features.contains(Features::CONFIRMATION) {
+                return self.receive_confirmed_with_outputs(inputs, channel, rng);
+            }
+            let keys = key_schedule(cointoss::receive(channel, &[rng.gen()])?[0]);
+            let n = inputs.len();
+            let (intersection, outputs) =
+                self.receive_against(inputs, n, n, keys.block(HASHING_KEY), channel, rng)?;
+            Ok((self.apply_policy(inputs, intersection, rng), outputs))
+        })
+    }
+
+    /// Run the PSI protocol over `inputs`, followed by a confirmation round
+    /// which re-checks the matched items under fresh, independent OPRF keys.
+    /// Must be paired with `Sender::send_confirmed`.
+    ///
+    /// An item survives only if its masked OPRF outputs collide in both
+    /// rounds, so false positives become negligible without raising the mask
+    /// size for the whole set. The sender learns the number of matched items
+    /// from the first round.
+    pub fn receive_confirmed<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let (intersection, _) = self.receive_confirmed_with_outputs(inputs, channel, rng)?;
+        Ok(utils::select(inputs, &intersection))
+    }
+
+    // Run `receive_confirmed`, returning indices into `inputs` and the OPRF
+    // outputs of the first round.
+    fn receive_confirmed_with_outputs<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(Vec<usize>, Vec<ReceiverOutput>), Error> {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "session")?;
+            let keys = key_schedule(cointoss::receive(channel, &[rng.gen()])?[0]);
+            let hashing_key = keys.block(HASHING_KEY);
+            let n = inputs.len();
+            let (candidates, outputs) =
+                self.receive_against(inputs, n, n, hashing_key, channel, rng)?;
+            channel.write_usize(candidates.len())?;
+            channel.flush()?;
+            if candidates.is_empty() {
+                return Ok((candidates, outputs));
+            }
+            let nsender = channel.read_usize()?;
+            let confirmation_key = keys.block(CONFIRMATION_KEY);
+            let (confirmed, _) = self.receive_against(
+                &utils::select(inputs, &candidates),
+                nsender,
+                nsender,
+                confirmation_key,
+                channel,
+                rng,
+            )?;
+            let intersection = confirmed.into_iter().map(|i| candidates[i]).collect();
+            Ok((self.apply_policy(inputs, intersection, rng), outputs))
+        })
+    }
+
+    /// Save the session, to be continued from another connection with
+    /// `Receiver::resume`. The `BinHash`, the `MatchPolicy`, the
+    /// `OutputOrder`, the number of hash functions, the `SecurityParams` and
+    /// the `MasksizeBounds` are not saved.
+    pub fn checkpoint(&self) -> Vec<u8> {
+        let session = Session {
+            epoch: self.epoch,
+            features: self.features,
+            expires: self.expires,
+        };
+        write_session(session, &self.oprf.checkpoint())
+    }
+
+    /// Continue a session saved by `Receiver::checkpoint`, against a sender
+    /// running `Sender::resume`. The session keeps its expiry time, and fails
+    /// to resume if it has expired.
+    pub fn resume<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        checkpoint: &[u8],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        let (session, oprf) = read_session(checkpoint)?;
+        exchange_version(channel)?;
+        let nonce = cointoss::receive(channel, &[rng.gen()])?[0];
+        let oprf = oprf::KkrtReceiver::resume(oprf, nonce)?;
+        Ok(Self {
+            oprf,
+            epoch: session.epoch,
+            bin_hash: Box::new(DefaultBinHash),
+            features: session.features,
+            nhashes: DEFAULT_NHASHES,
+            security: SecurityParams::default(),
+            masksize_bounds: MasksizeBounds::default(),
+            mask_filter: None,
+            filter_rate: None,
+            declared_size: None,
+            expires: session.expires,
+            policy: None,
+            order: OutputOrder::default(),
+        })
+    }
+
+    /// Run the PSI protocol over `inputs`, against a sender running
+    /// `Sender::send_checkpointed`, saving its progress in `checkpoint`.
+    ///
+    /// If `checkpoint` is `None` a new run starts. If the run fails,
+    /// `checkpoint` holds the tags received so far, and calling this method
+    /// again with the same `inputs`, from a session resumed from the same
+    /// session checkpoint, receives the rest. `checkpoint` is reset once the
+    /// run is done.
+    pub fn receive_checkpointed<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        checkpoint: &mut Option<ReceiveCheckpoint>,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "session")?;
+            exchange_resuming(channel, checkpoint.is_some())?;
+            let tbl = match checkpoint {
+                None => {
+                    let keys = key_schedule(cointoss::receive(channel, &[rng.gen()])?[0]);
+                    let key = keys.block(HASHING_KEY);
+                    let nsender = channel.read_usize()?;
+                    sizes::check_peer(nsender, self.declared_size)?;
+                    let (tbl, outputs) = self.perform_oprfs(inputs, key, channel, rng)?;
+                    *checkpoint = Some(ReceiveCheckpoint {
+                        run_id: keys.block(RUN_ID),
+                        key,
+                        nsender,
+                        outputs,
+                        tags: vec![
+                            HashSet::with_capacity(nsender.min(MAX_RESERVED_TAGS));
+                            tbl.nhashes
+                        ],
+                        nreceived: 0,
+                    });
+                    tbl
+                }
+                Some(checkpoint) => {
+                    let tbl = self.cuckoo_table(inputs, checkpoint.key)?;
+                    if tbl.nbins != checkpoint.outputs.len() || tbl.nhashes != checkpoint.tags.len()
+                    {
+                        return Err(invalid_checkpoint("the inputs are not those of the run"));
+                    }
+                    channel.write_block(&checkpoint.run_id)?;
+                    channel.write_usize(checkpoint.nreceived)?;
+                    channel.flush()?;
+                    if !channel.read_bool()? {
+                        return Err(Error::PsiProtocolError(
+                            "the sender's checkpoint is of another run".to_string(),
+                        ));
+                    }
+                    tbl
+                }
+            };
+            let saved = checkpoint
+                .as_mut()
+                .ok_or_else(|| utils::violated("a run has a checkpoint"))?;
+            let masksize = self.agree_masksize(channel, saved.nsender)?;
+            if saved.tags.iter().flatten().any(|tag| tag.len() != masksize) {
+                return Err(invalid_checkpoint("the tags are not of the agreed length"));
+            }
+            let total = saved.tags.len().checked_mul(saved.nsender).ok_or_else(|| {
+                Error::PsiProtocolError(format!(
+                    "the sender's {} items overflow the count of tags",
+                    saved.nsender
+                ))
+            })?;
+            while saved.nreceived < total {
+                let tag = channel.read_vec(masksize)?;
+                saved.tags[saved.nreceived / saved.nsender].insert(tag);
+                saved.nreceived += 1;
+            }
+            let (intersection, _) =
+                self.intersect(inputs, &tbl, &saved.outputs, &saved.tags, masksize)?;
+            *checkpoint = None;
+            let intersection = self.apply_policy(inputs, intersection, rng);
+            events::emit(&AuditEvent::ResultSize {
+                nitems: intersection.len(),
+            });
+            Ok(utils::select(inputs, &intersection))
+        })
+    }
+
+    /// Run the PSI protocol over `inputs`, against a sender streaming its set
+    /// with `Sender::send_stream`.
+    pub fn receive_stream<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "session")?;
+            let key = key_schedule(cointoss::receive(channel, &[rng.gen()])?[0]).block(HASHING_KEY);
+            let nsender = channel.read_usize()?;
+            let chunk_size = channel.read_usize()?;
+            utils::invariant(chunk_size > 0, "the sender streams non-empty chunks")?;
+            let (intersection, _) =
+                self.receive_against(inputs, nsender, chunk_size, key, channel, rng)?;
+            let intersection = self.apply_policy(inputs, intersection, rng);
+            events::emit(&AuditEvent::ResultSize {
+                nitems: intersection.len(),
+            });
+            Ok(utils::select(inputs, &intersection))
+        })
+    }
+
+    // Run the receiver side of `encode_chunk` for a sender holding `nsender`
+    // inputs, hashed under `key` and sent in chunks of `chunk_size`.
+    fn receive_against<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        nsender: usize,
+        chunk_size: usize,
+        key: Block,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(Vec<usize>, Vec<ReceiverOutput>), Error> {
+        sizes::check_peer(nsender, self.declared_size)?;
+        let masksize = self.agree_masksize(channel, nsender)?;
+        if self.mask_filter.is_some() && nsender > filter::MAX_CAPACITY {
+            return Err(Error::PsiProtocolError(format!(
+                "the sender holds {} items, more than a mask filter holds",
+                nsender
+            )));
+        }
+
+        let (tbl, outputs) = self.perform_oprfs(inputs, key, channel, rng)?;
+
+        // Receive all the sets from the sender.
+        match self.mask_filter {
+            None => {
+                let hs = vec![HashSet::with_capacity(nsender.min(MAX_RESERVED_TAGS)); tbl.nhashes];
+                let hs = collect_tags(hs, nsender, chunk_size, masksize, channel)?;
+                self.intersect(inputs, &tbl, &outputs, &hs, masksize)
+            }
+            Some(fp_rate) => {
+                let hs = (0..tbl.nhashes)
+                    .map(|_| CuckooFilter::new(nsender, fp_rate))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let hs = collect_tags(hs, nsender, chunk_size, masksize, channel)?;
+                let rate = hs.iter().map(CuckooFilter::false_positive_rate);
+                self.filter_rate = Some(rate.fold(0.0, f64::max));
+                self.intersect(inputs, &tbl, &outputs, &hs, masksize)
+            }
+        }
+    }
+
+    // Iterate through each input/output pair and see whether it exists in the
+    // appropriate set of tags `hs`, returning the indices into `inputs` of the
+    // intersection and the retained outputs.
+    fn intersect<T: TagSet>(
+        &self,
+        inputs: &[Vec<u8>],
+        tbl: &CuckooHash,
+        outputs: &[Block512],
+        hs: &[T],
+        masksize: usize,
+    ) -> Result<(Vec<usize>, Vec<ReceiverOutput>), Error> {
+        let n = inputs.len();
+        let mut intersection = Vec::with_capacity(n);
+        let mut retained = vec![None; n];
+        memtrack::phase("psz::receiver::intersect", || {
+            for (opt_item, output) in tbl.items.iter().zip(outputs.iter()) {
+                if let Some(item) = opt_item {
+                    if hs[item.hash_index].contains_tag(&output_tag(output, masksize)) {
+                        intersection.push(item.input_index);
+                    }
+                    retained[item.input_index] = Some(ReceiverOutput::new(output, self.epoch));
+                }
+            }
+        });
+        // Every input lands in exactly one bin, as the cuckoo hash errors out
+        // rather than dropping items.
+        let retained = retained
+            .into_iter()
+            .map(|o| o.ok_or_else(|| utils::violated("every input is in the cuckoo table")))
+            .collect::<Result<_, _>>()?;
+
+        Ok((intersection, retained))
+    }
+
+    /// Run the PSI protocol over `inputs`, returning the items in the
+    /// intersection, each mapped to the payload the sender associated with
+    /// it. Must be paired with `Sender::send_payloads`.
+    pub fn receive_payloads<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<
+        HashMap<
+            Vec<u8>, // Intersection item
+            Vec<u8>, // Payload
+        >,
+        Error,
+    > {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "session")?;
+            let key = key_schedule(cointoss::receive(channel, &[rng.gen()])?[0]).block(HASHING_KEY);
+            let (tbl, outputs) = self.perform_oprfs(inputs, key, channel, rng)?;
+            let nsender = channel.read_usize()?;
+            let payload_size = channel.read_usize()?;
+            sizes::check_peer(nsender, self.declared_size)?;
+            if payload_size > MAX_PAYLOAD_SIZE {
+                return Err(Error::PsiProtocolError(format!(
+                    "the sender's payloads are {} bytes, more than the limit of {}",
+                    payload_size, MAX_PAYLOAD_SIZE
+                )));
+            }
+            let masksize = self.agree_masksize(channel, nsender)?;
+
+            // Receive all the sets from the sender. Each tag is paired with a
+            // payload masked by the sender's OPRF output, so only payloads whose
+            // tag the receiver can recompute are unmasked.
+            let mut hs = vec![HashMap::with_capacity(nsender.min(MAX_RESERVED_TAGS)); tbl.nhashes];
+            for h in hs.iter_mut() {
+                for _ in 0..nsender {
+                    let tag = channel.read_vec(masksize)?;
+                    let ct = channel.read_vec(payload_size)?;
+                    h.insert(tag, ct);
+                }
+            }
+
+            // Iterate through each input/output pair and see whether it exists in
+            // the appropriate set.
+            let mut intersection = HashMap::with_capacity(inputs.len());
+            for (opt_item, output) in tbl.items.iter().zip(outputs) {
+                if let Some(item) = opt_item {
+                    let tag = output_tag(&output, masksize);
+                    let input = &inputs[item.input_index];
+                    if let Some(ct) = hs[item.hash_index].get(&tag) {
+                        // Payloads the policy does not reveal are never unmasked.
+                        if !self.reveals(input) {
+                            continue;
+                        }
+                        let mut payload = vec![0u8; payload_size];
+                        payload_pad(&output, &mut payload);
+                        scutils::xor_inplace(&mut payload, ct);
+                        intersection.insert(input.clone(), payload);
+                    }
+                }
+            }
+
+            Ok(intersection)
+        })
+    }
+
+    // Hash `inputs` under `key` into a cuckoo table.
+    fn cuckoo_table(&self, inputs: &[Vec<u8>], key: Block) -> Result<CuckooHash, Error> {
+        let hashed = utils::compress_and_hash_inputs_salted(inputs, key, epoch_salt(self.epoch));
+        CuckooHash::with_security(
+            &hashed,
+            self.nhashes,
+            self.bin_hash.as_ref(),
+            &self.security,
+        )
+    }
+
+    // Helper to do computation common to both receive and receive_payloads,
+    // hashing `inputs` under `key`.
+    fn perform_oprfs<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        key: Block,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<
+        (
+            CuckooHash,    // Cuckoo Table
+            Vec<Block512>, // OPRF outputs
+        ),
+        Error,
+    > {
+        let tbl = memtrack::phase("psz::receiver::hash", || self.cuckoo_table(inputs, key))?;
+        let nbins = tbl.nbins;
+
+        // Send cuckoo hash info to sender.
+        channel.write_usize(tbl.nhashes)?;
+        channel.write_usize(nbins)?;
+        channel.flush()?;
+
+        // Extract inputs from cuckoo hash.
+        let oprf_inputs = tbl
+            .items
+            .iter()
+            .map(|opt_item| {
+                if let Some(item) = opt_item {
+                    item.entry
+                } else {
+                    // No item found, so use the "default" item.
+                    Block::default()
+                }
+            })
+            .collect::<Vec<Block>>();
+
+        let oprf_outputs = memtrack::phase("psz::receiver::oprf", || {
+            self.oprf.receive(channel, &oprf_inputs, rng)
+        })?;
+
+        Ok((tbl, oprf_outputs))
+    }
+}
+
+impl PsiSender for Sender {
+    const PROTOCOL: &'static str = "psz";
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        Sender::init(channel, rng)
+    }
+
+    fn features(&self) -> Features {
+        Sender::features(self)
+    }
+
+    fn send<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        Sender::send(self, inputs, channel, rng)
+    }
+}
+
+impl PsiReceiver for Receiver {
+    const PROTOCOL: &'static str = "psz";
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        Receiver::init(channel, rng)
+    }
+
+    fn features(&self) -> Features {
+        Receiver::features(self)
+    }
+
+    fn receive<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        Receiver::receive(self, inputs, channel, rng)
+    }
+
+    fn receive_indices<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<usize>, Error> {
+        Receiver::receive_indices(self, inputs, channel, rng)
+    }
+}
+
+impl SemiHonest for Sender {}
+impl SemiHonest for Receiver {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rand_vec_vec;
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const ITEM_SIZE: usize = 8;
+    const SET_SIZE: usize = 1 << 16;
+
+    #[test]
+    fn test_psi_complete_intersection() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let receiver_inputs = sender_inputs.clone();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection.len(), SET_SIZE);
+    }
+
+    #[test]
+    fn test_psi_parallel() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(SET_SIZE / 2, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..SET_SIZE / 2]);
+        let expected = sender_inputs[..SET_SIZE / 2]
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.set_threads(3).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    #[test]
+    fn test_psi_receiver_outputs() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(SET_SIZE / 2, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..SET_SIZE / 2]);
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let (intersection, outputs) = psi
+            .receive_with_outputs(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection.len(), SET_SIZE / 2);
+        assert_eq!(outputs.len(), receiver_inputs.len());
+        let distinct = outputs.iter().map(|o| o.as_bytes()).collect::<HashSet<_>>();
+        assert_eq!(distinct.len(), outputs.len());
+    }
+
+    #[test]
+    fn test_psi_nhashes() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(1 << 10, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(1 << 9, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..1 << 9]);
+        let expected = sender_inputs[..1 << 9]
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.set_max_nhashes(4);
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng)
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        psi.set_nhashes(4);
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        assert_eq!(intersection.into_iter().collect::<HashSet<_>>(), expected);
+        // The sender turns away a table with more hash functions than it
+        // accepts.
+        psi.set_nhashes(5);
+        assert!(psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .is_err());
+        match handle.join().unwrap() {
+            Err(Error::PsiProtocolError(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_psi_security() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(1 << 10, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(1 << 9, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..1 << 9]);
+        let expected = sender_inputs[..1 << 9]
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let security = SecurityParams::new(60, 128).unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.set_security(security);
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        psi.set_security(security);
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    #[test]
+    fn test_psi_mask_filter() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(1 << 10, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(1 << 9, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..1 << 9]);
+        let expected = sender_inputs[..1 << 9]
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let exact = psi.false_positive_rate(receiver_inputs.len());
+        assert!(psi.set_mask_filter(Some(0.0)).is_err());
+        psi.set_mask_filter(Some(1e-6)).unwrap();
+        let filtered = psi.false_positive_rate(receiver_inputs.len());
+        assert!(filtered > exact && filtered <= exact + 1e-6 * 1024.0);
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection.into_iter().collect::<HashSet<_>>(), expected);
+        // The rate is now that of the filters used, which held the tags.
+        assert_eq!(psi.false_positive_rate(receiver_inputs.len()), filtered);
+        psi.filter_rate = Some(0.5);
+        assert!(psi.false_positive_rate(receiver_inputs.len()) > 0.5);
+    }
+
+    fn run_with_epochs(
+        sender_epoch: Option<u64>,
+        receiver_epoch: Option<u64>,
+        set_size: usize,
+    ) -> (Vec<Vec<u8>>, Vec<ReceiverOutput>) {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(set_size, ITEM_SIZE, &mut rng);
+        let receiver_inputs = sender_inputs.clone();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.set_epoch(sender_epoch);
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        psi.set_epoch(receiver_epoch);
+        let result = psi
+            .receive_with_outputs(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        result
+    }
+
+    #[test]
+    fn test_psi_epochs() {
+        let (intersection, _) = run_with_epochs(Some(7), Some(7), 1 << 10);
+        assert_eq!(intersection.len(), 1 << 10);
+        let (intersection, _) = run_with_epochs(Some(7), Some(8), 1 << 10);
+        assert_eq!(intersection.len(), 0);
+        let (intersection, _) = run_with_epochs(None, Some(0), 1 << 10);
+        assert_eq!(intersection.len(), 0);
+    }
+
+    // A partner's bin hash, reading the bins from the top of the item.
+    struct PartnerBinHash;
+
+    impl BinHash for PartnerBinHash {
+        fn bin(&self, hash: Block, hidx: usize, nbins: usize) -> usize {
+            ((u128::from(hash) >> (96 - 32 * hidx)) as u32 as usize) % nbins
+        }
+    }
+
+    #[test]
+    fn test_psi_masksize_bounds() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(1 << 10, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(1 << 9, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..1 << 9]);
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi =
+                Sender::init_negotiated(&mut channel, &mut rng, Features::MASKSIZE_NEGOTIATION)
+                    .unwrap();
+            psi.set_masksize_bounds(MasksizeBounds::new(12, 16).unwrap());
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng)
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi =
+            Receiver::init_negotiated(&mut channel, &mut rng, Features::MASKSIZE_NEGOTIATION)
+                .unwrap();
+        assert!(psi.features().contains(Features::MASKSIZE_NEGOTIATION));
+        // The receiver takes up the sender's floor.
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        assert_eq!(intersection.len(), 1 << 9);
+        // No length fits both parties once the receiver caps its tags below
+        // the sender's floor.
+        psi.set_masksize_bounds(MasksizeBounds::new(1, 8).unwrap());
+        match psi.receive(&receiver_inputs, &mut channel, &mut rng) {
+            Err(Error::IncompatibleMasksize { ours, theirs }) => {
+                assert_eq!((ours.max, theirs.min), (8, 12))
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match handle.join().unwrap() {
+            Err(e @ Error::IncompatibleMasksize { .. }) => {
+                assert_eq!(e.class(), crate::ErrorClass::ParameterMismatch)
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_psi_bin_hash() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(1 << 10, ITEM_SIZE, &mut rng);
+        let receiver_inputs = sender_inputs.clone();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.set_bin_hash(PartnerBinHash);
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        psi.set_bin_hash(PartnerBinHash);
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection.len(), 1 << 10);
+    }
+
+    #[test]
+    fn test_psi_confirmed() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(SET_SIZE / 4, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..SET_SIZE - SET_SIZE / 4]);
+        let expected = sender_inputs[..SET_SIZE - SET_SIZE / 4]
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.send_confirmed(&sender_inputs, &mut channel, &mut rng)
+                .unwrap();
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let intersection = psi
+            .receive_confirmed(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    #[test]
+    fn test_psi_stream() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(1 << 12, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(1 << 8, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[1000..1100]);
+        let expected = receiver_inputs[1 << 8..]
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            let n = sender_inputs.len();
+            // Chunks do not divide the set evenly.
+            psi.send_chunked(sender_inputs.clone(), n, 300, &mut channel, &mut rng)
+                .unwrap();
+            // A stream shorter than announced is caught.
+            let short = sender_inputs.into_iter().take(n - 1);
+            match psi.send_stream(short, n, &mut channel, &mut rng) {
+                Err(Error::InvalidInput(_)) => (),
+                _ => panic!("the short stream was accepted"),
+            }
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let intersection = psi
+            .receive_stream(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        assert!(psi
+            .receive_stream(&receiver_inputs, &mut channel, &mut rng)
+            .is_err());
+        handle.join().unwrap();
+        assert_eq!(intersection.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    // A writer failing once `remaining` bytes have been written, as if the
+    // connection dropped.
+    struct FailingWriter<W> {
+        inner: W,
+        remaining: usize,
+    }
+
+    impl<W: std::io::Write> std::io::Write for FailingWriter<W> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if buf.len() > self.remaining {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            self.remaining -= buf.len();
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    #[test]
+    fn test_psi_resume() {
+        let mut rng = AesRng::new();
+        let sender_inputs = rand_vec_vec(1 << 10, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(1 << 8, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[100..200]);
+        let expected = receiver_inputs[1 << 8..]
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        // The first connection sets up the sessions.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.set_epoch(Some(5));
+            psi.checkpoint()
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        psi.set_epoch(Some(5));
+        let receiver_session = psi.checkpoint();
+        let sender_session = handle.join().unwrap();
+        assert!(Receiver::resume(&receiver_session[1..], &mut channel, &mut rng).is_err());
+
+        // The second connection drops while the sender sends its tags.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_session_ = sender_session.clone();
+        let sender_inputs_ = sender_inputs.clone();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = FailingWriter {
+                inner: BufWriter::new(sender),
+                remaining: 10_000,
+            };
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::resume(&sender_session_, &mut channel, &mut rng).unwrap();
+            let mut checkpoint = None;
+            assert!(psi
+                .send_checkpointed(&sender_inputs_, &mut checkpoint, &mut channel, &mut rng)
+                .is_err());
+            checkpoint.unwrap().to_bytes()
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::resume(&receiver_session, &mut channel, &mut rng).unwrap();
+        let mut checkpoint = None;
+        assert!(psi
+            .receive_checkpointed(&receiver_inputs, &mut checkpoint, &mut channel, &mut rng)
+            .is_err());
+        let sender_run = handle.join().unwrap();
+        let receiver_run = checkpoint.unwrap().to_bytes();
+        let checkpoint = ReceiveCheckpoint::from_bytes(&receiver_run).unwrap();
+        assert!(checkpoint.nreceived() > 0);
+        assert!(checkpoint.nreceived() < DEFAULT_NHASHES * sender_inputs.len());
+        assert!(ReceiveCheckpoint::from_bytes(&receiver_run[..receiver_run.len() - 1]).is_err());
+        // A sender set so large that its count of tags overflows is rejected.
+        let mut overflowing = receiver_run.clone();
+        overflowing[33..41].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(ReceiveCheckpoint::from_bytes(&overflowing).is_err());
+
+        // The third connection finishes the run.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::resume(&sender_session, &mut channel, &mut rng).unwrap();
+            let mut checkpoint = Some(SendCheckpoint::from_bytes(&sender_run).unwrap());
+            psi.send_checkpointed(&sender_inputs, &mut checkpoint, &mut channel, &mut rng)
+                .unwrap();
+            assert!(checkpoint.is_none());
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::resume(&receiver_session, &mut channel, &mut rng).unwrap();
+        let mut checkpoint = Some(checkpoint);
+        let intersection = psi
+            .receive_checkpointed(&receiver_inputs, &mut checkpoint, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert!(checkpoint.is_none());
+        assert_eq!(intersection.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    #[test]
+    fn test_psi_lifetime() {
+        let mut rng = AesRng::new();
+        let inputs = rand_vec_vec(1 << 8, ITEM_SIZE, &mut rng);
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.set_lifetime(Some(Duration::from_secs(60)));
+            let live = psi.checkpoint();
+            psi.set_lifetime(Some(Duration::from_millis(0)));
+            (live, psi.checkpoint())
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let receiver_session = psi.checkpoint();
+        psi.set_lifetime(Some(Duration::from_millis(0)));
+        // Printing a party shows its settings, but not the OPRF's keys.
+        let shown = format!("{:?}", psi);
+        assert!(shown.starts_with("psz::Receiver { oprf: kkrt::Receiver { nbase: "));
+        assert!(shown.contains("expires: Some("));
+        // An expired session refuses to run before sending anything.
+        match psi.receive(&inputs, &mut channel, &mut rng) {
+            Err(Error::Expired(_)) => (),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
         }
-
-        // Iterate through each input/output pair and see whether it exists in
-        // the appropriate set.
-        let mut intersection = Vec::with_capacity(n);
-        for (opt_item, output) in tbl.items.iter().zip(outputs.into_iter()) {
-            if let Some(item) = opt_item {
-                let prefix = output.prefix(masksize);
-                if hs[item.hash_index].contains(prefix) {
-                    let val = inputs[item.input_index].clone();
-                    intersection.push(val);
-                }
-            }
+        let (sender_session, expired) = handle.join().unwrap();
+        match Sender::resume(&expired, &mut channel, &mut rng) {
+            Err(Error::Expired(_)) => (),
+            _ => panic!("resumed an expired session"),
         }
 
-        Ok(intersection)
+        // A resumed session keeps its expiry time, and runs until then.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = inputs.clone();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::resume(&sender_session, &mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+            psi.expires()
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::resume(&receiver_session, &mut channel, &mut rng).unwrap();
+        assert_eq!(psi.expires(), None);
+        let intersection = psi.receive(&inputs, &mut channel, &mut rng).unwrap();
+        assert_eq!(intersection.len(), inputs.len());
+        let expires = handle.join().unwrap().unwrap();
+        assert!(expires > SystemTime::now());
+        assert!(expires <= SystemTime::now() + Duration::from_secs(60));
     }
 
-    /// Run the PSI protocol over `inputs`, receiving a vector of tuples consisting of
-    /// the intersection items and associated payloads.
-    pub fn receive_payloads<C: AbstractChannel, RNG: CryptoRng + RngCore>(
-        &mut self,
-        inputs: &[Vec<u8>],
-        channel: &mut C,
-        rng: &mut RNG,
-    ) -> Result<
-        HashMap<
-            Vec<u8>, // Intersection item
-            Block,   // Payload
-        >,
-        Error,
-    > {
-        let (tbl, outputs) = self.perform_oprfs(inputs, channel, rng)?;
-        let n = inputs.len();
-        let masksize = compute_masksize(n)?;
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn test_psi_serde() {
+        let mut rng = AesRng::new();
+        let sender_inputs = rand_vec_vec(1 << 8, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(1 << 7, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..1 << 7]);
+        let expected = sender_inputs[..1 << 7]
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
 
-        // Receive all the sets from the sender. These come in paired with H(F(x)), which
-        // allows tree searching without learning the Sender's F(x) values (which are used
-        // to encrypt the payloads).
-        let mut hs = vec![HashMap::with_capacity(n); NHASHES];
-        for h in hs.iter_mut() {
-            for _ in 0..n {
-                let mut tag = vec![0; masksize];
-                channel.read_bytes(&mut tag)?;
-                let ct = channel.read_block()?;
-                h.insert(tag, ct);
-            }
-        }
+        // Set up and run once, then ship the parties elsewhere.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs_ = sender_inputs.clone();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.set_epoch(Some(3));
+            psi.send(&sender_inputs_, &mut channel, &mut rng).unwrap();
+            bincode::serialize(&psi).unwrap()
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        psi.set_epoch(Some(3));
+        psi.receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        let receiver_state = bincode::serialize(&psi).unwrap();
+        let sender_state = handle.join().unwrap();
+        assert!(bincode::deserialize::<Receiver>(&receiver_state[1..]).is_err());
 
-        // Iterate through each input/output pair and see whether it exists in
-        // the appropriate set.
-        let mut intersection = HashMap::with_capacity(n);
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = bincode::deserialize::<Sender>(&sender_state).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = bincode::deserialize::<Receiver>(&receiver_state).unwrap();
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection.into_iter().collect::<HashSet<_>>(), expected);
+    }
 
-        for (opt_item, output) in tbl.items.iter().zip(outputs.into_iter()) {
-            if let Some(item) = opt_item {
-                let tag = &output.as_ref()[0..masksize];
+    #[test]
+    fn test_psi_policy() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(1 << 10, ITEM_SIZE, &mut rng);
+        let receiver_inputs = sender_inputs.clone();
+        let payloads = rand_vec_vec(1 << 10, 16, &mut rng);
+        let denylist = sender_inputs[..100].iter().cloned().collect::<HashSet<_>>();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+            psi.send_payloads(&sender_inputs, &payloads, &mut channel, &mut rng)
+                .unwrap();
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let denied = denylist.clone();
+        psi.set_policy(move |item: &[u8]| !denied.contains(item));
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        let with_payloads = psi
+            .receive_payloads(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection.len(), receiver_inputs.len() - denylist.len());
+        assert!(intersection.iter().all(|item| !denylist.contains(item)));
+        assert_eq!(with_payloads.len(), intersection.len());
+        assert!(with_payloads.keys().all(|item| !denylist.contains(item)));
+    }
 
-                // if the tag is present, decrypt the payload using F(x).
-                if let Some(ct) = hs[item.hash_index].get(tag) {
-                    let val = inputs[item.input_index].clone();
-                    let key = &output.as_ref()[masksize..masksize + 16];
-                    let payload_bytes = scuttlebutt::utils::xor(ct.as_ref(), key);
-                    let payload =
-                        Block::try_from_slice(&payload_bytes).expect("it is exactly 16 bytes long");
-                    intersection.insert(val, payload);
-                }
+    #[test]
+    fn test_psi_output_order() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(SET_SIZE / 2, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..SET_SIZE / 2]);
+        receiver_inputs.shuffle(&mut rng);
+        let orders = [
+            OutputOrder::Input,
+            OutputOrder::Sorted,
+            OutputOrder::Shuffled,
+        ];
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            for _ in 0..orders.len() {
+                psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
             }
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        assert_eq!(psi.output_order(), OutputOrder::Input);
+        let mut results = Vec::new();
+        for &order in &orders {
+            psi.set_output_order(order);
+            results.push(
+                psi.receive_indices(&receiver_inputs, &mut channel, &mut rng)
+                    .unwrap(),
+            );
         }
-
-        Ok(intersection)
+        handle.join().unwrap();
+        let input = &results[0];
+        assert_eq!(input.len(), SET_SIZE / 2);
+        assert!(input.windows(2).all(|w| w[0] < w[1]));
+        let sorted = utils::select(&receiver_inputs, &results[1]);
+        assert!(sorted.windows(2).all(|w| w[0] <= w[1]));
+        let mut shuffled = results[2].clone();
+        shuffled.sort_unstable();
+        assert_eq!(&shuffled, input);
     }
 
-    // Helper to do computation common to both receive and receive_payloads
-    fn perform_oprfs<C: AbstractChannel, RNG: CryptoRng + RngCore>(
-        &mut self,
-        inputs: &[Vec<u8>],
-        channel: &mut C,
-        rng: &mut RNG,
-    ) -> Result<
+    fn run_negotiated(
+        sender_features: Features,
+        receiver_features: Features,
+    ) -> (Features, HashSet<Vec<u8>>, HashSet<Vec<u8>>) {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(1 << 9, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(1 << 8, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..1 << 8]);
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init_negotiated(&mut channel, &mut rng, sender_features).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+            psi.features()
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init_negotiated(&mut channel, &mut rng, receiver_features).unwrap();
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        assert_eq!(handle.join().unwrap(), psi.features());
         (
-            CuckooHash,    // Cuckoo Table
-            Vec<Block512>, // OPRF outputs
-        ),
-        Error,
-    > {
-        let key = cointoss::receive(channel, &[rng.gen()])?[0];
-
-        let hashed = utils::compress_and_hash_inputs(inputs, key);
-
-        let tbl = CuckooHash::new(&hashed, NHASHES)?;
-        let nbins = tbl.nbins;
-
-        // Send cuckoo hash info to sender.
-        channel.write_usize(nbins)?;
-        channel.flush()?;
-
-        // Extract inputs from cuckoo hash.
-        let oprf_inputs = tbl
-            .items
-            .iter()
-            .map(|opt_item| {
-                if let Some(item) = opt_item {
-                    item.entry
-                } else {
-                    // No item found, so use the "default" item.
-                    Block::default()
-                }
-            })
-            .collect::<Vec<Block>>();
-
-        let oprf_outputs = self.oprf.receive(channel, &oprf_inputs, rng)?;
-
-        Ok((tbl, oprf_outputs))
+            psi.features(),
+            intersection.into_iter().collect(),
+            receiver_inputs[1 << 8..].iter().cloned().collect(),
+        )
     }
-}
-
-impl SemiHonest for Sender {}
-impl SemiHonest for Receiver {}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::utils::rand_vec_vec;
-    use scuttlebutt::{AesRng, Channel};
-    use std::{
-        io::{BufReader, BufWriter},
-        os::unix::net::UnixStream,
-    };
 
-    const ITEM_SIZE: usize = 8;
-    const SET_SIZE: usize = 1 << 16;
+    #[test]
+    fn test_psi_negotiated() {
+        let both = Features::CONFIRMATION | Features::COMPRESSION;
+        let (features, intersection, expected) = run_negotiated(both, both);
+        assert_eq!(features, Features::STASH_FREE | Features::CONFIRMATION);
+        assert_eq!(intersection, expected);
+        // A peer which does not enable confirmation disables it for both.
+        let (features, intersection, expected) =
+            run_negotiated(Features::CONFIRMATION, Features::NONE);
+        assert_eq!(features, Features::STASH_FREE);
+        assert_eq!(intersection, expected);
+    }
 
     #[test]
-    fn test_psi_complete_intersection() {
+    fn test_psi_level() {
         let mut rng = AesRng::new();
         let (sender, receiver) = UnixStream::pair().unwrap();
         let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
-        let receiver_inputs = sender_inputs.clone();
+        let mut receiver_inputs = rand_vec_vec(SET_SIZE / 2, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[..SET_SIZE / 2]);
+        let level = SecurityLevel::Standard;
         let handle = std::thread::spawn(move || {
             let mut rng = AesRng::new();
             let reader = BufReader::new(sender.try_clone().unwrap());
             let writer = BufWriter::new(sender);
             let mut channel = Channel::new(reader, writer);
-            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            let mut psi = Sender::init_with_level(&mut channel, &mut rng, level).unwrap();
             psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
         });
-        let mut rng = AesRng::new();
         let reader = BufReader::new(receiver.try_clone().unwrap());
         let writer = BufWriter::new(receiver);
         let mut channel = Channel::new(reader, writer);
-        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let mut psi = Receiver::init_with_level(&mut channel, &mut rng, level).unwrap();
+        assert!(psi.features().contains(Features::MASKSIZE_NEGOTIATION));
+        assert_eq!(psi.security, level.params());
+        assert_eq!(psi.masksize_bounds.min, 7);
         let intersection = psi
             .receive(&receiver_inputs, &mut channel, &mut rng)
             .unwrap();
         handle.join().unwrap();
-        assert_eq!(intersection.len(), SET_SIZE);
+        assert_eq!(intersection.len(), SET_SIZE / 2);
+        // The build is checked before anything is sent.
+        let strict = SecurityLevel::Strict;
+        if strict.check_build().is_err() {
+            assert!(Receiver::init_with_level(&mut channel, &mut rng, strict).is_err());
+        }
     }
 
     #[test]
@@ -328,15 +2866,35 @@ mod tests {
         sender_inputs.extend(intersection.clone());
         receiver_inputs.extend(intersection.clone());
 
+        let sender_payloads = rand_vec_vec(SET_SIZE, 64, &mut rng);
+
         let thread_sender_inputs = sender_inputs.clone();
+        let thread_sender_payloads = sender_payloads.clone();
         let handle = std::thread::spawn(move || {
             let mut rng = AesRng::new();
             let reader = BufReader::new(sender.try_clone().unwrap());
             let writer = BufWriter::new(sender);
             let mut channel = Channel::new(reader, writer);
             let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
-            psi.send_payloads(&thread_sender_inputs, &mut channel, &mut rng)
-                .unwrap()
+            assert!(psi
+                .send_payloads(&thread_sender_inputs, &[], &mut channel, &mut rng)
+                .is_err());
+            let oversized = vec![vec![0u8; MAX_PAYLOAD_SIZE + 1]];
+            assert!(psi
+                .send_payloads(
+                    &thread_sender_inputs[..1],
+                    &oversized,
+                    &mut channel,
+                    &mut rng
+                )
+                .is_err());
+            psi.send_payloads(
+                &thread_sender_inputs,
+                &thread_sender_payloads,
+                &mut channel,
+                &mut rng,
+            )
+            .unwrap()
         });
 
         let mut rng = AesRng::new();
@@ -349,7 +2907,7 @@ mod tests {
             .receive_payloads(&receiver_inputs, &mut channel, &mut rng)
             .unwrap();
 
-        let sender_payloads = handle.join().unwrap();
+        handle.join().unwrap();
 
         assert_eq!(receiver_payloads.len(), intersection_size);
 
@@ -358,5 +2916,120 @@ mod tests {
                 assert_eq!(payload, other_payload);
             }
         }
+        for item in intersection.iter() {
+            assert!(receiver_payloads.contains_key(item));
+        }
+    }
+
+    #[cfg(feature = "hardened")]
+    #[test]
+    fn test_malformed_nbins() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng)
+        });
+        // A malicious receiver claims its cuckoo table has no bins.
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        exchange_version(&mut channel).unwrap();
+        let _oprf = oprf::KkrtReceiver::init(&mut channel, &mut rng).unwrap();
+        cointoss::receive(&mut channel, &[rng.gen()]).unwrap();
+        channel.write_usize(DEFAULT_NHASHES).unwrap();
+        channel.write_usize(0).unwrap();
+        channel.flush().unwrap();
+        match handle.join().unwrap() {
+            Err(Error::InternalInvariant(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        // Peers from before the version start on the OPRF setup right away,
+        // and fail along with this side whichever role they play.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            oprf::KkrtSender::init(&mut channel, &mut rng).map(|_| ())
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        match Receiver::init(&mut channel, &mut rng) {
+            Err(Error::PsiProtocolError(_)) | Err(Error::IoError(_)) => (),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+        drop(channel);
+        assert!(handle.join().unwrap().is_err());
+
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(receiver.try_clone().unwrap());
+            let writer = BufWriter::new(receiver);
+            let mut channel = Channel::new(reader, writer);
+            oprf::KkrtReceiver::init(&mut channel, &mut rng).map(|_| ())
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(sender.try_clone().unwrap());
+        let writer = BufWriter::new(sender);
+        let mut channel = Channel::new(reader, writer);
+        match Sender::init(&mut channel, &mut rng) {
+            Err(Error::PsiProtocolError(_)) | Err(Error::IoError(_)) => (),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+        drop(channel);
+        assert!(handle.join().unwrap().is_err());
+
+        // A peer running another version says so.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut magic = PROTOCOL_MAGIC;
+            magic[31] = PROTOCOL_VERSION + 1;
+            channel.write_bytes(&magic).unwrap();
+            channel.flush().unwrap();
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        match Receiver::init(&mut channel, &mut rng) {
+            Err(Error::PsiProtocolError(e)) => {
+                assert!(e.contains(&format!("version {}", PROTOCOL_VERSION + 1)))
+            }
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_hashed_tags() {
+        // Tags and pads depend on the whole OPRF output, not just its prefix.
+        let mut encoded = Block512::default();
+        let tag = output_tag(&encoded, 12);
+        let mut pad = [0u8; 16];
+        payload_pad(&encoded, &mut pad);
+        encoded.as_mut()[63] ^= 1;
+        assert_ne!(output_tag(&encoded, 12), tag);
+        let mut other = [0u8; 16];
+        payload_pad(&encoded, &mut other);
+        assert_ne!(other, pad);
+        assert_ne!(&pad[..12], &tag[..]);
     }
 }
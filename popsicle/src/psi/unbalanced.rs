@@ -0,0 +1,557 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Unbalanced private set intersection, for a server holding a set much larger
+//! than the client's.
+//!
+//! The protocol is built from the verifiable oblivious PRF of `voprf`. Offline,
+//! the server evaluates the PRF on each of its items and collects the results,
+//! truncated to a tag, into a `Digest`, which it serializes and ships to the
+//! client once. Online, the client obtains the PRF on each of its own items
+//! through the oblivious PRF, checking the server's proof that it used the key
+//! the digest was built under, and outputs the items whose tags are in the
+//! digest. The online phase costs a few scalar multiplications and 96 bytes
+//! of communication per client item, independently of the size of the
+//! server's set.
+//!
+//! The client learns the size of the server's set, and the digest can be
+//! reused by every client of the server, and across sessions, until the
+//! server's set changes. The server must then keep its key, which
+//! `Server::key_bytes` and `Server::from_key_bytes` store and restore.
+//!
+//! A key given an expiry time with `Server::set_expiry` stops being served
+//! once it has passed, and digests built under it carry the same expiry, which
+//! clients enforce. The server then rotates to a fresh key and ships a new
+//! digest, so that neither a leaked key nor a stale digest stays usable
+//! indefinitely.
+//!
+//! A client running daily against the same digest can keep the PRF outputs
+//! of its items in a `voprf::OutputCache` (see `Client::set_cache`), so that
+//! only the items it did not query before cost a query. A new digest under a
+//! rotated key invalidates the cache.
+//!
+//! Tags are long enough that a client querying as many items per session as
+//! the server evaluates (see `Server::set_max_queries`) sees a false positive
+//! with probability at most `2^-40`. A client whose set is larger than the
+//! tags of its digest allow for fails with `Error::SetSizeRejected` before
+//! querying the server.
+
+use crate::{
+    psi::{voprf, MatchPolicy},
+    utils,
+    Error,
+};
+use curve25519_dalek::{
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+};
+use rand::{CryptoRng, RngCore};
+use scuttlebutt::{memtrack, AbstractChannel, Malicious, SemiHonest};
+use std::{
+    cmp::Ordering,
+    io::{Read, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// The statistical security parameter.
+const STAT_SECURITY: usize = 40;
+
+fn log2_ceil(n: usize) -> usize {
+    n.max(1).next_power_of_two().trailing_zeros() as usize
+}
+
+// The number of bits of PRF output needed for a client querying `m` items
+// against a digest of `n` items.
+fn tag_bits(n: usize, m: usize) -> usize {
+    STAT_SECURITY + log2_ceil(n) + log2_ceil(m)
+}
+
+// The number of bytes of PRF output kept in a digest of `n` items, for
+// clients querying up to `m` items.
+pub(crate) fn tag_len(n: usize, m: usize) -> usize {
+    tag_bits(n, m).div_ceil(8).min(32)
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, msg))
+}
+
+/// The unbalanced PSI server, which holds the large set and learns nothing.
+pub struct Server {
+    inner: voprf::Sender,
+    expires: Option<SystemTime>,
+}
+
+/// The PRF tags of the server's items, precomputed offline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Digest {
+    public: RistrettoPoint,
+    tag_len: usize,
+    // The tags, sorted, and concatenated.
+    tags: Vec<u8>,
+    expires: Option<SystemTime>,
+}
+
+/// The unbalanced PSI client, which holds the small set and learns the
+/// intersection.
+pub struct Client {
+    inner: voprf::Receiver,
+    digest: Digest,
+    policy: Option<Box<dyn MatchPolicy>>,
+}
+
+impl Server {
+    /// Make a server with a fresh key.
+    pub fn new<RNG: CryptoRng + RngCore>(rng: &mut RNG) -> Self {
+        Self::from_key(Scalar::random(rng))
+    }
+
+    fn from_key(key: Scalar) -> Self {
+        Self {
+            inner: voprf::Sender::from_key(key),
+            expires: None,
+        }
+    }
+
+    /// Restore a server from the key returned by `key_bytes`.
+    pub fn from_key_bytes(bytes: [u8; 32]) -> Result<Self, Error> {
+        Scalar::from_canonical_bytes(bytes)
+            .map(Self::from_key)
+            .ok_or_else(|| invalid_data("non-canonical server key"))
+    }
+
+    /// The server's key, which must be kept secret.
+    pub fn key_bytes(&self) -> [u8; 32] {
+        self.inner.key().to_bytes()
+    }
+
+    /// Stop serving clients at `expires`, and stamp it on subsequent digests,
+    /// or serve indefinitely if `None`. The expiry is not part of the key
+    /// bytes, so a restored server must set it again.
+    pub fn set_expiry(&mut self, expires: Option<SystemTime>) {
+        self.expires = expires;
+    }
+
+    /// Evaluate up to `max_queries` items of a client per session,
+    /// `voprf::DEFAULT_MAX_QUERIES` unless set. Subsequent digests carry tags
+    /// long enough for clients querying that many items.
+    pub fn set_max_queries(&mut self, max_queries: usize) {
+        self.inner.set_max_queries(max_queries);
+    }
+
+    /// Compute the digest of `inputs` to ship to clients. This is the
+    /// expensive, offline part of the protocol.
+    pub fn digest(&self, inputs: &[Vec<u8>]) -> Digest {
+        let tag_len = tag_len(inputs.len(), self.inner.max_queries());
+        let mut tags = memtrack::phase("unbalanced::server::digest", || {
+            inputs
+                .iter()
+                .map(|x| self.inner.output(x)[..tag_len].to_vec())
+                .collect::<Vec<_>>()
+        });
+        // Sorting hides the order of the inputs and allows binary search.
+        tags.sort_unstable();
+        Digest {
+            public: *self.inner.public(),
+            tag_len,
+            tags: tags.concat(),
+            expires: self.expires,
+        }
+    }
+
+    /// Run the online phase of the protocol with a client, failing with
+    /// `Error::Expired` before doing so if the key has expired.
+    pub fn serve<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        utils::session(|| {
+            utils::check_expiry(self.expires, "server key")?;
+            self.inner.evaluate(channel, rng)?;
+            channel.flush()?;
+            Ok(())
+        })
+    }
+}
+
+// The key is left out.
+impl std::fmt::Debug for Server {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Server")
+            .field("expires", &self.expires)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Digest {
+    /// The number of items in the digest.
+    pub fn len(&self) -> usize {
+        self.tags.len() / self.tag_len
+    }
+
+    /// Whether the digest holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    /// The time at which the digest expires, if any.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    fn tag(&self, i: usize) -> &[u8] {
+        &self.tags[i * self.tag_len..(i + 1) * self.tag_len]
+    }
+
+    fn contains(&self, tag: &[u8]) -> bool {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.tag(mid).cmp(tag) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return true,
+            }
+        }
+        false
+    }
+
+    /// Serialize the digest to `writer`. The expiry time follows the tags, in
+    /// milliseconds since the Unix epoch, or zero if the digest never
+    /// expires.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let expires = self.expires.map_or(0, |expires| {
+            expires
+                .duration_since(UNIX_EPOCH)
+                .map_or(1, |d| (d.as_millis() as u64).max(1))
+        });
+        writer.write_all(self.public.compress().as_bytes())?;
+        writer.write_all(&(self.tag_len as u64).to_le_bytes())?;
+        writer.write_all(&(self.len() as u64).to_le_bytes())?;
+        writer.write_all(&self.tags)?;
+        writer.write_all(&expires.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Deserialize a digest written by `write_to` from `reader`.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, Error> {
+        let mut public = [0u8; 32];
+        reader.read_exact(&mut public)?;
+        let public = CompressedRistretto(public)
+            .decompress()
+            .ok_or_else(|| invalid_data("unable to decompress the server key"))?;
+        let mut bytes = [0u8; 8];
+        reader.read_exact(&mut bytes)?;
+        let tag_len = u64::from_le_bytes(bytes) as usize;
+        reader.read_exact(&mut bytes)?;
+        let n = u64::from_le_bytes(bytes) as usize;
+        if tag_len == 0 || tag_len > 32 {
+            return Err(invalid_data("invalid digest tag length"));
+        }
+        let size = n
+            .checked_mul(tag_len)
+            .ok_or_else(|| invalid_data("invalid digest size"))?;
+        let mut tags = Vec::new();
+        reader.take(size as u64).read_to_end(&mut tags)?;
+        if tags.len() != size {
+            return Err(invalid_data("truncated digest"));
+        }
+        reader.read_exact(&mut bytes)?;
+        let expires = match u64::from_le_bytes(bytes) {
+            0 => None,
+            ms => Some(UNIX_EPOCH + Duration::from_millis(ms)),
+        };
+        let digest = Digest {
+            public,
+            tag_len,
+            tags,
+            expires,
+        };
+        if (1..n).any(|i| digest.tag(i - 1) > digest.tag(i)) {
+            return Err(invalid_data("digest tags are not sorted"));
+        }
+        Ok(digest)
+    }
+}
+
+impl Client {
+    /// Make a client checking its items against `digest`.
+    pub fn new(digest: Digest) -> Self {
+        Self {
+            inner: voprf::Receiver::from_public(digest.public),
+            digest,
+            policy: None,
+        }
+    }
+
+    /// The digest of the server's items.
+    pub fn digest(&self) -> &Digest {
+        &self.digest
+    }
+
+    /// Reuse the PRF outputs of `cache` in subsequent runs, and add those
+    /// obtained to it, as in `voprf::Receiver::set_cache`. The cache is
+    /// invalidated first unless it holds outputs under the digest's key.
+    pub fn set_cache(&mut self, cache: voprf::OutputCache) {
+        self.inner.set_cache(cache);
+    }
+
+    /// Stop caching PRF outputs, returning the cache if any.
+    pub fn take_cache(&mut self) -> Option<voprf::OutputCache> {
+        self.inner.take_cache()
+    }
+
+    /// Set the policy consulted on each matched item, which drops the items it
+    /// does not reveal from the intersection.
+    pub fn set_policy(&mut self, policy: impl MatchPolicy + 'static) {
+        self.policy = Some(Box::new(policy));
+    }
+
+    /// Run the online phase of the protocol over `inputs`, returning those in
+    /// the intersection, and failing with `Error::PsiProtocolError` if the
+    /// server did not evaluate the PRF under the key of the digest. Fails
+    /// before querying the server with `Error::Expired` if the digest has
+    /// expired, and with `Error::SetSizeRejected` if its tags are too short
+    /// for as many items as `inputs`.
+    pub fn receive<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        utils::session(|| {
+            utils::check_expiry(self.digest.expires, "digest")?;
+            if tag_bits(self.digest.len(), inputs.len()) > 8 * self.digest.tag_len {
+                return Err(Error::SetSizeRejected(format!(
+                    "{} items exceed what the {}-byte tags of the digest allow for",
+                    inputs.len(),
+                    self.digest.tag_len
+                )));
+            }
+            let outputs = self.inner.query(inputs, channel, rng)?;
+            let digest = &self.digest;
+            let policy = &mut self.policy;
+            Ok(inputs
+                .iter()
+                .zip(outputs)
+                .filter(|(_, output)| digest.contains(&output[..digest.tag_len]))
+                .filter(|(y, _)| policy.as_mut().is_none_or(|p| p.reveal(y)))
+                .map(|(y, _)| y.clone())
+                .collect())
+        })
+    }
+}
+
+impl SemiHonest for Server {}
+impl SemiHonest for Client {}
+impl Malicious for Server {}
+impl Malicious for Client {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rand_vec_vec;
+    use scuttlebutt::{AesRng, Channel, TrackChannel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const ITEM_SIZE: usize = 8;
+
+    #[test]
+    fn test_psi() {
+        let mut rng = AesRng::new();
+        let server_inputs = rand_vec_vec(1 << 12, ITEM_SIZE, &mut rng);
+        let mut client_inputs = rand_vec_vec(1 << 5, ITEM_SIZE, &mut rng);
+        client_inputs[..1 << 4].clone_from_slice(&server_inputs[..1 << 4]);
+
+        // Offline: the server stores its key and ships the digest.
+        let server = Server::new(&mut rng);
+        let key = server.key_bytes();
+        let mut bytes = Vec::new();
+        server.digest(&server_inputs).write_to(&mut bytes).unwrap();
+        let digest = Digest::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(digest.len(), server_inputs.len());
+
+        // Online.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let server = Server::from_key_bytes(key).unwrap();
+            server.serve(&mut channel, &mut rng).unwrap();
+            server.serve(&mut channel, &mut rng).unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut client = Client::new(digest);
+        let intersection = client
+            .receive(&client_inputs, &mut channel, &mut rng)
+            .unwrap();
+        // The same digest serves a second session, under a policy.
+        let denied = client_inputs[0].clone();
+        client.set_policy(move |item: &[u8]| item != &denied[..]);
+        let filtered = client
+            .receive(&client_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection, &client_inputs[..1 << 4]);
+        assert_eq!(filtered, &client_inputs[1..1 << 4]);
+    }
+
+    #[test]
+    fn test_cache() {
+        let mut rng = AesRng::new();
+        let server_inputs = rand_vec_vec(1 << 10, ITEM_SIZE, &mut rng);
+        let mut day1 = rand_vec_vec(100, ITEM_SIZE, &mut rng);
+        day1[..50].clone_from_slice(&server_inputs[..50]);
+        // The next day, 90 of the items are the same.
+        let mut day2 = day1[10..].to_vec();
+        day2.extend(rand_vec_vec(10, ITEM_SIZE, &mut rng));
+        let server = Server::new(&mut rng);
+        let digest = server.digest(&server_inputs);
+        let rotated = Server::new(&mut rng);
+        let rotated_digest = rotated.digest(&server_inputs);
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            server.serve(&mut channel, &mut rng).unwrap();
+            server.serve(&mut channel, &mut rng).unwrap();
+            rotated.serve(&mut channel, &mut rng).unwrap();
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = TrackChannel::new(reader, writer);
+        let mut client = Client::new(digest.clone());
+        client.set_cache(voprf::OutputCache::new());
+        let intersection = client.receive(&day1, &mut channel, &mut rng).unwrap();
+        assert_eq!(intersection, &day1[..50]);
+
+        // The cache is stored overnight, and only the new items are queried.
+        let mut bytes = Vec::new();
+        client.take_cache().unwrap().write_to(&mut bytes).unwrap();
+        let cache = voprf::OutputCache::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(cache.len(), 100);
+        let mut client = Client::new(digest);
+        client.set_cache(cache);
+        let before = channel.bytes_written();
+        let intersection = client.receive(&day2, &mut channel, &mut rng).unwrap();
+        assert_eq!(intersection, &day1[10..50]);
+        assert_eq!(channel.bytes_written() - before, 8 + 10 * 32);
+
+        // Re-keying invalidates the cache.
+        let cache = client.take_cache().unwrap();
+        assert_eq!(cache.len(), 110);
+        let mut client = Client::new(rotated_digest);
+        client.set_cache(cache);
+        let before = channel.bytes_written();
+        let intersection = client.receive(&day2, &mut channel, &mut rng).unwrap();
+        assert_eq!(intersection, &day1[10..50]);
+        assert_eq!(channel.bytes_written() - before, 8 + 100 * 32);
+        assert_eq!(client.take_cache().unwrap().len(), 100);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let mut rng = AesRng::new();
+        let inputs = rand_vec_vec(1 << 4, ITEM_SIZE, &mut rng);
+        let digest = Server::new(&mut rng).digest(&inputs);
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            // A server answering under a key other than the digest's.
+            let _ = Server::new(&mut rng).serve(&mut channel, &mut rng);
+        });
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        match Client::new(digest).receive(&inputs, &mut channel, &mut rng) {
+            Err(Error::PsiProtocolError(_)) => (),
+            _ => panic!("the client accepted the wrong key"),
+        }
+        drop(channel);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut rng = AesRng::new();
+        let inputs = rand_vec_vec(1 << 4, ITEM_SIZE, &mut rng);
+        let mut server = Server::new(&mut rng);
+        let expires = UNIX_EPOCH + Duration::from_millis(1_000_000);
+        server.set_expiry(Some(expires));
+        let mut bytes = Vec::new();
+        server.digest(&inputs).write_to(&mut bytes).unwrap();
+        let digest = Digest::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(digest.expires(), Some(expires));
+        // Neither party runs once the key has expired.
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let reader = BufReader::new(sender.try_clone().unwrap());
+        let writer = BufWriter::new(sender);
+        let mut channel = Channel::new(reader, writer);
+        match server.serve(&mut channel, &mut rng) {
+            Err(Error::Expired(_)) => (),
+            _ => panic!("served under an expired key"),
+        }
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        match Client::new(digest).receive(&inputs, &mut channel, &mut rng) {
+            Err(Error::Expired(_)) => (),
+            _ => panic!("queried an expired digest"),
+        }
+    }
+
+    #[test]
+    fn test_client_set_size() {
+        let mut rng = AesRng::new();
+        let inputs = rand_vec_vec(1 << 4, ITEM_SIZE, &mut rng);
+        let mut server = Server::new(&mut rng);
+        assert_eq!(server.digest(&inputs).tag_len, 9);
+        // Tags for clients of up to 2^8 items need 40 + 4 + 8 bits.
+        server.set_max_queries(1 << 8);
+        let digest = server.digest(&inputs);
+        assert_eq!(digest.tag_len, 7);
+        let (sender, _receiver) = UnixStream::pair().unwrap();
+        let reader = BufReader::new(sender.try_clone().unwrap());
+        let writer = BufWriter::new(sender);
+        let mut channel = Channel::new(reader, writer);
+        let queries = rand_vec_vec((1 << 12) + 1, ITEM_SIZE, &mut rng);
+        match Client::new(digest).receive(&queries, &mut channel, &mut rng) {
+            Err(Error::SetSizeRejected(_)) => (),
+            _ => panic!("queried a digest with too short tags"),
+        }
+    }
+
+    #[test]
+    fn test_malformed_digest() {
+        let mut rng = AesRng::new();
+        let inputs = rand_vec_vec(1 << 4, ITEM_SIZE, &mut rng);
+        let mut bytes = Vec::new();
+        Server::new(&mut rng)
+            .digest(&inputs)
+            .write_to(&mut bytes)
+            .unwrap();
+        assert!(Digest::read_from(&mut &bytes[..bytes.len() - 8]).is_err());
+        // Swap the first two tags.
+        let tag_len = tag_len(inputs.len(), voprf::DEFAULT_MAX_QUERIES);
+        let (a, b) = bytes[48..].split_at_mut(tag_len);
+        a.swap_with_slice(&mut b[..tag_len]);
+        assert!(Digest::read_from(&mut bytes.as_slice()).is_err());
+    }
+}
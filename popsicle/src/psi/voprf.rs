@@ -0,0 +1,735 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Implementation of a maliciously secure two-party PSI protocol built from
+//! the verifiable 2HashDH oblivious PRF of Jarecki-Kiayias-Krawczyk (cf.
+//! <https://eprint.iacr.org/2014/650>).
+//!
+//! The sender holds a key `k` and publishes `K = k·G`. The PRF on `x` is
+//! `F(x) = H₂(x, k·H₁(x))`, where `H₁` hashes to the Ristretto group. The
+//! receiver obtains `F(y)` for each of its inputs `y` by sending `r·H₁(y)` for
+//! a random `r`, which the sender raises to `k` and the receiver unblinds.
+//! The sender proves with a batched Chaum-Pedersen proof that it used the
+//! same `k` as in `K` for every element, so it cannot answer some of the
+//! receiver's queries under a different key to learn whether they are in the
+//! intersection. It then sends `F(x)` for each of its own inputs, in random
+//! order, and the receiver outputs the inputs whose PRF values appear.
+//!
+//! A malicious receiver learns nothing beyond the PRF on the inputs it queried
+//! — one query per element it sends — under the one-more Diffie-Hellman
+//! assumption, in the random oracle model. The protocol costs a few scalar
+//! multiplications per element, so it is much slower than PSZ, and is best
+//! suited to small and medium sets.
+//!
+//! The sender evaluates at most `DEFAULT_MAX_QUERIES` elements per run unless
+//! set otherwise with `Sender::set_max_queries`, and the receiver reads at
+//! most as many PRF values, rejecting larger counts before allocating.
+//!
+//! A receiver querying overlapping sets under the same key, in repeated
+//! sessions, can keep the outputs it obtained in an `OutputCache`, and only
+//! queries the items the cache misses. The sender then learns how many items
+//! were missed.
+
+use crate::{
+    psi::{PsiReceiver, PsiSender},
+    sizes,
+    utils,
+    Error,
+};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_TABLE,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::MultiscalarMul,
+};
+use rand::{seq::SliceRandom, CryptoRng, RngCore};
+use scuttlebutt::{memtrack, AbstractChannel, Malicious, SemiHonest};
+use sha2::{Digest, Sha256, Sha512};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Write},
+};
+
+/// The most elements a sender evaluates, and a receiver reads the PRF values
+/// of, in one run unless set otherwise. Also bounds the parties of `dh`.
+pub const DEFAULT_MAX_QUERIES: usize = 1 << 24;
+
+// Domain separators for the hash functions.
+const HASH_TO_GROUP: &[u8] = b"popsicle::voprf::h1";
+const HASH_TO_OUTPUT: &[u8] = b"popsicle::voprf::h2";
+const HASH_TO_COEFFICIENTS: &[u8] = b"popsicle::voprf::batch";
+const HASH_TO_CHALLENGE: &[u8] = b"popsicle::voprf::challenge";
+const HASH_TO_CACHE_KEY: &[u8] = b"popsicle::voprf::cache";
+
+// `H₁`.
+pub(crate) fn hash_to_group(x: &[u8]) -> RistrettoPoint {
+    let mut h = Sha512::new();
+    h.input(HASH_TO_GROUP);
+    h.input(x);
+    RistrettoPoint::from_hash(h)
+}
+
+// `H₂`.
+pub(crate) fn hash_to_output(x: &[u8], p: &RistrettoPoint) -> [u8; 32] {
+    let mut h = Sha256::new();
+    h.input(HASH_TO_OUTPUT);
+    h.input((x.len() as u64).to_le_bytes());
+    h.input(x);
+    h.input(p.compress().as_bytes());
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&h.result());
+    out
+}
+
+// Derive the coefficients with which the statements `log_G(K) = log_B(Z)`
+// for each pair in `blinded` and `evaluated` are batched into one.
+fn coefficients(
+    key: &RistrettoPoint,
+    blinded: &[RistrettoPoint],
+    evaluated: &[RistrettoPoint],
+) -> Vec<Scalar> {
+    let mut h = Sha256::new();
+    h.input(HASH_TO_COEFFICIENTS);
+    h.input(key.compress().as_bytes());
+    for p in blinded.iter().chain(evaluated) {
+        h.input(p.compress().as_bytes());
+    }
+    let seed = h.result();
+    (0..blinded.len())
+        .map(|i| {
+            let mut h = Sha512::new();
+            h.input(seed);
+            h.input((i as u64).to_le_bytes());
+            Scalar::from_hash(h)
+        })
+        .collect()
+}
+
+fn challenge(points: &[&RistrettoPoint]) -> Scalar {
+    let mut h = Sha512::new();
+    h.input(HASH_TO_CHALLENGE);
+    for p in points {
+        h.input(p.compress().as_bytes());
+    }
+    Scalar::from_hash(h)
+}
+
+// The key of the output of `x` in an `OutputCache`.
+fn cache_key(x: &[u8]) -> [u8; 32] {
+    let mut h = Sha256::new();
+    h.input(HASH_TO_CACHE_KEY);
+    h.input(x);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&h.result());
+    out
+}
+
+// Prove that `log_G(K) = log_M(Z)`, for `K = key·G` and `M` and `Z` the
+// random combinations of the blinded and evaluated elements, returning the
+// challenge and the response.
+pub(crate) fn prove<RNG: CryptoRng + RngCore>(
+    key: &Scalar,
+    public: &RistrettoPoint,
+    blinded: &[RistrettoPoint],
+    evaluated: &[RistrettoPoint],
+    rng: &mut RNG,
+) -> (Scalar, Scalar) {
+    let cs = coefficients(public, blinded, evaluated);
+    let m = RistrettoPoint::multiscalar_mul(&cs, blinded);
+    let z = RistrettoPoint::multiscalar_mul(&cs, evaluated);
+    let t = Scalar::random(rng);
+    let a1 = &t * &RISTRETTO_BASEPOINT_TABLE;
+    let a2 = t * m;
+    let e = challenge(&[public, &m, &z, &a1, &a2]);
+    (e, t - e * key)
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, msg))
+}
+
+fn read_scalar<C: AbstractChannel>(channel: &mut C) -> Result<Scalar, Error> {
+    let mut bytes = [0u8; 32];
+    channel.read_bytes(&mut bytes)?;
+    Scalar::from_canonical_bytes(bytes)
+        .ok_or_else(|| Error::PsiProtocolError("non-canonical scalar".to_string()))
+}
+
+/// Private set intersection sender, secure against malicious receivers.
+pub struct Sender {
+    key: Scalar,
+    public: RistrettoPoint,
+    max_queries: usize,
+}
+
+/// Private set intersection receiver, secure against malicious senders.
+pub struct Receiver {
+    public: RistrettoPoint,
+    cache: Option<OutputCache>,
+    max_outputs: usize,
+    declared_size: Option<usize>,
+}
+
+/// The PRF outputs a receiver obtained, keyed by a hash of each item, for
+/// the receiver of a later session under the same key to reuse.
+///
+/// A cache is bound to the published key of the sender whose outputs it
+/// holds: handing it to a receiver of another key, as after the sender
+/// re-keys, invalidates it first. The cache holds the receiver's PRF outputs
+/// in the clear, and must be stored as securely as its inputs.
+#[derive(Clone, Default)]
+pub struct OutputCache {
+    public: Option<CompressedRistretto>,
+    outputs: HashMap<[u8; 32], [u8; 32]>,
+}
+
+impl OutputCache {
+    /// Make an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of outputs in the cache.
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Whether the cache holds no outputs.
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Drop every output in the cache.
+    pub fn invalidate(&mut self) {
+        self.outputs.clear();
+    }
+
+    // Bind the cache to the key `public`, invalidating it if it holds outputs
+    // under another key.
+    fn bind(&mut self, public: &RistrettoPoint) {
+        let public = public.compress();
+        if self.public != Some(public) {
+            self.invalidate();
+            self.public = Some(public);
+        }
+    }
+
+    /// Serialize the cache to `writer`: the key it is bound to, or zeros if
+    /// none, then the number of outputs and the outputs with their keys.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let public = self.public.unwrap_or_default();
+        writer.write_all(public.as_bytes())?;
+        writer.write_all(&(self.outputs.len() as u64).to_le_bytes())?;
+        for (key, output) in &self.outputs {
+            writer.write_all(key)?;
+            writer.write_all(output)?;
+        }
+        Ok(())
+    }
+
+    /// Deserialize a cache written by `write_to` from `reader`.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, Error> {
+        let mut public = [0u8; 32];
+        reader.read_exact(&mut public)?;
+        let public = match CompressedRistretto(public) {
+            p if p == CompressedRistretto::default() => None,
+            p => {
+                p.decompress()
+                    .ok_or_else(|| invalid_data("unable to decompress the cache key"))?;
+                Some(p)
+            }
+        };
+        let mut bytes = [0u8; 8];
+        reader.read_exact(&mut bytes)?;
+        let n = u64::from_le_bytes(bytes);
+        let mut outputs = HashMap::new();
+        for _ in 0..n {
+            let (mut key, mut output) = ([0u8; 32], [0u8; 32]);
+            reader.read_exact(&mut key)?;
+            reader.read_exact(&mut output)?;
+            outputs.insert(key, output);
+        }
+        if public.is_none() && !outputs.is_empty() {
+            return Err(invalid_data("cached outputs are bound to no key"));
+        }
+        Ok(Self { public, outputs })
+    }
+}
+
+// The outputs never show up in debug output.
+impl std::fmt::Debug for OutputCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("OutputCache")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Sender {
+    /// Initialize the PSI sender, publishing its key to the receiver.
+    pub fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        let key = Scalar::random(rng);
+        let public = &key * &RISTRETTO_BASEPOINT_TABLE;
+        channel.write_pt(&public)?;
+        channel.flush()?;
+        Ok(Self {
+            key,
+            public,
+            max_queries: DEFAULT_MAX_QUERIES,
+        })
+    }
+
+    /// Evaluate up to `max_queries` elements of the receiver in subsequent
+    /// runs, `DEFAULT_MAX_QUERIES` unless set, failing runs querying more.
+    pub fn set_max_queries(&mut self, max_queries: usize) {
+        self.max_queries = max_queries;
+    }
+
+    pub(super) fn max_queries(&self) -> usize {
+        self.max_queries
+    }
+
+    /// Run the PSI protocol over `inputs`.
+    pub fn send<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        utils::session(|| {
+            self.evaluate(channel, rng)?;
+            let mut outputs = memtrack::phase("voprf::sender::encode", || {
+                inputs.iter().map(|x| self.output(x)).collect::<Vec<_>>()
+            });
+            outputs.shuffle(rng);
+            channel.write_usize(outputs.len())?;
+            for output in &outputs {
+                channel.write_bytes(output)?;
+            }
+            channel.flush()?;
+            Ok(())
+        })
+    }
+
+    // Evaluate the PRF on the receiver's blinded elements, and prove that it
+    // was evaluated under the published key.
+    pub(super) fn evaluate<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &self,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        let n = channel.read_usize()?;
+        if n > self.max_queries {
+            return Err(Error::PsiProtocolError(format!(
+                "the receiver queried {} elements, more than the limit of {}",
+                n, self.max_queries
+            )));
+        }
+        let blinded = (0..n)
+            .map(|_| channel.read_pt())
+            .collect::<Result<Vec<_>, _>>()?;
+        let evaluated = memtrack::phase("voprf::sender::evaluate", || {
+            blinded.iter().map(|b| self.key * b).collect::<Vec<_>>()
+        });
+        for z in &evaluated {
+            channel.write_pt(z)?;
+        }
+        let (e, s) = prove(&self.key, &self.public, &blinded, &evaluated, rng);
+        channel.write_bytes(e.as_bytes())?;
+        channel.write_bytes(s.as_bytes())?;
+        Ok(())
+    }
+
+    // The PRF on `x`.
+    pub(super) fn output(&self, x: &[u8]) -> [u8; 32] {
+        hash_to_output(x, &(self.key * hash_to_group(x)))
+    }
+
+    // The key, for storage.
+    pub(super) fn key(&self) -> &Scalar {
+        &self.key
+    }
+
+    // A sender holding `key`.
+    pub(super) fn from_key(key: Scalar) -> Self {
+        let public = &key * &RISTRETTO_BASEPOINT_TABLE;
+        Self {
+            key,
+            public,
+            max_queries: DEFAULT_MAX_QUERIES,
+        }
+    }
+
+    // The published key.
+    pub(super) fn public(&self) -> &RistrettoPoint {
+        &self.public
+    }
+}
+
+impl Receiver {
+    /// Initialize the PSI receiver, reading the sender's key.
+    pub fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        _: &mut RNG,
+    ) -> Result<Self, Error> {
+        let public = channel.read_pt()?;
+        Ok(Self::from_public(public))
+    }
+
+    // A receiver of PRF values under the published key `public`.
+    pub(super) fn from_public(public: RistrettoPoint) -> Self {
+        Self {
+            public,
+            cache: None,
+            max_outputs: DEFAULT_MAX_QUERIES,
+            declared_size: None,
+        }
+    }
+
+    /// Read up to `max_outputs` PRF values of the sender's inputs in
+    /// subsequent runs, `DEFAULT_MAX_QUERIES` unless set, failing runs
+    /// sending more.
+    pub fn set_max_outputs(&mut self, max_outputs: usize) {
+        self.max_outputs = max_outputs;
+    }
+
+    /// Bind subsequent runs to the size the sender declared with
+    /// `sizes::exchange`, failing with `Error::SetSizeRejected` those in which
+    /// it sends more PRF values, or to no size if `None`, the default.
+    pub fn set_declared_size(&mut self, declared: Option<usize>) {
+        self.declared_size = declared;
+    }
+
+    /// Reuse the outputs of `cache` in subsequent runs, and add those
+    /// obtained to it. The cache is invalidated first unless it is bound to
+    /// the sender's key.
+    pub fn set_cache(&mut self, mut cache: OutputCache) {
+        cache.bind(&self.public);
+        self.cache = Some(cache);
+    }
+
+    /// Stop caching outputs, returning the cache if any, e.g. to hand it to
+    /// the receiver of the next session.
+    pub fn take_cache(&mut self) -> Option<OutputCache> {
+        self.cache.take()
+    }
+
+    /// Run the PSI protocol over `inputs`, failing with
+    /// `Error::PsiProtocolError` if the sender did not evaluate the PRF
+    /// honestly.
+    pub fn receive<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let intersection = self.receive_indices(inputs, channel, rng)?;
+        Ok(utils::select(inputs, &intersection))
+    }
+
+    /// Run the PSI protocol over `inputs`, as `receive`, returning the indices
+    /// in `inputs` of those in the intersection rather than the items.
+    pub fn receive_indices<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<usize>, Error> {
+        utils::session(|| {
+            let outputs = self.query(inputs, channel, rng)?;
+            let n = channel.read_usize()?;
+            if n > self.max_outputs {
+                return Err(Error::PsiProtocolError(format!(
+                    "the sender sent {} PRF values, more than the limit of {}",
+                    n, self.max_outputs
+                )));
+            }
+            sizes::check_peer(n, self.declared_size)?;
+            let mut theirs = HashSet::with_capacity(n);
+            for _ in 0..n {
+                let mut output = [0u8; 32];
+                channel.read_bytes(&mut output)?;
+                theirs.insert(output);
+            }
+            Ok((0..inputs.len())
+                .filter(|&i| theirs.contains(&outputs[i]))
+                .collect())
+        })
+    }
+
+    // Obtain the PRF on each of `inputs`, from the cache if it holds it and
+    // from the sender otherwise.
+    pub(super) fn query<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<[u8; 32]>, Error> {
+        let keys = inputs.iter().map(|y| cache_key(y)).collect::<Vec<_>>();
+        let cached = match &self.cache {
+            None => return self.query_sender(inputs, channel, rng),
+            Some(cache) => keys
+                .iter()
+                .map(|key| cache.outputs.get(key).copied())
+                .collect::<Vec<_>>(),
+        };
+        let missed = inputs
+            .iter()
+            .zip(&cached)
+            .filter(|(_, output)| output.is_none())
+            .map(|(y, _)| y)
+            .collect::<Vec<_>>();
+        let mut obtained = self.query_sender(&missed, channel, rng)?.into_iter();
+        let cache = self
+            .cache
+            .as_mut()
+            .ok_or_else(|| utils::violated("a cached query has a cache"))?;
+        let mut outputs = Vec::with_capacity(inputs.len());
+        for (output, key) in cached.into_iter().zip(keys) {
+            let output = match output {
+                Some(output) => output,
+                None => {
+                    let output = obtained
+                        .next()
+                        .ok_or_else(|| utils::violated("every missed item is queried"))?;
+                    cache.outputs.insert(key, output);
+                    output
+                }
+            };
+            outputs.push(output);
+        }
+        Ok(outputs)
+    }
+
+    // Obtain the PRF on each of `inputs` from the sender, checking its proof.
+    fn query_sender<Y: AsRef<[u8]>, C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &self,
+        inputs: &[Y],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<[u8; 32]>, Error> {
+        let blinds = (0..inputs.len())
+            .map(|_| Scalar::random(rng))
+            .collect::<Vec<_>>();
+        let blinded = memtrack::phase("voprf::receiver::blind", || {
+            inputs
+                .iter()
+                .zip(&blinds)
+                .map(|(y, r)| r * hash_to_group(y.as_ref()))
+                .collect::<Vec<_>>()
+        });
+        channel.write_usize(blinded.len())?;
+        for b in &blinded {
+            channel.write_pt(b)?;
+        }
+        channel.flush()?;
+
+        let evaluated = (0..inputs.len())
+            .map(|_| channel.read_pt())
+            .collect::<Result<Vec<_>, _>>()?;
+        let e = read_scalar(channel)?;
+        let s = read_scalar(channel)?;
+        let cs = coefficients(&self.public, &blinded, &evaluated);
+        let m = RistrettoPoint::multiscalar_mul(&cs, &blinded);
+        let z = RistrettoPoint::multiscalar_mul(&cs, &evaluated);
+        let a1 = &s * &RISTRETTO_BASEPOINT_TABLE + e * self.public;
+        let a2 = s * m + e * z;
+        if challenge(&[&self.public, &m, &z, &a1, &a2]) != e {
+            return Err(Error::PsiProtocolError(
+                "the sender's proof of key consistency is invalid".to_string(),
+            ));
+        }
+
+        Ok(memtrack::phase("voprf::receiver::unblind", || {
+            inputs
+                .iter()
+                .zip(blinds)
+                .zip(&evaluated)
+                .map(|((y, r), z)| hash_to_output(y.as_ref(), &(r.invert() * z)))
+                .collect()
+        }))
+    }
+}
+
+impl PsiSender for Sender {
+    const PROTOCOL: &'static str = "voprf";
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        Sender::init(channel, rng)
+    }
+
+    fn send<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        Sender::send(self, inputs, channel, rng)
+    }
+}
+
+impl PsiReceiver for Receiver {
+    const PROTOCOL: &'static str = "voprf";
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self, Error> {
+        Receiver::init(channel, rng)
+    }
+
+    fn receive<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        Receiver::receive(self, inputs, channel, rng)
+    }
+
+    fn receive_indices<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<usize>, Error> {
+        Receiver::receive_indices(self, inputs, channel, rng)
+    }
+}
+
+impl SemiHonest for Sender {}
+impl SemiHonest for Receiver {}
+impl Malicious for Sender {}
+impl Malicious for Receiver {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rand_vec_vec;
+    use scuttlebutt::{AesRng, Channel};
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    const ITEM_SIZE: usize = 8;
+    const SET_SIZE: usize = 1 << 8;
+
+    #[test]
+    fn test_psi() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let sender_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        receiver_inputs[..SET_SIZE / 2].clone_from_slice(&sender_inputs[SET_SIZE / 2..]);
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        let intersection = psi
+            .receive(&receiver_inputs, &mut channel, &mut rng)
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(intersection, &receiver_inputs[..SET_SIZE / 2]);
+    }
+
+    #[test]
+    fn test_inconsistent_key() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            // Answer with a key other than the published one.
+            psi.key = Scalar::random(&mut rng);
+            let _ = psi.send(&[], &mut channel, &mut rng);
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        match psi.receive(&inputs, &mut channel, &mut rng) {
+            Err(Error::PsiProtocolError(_)) => (),
+            _ => panic!("the receiver accepted an inconsistent key"),
+        }
+        drop(channel);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_max_queries() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            psi.set_max_queries(SET_SIZE - 1);
+            psi.send(&[], &mut channel, &mut rng)
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        assert!(psi.receive(&inputs, &mut channel, &mut rng).is_err());
+        match handle.join().unwrap() {
+            Err(Error::PsiProtocolError(_)) => (),
+            _ => panic!("the sender evaluated more queries than its limit"),
+        }
+    }
+
+    #[test]
+    fn test_declared_size() {
+        let mut rng = AesRng::new();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        let handle = std::thread::spawn(move || {
+            let mut rng = AesRng::new();
+            let reader = BufReader::new(sender.try_clone().unwrap());
+            let writer = BufWriter::new(sender);
+            let mut channel = Channel::new(reader, writer);
+            let mut psi = Sender::init(&mut channel, &mut rng).unwrap();
+            let _ = psi.send(&inputs, &mut channel, &mut rng);
+        });
+        let mut rng = AesRng::new();
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = Channel::new(reader, writer);
+        let mut psi = Receiver::init(&mut channel, &mut rng).unwrap();
+        psi.set_declared_size(Some(SET_SIZE - 1));
+        let inputs = rand_vec_vec(SET_SIZE, ITEM_SIZE, &mut rng);
+        match psi.receive(&inputs, &mut channel, &mut rng) {
+            Err(Error::SetSizeRejected(_)) => (),
+            _ => panic!("the receiver accepted more items than declared"),
+        }
+        drop(channel);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,577 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Restarting the phases of a PSZ run after transient failures.
+//!
+//! `send` and `receive` run `psz` over connections made by a closure, and
+//! when one fails, consult a `RetryPolicy` on whether to reconnect and
+//! restart the `Phase` it failed in, rather than the whole run. A failed
+//! base OT is redone from scratch. Once it is done each party keeps a
+//! session checkpoint, so that a failed OPRF extension restarts from a
+//! resumed session, with fresh randomness, and the run checkpoints of
+//! `Sender::send_checkpointed` and `Receiver::receive_checkpointed`, so that
+//! a failed mask exchange picks up after the last tag the receiver got.
+//!
+//! Both parties must run these functions, with policies giving up after the
+//! same failures, as they start each connection by agreeing on which
+//! checkpoints both still hold. Sessions start with `init_with_level`, and
+//! since a session checkpoint does not save the `SecurityParams`, the
+//! `MasksizeBounds` or the `BinHash`, resumed sessions get those of the
+//! `SecurityLevel` again, and every session, new or resumed, is passed to
+//! the `configure` closure, e.g. to set its `BinHash`, before it runs.
+
+use crate::{
+    psi::psz::{ReceiveCheckpoint, Receiver, SendCheckpoint, Sender},
+    security::SecurityLevel,
+    Error,
+};
+use rand::{CryptoRng, RngCore};
+use scuttlebutt::{utils as scutils, AbstractChannel};
+use std::time::Duration;
+
+/// A phase of a run which can be restarted on its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// The base OTs setting up the session.
+    BaseOt,
+    /// The OPRF extension, up to the sender holding its seeds.
+    OprfExtension,
+    /// The sender sending the masks of its items.
+    MaskExchange,
+}
+
+impl Phase {
+    fn index(self) -> usize {
+        match self {
+            Phase::BaseOt => 0,
+            Phase::OprfExtension => 1,
+            Phase::MaskExchange => 2,
+        }
+    }
+}
+
+/// Decides whether to restart a phase which failed. Any
+/// `FnMut(Phase, usize, &Error) -> Option<Duration>` closure is a policy.
+pub trait RetryPolicy {
+    /// Whether to restart `phase` after its `attempt`-th failure, counted
+    /// from 1 for each phase, with `error`: how long to wait before
+    /// reconnecting, or `None` to give up.
+    fn retry(&mut self, phase: Phase, attempt: usize, error: &Error) -> Option<Duration>;
+}
+
+impl<F: FnMut(Phase, usize, &Error) -> Option<Duration>> RetryPolicy for F {
+    fn retry(&mut self, phase: Phase, attempt: usize, error: &Error) -> Option<Duration> {
+        self(phase, attempt, error)
+    }
+}
+
+/// Restarts each phase up to `max_attempts` times after errors of a transient
+/// `ErrorClass`, doubling the wait from `initial` up to `max` each time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    /// The number of restarts of each phase.
+    pub max_attempts: usize,
+    /// The wait before the first restart.
+    pub initial: Duration,
+    /// The longest wait before a restart.
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy for Backoff {
+    fn retry(&mut self, _phase: Phase, attempt: usize, error: &Error) -> Option<Duration> {
+        if attempt > self.max_attempts || !error.class().is_transient() {
+            return None;
+        }
+        let shift = (attempt - 1).min(31) as u32;
+        Some(self.initial.saturating_mul(1 << shift).min(self.max))
+    }
+}
+
+// A party of a checkpointed run.
+trait Party: Sized {
+    type Run;
+    type Output;
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+        level: SecurityLevel,
+    ) -> Result<Self, Error>;
+
+    // Resume `session`, with the settings of `level` it does not save.
+    fn resume<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        session: &[u8],
+        channel: &mut C,
+        rng: &mut RNG,
+        level: SecurityLevel,
+    ) -> Result<Self, Error>;
+
+    fn checkpoint(&self) -> Vec<u8>;
+
+    fn run<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        run: &mut Option<Self::Run>,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Self::Output, Error>;
+}
+
+impl Party for Sender {
+    type Run = SendCheckpoint;
+    type Output = ();
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+        level: SecurityLevel,
+    ) -> Result<Self, Error> {
+        Sender::init_with_level(channel, rng, level)
+    }
+
+    fn resume<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        session: &[u8],
+        channel: &mut C,
+        rng: &mut RNG,
+        level: SecurityLevel,
+    ) -> Result<Self, Error> {
+        let mut psi = Sender::resume(session, channel, rng)?;
+        level.check_features(psi.features())?;
+        psi.set_security(level.params());
+        psi.set_masksize_bounds(level.masksize_bounds());
+        Ok(psi)
+    }
+
+    fn checkpoint(&self) -> Vec<u8> {
+        Sender::checkpoint(self)
+    }
+
+    fn run<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        run: &mut Option<SendCheckpoint>,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<(), Error> {
+        self.send_checkpointed(inputs, run, channel, rng)
+    }
+}
+
+impl Party for Receiver {
+    type Run = ReceiveCheckpoint;
+    type Output = Vec<Vec<u8>>;
+
+    fn init<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        channel: &mut C,
+        rng: &mut RNG,
+        level: SecurityLevel,
+    ) -> Result<Self, Error> {
+        Receiver::init_with_level(channel, rng, level)
+    }
+
+    fn resume<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        session: &[u8],
+        channel: &mut C,
+        rng: &mut RNG,
+        level: SecurityLevel,
+    ) -> Result<Self, Error> {
+        let mut psi = Receiver::resume(session, channel, rng)?;
+        level.check_features(psi.features())?;
+        psi.set_security(level.params());
+        psi.set_masksize_bounds(level.masksize_bounds());
+        Ok(psi)
+    }
+
+    fn checkpoint(&self) -> Vec<u8> {
+        Receiver::checkpoint(self)
+    }
+
+    fn run<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        inputs: &[Vec<u8>],
+        run: &mut Option<ReceiveCheckpoint>,
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        self.receive_checkpointed(inputs, run, channel, rng)
+    }
+}
+
+// What a party keeps across connections.
+struct State<P: Party> {
+    session: Option<Vec<u8>>,
+    run: Option<P::Run>,
+}
+
+impl<P: Party> State<P> {
+    // The phase a failure now restarts.
+    fn phase(&self) -> Phase {
+        match (&self.session, &self.run) {
+            (None, _) => Phase::BaseOt,
+            (Some(_), None) => Phase::OprfExtension,
+            (Some(_), Some(_)) => Phase::MaskExchange,
+        }
+    }
+
+    fn drop_session(&mut self) {
+        if let Some(mut session) = self.session.take() {
+            scutils::erase(&mut session);
+        }
+        self.run = None;
+    }
+
+    fn attempt<C: AbstractChannel, RNG: CryptoRng + RngCore>(
+        &mut self,
+        level: SecurityLevel,
+        inputs: &[Vec<u8>],
+        configure: &mut impl FnMut(&mut P),
+        channel: &mut C,
+        rng: &mut RNG,
+    ) -> Result<P::Output, Error> {
+        // Keep only the checkpoints both parties hold, so that they restart
+        // the same phase.
+        channel.write_bool(self.session.is_some())?;
+        channel.write_bool(self.run.is_some())?;
+        channel.flush()?;
+        let (session, run) = (channel.read_bool()?, channel.read_bool()?);
+        if !session {
+            self.drop_session();
+        } else if !run {
+            self.run = None;
+        }
+        let mut psi = match &self.session {
+            Some(session) => P::resume(session, channel, rng, level)?,
+            None => {
+                let psi = P::init(channel, rng, level)?;
+                self.session = Some(psi.checkpoint());
+                psi
+            }
+        };
+        configure(&mut psi);
+        psi.run(inputs, &mut self.run, channel, rng)
+    }
+}
+
+fn drive<P, C, RNG>(
+    policy: &mut impl RetryPolicy,
+    level: SecurityLevel,
+    inputs: &[Vec<u8>],
+    mut connect: impl FnMut() -> Result<C, Error>,
+    mut configure: impl FnMut(&mut P),
+    rng: &mut RNG,
+) -> Result<P::Output, Error>
+where
+    P: Party,
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    let mut state = State::<P> {
+        session: None,
+        run: None,
+    };
+    let mut attempts = [0; 3];
+    loop {
+        let result = connect().and_then(|mut channel| {
+            state.attempt(level, inputs, &mut configure, &mut channel, rng)
+        });
+        let error = match result {
+            Ok(output) => {
+                state.drop_session();
+                return Ok(output);
+            }
+            Err(e) => e,
+        };
+        let phase = state.phase();
+        attempts[phase.index()] += 1;
+        match policy.retry(phase, attempts[phase.index()], &error) {
+            Some(wait) => std::thread::sleep(wait),
+            None => {
+                state.drop_session();
+                return Err(error);
+            }
+        }
+    }
+}
+
+/// Run the PSZ sender over `inputs` at `level`, on channels made by
+/// `connect`, restarting the phases which fail as `policy` decides. Every
+/// session is passed to `configure` before it runs. Must be paired with
+/// `receive`, at the same level.
+pub fn send<C, RNG>(
+    policy: &mut impl RetryPolicy,
+    level: SecurityLevel,
+    inputs: &[Vec<u8>],
+    connect: impl FnMut() -> Result<C, Error>,
+    configure: impl FnMut(&mut Sender),
+    rng: &mut RNG,
+) -> Result<(), Error>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    drive(policy, level, inputs, connect, configure, rng)
+}
+
+/// Run the PSZ receiver over `inputs` at `level`, on channels made by
+/// `connect`, restarting the phases which fail as `policy` decides, and
+/// returning the intersection. Every session is passed to `configure` before
+/// it runs. Must be paired with `send`, at the same level.
+pub fn receive<C, RNG>(
+    policy: &mut impl RetryPolicy,
+    level: SecurityLevel,
+    inputs: &[Vec<u8>],
+    connect: impl FnMut() -> Result<C, Error>,
+    configure: impl FnMut(&mut Receiver),
+    rng: &mut RNG,
+) -> Result<Vec<Vec<u8>>, Error>
+where
+    C: AbstractChannel,
+    RNG: CryptoRng + RngCore,
+{
+    drive(policy, level, inputs, connect, configure, rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cuckoo::{BinHash, DefaultBinHash},
+        utils::rand_vec_vec,
+    };
+    use scuttlebutt::{AesRng, Block, Channel};
+    use std::{
+        collections::HashSet,
+        io::{BufReader, BufWriter, ErrorKind, Write},
+        os::unix::net::UnixStream,
+        sync::{Arc, Mutex},
+    };
+
+    const ITEM_SIZE: usize = 8;
+
+    // Fails once it has written `remaining` bytes, as if the connection
+    // dropped.
+    struct FailingWriter {
+        inner: BufWriter<UnixStream>,
+        remaining: usize,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if buf.len() > self.remaining {
+                return Err(std::io::Error::new(ErrorKind::BrokenPipe, "dropped"));
+            }
+            self.remaining -= buf.len();
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    type TestChannel = Channel<BufReader<UnixStream>, FailingWriter>;
+
+    fn channel(stream: UnixStream, remaining: usize) -> TestChannel {
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        let writer = FailingWriter {
+            inner: BufWriter::new(stream),
+            remaining,
+        };
+        Channel::new(reader, writer)
+    }
+
+    // The results of both parties, and the expected intersection.
+    type Outcome = (Result<(), Error>, Result<Vec<Vec<u8>>, Error>, Vec<Vec<u8>>);
+
+    fn run<S, R>(
+        level: SecurityLevel,
+        limits: &[(usize, usize)],
+        (mut sender_policy, sender_configure): (S, impl FnMut(&mut Sender) + Send + 'static),
+        (mut receiver_policy, receiver_configure): (R, impl FnMut(&mut Receiver)),
+    ) -> Outcome
+    where
+        S: RetryPolicy + Send + 'static,
+        R: RetryPolicy,
+    {
+        let mut rng = AesRng::new();
+        let sender_inputs = rand_vec_vec(1 << 10, ITEM_SIZE, &mut rng);
+        let mut receiver_inputs = rand_vec_vec(1 << 8, ITEM_SIZE, &mut rng);
+        receiver_inputs.extend_from_slice(&sender_inputs[100..200]);
+        // Each side of a connection drops once it has written its limit.
+        let (mut senders, mut receivers) = (Vec::new(), Vec::new());
+        for &(sender_limit, receiver_limit) in limits.iter().rev() {
+            let (sender, receiver) = UnixStream::pair().unwrap();
+            senders.push((sender, sender_limit));
+            receivers.push((receiver, receiver_limit));
+        }
+        let no_more = || Error::PsiProtocolError("no more connections".to_string());
+        let handle = std::thread::spawn(move || {
+            let connect = || {
+                let (stream, limit) = senders.pop().ok_or_else(no_more)?;
+                Ok(channel(stream, limit))
+            };
+            send(
+                &mut sender_policy,
+                level,
+                &sender_inputs,
+                connect,
+                sender_configure,
+                &mut AesRng::new(),
+            )
+        });
+        let connect = || {
+            let (stream, limit) = receivers.pop().ok_or_else(no_more)?;
+            Ok(channel(stream, limit))
+        };
+        let intersection = receive(
+            &mut receiver_policy,
+            level,
+            &receiver_inputs,
+            connect,
+            receiver_configure,
+            &mut rng,
+        );
+        (
+            handle.join().unwrap(),
+            intersection,
+            receiver_inputs[1 << 8..].to_vec(),
+        )
+    }
+
+    // Restarts every phase up to thrice right away, noting which failed.
+    fn no_wait() -> (impl RetryPolicy + Send + 'static, Arc<Mutex<Vec<Phase>>>) {
+        let phases = Arc::new(Mutex::new(Vec::new()));
+        let phases_ = phases.clone();
+        let policy = move |phase, attempt, error: &Error| {
+            phases_.lock().unwrap().push(phase);
+            if attempt <= 3 && error.class().is_transient() {
+                Some(Duration::from_millis(0))
+            } else {
+                None
+            }
+        };
+        (policy, phases)
+    }
+
+    #[test]
+    fn test_retry() {
+        // The connections drop during the base OTs, then the OPRF extension,
+        // then twice while the sender sends its masks.
+        const NONE: usize = usize::MAX;
+        let limits = [
+            (100, NONE),
+            (NONE, 30_000),
+            (5_000, NONE),
+            (10_000, NONE),
+            (NONE, NONE),
+        ];
+        let (sender_policy, sender_phases) = no_wait();
+        let (receiver_policy, receiver_phases) = no_wait();
+        let (sent, received, expected) = run(
+            SecurityLevel::Legacy,
+            &limits,
+            (sender_policy, |_: &mut Sender| ()),
+            (receiver_policy, |_: &mut Receiver| ()),
+        );
+        sent.unwrap();
+        let intersection = received.unwrap().into_iter().collect::<HashSet<_>>();
+        assert_eq!(intersection, expected.into_iter().collect::<HashSet<_>>());
+        let phases = vec![
+            Phase::BaseOt,
+            Phase::OprfExtension,
+            Phase::MaskExchange,
+            Phase::MaskExchange,
+        ];
+        assert_eq!(*sender_phases.lock().unwrap(), phases);
+        assert_eq!(*receiver_phases.lock().unwrap(), phases);
+    }
+
+    #[test]
+    fn test_give_up() {
+        let policy = Backoff {
+            max_attempts: 1,
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(1),
+        };
+        let limits = [
+            (100, usize::MAX),
+            (100, usize::MAX),
+            (usize::MAX, usize::MAX),
+        ];
+        let (sent, received, _) = run(
+            SecurityLevel::Legacy,
+            &limits,
+            (policy, |_: &mut Sender| ()),
+            (policy, |_: &mut Receiver| ()),
+        );
+        assert!(sent.is_err());
+        assert!(received.is_err());
+
+        let mut policy = Backoff::default();
+        let e = Error::from(std::io::Error::new(ErrorKind::BrokenPipe, "dropped"));
+        assert_eq!(
+            policy.retry(Phase::BaseOt, 1, &e),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            policy.retry(Phase::BaseOt, 3, &e),
+            Some(Duration::from_millis(400))
+        );
+        assert_eq!(policy.retry(Phase::BaseOt, 4, &e), None);
+        let e = Error::PsiProtocolError("mismatch".to_string());
+        assert_eq!(policy.retry(Phase::MaskExchange, 1, &e), None);
+    }
+
+    // Assigns items to the bins the default does not.
+    struct Reversed;
+
+    impl BinHash for Reversed {
+        fn bin(&self, hash: Block, hidx: usize, nbins: usize) -> usize {
+            nbins - 1 - DefaultBinHash.bin(hash, hidx, nbins)
+        }
+    }
+
+    #[test]
+    fn test_resumed_settings() {
+        // The connection drops while the sender sends its masks, so that
+        // both parties resume their session, which must again use the level
+        // and the bin hash set by `configure`.
+        let limits = [(20_000, usize::MAX), (usize::MAX, usize::MAX)];
+        let nsessions = Arc::new(Mutex::new((0, 0)));
+        let (sessions, sessions_) = (nsessions.clone(), nsessions.clone());
+        let sender_configure = move |sender: &mut Sender| {
+            sender.set_bin_hash(Reversed);
+            sessions.lock().unwrap().0 += 1;
+        };
+        let receiver_configure = move |receiver: &mut Receiver| {
+            receiver.set_bin_hash(Reversed);
+            sessions_.lock().unwrap().1 += 1;
+        };
+        let (sender_policy, sender_phases) = no_wait();
+        let (receiver_policy, _) = no_wait();
+        let (sent, received, expected) = run(
+            SecurityLevel::Standard,
+            &limits,
+            (sender_policy, sender_configure),
+            (receiver_policy, receiver_configure),
+        );
+        sent.unwrap();
+        let intersection = received.unwrap().into_iter().collect::<HashSet<_>>();
+        assert_eq!(intersection, expected.into_iter().collect::<HashSet<_>>());
+        assert_eq!(*sender_phases.lock().unwrap(), vec![Phase::MaskExchange]);
+        assert_eq!(*nsessions.lock().unwrap(), (2, 2));
+    }
+}
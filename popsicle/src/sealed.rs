@@ -0,0 +1,249 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Authenticated encryption of intersection results at rest.
+//!
+//! `write_sealed` encrypts a list of items, such as the intersection returned
+//! by a PSI receiver, before anything reaches the writer, and `read_sealed`
+//! returns them only once the whole file has been authenticated. Items are
+//! encrypted with the AES-CTR keystream of an `AesRng` and authenticated with
+//! HMAC-SHA256 (encrypt-then-MAC), under keys derived from a `SealingKey` and a
+//! fresh nonce, so the same key can seal many files.
+//!
+//! A `SealingKey` is either supplied by the caller or derived from a
+//! `KeySchedule` the application already holds for the session.
+//!
+//! # Usage
+//! ```rust
+//! use popsicle::sealed::{self, SealingKey};
+//! use scuttlebutt::AesRng;
+//!
+//! let mut rng = AesRng::new();
+//! let key = SealingKey::random(&mut rng);
+//! let intersection = vec![b"alice".to_vec(), b"bob".to_vec()];
+//! let mut file = Vec::new();
+//! sealed::write_sealed(&mut file, &key, &intersection, &mut rng).unwrap();
+//! assert_eq!(sealed::read_sealed(&mut file.as_slice(), &key).unwrap(), intersection);
+//! ```
+
+use crate::Error;
+use rand::{CryptoRng, Rng, RngCore};
+use scuttlebutt::{
+    keyschedule::{hmac, KeySchedule},
+    utils::constant_time_eq,
+    AesRng,
+};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+// Identifies the format, and its version, at the start of every file.
+const MAGIC: &[u8; 8] = b"PSISEAL1";
+const NONCE_SIZE: usize = 16;
+const TAG_SIZE: usize = 32;
+
+// Salt of the key schedule, and labels of the keys derived from it.
+const KEY_SCHEDULE_SALT: &[u8] = b"popsicle sealed";
+const ENCRYPTION_KEY: &[u8] = b"encryption key";
+const MAC_KEY: &[u8] = b"mac key";
+
+fn invalid(msg: &str) -> Error {
+    Error::InvalidSealedResults(msg.to_string())
+}
+
+/// A key sealing intersection results.
+#[derive(Clone)]
+pub struct SealingKey([u8; 32]);
+
+impl SealingKey {
+    /// Use `bytes`, which must be uniformly random and kept secret, as the key.
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Make a fresh key.
+    pub fn random<RNG: CryptoRng + RngCore>(rng: &mut RNG) -> Self {
+        Self(rng.gen())
+    }
+
+    /// Derive the key under `label` from a session's key schedule.
+    pub fn from_schedule(schedule: &KeySchedule, label: &[u8]) -> Self {
+        Self(schedule.key(label))
+    }
+
+    /// The bytes of the key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    // The keystream and the MAC key used under `nonce`.
+    fn expand(&self, nonce: &[u8]) -> (AesRng, [u8; 32]) {
+        let schedule = KeySchedule::new(KEY_SCHEDULE_SALT, &self.0).child(nonce);
+        (schedule.rng(ENCRYPTION_KEY), schedule.key(MAC_KEY))
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for SealingKey {
+    fn drop(&mut self) {
+        scuttlebutt::utils::erase(&mut self.0);
+    }
+}
+
+impl std::fmt::Debug for SealingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "SealingKey(..)")
+    }
+}
+
+/// Encrypt and authenticate `items` under `key`, writing the result to
+/// `writer`.
+pub fn write_sealed<W: Write, RNG: CryptoRng + RngCore>(
+    writer: &mut W,
+    key: &SealingKey,
+    items: &[Vec<u8>],
+    rng: &mut RNG,
+) -> Result<(), Error> {
+    let nonce = rng.gen::<[u8; NONCE_SIZE]>();
+    let (mut keystream, mac_key) = key.expand(&nonce);
+    let mut ct = Vec::with_capacity(8 + items.iter().map(|x| x.len() + 8).sum::<usize>());
+    ct.extend_from_slice(&(items.len() as u64).to_le_bytes());
+    for item in items {
+        ct.extend_from_slice(&(item.len() as u64).to_le_bytes());
+        ct.extend_from_slice(item);
+    }
+    let mut pad = vec![0u8; ct.len()];
+    keystream.fill_bytes(&mut pad);
+    scuttlebutt::utils::xor_inplace(&mut ct, &pad);
+    let len = (ct.len() as u64).to_le_bytes();
+    let tag = hmac(&mac_key, &[MAGIC, &nonce, &len, &ct]);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&nonce)?;
+    writer.write_all(&len)?;
+    writer.write_all(&ct)?;
+    writer.write_all(&tag)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read items written by `write_sealed` under `key` from `reader`, failing
+/// with `Error::InvalidSealedResults` if they were not sealed under `key` or
+/// have been modified.
+pub fn read_sealed<R: Read>(reader: &mut R, key: &SealingKey) -> Result<Vec<Vec<u8>>, Error> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a sealed results file"));
+    }
+    let mut nonce = [0u8; NONCE_SIZE];
+    reader.read_exact(&mut nonce)?;
+    let mut len = [0u8; 8];
+    reader.read_exact(&mut len)?;
+    let mut ct = Vec::new();
+    reader.take(u64::from_le_bytes(len)).read_to_end(&mut ct)?;
+    let mut tag = [0u8; TAG_SIZE];
+    reader.read_exact(&mut tag)?;
+
+    let (mut keystream, mac_key) = key.expand(&nonce);
+    let expected = hmac(&mac_key, &[MAGIC, &nonce, &len, &ct]);
+    if !constant_time_eq(&expected, &tag) {
+        return Err(invalid("authentication failed"));
+    }
+    let mut pt = ct;
+    let mut pad = vec![0u8; pt.len()];
+    keystream.fill_bytes(&mut pad);
+    scuttlebutt::utils::xor_inplace(&mut pt, &pad);
+
+    // The contents are authentic, so they are well-formed unless the key was
+    // misused; check anyway rather than panic.
+    let mut pt = pt.as_slice();
+    let mut next = |n: usize| -> Result<&[u8], Error> {
+        if pt.len() < n {
+            return Err(invalid("truncated contents"));
+        }
+        let (head, tail) = pt.split_at(n);
+        pt = tail;
+        Ok(head)
+    };
+    let mut u64_bytes = [0u8; 8];
+    u64_bytes.copy_from_slice(next(8)?);
+    let nitems = u64::from_le_bytes(u64_bytes);
+    let mut items = Vec::new();
+    for _ in 0..nitems {
+        u64_bytes.copy_from_slice(next(8)?);
+        let n = u64::from_le_bytes(u64_bytes) as usize;
+        items.push(next(n)?.to_vec());
+    }
+    Ok(items)
+}
+
+/// Seal `items` under `key` into a new file at `path`, replacing any file
+/// already there.
+pub fn seal_file<P: AsRef<Path>, RNG: CryptoRng + RngCore>(
+    path: P,
+    key: &SealingKey,
+    items: &[Vec<u8>],
+    rng: &mut RNG,
+) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_sealed(&mut writer, key, items, rng)
+}
+
+/// Open the file at `path`, written by `seal_file` under `key`.
+pub fn open_file<P: AsRef<Path>>(path: P, key: &SealingKey) -> Result<Vec<Vec<u8>>, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_sealed(&mut reader, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rand_vec_vec;
+
+    #[test]
+    fn test_seal() {
+        let mut rng = AesRng::new();
+        let items = rand_vec_vec(100, 8, &mut rng);
+        let key = SealingKey::from_schedule(&KeySchedule::new(b"test", &[1u8; 16]), b"results");
+        let mut bytes = Vec::new();
+        write_sealed(&mut bytes, &key, &items, &mut rng).unwrap();
+        assert_eq!(read_sealed(&mut bytes.as_slice(), &key).unwrap(), items);
+        // The items do not appear in the clear.
+        assert!(!bytes.windows(8).any(|w| w == &items[0][..]));
+        // Sealing again uses a fresh nonce.
+        let mut again = Vec::new();
+        write_sealed(&mut again, &key, &items, &mut rng).unwrap();
+        assert_ne!(bytes, again);
+
+        let path = std::env::temp_dir().join(format!("popsicle-sealed-{}", std::process::id()));
+        seal_file(&path, &key, &items, &mut rng).unwrap();
+        let opened = open_file(&path, &key);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(opened.unwrap(), items);
+    }
+
+    #[test]
+    fn test_tamper() {
+        let mut rng = AesRng::new();
+        let items = rand_vec_vec(10, 8, &mut rng);
+        let key = SealingKey::random(&mut rng);
+        let mut bytes = Vec::new();
+        write_sealed(&mut bytes, &key, &items, &mut rng).unwrap();
+        let wrong_key = SealingKey::random(&mut rng);
+        match read_sealed(&mut bytes.as_slice(), &wrong_key) {
+            Err(Error::InvalidSealedResults(_)) => (),
+            _ => panic!("opened under the wrong key"),
+        }
+        for i in &[0, 10, 40, bytes.len() - 1] {
+            let mut tampered = bytes.clone();
+            tampered[*i] ^= 1;
+            assert!(read_sealed(&mut tampered.as_slice(), &key).is_err());
+        }
+        assert!(read_sealed(&mut &bytes[..bytes.len() - 1], &key).is_err());
+    }
+}
@@ -0,0 +1,411 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Security parameters of the PSI protocols.
+//!
+//! The statistical parameter bounds the probability that a run goes wrong
+//! through bad luck rather than an attack: that the receiver's cuckoo table
+//! overflows, or that two different items get the same tag and show up as a
+//! false match. The computational parameter is the security of the
+//! cryptographic primitives themselves.
+//!
+//! Both parties must use the same parameters, which are not transmitted. A
+//! receiver sends the sender the size of its cuckoo table, but the sender and
+//! receiver each compute the length of the tags they exchange, unless both
+//! bound it with `MasksizeBounds` and negotiate it.
+//!
+//! A `SecurityLevel` names a consistent choice of all of these, together with
+//! the optional behaviors to negotiate and the crate features the build must
+//! enable, for integrators who would rather not pick each one.
+
+use crate::{features::Features, Error};
+use std::str::FromStr;
+
+/// The statistical security parameter used unless configured otherwise.
+pub const DEFAULT_LAMBDA_STAT: usize = 40;
+
+/// The computational security parameter of the protocols, which build on
+/// AES-128.
+pub const MAX_LAMBDA_COMP: usize = 128;
+
+// The largest statistical security parameter supported, whose tags still fit
+// in the SHA-256 digest the PSZ protocol truncates them from.
+const MAX_LAMBDA_STAT: usize = 128;
+
+/// The longest tag, in bytes, of the PSZ protocol: the length of the SHA-256
+/// digest tags are truncated from.
+pub const MAX_MASKSIZE: usize = 32;
+
+/// The statistical and computational security of a run, in bits.
+///
+/// A run fails, or reports a false match, with probability about
+/// `2^-lambda_stat`, and is as hard to attack as a `2^lambda_comp` brute-force
+/// search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct SecurityParams {
+    /// The statistical security parameter.
+    pub lambda_stat: usize,
+    /// The computational security parameter.
+    pub lambda_comp: usize,
+}
+
+impl Default for SecurityParams {
+    fn default() -> Self {
+        Self {
+            lambda_stat: DEFAULT_LAMBDA_STAT,
+            lambda_comp: MAX_LAMBDA_COMP,
+        }
+    }
+}
+
+impl SecurityParams {
+    /// Security parameters of `lambda_stat` and `lambda_comp` bits, failing
+    /// unless they are supported.
+    pub fn new(lambda_stat: usize, lambda_comp: usize) -> Result<Self, Error> {
+        let params = Self {
+            lambda_stat,
+            lambda_comp,
+        };
+        params.check()?;
+        Ok(params)
+    }
+
+    /// Fail unless the parameters are supported: `lambda_stat` must be
+    /// between 1 and 128, and `lambda_comp` at most `MAX_LAMBDA_COMP`.
+    /// Asking for less computational security does not make a run cheaper.
+    pub fn check(&self) -> Result<(), Error> {
+        if self.lambda_stat == 0 || self.lambda_stat > MAX_LAMBDA_STAT {
+            return Err(Error::InvalidSecurityParameters(format!(
+                "statistical security of {} bits is not between 1 and {}",
+                self.lambda_stat, MAX_LAMBDA_STAT
+            )));
+        }
+        if self.lambda_comp > MAX_LAMBDA_COMP {
+            return Err(Error::InvalidSecurityParameters(format!(
+                "computational security of {} bits exceeds the {} of AES-128",
+                self.lambda_comp, MAX_LAMBDA_COMP
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The lengths of tags, in bytes, a party accepts in a run.
+///
+/// When both parties enable `Features::MASKSIZE_NEGOTIATION`, they send each
+/// other their bounds and the length they computed before each run, and use
+/// the larger of the two lengths, raised to the larger floor and capped by the
+/// smaller ceiling; a run fails with `Error::IncompatibleMasksize` if the
+/// ceilings are below the floors. A party never agrees on tags shorter than
+/// the length it computed itself, as a peer could otherwise shrink them and
+/// cause false matches, unless its bounds opt in with `shrink`, trading
+/// statistical security for communication. Without negotiation, the peer
+/// accepts exactly the length it computed, so only bounds admitting that
+/// length let a run go ahead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct MasksizeBounds {
+    /// The shortest tag accepted.
+    pub min: usize,
+    /// The longest tag accepted.
+    pub max: usize,
+    /// Whether to accept tags shorter than the length this party computed.
+    #[cfg_attr(feature = "serde1", serde(default))]
+    pub shrink: bool,
+}
+
+impl Default for MasksizeBounds {
+    fn default() -> Self {
+        Self {
+            min: 1,
+            max: MAX_MASKSIZE,
+            shrink: false,
+        }
+    }
+}
+
+impl MasksizeBounds {
+    /// Bounds accepting tags of `min` to `max` bytes, but none shorter than the
+    /// computed length, failing unless `1 <= min <= max <= MAX_MASKSIZE`.
+    pub fn new(min: usize, max: usize) -> Result<Self, Error> {
+        let bounds = Self {
+            min,
+            max,
+            shrink: false,
+        };
+        bounds.check()?;
+        Ok(bounds)
+    }
+
+    /// The same bounds, also accepting tags shorter than the computed length
+    /// if `shrink` is set.
+    pub fn with_shrink(self, shrink: bool) -> Self {
+        Self { shrink, ..self }
+    }
+
+    /// Fail unless the bounds are supported: a non-empty range of lengths
+    /// between 1 and `MAX_MASKSIZE`.
+    pub fn check(&self) -> Result<(), Error> {
+        if self.min == 0 || self.min > self.max || self.max > MAX_MASKSIZE {
+            return Err(Error::InvalidSecurityParameters(format!(
+                "tags of {} to {} bytes are not between 1 and {} bytes",
+                self.min, self.max, MAX_MASKSIZE
+            )));
+        }
+        Ok(())
+    }
+
+    /// Agree on the length of the tags of a run, given the length `ours`
+    /// computed by this party, and the bounds and computed length `theirs` of
+    /// the peer. Unless `shrink` is set, `ours` is a floor.
+    pub fn agree(
+        &self,
+        ours: usize,
+        bounds: &MasksizeBounds,
+        theirs: usize,
+    ) -> Result<usize, Error> {
+        self.check()?;
+        let floor = if self.shrink { self.min } else { self.min.max(ours) };
+        let min = floor.max(bounds.min);
+        let max = self.max.min(bounds.max);
+        if min > max {
+            return Err(Error::IncompatibleMasksize {
+                ours: *self,
+                theirs: *bounds,
+            });
+        }
+        Ok(ours.max(theirs).max(min).min(max))
+    }
+}
+
+/// A preset of the security settings of a session.
+///
+/// Every level hashes the OPRF outputs before they are masked, which the PSZ
+/// protocol always does, and compares secrets in constant time, which the
+/// crate always does, so the levels differ in the rest:
+///
+/// | level      | `lambda_stat` | negotiated features                | build features           |
+/// |------------|---------------|------------------------------------|--------------------------|
+/// | `Legacy`   | 40            | none                               | none                     |
+/// | `Standard` | 40            | masksize negotiation               | none                     |
+/// | `Strict`   | 64            | masksize negotiation, confirmation | `zeroize` and `hardened` |
+///
+/// `Legacy` interoperates with peers which negotiate nothing. `Strict`
+/// refuses peers which do not run the confirmation round, erases secrets once
+/// they are no longer needed, and returns errors rather than panicking on a
+/// malformed message. Both parties must choose the same level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde1",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum SecurityLevel {
+    /// The settings of a plain `init`, for peers which negotiate nothing.
+    Legacy,
+    /// Safe defaults for new deployments.
+    #[default]
+    Standard,
+    /// Fewer false matches and hardened builds, for sensitive data.
+    Strict,
+}
+
+impl SecurityLevel {
+    /// All levels, weakest first.
+    pub const ALL: [SecurityLevel; 3] = [
+        SecurityLevel::Legacy,
+        SecurityLevel::Standard,
+        SecurityLevel::Strict,
+    ];
+
+    /// The name of the level, as parsed by `SecurityLevel::from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SecurityLevel::Legacy => "legacy",
+            SecurityLevel::Standard => "standard",
+            SecurityLevel::Strict => "strict",
+        }
+    }
+
+    /// The security parameters of the level.
+    pub fn params(self) -> SecurityParams {
+        let lambda_stat = match self {
+            SecurityLevel::Legacy | SecurityLevel::Standard => DEFAULT_LAMBDA_STAT,
+            SecurityLevel::Strict => 64,
+        };
+        SecurityParams {
+            lambda_stat,
+            lambda_comp: MAX_LAMBDA_COMP,
+        }
+    }
+
+    /// The bounds of the tags of the level, whose floor is the length its
+    /// statistical security gives the tags of the smallest sets, so that no
+    /// peer can shorten them further.
+    pub fn masksize_bounds(self) -> MasksizeBounds {
+        // Tags grow with the set size, from `lambda_stat + 16` bits.
+        let min = (self.params().lambda_stat + 16).div_ceil(8);
+        MasksizeBounds {
+            min,
+            max: MAX_MASKSIZE,
+            shrink: false,
+        }
+    }
+
+    /// The optional behaviors offered to the peer.
+    pub fn features(self) -> Features {
+        match self {
+            SecurityLevel::Legacy => Features::NONE,
+            SecurityLevel::Standard => Features::MASKSIZE_NEGOTIATION,
+            SecurityLevel::Strict => Features::MASKSIZE_NEGOTIATION | Features::CONFIRMATION,
+        }
+    }
+
+    /// The behaviors a session fails without.
+    pub fn required_features(self) -> Features {
+        match self {
+            SecurityLevel::Legacy | SecurityLevel::Standard => Features::NONE,
+            SecurityLevel::Strict => Features::CONFIRMATION,
+        }
+    }
+
+    /// Whether the level needs the crate built with the `zeroize` feature.
+    pub fn requires_zeroize(self) -> bool {
+        self == SecurityLevel::Strict
+    }
+
+    /// Whether the level needs the crate built with the `hardened` feature.
+    pub fn requires_hardened(self) -> bool {
+        self == SecurityLevel::Strict
+    }
+
+    /// Fail unless the crate was built with the features the level needs.
+    pub fn check_build(self) -> Result<(), Error> {
+        let missing = |feature| {
+            Err(Error::InvalidSecurityParameters(format!(
+                "the {} security level needs the `{}` feature of popsicle",
+                self, feature
+            )))
+        };
+        if self.requires_zeroize() && !cfg!(feature = "zeroize") {
+            return missing("zeroize");
+        }
+        if self.requires_hardened() && !cfg!(feature = "hardened") {
+            return missing("hardened");
+        }
+        Ok(())
+    }
+
+    /// Fail unless the `negotiated` features of a session include those the
+    /// level requires.
+    pub fn check_features(self, negotiated: Features) -> Result<(), Error> {
+        if negotiated.contains(self.required_features()) {
+            Ok(())
+        } else {
+            Err(Error::PsiProtocolError(format!(
+                "the peer does not support the features of the {} security level",
+                self
+            )))
+        }
+    }
+}
+
+impl FromStr for SecurityLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        SecurityLevel::ALL
+            .iter()
+            .find(|level| level.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                Error::InvalidSecurityParameters(format!("unknown security level `{}`", s))
+            })
+    }
+}
+
+impl std::fmt::Display for SecurityLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert_eq!(
+            SecurityParams::new(40, 128).unwrap(),
+            SecurityParams::default()
+        );
+        assert!(SecurityParams::new(60, 80).is_ok());
+        for &(stat, comp) in &[(0, 128), (129, 128), (40, 256)] {
+            match SecurityParams::new(stat, comp) {
+                Err(Error::InvalidSecurityParameters(_)) => (),
+                _ => panic!("accepted {} and {} bits of security", stat, comp),
+            }
+        }
+    }
+
+    #[test]
+    fn test_masksize_bounds() {
+        assert_eq!(
+            MasksizeBounds::new(1, MAX_MASKSIZE).unwrap(),
+            MasksizeBounds::default()
+        );
+        for &(min, max) in &[(0, 8), (9, 8), (8, 33)] {
+            match MasksizeBounds::new(min, max) {
+                Err(Error::InvalidSecurityParameters(_)) => (),
+                _ => panic!("accepted tags of {} to {} bytes", min, max),
+            }
+        }
+        let any = MasksizeBounds::default();
+        let exactly = |n| MasksizeBounds::new(n, n).unwrap();
+        assert_eq!(any.agree(8, &exactly(8), 8).unwrap(), 8);
+        assert_eq!(any.agree(8, &any, 10).unwrap(), 10);
+        let floor = MasksizeBounds::new(12, 16).unwrap();
+        assert_eq!(floor.agree(8, &any, 8).unwrap(), 12);
+        assert_eq!(any.agree(8, &floor, 8).unwrap(), 12);
+        let ceiling = MasksizeBounds::new(1, 6).unwrap();
+        assert_eq!(ceiling.with_shrink(true).agree(8, &any, 8).unwrap(), 6);
+        // A peer cannot shrink the tags below the length computed here.
+        assert!(any.agree(8, &ceiling, 8).is_err());
+        assert!(ceiling.agree(8, &any, 8).is_err());
+        match floor.agree(8, &ceiling, 8) {
+            Err(Error::IncompatibleMasksize { ours, theirs }) => {
+                assert_eq!((ours, theirs), (floor, ceiling))
+            }
+            r => panic!("agreed on {:?}", r),
+        }
+        // Without negotiation, the peer sticks to the length it computed.
+        assert!(floor.agree(8, &exactly(8), 8).is_err());
+    }
+
+    #[test]
+    fn test_levels() {
+        for level in SecurityLevel::ALL.iter() {
+            assert_eq!(level.name().parse::<SecurityLevel>().unwrap(), *level);
+            assert!(level.params().check().is_ok());
+            assert!(Features::SUPPORTED.contains(level.features()));
+            assert!(level.check_features(level.features()).is_ok());
+            let params = level.params();
+            let min = crate::cuckoo::compute_masksize_with(1, &params).unwrap();
+            assert_eq!(level.masksize_bounds(), MasksizeBounds::new(min, 32).unwrap());
+        }
+        assert!("paranoid".parse::<SecurityLevel>().is_err());
+        assert_eq!(SecurityLevel::Legacy.params(), SecurityParams::default());
+        assert!(SecurityLevel::Standard.check_build().is_ok());
+        assert_eq!(
+            SecurityLevel::Strict.check_build().is_ok(),
+            cfg!(all(feature = "zeroize", feature = "hardened"))
+        );
+        let negotiated = Features::STASH_FREE | Features::MASKSIZE_NEGOTIATION;
+        assert!(SecurityLevel::Strict.check_features(negotiated).is_err());
+    }
+}
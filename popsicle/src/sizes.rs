@@ -0,0 +1,196 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! An optional exchange of declared set sizes, run before any PSI protocol.
+//!
+//! The PSI protocols assume the parties agreed out of band on roughly how many
+//! items each holds, but only find out otherwise, if at all, once the base OTs
+//! and OPRFs have been paid for. Running `exchange` on a fresh connection lets
+//! each party declare a bound on the size of its set and check the peer's
+//! declaration against a `SizePolicy`, so that a peer presenting a far larger
+//! set than contracted, e.g. a receiver probing for many more items than it
+//! should, is turned away first. Either party rejecting a declaration fails
+//! the exchange on both sides.
+//!
+//! A declaration may be rounded up with `pad`, to only reveal the size class
+//! of a set. It binds the party making it: `check_inputs` refuses to run a
+//! protocol over more items than declared, and the receivers of the PSZ and
+//! VOPRF protocols, given the sender's declaration with `set_declared_size`,
+//! fail runs in which the sender sends more items than it declared.
+
+use crate::Error;
+use scuttlebutt::AbstractChannel;
+
+fn rejected(msg: String) -> Error {
+    Error::SetSizeRejected(msg)
+}
+
+/// Round the set size `n` up to a power of two, so that a declaration only
+/// reveals the size class of the set.
+pub fn pad(n: usize) -> usize {
+    n.max(1).next_power_of_two()
+}
+
+/// Limits on the set size a peer may declare.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SizePolicy {
+    max_size: Option<usize>,
+    max_ratio: Option<f64>,
+}
+
+impl SizePolicy {
+    /// A policy accepting any declaration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject peers declaring more than `max_size` items.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Reject peers declaring more than `max_ratio` times as many items as
+    /// this party.
+    pub fn max_ratio(mut self, max_ratio: f64) -> Self {
+        self.max_ratio = Some(max_ratio);
+        self
+    }
+
+    /// The most items a peer may declare, if the policy sets a limit.
+    pub fn size_limit(&self) -> Option<usize> {
+        self.max_size
+    }
+
+    /// Check the declaration `theirs` of the peer against the policy, where
+    /// this party declared `ours`.
+    pub fn check(&self, ours: usize, theirs: usize) -> Result<(), Error> {
+        if let Some(max_size) = self.max_size {
+            if theirs > max_size {
+                return Err(rejected(format!(
+                    "the peer declared {} items, more than the limit of {}",
+                    theirs, max_size
+                )));
+            }
+        }
+        if let Some(max_ratio) = self.max_ratio {
+            if theirs as f64 > max_ratio * ours as f64 {
+                return Err(rejected(format!(
+                    "the peer declared {} items, more than {} times our {}",
+                    theirs, max_ratio, ours
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Declare to the peer on `channel`, which also runs `exchange`, that this
+/// party holds at most `ours` items, and check the declaration of the peer
+/// against `policy`. Returns the size the peer declared.
+pub fn exchange<C: AbstractChannel>(
+    channel: &mut C,
+    ours: usize,
+    policy: &SizePolicy,
+) -> Result<usize, Error> {
+    channel.write_usize(ours)?;
+    channel.flush()?;
+    let theirs = channel.read_usize()?;
+    let checked = policy.check(ours, theirs);
+    channel.write_bool(checked.is_ok())?;
+    channel.flush()?;
+    let accepted = channel.read_bool()?;
+    checked?;
+    if !accepted {
+        return Err(rejected(format!(
+            "the peer rejected our declaration of {} items",
+            ours
+        )));
+    }
+    Ok(theirs)
+}
+
+/// Check that a peer sending `n` items keeps to the size it `declared`, if
+/// any.
+pub fn check_peer(n: usize, declared: Option<usize>) -> Result<(), Error> {
+    match declared {
+        Some(declared) if n > declared => Err(rejected(format!(
+            "the peer sent {} items, more than its declaration of {}",
+            n, declared
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Check that `inputs` holds no more items than the `declared` bound.
+pub fn check_inputs<T>(inputs: &[T], declared: usize) -> Result<(), Error> {
+    if inputs.len() > declared {
+        return Err(rejected(format!(
+            "{} items exceed our declaration of {}",
+            inputs.len(),
+            declared
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scuttlebutt::Channel;
+    use std::{
+        io::{BufReader, BufWriter},
+        os::unix::net::UnixStream,
+    };
+
+    // Run `exchange` between two parties, returning their outcomes.
+    fn run(
+        a: (usize, SizePolicy),
+        b: (usize, SizePolicy),
+    ) -> (Result<usize, Error>, Result<usize, Error>) {
+        let (x, y) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let reader = BufReader::new(x.try_clone().unwrap());
+            let writer = BufWriter::new(x);
+            let mut channel = Channel::new(reader, writer);
+            exchange(&mut channel, a.0, &a.1)
+        });
+        let reader = BufReader::new(y.try_clone().unwrap());
+        let writer = BufWriter::new(y);
+        let mut channel = Channel::new(reader, writer);
+        let b = exchange(&mut channel, b.0, &b.1);
+        (handle.join().unwrap(), b)
+    }
+
+    #[test]
+    fn test_exchange() {
+        let policy = SizePolicy::new().max_size(1 << 20).max_ratio(2.0);
+        let (a, b) = run((pad(1000), policy), (pad(1500), policy));
+        assert_eq!(a.unwrap(), 2048);
+        assert_eq!(b.unwrap(), 1024);
+
+        // Both parties fail when either rejects.
+        let (a, b) = run((1000, policy), (3000, SizePolicy::new()));
+        match (a, b) {
+            (Err(Error::SetSizeRejected(_)), Err(Error::SetSizeRejected(_))) => (),
+            _ => panic!("a declaration over the ratio was accepted"),
+        }
+        let (a, b) = run((1 << 21, policy), (1 << 21, policy));
+        assert!(a.is_err());
+        assert!(b.is_err());
+    }
+
+    #[test]
+    fn test_check_inputs() {
+        assert_eq!(pad(0), 1);
+        assert_eq!(pad(1024), 1024);
+        check_inputs(&[0u8; 1024], pad(1000)).unwrap();
+        assert!(check_inputs(&[0u8; 1025], pad(1000)).is_err());
+        check_peer(1024, Some(1024)).unwrap();
+        check_peer(1 << 20, None).unwrap();
+        assert!(check_peer(1025, Some(1024)).is_err());
+    }
+}
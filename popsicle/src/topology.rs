@@ -0,0 +1,523 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Descriptions of multi-party sessions: who the parties are, what role each
+//! plays, and which pairs of parties share a channel.
+//!
+//! A `Topology` is built with `TopologyBuilder`, or parsed from a config file
+//! with one party per line, in party-id order:
+//!
+//! ```text
+//! # role     address
+//! receiver   10.0.0.1:7000
+//! sender     10.0.0.2:7000
+//! sender     10.0.0.3:7000
+//! ```
+//!
+//! Topologies read from a file are fully connected. Once built, each party
+//! calls `Topology::connect` with its own id to open all of its channels,
+//! which come back in the `(PartyId, channel)` form expected by
+//! `MultiPartySender` and `MultiPartyReceiver`.
+
+use crate::{psi::kmprt::PartyId, Error};
+use scuttlebutt::{AbstractChannel, SyncChannel, TrackChannel};
+use std::{
+    collections::BTreeSet,
+    convert::TryFrom,
+    io::{BufReader, BufWriter, ErrorKind},
+    net::{TcpListener, TcpStream},
+    os::unix::net::UnixStream,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+// How often to retry while waiting for a peer.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// How long an accepted connection may take to name its party, which peers do
+// as soon as they connect.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A channel to another party over TCP.
+pub type TcpChannel = TrackChannel<BufReader<TcpStream>, BufWriter<TcpStream>>;
+
+/// A channel to another party in the same process, as used in tests.
+pub type LocalChannel = SyncChannel<BufReader<UnixStream>, BufWriter<UnixStream>>;
+
+/// The role a party plays in a session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub enum Role {
+    /// Contributes a set, but learns nothing.
+    #[cfg_attr(feature = "serde1", serde(alias = "sender"))]
+    Sender,
+    /// Contributes a set and learns the output.
+    #[cfg_attr(feature = "serde1", serde(alias = "receiver"))]
+    Receiver,
+}
+
+/// A single party in a session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct Party {
+    role: Role,
+    address: String,
+}
+
+impl Party {
+    /// The role of the party.
+    #[inline]
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// The address, as `host:port`, the party listens on.
+    #[inline]
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+/// A validated description of a multi-party session. Deserializing one
+/// validates it as `TopologyBuilder::build` does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde1",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "TopologyBuilder")
+)]
+pub struct Topology {
+    parties: Vec<Party>,
+    links: BTreeSet<(PartyId, PartyId)>,
+}
+
+/// A builder for `Topology`s. Parties are numbered in the order they are
+/// added, starting from zero.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde1", derive(serde::Deserialize))]
+pub struct TopologyBuilder {
+    parties: Vec<Party>,
+    links: BTreeSet<(PartyId, PartyId)>,
+}
+
+fn invalid(msg: String) -> Error {
+    Error::InvalidTopology(msg)
+}
+
+impl TopologyBuilder {
+    /// Make a new builder with no parties.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a party with role `role` listening on `address`.
+    pub fn party(mut self, role: Role, address: impl Into<String>) -> Self {
+        self.parties.push(Party {
+            role,
+            address: address.into(),
+        });
+        self
+    }
+
+    /// Add a channel between parties `a` and `b`.
+    pub fn link(mut self, a: PartyId, b: PartyId) -> Self {
+        self.links.insert((a.min(b), a.max(b)));
+        self
+    }
+
+    /// Add a channel between every pair of parties added so far.
+    pub fn fully_connected(mut self) -> Self {
+        for a in 0..self.parties.len() {
+            for b in a + 1..self.parties.len() {
+                self.links.insert((a, b));
+            }
+        }
+        self
+    }
+
+    /// Validate and build the topology.
+    ///
+    /// This checks that there are at least two parties, that party zero is
+    /// the only receiver, and that every pair of parties is linked, as the
+    /// multi-party PSI protocol requires.
+    pub fn build(self) -> Result<Topology, Error> {
+        let n = self.parties.len();
+        if n < 2 {
+            return Err(invalid(format!("need at least two parties, got {}", n)));
+        }
+        for (id, party) in self.parties.iter().enumerate() {
+            match (id, party.role) {
+                (0, Role::Sender) => {
+                    return Err(invalid("party 0 must be the receiver".to_string()))
+                }
+                (_, Role::Receiver) if id != 0 => {
+                    return Err(invalid(format!("party {} cannot also be a receiver", id)))
+                }
+                _ => (),
+            }
+        }
+        if let Some(&(a, b)) = self.links.iter().find(|&&(a, b)| a == b || b >= n) {
+            return Err(invalid(format!(
+                "link ({}, {}) is not between two parties",
+                a, b
+            )));
+        }
+        for a in 0..n {
+            for b in a + 1..n {
+                if !self.links.contains(&(a, b)) {
+                    return Err(invalid(format!("parties {} and {} are not linked", a, b)));
+                }
+            }
+        }
+        Ok(Topology {
+            parties: self.parties,
+            links: self.links,
+        })
+    }
+}
+
+impl TryFrom<TopologyBuilder> for Topology {
+    type Error = Error;
+
+    fn try_from(builder: TopologyBuilder) -> Result<Self, Error> {
+        builder.build()
+    }
+}
+
+impl FromStr for Topology {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut builder = TopologyBuilder::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let role = match fields.next() {
+                Some("sender") => Role::Sender,
+                Some("receiver") => Role::Receiver,
+                Some(r) => return Err(invalid(format!("line {}: unknown role `{}`", i + 1, r))),
+                None => unreachable!(),
+            };
+            let address = match (fields.next(), fields.next()) {
+                (Some(address), None) => address,
+                _ => return Err(invalid(format!("line {}: expected `role address`", i + 1))),
+            };
+            builder = builder.party(role, address);
+        }
+        builder.fully_connected().build()
+    }
+}
+
+impl Topology {
+    /// Read a topology from the config file at `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// The number of parties.
+    #[inline]
+    pub fn nparties(&self) -> usize {
+        self.parties.len()
+    }
+
+    /// The parties, indexed by id.
+    #[inline]
+    pub fn parties(&self) -> &[Party] {
+        &self.parties
+    }
+
+    /// The ids of the parties that `me` shares a channel with, in increasing
+    /// order.
+    pub fn peers(&self, me: PartyId) -> Vec<PartyId> {
+        self.links
+            .iter()
+            .filter_map(|&(a, b)| {
+                if a == me {
+                    Some(b)
+                } else if b == me {
+                    Some(a)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Open all channels of party `me` over TCP, failing if not every peer is
+    /// reached within `timeout`.
+    ///
+    /// Each party listens on its own address for peers with lower ids and
+    /// connects to peers with higher ids, so all parties may call this
+    /// concurrently. Connections which do not name a peer still expected
+    /// within a second are dropped.
+    pub fn connect(
+        &self,
+        me: PartyId,
+        timeout: Duration,
+    ) -> Result<Vec<(PartyId, TcpChannel)>, Error> {
+        if me >= self.nparties() {
+            return Err(invalid(format!("no party {}", me)));
+        }
+        let listener = TcpListener::bind(self.parties[me].address())?;
+        self.connect_with(me, listener, timeout)
+    }
+
+    /// Open all channels of party `me` as `connect` does, accepting peers on
+    /// `listener` rather than binding the address of `me`.
+    pub fn connect_with(
+        &self,
+        me: PartyId,
+        listener: TcpListener,
+        timeout: Duration,
+    ) -> Result<Vec<(PartyId, TcpChannel)>, Error> {
+        if me >= self.nparties() {
+            return Err(invalid(format!("no party {}", me)));
+        }
+        let deadline = Instant::now() + timeout;
+        let timed_out = || {
+            Error::IoError(std::io::Error::new(
+                ErrorKind::TimedOut,
+                "not every peer connected in time",
+            ))
+        };
+        let peers = self.peers(me);
+        let mut streams = Vec::with_capacity(peers.len());
+        for &peer in peers.iter().filter(|&&p| p > me) {
+            let stream = loop {
+                match TcpStream::connect(self.parties[peer].address()) {
+                    Ok(stream) => break stream,
+                    Err(e) if Instant::now() >= deadline => return Err(e.into()),
+                    Err(_) => std::thread::sleep(POLL_INTERVAL),
+                }
+            };
+            let mut channel = channel(stream)?;
+            channel.write_usize(me)?;
+            channel.flush()?;
+            streams.push((peer, channel));
+        }
+        listener.set_nonblocking(true)?;
+        while streams.len() < peers.len() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(timed_out());
+                    }
+                    std::thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            // A connection which does not promptly name a lower peer not yet
+            // connected, e.g. a port scan, is dropped rather than failing
+            // the session.
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let timeout = remaining.min(HANDSHAKE_TIMEOUT);
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+            let mut channel = channel(stream.try_clone()?)?;
+            match channel.read_usize() {
+                Ok(peer)
+                    if peer < me
+                        && peers.contains(&peer)
+                        && !streams.iter().any(|(p, _)| *p == peer) =>
+                {
+                    stream.set_read_timeout(None)?;
+                    streams.push((peer, channel));
+                }
+                _ => continue,
+            }
+        }
+        streams.sort_by_key(|(peer, _)| *peer);
+        Ok(streams)
+    }
+
+    /// Open all channels in-process, returning the channels of each party,
+    /// indexed by party id, in the same form as `connect`.
+    pub fn local_channels(&self) -> Vec<Vec<(PartyId, LocalChannel)>> {
+        let mut channels = (0..self.nparties()).map(|_| Vec::new()).collect::<Vec<_>>();
+        for &(a, b) in self.links.iter() {
+            let (s, r) = UnixStream::pair().unwrap();
+            let left = SyncChannel::new(BufReader::new(s.try_clone().unwrap()), BufWriter::new(s));
+            let right = SyncChannel::new(BufReader::new(r.try_clone().unwrap()), BufWriter::new(r));
+            channels[a].push((b, left));
+            channels[b].push((a, right));
+        }
+        channels
+    }
+}
+
+fn channel(stream: TcpStream) -> Result<TcpChannel, Error> {
+    Ok(TrackChannel::new(
+        BufReader::new(stream.try_clone()?),
+        BufWriter::new(stream),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let topology = TopologyBuilder::new()
+            .party(Role::Receiver, "localhost:7000")
+            .party(Role::Sender, "localhost:7001")
+            .party(Role::Sender, "localhost:7002")
+            .fully_connected()
+            .build()
+            .unwrap();
+        assert_eq!(topology.nparties(), 3);
+        assert_eq!(topology.peers(1), vec![0, 2]);
+        let config = "
+            # role    address
+            receiver  localhost:7000
+
+            sender    localhost:7001  # a comment
+            sender    localhost:7002
+        ";
+        let parsed = config.parse::<Topology>().unwrap();
+        assert_eq!(parsed, topology);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let incomplete = TopologyBuilder::new()
+            .party(Role::Receiver, "a:1")
+            .party(Role::Sender, "b:1")
+            .party(Role::Sender, "c:1")
+            .link(0, 1)
+            .link(0, 2);
+        assert!(incomplete.clone().build().is_err());
+        assert!(incomplete.link(2, 1).build().is_ok());
+        assert!(TopologyBuilder::new()
+            .party(Role::Receiver, "a:1")
+            .fully_connected()
+            .build()
+            .is_err());
+        assert!(TopologyBuilder::new()
+            .party(Role::Sender, "a:1")
+            .party(Role::Receiver, "b:1")
+            .fully_connected()
+            .build()
+            .is_err());
+        assert!(TopologyBuilder::new()
+            .party(Role::Receiver, "a:1")
+            .party(Role::Sender, "b:1")
+            .fully_connected()
+            .link(1, 5)
+            .build()
+            .is_err());
+        assert!("receiver a:1\nhelper b:1\n".parse::<Topology>().is_err());
+        assert!("receiver a:1\nsender\n".parse::<Topology>().is_err());
+    }
+
+    #[test]
+    fn test_connect() {
+        let listeners = (0..3)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect::<Vec<_>>();
+        let address = |l: &TcpListener| l.local_addr().unwrap().to_string();
+        let topology = TopologyBuilder::new()
+            .party(Role::Receiver, address(&listeners[0]))
+            .party(Role::Sender, address(&listeners[1]))
+            .party(Role::Sender, address(&listeners[2]))
+            .fully_connected()
+            .build()
+            .unwrap();
+        let handles = listeners
+            .into_iter()
+            .enumerate()
+            .map(|(me, listener)| {
+                let topology = topology.clone();
+                std::thread::spawn(move || {
+                    let mut channels = topology
+                        .connect_with(me, listener, Duration::from_secs(10))
+                        .unwrap();
+                    for (_, channel) in channels.iter_mut() {
+                        channel.write_usize(me).unwrap();
+                        channel.flush().unwrap();
+                    }
+                    channels
+                        .iter_mut()
+                        .map(|(peer, channel)| (*peer, channel.read_usize().unwrap()))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        for (me, handle) in handles.into_iter().enumerate() {
+            let seen = handle.join().unwrap();
+            assert_eq!(seen.len(), 2);
+            for (peer, id) in seen {
+                assert_ne!(peer, me);
+                assert_eq!(peer, id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_connect_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let topology = TopologyBuilder::new()
+            .party(Role::Receiver, "127.0.0.1:1")
+            .party(Role::Sender, listener.local_addr().unwrap().to_string())
+            .fully_connected()
+            .build()
+            .unwrap();
+        // Party 1 waits for party 0, which never connects.
+        assert!(topology
+            .connect_with(1, listener, Duration::from_millis(50))
+            .is_err());
+    }
+
+    #[test]
+    fn test_connect_skips_strangers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let topology = TopologyBuilder::new()
+            .party(Role::Receiver, "127.0.0.1:1")
+            .party(Role::Sender, address.to_string())
+            .fully_connected()
+            .build()
+            .unwrap();
+        // A connection which hangs up, one naming a party which does not
+        // exist, and finally party 0.
+        drop(TcpStream::connect(address).unwrap());
+        let mut stranger = channel(TcpStream::connect(address).unwrap()).unwrap();
+        stranger.write_usize(7).unwrap();
+        stranger.flush().unwrap();
+        let mut peer = channel(TcpStream::connect(address).unwrap()).unwrap();
+        peer.write_usize(0).unwrap();
+        peer.flush().unwrap();
+        let channels = topology
+            .connect_with(1, listener, Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].0, 0);
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn test_deserialize() {
+        let topology = TopologyBuilder::new()
+            .party(Role::Receiver, "a:1")
+            .party(Role::Sender, "b:1")
+            .fully_connected()
+            .build()
+            .unwrap();
+        let bytes = bincode::serialize(&topology).unwrap();
+        assert_eq!(bincode::deserialize::<Topology>(&bytes).unwrap(), topology);
+        let unlinked = TopologyBuilder::new()
+            .party(Role::Receiver, "a:1")
+            .party(Role::Sender, "b:1");
+        let bytes = bincode::serialize(&(unlinked.parties, unlinked.links)).unwrap();
+        assert!(bincode::deserialize::<Topology>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Two-choice hashing, as used by the sender in some multi-party and labeled
+//! PSI constructions.
+//!
+//! Each input is placed in the less loaded of the two bins given by the first
+//! two cuckoo hash functions, so that a receiver holding a cuckoo table with
+//! the same number of bins can find it. Unlike cuckoo hashing, bins hold many
+//! items and nothing is ever evicted.
+
+use crate::{cuckoo::CuckooHash, Error};
+use scuttlebutt::Block;
+
+/// The number of hash functions used.
+const NHASHES: usize = 2;
+
+/// Compute the number of bins and the maximum bin size for `n` inputs.
+///
+/// We use `n` bins. With two choices the maximum load is `ln ln n / ln 2 +
+/// O(1)` with high probability (cf. Azar-Broder-Karlin-Upfal, "Balanced
+/// Allocations"); we add a slack of four to the leading term.
+pub fn compute_params(n: usize) -> Result<(usize, usize), Error> {
+    if n == 0 || n > 1 << 28 {
+        return Err(Error::InvalidTwoChoiceSetSize(n));
+    }
+    let nbins = n;
+    let lnln = (n.max(3) as f64).ln().ln();
+    let binsize = (lnln / std::f64::consts::LN_2).ceil() as usize + 4;
+    Ok((nbins, binsize))
+}
+
+/// An entry in a two-choice hash table.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct TwoChoiceItem {
+    entry: Block,
+    input_index: usize,
+    hash_index: usize,
+}
+
+impl TwoChoiceItem {
+    /// The hashed input, with the hash index XORed into its lowest byte.
+    #[inline]
+    pub fn entry(&self) -> Block {
+        self.entry
+    }
+
+    /// The index of the input this entry corresponds to.
+    #[inline]
+    pub fn input_index(&self) -> usize {
+        self.input_index
+    }
+
+    /// The index of the hash function that placed this entry.
+    #[inline]
+    pub fn hash_index(&self) -> usize {
+        self.hash_index
+    }
+}
+
+/// A two-choice hash table.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub struct TwoChoiceHash {
+    bins: Vec<Vec<TwoChoiceItem>>,
+    nbins: usize,
+    binsize: usize,
+}
+
+impl TwoChoiceHash {
+    /// Build a new two-choice hash table, hashing `inputs` in. As with
+    /// `CuckooHash`, we require that the lower-order byte of the values in
+    /// `inputs` is zero-ed out.
+    pub fn new(inputs: &[Block]) -> Result<Self, Error> {
+        let (nbins, binsize) = compute_params(inputs.len())?;
+        Self::with_params(inputs, nbins, binsize)
+    }
+
+    /// Build a new two-choice hash table with `nbins` bins, each holding at most
+    /// `binsize` items. Both must be positive.
+    pub fn with_params(inputs: &[Block], nbins: usize, binsize: usize) -> Result<Self, Error> {
+        if nbins == 0 || binsize == 0 {
+            return Err(Error::InvalidTwoChoiceParameters { nbins, binsize });
+        }
+        let mut tbl = TwoChoiceHash {
+            bins: vec![Vec::new(); nbins],
+            nbins,
+            binsize,
+        };
+        for (j, input) in inputs.iter().enumerate() {
+            tbl.hash(*input, j)?;
+        }
+        Ok(tbl)
+    }
+
+    /// Place `input`, alongside the input index `idx` it corresponds to, in the
+    /// hash table.
+    pub fn hash(&mut self, input: Block, idx: usize) -> Result<(), Error> {
+        let mask = Block::from(0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FF00);
+        let input = input & mask;
+        let choices = (0..NHASHES)
+            .map(|h| CuckooHash::bin(input, h, self.nbins))
+            .collect::<Vec<usize>>();
+        // Ties go to the first hash function.
+        let hash_index = (1..NHASHES).fold(0, |best, h| {
+            if self.bins[choices[h]].len() < self.bins[choices[best]].len() {
+                h
+            } else {
+                best
+            }
+        });
+        let bin = &mut self.bins[choices[hash_index]];
+        if bin.len() >= self.binsize {
+            return Err(Error::HashTableFull);
+        }
+        bin.push(TwoChoiceItem {
+            entry: input ^ Block::from(hash_index as u128),
+            input_index: idx,
+            hash_index,
+        });
+        Ok(())
+    }
+
+    /// Return the bins of the table.
+    #[inline]
+    pub fn bins(&self) -> &[Vec<TwoChoiceItem>] {
+        &self.bins
+    }
+
+    /// Return the number of bins.
+    #[inline]
+    pub fn nbins(&self) -> usize {
+        self.nbins
+    }
+
+    /// Return the maximum number of items per bin.
+    #[inline]
+    pub fn binsize(&self) -> usize {
+        self.binsize
+    }
+
+    /// Return the number of items in the fullest bin.
+    pub fn max_load(&self) -> usize {
+        self.bins.iter().map(Vec::len).max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+    use rand::Rng;
+    use scuttlebutt::AesRng;
+
+    const ITEMSIZE: usize = 8;
+    const SETSIZE: usize = 1 << 16;
+
+    #[test]
+    fn test_build() {
+        let mut rng = AesRng::new();
+        let inputs = utils::rand_vec_vec(SETSIZE, ITEMSIZE, &mut rng);
+        let key = rng.gen();
+        let hashes = utils::compress_and_hash_inputs(&inputs, key);
+        let tbl = TwoChoiceHash::new(&hashes).unwrap();
+        assert!(tbl.max_load() <= tbl.binsize());
+        let nitems: usize = tbl.bins().iter().map(Vec::len).sum();
+        assert_eq!(nitems, SETSIZE);
+        // Each item lies in the bin its hash index points to.
+        for (i, bin) in tbl.bins().iter().enumerate() {
+            for item in bin {
+                let x = hashes[item.input_index()];
+                assert_eq!(item.entry(), x ^ Block::from(item.hash_index() as u128));
+                assert_eq!(CuckooHash::bin(x, item.hash_index(), tbl.nbins()), i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_overflow() {
+        let inputs = vec![Block::default(); 3];
+        let tbl = TwoChoiceHash::with_params(&inputs, 4, 1);
+        assert!(tbl.is_err());
+        for &(nbins, binsize) in &[(0, 4), (4, 0)] {
+            match TwoChoiceHash::with_params(&[], nbins, binsize) {
+                Err(Error::InvalidTwoChoiceParameters { .. }) => (),
+                _ => panic!("a table of {} bins of {} items", nbins, binsize),
+            }
+        }
+    }
+}
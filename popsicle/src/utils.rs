@@ -4,14 +4,24 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
+use crate::Error;
 use rand::{CryptoRng, Rng};
 use scuttlebutt::{AesHash, Block};
 use sha2::{Digest, Sha256};
+use std::{cell::Cell, time::SystemTime};
 
 // Compress an arbitrary vector into a 128-bit chunk, leaving the final 8-bits
 // as zero. We need to leave 8 bits free in order to add in the hash index when
 // running the OPRF (cf. <https://eprint.iacr.org/2016/799>, §5.2).
+#[allow(dead_code)] // used in tests and by `psty`
 pub fn compress_and_hash_inputs(inputs: &[Vec<u8>], key: Block) -> Vec<Block> {
+    compress_and_hash_inputs_salted(inputs, key, Block::default())
+}
+
+// As `compress_and_hash_inputs`, but XORs `salt` into each compressed input
+// before hashing, so that the same input yields unrelated outputs under
+// different salts.
+pub fn compress_and_hash_inputs_salted(inputs: &[Vec<u8>], key: Block, salt: Block) -> Vec<Block> {
     let mut hasher = Sha256::new(); // XXX can we do better than using SHA-256?
     let aes = AesHash::new(key);
     let mask = Block::from(0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FF00);
@@ -29,12 +39,83 @@ pub fn compress_and_hash_inputs(inputs: &[Vec<u8>], key: Block) -> Vec<Block> {
                 let h = hasher.result_reset();
                 digest[0..16].copy_from_slice(&h[0..16]);
             }
-            let block = aes.cr_hash(Block::from(i as u128), Block::from(digest));
+            let block = aes.cr_hash(Block::from(i as u128), Block::from(digest) ^ salt);
             block & mask
         })
         .collect::<Vec<Block>>()
 }
 
+// Check an internal invariant, described by `msg`. A violation panics unless
+// the `hardened` feature is enabled, in which case it is returned as
+// `Error::InternalInvariant`.
+#[inline]
+pub fn invariant(cond: bool, msg: &str) -> Result<(), Error> {
+    if cond {
+        Ok(())
+    } else {
+        Err(violated(msg))
+    }
+}
+
+// Report a violated internal invariant, as `invariant`, for use in
+// `ok_or_else`.
+pub fn violated(msg: &str) -> Error {
+    if cfg!(feature = "hardened") {
+        Error::InternalInvariant(msg.to_string())
+    } else {
+        panic!("internal invariant violated: {}", msg)
+    }
+}
+
+// Fail with `Error::Expired` if `what`, expiring at `expires`, has expired.
+pub fn check_expiry(expires: Option<SystemTime>, what: &str) -> Result<(), Error> {
+    match expires {
+        Some(expires) if SystemTime::now() >= expires => {
+            Err(Error::Expired(format!("the {} has expired", what)))
+        }
+        _ => Ok(()),
+    }
+}
+
+thread_local! {
+    // Whether a session is running on the current thread.
+    static IN_SESSION: Cell<bool> = const { Cell::new(false) };
+}
+
+// Clears `IN_SESSION` when the outermost session ends, even by panicking.
+struct SessionGuard;
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        IN_SESSION.with(|s| s.set(false));
+    }
+}
+
+// Run `f`, the body of a protocol entry point, as a session counted in the
+// installed `scuttlebutt::metrics` registry, if any. An entry point run by
+// another, such as `receive` by `receive_indices`, is part of its session.
+pub fn session<T>(f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    let metrics = match scuttlebutt::metrics::installed() {
+        Some(metrics) if !IN_SESSION.with(Cell::get) => metrics,
+        _ => return f(),
+    };
+    metrics.session_started();
+    IN_SESSION.with(|s| s.set(true));
+    let guard = SessionGuard;
+    let result = f();
+    drop(guard);
+    match &result {
+        Ok(_) => metrics.session_succeeded(),
+        Err(e) => metrics.session_failed(e.reason()),
+    }
+    result
+}
+
+// The items of `inputs` at `indices`, in their order.
+pub fn select(inputs: &[Vec<u8>], indices: &[usize]) -> Vec<Vec<u8>> {
+    indices.iter().map(|&i| inputs[i].clone()).collect()
+}
+
 #[allow(dead_code)] // used in tests
 pub fn rand_vec<RNG: CryptoRng + Rng>(n: usize, rng: &mut RNG) -> Vec<u8> {
     (0..n).map(|_| rng.gen()).collect()
@@ -57,6 +138,32 @@ mod tests {
         let inputs = rand_vec_vec(13, 16, &mut rng);
         let _ = compress_and_hash_inputs(&inputs, key);
     }
+
+    #[test]
+    fn test_compress_and_hash_inputs_salted() {
+        let mut rng = AesRng::new();
+        let key = rng.gen::<Block>();
+        let inputs = rand_vec_vec(13, 16, &mut rng);
+        let unsalted = compress_and_hash_inputs(&inputs, key);
+        let zero = compress_and_hash_inputs_salted(&inputs, key, Block::default());
+        let salted = compress_and_hash_inputs_salted(&inputs, key, rng.gen::<Block>());
+        assert_eq!(unsalted, zero);
+        assert!(unsalted.iter().zip(salted.iter()).all(|(x, y)| x != y));
+    }
+
+    #[test]
+    fn test_session() {
+        let metrics = std::sync::Arc::new(scuttlebutt::metrics::Metrics::new());
+        scuttlebutt::metrics::install(metrics.clone()).unwrap();
+        // No other session fails with this reason, so the count is exact
+        // although other tests run sessions meanwhile.
+        let fail = || Err::<(), _>(Error::InvalidFuzzyInput("test".to_string()));
+        assert!(session(|| session(fail)).is_err());
+        assert_eq!(metrics.failures("fuzzy"), 1);
+        assert!(session(fail).is_err());
+        assert_eq!(metrics.failures("fuzzy"), 2);
+        assert!(session(|| Ok(())).is_ok());
+    }
 }
 
 #[cfg(all(feature = "nightly", test))]
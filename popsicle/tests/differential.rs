@@ -0,0 +1,218 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Differential tests between the blocking PSI traits and their async
+//! counterparts, which must not drift apart.
+//!
+//! Each case draws the sets and the parties' seeds from one seed, then runs
+//! the protocol with each party blocking or async in turn. The parties talk
+//! through a relay recording the bytes each way, and every combination must
+//! produce the same transcript and the same intersection as two blocking
+//! parties. As an async call runs its protocol on an `AesRng` seeded from the
+//! party's, a blocking party does the same. Each async party runs on its own
+//! current-thread runtime. Only runs with the `async` feature.
+
+#![cfg(feature = "async")]
+
+use popsicle::{
+    Async,
+    AsyncPsiReceiver,
+    AsyncPsiSender,
+    MaliciousReceiver,
+    MaliciousSender,
+    PsiReceiver,
+    PsiSender,
+    Receiver,
+    Sender,
+    SmallSetReceiver,
+    SmallSetSender,
+};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use scuttlebutt::{AesRng, Block, Channel};
+use std::{
+    io::{BufReader, BufWriter, Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    thread::JoinHandle,
+};
+
+// The number of seeds each protocol is run from.
+const NCASES: u128 = 8;
+
+// The bytes the sender wrote and the bytes the receiver wrote.
+type Transcript = (Vec<u8>, Vec<u8>);
+
+#[derive(Clone, Copy, Debug)]
+enum Mode {
+    Blocking,
+    Async,
+}
+
+// Copy `from` to `to` until `from` is closed, returning what was copied.
+fn forward(mut from: UnixStream, mut to: UnixStream) -> Vec<u8> {
+    let mut copied = Vec::new();
+    let mut buf = [0u8; 1 << 14];
+    loop {
+        match from.read(&mut buf).unwrap() {
+            0 => break,
+            n => {
+                to.write_all(&buf[..n]).unwrap();
+                copied.extend_from_slice(&buf[..n]);
+            }
+        }
+    }
+    let _ = to.shutdown(Shutdown::Write);
+    copied
+}
+
+// Relay between the streams of the sender and the receiver, recording the
+// transcript.
+fn relay(sender: UnixStream, receiver: UnixStream) -> JoinHandle<Transcript> {
+    std::thread::spawn(move || {
+        let (s, r) = (sender.try_clone().unwrap(), receiver.try_clone().unwrap());
+        let upstream = std::thread::spawn(move || forward(s, r));
+        let downstream = forward(receiver, sender);
+        (upstream.join().unwrap(), downstream)
+    })
+}
+
+// A current-thread runtime for one async party.
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+// The generator of one call, as seeded by an async party.
+fn call_rng(rng: &mut AesRng) -> AesRng {
+    AesRng::from_seed(rng.gen())
+}
+
+fn channel(stream: UnixStream) -> Channel<BufReader<UnixStream>, BufWriter<UnixStream>> {
+    let reader = BufReader::new(stream.try_clone().unwrap());
+    let writer = BufWriter::new(stream);
+    Channel::new(reader, writer)
+}
+
+fn send<S>(mode: Mode, stream: UnixStream, inputs: Vec<Vec<u8>>, seed: Block)
+where
+    S: PsiSender + Send + 'static,
+{
+    let mut rng = AesRng::from_seed(seed);
+    match mode {
+        Mode::Blocking => {
+            let mut channel = channel(stream);
+            let mut psi = <S as PsiSender>::init(&mut channel, &mut call_rng(&mut rng)).unwrap();
+            PsiSender::send(&mut psi, &inputs, &mut channel, &mut call_rng(&mut rng)).unwrap();
+        }
+        Mode::Async => runtime().block_on(async move {
+            stream.set_nonblocking(true).unwrap();
+            let mut stream = tokio::net::UnixStream::from_std(stream).unwrap();
+            let mut psi = Async::<S>::init(&mut stream, &mut rng).await.unwrap();
+            AsyncPsiSender::send(&mut psi, &inputs, &mut stream, &mut rng)
+                .await
+                .unwrap();
+        }),
+    }
+}
+
+fn receive<R>(mode: Mode, stream: UnixStream, inputs: Vec<Vec<u8>>, seed: Block) -> Vec<Vec<u8>>
+where
+    R: PsiReceiver + Send + 'static,
+{
+    let mut rng = AesRng::from_seed(seed);
+    match mode {
+        Mode::Blocking => {
+            let mut channel = channel(stream);
+            let mut psi = <R as PsiReceiver>::init(&mut channel, &mut call_rng(&mut rng)).unwrap();
+            PsiReceiver::receive(&mut psi, &inputs, &mut channel, &mut call_rng(&mut rng)).unwrap()
+        }
+        Mode::Async => runtime().block_on(async move {
+            stream.set_nonblocking(true).unwrap();
+            let mut stream = tokio::net::UnixStream::from_std(stream).unwrap();
+            let mut psi = Async::<R>::init(&mut stream, &mut rng).await.unwrap();
+            AsyncPsiReceiver::receive(&mut psi, &inputs, &mut stream, &mut rng)
+                .await
+                .unwrap()
+        }),
+    }
+}
+
+// Run `S` and `R` in the given modes on the sets and seeds drawn from `seed`,
+// returning the intersection and the transcript.
+fn run<S, R>(modes: (Mode, Mode), seed: u128) -> (Vec<Vec<u8>>, Transcript)
+where
+    S: PsiSender + Send + 'static,
+    R: PsiReceiver + Send + 'static,
+{
+    // The sets have the same size, and share a random number of items.
+    let mut rng = AesRng::from_seed(Block::from(seed));
+    let n = rng.gen_range(1, 300);
+    let shared = rng.gen_range(0, n + 1);
+    let mut sender_inputs = (0..n)
+        .map(|_| (0..rng.gen_range(1, 32)).map(|_| rng.gen()).collect())
+        .collect::<Vec<Vec<u8>>>();
+    let mut receiver_inputs = sender_inputs[..shared].to_vec();
+    receiver_inputs.extend((shared..n).map(|i| format!("only the receiver's {}", i).into()));
+    sender_inputs.shuffle(&mut rng);
+    receiver_inputs.shuffle(&mut rng);
+    let (sender_seed, receiver_seed) = (rng.gen::<Block>(), rng.gen::<Block>());
+
+    let (sender, sender_end) = UnixStream::pair().unwrap();
+    let (receiver, receiver_end) = UnixStream::pair().unwrap();
+    let transcript = relay(sender_end, receiver_end);
+    let sender = std::thread::spawn(move || send::<S>(modes.0, sender, sender_inputs, sender_seed));
+    let intersection = receive::<R>(modes.1, receiver, receiver_inputs, receiver_seed);
+    sender.join().unwrap();
+    assert_eq!(
+        intersection.len(),
+        shared,
+        "seed {}, modes {:?}",
+        seed,
+        modes
+    );
+    (intersection, transcript.join().unwrap())
+}
+
+fn differential<S, R>()
+where
+    S: PsiSender + Send + 'static,
+    R: PsiReceiver + Send + 'static,
+{
+    for seed in 0..NCASES {
+        let expected = run::<S, R>((Mode::Blocking, Mode::Blocking), seed);
+        for &modes in &[
+            (Mode::Async, Mode::Blocking),
+            (Mode::Blocking, Mode::Async),
+            (Mode::Async, Mode::Async),
+        ] {
+            let (intersection, transcript) = run::<S, R>(modes, seed);
+            assert!(
+                transcript == expected.1,
+                "the transcripts of seed {} differ in modes {:?}",
+                seed,
+                modes
+            );
+            assert_eq!(intersection, expected.0, "seed {}, modes {:?}", seed, modes);
+        }
+    }
+}
+
+#[test]
+fn test_psz() {
+    differential::<Sender, Receiver>();
+}
+
+#[test]
+fn test_voprf() {
+    differential::<MaliciousSender, MaliciousReceiver>();
+}
+
+#[test]
+fn test_dh() {
+    differential::<SmallSetSender, SmallSetReceiver>();
+}
@@ -0,0 +1,302 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! End-to-end tests composing the PSI, OT and garbling crates, with the two
+//! parties in separate processes talking over TCP.
+//!
+//! Each test starts a second copy of this test binary, which runs the same
+//! test as the peer: `across_processes` tells the two copies apart by an
+//! environment variable. The test of the async PSI traits only runs with the
+//! `async` feature, and that of the `psi` binary reading a config with the
+//! `config` feature.
+
+use fancy_garbling::{
+    twopac::{
+        comparison,
+        semihonest::{Evaluator, Garbler},
+    },
+    BinaryGadgets, FancyInput, FancyReveal,
+};
+use ocelot::ot::{
+    AlszReceiver, AlszSender, ChouOrlandiReceiver, ChouOrlandiSender, KosReceiver, KosSender,
+    Receiver as OtReceiver, Sender as OtSender,
+};
+use popsicle::psz;
+use scuttlebutt::{AesRng, Block, SemiHonest, SyncChannel};
+use std::{
+    cmp::Ordering,
+    io::{BufReader, BufWriter, ErrorKind},
+    net::{TcpListener, TcpStream},
+    process::Command,
+    time::{Duration, Instant},
+};
+
+// Name the test a child process runs as the peer, and where to connect.
+const PEER_TEST: &str = "POPSICLE_PEER_TEST";
+const PEER_ADDR: &str = "POPSICLE_PEER_ADDR";
+
+// How long to wait for the peer process to connect.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+type TcpChannel = SyncChannel<BufReader<TcpStream>, BufWriter<TcpStream>>;
+
+fn channel(stream: TcpStream) -> TcpChannel {
+    let reader = BufReader::new(stream.try_clone().unwrap());
+    let writer = BufWriter::new(stream);
+    SyncChannel::new(reader, writer)
+}
+
+/// Run `theirs` in a child process running the test named `test` again, and
+/// `ours` here, connected to each other. Fails unless both succeed.
+fn across_processes(test: &str, theirs: impl FnOnce(TcpStream), ours: impl FnOnce(TcpStream)) {
+    if std::env::var(PEER_TEST).ok().as_deref() == Some(test) {
+        let addr = std::env::var(PEER_ADDR).unwrap();
+        theirs(TcpStream::connect(addr).unwrap());
+        return;
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args([test, "--exact", "--nocapture", "--test-threads=1"])
+        .env(PEER_TEST, test)
+        .env(PEER_ADDR, listener.local_addr().unwrap().to_string())
+        .spawn()
+        .unwrap();
+    // Give up early if the child fails before connecting.
+    listener.set_nonblocking(true).unwrap();
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    let stream = loop {
+        match listener.accept() {
+            Ok((stream, _)) => break stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if let Some(status) = child.try_wait().unwrap() {
+                    panic!("the peer exited before connecting: {}", status);
+                }
+                assert!(Instant::now() < deadline, "the peer did not connect");
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => panic!("{}", e),
+        }
+    };
+    stream.set_nonblocking(false).unwrap();
+    ours(stream);
+    assert!(child.wait().unwrap().success(), "the peer failed");
+}
+
+fn items(range: std::ops::Range<u32>) -> Vec<Vec<u8>> {
+    range.map(|i| i.to_le_bytes().to_vec()).collect()
+}
+
+// The receiver learns the intersection of the two sets, then compares its
+// size to a threshold of the sender's in a garbled circuit, without either
+// telling the other its number.
+#[test]
+fn psi_feeds_comparison() {
+    const NBITS: usize = 16;
+    const THRESHOLD: u128 = 150;
+    // The sets share 200 items. `send` and `receive` need sets of the same
+    // size.
+    let (sent, received) = (items(0..1000), items(800..1800));
+    across_processes(
+        "psi_feeds_comparison",
+        |stream| {
+            let mut rng = AesRng::new();
+            let mut channel = channel(stream);
+            let mut psi = psz::Sender::init(&mut channel, &mut rng).unwrap();
+            psi.send(&sent, &mut channel, &mut rng).unwrap();
+            let order = comparison::garbler_compare::<_, _, ChouOrlandiSender>(
+                channel, rng, THRESHOLD, NBITS,
+            )
+            .unwrap();
+            assert_eq!(order, Ordering::Less);
+        },
+        |stream| {
+            let mut rng = AesRng::new();
+            let mut channel = channel(stream);
+            let mut psi = psz::Receiver::init(&mut channel, &mut rng).unwrap();
+            let intersection = psi.receive(&received, &mut channel, &mut rng).unwrap();
+            assert_eq!(intersection.len(), 200);
+            let order = comparison::evaluator_compare::<_, _, ChouOrlandiReceiver>(
+                channel,
+                rng,
+                intersection.len() as u128,
+                NBITS,
+            )
+            .unwrap();
+            assert_eq!(order, Ordering::Greater);
+        },
+    );
+}
+
+// Add the garbler's and the evaluator's inputs in a garbled circuit, with the
+// evaluator's input labels sent over the OT extension `S` and `R`.
+fn ot_extension_feeds_garbler<S, R>(test: &str)
+where
+    S: OtSender<Msg = Block> + SemiHonest,
+    R: OtReceiver<Msg = Block> + SemiHonest,
+{
+    const NBITS: usize = 32;
+    let (x, ys) = (0xdead_beef_u128, [0, 1, 0x2152_4110, 0xffff_ffff]);
+    let sum = |y: u128| (x + y) & 0xffff_ffff;
+    across_processes(
+        test,
+        |stream| {
+            let mut gb = Garbler::<_, AesRng, S>::new(channel(stream), AesRng::new()).unwrap();
+            let xws = gb.bin_encode_many(&vec![x; ys.len()], NBITS).unwrap();
+            let yws = gb.bin_receive_many(ys.len(), NBITS).unwrap();
+            for ((xw, yw), &y) in xws.iter().zip(yws.iter()).zip(ys.iter()) {
+                let (z, _) = gb.bin_addition(xw, yw).unwrap();
+                assert_eq!(gb.bin_reveal(&z).unwrap(), sum(y));
+            }
+        },
+        |stream| {
+            let mut ev = Evaluator::<_, AesRng, R>::new(channel(stream), AesRng::new()).unwrap();
+            let xws = ev.bin_receive_many(ys.len(), NBITS).unwrap();
+            let yws = ev.bin_encode_many(&ys, NBITS).unwrap();
+            for ((xw, yw), &y) in xws.iter().zip(yws.iter()).zip(ys.iter()) {
+                let (z, _) = ev.bin_addition(xw, yw).unwrap();
+                assert_eq!(ev.bin_reveal(&z).unwrap(), sum(y));
+            }
+        },
+    );
+}
+
+#[test]
+fn alsz_feeds_garbler() {
+    ot_extension_feeds_garbler::<AlszSender, AlszReceiver>("alsz_feeds_garbler");
+}
+
+#[test]
+fn kos_feeds_garbler() {
+    ot_extension_feeds_garbler::<KosSender, KosReceiver>("kos_feeds_garbler");
+}
+
+// An async receiver on a tokio stream, against a blocking sender.
+#[cfg(feature = "async")]
+#[test]
+fn async_psi_against_blocking_peer() {
+    use popsicle::{Async, AsyncPsiReceiver};
+    use std::collections::HashSet;
+
+    let (sent, received) = (items(0..500), items(400..900));
+    across_processes(
+        "async_psi_against_blocking_peer",
+        |stream| {
+            let mut rng = AesRng::new();
+            let mut channel = channel(stream);
+            let mut psi = psz::Sender::init(&mut channel, &mut rng).unwrap();
+            psi.send(&sent, &mut channel, &mut rng).unwrap();
+        },
+        |stream| {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_io()
+                .build()
+                .unwrap();
+            let intersection = runtime.block_on(async {
+                stream.set_nonblocking(true).unwrap();
+                let mut stream = tokio::net::TcpStream::from_std(stream).unwrap();
+                let mut rng = AesRng::new();
+                let mut psi = Async::<psz::Receiver>::init(&mut stream, &mut rng)
+                    .await
+                    .unwrap();
+                AsyncPsiReceiver::receive(&mut psi, &received, &mut stream, &mut rng)
+                    .await
+                    .unwrap()
+            });
+            assert_eq!(
+                intersection.into_iter().collect::<HashSet<_>>(),
+                items(400..500).into_iter().collect()
+            );
+        },
+    );
+}
+
+// The `psi` binary, with each side in its own process.
+#[test]
+fn psi_binary_over_tcp() {
+    let dir = std::env::temp_dir().join(format!("popsicle-psi-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let lines =
+        |range: std::ops::Range<u32>| range.map(|i| format!("item{}\n", i)).collect::<String>();
+    std::fs::write(dir.join("sent.txt"), lines(0..2000)).unwrap();
+    std::fs::write(dir.join("received.txt"), lines(1900..2100)).unwrap();
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let addr = format!("127.0.0.1:{}", port);
+    let psi = env!("CARGO_BIN_EXE_psi");
+    let mut sender = Command::new(psi)
+        .args(["send", "--listen", &addr, "--input"])
+        .arg(dir.join("sent.txt"))
+        .spawn()
+        .unwrap();
+    let receiver = Command::new(psi)
+        .args(["receive", "--connect", &addr, "--input"])
+        .arg(dir.join("received.txt"))
+        .output()
+        .unwrap();
+    assert!(receiver.status.success());
+    assert!(sender.wait().unwrap().success());
+    assert_eq!(
+        String::from_utf8(receiver.stdout).unwrap(),
+        lines(1900..2000)
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// The `psi` binary, with each side reading its endpoint from a config.
+#[cfg(feature = "config")]
+#[test]
+fn psi_binary_with_config() {
+    let dir = std::env::temp_dir().join(format!("popsicle-psi-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let lines =
+        |range: std::ops::Range<u32>| range.map(|i| format!("item{}\n", i)).collect::<String>();
+    std::fs::write(dir.join("sent.txt"), lines(0..200)).unwrap();
+    std::fs::write(dir.join("received.txt"), lines(150..250)).unwrap();
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let network = |role: &str| {
+        format!(
+            "[network]\nrole = \"{}\"\naddress = \"127.0.0.1:{}\"\npeer = \"127.0.0.1:{}\"\n",
+            role, port, port
+        )
+    };
+    std::fs::write(dir.join("sender.toml"), network("sender")).unwrap();
+    std::fs::write(dir.join("receiver.toml"), network("receiver")).unwrap();
+    let psi = env!("CARGO_BIN_EXE_psi");
+    // A config of the other role is refused.
+    let wrong = Command::new(psi)
+        .args(["send", "--config"])
+        .arg(dir.join("receiver.toml"))
+        .arg("--input")
+        .arg(dir.join("sent.txt"))
+        .output()
+        .unwrap();
+    assert!(!wrong.status.success());
+    let mut sender = Command::new(psi)
+        .args(["send", "--config"])
+        .arg(dir.join("sender.toml"))
+        .arg("--input")
+        .arg(dir.join("sent.txt"))
+        .spawn()
+        .unwrap();
+    let receiver = Command::new(psi)
+        .args(["receive", "--config"])
+        .arg(dir.join("receiver.toml"))
+        .arg("--input")
+        .arg(dir.join("received.txt"))
+        .output()
+        .unwrap();
+    assert!(receiver.status.success());
+    assert!(sender.wait().unwrap().success());
+    assert_eq!(String::from_utf8(receiver.stdout).unwrap(), lines(150..200));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
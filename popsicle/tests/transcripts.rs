@@ -0,0 +1,289 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `popsicle`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Wire compatibility with recorded transcripts of released protocol versions.
+//!
+//! Each file in `tests/golden` records both sides of a run, from fixed seeds
+//! and sets, of a protocol version a peer may still be running. The tests
+//! replay the file against the current code in either role: the party must
+//! read the peer's recorded bytes, write exactly its own recorded bytes, and
+//! find the recorded intersection. A change failing these tests breaks rolling
+//! upgrades, and must bump the protocol version instead.
+//!
+//! Transcripts are named after the wire version of their protocol: the one
+//! PSZ exchanges when a session starts, and for VOPRF, which sends none, the
+//! version it has had since it was added. When a version is added, run the
+//! tests with `POPSICLE_BLESS=1` to record the missing transcripts from the
+//! current code. Existing transcripts are never rewritten. Once a version is
+//! no longer supported, its transcript is kept to check that the current code
+//! rejects a peer still running it, rather than misreading its messages.
+
+use popsicle::{MaliciousReceiver, MaliciousSender, PsiReceiver, PsiSender, Receiver, Sender};
+use rand::SeedableRng;
+use scuttlebutt::{AbstractChannel, AesRng, Block, Channel};
+use std::{
+    convert::TryInto,
+    io::{BufReader, BufWriter, Error, ErrorKind, Result},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+// The seeds of the sender and the receiver.
+const SENDER_SEED: u128 = 1;
+const RECEIVER_SEED: u128 = 2;
+
+// The size of each set, of which the sets share half.
+const NINPUTS: usize = 64;
+
+fn inputs() -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+    let item = |i: usize| format!("item {}", i).into_bytes();
+    let sender = (0..NINPUTS).map(item).collect();
+    let receiver = (NINPUTS / 2..NINPUTS + NINPUTS / 2).map(item).collect();
+    (sender, receiver)
+}
+
+/// The bytes each party wrote in a run, and the intersection found.
+struct Transcript {
+    sender: Vec<u8>,
+    receiver: Vec<u8>,
+    intersection: Vec<Vec<u8>>,
+}
+
+fn take<'a>(bytes: &mut &'a [u8]) -> &'a [u8] {
+    let n = u64::from_le_bytes(bytes[..8].try_into().unwrap()) as usize;
+    let (taken, rest) = bytes[8..].split_at(n);
+    *bytes = rest;
+    taken
+}
+
+fn put(bytes: &mut Vec<u8>, taken: &[u8]) {
+    bytes.extend_from_slice(&(taken.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(taken);
+}
+
+impl Transcript {
+    fn from_bytes(mut bytes: &[u8]) -> Self {
+        let sender = take(&mut bytes).to_vec();
+        let receiver = take(&mut bytes).to_vec();
+        let mut intersection = Vec::new();
+        while !bytes.is_empty() {
+            intersection.push(take(&mut bytes).to_vec());
+        }
+        Self {
+            sender,
+            receiver,
+            intersection,
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        put(&mut bytes, &self.sender);
+        put(&mut bytes, &self.receiver);
+        for item in self.intersection.iter() {
+            put(&mut bytes, item);
+        }
+        bytes
+    }
+}
+
+/// A channel keeping a copy of everything written to it.
+struct RecordChannel<C> {
+    channel: C,
+    written: Vec<u8>,
+}
+
+impl<C: AbstractChannel> AbstractChannel for RecordChannel<C> {
+    fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<()> {
+        self.channel.read_bytes(bytes)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.written.extend_from_slice(bytes);
+        self.channel.write_bytes(bytes)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.channel.flush()
+    }
+
+    fn clone(&self) -> Self {
+        unimplemented!()
+    }
+}
+
+/// A channel playing the peer's side of a transcript, failing as soon as the
+/// party's writes diverge from its own side, or discarding them if it has
+/// none.
+struct ReplayChannel<'a> {
+    theirs: &'a [u8],
+    ours: Option<&'a [u8]>,
+}
+
+impl AbstractChannel for ReplayChannel<'_> {
+    fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<()> {
+        if bytes.len() > self.theirs.len() {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "read past the end of the transcript",
+            ));
+        }
+        let (read, rest) = self.theirs.split_at(bytes.len());
+        bytes.copy_from_slice(read);
+        self.theirs = rest;
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        if let Some(ours) = self.ours.as_mut() {
+            if !ours.starts_with(bytes) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "wrote other bytes than the transcript",
+                ));
+            }
+            *ours = &ours[bytes.len()..];
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn clone(&self) -> Self {
+        unimplemented!()
+    }
+}
+
+fn seeded(seed: u128) -> AesRng {
+    AesRng::from_seed(Block::from(seed))
+}
+
+fn record<S: PsiSender, R: PsiReceiver + Send + 'static>() -> Transcript {
+    let (sender_inputs, receiver_inputs) = inputs();
+    let (sender, receiver) = UnixStream::pair().unwrap();
+    let handle = std::thread::spawn(move || {
+        let mut rng = seeded(RECEIVER_SEED);
+        let reader = BufReader::new(receiver.try_clone().unwrap());
+        let writer = BufWriter::new(receiver);
+        let mut channel = RecordChannel {
+            channel: Channel::new(reader, writer),
+            written: Vec::new(),
+        };
+        let mut psi = R::init(&mut channel, &mut rng).unwrap();
+        let intersection = psi.receive(&receiver_inputs, &mut channel, &mut rng).unwrap();
+        (channel.written, intersection)
+    });
+    let mut rng = seeded(SENDER_SEED);
+    let reader = BufReader::new(sender.try_clone().unwrap());
+    let writer = BufWriter::new(sender);
+    let mut channel = RecordChannel {
+        channel: Channel::new(reader, writer),
+        written: Vec::new(),
+    };
+    let mut psi = S::init(&mut channel, &mut rng).unwrap();
+    psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+    let (receiver, intersection) = handle.join().unwrap();
+    Transcript {
+        sender: channel.written,
+        receiver,
+        intersection,
+    }
+}
+
+// Load the transcript `name`, recording it first if it is missing and
+// `POPSICLE_BLESS` is set.
+fn load<S: PsiSender, R: PsiReceiver + Send + 'static>(name: &str) -> Transcript {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.bin", name));
+    if !path.exists() && std::env::var_os("POPSICLE_BLESS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, record::<S, R>().to_bytes()).unwrap();
+    }
+    match std::fs::read(&path) {
+        Ok(bytes) => Transcript::from_bytes(&bytes),
+        Err(e) => panic!(
+            "cannot read {}: {} (set POPSICLE_BLESS=1 to record it)",
+            path.display(),
+            e
+        ),
+    }
+}
+
+// Replay the transcript `name` against the current `S` and `R`.
+fn replay<S: PsiSender, R: PsiReceiver + Send + 'static>(name: &str) {
+    let transcript = load::<S, R>(name);
+    let (sender_inputs, receiver_inputs) = inputs();
+
+    let mut channel = ReplayChannel {
+        theirs: &transcript.receiver,
+        ours: Some(&transcript.sender),
+    };
+    let mut rng = seeded(SENDER_SEED);
+    let mut psi = S::init(&mut channel, &mut rng).unwrap();
+    psi.send(&sender_inputs, &mut channel, &mut rng).unwrap();
+    assert!(channel.theirs.is_empty() && channel.ours == Some(&[]));
+
+    let mut channel = ReplayChannel {
+        theirs: &transcript.sender,
+        ours: Some(&transcript.receiver),
+    };
+    let mut rng = seeded(RECEIVER_SEED);
+    let mut psi = R::init(&mut channel, &mut rng).unwrap();
+    let mut intersection = psi.receive(&receiver_inputs, &mut channel, &mut rng).unwrap();
+    assert!(channel.theirs.is_empty() && channel.ours == Some(&[]));
+    // The order of the intersection is the receiver's own, and not part of
+    // the wire protocol.
+    let mut expected = transcript.intersection;
+    intersection.sort();
+    expected.sort();
+    assert_eq!(intersection, expected);
+    assert_eq!(intersection.len(), NINPUTS / 2);
+}
+
+// Check that the current `S` and `R` refuse to run with a peer playing its
+// side of the transcript `name`, of an unsupported version.
+fn reject<S: PsiSender, R: PsiReceiver>(name: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.bin", name));
+    let transcript = Transcript::from_bytes(&std::fs::read(path).unwrap());
+
+    let mut channel = ReplayChannel {
+        theirs: &transcript.receiver,
+        ours: None,
+    };
+    match S::init(&mut channel, &mut seeded(SENDER_SEED)) {
+        Err(popsicle::Error::PsiProtocolError(_)) => (),
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+
+    let mut channel = ReplayChannel {
+        theirs: &transcript.sender,
+        ours: None,
+    };
+    match R::init(&mut channel, &mut seeded(RECEIVER_SEED)) {
+        Err(popsicle::Error::PsiProtocolError(_)) => (),
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_psz_v1() {
+    reject::<Sender, Receiver>("psz-v1");
+}
+
+#[test]
+fn test_psz_v2() {
+    replay::<Sender, Receiver>("psz-v2");
+}
+
+#[test]
+fn test_voprf_v1() {
+    replay::<MaliciousSender, MaliciousReceiver>("voprf-v1");
+}
@@ -57,6 +57,16 @@ fn bench_xor(c: &mut Criterion) {
     });
 }
 
+fn bench_prg_expand(c: &mut Criterion) {
+    c.bench_function("Block::prg_expand (1024)", |b| {
+        let x = rand::random::<Block>();
+        b.iter(|| {
+            let v = x.prg_expand(1024);
+            criterion::black_box(v)
+        });
+    });
+}
+
 fn bench_default(c: &mut Criterion) {
     c.bench_function("Block::default", |b| {
         b.iter(|| {
@@ -69,6 +79,6 @@ fn bench_default(c: &mut Criterion) {
 criterion_group! {
     name = block;
     config = Criterion::default().warm_up_time(Duration::from_millis(100));
-    targets = bench_hash_pt, bench_clmul, bench_rand, bench_xor, bench_default
+    targets = bench_hash_pt, bench_clmul, bench_rand, bench_xor, bench_prg_expand, bench_default
 }
 criterion_main!(block);
@@ -169,6 +169,20 @@ impl Aes128 {
         }
         blocks
     }
+    /// Encrypt `blocks` in place, eight at a time while there are enough, so
+    /// that the rounds of eight blocks are pipelined.
+    #[inline]
+    pub fn encrypt_blocks(&self, blocks: &mut [Block]) {
+        let mut chunks = blocks.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            let mut ms = [Block::default(); 8];
+            ms.copy_from_slice(chunk);
+            chunk.copy_from_slice(&self.encrypt8(ms));
+        }
+        for block in chunks.into_remainder() {
+            *block = self.encrypt(*block);
+        }
+    }
 }
 
 macro_rules! expand_round {
@@ -279,4 +293,29 @@ mod tests {
         let ct = cipher.encrypt(pt);
         assert_eq!(ct, Block::from(0x97EF6624F3CA9EA860367A0DB47BD73A));
     }
+
+    #[test]
+    fn test_encrypt_blocks() {
+        let cipher = Aes128::new(rand::random::<Block>());
+        let pts = (0..19).map(|_| rand::random::<Block>()).collect::<Vec<_>>();
+        let mut cts = pts.clone();
+        cipher.encrypt_blocks(&mut cts);
+        for (pt, ct) in pts.iter().zip(cts.iter()) {
+            assert_eq!(cipher.encrypt(*pt), *ct);
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Aes128 {
+    /// Erase the round keys.
+    fn zeroize(&mut self) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(
+                self.rkeys.as_mut_ptr() as *mut u8,
+                std::mem::size_of_val(&self.rkeys),
+            )
+        };
+        bytes.zeroize();
+    }
 }
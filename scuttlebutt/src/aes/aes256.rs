@@ -163,3 +163,17 @@ mod tests {
         assert_eq!(ct, Block::from(0xF881B13D7E5A4B063CA0D2B5BDD1EEF3));
     }
 }
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Aes256 {
+    /// Erase the round keys.
+    fn zeroize(&mut self) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(
+                self.rkeys.as_mut_ptr() as *mut u8,
+                std::mem::size_of_val(&self.rkeys),
+            )
+        };
+        bytes.zeroize();
+    }
+}
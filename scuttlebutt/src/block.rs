@@ -6,10 +6,11 @@
 
 //! Defines a block as a 128-bit value, and implements block-related functions.
 
+use crate::Aes128;
 #[cfg(feature = "curve25519-dalek")]
 use crate::Aes256;
 #[cfg(feature = "curve25519-dalek")]
-use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 #[cfg(feature = "serde")]
 use std::convert::TryInto;
 use std::{
@@ -19,6 +20,7 @@ use std::{
 
 /// A 128-bit chunk.
 #[derive(Clone, Copy)]
+#[repr(transparent)]
 pub struct Block(pub __m128i);
 
 union __U128 {
@@ -70,23 +72,28 @@ impl Block {
     /// Hash an elliptic curve point `pt` and tweak `tweak`.
     ///
     /// Computes the hash by computing `E_{pt}(tweak)`, where `E` is AES-256.
-    #[cfg(all(feature = "curve25519-dalek", feature = "nightly"))]
+    #[cfg(feature = "curve25519-dalek")]
     #[inline]
     pub fn hash_pt(tweak: usize, pt: &RistrettoPoint) -> Self {
-        let k = pt.compress();
-        let c = Aes256::new(k.as_bytes());
+        Self::hash_compressed_pt(tweak, &pt.compress())
+    }
+
+    /// Hash an already compressed elliptic curve point `pt` and tweak `tweak`,
+    /// as in `hash_pt`. Useful when points are compressed in batches.
+    #[cfg(all(feature = "curve25519-dalek", feature = "nightly"))]
+    #[inline]
+    pub fn hash_compressed_pt(tweak: usize, pt: &CompressedRistretto) -> Self {
+        let c = Aes256::new(pt.as_bytes());
         let m = unsafe { _mm_set_epi64(_mm_setzero_si64(), *(&tweak as *const _ as *const __m64)) };
         c.encrypt(Block(m))
     }
 
-    /// Hash an elliptic curve point `pt` and tweak `tweak`.
-    ///
-    /// Computes the hash by computing `E_{pt}(tweak)`, where `E` is AES-256.
+    /// Hash an already compressed elliptic curve point `pt` and tweak `tweak`,
+    /// as in `hash_pt`. Useful when points are compressed in batches.
     #[cfg(all(feature = "curve25519-dalek", not(feature = "nightly")))]
     #[inline]
-    pub fn hash_pt(tweak: usize, pt: &RistrettoPoint) -> Self {
-        let k = pt.compress();
-        let c = Aes256::new(k.as_bytes());
+    pub fn hash_compressed_pt(tweak: usize, pt: &CompressedRistretto) -> Self {
+        let c = Aes256::new(pt.as_bytes());
         let m = tweak as u128;
         c.encrypt(Block::from(m))
     }
@@ -117,6 +124,87 @@ impl Block {
         bytes[..16].clone_from_slice(&bytes_slice[..16]);
         Some(Block::from(bytes))
     }
+
+    /// Expand the block into `n` pseudorandom blocks.
+    ///
+    /// This runs AES-128 keyed by the block in counter mode, so that the `i`th
+    /// output is `E_{self}(i)`, and matches the first `n` blocks produced by
+    /// `AesRng::from_seed(self)`.
+    pub fn prg_expand(&self, n: usize) -> Vec<Block> {
+        let aes = Aes128::new(*self);
+        let mut out = Vec::with_capacity(n);
+        let mut ctr = 0u128;
+        while out.len() + 8 <= n {
+            let mut ms = [Block::default(); 8];
+            for m in ms.iter_mut() {
+                *m = Block::from(ctr);
+                ctr += 1;
+            }
+            out.extend_from_slice(&aes.encrypt8(ms));
+        }
+        while out.len() < n {
+            out.push(aes.encrypt(Block::from(ctr)));
+            ctr += 1;
+        }
+        out
+    }
+
+    /// XOR each block of `b` into the block of `a` at the same index, in the
+    /// widest vector registers the processor supports.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` and `b` differ in length.
+    #[inline]
+    pub fn xor_many(a: &mut [Block], b: &[Block]) {
+        assert_eq!(a.len(), b.len(), "xor_many of slices of unequal lengths");
+        // `Block` is a transparent wrapper of `__m128i`.
+        let a = unsafe { std::slice::from_raw_parts_mut(a.as_mut_ptr() as *mut __m128i, a.len()) };
+        let b = unsafe { std::slice::from_raw_parts(b.as_ptr() as *const __m128i, b.len()) };
+        xor_many(a, b)
+    }
+}
+
+// XORs `b` into `a`, dispatching on the vector extensions found at runtime.
+#[inline]
+pub(crate) fn xor_many(a: &mut [__m128i], b: &[__m128i]) {
+    if is_x86_feature_detected!("avx512f") {
+        unsafe { xor_many_avx512(a, b) }
+    } else if is_x86_feature_detected!("avx2") {
+        unsafe { xor_many_avx2(a, b) }
+    } else {
+        xor_many_sse2(a, b)
+    }
+}
+
+fn xor_many_sse2(a: &mut [__m128i], b: &[__m128i]) {
+    for (a, b) in a.iter_mut().zip(b.iter()) {
+        *a = unsafe { _mm_xor_si128(*a, *b) };
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn xor_many_avx2(a: &mut [__m128i], b: &[__m128i]) {
+    let n = a.len() / 2;
+    let pa = a.as_mut_ptr() as *mut __m256i;
+    let pb = b.as_ptr() as *const __m256i;
+    for i in 0..n {
+        let x = _mm256_xor_si256(_mm256_loadu_si256(pa.add(i)), _mm256_loadu_si256(pb.add(i)));
+        _mm256_storeu_si256(pa.add(i), x);
+    }
+    xor_many_sse2(&mut a[2 * n..], &b[2 * n..])
+}
+
+#[target_feature(enable = "avx512f")]
+unsafe fn xor_many_avx512(a: &mut [__m128i], b: &[__m128i]) {
+    let n = a.len() / 4;
+    let pa = a.as_mut_ptr() as *mut __m512i;
+    let pb = b.as_ptr() as *const __m512i;
+    for i in 0..n {
+        let x = _mm512_xor_si512(_mm512_loadu_si512(pa.add(i)), _mm512_loadu_si512(pb.add(i)));
+        _mm512_storeu_si512(pa.add(i), x);
+    }
+    xor_many_sse2(&mut a[4 * n..], &b[4 * n..])
 }
 
 impl Default for Block {
@@ -343,6 +431,14 @@ impl<'de> Deserialize<'de> for Block {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Block {
+    #[inline]
+    fn zeroize(&mut self) {
+        self.as_mut().zeroize();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -372,6 +468,26 @@ mod tests {
         assert_eq!(x, z);
     }
 
+    #[test]
+    fn test_xor_many() {
+        // Lengths exercising both the vector loop and the tail.
+        for &n in &[0, 1, 2, 3, 4, 7, 8, 13] {
+            let a = (0..n).map(|_| rand::random::<Block>()).collect::<Vec<_>>();
+            let b = (0..n).map(|_| rand::random::<Block>()).collect::<Vec<_>>();
+            let mut c = a.clone();
+            Block::xor_many(&mut c, &b);
+            for ((a, b), c) in a.iter().zip(b.iter()).zip(c.iter()) {
+                assert_eq!(*a ^ *b, *c);
+            }
+            let mut d = a.clone();
+            xor_many_sse2(
+                unsafe { std::slice::from_raw_parts_mut(d.as_mut_ptr() as *mut __m128i, n) },
+                unsafe { std::slice::from_raw_parts(b.as_ptr() as *const __m128i, n) },
+            );
+            assert_eq!(c, d);
+        }
+    }
+
     #[test]
     fn test_lsb() {
         let x = rand::random::<Block>();
@@ -388,6 +504,46 @@ mod tests {
         assert_eq!(x, y);
     }
 
+    #[test]
+    fn test_prg_expand_vectors() {
+        let expand = |key: [u8; 16]| {
+            Block::from(key)
+                .prg_expand(3)
+                .into_iter()
+                .map(|b| hex::encode(<[u8; 16]>::from(b)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            expand([0; 16]),
+            vec![
+                "66e94bd4ef8a2c3b884cfa59ca342b2e",
+                "47711816e91d6ff059bbbf2bf58e0fd3",
+                "bcf176a7eaad8085ebacea362462a281",
+            ]
+        );
+        assert_eq!(
+            expand([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]),
+            vec![
+                "c6a13b37878f5b826f4f8162a1c8d879",
+                "e37cd363dd7c87a09aff0e3e60e09c82",
+                "fb8ae31ba5db9cad97364d8722d47326",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prg_expand() {
+        use crate::AesRng;
+        use rand::{Rng, SeedableRng};
+        let seed = rand::random::<Block>();
+        let out = seed.prg_expand(21);
+        let mut rng = AesRng::from_seed(seed);
+        let expected = (0..21).map(|_| rng.gen::<Block>()).collect::<Vec<_>>();
+        assert_eq!(out, expected);
+        assert_eq!(seed.prg_expand(5), out[..5].to_vec());
+        assert!(seed.prg_expand(0).is_empty());
+    }
+
     #[test]
     fn test_conversion() {
         let x = rand::random::<u128>();
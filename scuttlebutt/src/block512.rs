@@ -15,6 +15,7 @@ use std::{
 
 /// A 512-bit value.
 #[derive(Clone, Copy)]
+#[repr(transparent)]
 pub struct Block512(pub(crate) [Block; 4]);
 
 impl Block512 {
@@ -31,6 +32,22 @@ impl Block512 {
         debug_assert!(n <= 64);
         unsafe { std::slice::from_raw_parts_mut(self as *mut Self as *mut u8, n) }
     }
+
+    /// XOR each block of `b` into the block of `a` at the same index, as
+    /// `Block::xor_many` does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` and `b` differ in length.
+    #[inline]
+    pub fn xor_many(a: &mut [Block512], b: &[Block512]) {
+        assert_eq!(a.len(), b.len(), "xor_many of slices of unequal lengths");
+        // `Block512` is a transparent wrapper of four `__m128i`s.
+        let a =
+            unsafe { std::slice::from_raw_parts_mut(a.as_mut_ptr() as *mut __m128i, 4 * a.len()) };
+        let b = unsafe { std::slice::from_raw_parts(b.as_ptr() as *const __m128i, 4 * b.len()) };
+        crate::block::xor_many(a, b)
+    }
 }
 
 impl AsMut<[u8]> for Block512 {
@@ -217,3 +234,11 @@ impl TryFrom<&[u8]> for Block512 {
         Ok(block)
     }
 }
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Block512 {
+    #[inline]
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
@@ -4,13 +4,17 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
+mod deadline_channel;
 mod hash_channel;
+pub mod multiparty;
 mod sync_channel;
 mod track_channel;
 #[cfg(unix)]
 mod unix_channel;
 
+pub use deadline_channel::{Deadline, DeadlineChannel};
 pub use hash_channel::HashChannel;
+pub use multiparty::{Network, PartyId};
 pub use sync_channel::SyncChannel;
 pub use track_channel::TrackChannel;
 
@@ -22,12 +26,22 @@ use crate::{Block, Block512};
 use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use std::{
     cell::RefCell,
+    convert::TryFrom,
     io::{Read, Result, Write},
     rc::Rc,
 };
 
 /// A trait for managing I/O. `AbstractChannel`s are clonable, and provide basic
 /// read/write capabilities for both common and scuttlebutt-specific types.
+///
+/// Every protocol in the `swanky` crates, from coin tossing through OT and the
+/// OPRFs to PSI, takes its I/O as a single `AbstractChannel` rather than a
+/// separate reader and writer. Instrumentation and testing wrap a channel:
+/// `TrackChannel` counts the bits sent each way, `HashChannel` hashes the
+/// transcript, `DeadlineChannel` fails once a deadline has passed, and
+/// `SyncChannel` can be shared across threads. Implementing `read_bytes`,
+/// `write_bytes`, `flush` and `clone` is enough to run every protocol over
+/// another transport.
 pub trait AbstractChannel {
     /// Read a slice of `u8`s from the channel.
     fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<()>;
@@ -81,8 +95,7 @@ pub trait AbstractChannel {
     /// Write a `u16` to the channel.
     #[inline(always)]
     fn write_u16(&mut self, s: u16) -> Result<()> {
-        let data: [u8; 2] = unsafe { std::mem::transmute(s) };
-        self.write_bytes(&data)?;
+        self.write_bytes(&s.to_le_bytes())?;
         Ok(())
     }
 
@@ -91,15 +104,13 @@ pub trait AbstractChannel {
     fn read_u16(&mut self) -> Result<u16> {
         let mut data = [0u8; 2];
         self.read_bytes(&mut data)?;
-        let s = unsafe { std::mem::transmute(data) };
-        Ok(s)
+        Ok(u16::from_le_bytes(data))
     }
 
     /// Write a `u32` to the channel.
     #[inline(always)]
     fn write_u32(&mut self, s: u32) -> Result<()> {
-        let data: [u8; 4] = unsafe { std::mem::transmute(s) };
-        self.write_bytes(&data)?;
+        self.write_bytes(&s.to_le_bytes())?;
         Ok(())
     }
 
@@ -108,15 +119,13 @@ pub trait AbstractChannel {
     fn read_u32(&mut self) -> Result<u32> {
         let mut data = [0u8; 4];
         self.read_bytes(&mut data)?;
-        let s = unsafe { std::mem::transmute(data) };
-        Ok(s)
+        Ok(u32::from_le_bytes(data))
     }
 
     /// Write a `u64` to the channel.
     #[inline(always)]
     fn write_u64(&mut self, s: u64) -> Result<()> {
-        let data: [u8; 8] = unsafe { std::mem::transmute(s) };
-        self.write_bytes(&data)?;
+        self.write_bytes(&s.to_le_bytes())?;
         Ok(())
     }
 
@@ -125,25 +134,27 @@ pub trait AbstractChannel {
     fn read_u64(&mut self) -> Result<u64> {
         let mut data = [0u8; 8];
         self.read_bytes(&mut data)?;
-        let s = unsafe { std::mem::transmute(data) };
-        Ok(s)
+        Ok(u64::from_le_bytes(data))
     }
 
-    /// Write a `usize` to the channel.
+    /// Write a `usize` to the channel, always as a `u64`, so that peers
+    /// agree on the framing whatever their pointer width.
     #[inline(always)]
     fn write_usize(&mut self, s: usize) -> Result<()> {
-        let data: [u8; 8] = unsafe { std::mem::transmute(s) };
-        self.write_bytes(&data)?;
-        Ok(())
+        self.write_u64(s as u64)
     }
 
-    /// Read a `usize` from the channel.
+    /// Read a `usize` written by `write_usize` from the channel, failing if
+    /// it does not fit in a `usize` on this platform.
     #[inline(always)]
     fn read_usize(&mut self) -> Result<usize> {
-        let mut data = [0u8; 8];
-        self.read_bytes(&mut data)?;
-        let s = unsafe { std::mem::transmute(data) };
-        Ok(s)
+        let s = self.read_u64()?;
+        usize::try_from(s).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("length {} does not fit in a usize", s),
+            )
+        })
     }
 
     /// Write a `Block` to the channel.
@@ -217,6 +228,14 @@ pub struct Channel<R, W> {
     writer: Rc<RefCell<W>>,
 }
 
+// Channels never show what they have buffered, which may include secrets
+// about to be masked or already unmasked.
+impl<R, W> std::fmt::Debug for Channel<R, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Channel").finish_non_exhaustive()
+    }
+}
+
 impl<R: Read, W: Write> Channel<R, W> {
     /// Make a new `Channel` from a `reader` and a `writer`.
     pub fn new(reader: R, writer: W) -> Self {
@@ -261,3 +280,35 @@ impl<R: Read, W: Write> AbstractChannel for Channel<R, W> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{sink, Cursor};
+
+    #[test]
+    fn test_integer_framing() {
+        // Integers are little-endian, and `usize`s always take eight bytes.
+        let mut channel = Channel::new(std::io::empty(), Vec::new());
+        channel.write_u16(0x0102).unwrap();
+        channel.write_u32(0x0304_0506).unwrap();
+        channel.write_usize(0x0708).unwrap();
+        let bytes = channel.writer().borrow().clone();
+        assert_eq!(bytes, [2, 1, 6, 5, 4, 3, 8, 7, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_large_lengths() {
+        let (len, mask_bytes) = ((1usize << 32) + 1, (1usize << 42) * 3);
+        let mut channel = Channel::new(std::io::empty(), Vec::new());
+        channel.write_usize(len).unwrap();
+        channel.write_usize(mask_bytes).unwrap();
+        channel.write_u64(u64::MAX).unwrap();
+        let bytes = channel.writer().borrow().clone();
+        let mut channel = Channel::new(Cursor::new(bytes), sink());
+        assert_eq!(channel.read_usize().unwrap(), len);
+        assert_eq!(channel.read_usize().unwrap(), mask_bytes);
+        assert_eq!(channel.read_usize().unwrap(), usize::MAX);
+    }
+}
@@ -0,0 +1,212 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `scuttlebutt`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+use crate::AbstractChannel;
+use std::{
+    io::{Error, ErrorKind, Result},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// A point in time by which a protocol run must complete.
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline {
+    at: Instant,
+    started: Instant,
+}
+
+impl Deadline {
+    /// A deadline `budget` from now.
+    pub fn after(budget: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            at: now + budget,
+            started: now,
+        }
+    }
+
+    /// A deadline at `at`.
+    pub fn at(at: Instant) -> Self {
+        Self {
+            at,
+            started: Instant::now(),
+        }
+    }
+
+    /// The instant of the deadline.
+    pub fn instant(&self) -> Instant {
+        self.at
+    }
+
+    /// The time left before the deadline, which is zero once it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+
+    /// The time since the deadline was made.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Whether the deadline has passed.
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.at
+    }
+
+    /// Fail with an error of kind `TimedOut` if the deadline has passed.
+    pub fn check(&self) -> Result<()> {
+        if self.is_expired() {
+            Err(Error::new(ErrorKind::TimedOut, "deadline exceeded"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A channel which fails every operation started after a `Deadline`, and
+/// counts the bytes read and written through it.
+///
+/// The deadline is checked before each read, write and flush, so it bounds a
+/// run that keeps communicating, as every protocol does between its phases,
+/// but cannot interrupt a read already blocked on the peer: bound those with
+/// the transport, e.g. with `TcpStream::set_read_timeout` set to
+/// `Deadline::remaining`. Such a timeout, once the deadline has passed,
+/// counts as exceeding it, as reported by `exceeded`. Clones share the
+/// deadline and the counts.
+pub struct DeadlineChannel<C> {
+    channel: C,
+    deadline: Deadline,
+    nbytes_read: Arc<AtomicU64>,
+    nbytes_written: Arc<AtomicU64>,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl<C> std::fmt::Debug for DeadlineChannel<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DeadlineChannel")
+            .field("deadline", &self.deadline)
+            .field("nbytes_read", &self.bytes_read())
+            .field("nbytes_written", &self.bytes_written())
+            .field("exceeded", &self.exceeded())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C> DeadlineChannel<C> {
+    /// Make a new `DeadlineChannel` running over `channel` until `deadline`.
+    pub fn new(channel: C, deadline: Deadline) -> Self {
+        Self {
+            channel,
+            deadline,
+            nbytes_read: Arc::new(AtomicU64::new(0)),
+            nbytes_written: Arc::new(AtomicU64::new(0)),
+            exceeded: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The deadline of the channel.
+    pub fn deadline(&self) -> Deadline {
+        self.deadline
+    }
+
+    /// Return the number of bytes read from the channel.
+    pub fn bytes_read(&self) -> u64 {
+        self.nbytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of bytes written to the channel.
+    pub fn bytes_written(&self) -> u64 {
+        self.nbytes_written.load(Ordering::Relaxed)
+    }
+
+    /// Whether an operation failed because the deadline had passed, either
+    /// when it started or by timing out on the transport.
+    pub fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+
+    // Fail if the deadline has passed, recording it.
+    fn check(&self) -> Result<()> {
+        self.deadline.check().map_err(|e| self.exceed(e))
+    }
+
+    // Record `e` as exceeding the deadline if it is a timeout of the transport
+    // after the deadline.
+    fn exceed(&self, e: Error) -> Error {
+        let timeout = matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock);
+        if timeout && self.deadline.is_expired() {
+            self.exceeded.store(true, Ordering::Relaxed);
+        }
+        e
+    }
+}
+
+impl<C: AbstractChannel> AbstractChannel for DeadlineChannel<C> {
+    #[inline]
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.check()?;
+        self.channel.write_bytes(bytes).map_err(|e| self.exceed(e))?;
+        self.nbytes_written
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    #[inline]
+    fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<()> {
+        self.check()?;
+        self.channel.read_bytes(bytes).map_err(|e| self.exceed(e))?;
+        self.nbytes_read
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        self.check()?;
+        self.channel.flush().map_err(|e| self.exceed(e))
+    }
+
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel.clone(),
+            deadline: self.deadline,
+            nbytes_read: self.nbytes_read.clone(),
+            nbytes_written: self.nbytes_written.clone(),
+            exceeded: self.exceeded.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Channel;
+    use std::io::{empty, sink};
+
+    #[test]
+    fn test_deadline() {
+        let mut channel = DeadlineChannel::new(
+            Channel::new(empty(), sink()),
+            Deadline::after(Duration::from_secs(60)),
+        );
+        channel.write_bytes(&[0; 16]).unwrap();
+        channel.flush().unwrap();
+        assert_eq!(channel.bytes_written(), 16);
+        assert!(!channel.exceeded());
+
+        let mut channel =
+            DeadlineChannel::new(Channel::new(empty(), sink()), Deadline::at(Instant::now()));
+        let e = channel.write_bytes(&[0; 16]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert_eq!(channel.bytes_written(), 0);
+        assert!(channel.exceeded());
+        assert_eq!(channel.deadline().remaining(), Duration::from_secs(0));
+    }
+}
@@ -15,6 +15,12 @@ pub struct HashChannel<R, W> {
     hash: Sha256,
 }
 
+impl<R, W> std::fmt::Debug for HashChannel<R, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("HashChannel").finish_non_exhaustive()
+    }
+}
+
 impl<R: Read, W: Write> HashChannel<R, W> {
     /// Make a new `HashChannel` from a `reader` and a `writer`.
     pub fn new(reader: R, writer: W) -> Self {
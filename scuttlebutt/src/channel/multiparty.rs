@@ -0,0 +1,291 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `scuttlebutt`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Point-to-point and broadcast messaging among `n` parties, on top of a
+//! channel to each peer.
+//!
+//! Every message is tagged with the sender's current round number, and a
+//! receiver rejects messages from any other round, so parties which fall out
+//! of step fail loudly instead of misinterpreting each other's messages.
+//! Parties advance rounds with `Network::next_round`.
+//!
+//! `Network::broadcast` just sends the same message on every channel, which
+//! does nothing to stop a corrupt sender from telling different parties
+//! different things. `Network::echo_broadcast` adds a round in which all
+//! parties exchange a hash of what they received, which guarantees that
+//! honest parties either output the same message or abort.
+
+use crate::AbstractChannel;
+use sha2::{Digest, Sha256};
+use std::io::{Error, ErrorKind, Result};
+
+/// The id of a party.
+pub type PartyId = usize;
+
+/// The maximum message length accepted by `Network::recv_from`.
+pub const MAX_MESSAGE_SIZE: usize = 1 << 30;
+
+/// A party's view of the network: its own id, and a channel to each peer.
+pub struct Network<'a, C> {
+    me: PartyId,
+    channels: &'a mut [(PartyId, C)],
+    round: u64,
+}
+
+impl<C> std::fmt::Debug for Network<'_, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let peers = self.channels.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        f.debug_struct("Network")
+            .field("me", &self.me)
+            .field("peers", &peers)
+            .field("round", &self.round)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, C: AbstractChannel> Network<'a, C> {
+    /// Make a new network for party `me`, with `channels` holding the id of
+    /// each peer alongside the channel to it. Starts at round zero.
+    pub fn new(me: PartyId, channels: &'a mut [(PartyId, C)]) -> Self {
+        Self {
+            me,
+            channels,
+            round: 0,
+        }
+    }
+
+    /// Return our own id.
+    #[inline]
+    pub fn me(&self) -> PartyId {
+        self.me
+    }
+
+    /// Return the ids of our peers.
+    pub fn peers(&self) -> Vec<PartyId> {
+        self.channels.iter().map(|(id, _)| *id).collect()
+    }
+
+    /// Return the current round.
+    #[inline]
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    /// Move on to the next round.
+    #[inline]
+    pub fn next_round(&mut self) {
+        self.round += 1;
+    }
+
+    fn channel(&mut self, party: PartyId) -> Result<&mut C> {
+        self.channels
+            .iter_mut()
+            .find(|(id, _)| *id == party)
+            .map(|(_, c)| c)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("no channel to party {}", party),
+                )
+            })
+    }
+
+    /// Send `msg` to `party`, tagged with the current round.
+    pub fn send_to(&mut self, party: PartyId, msg: &[u8]) -> Result<()> {
+        let round = self.round;
+        let channel = self.channel(party)?;
+        channel.write_u64(round)?;
+        channel.write_u64(msg.len() as u64)?;
+        channel.write_bytes(msg)?;
+        channel.flush()
+    }
+
+    /// Receive a message from `party`, failing if it was sent in a different
+    /// round.
+    pub fn recv_from(&mut self, party: PartyId) -> Result<Vec<u8>> {
+        let round = self.round;
+        let channel = self.channel(party)?;
+        let theirs = channel.read_u64()?;
+        if theirs != round {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "party {} sent a message for round {} during round {}",
+                    party, theirs, round
+                ),
+            ));
+        }
+        // Check the length before truncating it to a `usize`.
+        let len = channel.read_u64()?;
+        if len > MAX_MESSAGE_SIZE as u64 {
+            return Err(Error::new(ErrorKind::InvalidData, "message too large"));
+        }
+        channel.read_vec(len as usize)
+    }
+
+    /// Send `msg` to every peer.
+    pub fn broadcast(&mut self, msg: &[u8]) -> Result<()> {
+        for party in self.peers() {
+            self.send_to(party, msg)?;
+        }
+        Ok(())
+    }
+
+    /// Receive a message from every peer, in the order of `peers`.
+    pub fn gather(&mut self) -> Result<Vec<(PartyId, Vec<u8>)>> {
+        self.peers()
+            .into_iter()
+            .map(|party| Ok((party, self.recv_from(party)?)))
+            .collect()
+    }
+
+    /// Broadcast `msg` and gather every peer's message for this round.
+    pub fn exchange(&mut self, msg: &[u8]) -> Result<Vec<(PartyId, Vec<u8>)>> {
+        self.broadcast(msg)?;
+        self.gather()
+    }
+
+    /// Broadcast a message from `sender` with weak consistency, returning it.
+    ///
+    /// The sender passes its message as `msg`; everyone else passes `None`.
+    /// All parties then echo a hash of the message they hold to each other,
+    /// and fail if any echo differs from their own. This takes two rounds:
+    /// the current one and the next, and leaves the network in the latter.
+    pub fn echo_broadcast(&mut self, sender: PartyId, msg: Option<&[u8]>) -> Result<Vec<u8>> {
+        let msg = match (sender == self.me, msg) {
+            (true, Some(msg)) => {
+                self.broadcast(msg)?;
+                msg.to_vec()
+            }
+            (false, None) => self.recv_from(sender)?,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "exactly the sender must provide a message",
+                ))
+            }
+        };
+        self.next_round();
+        let hash = Sha256::digest(&msg);
+        for (party, echo) in self.exchange(&hash)? {
+            if echo.as_slice() != hash.as_slice() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "party {} received a different message from party {}",
+                        party, sender
+                    ),
+                ));
+            }
+        }
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::{unix_channel_pair, UnixChannel};
+
+    const NPARTIES: usize = 3;
+
+    fn mesh() -> Vec<Vec<(PartyId, UnixChannel)>> {
+        let mut channels = (0..NPARTIES).map(|_| Vec::new()).collect::<Vec<_>>();
+        for a in 0..NPARTIES {
+            for b in a + 1..NPARTIES {
+                let (left, right) = unix_channel_pair();
+                channels[a].push((b, left));
+                channels[b].push((a, right));
+            }
+        }
+        channels
+    }
+
+    fn run<T: Send + 'static>(
+        f: impl Fn(&mut Network<UnixChannel>) -> T + Send + Sync + Copy + 'static,
+    ) -> Vec<T> {
+        let handles = mesh()
+            .into_iter()
+            .enumerate()
+            .map(|(me, mut channels)| {
+                std::thread::spawn(move || f(&mut Network::new(me, &mut channels)))
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_exchange() {
+        let results = run(|net| {
+            let first = net.exchange(&[net.me() as u8]).unwrap();
+            net.next_round();
+            // Everyone sends their successor a longer message.
+            let next = (net.me() + 1) % NPARTIES;
+            let prev = (net.me() + NPARTIES - 1) % NPARTIES;
+            net.send_to(next, &[net.me() as u8; 100]).unwrap();
+            let second = net.recv_from(prev).unwrap();
+            (first, second)
+        });
+        for (me, (first, second)) in results.into_iter().enumerate() {
+            assert_eq!(first.len(), NPARTIES - 1);
+            for (party, msg) in first {
+                assert_ne!(party, me);
+                assert_eq!(msg, vec![party as u8]);
+            }
+            let prev = (me + NPARTIES - 1) % NPARTIES;
+            assert_eq!(second, vec![prev as u8; 100]);
+        }
+    }
+
+    #[test]
+    fn test_round_mismatch() {
+        let results = run(|net| {
+            if net.me() == 0 {
+                net.next_round();
+                net.broadcast(b"early").unwrap();
+                None
+            } else {
+                Some(net.recv_from(0).is_err())
+            }
+        });
+        assert_eq!(results, vec![None, Some(true), Some(true)]);
+    }
+
+    #[test]
+    fn test_echo_broadcast() {
+        let results = run(|net| {
+            let msg = if net.me() == 1 {
+                Some(&b"hello"[..])
+            } else {
+                None
+            };
+            let out = net.echo_broadcast(1, msg).unwrap();
+            (out, net.round())
+        });
+        for (out, round) in results {
+            assert_eq!(out, b"hello".to_vec());
+            assert_eq!(round, 1);
+        }
+    }
+
+    #[test]
+    fn test_echo_broadcast_equivocation() {
+        let results = run(|net| {
+            if net.me() == 0 {
+                // Tell each party something different, then echo honestly.
+                net.send_to(1, b"yes").unwrap();
+                net.send_to(2, b"no").unwrap();
+                net.next_round();
+                let hash = Sha256::digest(b"yes");
+                let _ = net.exchange(&hash);
+                None
+            } else {
+                Some(net.echo_broadcast(0, None).is_err())
+            }
+        });
+        assert_eq!(results, vec![None, Some(true), Some(true)]);
+    }
+}
@@ -16,6 +16,12 @@ pub struct SyncChannel<R, W> {
     writer: Arc<Mutex<W>>,
 }
 
+impl<R, W> std::fmt::Debug for SyncChannel<R, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SyncChannel").finish_non_exhaustive()
+    }
+}
+
 impl<R: Read, W: Write> SyncChannel<R, W> {
     /// Make a new `Channel` from a `reader` and a `writer`.
     pub fn new(reader: R, writer: W) -> Self {
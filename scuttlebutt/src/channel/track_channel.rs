@@ -4,7 +4,7 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
-use crate::{AbstractChannel, Channel};
+use crate::{metrics::Metrics, AbstractChannel, Channel};
 use std::{
     io::{Read, Result, Write},
     sync::{Arc, Mutex},
@@ -15,8 +15,23 @@ pub struct TrackChannel<R, W>(Arc<Mutex<InternalTrackChannel<R, W>>>);
 
 struct InternalTrackChannel<R, W> {
     channel: Channel<R, W>,
-    nbits_read: usize,
-    nbits_written: usize,
+    // Counted as `u64`s so that multi-terabyte sessions do not overflow on
+    // 32-bit platforms.
+    nbits_read: u64,
+    nbits_written: u64,
+    metrics: Option<Arc<Metrics>>,
+}
+
+// Shows the amount of communication so far.
+impl<R, W> std::fmt::Debug for TrackChannel<R, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut f = f.debug_struct("TrackChannel");
+        if let Ok(int) = self.0.lock() {
+            f.field("nbits_read", &int.nbits_read)
+                .field("nbits_written", &int.nbits_written);
+        }
+        f.finish_non_exhaustive()
+    }
 }
 
 impl<R: Read, W: Write> TrackChannel<R, W> {
@@ -27,10 +42,17 @@ impl<R: Read, W: Write> TrackChannel<R, W> {
             channel,
             nbits_read: 0,
             nbits_written: 0,
+            metrics: None,
         };
         Self(Arc::new(Mutex::new(internal)))
     }
 
+    /// Also count all bytes read and written from now on in `metrics`. This
+    /// applies to every clone of the channel.
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.0.lock().unwrap().metrics = Some(metrics);
+    }
+
     /// Clear the number of bits read/written.
     pub fn clear(&mut self) {
         let mut int = self.0.lock().unwrap();
@@ -38,6 +60,16 @@ impl<R: Read, W: Write> TrackChannel<R, W> {
         int.nbits_written = 0;
     }
 
+    /// Return the number of bytes written to the channel.
+    pub fn bytes_written(&self) -> u64 {
+        self.0.lock().unwrap().nbits_written / 8
+    }
+
+    /// Return the number of bytes read from the channel.
+    pub fn bytes_read(&self) -> u64 {
+        self.0.lock().unwrap().nbits_read / 8
+    }
+
     /// Return the number of kilobits written to the channel.
     pub fn kilobits_written(&self) -> f64 {
         self.0.lock().unwrap().nbits_written as f64 / 1000.0
@@ -73,13 +105,19 @@ impl<R: Read, W: Write> TrackChannel<R, W> {
 impl<R: Read, W: Write> AbstractChannel for TrackChannel<R, W> {
     fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
         let mut int = self.0.lock().unwrap();
-        int.nbits_written += bytes.len() * 8;
+        int.nbits_written += bytes.len() as u64 * 8;
+        if let Some(metrics) = &int.metrics {
+            metrics.add_bytes_written(bytes.len());
+        }
         int.channel.write_bytes(bytes)
     }
 
     fn read_bytes(&mut self, mut bytes: &mut [u8]) -> Result<()> {
         let mut int = self.0.lock().unwrap();
-        int.nbits_read += bytes.len() * 8;
+        int.nbits_read += bytes.len() as u64 * 8;
+        if let Some(metrics) = &int.metrics {
+            metrics.add_bytes_read(bytes.len());
+        }
         int.channel.read_bytes(&mut bytes)
     }
 
@@ -91,3 +129,22 @@ impl<R: Read, W: Write> AbstractChannel for TrackChannel<R, W> {
         Self(self.0.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{empty, sink};
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_count_past_4gib() {
+        // Stream a synthetic 5 GiB through the channel without storing it.
+        let chunk = vec![0u8; 1 << 20];
+        let mut channel = TrackChannel::new(empty(), sink());
+        for _ in 0..5 << 10 {
+            channel.write_bytes(&chunk).unwrap();
+        }
+        assert_eq!(channel.kilobytes_written(), (5u64 << 30) as f64 / 1024.0);
+        assert_eq!(channel.kilobytes_read(), 0.0);
+    }
+}
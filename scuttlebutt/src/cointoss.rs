@@ -11,9 +11,14 @@
 //! receiver. It then receives `seed_` from the receiver and outputs `seed ⊕
 //! seed_`. Likewise, on input `seed`, the receiver gets `r`, sends `seed` to
 //! the sender, and then receives `seed_`, checking that `PRG(seed_) = r`.
+//!
+//! `multiparty` generalizes this to any number of parties over a `Network`:
+//! every party broadcasts a hash commitment to its seeds, then all parties
+//! open their commitments, and the output is the XOR of everyone's seeds.
 
-use crate::{AbstractChannel, AesRng, Block};
+use crate::{channel::Network, AbstractChannel, AesRng, Block};
 use rand_core::{RngCore, SeedableRng};
+use sha2::{Digest, Sha256};
 
 /// Errors produced by the coin tossing protocol.
 #[derive(Debug)]
@@ -22,6 +27,8 @@ pub enum Error {
     IoError(std::io::Error),
     /// The commitment check failed.
     CommitmentCheckFailed,
+    /// Parties disagree on the commitments sent during a multi-party toss.
+    InconsistentCommitments,
 }
 
 impl From<std::io::Error> for Error {
@@ -35,6 +42,7 @@ impl std::fmt::Display for Error {
         match self {
             Error::IoError(e) => write!(f, "IO error: {}", e),
             Error::CommitmentCheckFailed => "committment check failed".fmt(f),
+            Error::InconsistentCommitments => "parties received different commitments".fmt(f),
         }
     }
 }
@@ -87,12 +95,78 @@ pub fn receive<C: AbstractChannel>(channel: &mut C, seeds: &[Block]) -> Result<V
     Ok(out)
 }
 
+fn commit(party: usize, seeds: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.input((party as u64).to_le_bytes());
+    hasher.input(seeds);
+    hasher.result().to_vec()
+}
+
+/// Coin tossing among all parties of `network`, where every party provides
+/// the same number of `seeds`.
+///
+/// Each party's commitment binds its id, so a party cannot replay another's
+/// commitment and opening to cancel out their seeds. Alongside its opening,
+/// every party sends a digest of all the commitments it received, so that
+/// honest parties either agree on the output or abort. This takes two rounds:
+/// the current one and the next, and leaves the network in the latter.
+pub fn multiparty<C: AbstractChannel>(
+    network: &mut Network<C>,
+    seeds: &[Block],
+) -> Result<Vec<Block>, Error> {
+    let me = network.me();
+    let bytes = seeds
+        .iter()
+        .flat_map(|seed| seed.as_ref().to_vec())
+        .collect::<Vec<u8>>();
+    let ours = commit(me, &bytes);
+    let mut coms = network.exchange(&ours)?;
+    coms.push((me, ours));
+    coms.sort_by_key(|(party, _)| *party);
+    let mut hasher = Sha256::new();
+    for (party, com) in coms.iter() {
+        hasher.input((*party as u64).to_le_bytes());
+        hasher.input(com);
+    }
+    let digest = hasher.result();
+
+    network.next_round();
+    let mut opening = bytes;
+    opening.extend_from_slice(&digest);
+    let openings = network.exchange(&opening)?;
+
+    let nbytes = 16 * seeds.len();
+    let mut out = seeds.to_vec();
+    for (party, opening) in openings {
+        if opening.len() != nbytes + digest.len() {
+            return Err(Error::CommitmentCheckFailed);
+        }
+        let (theirs, their_digest) = opening.split_at(nbytes);
+        if their_digest != digest.as_slice() {
+            return Err(Error::InconsistentCommitments);
+        }
+        let com = &coms.iter().find(|(p, _)| *p == party).unwrap().1;
+        if commit(party, theirs) != *com {
+            return Err(Error::CommitmentCheckFailed);
+        }
+        for (o, chunk) in out.iter_mut().zip(theirs.chunks(16)) {
+            let mut seed = [0u8; 16];
+            seed.copy_from_slice(chunk);
+            *o ^= Block::from(seed);
+        }
+    }
+    Ok(out)
+}
+
 #[cfg(test)]
 mod tests {
     #[cfg(feature = "nightly")]
     extern crate test;
     use super::*;
-    use crate::Channel;
+    use crate::{
+        channel::{unix_channel_pair, UnixChannel},
+        Channel,
+    };
     use std::{
         io::{BufReader, BufWriter},
         os::unix::net::UnixStream,
@@ -117,4 +191,71 @@ mod tests {
         assert_eq!(output_[0], seed ^ seed_);
         handle.join().unwrap();
     }
+
+    fn run_multiparty(
+        nparties: usize,
+        f: fn(usize, &mut Network<UnixChannel>) -> Result<Vec<Block>, Error>,
+    ) -> Vec<Result<Vec<Block>, Error>> {
+        let mut channels = (0..nparties).map(|_| Vec::new()).collect::<Vec<_>>();
+        for a in 0..nparties {
+            for b in a + 1..nparties {
+                let (left, right) = unix_channel_pair();
+                channels[a].push((b, left));
+                channels[b].push((a, right));
+            }
+        }
+        let handles = channels
+            .into_iter()
+            .enumerate()
+            .map(|(me, mut channels)| {
+                std::thread::spawn(move || f(me, &mut Network::new(me, &mut channels)))
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_multiparty() {
+        let outputs = run_multiparty(4, |me, network| {
+            let seeds = [Block::from(1u128 << me), Block::from(me as u128)];
+            multiparty(network, &seeds)
+        });
+        for output in outputs {
+            assert_eq!(
+                output.unwrap(),
+                vec![Block::from(0b1111u128), Block::from(1 ^ 2 ^ 3)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_multiparty_bad_opening() {
+        let outputs = run_multiparty(3, |me, network| {
+            if me == 2 {
+                // Commit to one seed, then open to another.
+                let ours = commit(me, &[0u8; 16]);
+                let mut coms = network.exchange(&ours)?;
+                coms.push((me, ours));
+                coms.sort_by_key(|(party, _)| *party);
+                let mut hasher = Sha256::new();
+                for (party, com) in coms.iter() {
+                    hasher.input((*party as u64).to_le_bytes());
+                    hasher.input(com);
+                }
+                network.next_round();
+                let mut opening = vec![1u8; 16];
+                opening.extend_from_slice(&hasher.result());
+                network.exchange(&opening)?;
+                Ok(vec![])
+            } else {
+                multiparty(network, &[rand::random::<Block>()])
+            }
+        });
+        for output in &outputs[..2] {
+            match output {
+                Err(Error::CommitmentCheckFailed) => (),
+                _ => panic!("opening to a different seed should fail"),
+            }
+        }
+    }
 }
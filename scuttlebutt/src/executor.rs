@@ -0,0 +1,141 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `scuttlebutt`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Executors for the parallel parts of protocols.
+//!
+//! Code which can split its work into independent jobs takes an `Executor`
+//! rather than spawning threads itself, so that the caller decides where the
+//! jobs run: inline (`Sequential`), on freshly spawned scoped threads
+//! (`ScopedThreads`), on a `rayon::ThreadPool` with the `rayon` feature, or on
+//! any custom implementation, e.g. one which enforces a per-tenant CPU quota.
+
+/// A unit of work handed to an `Executor`.
+pub type Job<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// Something which runs batches of jobs, possibly in parallel.
+pub trait Executor: Sync {
+    /// Return the number of jobs the executor can usefully run at once, which
+    /// callers use to decide how finely to split their work.
+    fn parallelism(&self) -> usize;
+
+    /// Run every job in `jobs`, returning once all of them have finished.
+    fn run<'a>(&self, jobs: Vec<Job<'a>>);
+}
+
+/// Runs jobs one after the other on the calling thread.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sequential;
+
+impl Executor for Sequential {
+    #[inline]
+    fn parallelism(&self) -> usize {
+        1
+    }
+
+    fn run<'a>(&self, jobs: Vec<Job<'a>>) {
+        for job in jobs {
+            job();
+        }
+    }
+}
+
+/// Runs each job on its own scoped thread, spawned for the batch.
+#[derive(Clone, Copy, Debug)]
+pub struct ScopedThreads(usize);
+
+impl ScopedThreads {
+    /// Make an executor which callers should feed up to `nthreads` jobs at a
+    /// time. Zero is treated as one.
+    pub fn new(nthreads: usize) -> Self {
+        Self(nthreads.max(1))
+    }
+}
+
+impl Executor for ScopedThreads {
+    #[inline]
+    fn parallelism(&self) -> usize {
+        self.0
+    }
+
+    fn run<'a>(&self, jobs: Vec<Job<'a>>) {
+        std::thread::scope(|scope| {
+            for job in jobs {
+                scope.spawn(job);
+            }
+        });
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Executor for rayon::ThreadPool {
+    #[inline]
+    fn parallelism(&self) -> usize {
+        self.current_num_threads()
+    }
+
+    fn run<'a>(&self, jobs: Vec<Job<'a>>) {
+        self.scope(|scope| {
+            for job in jobs {
+                scope.spawn(move |_| job());
+            }
+        });
+    }
+}
+
+impl<E: Executor + ?Sized> Executor for &E {
+    #[inline]
+    fn parallelism(&self) -> usize {
+        (**self).parallelism()
+    }
+
+    fn run<'a>(&self, jobs: Vec<Job<'a>>) {
+        (**self).run(jobs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn check<E: Executor>(executor: &E) {
+        let mut outputs = vec![0usize; 64];
+        let count = AtomicUsize::new(0);
+        let jobs = outputs
+            .chunks_mut(64 / executor.parallelism().min(64))
+            .enumerate()
+            .map(|(i, chunk)| {
+                let count = &count;
+                Box::new(move || {
+                    for x in chunk.iter_mut() {
+                        *x = i;
+                        count.fetch_add(1, Ordering::Relaxed);
+                    }
+                }) as Job
+            })
+            .collect();
+        executor.run(jobs);
+        assert_eq!(count.into_inner(), 64);
+        let chunk = 64 / executor.parallelism().min(64);
+        for (i, x) in outputs.into_iter().enumerate() {
+            assert_eq!(x, i / chunk);
+        }
+    }
+
+    #[test]
+    fn test_executors() {
+        check(&Sequential);
+        check(&ScopedThreads::new(0));
+        check(&ScopedThreads::new(8));
+        #[cfg(feature = "rayon")]
+        check(
+            &rayon::ThreadPoolBuilder::new()
+                .num_threads(3)
+                .build()
+                .unwrap(),
+        );
+    }
+}
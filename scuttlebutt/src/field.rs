@@ -0,0 +1,342 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `scuttlebutt`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Finite fields for arithmetic protocols such as VOLE.
+//!
+//! Two fields are provided: `Gf128`, the binary field GF(2^128), whose
+//! elements are the same size as a `Block` and whose multiplication uses
+//! carryless multiplication, and `F61p`, the small prime field of integers
+//! modulo the Mersenne prime `2^61 - 1`, in which reduction is a shift and an
+//! addition.
+
+use crate::{utils::Erasable, Block};
+use rand::Rng;
+use std::{
+    fmt::Debug,
+    ops::{Add, Mul, Neg, Sub},
+};
+
+/// A finite field.
+pub trait FiniteField:
+    Copy
+    + Debug
+    + Default
+    + Eq
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Neg<Output = Self>
+    + Erasable
+{
+    /// The additive identity.
+    const ZERO: Self;
+    /// The multiplicative identity.
+    const ONE: Self;
+    /// The number of bits of the canonical representation of an element,
+    /// which is the sum of `bit(i)·basis(i)` over `i < NBITS`.
+    const NBITS: usize;
+
+    /// Map `block` to an element. A uniformly random block maps to an element
+    /// statistically close to uniform, and `to_block` is undone exactly.
+    fn from_block(block: Block) -> Self;
+    /// Encode the element as a block.
+    fn to_block(self) -> Block;
+    /// Return bit `i` of the canonical representation of the element.
+    fn bit(self, i: usize) -> bool;
+    /// Return the `i`th element of the basis the canonical representation is
+    /// written in.
+    fn basis(i: usize) -> Self;
+
+    /// Sample a uniformly random element.
+    #[inline]
+    fn random<RNG: Rng + ?Sized>(rng: &mut RNG) -> Self {
+        Self::from_block(rng.gen())
+    }
+}
+
+/// An element of GF(2^128), represented as a polynomial over GF(2) modulo
+/// `X^128 + X^7 + X^2 + X + 1`, with bit `i` holding the coefficient of
+/// `X^i`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Gf128(u128);
+
+impl Gf128 {
+    /// Return the multiplicative inverse of the element, or zero for zero.
+    pub fn inverse(self) -> Self {
+        // `x^(2^128 - 2)`, raising `x^(2^k - 1)` to `x^(2^(k + 1) - 1)` at
+        // each step.
+        let mut r = self;
+        for _ in 0..126 {
+            r = r * r * self;
+        }
+        r * r
+    }
+
+    // Reduce the product `hi·X^128 + lo` modulo the field polynomial, using
+    // `X^128 = X^7 + X^2 + X + 1`.
+    #[inline]
+    fn reduce(lo: u128, hi: u128) -> u128 {
+        let low = hi ^ (hi << 1) ^ (hi << 2) ^ (hi << 7);
+        let overflow = (hi >> 127) ^ (hi >> 126) ^ (hi >> 121);
+        lo ^ low ^ overflow ^ (overflow << 1) ^ (overflow << 2) ^ (overflow << 7)
+    }
+}
+
+impl From<u128> for Gf128 {
+    #[inline]
+    fn from(x: u128) -> Self {
+        Gf128(x)
+    }
+}
+
+impl From<Gf128> for u128 {
+    #[inline]
+    fn from(x: Gf128) -> Self {
+        x.0
+    }
+}
+
+impl Add for Gf128 {
+    type Output = Self;
+    #[inline]
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn add(self, rhs: Self) -> Self {
+        Gf128(self.0 ^ rhs.0)
+    }
+}
+
+impl Sub for Gf128 {
+    type Output = Self;
+    #[inline]
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn sub(self, rhs: Self) -> Self {
+        Gf128(self.0 ^ rhs.0)
+    }
+}
+
+impl Neg for Gf128 {
+    type Output = Self;
+    #[inline]
+    fn neg(self) -> Self {
+        self
+    }
+}
+
+impl Mul for Gf128 {
+    type Output = Self;
+    #[inline]
+    fn mul(self, rhs: Self) -> Self {
+        let (lo, hi) = Block::from(self.0).clmul(Block::from(rhs.0));
+        Gf128(Self::reduce(u128::from(lo), u128::from(hi)))
+    }
+}
+
+impl FiniteField for Gf128 {
+    const ZERO: Self = Gf128(0);
+    const ONE: Self = Gf128(1);
+    const NBITS: usize = 128;
+
+    #[inline]
+    fn from_block(block: Block) -> Self {
+        Gf128(u128::from(block))
+    }
+
+    #[inline]
+    fn to_block(self) -> Block {
+        Block::from(self.0)
+    }
+
+    #[inline]
+    fn bit(self, i: usize) -> bool {
+        (self.0 >> i) & 1 == 1
+    }
+
+    #[inline]
+    fn basis(i: usize) -> Self {
+        Gf128(1 << i)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Gf128 {
+    #[inline]
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// An element of the prime field of integers modulo `2^61 - 1`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct F61p(u64);
+
+impl F61p {
+    /// The modulus, `2^61 - 1`.
+    pub const MODULUS: u64 = (1 << 61) - 1;
+
+    /// Make an element from `x`, reducing it modulo `2^61 - 1`.
+    #[inline]
+    pub fn new(x: u64) -> Self {
+        F61p(Self::reduce(u128::from(x)))
+    }
+
+    /// Return the canonical representative of the element, in `[0, 2^61 -
+    /// 1)`.
+    #[inline]
+    pub fn value(self) -> u64 {
+        self.0
+    }
+
+    // Reduce `x` modulo `2^61 - 1`, using `2^61 = 1`.
+    #[inline]
+    fn reduce(mut x: u128) -> u64 {
+        let modulus = u128::from(Self::MODULUS);
+        while x > modulus {
+            x = (x & modulus) + (x >> 61);
+        }
+        if x == modulus {
+            0
+        } else {
+            x as u64
+        }
+    }
+}
+
+impl Add for F61p {
+    type Output = Self;
+    #[inline]
+    fn add(self, rhs: Self) -> Self {
+        let x = self.0 + rhs.0;
+        F61p(if x >= Self::MODULUS {
+            x - Self::MODULUS
+        } else {
+            x
+        })
+    }
+}
+
+impl Sub for F61p {
+    type Output = Self;
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        self + (-rhs)
+    }
+}
+
+impl Neg for F61p {
+    type Output = Self;
+    #[inline]
+    fn neg(self) -> Self {
+        F61p(if self.0 == 0 {
+            0
+        } else {
+            Self::MODULUS - self.0
+        })
+    }
+}
+
+impl Mul for F61p {
+    type Output = Self;
+    #[inline]
+    fn mul(self, rhs: Self) -> Self {
+        F61p(Self::reduce(u128::from(self.0) * u128::from(rhs.0)))
+    }
+}
+
+impl FiniteField for F61p {
+    const ZERO: Self = F61p(0);
+    const ONE: Self = F61p(1);
+    const NBITS: usize = 61;
+
+    #[inline]
+    fn from_block(block: Block) -> Self {
+        F61p(Self::reduce(u128::from(block)))
+    }
+
+    #[inline]
+    fn to_block(self) -> Block {
+        Block::from(u128::from(self.0))
+    }
+
+    #[inline]
+    fn bit(self, i: usize) -> bool {
+        (self.0 >> i) & 1 == 1
+    }
+
+    #[inline]
+    fn basis(i: usize) -> Self {
+        F61p(1 << i)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for F61p {
+    #[inline]
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AesRng;
+
+    fn check_field<F: FiniteField>() {
+        let mut rng = AesRng::new();
+        for _ in 0..1000 {
+            let (a, b, c) = (
+                F::random(&mut rng),
+                F::random(&mut rng),
+                F::random(&mut rng),
+            );
+            assert_eq!(a + b, b + a);
+            assert_eq!(a * b, b * a);
+            assert_eq!((a + b) + c, a + (b + c));
+            assert_eq!((a * b) * c, a * (b * c));
+            assert_eq!(a * (b + c), a * b + a * c);
+            assert_eq!(a + F::ZERO, a);
+            assert_eq!(a * F::ONE, a);
+            assert_eq!(a * F::ZERO, F::ZERO);
+            assert_eq!(a - b + b, a);
+            assert_eq!(a + (-a), F::ZERO);
+            assert_eq!(F::from_block(a.to_block()), a);
+            let sum = (0..F::NBITS)
+                .filter(|&i| a.bit(i))
+                .fold(F::ZERO, |sum, i| sum + F::basis(i));
+            assert_eq!(sum, a);
+        }
+    }
+
+    #[test]
+    fn test_gf128() {
+        check_field::<Gf128>();
+        // `X^127·X = X^7 + X^2 + X + 1`.
+        assert_eq!(Gf128::basis(127) * Gf128::basis(1), Gf128(0x87));
+        assert_eq!(Gf128::basis(64) * Gf128::basis(63), Gf128::basis(127));
+        let mut rng = AesRng::new();
+        for _ in 0..100 {
+            let a = Gf128::random(&mut rng);
+            assert_eq!(a * a.inverse(), Gf128::ONE);
+        }
+        assert_eq!(Gf128::ZERO.inverse(), Gf128::ZERO);
+    }
+
+    #[test]
+    fn test_f61p() {
+        check_field::<F61p>();
+        let minus_one = F61p::new(F61p::MODULUS - 1);
+        assert_eq!(minus_one, -F61p::ONE);
+        assert_eq!(minus_one * minus_one, F61p::ONE);
+        assert_eq!(F61p::new(F61p::MODULUS), F61p::ZERO);
+        assert_eq!(F61p::basis(60) * F61p::new(2), F61p::ONE);
+        assert_eq!(
+            F61p::from_block(Block::from(u128::MAX)).value(),
+            (1 << 6) - 1
+        );
+    }
+}
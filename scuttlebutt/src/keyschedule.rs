@@ -0,0 +1,176 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `scuttlebutt`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! A key schedule deriving independent keys from one master secret, using the
+//! HKDF construction of RFC 5869 over HMAC-SHA256.
+//!
+//! A protocol which needs several shared keys, one per phase, can toss a
+//! single master secret and expand it under a distinct label for each phase,
+//! rather than tossing coins once per key. Keys derived under different labels
+//! are independent, so each phase can be argued about as if its key had been
+//! tossed on its own.
+//!
+//! # Usage
+//! ```rust
+//! use scuttlebutt::keyschedule::KeySchedule;
+//!
+//! let schedule = KeySchedule::new(b"my protocol", &[7u8; 16]);
+//! let hashing_key = schedule.block(b"hashing key");
+//! let confirmation_key = schedule.block(b"confirmation key");
+//! assert_ne!(hashing_key, confirmation_key);
+//! ```
+
+use crate::{AesRng, Block};
+use rand_core::SeedableRng;
+use sha2::{Digest, Sha256};
+
+const BLOCK_SIZE: usize = 64;
+
+/// HMAC-SHA256 (RFC 2104) of the concatenation of `inputs` under `key`.
+pub fn hmac(key: &[u8], inputs: &[&[u8]]) -> [u8; 32] {
+    // Keys longer than a block are hashed down to a hash output.
+    if key.len() > BLOCK_SIZE {
+        return hmac(&Sha256::digest(key), inputs);
+    }
+    let mut ipad = [0x36u8; BLOCK_SIZE];
+    let mut opad = [0x5cu8; BLOCK_SIZE];
+    for (i, k) in key.iter().enumerate() {
+        ipad[i] ^= k;
+        opad[i] ^= k;
+    }
+    let mut inner = Sha256::new();
+    inner.input(&ipad[..]);
+    for input in inputs {
+        inner.input(input);
+    }
+    let mut outer = Sha256::new();
+    outer.input(&opad[..]);
+    outer.input(inner.result());
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&outer.result());
+    out
+}
+
+/// A key schedule holding the pseudorandom key extracted from a master secret.
+#[derive(Clone)]
+pub struct KeySchedule {
+    prk: [u8; 32],
+}
+
+impl KeySchedule {
+    /// Extract a key schedule from `master`. The `salt` separates the
+    /// schedules of different protocols using the same master secret.
+    pub fn new(salt: &[u8], master: &[u8]) -> Self {
+        Self {
+            prk: hmac(salt, &[master]),
+        }
+    }
+
+    /// Fill `out` with the key derived under `label`.
+    ///
+    /// # Panics
+    /// Panics if `out` is longer than `255 * 32` bytes, the most HKDF can
+    /// expand to.
+    pub fn expand(&self, label: &[u8], out: &mut [u8]) {
+        assert!(out.len() <= 255 * 32, "HKDF expands to at most 8160 bytes");
+        let mut t = [0u8; 32];
+        for (i, chunk) in out.chunks_mut(32).enumerate() {
+            let counter = [i as u8 + 1];
+            let prev: &[u8] = if i == 0 { &[] } else { &t };
+            t = hmac(&self.prk, &[prev, label, &counter]);
+            chunk.copy_from_slice(&t[..chunk.len()]);
+        }
+    }
+
+    /// The 32-byte key derived under `label`.
+    pub fn key(&self, label: &[u8]) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        self.expand(label, &mut out);
+        out
+    }
+
+    /// The `Block` derived under `label`.
+    pub fn block(&self, label: &[u8]) -> Block {
+        let mut out = Block::default();
+        self.expand(label, out.as_mut());
+        out
+    }
+
+    /// An `AesRng` seeded with the `Block` derived under `label`.
+    pub fn rng(&self, label: &[u8]) -> AesRng {
+        AesRng::from_seed(self.block(label))
+    }
+
+    /// A key schedule for a sub-phase, whose keys are independent of those
+    /// derived directly from `self`.
+    pub fn child(&self, label: &[u8]) -> KeySchedule {
+        // Every input HKDF expands ends in a nonzero counter byte, so ending
+        // in a zero byte separates children from keys.
+        Self {
+            prk: hmac(&self.prk, &[label, &[0]]),
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for KeySchedule {
+    fn drop(&mut self) {
+        crate::utils::erase(&mut self.prk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc5869() {
+        // Test case 1 of RFC 5869.
+        let master = [0x0bu8; 22];
+        let salt = (0..13).collect::<Vec<u8>>();
+        let info = (0xf0..0xfa).collect::<Vec<u8>>();
+        let schedule = KeySchedule::new(&salt, &master);
+        assert_eq!(
+            hex::encode(schedule.prk),
+            "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"
+        );
+        let mut okm = [0u8; 42];
+        schedule.expand(&info, &mut okm);
+        assert_eq!(
+            hex::encode(&okm[..]),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+        );
+    }
+
+    #[test]
+    fn test_hmac() {
+        // Test cases 2 and 6 of RFC 4231.
+        assert_eq!(
+            hex::encode(hmac(b"Jefe", &[b"what do ya want ", b"for nothing?"])),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex::encode(hmac(
+                &[0xaau8; 131],
+                &[b"Test Using Larger Than Block-Size Key - Hash Key First"]
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let schedule = KeySchedule::new(b"test", &[1u8; 16]);
+        assert_eq!(schedule.block(b"a"), schedule.block(b"a"));
+        assert_ne!(schedule.block(b"a"), schedule.block(b"b"));
+        assert_ne!(schedule.key(b"a"), schedule.child(b"a").key(b"a"));
+        assert_ne!(schedule.key(b"a"), schedule.child(b"a").prk);
+        assert_ne!(
+            schedule.block(b"a"),
+            KeySchedule::new(b"other", &[1u8; 16]).block(b"a")
+        );
+    }
+}
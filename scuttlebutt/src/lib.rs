@@ -21,7 +21,12 @@ pub mod bloomfilter;
 pub mod channel;
 pub mod cointoss;
 pub mod commitment;
+pub mod executor;
+pub mod field;
 mod hash_aes;
+pub mod keyschedule;
+pub mod memtrack;
+pub mod metrics;
 mod rand_aes;
 pub mod utils;
 
@@ -32,7 +37,15 @@ pub use crate::{
     },
     block::Block,
     block512::Block512,
-    channel::{AbstractChannel, Channel, HashChannel, SyncChannel, TrackChannel},
+    channel::{
+        AbstractChannel,
+        Channel,
+        Deadline,
+        DeadlineChannel,
+        HashChannel,
+        SyncChannel,
+        TrackChannel,
+    },
     hash_aes::{AesHash, AES_HASH},
     rand_aes::AesRng,
 };
@@ -0,0 +1,332 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `scuttlebutt`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Per-phase heap allocation tracking.
+//!
+//! `CountingAlloc` wraps a global allocator and counts, per thread, the bytes
+//! currently live, the high-water mark of that number, and the total bytes and
+//! number of allocations made. Install it in a binary with
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: scuttlebutt::memtrack::CountingAlloc =
+//!     scuttlebutt::memtrack::CountingAlloc::system();
+//! ```
+//!
+//! Protocols mark their phases with `phase`. When recording is enabled on the
+//! current thread (see `start_recording`), each completed phase is appended to
+//! a thread-local report, which `take_report` hands back. Without the
+//! allocator installed all counts are zero; without recording enabled `phase`
+//! only runs its closure. An observer set with `set_observer`, or added for
+//! the duration of a closure with `observe`, is also told of each phase
+//! completed on the current thread, as it completes.
+//!
+//! The numbers are heap bytes requested through the allocator, not resident
+//! set size, and memory freed on a different thread from the one that
+//! allocated it is attributed to the freeing thread.
+
+use crate::metrics;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// A function told of the name and duration of each completed phase.
+pub type Observer = Box<dyn FnMut(&'static str, Duration)>;
+
+#[derive(Clone, Copy, Default)]
+struct Counters {
+    current: isize,
+    peak: isize,
+    allocated: usize,
+    nallocs: usize,
+}
+
+thread_local! {
+    static COUNTERS: Cell<Counters> = const {
+        Cell::new(Counters { current: 0, peak: 0, allocated: 0, nallocs: 0 })
+    };
+    static RECORDING: Cell<bool> = const { Cell::new(false) };
+    static REPORT: RefCell<Vec<(&'static str, PhaseStats)>> = const { RefCell::new(Vec::new()) };
+    static OBSERVER: RefCell<Option<Observer>> = const { RefCell::new(None) };
+}
+
+fn update(f: impl FnOnce(&mut Counters)) {
+    // Fails only during thread teardown, in which case we drop the update.
+    let _ = COUNTERS.try_with(|c| {
+        let mut counters = c.get();
+        f(&mut counters);
+        c.set(counters);
+    });
+}
+
+fn counters() -> Counters {
+    COUNTERS.try_with(Cell::get).unwrap_or_default()
+}
+
+fn on_alloc(size: usize) {
+    update(|c| {
+        c.current += size as isize;
+        c.peak = c.peak.max(c.current);
+        c.allocated += size;
+        c.nallocs += 1;
+    })
+}
+
+fn on_dealloc(size: usize) {
+    update(|c| c.current -= size as isize)
+}
+
+/// A global allocator wrapper which counts allocations made on each thread.
+#[derive(Debug, Default)]
+pub struct CountingAlloc<A = System>(pub A);
+
+impl CountingAlloc<System> {
+    /// Wrap the system allocator.
+    pub const fn system() -> Self {
+        CountingAlloc(System)
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.0.alloc(layout);
+        if !ptr.is_null() {
+            on_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.0.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            on_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout);
+        on_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = self.0.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            on_dealloc(layout.size());
+            on_alloc(new_size);
+        }
+        new
+    }
+}
+
+/// Allocation statistics for a single phase.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PhaseStats {
+    /// The most bytes live at any point during the phase, beyond those live
+    /// when it started.
+    pub peak: usize,
+    /// The total number of bytes allocated during the phase.
+    pub allocated: usize,
+    /// The number of allocations made during the phase.
+    pub nallocs: usize,
+}
+
+/// An in-progress measurement, started by `Phase::start`.
+#[derive(Debug)]
+pub struct Phase {
+    current: isize,
+    peak: isize,
+    allocated: usize,
+    nallocs: usize,
+}
+
+impl Phase {
+    /// Start measuring allocations on the current thread.
+    pub fn start() -> Self {
+        let c = counters();
+        // Reset the high-water mark, so that we see the peak of this phase
+        // alone; `finish` restores it for any enclosing phase.
+        update(|c| c.peak = c.current);
+        Phase {
+            current: c.current,
+            peak: c.peak,
+            allocated: c.allocated,
+            nallocs: c.nallocs,
+        }
+    }
+
+    /// Stop measuring, returning the statistics for the phase.
+    pub fn finish(self) -> PhaseStats {
+        let c = counters();
+        update(|c| c.peak = c.peak.max(self.peak));
+        PhaseStats {
+            peak: (c.peak - self.current).max(0) as usize,
+            allocated: c.allocated - self.allocated,
+            nallocs: c.nallocs - self.nallocs,
+        }
+    }
+}
+
+/// Run `f` as the phase `name`, recording its statistics if recording is
+/// enabled on the current thread, and its duration if a `metrics` registry is
+/// installed or an observer is set.
+pub fn phase<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    let recording = RECORDING.with(Cell::get);
+    let metrics = metrics::installed();
+    let observed = OBSERVER.with(|o| o.borrow().is_some());
+    if !recording && metrics.is_none() && !observed {
+        return f();
+    }
+    let start = Instant::now();
+    let phase = Phase::start();
+    let out = f();
+    let stats = phase.finish();
+    if let Some(metrics) = metrics {
+        metrics.observe_phase(name, start.elapsed());
+    }
+    if recording {
+        REPORT.with(|r| r.borrow_mut().push((name, stats)));
+    }
+    if observed {
+        OBSERVER.with(|o| {
+            if let Some(observer) = o.borrow_mut().as_mut() {
+                observer(name, start.elapsed());
+            }
+        });
+    }
+    out
+}
+
+/// Set the observer of the phases completed on the current thread, returning
+/// the previous one. The observer must not itself run phases.
+pub fn set_observer(observer: Option<Observer>) -> Option<Observer> {
+    OBSERVER.with(|o| std::mem::replace(&mut *o.borrow_mut(), observer))
+}
+
+/// Run `f`, also telling `observer` of each phase completed on the current
+/// thread meanwhile. Unlike `set_observer`, this keeps any observer already
+/// set, which sees the phases after `observer`, and puts it back when `f`
+/// returns or panics.
+pub fn observe<T>(
+    mut observer: impl FnMut(&'static str, Duration) + 'static,
+    f: impl FnOnce() -> T,
+) -> T {
+    let previous = Rc::new(RefCell::new(set_observer(None)));
+    let previous_ = previous.clone();
+    set_observer(Some(Box::new(move |name, elapsed| {
+        observer(name, elapsed);
+        if let Some(previous) = previous_.borrow_mut().as_mut() {
+            previous(name, elapsed);
+        }
+    })));
+    let _restore = Restore(previous);
+    f()
+}
+
+// Puts back the observer replaced by `observe`.
+struct Restore(Rc<RefCell<Option<Observer>>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.borrow_mut().take();
+        let _ = set_observer(previous);
+    }
+}
+
+/// Start recording phases run on the current thread.
+pub fn start_recording() {
+    RECORDING.with(|r| r.set(true));
+}
+
+/// Stop recording phases run on the current thread.
+pub fn stop_recording() {
+    RECORDING.with(|r| r.set(false));
+}
+
+/// Return and clear the phases recorded on the current thread, in the order
+/// in which they completed.
+pub fn take_report() -> Vec<(&'static str, PhaseStats)> {
+    REPORT.with(|r| std::mem::take(&mut *r.borrow_mut()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_nesting() {
+        // The test harness does not install `CountingAlloc`, so feed the
+        // counters directly.
+        start_recording();
+        phase("outer", || {
+            on_alloc(100);
+            phase("inner", || {
+                on_alloc(50);
+                on_dealloc(50);
+            });
+            on_dealloc(100);
+        });
+        stop_recording();
+        phase("ignored", || on_alloc(1));
+        on_dealloc(1);
+        let report = take_report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].0, "inner");
+        assert_eq!(
+            report[0].1,
+            PhaseStats {
+                peak: 50,
+                allocated: 50,
+                nallocs: 1
+            }
+        );
+        assert_eq!(report[1].0, "outer");
+        // The outer peak includes the inner allocation made on top of its own.
+        assert_eq!(
+            report[1].1,
+            PhaseStats {
+                peak: 150,
+                allocated: 150,
+                nallocs: 2
+            }
+        );
+        assert!(take_report().is_empty());
+    }
+
+    #[test]
+    fn test_observer() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let seen_ = seen.clone();
+        assert!(set_observer(Some(Box::new(move |name, _| {
+            seen_.borrow_mut().push(name)
+        })))
+        .is_none());
+        phase("outer", || phase("inner", || ()));
+        assert!(set_observer(None).is_some());
+        phase("unobserved", || ());
+        assert_eq!(*seen.borrow(), vec!["inner", "outer"]);
+
+        // Observers added with `observe` stack, and are removed afterwards.
+        let seen_ = seen.clone();
+        set_observer(Some(Box::new(move |name, _| seen_.borrow_mut().push(name))));
+        let (a, b) = (seen.clone(), seen.clone());
+        observe(
+            move |name, _| a.borrow_mut().push(name),
+            || {
+                observe(
+                    move |name, _| b.borrow_mut().push(name),
+                    || phase("x", || ()),
+                )
+            },
+        );
+        phase("y", || ());
+        assert!(set_observer(None).is_some());
+        assert_eq!(seen.borrow()[2..], ["x", "x", "x", "y"]);
+    }
+}
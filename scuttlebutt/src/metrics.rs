@@ -0,0 +1,361 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `scuttlebutt`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Operational metrics, exported in the Prometheus text format.
+//!
+//! A `Metrics` registry counts the bytes moved over channels, the sessions
+//! run and how they failed, and the time spent in each protocol phase. It is
+//! fed from several places:
+//!
+//! * a `TrackChannel` given the registry with `TrackChannel::set_metrics`
+//!   adds every byte it reads or writes;
+//! * once a registry is made process-wide with `install`, every phase marked
+//!   with `memtrack::phase` adds its duration, and the protocols of
+//!   `popsicle` count every session run through their entry points;
+//! * applications record other sessions with `Metrics::session_started`,
+//!   `Metrics::session_succeeded` and `Metrics::session_failed`.
+//!
+//! `Metrics::render` produces the text exposition format, and `serve`
+//! answers scrapes over HTTP.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+        Mutex,
+        OnceLock,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// The prefix of every exported metric name.
+pub const NAMESPACE: &str = "swanky";
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Timing {
+    count: u64,
+    seconds: f64,
+}
+
+/// A registry of metrics, safe to share between threads.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    sessions_started: AtomicU64,
+    sessions_succeeded: AtomicU64,
+    failures: Mutex<BTreeMap<String, u64>>,
+    phases: Mutex<BTreeMap<&'static str, Timing>>,
+}
+
+impl Metrics {
+    /// Make a new registry with every metric at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `n` bytes read from a channel.
+    #[inline]
+    pub fn add_bytes_read(&self, n: usize) {
+        self.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Count `n` bytes written to a channel.
+    #[inline]
+    pub fn add_bytes_written(&self, n: usize) {
+        self.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Return the number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of bytes written so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// Count the start of a session.
+    pub fn session_started(&self) {
+        self.sessions_started.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a session which completed successfully.
+    pub fn session_succeeded(&self) {
+        self.sessions_succeeded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a session which failed, where `reason` is a short label, such as
+    /// `"io"`, shared by all failures of the same kind.
+    pub fn session_failed(&self, reason: &str) {
+        let mut failures = self.failures.lock().unwrap();
+        *failures.entry(reason.to_string()).or_insert(0) += 1;
+    }
+
+    /// Return the number of failed sessions with failure `reason`.
+    pub fn failures(&self, reason: &str) -> u64 {
+        self.failures
+            .lock()
+            .unwrap()
+            .get(reason)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Add a run of phase `name` which took `elapsed`.
+    pub fn observe_phase(&self, name: &'static str, elapsed: Duration) {
+        let mut phases = self.phases.lock().unwrap();
+        let timing = phases.entry(name).or_default();
+        timing.count += 1;
+        timing.seconds += elapsed.as_secs_f64();
+    }
+
+    /// Return the number of runs of phase `name` and their total duration.
+    pub fn phase(&self, name: &str) -> (u64, Duration) {
+        let phases = self.phases.lock().unwrap();
+        let timing = phases.get(name).cloned().unwrap_or_default();
+        (timing.count, Duration::from_secs_f64(timing.seconds))
+    }
+
+    /// Render every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut counter = |name: &str, help: &str, samples: &[(String, u64)]| {
+            let _ = writeln!(out, "# HELP {}_{} {}", NAMESPACE, name, help);
+            let _ = writeln!(out, "# TYPE {}_{} counter", NAMESPACE, name);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}_{}{} {}", NAMESPACE, name, labels, value);
+            }
+        };
+        counter(
+            "channel_read_bytes_total",
+            "Bytes read from tracked channels.",
+            &[(String::new(), self.bytes_read())],
+        );
+        counter(
+            "channel_written_bytes_total",
+            "Bytes written to tracked channels.",
+            &[(String::new(), self.bytes_written())],
+        );
+        counter(
+            "sessions_started_total",
+            "Sessions started.",
+            &[(String::new(), self.sessions_started.load(Ordering::Relaxed))],
+        );
+        counter(
+            "sessions_succeeded_total",
+            "Sessions which completed successfully.",
+            &[(
+                String::new(),
+                self.sessions_succeeded.load(Ordering::Relaxed),
+            )],
+        );
+        let failures = self
+            .failures
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(reason, n)| (label("reason", reason), *n))
+            .collect::<Vec<_>>();
+        counter(
+            "sessions_failed_total",
+            "Sessions which failed, by reason.",
+            &failures,
+        );
+        let name = format!("{}_phase_duration_seconds", NAMESPACE);
+        let _ = writeln!(out, "# HELP {} Time spent in each protocol phase.", name);
+        let _ = writeln!(out, "# TYPE {} summary", name);
+        for (phase, timing) in self.phases.lock().unwrap().iter() {
+            let labels = label("phase", phase);
+            let _ = writeln!(out, "{}_sum{} {}", name, labels, timing.seconds);
+            let _ = writeln!(out, "{}_count{} {}", name, labels, timing.count);
+        }
+        out
+    }
+}
+
+// Format a single label, escaping its value as the text format requires.
+fn label(key: &str, value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    format!("{{{}=\"{}\"}}", key, escaped)
+}
+
+static INSTALLED: OnceLock<Arc<Metrics>> = OnceLock::new();
+
+/// Make `metrics` the process-wide registry, which `memtrack::phase` feeds.
+/// Fails, handing `metrics` back, if a registry is already installed.
+pub fn install(metrics: Arc<Metrics>) -> Result<(), Arc<Metrics>> {
+    INSTALLED.set(metrics)
+}
+
+/// Return the process-wide registry, if one is installed.
+#[inline]
+pub fn installed() -> Option<&'static Arc<Metrics>> {
+    INSTALLED.get()
+}
+
+/// The longest a scrape may stall reading the request or writing the
+/// response before `serve` drops its connection.
+pub const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The number of scrapes `serve` answers at once; connections beyond it are
+/// dropped.
+pub const MAX_SCRAPES: usize = 16;
+
+// The longest request `serve` reads.
+const MAX_REQUEST_SIZE: u64 = 8192;
+
+/// Answer every HTTP request on `listener` with `metrics.render()`, on
+/// background threads, until accepting a connection fails.
+///
+/// This is deliberately minimal: the path is ignored, so it is meant for a
+/// dedicated port scraped by Prometheus. Each connection is answered on its
+/// own thread, up to `MAX_SCRAPES` at once, and dropped if it stalls for
+/// `SCRAPE_TIMEOUT`, so that a slow or silent client cannot hold up others.
+pub fn serve(listener: TcpListener, metrics: Arc<Metrics>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let active = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+            if active.fetch_add(1, Ordering::AcqRel) >= MAX_SCRAPES {
+                active.fetch_sub(1, Ordering::AcqRel);
+                continue;
+            }
+            let metrics = metrics.clone();
+            let active = active.clone();
+            std::thread::spawn(move || {
+                let _ = answer(stream, &metrics);
+                active.fetch_sub(1, Ordering::AcqRel);
+            });
+        }
+    })
+}
+
+// Answer one scrape on `stream`.
+fn answer(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+    stream.set_write_timeout(Some(SCRAPE_TIMEOUT))?;
+    // Skip the request line and headers, up to the blank line.
+    let mut reader = BufReader::new(stream.try_clone()?.take(MAX_REQUEST_SIZE));
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        if line == "\r\n" || line == "\n" {
+            break;
+        }
+        line.clear();
+    }
+    let body = metrics.render();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AbstractChannel, TrackChannel};
+    use std::{io::BufWriter, os::unix::net::UnixStream};
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.session_started();
+        metrics.session_started();
+        metrics.session_succeeded();
+        metrics.session_failed("io");
+        metrics.session_failed("bad \"peer\"");
+        metrics.observe_phase("psz::sender::oprf", Duration::from_millis(250));
+        metrics.observe_phase("psz::sender::oprf", Duration::from_millis(250));
+        assert_eq!(metrics.failures("io"), 1);
+        assert_eq!(
+            metrics.phase("psz::sender::oprf"),
+            (2, Duration::from_millis(500))
+        );
+        let text = metrics.render();
+        for line in &[
+            "# TYPE swanky_sessions_started_total counter",
+            "swanky_sessions_started_total 2",
+            "swanky_sessions_succeeded_total 1",
+            "swanky_sessions_failed_total{reason=\"io\"} 1",
+            "swanky_sessions_failed_total{reason=\"bad \\\"peer\\\"\"} 1",
+            "# TYPE swanky_phase_duration_seconds summary",
+            "swanky_phase_duration_seconds_sum{phase=\"psz::sender::oprf\"} 0.5",
+            "swanky_phase_duration_seconds_count{phase=\"psz::sender::oprf\"} 2",
+        ] {
+            assert!(text.lines().any(|l| l == *line), "missing `{}`", line);
+        }
+    }
+
+    #[test]
+    fn test_channel() {
+        let metrics = Arc::new(Metrics::new());
+        let (left, right) = UnixStream::pair().unwrap();
+        let mut sender = TrackChannel::new(left.try_clone().unwrap(), BufWriter::new(left));
+        let mut receiver = TrackChannel::new(right.try_clone().unwrap(), BufWriter::new(right));
+        sender.set_metrics(metrics.clone());
+        receiver.set_metrics(metrics.clone());
+        sender.write_bytes(&[0; 100]).unwrap();
+        sender.flush().unwrap();
+        receiver.read_vec(100).unwrap();
+        assert_eq!(metrics.bytes_written(), 100);
+        assert_eq!(metrics.bytes_read(), 100);
+    }
+
+    #[test]
+    fn test_install() {
+        let metrics = Arc::new(Metrics::new());
+        install(metrics.clone()).unwrap();
+        assert!(install(Arc::new(Metrics::new())).is_err());
+        crate::memtrack::phase("metrics::test", || {
+            std::thread::sleep(Duration::from_millis(1))
+        });
+        let (count, elapsed) = metrics.phase("metrics::test");
+        assert_eq!(count, 1);
+        assert!(elapsed >= Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_serve() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.add_bytes_read(42);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _ = serve(listener, metrics);
+        // A client which never sends its request does not hold up others.
+        let _silent = TcpStream::connect(addr).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\nswanky_channel_read_bytes_total 42\n"));
+    }
+}
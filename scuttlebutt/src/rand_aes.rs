@@ -5,8 +5,13 @@
 // See LICENSE for licensing information.
 
 //! Fixed-key AES random number generator.
+//!
+//! The output of a RNG from a seed is the same on every platform: the AES-128
+//! encryptions under the seed of the counter `0, 1, 2, ...`, each as a
+//! little-endian `u128`, one after the other. Words are taken from it in
+//! little-endian order.
 
-use crate::{Aes128, Block};
+use crate::{keyschedule::hmac, Aes128, Block};
 use rand::{CryptoRng, Error, Rng, RngCore, SeedableRng};
 use rand_core::block::{BlockRng, BlockRngCore};
 
@@ -60,12 +65,55 @@ impl AesRng {
         AesRng::from_seed(seed)
     }
 
-    /// Create a new RNG using a random seed from this one.
+    /// Create a RNG from the seed `seed`, e.g. for reproducible tests. As for
+    /// any seed, its output is the same on every platform.
     #[inline]
-    pub fn fork(&mut self) -> Self {
+    pub fn from_u64(seed: u64) -> Self {
+        AesRng::from_seed(Block::from(u128::from(seed)))
+    }
+
+    /// Create a child RNG under `label`, seeded from the seed of this one and
+    /// `label` alone, so that however far either has run, a RNG from the same
+    /// seed forks the same child under the same label. Children under
+    /// different labels, and their parent, are independent.
+    #[inline]
+    pub fn fork(&self, label: &[u8]) -> Self {
+        let seed = hmac(self.0.core.seed.as_ref(), &[FORK_LABEL, label]);
+        let seed = Block::try_from_slice(&seed[..16]).expect("the digest has 16 bytes");
+        AesRng::from_seed(seed)
+    }
+
+    /// Create a RNG using a random seed from this one.
+    #[inline]
+    pub fn fork_random(&mut self) -> Self {
         let seed = self.gen::<Block>();
         AesRng::from_seed(seed)
     }
+
+    /// The number of 32-bit words output so far. Byte outputs are drawn a
+    /// word at a time, so a partial word counts as a whole one.
+    #[inline]
+    pub fn position(&self) -> u64 {
+        let generated = (self.0.core.state / 8) as u64;
+        if generated == 0 {
+            0
+        } else {
+            generated * 32 - (32 - self.0.index() as u64)
+        }
+    }
+
+    /// Create a RNG from `seed` which has already output `position` words, so
+    /// that it continues the output of a RNG from `seed` whose `position` it
+    /// was.
+    #[inline]
+    pub fn from_seed_at(seed: Block, position: u64) -> Self {
+        let mut rng = AesRng::from_seed(seed);
+        if position > 0 {
+            rng.0.core.state = u128::from(position / 32) * 8;
+            rng.0.generate_and_set((position % 32) as usize);
+        }
+        rng
+    }
 }
 
 impl Default for AesRng {
@@ -75,10 +123,15 @@ impl Default for AesRng {
     }
 }
 
+// Separates the seeds of forked RNGs from other uses of the parent's seed.
+const FORK_LABEL: &[u8] = b"scuttlebutt AesRng fork";
+
 /// The core of `AesRng`, used with `BlockRng`.
 #[derive(Clone)]
 pub struct AesRngCore {
     aes: Aes128,
+    // Kept for forking.
+    seed: Block,
     state: u128,
 }
 
@@ -131,6 +184,7 @@ impl SeedableRng for AesRngCore {
         let aes = Aes128::new(seed);
         AesRngCore {
             aes,
+            seed,
             state: Default::default(),
         }
     }
@@ -145,6 +199,28 @@ impl From<AesRngCore> for AesRng {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for AesRngCore {
+    fn zeroize(&mut self) {
+        self.aes.zeroize();
+        self.seed.zeroize();
+        self.state.zeroize();
+    }
+}
+
+/// With the `zeroize` feature, the seed, key, counter and buffered output of an
+/// `AesRng` are erased when it is dropped.
+#[cfg(feature = "zeroize")]
+impl Drop for AesRng {
+    fn drop(&mut self) {
+        use zeroize::Zeroize;
+        self.0.core.zeroize();
+        // `BlockRng` keeps its buffer of outputs private, so refill it from
+        // the now zeroed core, which makes it independent of the old key.
+        self.0.generate_and_set(0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -157,4 +233,42 @@ mod tests {
         let b = rng.gen::<[Block; 8]>();
         assert_ne!(a, b);
     }
+
+    #[test]
+    fn test_from_seed_at() {
+        let seed = rand::random::<Block>();
+        let mut rng = AesRng::from_seed(seed);
+        for n in [0, 6, 1, 125, 128, 3, 4098, 70].iter() {
+            let mut bytes = vec![0u8; *n];
+            rng.fill_bytes(&mut bytes);
+            let mut copy = AesRng::from_seed_at(seed, rng.position());
+            assert_eq!(copy.gen::<[Block; 9]>(), rng.clone().gen::<[Block; 9]>());
+        }
+    }
+
+    #[test]
+    fn test_known_answer() {
+        // FIPS-197 gives the encryption of zero under the zero key.
+        let mut bytes = [0u8; 20];
+        AesRng::from_u64(0).fill_bytes(&mut bytes);
+        assert_eq!(
+            bytes[..16],
+            [
+                0x66, 0xe9, 0x4b, 0xd4, 0xef, 0x8a, 0x2c, 0x3b, 0x88, 0x4c, 0xfa, 0x59, 0xca, 0x34,
+                0x2b, 0x2e
+            ]
+        );
+        assert_eq!(AesRng::from_u64(0).next_u32(), 0xd44b_e966);
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut rng = AesRng::from_u64(42);
+        let child = rng.fork(b"a").gen::<Block>();
+        rng.gen::<[Block; 3]>();
+        assert_eq!(rng.fork(b"a").gen::<Block>(), child);
+        assert_ne!(rng.fork(b"b").gen::<Block>(), child);
+        assert_ne!(AesRng::from_u64(42).gen::<Block>(), child);
+        assert_ne!(AesRng::from_u64(43).fork(b"a").gen::<Block>(), child);
+    }
 }
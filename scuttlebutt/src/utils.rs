@@ -63,6 +63,12 @@ pub fn xor_inplace_n(a: &mut [u8], b: &[u8], n: usize) {
     }
 }
 
+/// Whether `a` and `b` are equal, in time depending only on their lengths,
+/// e.g. to check a MAC tag without revealing where it first differs.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 /// AND two byte arrays, outputting the result.
 pub fn and(a: &[u8], b: &[u8]) -> Vec<u8> {
     a.iter().zip(b.iter()).map(|(a, b)| a & b).collect()
@@ -75,6 +81,38 @@ pub fn and_inplace(a: &mut [u8], b: &[u8]) {
     }
 }
 
+/// Overwrite `secret` with zeros when the `zeroize` feature is enabled, and do
+/// nothing otherwise.
+#[cfg(feature = "zeroize")]
+#[inline]
+pub fn erase<T: zeroize::Zeroize + ?Sized>(secret: &mut T) {
+    secret.zeroize();
+}
+
+/// Overwrite `secret` with zeros when the `zeroize` feature is enabled, and do
+/// nothing otherwise.
+#[cfg(not(feature = "zeroize"))]
+#[inline]
+pub fn erase<T: ?Sized>(_secret: &mut T) {}
+
+/// Values `erase` can overwrite, for use as a bound by generic code: those
+/// implementing `zeroize::Zeroize` when the `zeroize` feature is enabled, and
+/// any value otherwise.
+#[cfg(feature = "zeroize")]
+pub trait Erasable: zeroize::Zeroize {}
+
+#[cfg(feature = "zeroize")]
+impl<T: zeroize::Zeroize + ?Sized> Erasable for T {}
+
+/// Values `erase` can overwrite, for use as a bound by generic code: those
+/// implementing `zeroize::Zeroize` when the `zeroize` feature is enabled, and
+/// any value otherwise.
+#[cfg(not(feature = "zeroize"))]
+pub trait Erasable {}
+
+#[cfg(not(feature = "zeroize"))]
+impl<T: ?Sized> Erasable for T {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -88,6 +126,21 @@ mod tests {
         assert_eq!(v___, v);
     }
 
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_erase() {
+        let mut secret = [rand::random::<crate::Block>(); 4];
+        erase(&mut secret);
+        assert!(secret.iter().all(|b| *b == crate::Block::default()));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+
     #[test]
     fn test_xor_inplace() {
         let mut v = (0..128).map(|_| rand::random::<u8>()).collect::<Vec<u8>>();